@@ -0,0 +1,153 @@
+//! Shared setup of the examples
+//!
+//! The examples talk to an instance of the program deployed on a local
+//! validator through client::PaymentProcessorClient, the way an integration
+//! does.  Start `solana-test-validator`, deploy the program with
+//! `solana program deploy` and pass its address to the example:
+//!
+//! ```sh
+//! $ cargo run --example web_shop --features client -- <PROGRAM_ID> [RPC_URL]
+//! ```
+//!
+//! The keypair of the Solana CLI (`~/.config/solana/id.json`) pays for the
+//! transactions and plays the merchant owner.  It deployed the program, so it
+//! can initialize the program config when the instance is new; it is then the
+//! program owner as well.  The example registers its merchant and creates the
+//! mint that its flow pays with, and funds the wallets of its buyers.
+
+#![allow(dead_code)]
+
+use sol_payment_processor::{
+    client::PaymentProcessorClient,
+    engine::{
+        associated_token::create_associated_token_account_instruction,
+        constants::DEFAULT_PROGRAM_NAME,
+    },
+    instruction::{initialize_program, InitializeProgramArgs},
+    pda::{get_associated_token_address, get_pda},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    instruction::Instruction, program_pack::Pack, pubkey::Pubkey, rent::Rent, system_instruction,
+};
+use solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use spl_token::{
+    instruction::{initialize_mint, mint_to},
+    state::Mint,
+};
+use std::str::FromStr;
+
+/// the RPC URL used when none is given
+const DEFAULT_URL: &str = "http://localhost:8899";
+/// the keypair file of the Solana CLI, relative to the home directory
+const DEFAULT_KEYPAIR: &str = ".config/solana/id.json";
+
+pub struct Setup {
+    pub client: PaymentProcessorClient,
+    pub program_id: Pubkey,
+    pub program_owner: Pubkey,
+    pub payer: Keypair,
+}
+
+impl Setup {
+    /// Connect to the instance of the program given on the command line,
+    /// initializing its config unless it was initialized already
+    pub async fn start() -> Setup {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let program_id = match args.first() {
+            Some(value) => Pubkey::from_str(value).expect("Invalid program id"),
+            None => panic!("Usage: <PROGRAM_ID> [RPC_URL]"),
+        };
+        let url = args
+            .get(1)
+            .cloned()
+            .unwrap_or_else(|| String::from(DEFAULT_URL));
+        let home = std::env::var("HOME").unwrap();
+        let payer = read_keypair_file(&format!("{}/{}", home, DEFAULT_KEYPAIR)).unwrap();
+        let client = PaymentProcessorClient::new(RpcClient::new(url), program_id);
+        let program_owner = match client.get_program_config().await {
+            Ok(program_config) => Pubkey::new_from_array(program_config.owner),
+            Err(_error) => {
+                let instruction = initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: payer.pubkey(),
+                        program_owner: payer.pubkey(),
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Option::None,
+                    },
+                );
+                client.send(&[instruction], &payer, &[]).await.unwrap();
+                payer.pubkey()
+            }
+        };
+        Setup {
+            client,
+            program_id,
+            program_owner,
+            payer,
+        }
+    }
+
+    /// Send a transaction paid for by the payer, who always signs
+    pub async fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Signature {
+        self.client
+            .send(instructions, &self.payer, signers)
+            .await
+            .unwrap()
+    }
+
+    /// Create a mint whose authority is the payer
+    pub async fn create_mint(&self, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.payer.pubkey();
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                Rent::default().minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, decimals).unwrap(),
+        ];
+        self.send(&instructions, &[&mint]).await;
+        mint.pubkey()
+    }
+
+    /// Create the associated token account of the wallet for the mint, minting
+    /// amount to it
+    pub async fn create_wallet_token(&self, wallet: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.payer.pubkey();
+        let token = get_associated_token_address(wallet, &spl_token::id(), mint);
+        let mut instructions = vec![create_associated_token_account_instruction(
+            &payer, wallet, mint,
+        )];
+        if amount > 0 {
+            instructions
+                .push(mint_to(&spl_token::id(), mint, &token, &payer, &[], amount).unwrap());
+        }
+        self.send(&instructions, &[]).await;
+        token
+    }
+
+    /// Create a buyer with a wallet that holds lamports for the fees and amount
+    /// of the mint in its associated token account
+    pub async fn create_buyer(&self, mint: &Pubkey, amount: u64) -> Keypair {
+        let buyer = Keypair::new();
+        let instruction =
+            system_instruction::transfer(&self.payer.pubkey(), &buyer.pubkey(), 1000000000);
+        self.send(&[instruction], &[]).await;
+        self.create_wallet_token(&buyer.pubkey(), mint, amount)
+            .await;
+        buyer
+    }
+
+    /// The program derived address that owns the order token accounts
+    pub fn pda(&self) -> Pubkey {
+        get_pda(&self.program_id).0
+    }
+}
@@ -0,0 +1,167 @@
+//! Point of sale flow
+//!
+//! A cafe takes payments at the counter in two ways:
+//!
+//! - quick sales: the register shows a Solana Pay transfer request as a QR code,
+//!   and the customer's wallet pays it with an ExpressCheckout from the
+//!   customer's associated token account
+//! - tabs: the register opens an invoice for the table, which the customer pays
+//!   when leaving
+//!
+//! At the end of the day the cafe withdraws the takings to the associated token
+//! account of its owner, which the first withdrawal creates.
+//!
+//! Run with `cargo run --example pos --features client -- <PROGRAM_ID>` (see
+//! common).
+
+mod common;
+
+use common::Setup;
+use sol_payment_processor::{
+    instruction::{
+        create_invoice, pay_invoice, withdraw_to_wallet, CreateInvoiceArgs, PayInvoiceArgs,
+        WithdrawToWalletArgs,
+    },
+    pda::{get_associated_token_address, get_order_address, get_order_token_address},
+    solana_pay::{express_checkout_instructions, ExpressCheckoutInstructionsArgs, TransferRequest},
+    state::OrderStatus,
+};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+const DECIMALS: u8 = 6;
+const TAB: u64 = 12750000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let setup = Setup::start().await;
+    let client = &setup.client;
+    let owner = setup.payer.pubkey();
+    let merchant = client
+        .register_merchant(
+            &setup.payer,
+            Some("cafe"),
+            Option::None,
+            Some(String::from(r#"{"name": "Corner Cafe"}"#)),
+        )
+        .await
+        .unwrap();
+    let sponsor = Pubkey::new_from_array(client.get_merchant(&merchant).await.unwrap().sponsor);
+    let mint = setup.create_mint(DECIMALS).await;
+    println!("Registered merchant {}", merchant);
+
+    // the customer has a wallet with SOL for the fees and tokens in its
+    // associated token account
+    let customer = setup.create_buyer(&mint, 50000000).await;
+    let customer_token = get_associated_token_address(&customer.pubkey(), &spl_token::id(), &mint);
+
+    // a quick sale: the register shows the transfer request, with a reference
+    // to find the payment by
+    let reference = Pubkey::new_unique();
+    let url = TransferRequest {
+        recipient: merchant,
+        amount: Some(String::from("4.5")),
+        spl_token: Some(mint),
+        references: vec![reference],
+        label: Some(String::from("Corner Cafe")),
+        message: Some(String::from("Flat white and a croissant")),
+        memo: Option::None,
+    }
+    .to_url();
+    println!("QR code: {}", url);
+
+    // the customer's wallet scans the QR code and pays it
+    let request = TransferRequest::parse(&url).unwrap();
+    let (sale, _bump_seed) = get_order_address(&setup.program_id, &merchant, "POS-1");
+    let instructions = express_checkout_instructions(
+        setup.program_id,
+        ExpressCheckoutInstructionsArgs {
+            signer: customer.pubkey(),
+            order: sale,
+            program_owner: setup.program_owner,
+            sponsor,
+            request: &request,
+            decimals: DECIMALS,
+            order_id: String::from("POS-1"),
+            secret: String::from(""),
+        },
+    )
+    .unwrap();
+    client.send(&instructions, &customer, &[]).await.unwrap();
+    let order_account = client.get_order(&sale).await.unwrap();
+    assert_eq!(OrderStatus::Paid as u8, order_account.status);
+    assert_eq!(4500000, order_account.paid_amount);
+    println!("Quick sale paid: {}", sale);
+
+    // a tab: the register opens an invoice for the table...
+    let (tab, _bump_seed) = get_order_address(&setup.program_id, &merchant, "TABLE-7");
+    let instruction = create_invoice(
+        setup.program_id,
+        CreateInvoiceArgs {
+            signer: owner,
+            order: tab,
+            merchant,
+            seller_token: get_order_token_address(&setup.program_id, &tab, &mint),
+            mint,
+            pda: setup.pda(),
+            amount: TAB,
+            order_id: String::from("TABLE-7"),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    setup.send(&[instruction], &[]).await;
+    assert_eq!(
+        OrderStatus::Pending,
+        client.get_order_status(&tab).await.unwrap()
+    );
+
+    // ...which the customer pays when leaving
+    let instruction = pay_invoice(
+        setup.program_id,
+        PayInvoiceArgs {
+            signer: customer.pubkey(),
+            order: tab,
+            merchant,
+            seller_token: get_order_token_address(&setup.program_id, &tab, &mint),
+            buyer_token: customer_token,
+            mint,
+            program_owner: setup.program_owner,
+            sponsor,
+            integrator: Option::None,
+        },
+    );
+    client.send(&[instruction], &customer, &[]).await.unwrap();
+    assert_eq!(
+        OrderStatus::Paid,
+        client.get_order_status(&tab).await.unwrap()
+    );
+    println!("Tab paid: {}", tab);
+
+    // at the end of the day the takings go to the wallet of the owner, whose
+    // associated token account is created by the first withdrawal
+    for (order, create_merchant_token) in [(sale, true), (tab, false)].iter() {
+        let instruction = withdraw_to_wallet(
+            setup.program_id,
+            WithdrawToWalletArgs {
+                signer: owner,
+                order: *order,
+                merchant,
+                merchant_owner: owner,
+                mint,
+                account_to_receive_sol_refund: owner,
+                subscription: Option::None,
+                close_order_account: false,
+                create_merchant_token: *create_merchant_token,
+                other_tokens: vec![],
+            },
+        );
+        setup.send(&[instruction], &[]).await;
+        assert_eq!(
+            OrderStatus::Withdrawn,
+            client.get_order_status(order).await.unwrap()
+        );
+    }
+    let owner_token = get_associated_token_address(&owner, &spl_token::id(), &mint);
+    println!("Takings withdrawn to {}", owner_token);
+}
@@ -0,0 +1,154 @@
+//! SaaS subscription flow with delegated renewals
+//!
+//! A SaaS vendor sells its plan as a subscription package.  The customer pays
+//! the first period with an ExpressCheckout and subscribes with the paid order.
+//! The customer then approves an auto-debit, which lets anyone (e.g. the vendor's
+//! renewal bot) renew the subscription once it is due with RenewDueSubscriptions,
+//! without the customer having to sign again.  The vendor withdraws each payment.
+//!
+//! The plan renews as soon as it starts (its duration is 0) so that the renewal
+//! is due right away.
+//!
+//! Run with `cargo run --example saas_subscription --features client -- <PROGRAM_ID>`
+//! (see common).
+
+mod common;
+
+use common::Setup;
+use sol_payment_processor::{
+    client::Checkout,
+    engine::common::get_next_order_id,
+    instruction::{
+        approve_auto_debit, renew_due_subscriptions, subscribe, RenewDueSubscriptionsArgs,
+        SubscribeArgs,
+    },
+    pda::{get_associated_token_address, get_auto_debit_address, get_order_address},
+    state::{OrderStatus, SubscriptionStatus},
+};
+use solana_program::{pubkey::Pubkey, system_instruction};
+use solana_sdk::signature::Signer;
+
+const PLAN: &str = "pro";
+const PRICE: u64 = 5000000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let setup = Setup::start().await;
+    let client = &setup.client;
+    let owner = setup.payer.pubkey();
+    let mint = setup.create_mint(6).await;
+    let packages = format!(
+        r#"{{"packages":[{{"name":"{}","price":{},"duration":0,"mint":"{}"}}]}}"#,
+        PLAN, PRICE, mint
+    );
+    let merchant = client
+        .register_merchant(&setup.payer, Some("saas"), Option::None, Some(packages))
+        .await
+        .unwrap();
+    let sponsor = Pubkey::new_from_array(client.get_merchant(&merchant).await.unwrap().sponsor);
+    // the payments are withdrawn to the associated token account of the vendor
+    setup.create_wallet_token(&owner, &mint, 0).await;
+    let customer = setup.create_buyer(&mint, PRICE * 3).await;
+    let customer_token = get_associated_token_address(&customer.pubkey(), &spl_token::id(), &mint);
+    println!("Registered merchant {}", merchant);
+
+    // the subscription account is derived from the subscriber, the merchant and
+    // the package name
+    let (subscription, _bump_seed) = Pubkey::find_program_address(
+        &[
+            &customer.pubkey().to_bytes(),
+            &merchant.to_bytes(),
+            PLAN.as_bytes(),
+        ],
+        &setup.program_id,
+    );
+
+    // the customer pays the first period, then subscribes with the paid order
+    let checkout = Checkout {
+        merchant,
+        mint,
+        amount: PRICE,
+        order_id: String::from(PLAN),
+        data: Some(format!(r#"{{"subscription": "{}"}}"#, subscription)),
+        ..Checkout::default()
+    };
+    let first_order = client.checkout(&customer, &checkout).await.unwrap();
+    let instruction = subscribe(
+        setup.program_id,
+        SubscribeArgs {
+            signer: customer.pubkey(),
+            subscription,
+            merchant,
+            order: first_order,
+            name: String::from(PLAN),
+            data: Option::None,
+            seats: Option::None,
+        },
+    );
+    client.send(&[instruction], &customer, &[]).await.unwrap();
+    let (_address, subscription_account) = client
+        .find_subscriptions_for_merchant(&merchant)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|(address, _subscription_account)| *address == subscription)
+        .unwrap();
+    assert_eq!(
+        SubscriptionStatus::Initialized as u8,
+        subscription_account.status
+    );
+    println!("Subscribed: {}", subscription);
+
+    // the customer lets the vendor pull the price once a day, and funds the
+    // auto-debit account so that it can pay back whoever sends the renewals
+    let (auto_debit, _bump_seed) =
+        get_auto_debit_address(&setup.program_id, &merchant, &customer_token);
+    let instructions = [
+        approve_auto_debit(
+            setup.program_id,
+            customer.pubkey(),
+            merchant,
+            customer_token,
+            PRICE * 2,
+            PRICE,
+            86400,
+        ),
+        system_instruction::transfer(&customer.pubkey(), &auto_debit, 100000000),
+    ];
+    client.send(&instructions, &customer, &[]).await.unwrap();
+    println!("Auto-debit approved: {}", auto_debit);
+
+    // the renewal bot renews the subscription now that it is due, into the order
+    // numbered next in the order sequence of the merchant
+    let order_sequence = client.get_merchant(&merchant).await.unwrap().order_sequence;
+    let renewal_order_id = format!("{}-{}", PLAN, get_next_order_id(order_sequence).unwrap());
+    let (renewal_order, _bump_seed) =
+        get_order_address(&setup.program_id, &merchant, &renewal_order_id);
+    let instruction = renew_due_subscriptions(
+        setup.program_id,
+        RenewDueSubscriptionsArgs {
+            signer: owner,
+            order: renewal_order,
+            merchant,
+            subscription,
+            subscriber_token: customer_token,
+            mint,
+            program_owner: setup.program_owner,
+            sponsor,
+            guarantee: Option::None,
+        },
+    );
+    setup.send(&[instruction], &[]).await;
+    let order_account = client.get_order(&renewal_order).await.unwrap();
+    assert_eq!(OrderStatus::Paid as u8, order_account.status);
+    assert_eq!(PRICE, order_account.paid_amount);
+    println!("Subscription renewed: {}", renewal_order);
+
+    // the vendor withdraws both payments
+    let report = client.withdraw_all(&setup.payer, &merchant).await.unwrap();
+    assert!(report.failed.is_empty());
+    for order in [first_order, renewal_order].iter() {
+        assert!(report.settled.contains(order));
+    }
+    println!("Payments withdrawn");
+}
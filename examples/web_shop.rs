@@ -0,0 +1,106 @@
+//! Web shop checkout flow
+//!
+//! A web shop sells with ExpressCheckout: the buyer pays the price (plus a tip)
+//! into an order token account held by the program, and the shop withdraws it
+//! once the order ships.  An order that cannot be fulfilled is refunded with a
+//! refund code that support hands to the buyer.  When the shop closes down, it
+//! closes its merchant account to get the rent back.
+//!
+//! Run with `cargo run --example web_shop --features client -- <PROGRAM_ID>`
+//! (see common).
+
+mod common;
+
+use common::Setup;
+use sol_payment_processor::{
+    client::{Checkout, TransactionBuilder},
+    instruction::{close_merchant, redeem_refund_code},
+    pda::{get_associated_token_address, get_order_token_address},
+    state::OrderStatus,
+};
+use solana_sdk::signature::Signer;
+
+const PRICE: u64 = 2500000;
+const TIP: u64 = 100000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let setup = Setup::start().await;
+    let client = &setup.client;
+    let owner = setup.payer.pubkey();
+    let merchant = client
+        .register_merchant(
+            &setup.payer,
+            Some("web shop"),
+            Option::None,
+            Some(String::from(r#"{"name": "Web Shop"}"#)),
+        )
+        .await
+        .unwrap();
+    let mint = setup.create_mint(6).await;
+    // the payments are withdrawn to the associated token account of the owner
+    setup.create_wallet_token(&owner, &mint, 0).await;
+    let buyer = setup.create_buyer(&mint, 10000000).await;
+    println!("Registered merchant {}", merchant);
+
+    // two orders are paid at checkout, the first one with a tip
+    let mut checkout = Checkout {
+        merchant,
+        mint,
+        amount: PRICE,
+        tip: TIP,
+        order_id: String::from("WEB-1"),
+        data: Some(String::from(r#"{"email": "buyer@example.com"}"#)),
+        ..Checkout::default()
+    };
+    let shipped = client.checkout(&buyer, &checkout).await.unwrap();
+    checkout.tip = 0;
+    checkout.order_id = String::from("WEB-2");
+    let out_of_stock = client.checkout(&buyer, &checkout).await.unwrap();
+    let order_account = client.get_order(&shipped).await.unwrap();
+    assert_eq!(OrderStatus::Paid as u8, order_account.status);
+    assert_eq!(PRICE, order_account.paid_amount);
+    assert_eq!(TIP, order_account.tip);
+    println!("Order WEB-1 paid: {}", shipped);
+    println!("Order WEB-2 paid: {}", out_of_stock);
+
+    // the first order ships, and the shop withdraws the payment and the tip,
+    // which closes the order account
+    let instructions = TransactionBuilder::new(client)
+        .withdraw(&owner, &shipped)
+        .await
+        .unwrap();
+    setup.send(&instructions, &[]).await;
+    assert!(client.get_order(&shipped).await.is_err());
+    println!("Order WEB-1 withdrawn");
+
+    // the second order cannot be fulfilled: support issues a refund code that
+    // the buyer redeems to get the payment back
+    let code = "SUPPORT-0002";
+    client
+        .issue_refund_code(&setup.payer, &out_of_stock, code)
+        .await
+        .unwrap();
+    let instruction = redeem_refund_code(
+        setup.program_id,
+        buyer.pubkey(),
+        out_of_stock,
+        merchant,
+        get_order_token_address(&setup.program_id, &out_of_stock, &mint),
+        get_associated_token_address(&buyer.pubkey(), &spl_token::id(), &mint),
+        String::from(code),
+    );
+    client.send(&[instruction], &buyer, &[]).await.unwrap();
+    assert_eq!(
+        OrderStatus::Refunded,
+        client.get_order_status(&out_of_stock).await.unwrap()
+    );
+    println!("Order WEB-2 refunded");
+
+    // with no open orders left, the shop can close its merchant account
+    assert_eq!(0, client.get_merchant(&merchant).await.unwrap().references);
+    let instruction = close_merchant(setup.program_id, owner, merchant, vec![]);
+    setup.send(&[instruction], &[]).await;
+    assert!(client.get_merchant(&merchant).await.is_err());
+    println!("Merchant account closed");
+}
@@ -0,0 +1,233 @@
+//! Validator plugin that keeps a payments database of the accounts of the program
+//!
+//! The validator streams the account updates of the cluster to the plugin, which
+//! decodes the updates of the merchant, order and subscription accounts of the
+//! program into normalized rows (see rows) and writes them to Postgres (see
+//! sink).  Updates are only written once their slot is rooted, so that the
+//! database never holds the state of an abandoned fork.
+//!
+//! The plugin is loaded by starting the validator with
+//! `--accountsdb-plugin-config config.json`, where the config file gives the
+//! path of the plugin library, the address of the program and the connection
+//! string of the database (see config.json).
+
+pub mod rows;
+pub mod sink;
+
+use crate::{
+    rows::{decode_account, IndexedAccount},
+    sink::PostgresSink,
+};
+use log::warn;
+use serde::Deserialize;
+use solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+    AccountsDbPlugin, AccountsDbPluginError, ReplicaAccountInfoVersions, Result, SlotStatus,
+};
+use solana_program::{clock::Slot, pubkey::Pubkey};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    fs,
+    str::FromStr,
+};
+
+/// the config file of the plugin
+#[derive(Deserialize)]
+struct PluginConfig {
+    /// the address of the program, whose accounts are indexed
+    program_id: String,
+    /// the connection string of the payments database e.g.
+    /// "host=localhost user=solana dbname=payments"
+    connection_str: String,
+}
+
+#[derive(Default)]
+pub struct PaymentsPlugin {
+    program_id: Pubkey,
+    sink: Option<PostgresSink>,
+    /// the updates of the slots that are not rooted yet
+    pending: BTreeMap<Slot, Vec<IndexedAccount>>,
+    /// the parents of the slots that are not rooted yet
+    parents: HashMap<Slot, Slot>,
+}
+
+impl std::fmt::Debug for PaymentsPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PaymentsPlugin")
+            .field("program_id", &self.program_id)
+            .field("pending_slots", &self.pending.len())
+            .finish()
+    }
+}
+
+impl PaymentsPlugin {
+    /// Take the updates that a new root makes final
+    ///
+    /// The validator does not notify every slot that is rooted, so the updates
+    /// of the new root and of its ancestors are taken, oldest first.  The
+    /// updates of the other slots before the new root were made on abandoned
+    /// forks and are dropped.
+    fn take_rooted(&mut self, root: Slot) -> Vec<IndexedAccount> {
+        let later = self.pending.split_off(&(root + 1));
+        let mut earlier = std::mem::replace(&mut self.pending, later);
+        let mut rooted = vec![];
+        let mut slot = Some(root);
+        while let Some(current) = slot {
+            if earlier.is_empty() {
+                break;
+            }
+            if let Some(updates) = earlier.remove(&current) {
+                rooted.push(updates);
+            }
+            slot = self.parents.get(&current).copied();
+        }
+        if !earlier.is_empty() {
+            warn!(
+                "Dropping the updates of {} slots that are not ancestors of root {}",
+                earlier.len(),
+                root
+            );
+        }
+        self.parents.retain(|slot, _| *slot > root);
+        rooted.into_iter().rev().flatten().collect()
+    }
+
+    fn write(&self, updates: &[IndexedAccount]) -> Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        match &self.sink {
+            None => Err(AccountsDbPluginError::AccountsUpdateError {
+                msg: String::from("The plugin is not loaded"),
+            }),
+            Some(sink) => {
+                sink.write(updates)
+                    .map_err(|error| AccountsDbPluginError::AccountsUpdateError {
+                        msg: error.to_string(),
+                    })
+            }
+        }
+    }
+}
+
+impl AccountsDbPlugin for PaymentsPlugin {
+    fn name(&self) -> &'static str {
+        "sol-payment-processor-geyser"
+    }
+
+    fn on_load(&mut self, config_file: &str) -> Result<()> {
+        let config = fs::read_to_string(config_file)?;
+        let config: PluginConfig = serde_json::from_str(&config).map_err(|error| {
+            AccountsDbPluginError::ConfigFileReadError {
+                msg: error.to_string(),
+            }
+        })?;
+        self.program_id = Pubkey::from_str(&config.program_id).map_err(|error| {
+            AccountsDbPluginError::ConfigFileReadError {
+                msg: format!("Invalid program_id: {}", error),
+            }
+        })?;
+        let sink = PostgresSink::connect(&config.connection_str)
+            .map_err(|error| AccountsDbPluginError::Custom(Box::new(error)))?;
+        self.sink = Some(sink);
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        self.sink = None;
+        self.pending.clear();
+        self.parents.clear();
+    }
+
+    fn update_account(
+        &mut self,
+        account: ReplicaAccountInfoVersions,
+        slot: u64,
+        is_startup: bool,
+    ) -> Result<()> {
+        let ReplicaAccountInfoVersions::V0_0_1(account) = account;
+        let (address, owner) = match (account.pubkey.try_into(), account.owner.try_into()) {
+            (Ok(address), Ok(owner)) => (
+                Pubkey::new_from_array(address),
+                Pubkey::new_from_array(owner),
+            ),
+            _ => return Ok(()),
+        };
+        // accounts without lamports are removed at the end of the transaction
+        let data = if account.lamports == 0 {
+            &[][..]
+        } else {
+            account.data
+        };
+        let indexed = match decode_account(&self.program_id, &address, &owner, data, slot) {
+            None => return Ok(()),
+            Some(value) => value,
+        };
+        // the accounts of the snapshot that the validator starts from are rooted
+        if is_startup {
+            return self.write(&[indexed]);
+        }
+        self.pending.entry(slot).or_default().push(indexed);
+        Ok(())
+    }
+
+    fn notify_end_of_startup(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn update_slot_status(
+        &mut self,
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<()> {
+        if let Some(parent) = parent {
+            self.parents.insert(slot, parent);
+        }
+        if let SlotStatus::Rooted = status {
+            let updates = self.take_rooted(slot);
+            self.write(&updates)?;
+        }
+        Ok(())
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+/// # Safety
+///
+/// The validator takes ownership of the plugin, and drops it when unloading it.
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn AccountsDbPlugin {
+    let plugin: Box<dyn AccountsDbPlugin> = Box::new(PaymentsPlugin::default());
+    Box::into_raw(plugin)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn closed(slot: Slot) -> IndexedAccount {
+        IndexedAccount::Closed {
+            address: Pubkey::default().to_string(),
+            slot,
+        }
+    }
+
+    #[test]
+    fn test_take_rooted() {
+        let mut plugin = PaymentsPlugin::default();
+        // 1 <- 2 <- 4 is rooted, while 3 is on another fork of 1
+        for (slot, parent) in &[(2, 1), (3, 1), (4, 2), (5, 4)] {
+            plugin.pending.insert(*slot, vec![closed(*slot)]);
+            plugin.parents.insert(*slot, *parent);
+        }
+        plugin.pending.insert(1, vec![closed(1)]);
+
+        assert_eq!(vec![closed(1)], plugin.take_rooted(1));
+        assert_eq!(vec![closed(2), closed(4)], plugin.take_rooted(4));
+        // the updates of the abandoned fork are dropped, the later ones kept
+        assert_eq!(vec![5], plugin.pending.keys().copied().collect::<Vec<_>>());
+        assert_eq!(vec![closed(5)], plugin.take_rooted(5));
+        assert!(plugin.pending.is_empty() && plugin.parents.is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+//! Normalized rows of the accounts of the program
+//!
+//! Every update of an account owned by the program is decoded into a row of the
+//! merchants, orders or subscriptions table of POSTGRES_SCHEMA, and upserted
+//! with the statement of its table.  Keys are base58 strings and statuses are names, so
+//! that the database can be queried without knowing the account layouts.
+//!
+//! Updates are applied in slot order per account: an upsert does not overwrite
+//! a row with an update from an older slot.
+
+use num_traits::FromPrimitive;
+use sol_payment_processor::state::{
+    Discriminator, MerchantAccount, OrderAccount, OrderStatus, Serdes, SubscriptionAccount,
+    SubscriptionStatus,
+};
+use solana_program::{
+    clock::{Slot, UnixTimestamp},
+    pubkey::Pubkey,
+};
+
+/// the tables of the payments database
+pub const POSTGRES_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS merchants (
+    address TEXT PRIMARY KEY,
+    owner TEXT NOT NULL,
+    sponsor TEXT NOT NULL,
+    fee NUMERIC(20) NOT NULL,
+    is_paused BOOLEAN NOT NULL,
+    order_sequence NUMERIC(20) NOT NULL,
+    lifetime_volume NUMERIC(20) NOT NULL,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    slot BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS orders (
+    address TEXT PRIMARY KEY,
+    merchant TEXT NOT NULL,
+    payer TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    status TEXT NOT NULL,
+    expected_amount NUMERIC(20) NOT NULL,
+    paid_amount NUMERIC(20) NOT NULL,
+    tip NUMERIC(20) NOT NULL,
+    withdrawn_amount NUMERIC(20) NOT NULL,
+    sequence NUMERIC(20) NOT NULL,
+    order_id TEXT NOT NULL,
+    created BIGINT NOT NULL,
+    modified BIGINT NOT NULL,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    slot BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS orders_merchant ON orders (merchant, sequence);
+CREATE TABLE IF NOT EXISTS subscriptions (
+    address TEXT PRIMARY KEY,
+    merchant TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    name TEXT NOT NULL,
+    status TEXT NOT NULL,
+    joined BIGINT NOT NULL,
+    period_start BIGINT NOT NULL,
+    period_end BIGINT NOT NULL,
+    price NUMERIC(20) NOT NULL,
+    seats NUMERIC(20) NOT NULL,
+    closed BOOLEAN NOT NULL DEFAULT FALSE,
+    slot BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS subscriptions_merchant ON subscriptions (merchant);
+";
+
+/// the upsert of a MerchantRow, whose parameters are given by MerchantRow::params
+pub const UPSERT_MERCHANT: &str = "INSERT INTO merchants (address, owner, sponsor, fee, is_paused, order_sequence, lifetime_volume, slot) VALUES ($1, $2, $3, $4::TEXT::NUMERIC, $5, $6::TEXT::NUMERIC, $7::TEXT::NUMERIC, $8) ON CONFLICT (address) DO UPDATE SET owner = EXCLUDED.owner, sponsor = EXCLUDED.sponsor, fee = EXCLUDED.fee, is_paused = EXCLUDED.is_paused, order_sequence = EXCLUDED.order_sequence, lifetime_volume = EXCLUDED.lifetime_volume, closed = FALSE, slot = EXCLUDED.slot WHERE merchants.slot <= EXCLUDED.slot";
+/// the upsert of an OrderRow, whose parameters are given by OrderRow::params
+pub const UPSERT_ORDER: &str = "INSERT INTO orders (address, merchant, payer, mint, status, expected_amount, paid_amount, tip, withdrawn_amount, sequence, order_id, created, modified, slot) VALUES ($1, $2, $3, $4, $5, $6::TEXT::NUMERIC, $7::TEXT::NUMERIC, $8::TEXT::NUMERIC, $9::TEXT::NUMERIC, $10::TEXT::NUMERIC, $11, $12, $13, $14) ON CONFLICT (address) DO UPDATE SET status = EXCLUDED.status, expected_amount = EXCLUDED.expected_amount, paid_amount = EXCLUDED.paid_amount, tip = EXCLUDED.tip, withdrawn_amount = EXCLUDED.withdrawn_amount, modified = EXCLUDED.modified, closed = FALSE, slot = EXCLUDED.slot WHERE orders.slot <= EXCLUDED.slot";
+/// the upsert of a SubscriptionRow, whose parameters are given by SubscriptionRow::params
+pub const UPSERT_SUBSCRIPTION: &str = "INSERT INTO subscriptions (address, merchant, owner, name, status, joined, period_start, period_end, price, seats, slot) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::TEXT::NUMERIC, $10::TEXT::NUMERIC, $11) ON CONFLICT (address) DO UPDATE SET owner = EXCLUDED.owner, status = EXCLUDED.status, period_start = EXCLUDED.period_start, period_end = EXCLUDED.period_end, price = EXCLUDED.price, seats = EXCLUDED.seats, closed = FALSE, slot = EXCLUDED.slot WHERE subscriptions.slot <= EXCLUDED.slot";
+/// marks a closed account as closed in whichever table it is in - its address
+/// and the slot are the parameters
+pub const CLOSE_ACCOUNT: &[&str] = &[
+    "UPDATE merchants SET closed = TRUE, slot = $2 WHERE address = $1 AND slot <= $2",
+    "UPDATE orders SET closed = TRUE, slot = $2 WHERE address = $1 AND slot <= $2",
+    "UPDATE subscriptions SET closed = TRUE, slot = $2 WHERE address = $1 AND slot <= $2",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+/// A parameter of a statement - the u64 amounts are passed as text, which the
+/// statements cast to NUMERIC since they do not fit in a BIGINT (the parameters
+/// are cast to TEXT first, so that the server expects text for them)
+pub enum SqlValue {
+    Text(String),
+    BigInt(i64),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerchantRow {
+    pub address: String,
+    pub owner: String,
+    pub sponsor: String,
+    pub fee: u64,
+    pub is_paused: bool,
+    pub order_sequence: u64,
+    pub lifetime_volume: u64,
+    pub slot: Slot,
+}
+
+impl MerchantRow {
+    pub fn params(&self) -> Vec<SqlValue> {
+        vec![
+            SqlValue::Text(self.address.clone()),
+            SqlValue::Text(self.owner.clone()),
+            SqlValue::Text(self.sponsor.clone()),
+            SqlValue::Text(self.fee.to_string()),
+            SqlValue::Bool(self.is_paused),
+            SqlValue::Text(self.order_sequence.to_string()),
+            SqlValue::Text(self.lifetime_volume.to_string()),
+            SqlValue::BigInt(self.slot as i64),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderRow {
+    pub address: String,
+    pub merchant: String,
+    pub payer: String,
+    pub mint: String,
+    /// the name of the OrderStatus e.g. "Paid"
+    pub status: String,
+    pub expected_amount: u64,
+    pub paid_amount: u64,
+    pub tip: u64,
+    pub withdrawn_amount: u64,
+    pub sequence: u64,
+    pub order_id: String,
+    pub created: UnixTimestamp,
+    pub modified: UnixTimestamp,
+    pub slot: Slot,
+}
+
+impl OrderRow {
+    pub fn params(&self) -> Vec<SqlValue> {
+        vec![
+            SqlValue::Text(self.address.clone()),
+            SqlValue::Text(self.merchant.clone()),
+            SqlValue::Text(self.payer.clone()),
+            SqlValue::Text(self.mint.clone()),
+            SqlValue::Text(self.status.clone()),
+            SqlValue::Text(self.expected_amount.to_string()),
+            SqlValue::Text(self.paid_amount.to_string()),
+            SqlValue::Text(self.tip.to_string()),
+            SqlValue::Text(self.withdrawn_amount.to_string()),
+            SqlValue::Text(self.sequence.to_string()),
+            SqlValue::Text(self.order_id.clone()),
+            SqlValue::BigInt(self.created),
+            SqlValue::BigInt(self.modified),
+            SqlValue::BigInt(self.slot as i64),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionRow {
+    pub address: String,
+    pub merchant: String,
+    pub owner: String,
+    pub name: String,
+    /// the name of the SubscriptionStatus e.g. "Initialized"
+    pub status: String,
+    pub joined: UnixTimestamp,
+    pub period_start: UnixTimestamp,
+    pub period_end: UnixTimestamp,
+    pub price: u64,
+    pub seats: u64,
+    pub slot: Slot,
+}
+
+impl SubscriptionRow {
+    pub fn params(&self) -> Vec<SqlValue> {
+        vec![
+            SqlValue::Text(self.address.clone()),
+            SqlValue::Text(self.merchant.clone()),
+            SqlValue::Text(self.owner.clone()),
+            SqlValue::Text(self.name.clone()),
+            SqlValue::Text(self.status.clone()),
+            SqlValue::BigInt(self.joined),
+            SqlValue::BigInt(self.period_start),
+            SqlValue::BigInt(self.period_end),
+            SqlValue::Text(self.price.to_string()),
+            SqlValue::Text(self.seats.to_string()),
+            SqlValue::BigInt(self.slot as i64),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// An account update, as a row of the payments database
+pub enum IndexedAccount {
+    Merchant(MerchantRow),
+    Order(OrderRow),
+    Subscription(SubscriptionRow),
+    /// the account was closed (or emptied), see CLOSE_ACCOUNT
+    Closed {
+        address: String,
+        slot: Slot,
+    },
+}
+
+impl IndexedAccount {
+    /// Get the statements that apply the update, with their parameters
+    pub fn statements(&self) -> Vec<(&'static str, Vec<SqlValue>)> {
+        match self {
+            IndexedAccount::Merchant(row) => vec![(UPSERT_MERCHANT, row.params())],
+            IndexedAccount::Order(row) => vec![(UPSERT_ORDER, row.params())],
+            IndexedAccount::Subscription(row) => vec![(UPSERT_SUBSCRIPTION, row.params())],
+            IndexedAccount::Closed { address, slot } => CLOSE_ACCOUNT
+                .iter()
+                .map(|statement| {
+                    (
+                        *statement,
+                        vec![
+                            SqlValue::Text(address.clone()),
+                            SqlValue::BigInt(*slot as i64),
+                        ],
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+fn get_status_name<T: FromPrimitive + std::fmt::Debug>(status: u8) -> String {
+    match T::from_u8(status) {
+        Some(value) => format!("{:?}", value),
+        None => status.to_string(),
+    }
+}
+
+/// Decode an update of an account into its row
+///
+/// Returns None for accounts that are not owned by the program, and for the
+/// accounts of the program that are not indexed.
+pub fn decode_account(
+    program_id: &Pubkey,
+    address: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+    slot: Slot,
+) -> Option<IndexedAccount> {
+    if owner != program_id {
+        return Option::None;
+    }
+    let key = |value: &[u8; 32]| Pubkey::new_from_array(*value).to_string();
+    let discriminator = match data.first() {
+        None => Discriminator::Closed as u8,
+        Some(value) => *value,
+    };
+    if discriminator == Discriminator::Closed as u8 {
+        return Some(IndexedAccount::Closed {
+            address: address.to_string(),
+            slot,
+        });
+    }
+    if MerchantAccount::DISCRIMINATORS.contains(&discriminator) {
+        let merchant_account = MerchantAccount::unpack(data).ok()?;
+        return Some(IndexedAccount::Merchant(MerchantRow {
+            address: address.to_string(),
+            owner: key(&merchant_account.owner),
+            sponsor: key(&merchant_account.sponsor),
+            fee: merchant_account.fee,
+            is_paused: merchant_account.is_paused,
+            order_sequence: merchant_account.order_sequence,
+            lifetime_volume: merchant_account.lifetime_volume,
+            slot,
+        }));
+    }
+    if OrderAccount::DISCRIMINATORS.contains(&discriminator) {
+        let order_account = OrderAccount::unpack(data).ok()?;
+        return Some(IndexedAccount::Order(OrderRow {
+            address: address.to_string(),
+            merchant: key(&order_account.merchant),
+            payer: key(&order_account.payer),
+            mint: key(&order_account.mint),
+            status: get_status_name::<OrderStatus>(order_account.status),
+            expected_amount: order_account.expected_amount,
+            paid_amount: order_account.paid_amount,
+            tip: order_account.tip,
+            withdrawn_amount: order_account.withdrawn_amount,
+            sequence: order_account.sequence,
+            order_id: order_account.order_id,
+            created: order_account.created,
+            modified: order_account.modified,
+            slot,
+        }));
+    }
+    if SubscriptionAccount::DISCRIMINATORS.contains(&discriminator) {
+        let subscription_account = SubscriptionAccount::unpack(data).ok()?;
+        return Some(IndexedAccount::Subscription(SubscriptionRow {
+            address: address.to_string(),
+            merchant: key(&subscription_account.merchant),
+            owner: key(&subscription_account.owner),
+            name: subscription_account.name,
+            status: get_status_name::<SubscriptionStatus>(subscription_account.status),
+            joined: subscription_account.joined,
+            period_start: subscription_account.period_start,
+            period_end: subscription_account.period_end,
+            price: subscription_account.price,
+            seats: subscription_account.seats,
+            slot,
+        }));
+    }
+    Option::None
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, sol_payment_processor::engine::constants::ACCOUNT_VERSION};
+
+    #[test]
+    fn test_decode_account() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let order_account = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 100,
+            modified: 200,
+            merchant: merchant.to_bytes(),
+            mint: mint.to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: payer.to_bytes(),
+            expected_amount: u64::MAX,
+            paid_amount: 1000,
+            tip: 10,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 7,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("order-1"),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let data = order_account.to_account_data();
+
+        // accounts of other programs are not indexed
+        assert_eq!(
+            None,
+            decode_account(&program_id, &address, &Pubkey::new_unique(), &data, 5)
+        );
+
+        let indexed = decode_account(&program_id, &address, &program_id, &data, 5).unwrap();
+        assert_eq!(
+            IndexedAccount::Order(OrderRow {
+                address: address.to_string(),
+                merchant: merchant.to_string(),
+                payer: payer.to_string(),
+                mint: mint.to_string(),
+                status: String::from("Paid"),
+                expected_amount: u64::MAX,
+                paid_amount: 1000,
+                tip: 10,
+                withdrawn_amount: 0,
+                sequence: 7,
+                order_id: String::from("order-1"),
+                created: 100,
+                modified: 200,
+                slot: 5,
+            }),
+            indexed
+        );
+        let statements = indexed.statements();
+        assert_eq!(1, statements.len());
+        assert_eq!(UPSERT_ORDER, statements[0].0);
+        // as many parameters as the statement has
+        assert_eq!(14, statements[0].1.len());
+        assert!(UPSERT_ORDER.contains("$14") && !UPSERT_ORDER.contains("$15"));
+        // amounts are passed as text, since they do not fit in a BIGINT
+        assert_eq!(SqlValue::Text(u64::MAX.to_string()), statements[0].1[5]);
+
+        // closed accounts are marked as closed in every table
+        let indexed = decode_account(&program_id, &address, &program_id, &[], 6).unwrap();
+        assert_eq!(
+            IndexedAccount::Closed {
+                address: address.to_string(),
+                slot: 6
+            },
+            indexed
+        );
+        assert_eq!(3, indexed.statements().len());
+
+        // other accounts of the program are not indexed
+        let mut data = data;
+        data[0] = Discriminator::Coupon as u8;
+        assert_eq!(
+            None,
+            decode_account(&program_id, &address, &program_id, &data, 5)
+        );
+    }
+
+    #[test]
+    fn test_decode_subscription_account() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let subscription_account = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::PastDue as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            merchant: Pubkey::new_unique().to_bytes(),
+            name: String::from("basic"),
+            joined: 1,
+            period_start: 1,
+            period_end: 2,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 3,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let data = subscription_account.to_account_data();
+
+        match decode_account(&program_id, &address, &program_id, &data, 9) {
+            Some(IndexedAccount::Subscription(row)) => {
+                assert_eq!("PastDue", row.status);
+                assert_eq!("basic", row.name);
+                assert_eq!(3, row.seats);
+                assert_eq!(11, row.params().len());
+                assert!(UPSERT_SUBSCRIPTION.contains("$11"));
+            }
+            other => panic!("Not a subscription row {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! The Postgres database that the rows are written to
+
+use crate::rows::{IndexedAccount, SqlValue, POSTGRES_SCHEMA};
+use postgres::{types::ToSql, Client, NoTls};
+use std::sync::Mutex;
+
+/// A connection to the payments database
+///
+/// The validator calls the plugin from several threads, so the connection is
+/// used by one of them at a time.
+pub struct PostgresSink {
+    client: Mutex<Client>,
+}
+
+impl PostgresSink {
+    /// Connect to the database, creating the tables of POSTGRES_SCHEMA unless
+    /// they exist
+    pub fn connect(connection_str: &str) -> Result<Self, postgres::Error> {
+        let mut client = Client::connect(connection_str, NoTls)?;
+        client.batch_execute(POSTGRES_SCHEMA)?;
+        Ok(PostgresSink {
+            client: Mutex::new(client),
+        })
+    }
+
+    /// Write the updates in a single transaction, in their order
+    pub fn write(&self, updates: &[IndexedAccount]) -> Result<(), postgres::Error> {
+        let mut client = self.client.lock().unwrap();
+        let mut transaction = client.transaction()?;
+        for update in updates {
+            for (statement, params) in update.statements() {
+                let params: Vec<Box<dyn ToSql + Sync>> =
+                    params.into_iter().map(get_sql_param).collect();
+                let param_refs: Vec<&(dyn ToSql + Sync)> =
+                    params.iter().map(|param| param.as_ref()).collect();
+                transaction.execute(statement, &param_refs)?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+fn get_sql_param(value: SqlValue) -> Box<dyn ToSql + Sync> {
+    match value {
+        SqlValue::Text(value) => Box::new(value),
+        SqlValue::BigInt(value) => Box::new(value),
+        SqlValue::Bool(value) => Box::new(value),
+    }
+}
@@ -0,0 +1,185 @@
+//! Payment link analytics
+//!
+//! Meant to be used off-chain by merchants to see which of their payment links
+//! drove revenue.  The counters are kept on-chain by the payment link accounts,
+//! which can be fetched using getProgramAccounts with the filters found here.
+
+use crate::state::{Discriminator, PaymentLinkAccount};
+use serde::Serialize;
+use solana_program::{clock::UnixTimestamp, pubkey::Pubkey};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// The statistics of a payment link
+pub struct LinkStats {
+    /// the payment link account
+    pub link: String,
+    pub link_id: String,
+    pub mint: String,
+    pub payments: u64,
+    pub volume: u64,
+    pub unique_payers: u64,
+    /// the volume divided by the number of payments
+    pub average_payment: u64,
+    /// the share (in basis points) of the volume of all the links paid in the same mint
+    pub volume_share: u64,
+    pub last_paid: UnixTimestamp,
+}
+
+/// getProgramAccounts memcmp filters (as offset and bytes) that match the
+/// payment link accounts of a merchant
+pub fn payment_link_filters(merchant: &Pubkey) -> Vec<(usize, Vec<u8>)> {
+    vec![
+        (0, vec![Discriminator::PaymentLink as u8]),
+        (1, merchant.to_bytes().to_vec()),
+    ]
+}
+
+/// getProgramAccounts memcmp filters (as offset and bytes) that match the
+/// accounts of the wallets that paid through a payment link
+pub fn link_payer_filters(link: &Pubkey) -> Vec<(usize, Vec<u8>)> {
+    vec![
+        (0, vec![Discriminator::LinkPayer as u8]),
+        (1, link.to_bytes().to_vec()),
+    ]
+}
+
+/// Get the statistics of the payment links of a merchant
+///
+/// The links are expected as (address, payment link account) pairs e.g. as
+/// returned by getProgramAccounts.  Links that belong to other merchants are
+/// ignored.  The links that drove the most volume come first.
+pub fn link_stats(merchant: &Pubkey, links: &[(Pubkey, PaymentLinkAccount)]) -> Vec<LinkStats> {
+    let links: Vec<&(Pubkey, PaymentLinkAccount)> = links
+        .iter()
+        .filter(|(_address, link_account)| link_account.merchant == merchant.to_bytes())
+        .collect();
+
+    // volumes in different mints cannot be added up
+    let mut mint_volumes: BTreeMap<[u8; 32], u128> = BTreeMap::new();
+    for (_address, link_account) in links.iter() {
+        *mint_volumes.entry(link_account.mint).or_insert(0) += link_account.volume as u128;
+    }
+
+    let mut stats: Vec<LinkStats> = links
+        .iter()
+        .map(|(address, link_account)| {
+            let mint_volume = mint_volumes[&link_account.mint];
+            LinkStats {
+                link: address.to_string(),
+                link_id: link_account.link_id.clone(),
+                mint: Pubkey::new_from_array(link_account.mint).to_string(),
+                payments: link_account.payments,
+                volume: link_account.volume,
+                unique_payers: link_account.unique_payers,
+                average_payment: match link_account.payments {
+                    0 => 0,
+                    payments => link_account.volume / payments,
+                },
+                volume_share: match mint_volume {
+                    0 => 0,
+                    value => (link_account.volume as u128 * 10000 / value) as u64,
+                },
+                last_paid: link_account.last_paid,
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| b.volume.cmp(&a.volume));
+    stats
+}
+
+/// Get the statistics of the payment links of a merchant as a JSON string
+pub fn link_stats_to_json(merchant: &Pubkey, links: &[(Pubkey, PaymentLinkAccount)]) -> String {
+    serde_json::to_string(&link_stats(merchant, links)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::engine::constants::ACCOUNT_VERSION, solana_program_test::*};
+
+    fn link(
+        merchant: &Pubkey,
+        mint: &Pubkey,
+        link_id: &str,
+        payments: u64,
+        volume: u64,
+    ) -> PaymentLinkAccount {
+        PaymentLinkAccount {
+            discriminator: Discriminator::PaymentLink as u8,
+            merchant: merchant.to_bytes(),
+            mint: mint.to_bytes(),
+            created: 0,
+            last_paid: 0,
+            payments,
+            volume,
+            unique_payers: payments,
+            amount: 0,
+            link_id: String::from(link_id),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_link_stats() {
+        let merchant = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let links = vec![
+            (
+                Pubkey::new_unique(),
+                link(&merchant, &usdc, "twitter", 3, 300),
+            ),
+            (
+                Pubkey::new_unique(),
+                link(&merchant, &usdc, "newsletter", 1, 700),
+            ),
+            (
+                Pubkey::new_unique(),
+                link(&merchant, &other_mint, "qr", 0, 0),
+            ),
+            // other merchants' links are ignored
+            (
+                Pubkey::new_unique(),
+                link(&Pubkey::new_unique(), &usdc, "twitter", 5, 5000),
+            ),
+        ];
+        let stats = link_stats(&merchant, &links);
+        assert_eq!(3, stats.len());
+        assert_eq!(
+            LinkStats {
+                link: links[1].0.to_string(),
+                link_id: String::from("newsletter"),
+                mint: usdc.to_string(),
+                payments: 1,
+                volume: 700,
+                unique_payers: 1,
+                average_payment: 700,
+                volume_share: 7000,
+                last_paid: 0,
+            },
+            stats[0]
+        );
+        assert_eq!("twitter", stats[1].link_id);
+        assert_eq!(100, stats[1].average_payment);
+        assert_eq!(3000, stats[1].volume_share);
+        assert_eq!("qr", stats[2].link_id);
+        assert_eq!(0, stats[2].average_payment);
+        assert_eq!(0, stats[2].volume_share);
+
+        let json_value: serde_json::Value =
+            serde_json::from_str(&link_stats_to_json(&merchant, &links)).unwrap();
+        assert_eq!("newsletter", json_value[0]["link_id"]);
+        assert_eq!(700, json_value[0]["volume"]);
+    }
+
+    #[tokio::test]
+    async fn test_filters() {
+        let merchant = Pubkey::new_unique();
+        let filters = payment_link_filters(&merchant);
+        assert_eq!((0, vec![80]), filters[0]);
+        assert_eq!((1, merchant.to_bytes().to_vec()), filters[1]);
+        let link = Pubkey::new_unique();
+        assert_eq!((0, vec![81]), link_payer_filters(&link)[0]);
+    }
+}
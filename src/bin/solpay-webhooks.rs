@@ -0,0 +1,181 @@
+//! Webhook relay
+//!
+//! A reference service that follows the transaction logs of the program over
+//! the websocket of a cluster, and POSTs the events of the merchants listed in
+//! its config file to their webhook URLs (see crate::webhook).  Deliveries that
+//! fail are retried with an exponential backoff.
+//!
+//! Build with `cargo build --release --features webhook --bin solpay-webhooks`.
+
+use sol_payment_processor::{
+    events::{find_events, PaymentProcessorEvent},
+    state::{OrderAccount, Serdes, SubscriptionAccount},
+    webhook::{
+        get_event_account, get_webhook, sign_webhook, Webhook, WebhookConfig, WebhookEndpoint,
+        EVENT_HEADER, SIGNATURE_HEADER,
+    },
+};
+use solana_client::{
+    pubsub_client::PubsubClient,
+    rpc_client::RpcClient,
+    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_program::pubkey::Pubkey;
+use std::{fs, process, str::FromStr, thread, time::Duration};
+
+const USAGE: &str = "Usage: solpay-webhooks --program-id <PROGRAM_ID> --config <CONFIG_FILE> [--url <RPC_URL>] [--ws-url <WEBSOCKET_URL>]";
+
+/// the RPC URL used when none is given
+const DEFAULT_URL: &str = "http://localhost:8899";
+/// the number of times that a webhook is sent before giving up on it
+const MAX_ATTEMPTS: u32 = 6;
+/// the wait before the first retry, doubled after every attempt
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Get the websocket URL of an RPC URL, which is served on the next port
+fn get_ws_url(url: &str) -> String {
+    let ws_url = url.replacen("http", "ws", 1);
+    match ws_url.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(value) => format!("{}:{}", host, value + 1),
+            Err(_error) => ws_url,
+        },
+        None => ws_url,
+    }
+}
+
+/// Read the merchant of an event from the account it names, for the events that
+/// do not name their merchant
+fn get_account_merchant(rpc: &RpcClient, account: &Pubkey) -> Option<Pubkey> {
+    let data = rpc.get_account_data(account).ok()?;
+    let merchant = match OrderAccount::unpack(&data) {
+        Ok(order_account) => order_account.merchant,
+        Err(_error) => SubscriptionAccount::unpack(&data).ok()?.merchant,
+    };
+    Some(Pubkey::new_from_array(merchant))
+}
+
+/// POST a webhook to the endpoint of its merchant, retrying until it is accepted
+fn deliver(endpoint: WebhookEndpoint, webhook: Webhook) {
+    let body = serde_json::to_vec(&webhook).unwrap();
+    let signature = sign_webhook(&endpoint.secret, &body);
+    let client = reqwest::blocking::Client::new();
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, &webhook.event_type)
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send();
+        match result {
+            Ok(response) if response.status().is_success() => {
+                println!("Delivered {} {}", webhook.event_type, webhook.id);
+                return;
+            }
+            Ok(response) => eprintln!(
+                "Attempt {} of {} failed: {}",
+                attempt,
+                webhook.id,
+                response.status()
+            ),
+            Err(error) => eprintln!("Attempt {} of {} failed: {}", attempt, webhook.id, error),
+        }
+        thread::sleep(delay);
+        delay *= 2;
+    }
+    eprintln!("Gave up on {} {}", webhook.event_type, webhook.id);
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut url = String::from(DEFAULT_URL);
+    let mut ws_url = Option::None;
+    let mut program_id = Option::None;
+    let mut config_path = Option::None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next().cloned().ok_or_else(|| String::from(USAGE))?;
+        match arg.as_str() {
+            "--url" => url = value,
+            "--ws-url" => ws_url = Some(value),
+            "--program-id" => program_id = Some(value),
+            "--config" => config_path = Some(value),
+            _ => return Err(String::from(USAGE)),
+        }
+    }
+    let program_id = program_id.ok_or_else(|| String::from(USAGE))?;
+    Pubkey::from_str(&program_id).map_err(|_error| format!("Invalid program id {}", program_id))?;
+    let config_path = config_path.ok_or_else(|| String::from(USAGE))?;
+    let config: WebhookConfig = serde_json::from_str(
+        &fs::read_to_string(&config_path)
+            .map_err(|error| format!("Cannot read {}: {}", config_path, error))?,
+    )
+    .map_err(|error| format!("Invalid config {}: {}", config_path, error))?;
+    let ws_url = ws_url.unwrap_or_else(|| get_ws_url(&url));
+
+    let rpc = RpcClient::new(url);
+    let (_subscription, receiver) = PubsubClient::logs_subscribe(
+        &ws_url,
+        RpcTransactionLogsFilter::Mentions(vec![program_id]),
+        RpcTransactionLogsConfig { commitment: None },
+    )
+    .map_err(|error| format!("Cannot subscribe to {}: {}", ws_url, error))?;
+    println!(
+        "Relaying the events of {} merchants",
+        config.merchants.len()
+    );
+
+    for response in receiver.iter() {
+        let logs = response.value;
+        // failed transactions change nothing
+        if logs.err.is_some() {
+            continue;
+        }
+        for (index, event) in find_events(&logs.logs).iter().enumerate() {
+            let merchant = match (event, get_event_account(event)) {
+                (PaymentProcessorEvent::OrderPaid { merchant, .. }, _) => {
+                    Pubkey::new_from_array(*merchant)
+                }
+                (_, Some(account)) => match get_account_merchant(&rpc, &account) {
+                    Some(value) => value,
+                    None => {
+                        eprintln!("Cannot read the merchant of {}", account);
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            let endpoint = match config.get_endpoint(&merchant) {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            if let Some(webhook) = get_webhook(event, &merchant, &logs.signature, index) {
+                thread::spawn(move || deliver(endpoint, webhook));
+            }
+        }
+    }
+    Err(String::from("The websocket subscription ended"))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(error) = run(&args) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_ws_url() {
+        assert_eq!("ws://localhost:8900", get_ws_url("http://localhost:8899"));
+        assert_eq!(
+            "wss://api.devnet.solana.com",
+            get_ws_url("https://api.devnet.solana.com")
+        );
+    }
+}
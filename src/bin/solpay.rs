@@ -0,0 +1,324 @@
+//! Merchant command line tool
+//!
+//! Lets merchants run their merchant account without writing code: register,
+//! manage the packages of their catalog, list their orders and subscriptions,
+//! withdraw their orders and authorize refunds.  Every command acts on the
+//! merchant account of the keypair, registered with the given seed (or MERCHANT
+//! when there is none).
+//!
+//! Build with `cargo build --release --features cli --bin solpay`.
+
+use sol_payment_processor::{
+    client::PaymentProcessorClient,
+    pda::get_merchant_address,
+    state::{OrderAccount, SubscriptionAccount},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use std::{fs, process, str::FromStr};
+
+const USAGE: &str = "Usage: solpay --program-id <PROGRAM_ID> [OPTIONS] <COMMAND>
+
+Commands:
+  register [--fee <LAMPORTS>] [--data <JSON>]  register the merchant account
+  catalog add <PACKAGE_JSON_FILE>              add a subscription package to the catalog
+  catalog remove <NAME>                        retire a subscription package of the catalog
+  orders list                                  list the orders of the merchant
+  withdraw --all                               withdraw every order that can be withdrawn
+  subscriptions list                           list the subscriptions to the merchant
+  refund <ORDER> <CODE>                        authorize the refund of an order with a refund code
+
+Options:
+  --url <URL>           the RPC URL of the cluster [default: http://localhost:8899]
+  --keypair <PATH>      the keypair file of the merchant owner [default: ~/.config/solana/id.json]
+  --seed <SEED>         the seed of the merchant account [default: merchant]";
+
+/// the RPC URL used when none is given
+const DEFAULT_URL: &str = "http://localhost:8899";
+/// the keypair file used when none is given, relative to the home directory
+const DEFAULT_KEYPAIR: &str = ".config/solana/id.json";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Register {
+        fee: Option<u64>,
+        data: Option<String>,
+    },
+    CatalogAdd {
+        path: String,
+    },
+    CatalogRemove {
+        name: String,
+    },
+    OrdersList,
+    WithdrawAll,
+    SubscriptionsList,
+    Refund {
+        order: Pubkey,
+        code: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    url: String,
+    keypair: Option<String>,
+    program_id: Pubkey,
+    seed: Option<String>,
+    command: Command,
+}
+
+/// Parse the command line arguments (without the name of the binary)
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut url = String::from(DEFAULT_URL);
+    let mut keypair = Option::None;
+    let mut program_id = Option::None;
+    let mut seed = Option::None;
+    let mut fee = Option::None;
+    let mut data = Option::None;
+    let mut all = false;
+    let mut words = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--url" => url = value()?,
+            "--keypair" => keypair = Some(value()?),
+            "--program-id" => program_id = Some(value()?),
+            "--seed" => seed = Some(value()?),
+            "--fee" => {
+                let fee_value = value()?;
+                fee = Some(
+                    fee_value
+                        .parse()
+                        .map_err(|_error| format!("Invalid fee {}", fee_value))?,
+                );
+            }
+            "--data" => data = Some(value()?),
+            "--all" => all = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => words.push(arg.as_str()),
+        }
+    }
+
+    let program_id = match program_id {
+        None => return Err(String::from("--program-id is required")),
+        Some(value) => {
+            Pubkey::from_str(&value).map_err(|_error| format!("Invalid program id {}", value))?
+        }
+    };
+    let command = match words.as_slice() {
+        ["register"] => Command::Register { fee, data },
+        ["catalog", "add", path] => Command::CatalogAdd {
+            path: path.to_string(),
+        },
+        ["catalog", "remove", name] => Command::CatalogRemove {
+            name: name.to_string(),
+        },
+        ["orders", "list"] => Command::OrdersList,
+        ["withdraw"] if all => Command::WithdrawAll,
+        ["subscriptions", "list"] => Command::SubscriptionsList,
+        ["refund", order, code] => Command::Refund {
+            order: Pubkey::from_str(order).map_err(|_error| format!("Invalid order {}", order))?,
+            code: code.to_string(),
+        },
+        _ => return Err(String::from(USAGE)),
+    };
+    Ok(Args {
+        url,
+        keypair,
+        program_id,
+        seed,
+        command,
+    })
+}
+
+fn read_keypair(path: Option<String>) -> Result<Keypair, String> {
+    let path = match path {
+        Some(value) => value,
+        None => {
+            let home = std::env::var("HOME").map_err(|error| error.to_string())?;
+            format!("{}/{}", home, DEFAULT_KEYPAIR)
+        }
+    };
+    read_keypair_file(&path).map_err(|error| format!("Cannot read keypair {}: {}", path, error))
+}
+
+fn print_order(address: &Pubkey, order_account: &OrderAccount) {
+    println!(
+        "{}\t{}\tstatus {}\tpaid {} of {}\tmint {}",
+        address,
+        order_account.order_id,
+        order_account.status,
+        order_account.paid_amount,
+        order_account.expected_amount,
+        Pubkey::new_from_array(order_account.mint)
+    );
+}
+
+fn print_subscription(address: &Pubkey, subscription_account: &SubscriptionAccount) {
+    println!(
+        "{}\t{}\tstatus {}\tsubscriber {}\tperiod end {}",
+        address,
+        subscription_account.name,
+        subscription_account.status,
+        Pubkey::new_from_array(subscription_account.owner),
+        subscription_account.period_end
+    );
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let owner = read_keypair(args.keypair)?;
+    let client = PaymentProcessorClient::new(RpcClient::new(args.url), args.program_id);
+    let merchant = get_merchant_address(&args.program_id, &owner.pubkey(), args.seed.as_deref())
+        .map_err(|error| error.to_string())?;
+
+    match args.command {
+        Command::Register { fee, data } => {
+            let merchant = client
+                .register_merchant(&owner, args.seed.as_deref(), fee, data)
+                .await
+                .map_err(|error| error.to_string())?;
+            println!("Registered merchant account {}", merchant);
+        }
+        Command::CatalogAdd { path } => {
+            let package = fs::read_to_string(&path)
+                .map_err(|error| format!("Cannot read {}: {}", path, error))?;
+            let package = client
+                .create_package(&owner, &merchant, package)
+                .await
+                .map_err(|error| error.to_string())?;
+            println!("Added package account {}", package);
+        }
+        Command::CatalogRemove { name } => {
+            client
+                .retire_package(&owner, &merchant, &name)
+                .await
+                .map_err(|error| error.to_string())?;
+            println!("Retired package {}", name);
+        }
+        Command::OrdersList => {
+            let mut orders = client
+                .find_orders_for_merchant(&merchant)
+                .await
+                .map_err(|error| error.to_string())?;
+            orders.sort_by_key(|(_address, order_account)| order_account.created);
+            for (address, order_account) in orders.iter() {
+                print_order(address, order_account);
+            }
+        }
+        Command::WithdrawAll => {
+            let report = client
+                .withdraw_all(&owner, &merchant)
+                .await
+                .map_err(|error| error.to_string())?;
+            for order in report.settled.iter() {
+                println!("Withdrew order {}", order);
+            }
+            for (order, error) in report.failed.iter() {
+                eprintln!("Failed to withdraw order {}: {}", order, error);
+            }
+        }
+        Command::SubscriptionsList => {
+            let subscriptions = client
+                .find_subscriptions_for_merchant(&merchant)
+                .await
+                .map_err(|error| error.to_string())?;
+            for (address, subscription_account) in subscriptions.iter() {
+                print_subscription(address, subscription_account);
+            }
+        }
+        Command::Refund { order, code } => {
+            client
+                .issue_refund_code(&owner, &order, &code)
+                .await
+                .map_err(|error| error.to_string())?;
+            println!("Issued refund code {} for order {}", code, order);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match parse_args(&args) {
+        Ok(args) => run(args).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let program_id = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+
+        let args = parse_args(&to_args(&format!(
+            "--program-id {} --seed shop register --fee 5000",
+            program_id
+        )))
+        .unwrap();
+        assert_eq!(
+            Args {
+                url: String::from(DEFAULT_URL),
+                keypair: Option::None,
+                program_id,
+                seed: Some(String::from("shop")),
+                command: Command::Register {
+                    fee: Some(5000),
+                    data: Option::None
+                },
+            },
+            args
+        );
+
+        let args = parse_args(&to_args(&format!(
+            "refund {} secret --program-id {} --url https://api.devnet.solana.com",
+            order, program_id
+        )))
+        .unwrap();
+        assert_eq!("https://api.devnet.solana.com", args.url);
+        assert_eq!(
+            Command::Refund {
+                order,
+                code: String::from("secret")
+            },
+            args.command
+        );
+
+        let args = parse_args(&to_args(&format!(
+            "--program-id {} catalog remove basic",
+            program_id
+        )))
+        .unwrap();
+        assert_eq!(
+            Command::CatalogRemove {
+                name: String::from("basic")
+            },
+            args.command
+        );
+
+        // withdrawing needs --all
+        assert!(parse_args(&to_args(&format!("--program-id {} withdraw", program_id))).is_err());
+        assert!(parse_args(&to_args("orders list")).is_err());
+        assert!(parse_args(&to_args(&format!("--program-id {} --fee", program_id))).is_err());
+    }
+}
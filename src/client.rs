@@ -0,0 +1,692 @@
+//! RPC client of the program
+//!
+//! Meant to be used off-chain by integrators (shops, backends, bots) to talk to
+//! the program through the JSON RPC API of a cluster, instead of copy-pasting the
+//! transactions built by the tests.  `PaymentProcessorClient` registers merchants,
+//! takes checkouts and reads orders.  `TransactionBuilder` builds the instructions
+//! from high-level inputs, for callers that send the transactions themselves.
+//! Transactions can use a durable nonce instead of a recent blockhash
+//! (see get_durable_nonce_transaction), to be signed offline.
+//! The addresses derived by the program are found in `pda`.
+//!
+//! The RpcClient of this Solana version is blocking, so every RPC call is run on
+//! the blocking threads of the tokio runtime.
+
+use crate::{
+    engine::{associated_token::create_associated_token_account_instruction, json::Package},
+    filters::{get_legacy_filter, get_merchant_orders_filter, get_merchant_subscriptions_filter},
+    instruction::{
+        create_package, express_checkout_from_wallet, issue_refund_code, register_merchant,
+        retire_package, ExpressCheckoutFromWalletArgs, RegisterMerchantArgs,
+    },
+    pda::{
+        get_associated_token_address, get_merchant_address, get_order_address, get_package_address,
+        get_program_config_address,
+    },
+    settlement::{plan_settlement, SettlementPolicy, SettlementReport},
+    state::{
+        MerchantAccount, OrderAccount, OrderStatus, ProgramConfigAccount, Serdes,
+        SubscriptionAccount,
+    },
+};
+use num_traits::FromPrimitive;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    client_error::ClientError as RpcClientError,
+    nonce_utils,
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::RpcFilterType,
+};
+use solana_program::{
+    hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey,
+    system_instruction::advance_nonce_account,
+};
+use solana_sdk::{
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ClientError {
+    /// The RPC request failed, or the transaction was rejected
+    #[error("Error: RPC request failed: {0}")]
+    Rpc(String),
+    /// The account is not an account of the expected type
+    #[error("Error: Invalid account {0}")]
+    InvalidAccount(Pubkey),
+    /// The seed of a merchant account is longer than a seed can be
+    #[error("Error: Invalid seed {0}")]
+    InvalidSeed(String),
+    /// The package is not a valid package JSON (see engine::json::Package)
+    #[error("Error: Invalid package {0}")]
+    InvalidPackage(String),
+    /// The order account has a status that this client does not know
+    #[error("Error: Unknown order status {0}")]
+    UnknownOrderStatus(u8),
+    /// The order cannot be withdrawn now e.g. it is not paid or still in escrow
+    #[error("Error: Order {0} cannot be withdrawn")]
+    NotWithdrawable(Pubkey),
+    /// The account is not an initialized nonce account
+    #[error("Error: Invalid nonce account {0}")]
+    InvalidNonce(Pubkey),
+}
+
+impl From<RpcClientError> for ClientError {
+    fn from(error: RpcClientError) -> Self {
+        ClientError::Rpc(error.to_string())
+    }
+}
+
+/// A client of an instance of the program
+#[derive(Clone)]
+pub struct PaymentProcessorClient {
+    rpc: Arc<RpcClient>,
+    program_id: Pubkey,
+}
+
+impl PaymentProcessorClient {
+    pub fn new(rpc: RpcClient, program_id: Pubkey) -> Self {
+        PaymentProcessorClient {
+            rpc: Arc::new(rpc),
+            program_id,
+        }
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// Run an RPC call on the blocking threads of the runtime
+    async fn call<T, F>(&self, function: F) -> Result<T, ClientError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&RpcClient) -> Result<T, ClientError> + Send + 'static,
+    {
+        let rpc = self.rpc.clone();
+        tokio::task::spawn_blocking(move || function(&rpc))
+            .await
+            .map_err(|error| ClientError::Rpc(error.to_string()))?
+    }
+
+    /// Get an account of the program
+    async fn get_account<T: Serdes + Send + 'static>(
+        &self,
+        address: &Pubkey,
+    ) -> Result<T, ClientError> {
+        let address = *address;
+        let data = self
+            .call(move |rpc| Ok(rpc.get_account_data(&address)?))
+            .await?;
+        T::unpack(&data).map_err(|_error| ClientError::InvalidAccount(address))
+    }
+
+    /// Check whether an account exists, whoever owns it
+    async fn account_exists(&self, address: &Pubkey) -> Result<bool, ClientError> {
+        let address = *address;
+        self.call(move |rpc| {
+            Ok(rpc
+                .get_account_with_commitment(&address, rpc.commitment())?
+                .value
+                .is_some())
+        })
+        .await
+    }
+
+    /// Sign the instructions with the payer (and the other signers) and send them
+    /// in a transaction, waiting for it to be confirmed
+    pub async fn send(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        signers: &[&Keypair],
+    ) -> Result<Signature, ClientError> {
+        let (recent_blockhash, _fee_calculator) =
+            self.call(|rpc| Ok(rpc.get_recent_blockhash()?)).await?;
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&payer.pubkey()));
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+        transaction.sign(&all_signers, recent_blockhash);
+        self.call(move |rpc| Ok(rpc.send_and_confirm_transaction(&transaction)?))
+            .await
+    }
+
+    /// Send a transaction that was signed elsewhere e.g. on an offline signer,
+    /// waiting for it to be confirmed
+    pub async fn send_signed(&self, transaction: Transaction) -> Result<Signature, ClientError> {
+        self.call(move |rpc| Ok(rpc.send_and_confirm_transaction(&transaction)?))
+            .await
+    }
+
+    /// Get the durable nonce stored in a nonce account
+    pub async fn get_durable_nonce(&self, account: &Pubkey) -> Result<DurableNonce, ClientError> {
+        let account = *account;
+        let nonce_account = self.call(move |rpc| Ok(rpc.get_account(&account)?)).await?;
+        let data = nonce_utils::data_from_account(&nonce_account)
+            .map_err(|_error| ClientError::InvalidNonce(account))?;
+        Ok(DurableNonce {
+            account,
+            authority: data.authority,
+            blockhash: data.blockhash,
+        })
+    }
+
+    /// Get the program config account of the instance
+    pub async fn get_program_config(&self) -> Result<ProgramConfigAccount, ClientError> {
+        let (address, _bump_seed) = get_program_config_address(&self.program_id);
+        self.get_account(&address).await
+    }
+
+    pub async fn get_merchant(&self, merchant: &Pubkey) -> Result<MerchantAccount, ClientError> {
+        self.get_account(merchant).await
+    }
+
+    pub async fn get_order(&self, order: &Pubkey) -> Result<OrderAccount, ClientError> {
+        self.get_account(order).await
+    }
+
+    pub async fn get_order_status(&self, order: &Pubkey) -> Result<OrderStatus, ClientError> {
+        let order_account = self.get_order(order).await?;
+        OrderStatus::from_u8(order_account.status)
+            .ok_or(ClientError::UnknownOrderStatus(order_account.status))
+    }
+
+    /// Get the accounts of the program that match the filter
+    ///
+    /// Accounts in the legacy layout are found by the legacy filter (see
+    /// get_legacy_filter), and accounts of other types that happen to match the
+    /// filter are left out.
+    async fn find_accounts<T: Serdes + Send + 'static>(
+        &self,
+        filter: RpcFilterType,
+    ) -> Result<Vec<(Pubkey, T)>, ClientError> {
+        let legacy_filter = get_legacy_filter(filter.clone());
+        let mut filters = vec![filter];
+        if legacy_filter != filters[0] {
+            filters.push(legacy_filter);
+        }
+        let mut found = vec![];
+        for filter in filters {
+            let program_id = self.program_id;
+            let config = RpcProgramAccountsConfig {
+                filters: Some(vec![filter]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            };
+            let accounts = self
+                .call(move |rpc| Ok(rpc.get_program_accounts_with_config(&program_id, config)?))
+                .await?;
+            for (address, account) in accounts {
+                // an account can match the filters of both layouts
+                if found
+                    .iter()
+                    .any(|(found_address, _)| *found_address == address)
+                {
+                    continue;
+                }
+                if let Ok(value) = T::unpack(&account.data) {
+                    found.push((address, value));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Get the order accounts of a merchant
+    pub async fn find_orders_for_merchant(
+        &self,
+        merchant: &Pubkey,
+    ) -> Result<Vec<(Pubkey, OrderAccount)>, ClientError> {
+        self.find_accounts(get_merchant_orders_filter(merchant))
+            .await
+    }
+
+    /// Get the subscription accounts of the subscribers of a merchant
+    pub async fn find_subscriptions_for_merchant(
+        &self,
+        merchant: &Pubkey,
+    ) -> Result<Vec<(Pubkey, SubscriptionAccount)>, ClientError> {
+        self.find_accounts(get_merchant_subscriptions_filter(merchant))
+            .await
+    }
+
+    /// Register a merchant account for the owner, with the given seed (or MERCHANT
+    /// when there is none)
+    ///
+    /// Returns the address of the merchant account.
+    pub async fn register_merchant(
+        &self,
+        owner: &Keypair,
+        seed: Option<&str>,
+        fee: Option<u64>,
+        data: Option<String>,
+    ) -> Result<Pubkey, ClientError> {
+        let merchant = get_merchant_address(&self.program_id, &owner.pubkey(), seed)
+            .map_err(|error| ClientError::InvalidSeed(error.to_string()))?;
+        let instruction = register_merchant(
+            self.program_id,
+            RegisterMerchantArgs {
+                signer: owner.pubkey(),
+                merchant,
+                seed: seed.map(String::from),
+                fee,
+                data,
+                sponsor: Option::None,
+                sponsor_fee: Option::None,
+            },
+        );
+        self.send(&[instruction], owner, &[]).await?;
+        Ok(merchant)
+    }
+
+    /// Pay an order of a merchant with an ExpressCheckout from the associated token
+    /// account of the buyer
+    ///
+    /// The order account is the address derived from the merchant account and the
+    /// order id, so the same order cannot be paid twice.  Returns the address of
+    /// the order account.
+    pub async fn checkout(
+        &self,
+        buyer: &Keypair,
+        checkout: &Checkout,
+    ) -> Result<Pubkey, ClientError> {
+        let (order, instructions) = TransactionBuilder::new(self)
+            .checkout(&buyer.pubkey(), checkout)
+            .await?;
+        self.send(&instructions, buyer, &[]).await?;
+        Ok(order)
+    }
+
+    /// Withdraw every order of the merchant that can be withdrawn now to the
+    /// associated token accounts of the owner, closing the order accounts
+    ///
+    /// The withdrawals are sent one by one, and the ones that fail are reported.
+    pub async fn withdraw_all(
+        &self,
+        owner: &Keypair,
+        merchant: &Pubkey,
+    ) -> Result<SettlementReport, ClientError> {
+        let merchant_account = self.get_merchant(merchant).await?;
+        let orders = self.find_orders_for_merchant(merchant).await?;
+        let policy = SettlementPolicy {
+            max_withdrawals: usize::MAX,
+            ..SettlementPolicy::default()
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|error| ClientError::Rpc(error.to_string()))?
+            .as_secs() as i64;
+        let settlements = plan_settlement(
+            &self.program_id,
+            &owner.pubkey(),
+            merchant,
+            &merchant_account,
+            &orders,
+            &policy,
+            timestamp,
+        );
+        let mut report = SettlementReport::default();
+        for settlement in settlements {
+            match self.send(&[settlement.instruction], owner, &[]).await {
+                Ok(_signature) => report.settled.push(settlement.order),
+                Err(error) => report.failed.push((settlement.order, error.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Add a subscription package (as a JSON string, see engine::json::Package) to
+    /// the catalog of the merchant
+    ///
+    /// Returns the address of the package account.
+    pub async fn create_package(
+        &self,
+        owner: &Keypair,
+        merchant: &Pubkey,
+        package: String,
+    ) -> Result<Pubkey, ClientError> {
+        let name = match serde_json::from_str::<Package>(&package) {
+            Ok(value) => value.name,
+            Err(error) => return Err(ClientError::InvalidPackage(error.to_string())),
+        };
+        let instruction =
+            create_package(self.program_id, owner.pubkey(), *merchant, &name, package);
+        self.send(&[instruction], owner, &[]).await?;
+        Ok(get_package_address(&self.program_id, merchant, &name).0)
+    }
+
+    /// Retire a subscription package of the catalog of the merchant
+    pub async fn retire_package(
+        &self,
+        owner: &Keypair,
+        merchant: &Pubkey,
+        name: &str,
+    ) -> Result<Signature, ClientError> {
+        let instruction = retire_package(self.program_id, owner.pubkey(), *merchant, name);
+        self.send(&[instruction], owner, &[]).await
+    }
+
+    /// Authorize the refund of an order with a refund code, which is handed to the
+    /// buyer to redeem
+    pub async fn issue_refund_code(
+        &self,
+        owner: &Keypair,
+        order: &Pubkey,
+        code: &str,
+    ) -> Result<Signature, ClientError> {
+        let instructions = TransactionBuilder::new(self)
+            .issue_refund_code(&owner.pubkey(), order, code)
+            .await?;
+        self.send(&instructions, owner, &[]).await
+    }
+}
+
+/// The inputs of a checkout, from which TransactionBuilder resolves the accounts
+/// that the instructions expect
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checkout {
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub tip: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    /// create the associated token account of the buyer first, when it does not
+    /// exist yet e.g. to fund it with wrapped SOL in the same transaction
+    pub create_buyer_token_account: bool,
+}
+
+/// Builds the instructions of a transaction from high-level inputs
+///
+/// The accounts that the instruction builders of `instruction` take in order
+/// (the program PDA, the order token account, the program owner, the sponsor of
+/// the merchant...) are derived, or read from the cluster.
+pub struct TransactionBuilder<'a> {
+    client: &'a PaymentProcessorClient,
+}
+
+impl<'a> TransactionBuilder<'a> {
+    pub fn new(client: &'a PaymentProcessorClient) -> Self {
+        TransactionBuilder { client }
+    }
+
+    /// Build an ExpressCheckout of an order of a merchant, paid from the
+    /// associated token account of the buyer
+    ///
+    /// Returns the address of the order account (see
+    /// PaymentProcessorClient::checkout) and the instructions.
+    pub async fn checkout(
+        &self,
+        buyer: &Pubkey,
+        checkout: &Checkout,
+    ) -> Result<(Pubkey, Vec<Instruction>), ClientError> {
+        let program_config = self.client.get_program_config().await?;
+        let merchant_account = self.client.get_merchant(&checkout.merchant).await?;
+        let create_buyer_token_account = checkout.create_buyer_token_account
+            && !self
+                .client
+                .account_exists(&get_associated_token_address(
+                    buyer,
+                    &spl_token::id(),
+                    &checkout.mint,
+                ))
+                .await?;
+        Ok(get_checkout_instructions(
+            &self.client.program_id,
+            buyer,
+            checkout,
+            &Pubkey::new_from_array(program_config.owner),
+            &Pubkey::new_from_array(merchant_account.sponsor),
+            create_buyer_token_account,
+        ))
+    }
+
+    /// Build the withdrawal of an order to the associated token accounts of the
+    /// owner of its merchant, closing the order account
+    pub async fn withdraw(
+        &self,
+        signer: &Pubkey,
+        order: &Pubkey,
+    ) -> Result<Vec<Instruction>, ClientError> {
+        let order_account = self.client.get_order(order).await?;
+        let merchant = Pubkey::new_from_array(order_account.merchant);
+        let merchant_account = self.client.get_merchant(&merchant).await?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|error| ClientError::Rpc(error.to_string()))?
+            .as_secs() as i64;
+        let settlements = plan_settlement(
+            &self.client.program_id,
+            signer,
+            &merchant,
+            &merchant_account,
+            &[(*order, order_account)],
+            &SettlementPolicy::default(),
+            timestamp,
+        );
+        match settlements.into_iter().next() {
+            Some(settlement) => Ok(vec![settlement.instruction]),
+            None => Err(ClientError::NotWithdrawable(*order)),
+        }
+    }
+
+    /// Build the authorization of the refund of an order with a refund code
+    pub async fn issue_refund_code(
+        &self,
+        signer: &Pubkey,
+        order: &Pubkey,
+        code: &str,
+    ) -> Result<Vec<Instruction>, ClientError> {
+        let order_account = self.client.get_order(order).await?;
+        Ok(vec![issue_refund_code(
+            self.client.program_id,
+            *signer,
+            *order,
+            Pubkey::new_from_array(order_account.merchant),
+            code,
+        )])
+    }
+}
+
+/// A durable nonce, which stands in for the recent blockhash of transactions
+/// that are signed long before they are sent e.g. on air-gapped signers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DurableNonce {
+    /// the nonce account that stores the nonce
+    pub account: Pubkey,
+    /// the signer that must advance the nonce in every transaction that uses it
+    pub authority: Pubkey,
+    pub blockhash: Hash,
+}
+
+/// Get an unsigned transaction of the instructions that uses a durable nonce
+///
+/// The nonce is advanced by the first instruction, as the runtime requires, so
+/// the transaction must be signed by the payer, the nonce authority and the
+/// signers of the instructions.  They can sign it offline one by one with
+/// Transaction::partial_sign and the blockhash of the nonce, then anyone can send
+/// it with PaymentProcessorClient::send_signed until the nonce is advanced.
+pub fn get_durable_nonce_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    nonce: &DurableNonce,
+) -> Transaction {
+    let mut all_instructions = vec![advance_nonce_account(&nonce.account, &nonce.authority)];
+    all_instructions.extend_from_slice(instructions);
+    let mut transaction = Transaction::new_unsigned(Message::new(&all_instructions, Some(payer)));
+    transaction.message.recent_blockhash = nonce.blockhash;
+    transaction
+}
+
+/// Get the instructions of a checkout, given the accounts that are read from the
+/// cluster
+///
+/// Returns the address of the order account and the instructions.
+pub fn get_checkout_instructions(
+    program_id: &Pubkey,
+    buyer: &Pubkey,
+    checkout: &Checkout,
+    program_owner: &Pubkey,
+    sponsor: &Pubkey,
+    create_buyer_token_account: bool,
+) -> (Pubkey, Vec<Instruction>) {
+    let (order, _bump_seed) = get_order_address(program_id, &checkout.merchant, &checkout.order_id);
+    let mut instructions = vec![];
+    if create_buyer_token_account {
+        instructions.push(create_associated_token_account_instruction(
+            buyer,
+            buyer,
+            &checkout.mint,
+        ));
+    }
+    instructions.push(express_checkout_from_wallet(
+        *program_id,
+        ExpressCheckoutFromWalletArgs {
+            signer: *buyer,
+            order,
+            merchant: checkout.merchant,
+            mint: checkout.mint,
+            program_owner: *program_owner,
+            sponsor: *sponsor,
+            integrator: checkout.integrator,
+            coupon: checkout.coupon,
+            link: Option::None,
+            amount: checkout.amount,
+            tip: checkout.tip,
+            order_id: checkout.order_id.clone(),
+            secret: checkout.secret.clone(),
+            data: checkout.data.clone(),
+        },
+    ));
+    (order, instructions)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            engine::constants::PDA_SEED,
+            pda::{get_associated_token_program_id, get_order_token_address},
+        },
+        solana_program_test::*,
+    };
+
+    #[tokio::test]
+    async fn test_get_durable_nonce_transaction() {
+        let owner = Keypair::new();
+        let authority = Keypair::new();
+        let nonce = DurableNonce {
+            account: Pubkey::new_unique(),
+            authority: authority.pubkey(),
+            blockhash: Hash::new_unique(),
+        };
+        let instruction = issue_refund_code(
+            Pubkey::new_unique(),
+            owner.pubkey(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            "refund-1",
+        );
+        let mut transaction =
+            get_durable_nonce_transaction(&[instruction.clone()], &owner.pubkey(), &nonce);
+        assert_eq!(nonce.blockhash, transaction.message.recent_blockhash);
+        assert_eq!(owner.pubkey(), transaction.message.account_keys[0]);
+        assert_eq!(2, transaction.message.instructions.len());
+        assert_eq!(
+            advance_nonce_account(&nonce.account, &authority.pubkey()),
+            transaction
+                .message
+                .decompile_instruction(&transaction.message.instructions[0])
+        );
+        assert_eq!(
+            instruction,
+            transaction
+                .message
+                .decompile_instruction(&transaction.message.instructions[1])
+        );
+
+        // the signers sign one by one
+        transaction.partial_sign(&[&owner], nonce.blockhash);
+        assert!(!transaction.is_signed());
+        transaction.partial_sign(&[&authority], nonce.blockhash);
+        assert!(transaction.is_signed());
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_checkout_instructions() {
+        let program_id = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let program_owner = Pubkey::new_unique();
+        let sponsor = Pubkey::new_unique();
+        let checkout = Checkout {
+            merchant: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            amount: 1000,
+            order_id: String::from("order-1"),
+            secret: String::from("hunter2"),
+            ..Checkout::default()
+        };
+        let (order, _bump_seed) = get_order_address(&program_id, &checkout.merchant, "order-1");
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+
+        let (address, instructions) = get_checkout_instructions(
+            &program_id,
+            &buyer,
+            &checkout,
+            &program_owner,
+            &sponsor,
+            false,
+        );
+        assert_eq!(order, address);
+        assert_eq!(1, instructions.len());
+        let accounts: Vec<Pubkey> = instructions[0]
+            .accounts
+            .iter()
+            .map(|account| account.pubkey)
+            .collect();
+        for key in [
+            buyer,
+            order,
+            checkout.merchant,
+            get_order_token_address(&program_id, &order, &checkout.mint),
+            get_associated_token_address(&buyer, &spl_token::id(), &checkout.mint),
+            checkout.mint,
+            program_owner,
+            sponsor,
+            pda,
+        ]
+        .iter()
+        {
+            assert!(accounts.contains(key));
+        }
+
+        // the associated token account of the buyer is created first
+        let (_address, instructions) = get_checkout_instructions(
+            &program_id,
+            &buyer,
+            &checkout,
+            &program_owner,
+            &sponsor,
+            true,
+        );
+        assert_eq!(2, instructions.len());
+        assert_eq!(
+            get_associated_token_program_id(),
+            instructions[0].program_id
+        );
+        assert_eq!(program_id, instructions[1].program_id);
+    }
+}
@@ -0,0 +1,148 @@
+//! Wallet deep links for checkouts prepared off-chain
+//!
+//! Meant to be used by mobile web checkouts to hand the signing of a prepared
+//! checkout transaction over to the buyer's wallet, either by opening the checkout
+//! in the wallet's in-app browser, by sending a Solana Pay transaction request or
+//! by sending the transaction to the wallet over the mobile wallet adapter.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The wallets that deep links can be made for
+pub enum Wallet {
+    Phantom,
+    Solflare,
+    Backpack,
+}
+
+impl Wallet {
+    /// the universal link that opens a url in the wallet's in-app browser
+    fn browse_base(&self) -> &'static str {
+        match self {
+            Wallet::Phantom => "https://phantom.app/ul/browse",
+            Wallet::Solflare => "https://solflare.com/ul/v1/browse",
+            Wallet::Backpack => "https://backpack.app/ul/v1/browse",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The mobile wallet adapter methods that take transactions
+pub enum MobileWalletAdapterMethod {
+    /// the wallet signs the transactions and hands them back
+    SignTransactions,
+    /// the wallet signs the transactions and sends them to the cluster
+    SignAndSendTransactions,
+}
+
+impl MobileWalletAdapterMethod {
+    fn name(&self) -> &'static str {
+        match self {
+            MobileWalletAdapterMethod::SignTransactions => "sign_transactions",
+            MobileWalletAdapterMethod::SignAndSendTransactions => "sign_and_send_transactions",
+        }
+    }
+}
+
+/// the characters escaped by javascript's encodeURIComponent
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+pub(crate) fn encode_uri_component(value: &str) -> String {
+    utf8_percent_encode(value, URI_COMPONENT).to_string()
+}
+
+/// Deep link that opens the checkout page in the wallet's in-app browser
+///
+/// ref_url is the url of the site that links to the wallet (usually the merchant)
+pub fn browse_link(wallet: Wallet, checkout_url: &str, ref_url: &str) -> String {
+    format!(
+        "{base}/{url}?ref={ref_url}",
+        base = wallet.browse_base(),
+        url = encode_uri_component(checkout_url),
+        ref_url = encode_uri_component(ref_url)
+    )
+}
+
+/// Solana Pay transaction request link
+///
+/// The wallet fetches the prepared checkout transaction from transaction_url.
+/// Phantom, Solflare and Backpack all handle these links.
+pub fn transaction_request_link(transaction_url: &str) -> String {
+    format!("solana:{}", encode_uri_component(transaction_url))
+}
+
+/// Mobile wallet adapter request for prepared checkout transactions
+///
+/// The transactions are expected to be serialized and (if need be) partially
+/// signed e.g. by the merchant server.  Returns the JSON-RPC request as a string.
+pub fn mobile_wallet_adapter_request(
+    id: u64,
+    method: MobileWalletAdapterMethod,
+    transactions: &[Vec<u8>],
+) -> String {
+    let payloads: Vec<String> = transactions.iter().map(base64::encode).collect();
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method.name(),
+        "params": { "payloads": payloads }
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, serde_json::Value, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_browse_link() {
+        assert_eq!(
+            "https://phantom.app/ul/browse/https%3A%2F%2Fshop.example%2Fcheckout%3Forder%3D1?ref=https%3A%2F%2Fshop.example",
+            browse_link(
+                Wallet::Phantom,
+                "https://shop.example/checkout?order=1",
+                "https://shop.example"
+            )
+        );
+        assert!(
+            browse_link(Wallet::Solflare, "https://a.b", "https://a.b")
+                .starts_with("https://solflare.com/ul/v1/browse/https%3A%2F%2Fa.b?ref=")
+        );
+        assert!(
+            browse_link(Wallet::Backpack, "https://a.b", "https://a.b")
+                .starts_with("https://backpack.app/ul/v1/browse/https%3A%2F%2Fa.b?ref=")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_request_link() {
+        assert_eq!(
+            "solana:https%3A%2F%2Fshop.example%2Fpay%3Forder%3D1",
+            transaction_request_link("https://shop.example/pay?order=1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mobile_wallet_adapter_request() {
+        let request = mobile_wallet_adapter_request(
+            7,
+            MobileWalletAdapterMethod::SignAndSendTransactions,
+            &[vec![1, 2, 3], vec![255]],
+        );
+        let json_value: Value = serde_json::from_str(&request).unwrap();
+        assert_eq!("2.0", json_value["jsonrpc"]);
+        assert_eq!(7, json_value["id"]);
+        assert_eq!("sign_and_send_transactions", json_value["method"]);
+        assert_eq!(json!(["AQID", "/w=="]), json_value["params"]["payloads"]);
+    }
+}
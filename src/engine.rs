@@ -1,9 +1,63 @@
+pub mod accepted_mints;
+pub mod associated_token;
+pub mod attestation;
+pub mod auto_debit;
+pub mod blocklist;
+pub mod bundle;
+pub mod campaign;
+pub mod catalog;
 pub mod cancel_subscription;
+pub mod collection;
+pub mod close_merchant;
+pub mod close_order;
+pub mod close_subscription;
 pub mod common;
+pub mod config;
 pub mod constants;
+pub mod coupon;
+pub mod escrow;
+pub mod expire;
+pub mod fee_history;
+pub mod freeze_policy;
+pub mod guarantee;
+pub mod installment;
+pub mod instant_settlement;
+pub mod integrator;
+pub mod invoice;
 pub mod json;
+pub mod link;
+pub mod migrate;
+pub mod notes;
+pub mod operators;
+pub mod oracle;
+pub mod pause;
+pub mod pause_subscription;
+pub mod payout;
+pub mod receipt;
+pub mod reclaim;
+pub mod recovery;
+pub mod referral;
+pub mod refund;
 pub mod register;
 pub mod renew;
+pub mod screening;
+pub mod seats;
+pub mod settlement;
+pub mod stats;
+pub mod stock;
+pub mod store;
+pub mod sub_order;
 pub mod subscribe;
+pub mod swap;
+pub mod sweep;
+pub mod sysvars;
+pub mod terms;
+pub mod token;
+pub mod tolerance;
+pub mod transfer_subscription;
+pub mod usage;
+pub mod validation;
+pub mod voucher;
+pub mod waiver;
 pub mod withdraw;
 pub mod pay;
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::{
+    engine::common::get_program_account_discriminator,
+    engine::constants::{ACCEPTED_MINTS, ACCOUNT_VERSION, MAX_ACCEPTED_MINTS},
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{AcceptedMintsAccount, Discriminator, IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the accepted mints account of a merchant
+pub fn get_accepted_mints_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ACCEPTED_MINTS.as_bytes(), &merchant.to_bytes()],
+        program_id,
+    )
+}
+
+/// Ensure that a payment to a merchant is made in mints that it accepts
+///
+/// Merchants without accepted mints take payments in any mint.  Otherwise the
+/// accepted mints account of the merchant is looked up among the accounts of the
+/// instruction, wherever it is.
+pub fn check_accepted_mints(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merchant_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+    mints: &[&Pubkey],
+) -> ProgramResult {
+    if !merchant_account.restricts_mints {
+        return Ok(());
+    }
+    let accepted_mints_info = match accounts.iter().find(|account_info| {
+        get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::AcceptedMints as u8)
+    }) {
+        None => {
+            msg!("Error: The accepted mints account of the merchant is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(value) => value,
+    };
+    let accepted_mints_account = AcceptedMintsAccount::unpack(&accepted_mints_info.data.borrow())?;
+    if accepted_mints_account.merchant != merchant_info.key.to_bytes() {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let accepted_mints = &accepted_mints_account.mints[..accepted_mints_account.count as usize];
+    for mint in mints {
+        if !accepted_mints.contains(&mint.to_bytes()) {
+            msg!("Error: The merchant does not accept mint {:?}", mint);
+            return Err(PaymentProcessorError::WrongMint.into());
+        }
+    }
+    Ok(())
+}
+
+/// Set Accepted Mints
+///
+/// Sets the mints that the merchant takes payments in, creating the accepted
+/// mints account of the merchant if needed.  Checkouts in other mints then fail.
+/// An empty list lets the merchant take payments in any mint again.
+pub fn process_set_accepted_mints(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    mints: Vec<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let accepted_mints_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the accepted mints
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    if mints.len() > MAX_ACCEPTED_MINTS {
+        msg!(
+            "Error: A merchant can accept at most {:?} mints",
+            MAX_ACCEPTED_MINTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut accepted_mints_account = if *accepted_mints_info.owner != *program_id {
+        let (accepted_mints_address, bump_seed) =
+            get_accepted_mints_address(program_id, merchant_info.key);
+        if accepted_mints_address != *accepted_mints_info.key {
+            msg!("Error: Accepted mints address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[ACCEPTED_MINTS.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = AcceptedMintsAccount::MIN_LEN;
+        // Fund the accepted mints account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                accepted_mints_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                accepted_mints_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the accepted mints account
+        invoke_signed(
+            &system_instruction::allocate(accepted_mints_info.key, account_size as u64),
+            &[accepted_mints_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the accepted mints account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(accepted_mints_info.key, program_id),
+            &[accepted_mints_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(accepted_mints_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+        AcceptedMintsAccount {
+            discriminator: Discriminator::AcceptedMints as u8,
+            merchant: merchant_bytes,
+            count: 0,
+            mints: [[0; 32]; MAX_ACCEPTED_MINTS],
+            version: ACCOUNT_VERSION,
+        }
+    } else {
+        let accepted_mints_account =
+            AcceptedMintsAccount::unpack(&accepted_mints_info.data.borrow())?;
+        if accepted_mints_account.discriminator != Discriminator::AcceptedMints as u8
+            || accepted_mints_account.merchant != merchant_info.key.to_bytes()
+        {
+            msg!("Error: Invalid accepted mints account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        accepted_mints_account
+    };
+
+    // Saving accepted mints...
+    accepted_mints_account.count = mints.len() as u64;
+    accepted_mints_account.mints = [[0; 32]; MAX_ACCEPTED_MINTS];
+    for (index, mint) in mints.iter().enumerate() {
+        accepted_mints_account.mints[index] = mint.to_bytes();
+    }
+    accepted_mints_account.pack(&mut accepted_mints_info.try_borrow_mut_data()?);
+    merchant_account.restricts_mints = !mints.is_empty();
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::engine::constants::ASSOCIATED_TOKEN_PROGRAM_ID;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar,
+};
+use std::str::FromStr;
+
+/// Get the id of the SPL associated token account program
+pub fn get_associated_token_program_id() -> Pubkey {
+    Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID).unwrap()
+}
+
+/// Get the address of the associated token account of a wallet for a mint
+///
+/// The address depends on the token program of the mint, SPL Token or Token-2022.
+pub fn get_associated_token_address(
+    wallet: &Pubkey,
+    token_program_id: &Pubkey,
+    mint: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            &wallet.to_bytes(),
+            &token_program_id.to_bytes(),
+            &mint.to_bytes(),
+        ],
+        &get_associated_token_program_id(),
+    )
+    .0
+}
+
+/// Get the address of the token account (owned by this program) that holds the
+/// payment of an order
+pub fn get_order_token_address(program_id: &Pubkey, order: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            &order.to_bytes(),
+            &spl_token::id().to_bytes(),
+            &mint.to_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+/// Creates an instruction that creates the associated token account of a wallet
+///
+/// This is the same as spl_associated_token_account::create_associated_token_account
+pub fn create_associated_token_account_instruction(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: get_associated_token_program_id(),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(
+                get_associated_token_address(wallet, &spl_token::id(), mint),
+                false,
+            ),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: vec![],
+    }
+}
+
+/// Create the associated token account of a wallet
+///
+/// Used to pay out to a wallet whose associated token account does not exist
+/// yet.  The payer pays for the rent of the new token account.
+pub fn create_associated_token_account(accounts: &[AccountInfo; 8]) -> ProgramResult {
+    let payer_info = &accounts[0];
+    let associated_token_info = &accounts[1];
+    let wallet_info = &accounts[2];
+    let mint_info = &accounts[3];
+    let system_program_info = &accounts[4];
+    let token_program_info = &accounts[5];
+    let rent_sysvar_info = &accounts[6];
+    let associated_token_program_info = &accounts[7];
+
+    if *associated_token_program_info.key != get_associated_token_program_id() {
+        msg!("Error: Wrong associated token account program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program_info.key != spl_token::id() {
+        msg!("Error: Associated token accounts are only created for the SPL Token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // assert that the derived address matches the one supplied
+    if get_associated_token_address(wallet_info.key, token_program_info.key, mint_info.key)
+        != *associated_token_info.key
+    {
+        msg!("Error: Associated address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    invoke(
+        &create_associated_token_account_instruction(
+            payer_info.key,
+            wallet_info.key,
+            mint_info.key,
+        ),
+        &[
+            payer_info.clone(),
+            associated_token_info.clone(),
+            wallet_info.clone(),
+            mint_info.clone(),
+            system_program_info.clone(),
+            token_program_info.clone(),
+            rent_sysvar_info.clone(),
+            associated_token_program_info.clone(),
+        ],
+    )
+}
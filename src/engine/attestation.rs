@@ -0,0 +1,292 @@
+use crate::{
+    engine::config::get_program_config,
+    engine::constants::{ACCOUNT_VERSION, ATTESTATION, TRUSTED_INSTANCES},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        AttestationAccount, Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus,
+        Serdes, SubscriptionAccount, SubscriptionStatus,
+    },
+};
+use serde_json::Value;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::hashv,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the attestation account of a merchant
+pub fn get_attestation_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ATTESTATION.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Get the merchant account and ensure that the signer owns it
+fn get_owned_merchant_account(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<MerchantAccount, ProgramError> {
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure the signer is the merchant owner
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(merchant_account)
+}
+
+/// Save an attestation to the attestation account of a merchant
+///
+/// The attestation account is created if it does not exist yet, otherwise it is
+/// overwritten with the newer attestation.
+fn save_attestation<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    merchant_info: &AccountInfo<'a>,
+    attestation_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent_sysvar_info: &AccountInfo<'a>,
+    attestation: &AttestationAccount,
+) -> ProgramResult {
+    let (attestation_address, bump_seed) = get_attestation_address(program_id, merchant_info.key);
+    if attestation_address != *attestation_info.key {
+        msg!("Error: Attestation address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let account_size = AttestationAccount::MIN_LEN;
+    if *attestation_info.owner != *program_id {
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[ATTESTATION.as_bytes(), &merchant_bytes, &[bump_seed]];
+        // Fund the attestation account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                attestation_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                attestation_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the attestation account
+        invoke_signed(
+            &system_instruction::allocate(attestation_info.key, account_size as u64),
+            &[attestation_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the attestation account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(attestation_info.key, program_id),
+            &[attestation_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+    }
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving attestation information...
+    attestation.pack(&mut attestation_info.try_borrow_mut_data()?);
+
+    // ensure attestation account is rent exempt
+    if !rent.is_exempt(attestation_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Export an attestation of a merchant's order history and subscriptions
+///
+/// The attestation is stored in an account owned by this program and thus can
+/// only have been written by this program.  Other instances of the program can
+/// therefore rely on it without trusting the merchant.
+///
+/// The order and subscription accounts must be sorted by address so that none
+/// of them can be counted twice.
+pub fn process_export_attestation(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let attestation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let merchant_account = get_owned_merchant_account(program_id, signer_info, merchant_info)?;
+
+    let mut digest = hashv(&[
+        &merchant_info.key.to_bytes(),
+        &merchant_info.data.borrow()[..],
+    ]);
+    let mut order_count: u64 = 0;
+    let mut subscription_count: u64 = 0;
+    let mut active_subscription_count: u64 = 0;
+    let mut previous_key: Option<&Pubkey> = None;
+
+    for account_info in account_info_iter {
+        // ensure no account is attested twice
+        if let Some(previous_key) = previous_key {
+            if account_info.key <= previous_key {
+                msg!("Error: Attested accounts must be sorted by address");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        previous_key = Some(account_info.key);
+        // ensure the account is owned by this program
+        check_program_owned(program_id, account_info, "attested")?;
+        let discriminator = match account_info.data.borrow().first() {
+            None => return Err(ProgramError::InvalidAccountData),
+            Some(value) => *value,
+        };
+        if discriminator == Discriminator::OrderExpressCheckout as u8
+            || discriminator == Discriminator::OrderChainCheckout as u8
+            || discriminator == Discriminator::OrderSplitCheckout as u8
+            || discriminator == Discriminator::OrderInvoice as u8
+        {
+            // the order account is not unpacked, its merchant and status are read
+            // in place
+            let order_data = account_info.data.borrow();
+            if OrderAccount::MERCHANT.get(&order_data)? != merchant_info.key.to_bytes() {
+                return Err(PaymentProcessorError::WrongMerchant.into());
+            }
+            let status = OrderAccount::STATUS.get(&order_data)?;
+            if status == OrderStatus::Paid as u8
+                || status == OrderStatus::Withdrawn as u8
+                || status == OrderStatus::Releasable as u8
+            {
+                order_count += 1;
+            }
+        } else if discriminator == Discriminator::Subscription as u8 {
+            let subscription_account = SubscriptionAccount::unpack(&account_info.data.borrow())?;
+            if subscription_account.merchant != merchant_info.key.to_bytes() {
+                return Err(PaymentProcessorError::WrongMerchant.into());
+            }
+            subscription_count += 1;
+            if subscription_account.status == SubscriptionStatus::Initialized as u8
+                && subscription_account.period_end > timestamp
+            {
+                active_subscription_count += 1;
+            }
+        } else {
+            msg!("Error: Only order and subscription accounts can be attested");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        digest = hashv(&[
+            &digest.to_bytes(),
+            &account_info.key.to_bytes(),
+            &account_info.data.borrow()[..],
+        ]);
+    }
+
+    let attestation = AttestationAccount {
+        discriminator: Discriminator::Attestation as u8,
+        source: program_id.to_bytes(),
+        merchant: merchant_info.key.to_bytes(),
+        owner: merchant_account.owner,
+        created: timestamp,
+        order_count,
+        subscription_count,
+        active_subscription_count,
+        digest: digest.to_bytes(),
+        version: ACCOUNT_VERSION,
+    };
+    save_attestation(
+        program_id,
+        signer_info,
+        merchant_info,
+        attestation_info,
+        system_program_info,
+        rent_sysvar_info,
+        &attestation,
+    )
+}
+
+/// Import an attestation issued by another instance of the program
+///
+/// The issuing instance has to be listed in the trusted instances of the program
+/// config e.g. {"trusted_instances": ["<program id>"]}.  The imported attestation
+/// is stored in the attestation account of the merchant on this instance.
+pub fn process_import_attestation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let source_attestation_info = next_account_info(account_info_iter)?;
+    let attestation_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    get_owned_merchant_account(program_id, signer_info, merchant_info)?;
+    let config_account = get_program_config(program_id, config_info)?;
+
+    // ensure the attestation was issued by a trusted instance
+    let source = source_attestation_info.owner;
+    if *source == *program_id {
+        msg!("Error: The attestation was issued by this instance");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let config_json_data: Value = match serde_json::from_str(&config_account.data) {
+        Err(_error) => return Err(PaymentProcessorError::UntrustedInstance.into()),
+        Ok(data) => data,
+    };
+    let is_trusted = match config_json_data[TRUSTED_INSTANCES].as_array() {
+        None => false,
+        Some(trusted_instances) => trusted_instances
+            .iter()
+            .any(|instance| instance.as_str() == Some(&source.to_string())),
+    };
+    if !is_trusted {
+        return Err(PaymentProcessorError::UntrustedInstance.into());
+    }
+
+    let attestation = AttestationAccount::unpack(&source_attestation_info.data.borrow())?;
+    if attestation.discriminator != Discriminator::Attestation as u8 {
+        msg!("Error: Invalid attestation account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // an instance only ever issues attestations under its own program id
+    if attestation.source != source.to_bytes() {
+        msg!("Error: Invalid attestation source");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the attested merchant belongs to the signer
+    if attestation.owner != signer_info.key.to_bytes() {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+
+    save_attestation(
+        program_id,
+        signer_info,
+        merchant_info,
+        attestation_info,
+        system_program_info,
+        rent_sysvar_info,
+        &attestation,
+    )
+}
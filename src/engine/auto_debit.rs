@@ -0,0 +1,536 @@
+use crate::{
+    engine::common::{
+        create_order_account, create_program_owned_associated_token_account, next_order_sequence,
+        record_merchant_volume, set_order_fees, update_merchant_references,
+    },
+    engine::config::{check_migration_window, get_program_config},
+    engine::constants::{ACCOUNT_VERSION, AUTO_DEBIT, DEFAULT_DATA},
+    engine::pay::{order_checks, pay_fees, FeeAccounts, OrderArgs},
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sweep::{get_sweep_bounty, set_aside_sweep_bounty},
+    engine::sysvars::SysvarProvider,
+    engine::token::{self, check_token_program, get_net_amounts, unpack_token_account},
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    events::emit_order_paid,
+    state::{
+        AutoDebitAccount, Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus,
+        Serdes,
+    },
+    utils::{get_fees, get_order_account_size},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the auto-debit account of a buyer token account
+///
+/// The auto-debit account is also the delegate of the buyer token account
+pub fn get_auto_debit_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    token: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            AUTO_DEBIT.as_bytes(),
+            &merchant.to_bytes(),
+            &token.to_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Get an auto-debit account and ensure that it lets the merchant pull from the
+/// buyer token account
+fn get_auto_debit_account(
+    program_id: &Pubkey,
+    auto_debit_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    buyer_token_info: &AccountInfo<'_>,
+) -> Result<AutoDebitAccount, ProgramError> {
+    check_program_owned(program_id, auto_debit_info, "auto-debit")?;
+    let auto_debit_account = AutoDebitAccount::unpack(&auto_debit_info.data.borrow())?;
+    if auto_debit_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !auto_debit_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if auto_debit_account.discriminator != Discriminator::AutoDebit as u8 {
+        msg!("Error: Invalid auto-debit account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if merchant_info.key.to_bytes() != auto_debit_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if buyer_token_info.key.to_bytes() != auto_debit_account.token {
+        msg!("Error: Incorrect buyer token account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(auto_debit_account)
+}
+
+/// The accounts of a payment pulled through an auto-debit account
+pub struct AutoDebitAccounts<'a, 'b> {
+    pub auto_debit: &'a AccountInfo<'b>,
+    pub merchant: &'a AccountInfo<'b>,
+    /// the buyer token account that the auto-debit account may pull from
+    pub buyer_token: &'a AccountInfo<'b>,
+}
+
+/// Pull an amount from a buyer token account using its auto-debit allowance
+///
+/// Ensures that the amount is within what the buyer approved and that enough time
+/// has passed since the last collection, then uses up the allowance.
+pub fn collect_auto_debit<'a>(
+    program_id: &Pubkey,
+    auto_debit: &AutoDebitAccounts<'_, 'a>,
+    destination_token_info: &AccountInfo<'a>,
+    mint_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    amount: u64,
+    timestamp: UnixTimestamp,
+) -> Result<AutoDebitAccount, ProgramError> {
+    let AutoDebitAccounts {
+        auto_debit: auto_debit_info,
+        merchant: merchant_info,
+        buyer_token: buyer_token_info,
+    } = *auto_debit;
+    let mut auto_debit_account =
+        get_auto_debit_account(program_id, auto_debit_info, merchant_info, buyer_token_info)?;
+    if amount == 0 || amount > auto_debit_account.max_amount {
+        msg!(
+            "Error: The amount must be 1 to {:?}",
+            auto_debit_account.max_amount
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if amount > auto_debit_account.allowance {
+        msg!(
+            "Error: Only {:?} is left of the allowance",
+            auto_debit_account.allowance
+        );
+        return Err(ProgramError::InsufficientFunds);
+    }
+    let next_collection = auto_debit_account
+        .last_collected
+        .checked_add(auto_debit_account.interval)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if timestamp < next_collection {
+        msg!(
+            "Error: The next payment can be collected at {:?}",
+            next_collection
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (_auto_debit_address, bump_seed) =
+        get_auto_debit_address(program_id, merchant_info.key, buyer_token_info.key);
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let token_bytes = buyer_token_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        AUTO_DEBIT.as_bytes(),
+        &merchant_bytes,
+        &token_bytes,
+        &[bump_seed],
+    ];
+
+    // Pulling the amount from the buyer token account...
+    invoke_signed(
+        &token::transfer_checked(
+            token_program_info.key,
+            buyer_token_info.key,
+            mint_info,
+            destination_token_info.key,
+            auto_debit_info.key,
+            &[],
+            amount,
+        )?,
+        &[
+            buyer_token_info.clone(),
+            mint_info.clone(),
+            destination_token_info.clone(),
+            auto_debit_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    auto_debit_account.allowance = auto_debit_account
+        .allowance
+        .checked_sub(amount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    auto_debit_account.last_collected = timestamp;
+    auto_debit_account.pack(&mut auto_debit_info.data.borrow_mut());
+
+    Ok(auto_debit_account)
+}
+
+/// Approve Auto Debit
+///
+/// Lets a merchant pull payments from a buyer token account later on, without
+/// the buyer signing each payment.  The auto-debit account records what the
+/// merchant may collect and is made the delegate of the buyer token account for
+/// the whole allowance.  Approving again replaces the allowance.
+pub fn process_approve_auto_debit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    allowance: u64,
+    max_amount: u64,
+    interval: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let auto_debit_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let buyer_token_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure the buyer token account belongs to the signer
+    check_token_program(token_program_info)?;
+    let buyer_token_data = unpack_token_account(buyer_token_info, token_program_info.key)?;
+    if buyer_token_data.owner != *signer_info.key {
+        msg!("Error: The buyer token account must be owned by the signer");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if max_amount == 0 || max_amount > allowance || interval < 0 {
+        msg!("Error: The maximum amount must be 1 to the allowance");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (auto_debit_address, bump_seed) =
+        get_auto_debit_address(program_id, merchant_info.key, buyer_token_info.key);
+    if auto_debit_address != *auto_debit_info.key {
+        msg!("Error: Auto-debit address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let auto_debit_account = if *auto_debit_info.owner == *program_id {
+        // Replacing the allowance...
+        let auto_debit_account =
+            get_auto_debit_account(program_id, auto_debit_info, merchant_info, buyer_token_info)?;
+        if signer_info.key.to_bytes() != auto_debit_account.owner {
+            return Err(PaymentProcessorError::WrongPayer.into());
+        }
+        AutoDebitAccount {
+            allowance,
+            max_amount,
+            interval,
+            ..auto_debit_account
+        }
+    } else {
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let token_bytes = buyer_token_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[
+            AUTO_DEBIT.as_bytes(),
+            &merchant_bytes,
+            &token_bytes,
+            &[bump_seed],
+        ];
+        let account_size = AutoDebitAccount::MIN_LEN;
+        // Fund the auto-debit account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                auto_debit_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                auto_debit_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the auto-debit account
+        invoke_signed(
+            &system_instruction::allocate(auto_debit_info.key, account_size as u64),
+            &[auto_debit_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the auto-debit account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(auto_debit_info.key, program_id),
+            &[auto_debit_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(auto_debit_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+        AutoDebitAccount {
+            discriminator: Discriminator::AutoDebit as u8,
+            merchant: merchant_bytes,
+            owner: signer_info.key.to_bytes(),
+            token: token_bytes,
+            mint: buyer_token_data.mint.to_bytes(),
+            allowance,
+            max_amount,
+            interval,
+            created: timestamp,
+            last_collected: 0,
+            version: ACCOUNT_VERSION,
+        }
+    };
+
+    // Delegating the allowance to the auto-debit account...
+    invoke(
+        &token::approve(
+            token_program_info.key,
+            buyer_token_info.key,
+            auto_debit_info.key,
+            signer_info.key,
+            &[],
+            allowance,
+        )?,
+        &[
+            buyer_token_info.clone(),
+            auto_debit_info.clone(),
+            signer_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+
+    // Saving auto-debit information...
+    auto_debit_account.pack(&mut auto_debit_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Collect Payment
+///
+/// Lets the merchant pull a payment that the buyer approved beforehand using
+/// ApproveAutoDebit.  Creates a paid order for the buyer, just like an express
+/// checkout, except that the merchant signs and pays the transaction fee.
+pub fn process_collect_payment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+    order_id: String,
+    secret: String,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    collect_order(
+        program_id,
+        accounts,
+        sysvars,
+        OrderArgs {
+            amount,
+            order_id,
+            secret,
+            maybe_data,
+        },
+        true,
+    )?;
+    Ok(())
+}
+
+/// Create a paid order from a payment pulled through an auto-debit account
+///
+/// Expects the accounts of CollectPayment.  Unless `by_merchant` is set, anyone
+/// may sign, e.g. to renew a subscription that is due.
+pub fn collect_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    order: OrderArgs,
+    by_merchant: bool,
+) -> Result<OrderAccount, ProgramError> {
+    let OrderArgs {
+        amount,
+        order_id,
+        secret,
+        maybe_data,
+    } = order;
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let seller_token_info = next_account_info(account_info_iter)?;
+    let buyer_token_info = next_account_info(account_info_iter)?;
+    let auto_debit_info = next_account_info(account_info_iter)?;
+    let program_owner_info = next_account_info(account_info_iter)?;
+    let sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let possible_stats_info = find_merchant_stats(program_id, account_info_iter);
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let config_account = get_program_config(program_id, config_info)?;
+    check_migration_window(&config_account, timestamp)?;
+    let fee_accounts = FeeAccounts {
+        program_owner: program_owner_info,
+        sponsor: sponsor_info,
+        system_program: system_program_info,
+    };
+    let merchant_account = order_checks(
+        program_id,
+        signer_info,
+        merchant_info,
+        buyer_token_info,
+        mint_info,
+        &fee_accounts,
+        &config_account,
+    )?;
+    // ensure only the merchant owner can collect payments
+    if by_merchant && signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+
+    // keep track of the fees so that they can be waived if the order is refunded
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+    let (program_owner_fee, sponsor_fee, _integrator_fee) =
+        get_fees(&merchant_account, &config_account, false)?;
+    // auto debits are never settled instantly, so they set aside the sweep
+    // bounty of the merchant (if any) out of the program owner fee
+    let sweep_bounty = get_sweep_bounty(&merchant_account, program_owner_fee);
+    let data = set_order_fees(data, program_owner_fee - sweep_bounty, sponsor_fee);
+
+    // count the order token account towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        possible_stats_info,
+        &merchant_info.key.to_bytes(),
+        1,
+        0,
+    )?;
+    // count the order towards the references of the merchant
+    update_merchant_references(merchant_info, true)?;
+
+    // create order account
+    let order_account_size = get_order_account_size(&order_id, &secret, &data);
+    create_order_account(
+        program_id,
+        signer_info,
+        order_info,
+        system_program_info,
+        merchant_info.key,
+        &order_id,
+        order_account_size,
+    )?;
+
+    // create the token account that the payment is pulled into
+    create_program_owned_associated_token_account(
+        program_id,
+        &[
+            signer_info.clone(),
+            order_info.clone(),
+            seller_token_info.clone(),
+            mint_info.clone(),
+            pda_info.clone(),
+            token_program_info.clone(),
+            system_program_info.clone(),
+            rent_sysvar_info.clone(),
+        ],
+        rent,
+    )?;
+
+    let auto_debit_account = collect_auto_debit(
+        program_id,
+        &AutoDebitAccounts {
+            auto_debit: auto_debit_info,
+            merchant: merchant_info,
+            buyer_token: buyer_token_info,
+        },
+        seller_token_info,
+        mint_info,
+        token_program_info,
+        amount,
+        timestamp,
+    )?;
+    // the mint may withhold part of the payment as a transfer fee
+    let received = unpack_token_account(seller_token_info, token_program_info.key)?.amount;
+    let (net_amount, _tip, transfer_fee) = get_net_amounts(amount, 0, received)?;
+
+    set_aside_sweep_bounty(
+        program_id,
+        accounts,
+        merchant_info,
+        signer_info,
+        system_program_info,
+        sweep_bounty,
+    )?;
+    pay_fees(
+        program_id,
+        &merchant_account,
+        &config_account,
+        signer_info,
+        &fee_accounts,
+        Err(ProgramError::NotEnoughAccountKeys),
+        sweep_bounty,
+    )?;
+    // count the payment towards the fee tier of the merchant
+    record_merchant_volume(merchant_info, net_amount)?;
+
+    // number the order in the order sequence of the merchant
+    let sequence = next_order_sequence(merchant_info)?;
+    // Saving order information...
+    let order = OrderAccount {
+        discriminator: Discriminator::OrderExpressCheckout as u8,
+        status: OrderStatus::Paid as u8,
+        created: timestamp,
+        modified: timestamp,
+        merchant: merchant_info.key.to_bytes(),
+        mint: mint_info.key.to_bytes(),
+        token: seller_token_info.key.to_bytes(),
+        payer: auto_debit_account.owner,
+        expected_amount: amount,
+        paid_amount: net_amount,
+        tip: 0,
+        transfer_fee,
+        withdrawn_amount: 0,
+        sequence,
+        items: vec![],
+        released_amount: 0,
+        created_slot: sysvars.slot()?,
+        sweep_bounty,
+        order_id,
+        secret,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    order.pack(&mut order_info.try_borrow_mut_data()?);
+
+    // ensure order account is rent exempt
+    if !rent.is_exempt(order_info.lamports(), order_account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    emit_order_paid(order_info.key, &order);
+
+    Ok(order)
+}
@@ -0,0 +1,246 @@
+use crate::{
+    engine::common::{get_program_account_discriminator, transfer_sol},
+    engine::constants::{ACCOUNT_VERSION, BLOCKED},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{BlockedBuyerAccount, Discriminator, IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the blocked buyer account of a buyer of a merchant
+pub fn get_blocked_buyer_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    buyer: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BLOCKED.as_bytes(), &merchant.to_bytes(), &buyer.to_bytes()],
+        program_id,
+    )
+}
+
+/// Ensure that the buyer of a checkout is not blocked by the merchant
+///
+/// Merchants that have not blocked anyone take checkouts from any buyer.
+/// Otherwise the blocked buyer address of the buyer is looked up among the
+/// accounts of the instruction, wherever it is - there is only an account at
+/// that address while the buyer is blocked.
+pub fn check_buyer_not_blocked(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merchant_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+    buyer: &Pubkey,
+) -> ProgramResult {
+    if merchant_account.blocked_buyers == 0 {
+        return Ok(());
+    }
+    let (blocked_buyer_address, _bump_seed) =
+        get_blocked_buyer_address(program_id, merchant_info.key, buyer);
+    let blocked_buyer_info = match accounts
+        .iter()
+        .find(|account_info| *account_info.key == blocked_buyer_address)
+    {
+        None => {
+            msg!("Error: The blocked buyer address of the buyer is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(value) => value,
+    };
+    if get_program_account_discriminator(program_id, blocked_buyer_info)
+        == Some(Discriminator::BlockedBuyer as u8)
+    {
+        msg!("Error: The buyer is blocked by the merchant");
+        return Err(PaymentProcessorError::BuyerBlocked.into());
+    }
+    Ok(())
+}
+
+/// Skip the blocked buyer address of the buyer where an instruction takes its
+/// optional integrator account
+///
+/// The blocked buyer address goes before the integrator account (if any) as it
+/// is never an account of this program unless the buyer is blocked.
+pub fn skip_blocked_buyer_address<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    account_info_iter: &mut I,
+    merchant_info: &AccountInfo<'_>,
+    buyer: &Pubkey,
+    possible_integrator_info: Result<&'a AccountInfo<'b>, ProgramError>,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    match possible_integrator_info {
+        Ok(account_info)
+            if *account_info.owner != *program_id
+                && *account_info.key
+                    == get_blocked_buyer_address(program_id, merchant_info.key, buyer).0 =>
+        {
+            next_account_info(account_info_iter)
+        }
+        other => other,
+    }
+}
+
+/// Block Buyer
+///
+/// Lets the merchant owner stop a wallet from checking out with the merchant
+/// e.g. after chargeback-style abuse or because the wallet is sanctioned.  The
+/// buyer is blocked by creating its blocked buyer account.
+pub fn process_block_buyer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    buyer: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let blocked_buyer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can block and unblock buyers
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    let (blocked_buyer_address, bump_seed) =
+        get_blocked_buyer_address(program_id, merchant_info.key, &buyer);
+    if blocked_buyer_address != *blocked_buyer_info.key {
+        msg!("Error: Blocked buyer address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *blocked_buyer_info.owner == *program_id {
+        msg!("Error: The buyer is already blocked");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let buyer_bytes = buyer.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        BLOCKED.as_bytes(),
+        &merchant_bytes,
+        &buyer_bytes,
+        &[bump_seed],
+    ];
+    let account_size = BlockedBuyerAccount::MIN_LEN;
+    // Fund the blocked buyer account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            blocked_buyer_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            blocked_buyer_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the blocked buyer account
+    invoke_signed(
+        &system_instruction::allocate(blocked_buyer_info.key, account_size as u64),
+        &[blocked_buyer_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the blocked buyer account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(blocked_buyer_info.key, program_id),
+        &[blocked_buyer_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    if !rent.is_exempt(blocked_buyer_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    // Saving blocked buyer information...
+    let blocked_buyer_account = BlockedBuyerAccount {
+        discriminator: Discriminator::BlockedBuyer as u8,
+        merchant: merchant_bytes,
+        buyer: buyer_bytes,
+        blocked: timestamp,
+        version: ACCOUNT_VERSION,
+    };
+    blocked_buyer_account.pack(&mut blocked_buyer_info.try_borrow_mut_data()?);
+    merchant_account.blocked_buyers += 1;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Unblock Buyer
+///
+/// Lets the merchant owner allow a blocked wallet to check out again, getting
+/// back the rent paid for its blocked buyer account.
+pub fn process_unblock_buyer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let blocked_buyer_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can block and unblock buyers
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    // ensure blocked buyer account is owned by this program
+    check_program_owned(program_id, blocked_buyer_info, "blocked buyer")?;
+    let blocked_buyer_account = BlockedBuyerAccount::unpack(&blocked_buyer_info.data.borrow())?;
+    if blocked_buyer_account.discriminator != Discriminator::BlockedBuyer as u8 {
+        msg!("Error: Invalid blocked buyer account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the buyer is blocked by this merchant
+    if blocked_buyer_account.merchant != merchant_info.key.to_bytes() {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+
+    // Closing the blocked buyer account...
+    blocked_buyer_info.data.borrow_mut().fill(0);
+    transfer_sol(
+        blocked_buyer_info.clone(),
+        signer_info.clone(),
+        blocked_buyer_info.lamports(),
+    )?;
+    merchant_account.blocked_buyers -= 1;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
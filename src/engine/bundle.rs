@@ -0,0 +1,388 @@
+use crate::{
+    engine::common::{get_subscription_package, is_subscription_ended},
+    engine::constants::{ACCOUNT_VERSION, BASIS_POINTS, BUNDLE},
+    engine::json::{OrderDiscount, OrderSubscription},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        BundleAccount, BundleMemberAccount, Discriminator, IsClosed, MerchantAccount, Serdes,
+        SubscriptionAccount,
+    },
+    utils::{get_bundle_account_size, get_bundle_member_account_size},
+};
+use serde_json::Error as JSONError;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of a bundle account
+pub fn get_bundle_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    bundle_id: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            BUNDLE.as_bytes(),
+            &authority.to_bytes(),
+            bundle_id.as_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Get the address of the bundle member account of a merchant
+pub fn get_bundle_member_address(
+    program_id: &Pubkey,
+    bundle: &Pubkey,
+    merchant: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BUNDLE.as_bytes(), &bundle.to_bytes(), &merchant.to_bytes()],
+        program_id,
+    )
+}
+
+/// Get the bundle account
+fn get_bundle_account(
+    program_id: &Pubkey,
+    bundle_info: &AccountInfo<'_>,
+) -> Result<BundleAccount, ProgramError> {
+    check_program_owned(program_id, bundle_info, "bundle")?;
+    let bundle_account = BundleAccount::unpack(&bundle_info.data.borrow())?;
+    if bundle_account.discriminator != Discriminator::Bundle as u8 {
+        msg!("Error: Invalid bundle account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(bundle_account)
+}
+
+/// Get the bundle member account of a merchant taking part in the bundle
+fn get_bundle_member_account(
+    program_id: &Pubkey,
+    member_info: &AccountInfo<'_>,
+    bundle_info: &AccountInfo<'_>,
+) -> Result<BundleMemberAccount, ProgramError> {
+    check_program_owned(program_id, member_info, "bundle member")?;
+    let member_account = BundleMemberAccount::unpack(&member_info.data.borrow())?;
+    if member_account.discriminator != Discriminator::BundleMember as u8
+        || bundle_info.key.to_bytes() != member_account.bundle
+    {
+        msg!("Error: Invalid bundle member account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(member_account)
+}
+
+/// The accounts that a checkout needs to apply a bundle discount
+pub struct BundleAccounts<'a, 'b> {
+    pub bundle: &'a AccountInfo<'b>,
+    /// the bundle member account of the package of the order
+    pub member: &'a AccountInfo<'b>,
+    /// the subscription of the payer to the package of another merchant
+    pub subscription: &'a AccountInfo<'b>,
+    /// the bundle member account of the package of that subscription
+    pub other_member: &'a AccountInfo<'b>,
+}
+
+/// Get the bundle accounts that follow the bundle account in a checkout
+pub fn next_bundle_accounts<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    bundle_info: &'a AccountInfo<'b>,
+    account_info_iter: &mut I,
+) -> Result<BundleAccounts<'a, 'b>, ProgramError> {
+    Ok(BundleAccounts {
+        bundle: bundle_info,
+        member: next_account_info(account_info_iter)?,
+        subscription: next_account_info(account_info_iter)?,
+        other_member: next_account_info(account_info_iter)?,
+    })
+}
+
+/// Get the bundle discount of an order for a subscription package
+///
+/// The discount applies when the package takes part in the bundle and the
+/// payer holds an active subscription to the package of another merchant in
+/// the bundle.  The bundle is recorded in place of a coupon.
+pub fn get_bundle_discount(
+    program_id: &Pubkey,
+    bundle: &BundleAccounts<'_, '_>,
+    signer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    data: &str,
+    amount: u64,
+    timestamp: UnixTimestamp,
+) -> Result<OrderDiscount, ProgramError> {
+    let BundleAccounts {
+        bundle: bundle_info,
+        member: member_info,
+        subscription: subscription_info,
+        other_member: other_member_info,
+    } = *bundle;
+    let bundle_account = get_bundle_account(program_id, bundle_info)?;
+    // ensure the order is for the package of the merchant in the bundle
+    let member_account = get_bundle_member_account(program_id, member_info, bundle_info)?;
+    if merchant_info.key.to_bytes() != member_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let order_subscription: Result<OrderSubscription, JSONError> = serde_json::from_str(data);
+    let (expected_subscription, _bump_seed) = Pubkey::find_program_address(
+        &[
+            &signer_info.key.to_bytes(),
+            &merchant_info.key.to_bytes(),
+            member_account.package.as_bytes(),
+        ],
+        program_id,
+    );
+    match order_subscription {
+        Ok(value) if value.subscription == expected_subscription.to_string() => {}
+        _ => {
+            msg!(
+                "Error: The order is not for the bundle package {:?}",
+                member_account.package
+            );
+            return Err(PaymentProcessorError::InvalidOrderData.into());
+        }
+    }
+    // ensure the payer holds an active subscription to another package in the bundle
+    check_program_owned(program_id, subscription_info, "subscription")?;
+    let subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
+    if subscription_account.discriminator != Discriminator::Subscription as u8 {
+        msg!("Error: Invalid subscription account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if signer_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    if is_subscription_ended(&subscription_account) || timestamp >= subscription_account.period_end
+    {
+        msg!("Error: The bundle subscription is not active");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    let other_member_account =
+        get_bundle_member_account(program_id, other_member_info, bundle_info)?;
+    if other_member_account.merchant != subscription_account.merchant
+        || other_member_account.package != subscription_account.name
+        || other_member_account.merchant == member_account.merchant
+    {
+        msg!("Error: The subscription is not for another package in the bundle");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+
+    Ok(OrderDiscount {
+        coupon: bundle_info.key.to_string(),
+        code: bundle_account.bundle_id,
+        amount: ((amount as u128 * bundle_account.discount as u128) / BASIS_POINTS as u128) as u64,
+    })
+}
+
+/// Create Bundle
+///
+/// Creates a bundle account that merchants can join with a subscription
+/// package.  Subscribers of a package in the bundle get the bundle discount
+/// off the packages of the other merchants in the bundle.
+pub fn process_create_bundle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    bundle_id: String,
+    discount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let bundle_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // the bundle id is used as a seed so it has to fit
+    if bundle_id.is_empty() || bundle_id.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The bundle id must be 1 to {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if discount == 0 || discount > BASIS_POINTS {
+        msg!(
+            "Error: The discount must be 1 to {:?} basis points",
+            BASIS_POINTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (bundle_address, bump_seed) = get_bundle_address(program_id, signer_info.key, &bundle_id);
+    if bundle_address != *bundle_info.key {
+        msg!("Error: Bundle address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let signer_bytes = signer_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        BUNDLE.as_bytes(),
+        &signer_bytes,
+        bundle_id.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let account_size = get_bundle_account_size(&bundle_id);
+    // Fund the bundle account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            bundle_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            bundle_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the bundle account
+    invoke_signed(
+        &system_instruction::allocate(bundle_info.key, account_size as u64),
+        &[bundle_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the bundle account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(bundle_info.key, program_id),
+        &[bundle_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    if !rent.is_exempt(bundle_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    // Saving bundle information...
+    let bundle = BundleAccount {
+        discriminator: Discriminator::Bundle as u8,
+        authority: signer_bytes,
+        discount,
+        created: sysvars.unix_timestamp()?,
+        members: 0,
+        bundle_id,
+        version: ACCOUNT_VERSION,
+    };
+    bundle.pack(&mut bundle_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Join Bundle
+///
+/// Adds a subscription package of a merchant to a bundle.  Both the merchant
+/// owner and the bundle authority have to agree to it.
+pub fn process_join_bundle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    package: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let bundle_info = next_account_info(account_info_iter)?;
+    let member_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signers can sign
+    check_signer(signer_info)?;
+    check_signer(authority_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can join the bundle
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the package is one of the merchant's subscription packages
+    get_subscription_package(&package, &merchant_account)?;
+    let mut bundle_account = get_bundle_account(program_id, bundle_info)?;
+    if authority_info.key.to_bytes() != bundle_account.authority {
+        msg!("Error: Wrong bundle authority");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let (member_address, bump_seed) =
+        get_bundle_member_address(program_id, bundle_info.key, merchant_info.key);
+    if member_address != *member_info.key {
+        msg!("Error: Bundle member address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let bundle_bytes = bundle_info.key.to_bytes();
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        BUNDLE.as_bytes(),
+        &bundle_bytes,
+        &merchant_bytes,
+        &[bump_seed],
+    ];
+
+    let account_size = get_bundle_member_account_size(&package);
+    // Fund the bundle member account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            member_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            member_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the bundle member account
+    invoke_signed(
+        &system_instruction::allocate(member_info.key, account_size as u64),
+        &[member_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the bundle member account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(member_info.key, program_id),
+        &[member_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    if !rent.is_exempt(member_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    // Saving bundle member information...
+    let member = BundleMemberAccount {
+        discriminator: Discriminator::BundleMember as u8,
+        bundle: bundle_bytes,
+        merchant: merchant_bytes,
+        joined: sysvars.unix_timestamp()?,
+        package,
+        version: ACCOUNT_VERSION,
+    };
+    member.pack(&mut member_info.try_borrow_mut_data()?);
+
+    // Updating bundle information...
+    bundle_account.members += 1;
+    bundle_account.pack(&mut bundle_info.data.borrow_mut());
+
+    Ok(())
+}
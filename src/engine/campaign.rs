@@ -0,0 +1,246 @@
+use crate::{
+    engine::common::get_installment_expected_amount,
+    engine::constants::{ACCOUNT_VERSION, CAMPAIGN, DEFAULT_DATA, DONATION},
+    engine::pay::{process_order, OrderArgs},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{CampaignAccount, Discriminator, IsClosed, MerchantAccount, Serdes},
+    utils::get_campaign_account_size,
+};
+use serde_json::Value;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the campaign account of a campaign id
+pub fn get_campaign_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    campaign_id: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            CAMPAIGN.as_bytes(),
+            &merchant.to_bytes(),
+            campaign_id.as_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Record a donation made to a campaign
+///
+/// Adds the amount donated to the total raised by the campaign.
+pub fn record_campaign_donation(
+    program_id: &Pubkey,
+    campaign_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    mint_info: &AccountInfo<'_>,
+    amount: u64,
+) -> ProgramResult {
+    check_program_owned(program_id, campaign_info, "campaign")?;
+    let mut campaign_account = CampaignAccount::unpack(&campaign_info.data.borrow())?;
+    if campaign_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !campaign_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if campaign_account.discriminator != Discriminator::Campaign as u8 {
+        msg!("Error: Invalid campaign account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the campaign belongs to this merchant and currency
+    if merchant_info.key.to_bytes() != campaign_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if mint_info.key.to_bytes() != campaign_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+
+    let was_below_goal = campaign_account.raised < campaign_account.goal;
+    // Updating campaign counters...
+    campaign_account.raised += amount;
+    campaign_account.donations += 1;
+    if was_below_goal && campaign_account.raised >= campaign_account.goal {
+        msg!("SolPayments: Campaign goal reached");
+    }
+    campaign_account.pack(&mut campaign_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Create Campaign
+///
+/// Creates a campaign account that adds up the donations made to a campaign of
+/// the merchant, towards an optional goal.
+pub fn process_create_campaign(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    campaign_id: String,
+    goal: u64,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let campaign_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can create campaigns
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // the campaign id is used as a seed so it has to fit
+    if campaign_id.is_empty() || campaign_id.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The campaign id must be 1 to {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (campaign_address, bump_seed) =
+        get_campaign_address(program_id, merchant_info.key, &campaign_id);
+    if campaign_address != *campaign_info.key {
+        msg!("Error: Campaign address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        CAMPAIGN.as_bytes(),
+        &merchant_bytes,
+        campaign_id.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+
+    let account_size = get_campaign_account_size(&campaign_id, &data);
+    // Fund the campaign account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            campaign_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            campaign_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the campaign account
+    invoke_signed(
+        &system_instruction::allocate(campaign_info.key, account_size as u64),
+        &[campaign_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the campaign account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(campaign_info.key, program_id),
+        &[campaign_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving campaign information...
+    let campaign = CampaignAccount {
+        discriminator: Discriminator::Campaign as u8,
+        merchant: merchant_bytes,
+        mint: mint_info.key.to_bytes(),
+        created: timestamp,
+        goal,
+        raised: 0,
+        donations: 0,
+        campaign_id,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    campaign.pack(&mut campaign_info.try_borrow_mut_data()?);
+
+    // ensure campaign account is rent exempt
+    if !rent.is_exempt(campaign_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Donate
+///
+/// An express checkout for whatever amount the donor chooses.  The order is
+/// marked as a donation in its data and is never paid in installments, and the
+/// donation is added to the campaign when the campaign account is included.
+pub fn process_donate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+    order_id: String,
+    secret: String,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    if amount == 0 {
+        msg!("Error: The amount must be greater than 0");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+    // there is no expected amount to pay in installments towards
+    if get_installment_expected_amount(&data).is_some() {
+        msg!("Error: Donations do not have an expected amount");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    let data = match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            json_data.insert(String::from(DONATION), Value::Bool(true));
+            Value::Object(json_data).to_string()
+        }
+        _ => return Err(PaymentProcessorError::InvalidOrderData.into()),
+    };
+    process_order(
+        program_id,
+        accounts,
+        sysvars,
+        OrderArgs {
+            amount,
+            order_id,
+            secret,
+            maybe_data: Some(data),
+        },
+        0,
+        Option::None,
+    )
+}
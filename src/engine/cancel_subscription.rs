@@ -1,7 +1,19 @@
 use crate::{
-    engine::common::{subscribe_checks, transfer_sol},
+    engine::common::{
+        check_package_mint, get_package_of_subscription, is_subscription_ended, subscribe_checks,
+        transfer_sol, update_merchant_references,
+    },
     engine::constants::PDA_SEED,
+    engine::json::Package,
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sweep::return_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        self, check_token_program, find_mint, has_withheld_transfer_fees, invoke_transfer,
+    },
+    engine::validation::{check_pda, check_program_owned, check_signer, check_token_account_mint},
     error::PaymentProcessorError,
+    events::{emit_event, PaymentProcessorEvent},
     state::{
         Discriminator, IsClosed, OrderAccount, OrderStatus, Serdes, SubscriptionAccount,
         SubscriptionStatus,
@@ -9,19 +21,52 @@ use crate::{
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
     entrypoint::ProgramResult,
     msg,
     program::invoke_signed,
     program_error::ProgramError,
     program_pack::IsInitialized,
     pubkey::Pubkey,
-    sysvar::{clock::Clock, Sysvar},
 };
-use spl_token::{self};
+
+/// Get the part of the amount paid for a subscription order that pays for the
+/// unused part of the subscription period
+///
+/// The order is the one that paid for the current period (see period_order), and
+/// is taken to pay for the last periods of the subscription, as many as the
+/// package price of all the seats goes into the amount paid (at least one).
+pub fn get_prorated_refund(
+    order_account: &OrderAccount,
+    package: &Package,
+    seats: u64,
+    period_end: UnixTimestamp,
+    timestamp: UnixTimestamp,
+) -> u64 {
+    if package.duration <= 0 || timestamp >= period_end {
+        return 0;
+    }
+    let periods = match package.price.saturating_mul(seats) {
+        0 => 1,
+        price => std::cmp::max(1, order_account.paid_amount / price),
+    };
+    let paid_duration = package.duration as u128 * periods as u128;
+    let unused_duration =
+        std::cmp::min(period_end.saturating_sub(timestamp) as u128, paid_duration);
+    ((order_account.paid_amount as u128 * unused_duration) / paid_duration) as u64
+}
 
 /// Cancel Subscription
-/// currently only works well for subscriptions still in the trial period
-pub fn process_cancel_subscription(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+///
+/// Cancelling during the trial period refunds the order in full.  After the
+/// trial period, packages with prorated refunds refund the unused part of the
+/// period paid by the order of the current period and end the subscription right
+/// away, leaving the rest of the order to be withdrawn by the merchant.
+pub fn process_cancel_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let signer_info = next_account_info(account_info_iter)?;
@@ -34,31 +79,24 @@ pub fn process_cancel_subscription(program_id: &Pubkey, accounts: &[AccountInfo]
     let pda_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
 
-    let timestamp = Clock::get()?.unix_timestamp;
+    let timestamp = sysvars.unix_timestamp()?;
 
     // ensure signer can sign
-    if !signer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    check_signer(signer_info)?;
     // ensure subscription account is owned by this program
-    if *subscription_info.owner != *program_id {
-        msg!("Error: Wrong owner for subscription account");
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    check_program_owned(program_id, subscription_info, "subscription")?;
     // ensure token accounts are owned by token program
-    if *order_token_info.owner != spl_token::id() {
+    check_token_program(token_program_info)?;
+    if *order_token_info.owner != *token_program_info.key {
         msg!("Error: Order token account must be owned by token program");
         return Err(ProgramError::IncorrectProgramId);
     }
-    if *refund_token_info.owner != spl_token::id() {
+    if *refund_token_info.owner != *token_program_info.key {
         msg!("Error: Refund token account must be owned by token program");
         return Err(ProgramError::IncorrectProgramId);
     }
     // check that provided pda is correct
-    let (pda, pda_nonce) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
-    if pda_info.key != &pda {
-        return Err(ProgramError::InvalidSeeds);
-    }
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
 
     // get the subscription account
     let mut subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
@@ -72,72 +110,151 @@ pub fn process_cancel_subscription(program_id: &Pubkey, accounts: &[AccountInfo]
         msg!("Error: Invalid subscription account");
         return Err(ProgramError::InvalidAccountData);
     }
-    let (mut order_account, package) = subscribe_checks(
+    let (mut order_account, merchant_account) = subscribe_checks(
         program_id,
         signer_info,
         merchant_info,
         order_info,
         subscription_info,
-        &subscription_account.name,
     )?;
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        &merchant_account,
+    )?;
+    check_package_mint(&package, &order_account)?;
 
     // ensure the order payment token account is the right one
     if order_token_info.key.to_bytes() != order_account.token {
         msg!("Error: Incorrect order token account");
-        return Err(ProgramError::InvalidAccountData);
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
     }
-    // ensure the signer is the order payer
-    if signer_info.key.to_bytes() != order_account.payer {
-        msg!("Error: One can only cancel their own subscription payment");
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
+    // ensure the signer owns the subscription, which the payer of the order may
+    // have handed over
+    if signer_info.key.to_bytes() != subscription_account.owner {
+        msg!("Error: One can only cancel their own subscription");
+        return Err(PaymentProcessorError::NotOrderOwner.into());
+    }
+    // ensure the order is the one that paid for the current period, which
+    // subscriptions that have not been renewed since they were migrated do not
+    // know (and get no prorated refund for)
+    let is_period_order = subscription_account.period_order == order_info.key.to_bytes();
+    if !is_period_order && subscription_account.period_order != [0; 32] {
+        msg!("Error: The order did not pay for the current period of the subscription");
         return Err(ProgramError::InvalidAccountData);
     }
+    // ensure the refund goes to the subscription owner
+    let refund_token_data = check_token_account_mint(
+        refund_token_info,
+        token_program_info.key,
+        &Pubkey::new_from_array(order_account.mint),
+    )?;
+    if refund_token_data.owner != *signer_info.key {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
 
     // get the trial period duration
     let trial_duration: i64 = match package.trial {
         None => 0,
         Some(value) => value,
     };
+    let trial_end = subscription_account
+        .joined
+        .checked_add(trial_duration)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
     // don't allow cancellation if trial period ended
-    if timestamp >= (subscription_account.joined + trial_duration) {
-        msg!("Info: Subscription amount not refunded because trial period has ended.");
+    let refund = if timestamp >= trial_end {
+        // the period of a paused subscription carries on from when it is resumed
+        let period_end = if subscription_account.status == SubscriptionStatus::Paused as u8 {
+            timestamp
+                .checked_add(subscription_account.remaining_seconds)
+                .ok_or(PaymentProcessorError::AmountOverflow)?
+        } else {
+            subscription_account.period_end
+        };
+        let refund_amount = match package.prorated_refunds {
+            Some(true) if is_period_order => get_prorated_refund(
+                &order_account,
+                &package,
+                subscription_account.seats,
+                period_end,
+                timestamp,
+            ),
+            _ => 0,
+        };
+        if refund_amount == 0 {
+            msg!("Info: Subscription amount not refunded because trial period has ended.");
+        } else {
+            msg!(
+                "Info: Refunding {:?} for the unused part of the subscription period",
+                refund_amount
+            );
+            // Transferring the unused part of the payment back to the payer...
+            invoke_transfer(
+                token_program_info,
+                order_token_info,
+                possible_mint_info,
+                refund_token_info,
+                pda_info,
+                refund_amount,
+                &[&[PDA_SEED, &[pda_nonce]]],
+            )?;
+            // the rest of the order stays withdrawable by the merchant
+            order_account.paid_amount -= refund_amount;
+            order_account.expected_amount =
+                order_account.expected_amount.saturating_sub(refund_amount);
+            order_account.modified = timestamp;
+            OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+            // set period end to right now
+            subscription_account.period_end = timestamp;
+        }
+        refund_amount
     } else {
-        // Transferring payment back to the payer...
-        invoke_signed(
-            &spl_token::instruction::transfer(
-                token_program_info.key,
-                order_token_info.key,
-                refund_token_info.key,
-                &pda,
-                &[&pda],
-                order_account.paid_amount,
-            )
-            .unwrap(),
-            &[
-                token_program_info.clone(),
-                pda_info.clone(),
-                order_token_info.clone(),
-                refund_token_info.clone(),
-            ],
-            &[&[&PDA_SEED, &[pda_nonce]]],
+        let refund_amount = order_account
+            .paid_amount
+            .checked_add(order_account.tip)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+        // Transferring payment (and tip) back to the payer...
+        invoke_transfer(
+            token_program_info,
+            order_token_info,
+            possible_mint_info,
+            refund_token_info,
+            pda_info,
+            refund_amount,
+            &[&[PDA_SEED, &[pda_nonce]]],
         )?;
         // Close the order token account since it will never be needed again
-        invoke_signed(
-            &spl_token::instruction::close_account(
-                token_program_info.key,
-                order_token_info.key,
-                account_to_receive_sol_refund_info.key,
-                &pda,
-                &[&pda],
-            )
-            .unwrap(),
-            &[
-                token_program_info.clone(),
-                order_token_info.clone(),
-                account_to_receive_sol_refund_info.clone(),
-                pda_info.clone(),
-            ],
-            &[&[&PDA_SEED, &[pda_nonce]]],
-        )?;
+        if !has_withheld_transfer_fees(order_token_info) {
+            invoke_signed(
+                &token::close_account(
+                    token_program_info.key,
+                    order_token_info.key,
+                    account_to_receive_sol_refund_info.key,
+                    &pda,
+                    &[&pda],
+                )
+                .unwrap(),
+                &[
+                    token_program_info.clone(),
+                    order_token_info.clone(),
+                    account_to_receive_sol_refund_info.clone(),
+                    pda_info.clone(),
+                ],
+                &[&[PDA_SEED, &[pda_nonce]]],
+            )?;
+            // count the closed order token account towards the open token accounts of the merchant
+            record_token_accounts(
+                program_id,
+                find_merchant_stats(program_id, account_info_iter),
+                &order_account.merchant,
+                0,
+                1,
+            )?;
+        }
         // mark order account as closed
         order_account.discriminator = Discriminator::Closed as u8;
         // Transfer all the sol from the order account to the sol_destination.
@@ -146,20 +263,36 @@ pub fn process_cancel_subscription(program_id: &Pubkey, accounts: &[AccountInfo]
             account_to_receive_sol_refund_info.clone(),
             order_info.lamports(),
         )?;
+        // the cancelled order no longer counts towards the references of the merchant
+        update_merchant_references(merchant_info, false)?;
+        // the bounty the order set aside for the sweep crank goes back to the program owner
+        return_sweep_bounty(program_id, accounts, &mut order_account)?;
         // Updating order account information...
         order_account.status = OrderStatus::Cancelled as u8;
         order_account.modified = timestamp;
         OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
         // set period end to right now
         subscription_account.period_end = timestamp;
+        refund_amount
+    };
+
+    // the cancelled subscription no longer counts towards the references of the merchant
+    if !is_subscription_ended(&subscription_account) {
+        update_merchant_references(merchant_info, false)?;
     }
 
     // Updating subscription account information...
     subscription_account.status = SubscriptionStatus::Cancelled as u8;
+    subscription_account.remaining_seconds = 0;
     SubscriptionAccount::pack(
         &subscription_account,
         &mut subscription_info.data.borrow_mut(),
     );
 
+    emit_event(&PaymentProcessorEvent::Cancelled {
+        subscription: subscription_info.key.to_bytes(),
+        refund,
+    });
+
     Ok(())
 }
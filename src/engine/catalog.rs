@@ -0,0 +1,288 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, PACKAGE},
+    engine::json::Package,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantAccount, PackageAccount, Serdes},
+    utils::get_package_account_size,
+};
+use serde_json::Error as JSONError;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the package account of a package of the catalog of a merchant
+pub fn get_package_address(program_id: &Pubkey, merchant: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PACKAGE.as_bytes(), &merchant.to_bytes(), name.as_bytes()],
+        program_id,
+    )
+}
+
+/// Get a package of the catalog of a merchant
+pub fn get_catalog_package(
+    program_id: &Pubkey,
+    package_info: &AccountInfo<'_>,
+    merchant: &Pubkey,
+) -> Result<(PackageAccount, Package), ProgramError> {
+    let package_account = get_package_account(program_id, package_info)?;
+    if merchant.to_bytes() != package_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let package = parse_package(&package_account.data)?;
+    Ok((package_account, package))
+}
+
+fn get_package_account(
+    program_id: &Pubkey,
+    package_info: &AccountInfo<'_>,
+) -> Result<PackageAccount, ProgramError> {
+    // ensure package account is owned by this program
+    check_program_owned(program_id, package_info, "package")?;
+    let package_account = PackageAccount::unpack(&package_info.data.borrow())?;
+    if package_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !package_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if package_account.discriminator != Discriminator::Package as u8 {
+        msg!("Error: Invalid package account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(package_account)
+}
+
+fn parse_package(data: &str) -> Result<Package, ProgramError> {
+    let maybe_package: Result<Package, JSONError> = serde_json::from_str(data);
+    match maybe_package {
+        Err(_error) => Err(PaymentProcessorError::InvalidSubscriptionData.into()),
+        Ok(value) => Ok(value),
+    }
+}
+
+/// Get the merchant account of the merchant owner signing for its catalog
+fn get_catalog_merchant(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<MerchantAccount, ProgramError> {
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can manage the catalog
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let allowed_merchant_account_types = [
+        Discriminator::MerchantSubscription as u8,
+        Discriminator::MerchantSubscriptionWithTrial as u8,
+    ];
+    if !allowed_merchant_account_types.contains(&merchant_account.discriminator) {
+        msg!("Error: Invalid merchant account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(merchant_account)
+}
+
+/// Mark the merchant as having packages with trial periods when the package has one
+fn update_merchant_trial(
+    merchant_info: &AccountInfo<'_>,
+    merchant_account: &mut MerchantAccount,
+    package: &Package,
+) {
+    if package.trial.unwrap_or(0) > 0 {
+        merchant_account.discriminator = Discriminator::MerchantSubscriptionWithTrial as u8;
+        merchant_account.pack(&mut merchant_info.data.borrow_mut());
+    }
+}
+
+/// Create Package
+///
+/// Lets the merchant owner add a subscription package to the catalog of the
+/// merchant, as an account of its own instead of an entry of the merchant data.
+/// The merchant has to take subscriptions, i.e. its data holds a list of
+/// packages (which may be empty).
+pub fn process_create_package(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    data: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let package_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let mut merchant_account = get_catalog_merchant(program_id, signer_info, merchant_info)?;
+    let package = parse_package(&data)?;
+    // the package name is used as a seed so it has to fit
+    if package.name.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The package name must be at most {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (package_address, bump_seed) =
+        get_package_address(program_id, merchant_info.key, &package.name);
+    if package_address != *package_info.key {
+        msg!("Error: Package address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        PACKAGE.as_bytes(),
+        &merchant_bytes,
+        package.name.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let account_size = get_package_account_size(&package.name, &data);
+    // Fund the package account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            package_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            package_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the package account
+    invoke_signed(
+        &system_instruction::allocate(package_info.key, account_size as u64),
+        &[package_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the package account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(package_info.key, program_id),
+        &[package_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving package information...
+    let package_account = PackageAccount {
+        discriminator: Discriminator::Package as u8,
+        merchant: merchant_bytes,
+        is_retired: false,
+        modified: sysvars.unix_timestamp()?,
+        name: package.name.clone(),
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    package_account.pack(&mut package_info.try_borrow_mut_data()?);
+    update_merchant_trial(merchant_info, &mut merchant_account, &package);
+
+    // ensure package account is rent exempt
+    if !rent.is_exempt(package_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Update Package
+///
+/// Lets the merchant owner change a package of its catalog.  New subscribers
+/// get the updated package, while existing subscribers keep renewing at the
+/// price they subscribed at.  The package keeps its name, and its data has to
+/// fit in the package account.
+pub fn process_update_package(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    mut data: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let package_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    let mut merchant_account = get_catalog_merchant(program_id, signer_info, merchant_info)?;
+    let (mut package_account, _package) =
+        get_catalog_package(program_id, package_info, merchant_info.key)?;
+    let package = parse_package(&data)?;
+    if package.name != package_account.name {
+        msg!("Error: The package name cannot be changed");
+        return Err(PaymentProcessorError::InvalidSubscriptionPackage.into());
+    }
+    let account_size = package_info.data_len();
+    let data_size = get_package_account_size(&package_account.name, &data);
+    if data.len() != data.chars().count() || data_size > account_size {
+        msg!(
+            "Error: The package data must be ASCII and fit in {:?} bytes",
+            account_size - get_package_account_size(&package_account.name, &String::new())
+        );
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    // keep the account size by padding the data, in the latest layout
+    data.push_str(&" ".repeat(account_size - data_size));
+
+    // Updating package information...
+    package_account.version = ACCOUNT_VERSION;
+    package_account.modified = sysvars.unix_timestamp()?;
+    package_account.data = data;
+    package_account.pack(&mut package_info.data.borrow_mut());
+    update_merchant_trial(merchant_info, &mut merchant_account, &package);
+
+    Ok(())
+}
+
+/// Retire Package
+///
+/// Lets the merchant owner take a package of its catalog off sale.  Retired
+/// packages take no new subscriptions, while existing subscribers keep renewing.
+pub fn process_retire_package(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let package_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    get_catalog_merchant(program_id, signer_info, merchant_info)?;
+    let (mut package_account, _package) =
+        get_catalog_package(program_id, package_info, merchant_info.key)?;
+    if package_account.is_retired {
+        msg!("Error: The package has already been retired");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Updating package information...
+    package_account.is_retired = true;
+    package_account.modified = sysvars.unix_timestamp()?;
+    package_account.pack(&mut package_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::{
+    engine::common::{is_subscription_ended, transfer_sol},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, MerchantAccount, Serdes, SubscriptionAccount, SubscriptionStatus,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Close Merchant
+///
+/// Lets the merchant owner close a merchant account that is no longer in use and
+/// get back the rent paid for it.  The merchant account keeps count of its open
+/// orders and active subscriptions, and can only be closed once there are none.
+/// Subscriptions whose period has ended without being renewed or cancelled can be
+/// included to be released along the way.  Merchant accounts in the legacy layout
+/// have to be migrated first, which counts their references.
+pub fn process_close_merchant(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can close the merchant account
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // merchants in the legacy layout do not count their references, which the
+    // migration counts
+    check_account_migrated(merchant_account.version, "merchant")?;
+
+    // Releasing the lapsed subscriptions (if any)...
+    for subscription_info in account_info_iter {
+        check_program_owned(program_id, subscription_info, "subscription")?;
+        let mut subscription_account =
+            SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
+        if subscription_account.discriminator != Discriminator::Subscription as u8 {
+            msg!("Error: Invalid subscription account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if merchant_info.key.to_bytes() != subscription_account.merchant {
+            return Err(PaymentProcessorError::WrongMerchant.into());
+        }
+        if is_subscription_ended(&subscription_account) {
+            continue;
+        }
+        // paused subscriptions still have the rest of their period to come
+        if timestamp < subscription_account.period_end
+            || subscription_account.status == SubscriptionStatus::Paused as u8
+        {
+            msg!(
+                "Error: The subscription is active until {:?}",
+                subscription_account.period_end
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        subscription_account.status = SubscriptionStatus::Cancelled as u8;
+        SubscriptionAccount::pack(
+            &subscription_account,
+            &mut subscription_info.data.borrow_mut(),
+        );
+        merchant_account.references = merchant_account.references.saturating_sub(1);
+    }
+
+    // ensure nothing references the merchant account anymore
+    if merchant_account.references > 0 {
+        msg!(
+            "Error: The merchant still has {:?} open orders or active subscriptions",
+            merchant_account.references
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Closing the merchant account...
+    merchant_info.data.borrow_mut().fill(0);
+    transfer_sol(
+        merchant_info.clone(),
+        signer_info.clone(),
+        merchant_info.lamports(),
+    )?;
+
+    Ok(())
+}
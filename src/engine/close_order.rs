@@ -0,0 +1,154 @@
+use crate::{
+    engine::associated_token::get_order_token_address,
+    engine::common::{get_program_account_discriminator, transfer_sol},
+    engine::constants::PDA_SEED,
+    engine::stats::record_token_accounts,
+    engine::sweep::return_sweep_bounty,
+    engine::token::{self, check_token_program, has_withheld_transfer_fees, unpack_token_account},
+    engine::validation::{check_pda, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Close Order
+///
+/// Lets the merchant owner close the account of an order that is settled (i.e.
+/// withdrawn, cancelled, reclaimed, refunded or had its fees waived) and get
+/// back the rent paid for it.  The order token accounts that were left open
+/// when the order was settled (e.g. because they held withheld transfer fees
+/// that have since been harvested) are closed along the way.
+pub fn process_close_order(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    check_token_program(token_program_info)?;
+    // get the merchant account
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can close the order account
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the order is settled
+    if order_account.status != OrderStatus::Withdrawn as u8
+        && order_account.status != OrderStatus::Cancelled as u8
+        && order_account.status != OrderStatus::Expired as u8
+        && order_account.status != OrderStatus::FeesWaived as u8
+        && order_account.status != OrderStatus::Refunded as u8
+    {
+        msg!("Error: Only settled orders can be closed");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Closing the order token accounts that are still open (if any)...
+    let mut possible_stats_info = Option::None;
+    let mut closed = 0;
+    for account_info in account_info_iter {
+        if get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::MerchantStats as u8)
+        {
+            possible_stats_info = Some(account_info);
+            continue;
+        }
+        if get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::SweepBounty as u8)
+        {
+            continue;
+        }
+        let token_data = unpack_token_account(account_info, token_program_info.key)?;
+        // ensure this is one of the token accounts of the order
+        if account_info.key.to_bytes() != order_account.token
+            && *account_info.key
+                != get_order_token_address(program_id, order_info.key, &token_data.mint)
+        {
+            msg!("Error: Not a token account of this order");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if token_data.amount > 0 || has_withheld_transfer_fees(account_info) {
+            msg!(
+                "Error: The order token account {:?} is not empty",
+                account_info.key
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+        invoke_signed(
+            &token::close_account(
+                token_program_info.key,
+                account_info.key,
+                recipient_info.key,
+                &pda,
+                &[&pda],
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                account_info.clone(),
+                recipient_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        closed += 1;
+    }
+    // count the closed order token accounts towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        possible_stats_info,
+        &order_account.merchant,
+        0,
+        closed,
+    )?;
+
+    // the bounty the order set aside for the sweep crank (if it is still held)
+    // goes back to the program owner
+    return_sweep_bounty(program_id, accounts, &mut order_account)?;
+    // Closing the order account...
+    order_account.discriminator = Discriminator::Closed as u8;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+    transfer_sol(
+        order_info.clone(),
+        recipient_info.clone(),
+        order_info.lamports(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::{
+    engine::common::{is_subscription_ended, transfer_sol},
+    engine::constants::SUBSCRIPTION_RETENTION_PERIOD,
+    engine::renew::get_subscription_account,
+    engine::sysvars::SysvarProvider,
+    error::PaymentProcessorError,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Close Subscription
+///
+/// A permissionless instruction that closes the account of a subscription that
+/// was cancelled or has expired, once `SUBSCRIPTION_RETENTION_PERIOD` has passed
+/// since the end of its period, and gives the rent back to the subscription owner.
+/// Until then the subscription can still be renewed.
+pub fn process_close_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let subscription_info = next_account_info(account_info_iter)?;
+    let owner_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let subscription_account = get_subscription_account(program_id, subscription_info)?;
+    // ensure the rent goes back to the subscription owner
+    if owner_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    if !is_subscription_ended(&subscription_account) {
+        msg!("Error: Only cancelled or expired subscriptions can be closed");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let retained_until = subscription_account.period_end + SUBSCRIPTION_RETENTION_PERIOD;
+    if timestamp < retained_until {
+        msg!(
+            "Error: The subscription is retained until {:?}",
+            retained_until
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Closing the subscription account...
+    subscription_info.data.borrow_mut().fill(0);
+    transfer_sol(
+        subscription_info.clone(),
+        owner_info.clone(),
+        subscription_info.lamports(),
+    )?;
+
+    Ok(())
+}
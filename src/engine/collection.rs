@@ -0,0 +1,154 @@
+use crate::{
+    engine::constants::BASIS_POINTS,
+    engine::json::{OrderDiscount, OrderItems},
+    engine::receipt::{get_metadata_address, get_token_metadata_program_id},
+    engine::terms::find_catalog_item,
+    engine::token::unpack_token_account,
+    error::PaymentProcessorError,
+    state::CatalogItem,
+};
+use borsh::BorshDeserialize;
+use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+
+/// the MetadataV1 key of the accounts of the token metadata program
+const METADATA_V1: u8 = 4;
+
+/// The start of a metadata account of the token metadata program, up to the
+/// collection of the token
+#[derive(BorshDeserialize)]
+#[allow(dead_code)] // not dead code..
+struct Metadata {
+    key: u8,
+    update_authority: Pubkey,
+    mint: Pubkey,
+    data: Data,
+    primary_sale_happened: bool,
+    is_mutable: bool,
+    edition_nonce: Option<u8>,
+    token_standard: Option<u8>,
+    collection: Option<Collection>,
+}
+
+#[derive(BorshDeserialize)]
+#[allow(dead_code)] // not dead code..
+struct Data {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<Vec<Creator>>,
+}
+
+#[derive(BorshDeserialize)]
+#[allow(dead_code)] // not dead code..
+struct Creator {
+    address: Pubkey,
+    verified: bool,
+    share: u8,
+}
+
+#[derive(BorshDeserialize)]
+struct Collection {
+    verified: bool,
+    key: Pubkey,
+}
+
+/// Get the collection of an NFT held by the buyer
+///
+/// The NFT has to be in a token account of the buyer, and only a collection that
+/// was verified by its collection authority counts, since anyone can mint an NFT
+/// that claims to be part of a collection.
+pub fn get_held_collection(
+    signer_info: &AccountInfo<'_>,
+    metadata_info: &AccountInfo<'_>,
+    nft_token_info: &AccountInfo<'_>,
+) -> Result<Pubkey, ProgramError> {
+    if *metadata_info.owner != get_token_metadata_program_id() {
+        msg!("Error: Wrong owner for metadata account");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let nft_token_data = unpack_token_account(nft_token_info, &spl_token::id())?;
+    if nft_token_data.owner != *signer_info.key {
+        msg!("Error: The NFT is not held by the buyer");
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    if nft_token_data.amount == 0 {
+        msg!("Error: The NFT is not held by the buyer");
+        return Err(ProgramError::InsufficientFunds);
+    }
+    if *metadata_info.key != get_metadata_address(&nft_token_data.mint) {
+        msg!("Error: Metadata address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // metadata accounts are padded, so only the start of the data is read
+    let metadata = match Metadata::deserialize(&mut &metadata_info.data.borrow()[..]) {
+        Ok(value) if value.key == METADATA_V1 && value.mint == nft_token_data.mint => value,
+        _ => {
+            msg!("Error: Invalid metadata account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    };
+    match metadata.collection {
+        Some(collection) if collection.verified => Ok(collection.key),
+        _ => {
+            msg!("Error: The NFT is not part of a verified collection");
+            Err(ProgramError::InvalidAccountData)
+        }
+    }
+}
+
+/// Get the holder discount of a chain checkout order
+///
+/// Items gated by a collection without a holder discount are only sold to
+/// holders of an NFT of the collection.  Items with a holder discount are sold
+/// to anyone, and holders get the discount off the price of the items priced in
+/// the checkout mint.  The metadata account of the NFT is recorded in place of a
+/// coupon, with the collection as its code.
+pub fn get_holder_discount(
+    catalog: &[CatalogItem],
+    order_items: &OrderItems,
+    mint: &Pubkey,
+    possible_nft: Option<(&Pubkey, Pubkey)>,
+) -> Result<Option<OrderDiscount>, ProgramError> {
+    let mut amount: u64 = 0;
+    for (key, quantity) in order_items.iter() {
+        let registered_item = match find_catalog_item(catalog, key) {
+            None => return Err(PaymentProcessorError::InvalidOrderData.into()),
+            Some(value) => value,
+        };
+        let collection = match registered_item.collection {
+            None => continue,
+            Some(value) => value,
+        };
+        let is_holder = match possible_nft {
+            Some((_metadata, held_collection)) => held_collection.to_bytes() == collection,
+            None => false,
+        };
+        match registered_item.holder_discount {
+            None if !is_holder => {
+                msg!(
+                    "Error: Item {:?} is only sold to holders of the collection {:?}",
+                    key,
+                    Pubkey::new_from_array(collection)
+                );
+                return Err(PaymentProcessorError::InvalidOrderData.into());
+            }
+            Some(discount) if is_holder && registered_item.mint == mint.to_bytes() => {
+                amount += ((registered_item.price as u128
+                    * *quantity as u128
+                    * discount.min(BASIS_POINTS) as u128)
+                    / BASIS_POINTS as u128) as u64;
+            }
+            _ => {}
+        }
+    }
+
+    match possible_nft {
+        Some((metadata, held_collection)) if amount > 0 => Ok(Some(OrderDiscount {
+            coupon: metadata.to_string(),
+            code: held_collection.to_string(),
+            amount,
+        })),
+        _ => Ok(None),
+    }
+}
@@ -1,13 +1,30 @@
 use crate::{
-    engine::json::{OrderSubscription, Package, Packages},
+    engine::catalog::{get_catalog_package, get_package_address},
+    engine::constants::{
+        DISCOUNT, ESCROW, EXPECTED_AMOUNT, EXPIRES_AT, FEES, FIAT, INITIAL, ORDER, PDA_SEED, TOKENS,
+    },
+    engine::json::{
+        OrderDiscount, OrderFees, OrderFiatPrice, OrderSubscription, OrderToken, Package,
+    },
+    engine::pause::check_merchant_not_paused,
+    engine::terms::get_merchant_packages,
+    engine::token::{
+        self, check_token_program, get_token_account_size, has_withheld_transfer_fees,
+        invoke_transfer, unpack_token_account,
+    },
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
     error::PaymentProcessorError,
-    state::{Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+    state::{
+        Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, PublicKey, Serdes,
+        SubscriptionAccount, SubscriptionStatus,
+    },
 };
-use serde_json::Error as JSONError;
-use solana_program::program_pack::Pack;
+use serde_json::{Error as JSONError, Value};
 use solana_program::{
-    account_info::AccountInfo,
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
     entrypoint::ProgramResult,
+    hash::hashv,
     msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
@@ -34,18 +51,260 @@ pub fn verify_subscription_order(
     Ok(())
 }
 
+/// Get an integer value (if any) from the order data
+///
+/// For split checkout orders (and chain checkout orders paid before their items
+/// were typed) the data supplied at checkout is found under INITIAL
+pub fn get_order_data_i64(order_account: &OrderAccount, key: &str) -> Option<i64> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    json_data[key]
+        .as_i64()
+        .or_else(|| json_data[INITIAL][key].as_i64())
+}
+
+/// Get the order expiry (if any)
+///
+/// The expiry is read from the order data e.g. {"expires_at": 1625097600}
+pub fn get_order_expiry(order_account: &OrderAccount) -> Option<UnixTimestamp> {
+    get_order_data_i64(order_account, EXPIRES_AT)
+}
+
+/// Check whether an order has expired i.e. its payment can only be reclaimed by the payer
+///
+/// An expiry that is not after the creation of the order is ignored, such an
+/// order was paid when it had already expired, which checkouts refuse.
+pub fn is_order_expired(order_account: &OrderAccount, timestamp: UnixTimestamp) -> bool {
+    match get_order_expiry(order_account) {
+        None => false,
+        Some(expires_at) => expires_at > order_account.created && timestamp >= expires_at,
+    }
+}
+
+/// Get the escrow timeout (if any)
+///
+/// The escrow timeout is read from the order data e.g. {"escrow": 1209600} and is
+/// the number of seconds after the order was created that the payment is released
+/// to the merchant if the buyer has not confirmed delivery
+pub fn get_order_escrow_timeout(order_account: &OrderAccount) -> Option<i64> {
+    get_order_data_i64(order_account, ESCROW)
+}
+
+/// Get the amount received for an order
+///
+/// i.e. the payment, plus the transfer fees that the mint withheld from it.
+/// Amounts out of range fail with AmountOverflow.
+pub fn get_order_received_amount(order_account: &OrderAccount) -> Result<u64, ProgramError> {
+    order_account
+        .paid_amount
+        .checked_add(order_account.transfer_fee)
+        .ok_or_else(|| PaymentProcessorError::AmountOverflow.into())
+}
+
+/// Get the amount that the order token account still holds for an order
+///
+/// i.e. the payment and tip, less the part of them withdrawn with WithdrawAmount.
+/// Amounts out of range fail with AmountOverflow.
+pub fn get_order_balance(order_account: &OrderAccount) -> Result<u64, ProgramError> {
+    order_account
+        .paid_amount
+        .checked_add(order_account.tip)
+        .and_then(|value| value.checked_sub(order_account.withdrawn_amount))
+        .ok_or_else(|| PaymentProcessorError::AmountOverflow.into())
+}
+
+/// Get the expected amount (if any) of an order paid in installments
+///
+/// The expected amount is read from the order data e.g. {"expected_amount": 5000000}
+pub fn get_installment_expected_amount(data: &str) -> Option<u64> {
+    let json_data: Value = match serde_json::from_str(data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    json_data[EXPECTED_AMOUNT].as_u64()
+}
+
+/// Get the discriminator of an account owned by this program
+///
+/// Used to tell apart the optional accounts of an instruction
+pub fn get_program_account_discriminator(
+    program_id: &Pubkey,
+    account_info: &AccountInfo<'_>,
+) -> Option<u8> {
+    if *account_info.owner != *program_id {
+        return None;
+    }
+    account_info.data.borrow().first().copied()
+}
+
+/// Record the fees paid for an order in the order data
+///
+/// The fees are only recorded if the order data is a JSON object
+pub fn set_order_fees(data: String, program_owner_fee: u64, sponsor_fee: u64) -> String {
+    match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            let fees = OrderFees {
+                program_owner: program_owner_fee,
+                sponsor: sponsor_fee,
+            };
+            json_data.insert(String::from(FEES), serde_json::to_value(fees).unwrap());
+            Value::Object(json_data).to_string()
+        }
+        _ => data,
+    }
+}
+
+/// Record the coupon discount taken off an order in the order data
+///
+/// The discount is only recorded if the order data is a JSON object
+pub fn set_order_discount(data: String, order_discount: OrderDiscount) -> String {
+    match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            json_data.insert(
+                String::from(DISCOUNT),
+                serde_json::to_value(order_discount).unwrap(),
+            );
+            Value::Object(json_data).to_string()
+        }
+        _ => data,
+    }
+}
+
+/// Get the coupon discount (if any) that was taken off an order
+pub fn get_order_discount(order_account: &OrderAccount) -> Option<OrderDiscount> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    serde_json::from_value(json_data[DISCOUNT].clone()).ok()
+}
+
+/// Record the fiat amounts converted at an oracle price in the order data
+///
+/// The fiat amounts are only recorded if the order data is a JSON object
+pub fn set_order_fiat_price(data: String, order_fiat_price: OrderFiatPrice) -> String {
+    match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            json_data.insert(
+                String::from(FIAT),
+                serde_json::to_value(order_fiat_price).unwrap(),
+            );
+            Value::Object(json_data).to_string()
+        }
+        _ => data,
+    }
+}
+
+/// Get the fiat amounts (if any) that an order was priced in
+pub fn get_order_fiat_price(order_account: &OrderAccount) -> Option<OrderFiatPrice> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    serde_json::from_value(json_data[FIAT].clone()).ok()
+}
+
+/// Get the fees (if any) that were paid for an order
+pub fn get_order_fees(order_account: &OrderAccount) -> Option<OrderFees> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    serde_json::from_value(json_data[FEES].clone()).ok()
+}
+
+/// Get the order token accounts (if any) of the other mints of a chain checkout order
+pub fn get_order_tokens(order_account: &OrderAccount) -> Vec<OrderToken> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return vec![],
+        Ok(data) => data,
+    };
+    serde_json::from_value(json_data[TOKENS].clone()).unwrap_or_default()
+}
+
+/// Transfer out the amounts held in the order token accounts of the other mints
+/// of a chain checkout order, and close these token accounts
+///
+/// Expects two accounts per order token account: the order token account itself
+/// and the token account that receives the amount.  The mint accounts of the
+/// other mints are optional and are found among the accounts that follow (see
+/// find_mint).  If destination_owner is set then the receiving token accounts
+/// must be owned by it.  Returns the number of order token accounts closed.
+pub fn transfer_order_tokens<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    order_account: &OrderAccount,
+    account_info_iter: &mut I,
+    destination_owner: Option<&Pubkey>,
+    account_to_receive_sol_refund_info: &AccountInfo<'b>,
+    pda_info: &AccountInfo<'b>,
+    token_program_info: &AccountInfo<'b>,
+    pda_nonce: u8,
+) -> Result<u64, ProgramError> {
+    let mut closed = 0;
+    for order_token in get_order_tokens(order_account) {
+        let order_token_info = next_account_info(account_info_iter)?;
+        let destination_token_info = next_account_info(account_info_iter)?;
+        // ensure the order token account is the right one
+        if order_token_info.key.to_string() != order_token.token {
+            msg!("Error: Incorrect order token account");
+            return Err(PaymentProcessorError::SellerAccountMismatch.into());
+        }
+        // ensure the receiving token account is owned by token program
+        let destination_token_data =
+            unpack_token_account(destination_token_info, token_program_info.key)?;
+        if destination_token_data.mint.to_string() != order_token.mint {
+            return Err(PaymentProcessorError::MintNotEqual.into());
+        }
+        if let Some(owner) = destination_owner {
+            if destination_token_data.owner != *owner {
+                return Err(PaymentProcessorError::WrongMerchant.into());
+            }
+        }
+        // the mint may have withheld part of the amount as a transfer fee
+        let held_amount = unpack_token_account(order_token_info, token_program_info.key)?.amount;
+        invoke_transfer(
+            token_program_info,
+            order_token_info,
+            account_info_iter
+                .clone()
+                .find(|account_info| *account_info.key == destination_token_data.mint),
+            destination_token_info,
+            pda_info,
+            held_amount,
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        if !has_withheld_transfer_fees(order_token_info) {
+            invoke_signed(
+                &token::close_account(
+                    token_program_info.key,
+                    order_token_info.key,
+                    account_to_receive_sol_refund_info.key,
+                    pda_info.key,
+                    &[pda_info.key],
+                )
+                .unwrap(),
+                &[
+                    token_program_info.clone(),
+                    order_token_info.clone(),
+                    account_to_receive_sol_refund_info.clone(),
+                    pda_info.clone(),
+                ],
+                &[&[PDA_SEED, &[pda_nonce]]],
+            )?;
+            closed += 1;
+        }
+    }
+    Ok(closed)
+}
+
 /// Get subscription package
 pub fn get_subscription_package(
     subscription_package_name: &str,
     merchant_account: &MerchantAccount,
 ) -> Result<Package, ProgramError> {
     // ensure the merchant has a subscription by this name
-    let merchant_json_data: Result<Packages, JSONError> =
-        serde_json::from_str(&merchant_account.data);
-    let packages = match merchant_json_data {
-        Err(_error) => return Err(PaymentProcessorError::InvalidSubscriptionData.into()),
-        Ok(data) => data.packages,
-    };
+    let packages = get_merchant_packages(merchant_account)?;
     // NB: if the are duplicates, take the first one --> verified in a test
     let package = packages
         .into_iter()
@@ -56,28 +315,89 @@ pub fn get_subscription_package(
     }
 }
 
-/// run checks for subscription processing
-pub fn subscribe_checks(
+/// Get the package to subscribe to
+///
+/// Packages of the catalog of the merchant (see PackageAccount) are found by the
+/// address of their package account among the accounts, the others come from the
+/// merchant data.  Returns the package account address, or all zeros for the latter.
+pub fn get_package_to_subscribe(
     program_id: &Pubkey,
-    signer_info: &AccountInfo<'_>,
+    accounts: &[AccountInfo],
     merchant_info: &AccountInfo<'_>,
-    order_info: &AccountInfo<'_>,
-    subscription_info: &AccountInfo<'_>,
-    subscription_name: &str,
-) -> Result<(OrderAccount, Package), ProgramError> {
-    // ensure signer can sign
-    if !signer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
+    merchant_account: &MerchantAccount,
+    subscription_package_name: &str,
+) -> Result<(PublicKey, Package), ProgramError> {
+    let (package_address, _bump_seed) =
+        get_package_address(program_id, merchant_info.key, subscription_package_name);
+    match accounts
+        .iter()
+        .find(|account_info| *account_info.key == package_address)
+    {
+        None => Ok((
+            [0; 32],
+            get_subscription_package(subscription_package_name, merchant_account)?,
+        )),
+        Some(package_info) => {
+            let (package_account, package) =
+                get_catalog_package(program_id, package_info, merchant_info.key)?;
+            // retired packages take no new subscriptions
+            if package_account.is_retired {
+                msg!("Error: The package has been retired");
+                return Err(PaymentProcessorError::InvalidSubscriptionPackage.into());
+            }
+            Ok((package_address.to_bytes(), package))
+        }
     }
-    // ensure merchant & order accounts are owned by this program
-    if *merchant_info.owner != *program_id {
-        msg!("Error: Wrong owner for merchant account");
-        return Err(ProgramError::IncorrectProgramId);
+}
+
+/// Get the package of a subscription
+///
+/// Subscriptions to packages of the catalog of the merchant need the package
+/// account among the accounts, and keep the price they subscribed at whatever
+/// the current price of the package.
+pub fn get_package_of_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    subscription_account: &SubscriptionAccount,
+    merchant_account: &MerchantAccount,
+) -> Result<Package, ProgramError> {
+    if subscription_account.package == [0; 32] {
+        return get_subscription_package(&subscription_account.name, merchant_account);
     }
-    if *order_info.owner != *program_id {
-        msg!("Error: Wrong owner for order account");
-        return Err(ProgramError::IncorrectProgramId);
+    let package_info = match accounts
+        .iter()
+        .find(|account_info| account_info.key.to_bytes() == subscription_account.package)
+    {
+        None => {
+            msg!("Error: The package account of the subscription is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(value) => value,
+    };
+    let (_package_account, mut package) = get_catalog_package(
+        program_id,
+        package_info,
+        &Pubkey::new_from_array(subscription_account.merchant),
+    )?;
+    package.price = subscription_account.price;
+    Ok(package)
+}
+
+/// Ensure the order is paid in the mint of the package
+pub fn check_package_mint(package: &Package, order_account: &OrderAccount) -> ProgramResult {
+    if package.mint != Pubkey::new_from_array(order_account.mint).to_string() {
+        return Err(PaymentProcessorError::WrongMint.into());
     }
+    Ok(())
+}
+
+/// Get the merchant account of a subscription and ensure that it takes subscriptions
+pub fn get_subscription_merchant(
+    program_id: &Pubkey,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<MerchantAccount, ProgramError> {
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
     // get the merchant account
     let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
     if merchant_account.is_closed() {
@@ -86,6 +406,7 @@ pub fn subscribe_checks(
     if !merchant_account.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
+    check_merchant_not_paused(&merchant_account)?;
     let allowed_merchant_account_types = vec![
         Discriminator::MerchantSubscription as u8,
         Discriminator::MerchantSubscriptionWithTrial as u8,
@@ -94,6 +415,25 @@ pub fn subscribe_checks(
         msg!("Error: Invalid merchant account");
         return Err(ProgramError::InvalidAccountData);
     }
+    Ok(merchant_account)
+}
+
+/// run checks for subscription processing
+///
+/// Who may sign is left to the caller: the payer of the order subscribes, but
+/// the subscription may since have been handed over to another owner.
+pub fn subscribe_checks(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    order_info: &AccountInfo<'_>,
+    subscription_info: &AccountInfo<'_>,
+) -> Result<(OrderAccount, MerchantAccount), ProgramError> {
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let merchant_account = get_subscription_merchant(program_id, merchant_info)?;
+    // ensure order account is owned by this program
+    check_program_owned(program_id, order_info, "order")?;
     // get the order account
     let order_account = OrderAccount::unpack(&order_info.data.borrow())?;
     if order_account.is_closed() {
@@ -108,10 +448,6 @@ pub fn subscribe_checks(
     }
     // ensure this order is for this subscription
     verify_subscription_order(subscription_info, &order_account)?;
-    // ensure we have the right payer
-    if signer_info.key.to_bytes() != order_account.payer {
-        return Err(PaymentProcessorError::WrongPayer.into());
-    }
     // ensure order account is paid
     if order_account.status != (OrderStatus::Paid as u8) {
         return Err(PaymentProcessorError::NotPaid.into());
@@ -120,12 +456,94 @@ pub fn subscribe_checks(
     if merchant_info.key.to_bytes() != order_account.merchant {
         return Err(ProgramError::InvalidAccountData);
     }
-    // get the package
-    let package = get_subscription_package(subscription_name, &merchant_account)?;
-    if package.mint != Pubkey::new_from_array(order_account.mint).to_string() {
-        return Err(PaymentProcessorError::WrongMint.into());
+    Ok((order_account, merchant_account))
+}
+
+/// Get the address of the order account of a merchant for an order id
+///
+/// The order id is hashed into the seeds, since it can be longer than a seed.
+pub fn get_order_address(program_id: &Pubkey, merchant: &Pubkey, order_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            ORDER.as_bytes(),
+            &merchant.to_bytes(),
+            &hashv(&[order_id.as_bytes()]).to_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Get the order id of the next order of a merchant, for the orders that have
+/// no order id of their own (chain checkouts, and the renewals and charges of
+/// subscriptions)
+///
+/// The id is the number that the order gets in the order sequence of the
+/// merchant, so such an order passes the order account derived from the order
+/// sequence of the merchant at the time it is made (see get_order_address).
+pub fn get_next_order_id(order_sequence: u64) -> Result<String, ProgramError> {
+    let sequence = order_sequence
+        .checked_add(1)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    Ok(sequence.to_string())
+}
+
+/// Create order account
+///
+/// The order account is the address derived from the merchant account and the
+/// order id (see get_order_address).  An order can only be made once for an
+/// order id, so that a checkout that is sent again does not charge the buyer
+/// twice, and it can be looked up from its order id.
+pub fn create_order_account<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    order_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    merchant: &Pubkey,
+    order_id: &str,
+    order_account_size: usize,
+) -> ProgramResult {
+    let order_account_amount = Rent::default().minimum_balance(order_account_size);
+    let (order_address, bump_seed) = get_order_address(program_id, merchant, order_id);
+    if order_address != *order_info.key {
+        msg!("Error: Order address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
     }
-    Ok((order_account, package))
+    if *order_info.owner == *program_id || !order_info.data_is_empty() {
+        msg!("Error: Order {:?} already exists", order_id);
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    let merchant_bytes = merchant.to_bytes();
+    let order_id_hash = hashv(&[order_id.as_bytes()]).to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        ORDER.as_bytes(),
+        &merchant_bytes,
+        &order_id_hash,
+        &[bump_seed],
+    ];
+    // Fund the order account with the minimum balance to be rent exempt
+    let required_lamports = order_account_amount.saturating_sub(order_info.lamports());
+    if required_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(signer_info.key, order_info.key, required_lamports),
+            &[
+                signer_info.clone(),
+                order_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+    // Allocate space for the order account
+    invoke_signed(
+        &system_instruction::allocate(order_info.key, order_account_size as u64),
+        &[order_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the order account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(order_info.key, program_id),
+        &[order_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
 }
 
 /// Create associated token account
@@ -147,6 +565,8 @@ pub fn create_program_owned_associated_token_account(
     let system_program_info = &accounts[6];
     let rent_sysvar_info = &accounts[7];
 
+    check_token_program(token_program_info)?;
+    // the address is derived the same way whichever token program is used
     let (associated_token_address, bump_seed) = Pubkey::find_program_address(
         &[
             &base_account_info.key.to_bytes(),
@@ -167,19 +587,17 @@ pub fn create_program_owned_associated_token_account(
         &mint_info.key.to_bytes(),
         &[bump_seed],
     ];
+    // Token-2022 mints may need room for extensions in their token accounts
+    let account_size = get_token_account_size(mint_info)?;
     // Fund the associated seller token account with the minimum balance to be rent exempt
     let required_lamports = rent
-        .minimum_balance(spl_token::state::Account::LEN)
+        .minimum_balance(account_size)
         .max(1)
         .saturating_sub(new_account_info.lamports());
     if required_lamports > 0 {
         // Transfer lamports to the associated seller token account
         invoke(
-            &system_instruction::transfer(
-                &signer_info.key,
-                new_account_info.key,
-                required_lamports,
-            ),
+            &system_instruction::transfer(signer_info.key, new_account_info.key, required_lamports),
             &[
                 signer_info.clone(),
                 new_account_info.clone(),
@@ -189,20 +607,20 @@ pub fn create_program_owned_associated_token_account(
     }
     // Allocate space for the associated seller token account
     invoke_signed(
-        &system_instruction::allocate(new_account_info.key, spl_token::state::Account::LEN as u64),
+        &system_instruction::allocate(new_account_info.key, account_size as u64),
         &[new_account_info.clone(), system_program_info.clone()],
-        &[&associated_token_account_signer_seeds],
+        &[associated_token_account_signer_seeds],
     )?;
-    // Assign the associated seller token account to the SPL Token program
+    // Assign the associated seller token account to the token program
     invoke_signed(
-        &system_instruction::assign(new_account_info.key, &spl_token::id()),
+        &system_instruction::assign(new_account_info.key, token_program_info.key),
         &[new_account_info.clone(), system_program_info.clone()],
-        &[&associated_token_account_signer_seeds],
+        &[associated_token_account_signer_seeds],
     )?;
     // Initialize the associated seller token account
     invoke(
-        &spl_token::instruction::initialize_account(
-            &spl_token::id(),
+        &token::initialize_account(
+            token_program_info.key,
             new_account_info.key,
             mint_info.key,
             pda_info.key,
@@ -235,3 +653,70 @@ pub fn transfer_sol(
     **sol_origin_info.lamports.borrow_mut() = origin_starting_lamports.checked_sub(amount).unwrap();
     Ok(())
 }
+
+/// Count an order or subscription that comes to reference a merchant (or one that
+/// no longer does) towards the references of the merchant account
+///
+/// The merchant account can only be closed once it has no references left.
+/// Merchants in the legacy layout have no room for their references, so they
+/// have to be migrated (which counts their references) before they take new
+/// orders or subscriptions.
+pub fn update_merchant_references(merchant_info: &AccountInfo, referenced: bool) -> ProgramResult {
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.try_borrow_data()?)?;
+    // nothing is counted for merchants in the legacy layout
+    if merchant_account.version == 0 && !referenced {
+        return Ok(());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    merchant_account.references = if referenced {
+        merchant_account.references + 1
+    } else {
+        merchant_account.references.saturating_sub(1)
+    };
+    merchant_account.pack(&mut merchant_info.try_borrow_mut_data()?);
+    Ok(())
+}
+
+/// Check whether a subscription has ended i.e. it was cancelled or has expired
+///
+/// Ended subscriptions no longer count towards the references of the merchant.
+pub fn is_subscription_ended(subscription_account: &SubscriptionAccount) -> bool {
+    subscription_account.status == SubscriptionStatus::Cancelled as u8
+        || subscription_account.status == SubscriptionStatus::Expired as u8
+}
+
+/// Ensure that a subscription is not paused
+///
+/// The remaining period of a paused subscription is frozen until it is resumed.
+pub fn check_subscription_not_paused(subscription_account: &SubscriptionAccount) -> ProgramResult {
+    if subscription_account.status == SubscriptionStatus::Paused as u8 {
+        msg!("Error: The subscription is paused");
+        return Err(PaymentProcessorError::SubscriptionPaused.into());
+    }
+    Ok(())
+}
+
+/// Add the amount paid in a checkout to the lifetime volume of the merchant
+///
+/// The lifetime volume picks the fee tier of the merchant (see get_fees), so
+/// merchants in the legacy layout have to be migrated before they take payments.
+pub fn record_merchant_volume(merchant_info: &AccountInfo, amount: u64) -> ProgramResult {
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.try_borrow_data()?)?;
+    check_account_migrated(merchant_account.version, "merchant")?;
+    merchant_account.lifetime_volume = merchant_account.lifetime_volume.saturating_add(amount);
+    merchant_account.pack(&mut merchant_info.try_borrow_mut_data()?);
+    Ok(())
+}
+
+/// Take the next number of the order sequence of a merchant
+///
+/// Orders are numbered 1, 2, 3... per merchant, so that a gap shows a missing
+/// order to the merchant.  Merchants in the legacy layout have no room for the
+/// sequence, so they have to be migrated before they take new orders.
+pub fn next_order_sequence(merchant_info: &AccountInfo) -> Result<u64, ProgramError> {
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.try_borrow_data()?)?;
+    check_account_migrated(merchant_account.version, "merchant")?;
+    merchant_account.order_sequence += 1;
+    merchant_account.pack(&mut merchant_info.try_borrow_mut_data()?);
+    Ok(merchant_account.order_sequence)
+}
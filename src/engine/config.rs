@@ -0,0 +1,384 @@
+use crate::{
+    engine::constants::{
+        ACCOUNT_VERSION, CONFIG, DEFAULT_DATA, DEFAULT_FEE_IN_LAMPORTS, FEE_TIERS,
+        MIN_FEE_IN_LAMPORTS, PROGRAM_VERSION, SPONSOR_FEE,
+    },
+    engine::json::FeeTier,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantAccount, ProgramConfigAccount, Serdes},
+    utils::get_program_config_account_size,
+};
+use serde_json::Value;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the program config account
+pub fn get_program_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG.as_bytes()], program_id)
+}
+
+/// Get the address of the program data account of the program, which holds the
+/// upgrade authority of programs deployed with the upgradeable BPF loader
+pub fn get_program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// Ensure that the signer is the upgrade authority of the program
+///
+/// Whoever initializes the program picks the program owner and the program
+/// authority, so only the deployer of the program can do it.
+pub fn check_upgrade_authority(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'_>,
+    program_data_info: &AccountInfo<'_>,
+) -> ProgramResult {
+    if *program_data_info.key != get_program_data_address(program_id)
+        || *program_data_info.owner != bpf_loader_upgradeable::id()
+    {
+        msg!("Error: Invalid program data account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let upgrade_authority = match bincode::deserialize(&program_data_info.data.borrow()) {
+        Ok(UpgradeableLoaderState::ProgramData {
+            upgrade_authority_address,
+            ..
+        }) => upgrade_authority_address,
+        _ => {
+            msg!("Error: Invalid program data account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    };
+    if upgrade_authority != Some(*signer_info.key) {
+        msg!("Error: Only the upgrade authority can initialize the program");
+        return Err(PaymentProcessorError::NotUpgradeAuthority.into());
+    }
+    Ok(())
+}
+
+/// Get the program config account
+///
+/// Ensures that the account is the program config account of this program and
+/// that its version is not newer than what this build of the program understands
+pub fn get_program_config(
+    program_id: &Pubkey,
+    config_info: &AccountInfo<'_>,
+) -> Result<ProgramConfigAccount, ProgramError> {
+    let (config_address, _bump_seed) = get_program_config_address(program_id);
+    if config_address != *config_info.key {
+        msg!("Error: Program config address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    check_program_owned(program_id, config_info, "program config")?;
+    let config_account = ProgramConfigAccount::unpack(&config_info.data.borrow())?;
+    if config_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !config_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if config_account.discriminator != Discriminator::ProgramConfig as u8 {
+        msg!("Error: Invalid program config account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if config_account.state_version > PROGRAM_VERSION {
+        msg!(
+            "Error: Program state version {:?} is newer than {:?}",
+            config_account.state_version,
+            PROGRAM_VERSION
+        );
+        return Err(PaymentProcessorError::UnsupportedVersion.into());
+    }
+    Ok(config_account)
+}
+
+/// Ensure that payments are not paused for an account migration
+pub fn check_migration_window(
+    config_account: &ProgramConfigAccount,
+    timestamp: UnixTimestamp,
+) -> ProgramResult {
+    if timestamp < config_account.migration_ends {
+        msg!(
+            "Error: Payments are paused until {:?}",
+            config_account.migration_ends
+        );
+        return Err(PaymentProcessorError::MigrationInProgress.into());
+    }
+    Ok(())
+}
+
+/// Ensure that a fee schedule is consistent
+pub fn check_fee_schedule(default_fee: u64, min_fee: u64, sponsor_fee: u64) -> ProgramResult {
+    if default_fee < min_fee {
+        msg!("Error: The default fee cannot be less than the minimum fee");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    check_sponsor_fee(sponsor_fee)
+}
+
+/// Ensure that a sponsor share of the transaction fee is valid
+pub fn check_sponsor_fee(sponsor_fee: u64) -> ProgramResult {
+    // the sponsor fee is expressed in tenths of a percent
+    if sponsor_fee > 1000 {
+        msg!("Error: The sponsor fee cannot be more than 1000");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+/// Get the fee tiers set in the program config data (if any)
+///
+/// e.g. {"fee_tiers": [{"threshold": 1000000000000, "fee": 250000}]}
+pub fn get_fee_tiers(config_data: &str) -> Result<Vec<FeeTier>, ProgramError> {
+    let config_json_data: Value = match serde_json::from_str(config_data) {
+        Err(_error) => return Ok(vec![]),
+        Ok(data) => data,
+    };
+    if config_json_data[FEE_TIERS].is_null() {
+        return Ok(vec![]);
+    }
+    match serde_json::from_value(config_json_data[FEE_TIERS].clone()) {
+        Err(_error) => {
+            msg!("Error: Invalid fee tiers in program config");
+            Err(ProgramError::InvalidAccountData)
+        }
+        Ok(fee_tiers) => Ok(fee_tiers),
+    }
+}
+
+/// Initialize the program
+///
+/// Meant to be called once by the upgrade authority, right after the program is
+/// deployed.  Since the config account address is derived from the program id
+/// only, a second call fails because the account already exists.
+pub fn process_initialize_program(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    default_fee: Option<u64>,
+    min_fee: Option<u64>,
+    sponsor_fee: Option<u64>,
+    name: String,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let program_owner_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let program_data_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure that nobody can front-run the deployer and take over the fees
+    check_upgrade_authority(program_id, signer_info, program_data_info)?;
+    let (config_address, bump_seed) = get_program_config_address(program_id);
+    if config_address != *config_info.key {
+        msg!("Error: Program config address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let signer_seeds: &[&[_]] = &[CONFIG.as_bytes(), &[bump_seed]];
+
+    let min_fee = min_fee.unwrap_or(MIN_FEE_IN_LAMPORTS);
+    let default_fee = default_fee.unwrap_or(DEFAULT_FEE_IN_LAMPORTS);
+    let sponsor_fee = sponsor_fee.unwrap_or(SPONSOR_FEE as u64);
+    check_fee_schedule(default_fee, min_fee, sponsor_fee)?;
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+    get_fee_tiers(&data)?;
+
+    let account_size = get_program_config_account_size(&name, &data);
+    // Fund the config account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            config_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            config_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the config account
+    invoke_signed(
+        &system_instruction::allocate(config_info.key, account_size as u64),
+        &[config_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the config account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(config_info.key, program_id),
+        &[config_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving program config information...
+    let mut config_data = config_info.try_borrow_mut_data()?;
+    let config = ProgramConfigAccount {
+        discriminator: Discriminator::ProgramConfig as u8,
+        state_version: PROGRAM_VERSION,
+        owner: program_owner_info.key.to_bytes(),
+        authority: signer_info.key.to_bytes(),
+        default_fee,
+        min_fee,
+        sponsor_fee,
+        migration_ends: 0,
+        name,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    config.pack(&mut config_data);
+
+    // ensure config account is rent exempt
+    if !rent.is_exempt(config_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Set Migration Window
+///
+/// Lets the program authority pause payments while accounts are migrated after
+/// a program upgrade, and record the state version once the migration is done.
+/// Withdrawals and refunds are not paused so that funds are never locked.
+pub fn process_set_migration_window(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    ends_at: UnixTimestamp,
+    version: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut config_account = get_program_config(program_id, config_info)?;
+    // ensure only the program authority can start or end a migration
+    if signer_info.key.to_bytes() != config_account.authority {
+        msg!("Error: Only the program authority can set the migration window");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    // the state version can never go back, nor past what this program understands
+    if version < config_account.state_version || version > PROGRAM_VERSION {
+        msg!(
+            "Error: The version must be {:?} to {:?}",
+            config_account.state_version,
+            PROGRAM_VERSION
+        );
+        return Err(PaymentProcessorError::UnsupportedVersion.into());
+    }
+
+    // Updating program config information...
+    config_account.migration_ends = ends_at;
+    config_account.state_version = version;
+    config_account.pack(&mut config_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Update Config
+///
+/// Lets the program authority change the program owner and the fee schedule of
+/// this instance of the program after it was initialized.  Merchants keep the
+/// fee they registered with, and every checkout reads the rest at runtime.
+pub fn process_update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    default_fee: Option<u64>,
+    min_fee: Option<u64>,
+    sponsor_fee: Option<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let possible_program_owner_info = next_account_info(account_info_iter);
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut config_account = get_program_config(program_id, config_info)?;
+    // ensure only the program authority can update the program config
+    if signer_info.key.to_bytes() != config_account.authority {
+        msg!("Error: Only the program authority can update the program config");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let default_fee = default_fee.unwrap_or(config_account.default_fee);
+    let min_fee = min_fee.unwrap_or(config_account.min_fee);
+    let sponsor_fee = sponsor_fee.unwrap_or(config_account.sponsor_fee);
+    check_fee_schedule(default_fee, min_fee, sponsor_fee)?;
+
+    // Updating program config information...
+    if let Ok(program_owner_info) = possible_program_owner_info {
+        config_account.owner = program_owner_info.key.to_bytes();
+    }
+    config_account.default_fee = default_fee;
+    config_account.min_fee = min_fee;
+    config_account.sponsor_fee = sponsor_fee;
+    config_account.pack(&mut config_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Set Merchant Sponsor Fee
+///
+/// Lets the program authority change the share of the transaction fee that the
+/// sponsor of a merchant gets, e.g. to honour a deal made with the sponsor.
+pub fn process_set_merchant_sponsor_fee(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sponsor_fee: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let config_account = get_program_config(program_id, config_info)?;
+    // ensure only the program authority can set the sponsor fee of a merchant
+    if signer_info.key.to_bytes() != config_account.authority {
+        msg!("Error: Only the program authority can set the sponsor fee of a merchant");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    check_sponsor_fee(sponsor_fee)?;
+
+    // Updating merchant information...
+    merchant_account.sponsor_fee = sponsor_fee;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -2,22 +2,178 @@
 pub const MERCHANT: &str = "merchant";
 /// the word trial as a string
 pub const TRIAL: &str = "trial";
+/// the word attestation as a string
+pub const ATTESTATION: &str = "attestation";
+/// the word config as a string
+pub const CONFIG: &str = "config";
+/// the word integrator as a string
+pub const INTEGRATOR: &str = "integrator";
+/// the word voucher as a string
+pub const VOUCHER: &str = "voucher";
+/// the word coupon as a string
+pub const COUPON: &str = "coupon";
+/// the word link as a string
+pub const LINK: &str = "link";
+/// the word guarantee as a string
+pub const GUARANTEE: &str = "guarantee";
+/// the word auto_debit as a string
+pub const AUTO_DEBIT: &str = "auto_debit";
+/// the word fee_history as a string
+pub const FEE_HISTORY: &str = "fee_history";
+/// the word campaign as a string
+pub const CAMPAIGN: &str = "campaign";
+/// the word refund as a string
+pub const REFUND: &str = "refund";
+/// the word recovery as a string
+pub const RECOVERY: &str = "recovery";
+/// the word transfer as a string
+pub const TRANSFER: &str = "transfer";
+/// the word stats as a string
+pub const STATS: &str = "stats";
+/// the word migration as a string
+pub const MIGRATION: &str = "migration";
+/// the word sweep as a string
+pub const SWEEP: &str = "sweep";
+/// the word stock as a string
+pub const STOCK: &str = "stock";
+/// the word oracle as a string
+pub const ORACLE: &str = "oracle";
+/// the word bundle as a string
+pub const BUNDLE: &str = "bundle";
+/// the word settlement as a string
+pub const SETTLEMENT: &str = "settlement";
+/// the word notes as a string
+pub const NOTES: &str = "notes";
+/// the word order as a string
+pub const ORDER: &str = "order";
+/// the word store as a string
+pub const STORE: &str = "store";
+/// the words accepted mints as a string
+pub const ACCEPTED_MINTS: &str = "accepted_mints";
+/// the word blocked as a string
+pub const BLOCKED: &str = "blocked";
+/// the word referrer as a string
+pub const REFERRER: &str = "referrer";
+/// the word package as a string
+pub const PACKAGE: &str = "package";
 /// the word packages as a string
 pub const PACKAGES: &str = "packages";
 /// the word packages as a string
-pub const PAID: &str = "_paid";
-/// the word packages as a string
 pub const INITIAL: &str = "_initial";
+/// the trusted instances key in program config data
+pub const TRUSTED_INSTANCES: &str = "trusted_instances";
+/// the screening program key in program config data
+pub const SCREENING_PROGRAM: &str = "screening_program";
+/// the fee tiers key in program config data
+pub const FEE_TIERS: &str = "fee_tiers";
+/// the swap program key in program config data
+pub const SWAP_PROGRAM: &str = "swap_program";
+/// the receipt URI key in program config data
+pub const RECEIPT_URI: &str = "receipt_uri";
+/// the word fees as a string
+pub const FEES: &str = "_fees";
+/// the word tokens as a string
+pub const TOKENS: &str = "_tokens";
+/// the word discount as a string
+pub const DISCOUNT: &str = "_discount";
+/// the word splits as a string
+pub const SPLITS: &str = "_splits";
+/// the word donation as a string
+pub const DONATION: &str = "_donation";
+/// the word fiat as a string
+pub const FIAT: &str = "_fiat";
+/// the parent order key in sub-order data
+pub const PARENT: &str = "_parent";
+/// the store key in order data
+pub const ORDER_STORE: &str = "_store";
+/// the referral key in order data
+pub const ORDER_REFERRAL: &str = "_referral";
+/// the escrow timeout key in order data
+pub const ESCROW: &str = "escrow";
+/// the installments expected amount key in order data
+pub const EXPECTED_AMOUNT: &str = "expected_amount";
+/// the order expiry key in order data
+pub const EXPIRES_AT: &str = "expires_at";
 /// seed for pgram derived addresses
 pub const PDA_SEED: &[u8] = b"sol_payment_processor";
-/// the program owner
-pub const PROGRAM_OWNER: &str = "mosh782eoKyPca9eotWfepHVSKavjDMBjNkNE3Gge6Z";
-/// minimum transaction fee percentage
+/// default minimum transaction fee percentage
 pub const MIN_FEE_IN_LAMPORTS: u64 = 50000;
 /// default transaction fee percentage
 pub const DEFAULT_FEE_IN_LAMPORTS: u64 = 500000;
-/// sponsor fee percentage
+/// what the renewal crank earns on top of the rent and fees it pays for
+pub const RENEWAL_INCENTIVE_IN_LAMPORTS: u64 = 10000;
+/// what the sweep crank earns for each order it sweeps, set aside out of the
+/// program owner fee when the order is paid
+pub const SWEEP_BOUNTY_IN_LAMPORTS: u64 = 5000;
+/// default sponsor fee percentage
 pub const SPONSOR_FEE: u128 = 3;
+/// integrator share (in tenths of a percent) of the transaction fee
+pub const INTEGRATOR_FEE: u128 = 100;
+/// the maximum number of recipients a payment can be split among
+pub const MAX_SPLIT_RECIPIENTS: usize = 10;
+/// the number of basis points that make up the whole amount
+pub const BASIS_POINTS: u64 = 10000;
+/// the highest tolerance (in basis points) that a merchant can set for chain checkouts
+pub const MAX_TOLERANCE: u64 = 1000;
+/// the maximum number of notes that can be posted on an order
+pub const MAX_ORDER_NOTES: u64 = 50;
+/// the maximum length (in bytes) of an order note
+pub const MAX_NOTE_LEN: usize = 512;
+/// the most program-owned token accounts that the orders of a merchant can keep open
+pub const MAX_OPEN_TOKEN_ACCOUNTS: u64 = 10000;
+/// the most mints that a merchant can accept payments in
+pub const MAX_ACCEPTED_MINTS: usize = 16;
+/// the most wallets that the withdrawals of a merchant can be split among
+pub const MAX_PAYOUT_DESTINATIONS: usize = 4;
+/// the most operators that can withdraw the payments of a merchant
+pub const MAX_OPERATORS: usize = 3;
+/// the maximum length (in bytes) of the name of a receipt NFT
+pub const MAX_RECEIPT_NAME_LEN: usize = 32;
+/// the maximum length (in bytes) of the URI of a receipt NFT
+pub const MAX_RECEIPT_URI_LEN: usize = 200;
+/// the maximum length (in bytes) of the metadata of a merchant
+pub const MAX_METADATA_LEN: usize = 256;
+/// the time (in seconds) after payment within which the fees of a refunded order can be waived
+pub const FEE_WAIVER_PERIOD: i64 = 604800;
+/// the time (in seconds) after the end of its period that an ended subscription is kept before it can be closed
+pub const SUBSCRIPTION_RETENTION_PERIOD: i64 = 2592000;
+/// the ed25519 signature verification program
+pub const ED25519_PROGRAM_ID: &str = "Ed25519SigVerify111111111111111111111111111";
+/// the Token-2022 program
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+/// the Token-2022 extension that holds the transfer fee of a mint
+pub const TRANSFER_FEE_CONFIG_EXTENSION: u16 = 1;
+/// the Token-2022 extension that holds the transfer fees withheld in a token account
+pub const TRANSFER_FEE_AMOUNT_EXTENSION: u16 = 2;
+/// the Token-2022 extension of mints whose tokens cannot be transferred
+pub const NON_TRANSFERABLE_EXTENSION: u16 = 9;
+/// the Token-2022 extension of mints whose transfers call a transfer hook program
+pub const TRANSFER_HOOK_EXTENSION: u16 = 14;
+/// the number of decimals of fiat amounts converted at an oracle price
+pub const FIAT_DECIMALS: u32 = 6;
+/// the magic number that Pyth accounts start with
+pub const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+/// the version of the Pyth account layout that is understood
+pub const PYTH_VERSION: u32 = 2;
+/// the type of Pyth accounts that hold prices
+pub const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+/// the status of a Pyth price that is being traded
+pub const PYTH_TRADING_STATUS: u32 = 1;
+/// the size of the part of a Pyth price account that is read
+pub const PYTH_PRICE_ACCOUNT_LEN: usize = 240;
+/// the SPL associated token account program
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+/// the Metaplex token metadata program
+pub const TOKEN_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+/// the symbol of the receipt NFTs minted for orders
+pub const RECEIPT_SYMBOL: &str = "RCPT";
+/// default program name
+pub const DEFAULT_PROGRAM_NAME: &str = "SolPayments";
+/// the version of the program state that this build of the program understands
+pub const PROGRAM_VERSION: u8 = 1;
+/// the layout version of the accounts created by this build of the program -
+/// accounts created before accounts had a version are read as version 0
+pub const ACCOUNT_VERSION: u8 = 1;
 /// default data value
 pub const DEFAULT_DATA: &str = "{}";
 // these are purely by trial and error ... TODO: understand these some more
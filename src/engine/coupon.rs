@@ -0,0 +1,238 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, BASIS_POINTS, COUPON},
+    engine::json::OrderDiscount,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{CouponAccount, DiscountType, Discriminator, IsClosed, MerchantAccount, Serdes},
+    utils::get_coupon_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the coupon account of a discount code
+pub fn get_coupon_address(program_id: &Pubkey, merchant: &Pubkey, code: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[COUPON.as_bytes(), &merchant.to_bytes(), code.as_bytes()],
+        program_id,
+    )
+}
+
+/// Get the discount of a coupon
+///
+/// A percentage discount is rounded down and a fixed discount never takes more
+/// than the amount off.
+pub fn get_discount(coupon_account: &CouponAccount, amount: u64) -> u64 {
+    if coupon_account.discount_type == DiscountType::Percentage as u8 {
+        ((amount as u128 * coupon_account.discount as u128) / BASIS_POINTS as u128) as u64
+    } else {
+        std::cmp::min(coupon_account.discount, amount)
+    }
+}
+
+/// Redeem a coupon for an order
+///
+/// Ensures that the coupon can be used for this merchant and mint, uses it up
+/// once and returns the discount taken off the amount.
+pub fn redeem_coupon(
+    program_id: &Pubkey,
+    coupon_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    mint_info: &AccountInfo<'_>,
+    amount: u64,
+    timestamp: UnixTimestamp,
+) -> Result<OrderDiscount, ProgramError> {
+    check_program_owned(program_id, coupon_info, "coupon")?;
+    let mut coupon_account = CouponAccount::unpack(&coupon_info.data.borrow())?;
+    if coupon_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !coupon_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if coupon_account.discriminator != Discriminator::Coupon as u8 {
+        msg!("Error: Invalid coupon account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the coupon was issued by this merchant
+    if merchant_info.key.to_bytes() != coupon_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the coupon is for this currency (if it is tied to one)
+    if coupon_account.mint != Pubkey::default().to_bytes()
+        && mint_info.key.to_bytes() != coupon_account.mint
+    {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    if timestamp >= coupon_account.expires_at {
+        msg!("Error: Coupon expired at {:?}", coupon_account.expires_at);
+        return Err(PaymentProcessorError::CouponExpired.into());
+    }
+    if coupon_account.remaining_uses == 0 {
+        return Err(PaymentProcessorError::CouponExhausted.into());
+    }
+
+    let discount = get_discount(&coupon_account, amount);
+
+    // Using up the coupon...
+    coupon_account.remaining_uses -= 1;
+    coupon_account.pack(&mut coupon_info.data.borrow_mut());
+
+    Ok(OrderDiscount {
+        coupon: coupon_info.key.to_string(),
+        code: coupon_account.code,
+        amount: discount,
+    })
+}
+
+/// The terms of a new coupon
+pub struct CouponTerms {
+    /// a DiscountType
+    pub discount_type: u8,
+    pub discount: u64,
+    pub mint: Option<Pubkey>,
+    pub max_uses: u64,
+    pub expires_at: UnixTimestamp,
+}
+
+/// Create Coupon
+///
+/// Creates a coupon account holding a discount code that buyers can redeem at
+/// checkout until it expires or runs out of uses.
+pub fn process_create_coupon(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    code: String,
+    terms: CouponTerms,
+) -> ProgramResult {
+    let CouponTerms {
+        discount_type,
+        discount,
+        mint,
+        max_uses,
+        expires_at,
+    } = terms;
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let coupon_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can create coupons
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // the code is used as a seed so it has to fit
+    if code.is_empty() || code.len() > MAX_SEED_LEN {
+        msg!("Error: The code must be 1 to {:?} bytes long", MAX_SEED_LEN);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if discount_type == DiscountType::Percentage as u8 {
+        if discount == 0 || discount > BASIS_POINTS {
+            msg!(
+                "Error: The discount must be 1 to {:?} basis points",
+                BASIS_POINTS
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    } else if discount_type == DiscountType::Fixed as u8 {
+        // a fixed discount only makes sense in a given currency
+        if discount == 0 || mint.is_none() {
+            msg!("Error: A fixed discount needs an amount and a mint");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    } else {
+        msg!("Error: Invalid discount type");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if max_uses == 0 {
+        msg!("Error: The coupon must be usable at least once");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    // the address of the coupon account is derived from the merchant and the code
+    // thus ensuring that each code is used only once per merchant
+    let (coupon_address, bump_seed) = get_coupon_address(program_id, merchant_info.key, &code);
+    if coupon_address != *coupon_info.key {
+        msg!("Error: Coupon address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        COUPON.as_bytes(),
+        &merchant_bytes,
+        code.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let account_size = get_coupon_account_size(&code);
+    // Fund the coupon account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            coupon_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            coupon_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the coupon account
+    invoke_signed(
+        &system_instruction::allocate(coupon_info.key, account_size as u64),
+        &[coupon_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the coupon account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(coupon_info.key, program_id),
+        &[coupon_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving coupon information...
+    let coupon = CouponAccount {
+        discriminator: Discriminator::Coupon as u8,
+        merchant: merchant_bytes,
+        mint: mint.unwrap_or_default().to_bytes(),
+        discount_type,
+        discount,
+        remaining_uses: max_uses,
+        expires_at,
+        code,
+        version: ACCOUNT_VERSION,
+    };
+    coupon.pack(&mut coupon_info.try_borrow_mut_data()?);
+
+    // ensure coupon account is rent exempt
+    if !rent.is_exempt(coupon_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
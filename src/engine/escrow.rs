@@ -0,0 +1,95 @@
+use crate::{
+    engine::common::get_order_escrow_timeout,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, OrderAccount, OrderStatus, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Confirm Delivery
+///
+/// Releases the payment for an escrow order so that the merchant can withdraw it,
+/// or part of it, in which case the merchant can only withdraw the part released
+/// (see WithdrawAmount) until the rest is released too or the escrow timeout elapses
+pub fn process_confirm_delivery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: Option<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure order account is owned by this program
+    check_program_owned(program_id, order_info, "order")?;
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the buyer can confirm delivery
+    if signer_info.key.to_bytes() != order_account.payer {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // ensure this is an escrow order
+    if get_order_escrow_timeout(&order_account).is_none() {
+        msg!("Error: Order is not held in escrow");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    // ensure order account is paid
+    if order_account.status != OrderStatus::Paid as u8 {
+        return Err(PaymentProcessorError::NotPaid.into());
+    }
+
+    let total_amount = order_account
+        .paid_amount
+        .checked_add(order_account.tip)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    let released_amount = match amount {
+        None => total_amount,
+        Some(value) => {
+            // orders in the legacy layout have no room for the amount released so far
+            check_account_migrated(order_account.version, "order")?;
+            let released_amount = order_account
+                .released_amount
+                .checked_add(value)
+                .ok_or(PaymentProcessorError::AmountOverflow)?;
+            if value == 0 || released_amount > total_amount {
+                msg!(
+                    "Error: The amount released must be 1 to {:?}",
+                    total_amount - order_account.released_amount
+                );
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            released_amount
+        }
+    };
+
+    // Updating order account information...
+    if released_amount == total_amount {
+        order_account.status = OrderStatus::Releasable as u8;
+    } else {
+        order_account.released_amount = released_amount;
+    }
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    Ok(())
+}
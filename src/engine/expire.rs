@@ -0,0 +1,107 @@
+use crate::{
+    engine::common::{
+        check_subscription_not_paused, get_package_of_subscription, is_subscription_ended,
+        update_merchant_references,
+    },
+    engine::json::Package,
+    engine::renew::get_subscription_account,
+    engine::sysvars::SysvarProvider,
+    engine::validation::check_program_owned,
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, Serdes, SubscriptionAccount, SubscriptionStatus},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Get the status that a subscription has lapsed into (if any)
+///
+/// A subscription whose period has ended without being renewed is past due for
+/// the grace period of its package, during which services can still treat it as
+/// active while a renewal is retried, and has expired after that.
+pub fn get_lapsed_status(
+    subscription_account: &SubscriptionAccount,
+    package: &Package,
+    timestamp: UnixTimestamp,
+) -> Option<SubscriptionStatus> {
+    if timestamp < subscription_account.period_end {
+        return None;
+    }
+    let grace_seconds = package.grace_seconds.unwrap_or(0);
+    if timestamp < subscription_account.period_end + grace_seconds {
+        Some(SubscriptionStatus::PastDue)
+    } else {
+        Some(SubscriptionStatus::Expired)
+    }
+}
+
+/// Expire Subscription
+///
+/// A permissionless instruction that moves a subscription whose period has ended
+/// without being renewed to `PastDue` while it is within the grace period of its
+/// package, and to `Expired` after that.  Expired subscriptions no longer count
+/// towards the references of the merchant.
+pub fn process_expire_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let subscription_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    if is_subscription_ended(&subscription_account) {
+        msg!("Error: The subscription has been cancelled or has expired");
+        return Err(PaymentProcessorError::SubscriptionExpired.into());
+    }
+    // the remaining period of a paused subscription is frozen
+    check_subscription_not_paused(&subscription_account)?;
+    if merchant_info.key.to_bytes() != subscription_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        &merchant_account,
+    )?;
+
+    let status = match get_lapsed_status(&subscription_account, &package, timestamp) {
+        None => {
+            msg!(
+                "Error: The subscription is active until {:?}",
+                subscription_account.period_end
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        Some(value) => value,
+    };
+    // the expired subscription no longer counts towards the references of the merchant
+    if status == SubscriptionStatus::Expired {
+        update_merchant_references(merchant_info, false)?;
+    }
+
+    // Updating subscription account information...
+    subscription_account.status = status as u8;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::{
+    engine::config::{check_fee_schedule, get_program_config},
+    engine::constants::{ACCOUNT_VERSION, FEE_HISTORY},
+    engine::sysvars::SysvarProvider,
+    engine::validation::check_signer,
+    error::PaymentProcessorError,
+    state::{Discriminator, FeeHistoryAccount, FeeScheduleAccount, IsClosed, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the fee history account
+pub fn get_fee_history_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_HISTORY.as_bytes()], program_id)
+}
+
+/// Get the address of a fee schedule in the fee history
+pub fn get_fee_schedule_address(program_id: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_HISTORY.as_bytes(), &index.to_le_bytes()], program_id)
+}
+
+/// Create an account owned by this program at a program derived address
+fn create_fee_history_account<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    new_account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent: &Rent,
+    account_size: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    // Fund the account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            new_account_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            new_account_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the account
+    invoke_signed(
+        &system_instruction::allocate(new_account_info.key, account_size as u64),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(new_account_info.key, program_id),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    if !rent.is_exempt(new_account_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    Ok(())
+}
+
+/// Set Fee Schedule
+///
+/// Lets the program authority change the fees of the program config.  The fee
+/// schedule being replaced is first recorded in the fee history along with the
+/// slots during which it applied, so that the fee that applied to any payment
+/// can be found on-chain later on.  The fee history is append-only.
+pub fn process_set_fee_schedule(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    default_fee: u64,
+    min_fee: u64,
+    sponsor_fee: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let fee_history_info = next_account_info(account_info_iter)?;
+    let fee_schedule_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let slot = sysvars.slot()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut config_account = get_program_config(program_id, config_info)?;
+    // ensure only the program authority can change the fees
+    if signer_info.key.to_bytes() != config_account.authority {
+        msg!("Error: Only the program authority can set the fee schedule");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_fee_schedule(default_fee, min_fee, sponsor_fee)?;
+
+    // get the fee history, which is created along with the first fee schedule
+    let (fee_history_address, fee_history_bump_seed) = get_fee_history_address(program_id);
+    if fee_history_address != *fee_history_info.key {
+        msg!("Error: Fee history address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut fee_history_account = if *fee_history_info.owner == *program_id {
+        let fee_history_account = FeeHistoryAccount::unpack(&fee_history_info.data.borrow())?;
+        if fee_history_account.is_closed() {
+            return Err(PaymentProcessorError::ClosedAccount.into());
+        }
+        if !fee_history_account.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if fee_history_account.discriminator != Discriminator::FeeHistory as u8 {
+            msg!("Error: Invalid fee history account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        fee_history_account
+    } else {
+        create_fee_history_account(
+            program_id,
+            signer_info,
+            fee_history_info,
+            system_program_info,
+            rent,
+            FeeHistoryAccount::MIN_LEN,
+            &[FEE_HISTORY.as_bytes(), &[fee_history_bump_seed]],
+        )?;
+        // the fee schedule of the program config has applied since it was initialized
+        FeeHistoryAccount {
+            discriminator: Discriminator::FeeHistory as u8,
+            count: 0,
+            since: 0,
+            version: ACCOUNT_VERSION,
+        }
+    };
+
+    // Recording the fee schedule being replaced...
+    let index = fee_history_account.count;
+    let (fee_schedule_address, fee_schedule_bump_seed) =
+        get_fee_schedule_address(program_id, index);
+    if fee_schedule_address != *fee_schedule_info.key {
+        msg!("Error: Fee schedule address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    create_fee_history_account(
+        program_id,
+        signer_info,
+        fee_schedule_info,
+        system_program_info,
+        rent,
+        FeeScheduleAccount::MIN_LEN,
+        &[
+            FEE_HISTORY.as_bytes(),
+            &index.to_le_bytes(),
+            &[fee_schedule_bump_seed],
+        ],
+    )?;
+    let fee_schedule_account = FeeScheduleAccount {
+        discriminator: Discriminator::FeeSchedule as u8,
+        index,
+        default_fee: config_account.default_fee,
+        min_fee: config_account.min_fee,
+        sponsor_fee: config_account.sponsor_fee,
+        effective_from: fee_history_account.since,
+        effective_until: slot,
+        version: ACCOUNT_VERSION,
+    };
+    fee_schedule_account.pack(&mut fee_schedule_info.try_borrow_mut_data()?);
+
+    fee_history_account.count = index + 1;
+    fee_history_account.since = slot;
+    fee_history_account.pack(&mut fee_history_info.try_borrow_mut_data()?);
+
+    // Updating program config information...
+    config_account.default_fee = default_fee;
+    config_account.min_fee = min_fee;
+    config_account.sponsor_fee = sponsor_fee;
+    config_account.pack(&mut config_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::{
+    engine::token::has_freeze_authority,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Ensure that a payment to a merchant is not made in mints that it rejects for
+/// having a freeze authority
+///
+/// The freeze authority of such a mint could freeze the order token accounts
+/// (owned by the program derived address) before the merchant withdraws them.
+/// The mints that the merchant accepts in its accepted mints account were picked
+/// by the merchant, so they are allowed whatever their freeze authority (see
+/// check_accepted_mints).
+pub fn check_freezable_mints(
+    merchant_account: &MerchantAccount,
+    mint_infos: &[&AccountInfo],
+) -> ProgramResult {
+    if !merchant_account.rejects_freezable_mints || merchant_account.restricts_mints {
+        return Ok(());
+    }
+    for mint_info in mint_infos {
+        if has_freeze_authority(mint_info)? {
+            msg!(
+                "Error: The merchant rejects mint {:?}, which has a freeze authority",
+                mint_info.key
+            );
+            return Err(PaymentProcessorError::FreezableMint.into());
+        }
+    }
+    Ok(())
+}
+
+/// Set Freeze Policy
+///
+/// Lets the merchant owner reject checkouts in mints with a freeze authority,
+/// other than the mints of its accepted mints account.
+pub fn process_set_freeze_policy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    rejects_freezable_mints: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the freeze policy
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+
+    // Updating merchant freeze policy...
+    merchant_account.rejects_freezable_mints = rejects_freezable_mints;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::{
+    engine::common::get_subscription_package,
+    engine::constants::{ACCOUNT_VERSION, GUARANTEE},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, MerchantAccount, PriceGuaranteeAccount, Serdes,
+        SubscriptionAccount,
+    },
+    utils::get_price_guarantee_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the price guarantee account of a subscription package
+pub fn get_price_guarantee_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    package: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            GUARANTEE.as_bytes(),
+            &merchant.to_bytes(),
+            package.as_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Get the price of a subscription package taking a price guarantee into account
+///
+/// While the guarantee holds, subscriptions that joined before it was given pay
+/// no more than the guaranteed price, whatever the current price of the package.
+pub fn get_guaranteed_price(
+    program_id: &Pubkey,
+    guarantee_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    subscription_account: &SubscriptionAccount,
+    price: u64,
+    timestamp: UnixTimestamp,
+) -> Result<u64, ProgramError> {
+    check_program_owned(program_id, guarantee_info, "price guarantee")?;
+    let guarantee_account = PriceGuaranteeAccount::unpack(&guarantee_info.data.borrow())?;
+    if guarantee_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !guarantee_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if guarantee_account.discriminator != Discriminator::PriceGuarantee as u8 {
+        msg!("Error: Invalid price guarantee account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the guarantee is for this merchant and package
+    if merchant_info.key.to_bytes() != guarantee_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if guarantee_account.package != subscription_account.name {
+        return Err(PaymentProcessorError::InvalidSubscriptionPackage.into());
+    }
+    if timestamp >= guarantee_account.guaranteed_until
+        || subscription_account.joined > guarantee_account.created
+    {
+        // the guarantee does not cover this renewal
+        return Ok(price);
+    }
+    Ok(std::cmp::min(price, guarantee_account.price))
+}
+
+/// Guarantee Package Price
+///
+/// Lets the merchant commit that the price of a subscription package will not
+/// increase for existing subscriptions before a given time.  The guarantee is
+/// given at the current price of the package.
+pub fn process_guarantee_package_price(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    package: String,
+    guaranteed_until: UnixTimestamp,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let guarantee_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can give price guarantees
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // the package name is used as a seed so it has to fit
+    if package.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The package name must be at most {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if guaranteed_until <= timestamp {
+        msg!("Error: The guarantee must end in the future");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let price = get_subscription_package(&package, &merchant_account)?.price;
+    let (guarantee_address, bump_seed) =
+        get_price_guarantee_address(program_id, merchant_info.key, &package);
+    if guarantee_address != *guarantee_info.key {
+        msg!("Error: Price guarantee address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        GUARANTEE.as_bytes(),
+        &merchant_bytes,
+        package.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let account_size = get_price_guarantee_account_size(&package);
+    // Fund the price guarantee account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            guarantee_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            guarantee_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the price guarantee account
+    invoke_signed(
+        &system_instruction::allocate(guarantee_info.key, account_size as u64),
+        &[guarantee_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the price guarantee account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(guarantee_info.key, program_id),
+        &[guarantee_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving price guarantee information...
+    let guarantee = PriceGuaranteeAccount {
+        discriminator: Discriminator::PriceGuarantee as u8,
+        merchant: merchant_bytes,
+        price,
+        created: timestamp,
+        guaranteed_until,
+        package,
+        version: ACCOUNT_VERSION,
+    };
+    guarantee.pack(&mut guarantee_info.try_borrow_mut_data()?);
+
+    // ensure price guarantee account is rent exempt
+    if !rent.is_exempt(guarantee_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
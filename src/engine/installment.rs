@@ -0,0 +1,154 @@
+use crate::{
+    engine::blocklist::check_buyer_not_blocked,
+    engine::common::{get_order_expiry, get_order_received_amount},
+    engine::pause::check_merchant_not_paused,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        check_token_program, find_mint, get_net_amounts, invoke_transfer, unpack_token_account,
+    },
+    engine::validation::{check_program_owned, check_signer, check_token_account_mint},
+    error::PaymentProcessorError,
+    events::emit_order_paid,
+    state::{IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Pay Installment
+///
+/// Pays down an order that was created with an expected amount larger than the
+/// amount paid at checkout.  The order becomes Paid once it is fully funded.
+/// Installments are refused like checkouts while the merchant is paused or has
+/// blocked the buyer.
+pub fn process_pay_installment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let order_token_info = next_account_info(account_info_iter)?;
+    let buyer_token_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure order account is owned by this program
+    check_program_owned(program_id, order_info, "order")?;
+    // ensure buyer token account is owned by token program
+    check_token_program(token_program_info)?;
+    if *buyer_token_info.owner != *token_program_info.key {
+        msg!("Error: Buyer token account not owned by Token Program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the buyer pays the installments
+    if signer_info.key.to_bytes() != order_account.payer {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // ensure the order payment token account is the right one
+    if order_token_info.key.to_bytes() != order_account.token {
+        msg!("Error: Incorrect order token account");
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
+    // ensure the merchant is still taking payments from the buyer
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    check_merchant_not_paused(&merchant_account)?;
+    check_buyer_not_blocked(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        signer_info.key,
+    )?;
+    // ensure the installment is paid in the currency of the order
+    check_token_account_mint(
+        buyer_token_info,
+        token_program_info.key,
+        &Pubkey::new_from_array(order_account.mint),
+    )?;
+    // ensure the order is still being paid for
+    if order_account.status != OrderStatus::Pending as u8 {
+        msg!("Error: Order is not awaiting installments");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    if let Some(expires_at) = get_order_expiry(&order_account) {
+        if timestamp >= expires_at {
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+    }
+    // ensure the order is not overpaid (transfer fees withheld by the mint count
+    // towards the amount paid)
+    let received_amount = get_order_received_amount(&order_account)?;
+    let outstanding_amount = order_account
+        .expected_amount
+        .saturating_sub(received_amount);
+    if amount == 0 || amount > outstanding_amount {
+        msg!(
+            "Error: The installment must be 1 to {:?}",
+            outstanding_amount
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Transferring installment to the order token account...
+    let balance = unpack_token_account(order_token_info, token_program_info.key)?.amount;
+    invoke_transfer(
+        token_program_info,
+        buyer_token_info,
+        possible_mint_info,
+        order_token_info,
+        signer_info,
+        amount,
+        &[],
+    )?;
+
+    // the mint may withhold part of the installment as a transfer fee
+    let received = unpack_token_account(order_token_info, token_program_info.key)?.amount - balance;
+    let (net_amount, _tip, transfer_fee) = get_net_amounts(amount, 0, received)?;
+
+    // Updating order account information...
+    order_account.paid_amount = order_account
+        .paid_amount
+        .checked_add(net_amount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    order_account.transfer_fee = order_account
+        .transfer_fee
+        .checked_add(transfer_fee)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if get_order_received_amount(&order_account)? == order_account.expected_amount {
+        order_account.status = OrderStatus::Paid as u8;
+    }
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+    if order_account.status == OrderStatus::Paid as u8 {
+        emit_order_paid(order_info.key, &order_account);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::{
+    engine::constants::{ESCROW, EXPIRES_AT, ORDER_REFERRAL},
+    engine::json::OrderSubscription,
+    engine::token::unpack_token_account,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, OrderStatus, Serdes},
+};
+use serde_json::{Error as JSONError, Value};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Check whether a checkout of the merchant is settled instantly
+///
+/// Only orders that a withdrawal would have nothing more to do for are settled
+/// instantly.  Orders paid in installments, held in escrow, that expire, that
+/// were referred or that are made for a subscription are still paid into an
+/// order token account and withdrawn as usual, as are all the orders of a
+/// merchant with a payout schedule.
+pub fn is_settled_instantly(
+    merchant_account: &MerchantAccount,
+    order_status: u8,
+    data: &str,
+) -> bool {
+    if !merchant_account.instant_settlement
+        || merchant_account.payout_count > 0
+        || order_status != OrderStatus::Paid as u8
+    {
+        return false;
+    }
+    let order_subscription: Result<OrderSubscription, JSONError> = serde_json::from_str(data);
+    if order_subscription.is_ok() {
+        return false;
+    }
+    let json_data: Value = match serde_json::from_str(data) {
+        Err(_error) => return true,
+        Ok(data) => data,
+    };
+    json_data[ESCROW].is_null()
+        && json_data[EXPIRES_AT].is_null()
+        && json_data[ORDER_REFERRAL].is_null()
+}
+
+/// Ensure that a token account that a checkout pays directly belongs to the merchant
+pub fn check_merchant_token(
+    merchant_account: &MerchantAccount,
+    merchant_token_info: &AccountInfo<'_>,
+    mint_info: &AccountInfo<'_>,
+    token_program_info: &AccountInfo<'_>,
+) -> ProgramResult {
+    let merchant_token_data = unpack_token_account(merchant_token_info, token_program_info.key)?;
+    if merchant_token_data.owner != Pubkey::new_from_array(merchant_account.owner) {
+        msg!("Error: Instantly settled orders are paid to a token account of the merchant");
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if merchant_token_data.mint != *mint_info.key {
+        return Err(PaymentProcessorError::MintNotEqual.into());
+    }
+    Ok(())
+}
+
+/// Set Instant Settlement
+///
+/// Lets the merchant owner have checkouts pay the merchant token account
+/// directly instead of an order token account.  Such orders are created
+/// already withdrawn, which saves the rent of the order token account and the
+/// withdrawal, at the cost of the buyer protections that need the funds held.
+pub fn process_set_instant_settlement(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instant_settlement: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can change how orders are settled
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+
+    // Updating merchant settlement...
+    merchant_account.instant_settlement = instant_settlement;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use crate::{
+    engine::common::transfer_sol,
+    engine::constants::{ACCOUNT_VERSION, INTEGRATOR},
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IntegratorAccount, IsClosed, Serdes},
+    utils::get_integrator_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the integrator account
+///
+/// Ensures that the account is a valid integrator account owned by this program
+pub fn get_integrator_account(
+    program_id: &Pubkey,
+    integrator_info: &AccountInfo<'_>,
+) -> Result<IntegratorAccount, ProgramError> {
+    check_program_owned(program_id, integrator_info, "integrator")?;
+    let integrator_account = IntegratorAccount::unpack(&integrator_info.data.borrow())?;
+    if integrator_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !integrator_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if integrator_account.discriminator != Discriminator::Integrator as u8 {
+        msg!("Error: Invalid integrator account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(integrator_account)
+}
+
+pub fn process_register_integrator(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    app_id: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let integrator_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // the app id is used as a seed so it has to fit
+    if app_id.is_empty() || app_id.len() > MAX_SEED_LEN {
+        msg!("Error: The app id must be 1 to {:?} bytes long", MAX_SEED_LEN);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    // the address of the integrator account is derived using the program id
+    // and the app id thus ensuring that each app id is registered only once
+    let (integrator_address, bump_seed) =
+        Pubkey::find_program_address(&[INTEGRATOR.as_bytes(), app_id.as_bytes()], program_id);
+    if integrator_address != *integrator_info.key {
+        msg!("Error: Integrator address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let signer_seeds: &[&[_]] = &[INTEGRATOR.as_bytes(), app_id.as_bytes(), &[bump_seed]];
+
+    let account_size = get_integrator_account_size(&app_id);
+    // Fund the integrator account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            integrator_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            integrator_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the integrator account
+    invoke_signed(
+        &system_instruction::allocate(integrator_info.key, account_size as u64),
+        &[integrator_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the integrator account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(integrator_info.key, program_id),
+        &[integrator_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving integrator information...
+    let mut integrator_data = integrator_info.try_borrow_mut_data()?;
+    let integrator = IntegratorAccount {
+        discriminator: Discriminator::Integrator as u8,
+        owner: signer_info.key.to_bytes(),
+        app_id,
+        version: ACCOUNT_VERSION,
+    };
+    integrator.pack(&mut integrator_data);
+
+    // ensure integrator account is rent exempt
+    if !rent.is_exempt(integrator_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Withdraw the fees accrued by an integrator
+pub fn process_withdraw_integrator_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let integrator_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let integrator_account = get_integrator_account(program_id, integrator_info)?;
+    // ensure only the integrator owner can withdraw
+    if integrator_account.owner != signer_info.key.to_bytes() {
+        msg!("Error: Only the integrator owner can withdraw fees");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    // leave enough behind to keep the integrator account rent exempt
+    let minimum_balance = rent.minimum_balance(integrator_info.data_len());
+    let amount = integrator_info.lamports().saturating_sub(minimum_balance);
+    if amount > 0 {
+        transfer_sol(integrator_info.clone(), destination_info.clone(), amount)?;
+    }
+
+    Ok(())
+}
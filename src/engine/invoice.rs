@@ -0,0 +1,325 @@
+use crate::{
+    engine::blocklist::{check_buyer_not_blocked, skip_blocked_buyer_address},
+    engine::common::{
+        create_order_account, create_program_owned_associated_token_account, get_order_expiry,
+        next_order_sequence, record_merchant_volume, update_merchant_references,
+    },
+    engine::config::{check_migration_window, get_program_config},
+    engine::constants::{ACCOUNT_VERSION, DEFAULT_DATA},
+    engine::pay::{order_checks, pay_fees, FeeAccounts},
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sweep::{get_sweep_bounty, set_aside_sweep_bounty, skip_sweep_bounty},
+    engine::sysvars::SysvarProvider,
+    engine::token::{self, get_net_amounts, unpack_token_account},
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    events::emit_order_paid,
+    state::{Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+    utils::{get_fees, get_order_account_size},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Create Invoice
+///
+/// Creates an order that is awaiting payment.  The invoice can then be paid by
+/// any wallet using PayInvoice.
+pub fn process_create_invoice(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+    order_id: String,
+    secret: String,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let seller_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let possible_stats_info = find_merchant_stats(program_id, account_info_iter);
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can create invoices
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if amount == 0 {
+        msg!("Error: The invoice amount cannot be 0");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+
+    // count the order token account towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        possible_stats_info,
+        &merchant_info.key.to_bytes(),
+        1,
+        0,
+    )?;
+    // count the order towards the references of the merchant
+    update_merchant_references(merchant_info, true)?;
+
+    // create order account
+    let order_account_size = get_order_account_size(&order_id, &secret, &data);
+    create_order_account(
+        program_id,
+        signer_info,
+        order_info,
+        system_program_info,
+        merchant_info.key,
+        &order_id,
+        order_account_size,
+    )?;
+
+    // create the token account that the invoice will be paid into
+    create_program_owned_associated_token_account(
+        program_id,
+        &[
+            signer_info.clone(),
+            order_info.clone(),
+            seller_token_info.clone(),
+            mint_info.clone(),
+            pda_info.clone(),
+            token_program_info.clone(),
+            system_program_info.clone(),
+            rent_sysvar_info.clone(),
+        ],
+        rent,
+    )?;
+
+    // number the order in the order sequence of the merchant
+    let sequence = next_order_sequence(merchant_info)?;
+    // Saving order information...
+    let order = OrderAccount {
+        discriminator: Discriminator::OrderInvoice as u8,
+        status: OrderStatus::Pending as u8,
+        created: timestamp,
+        modified: timestamp,
+        merchant: merchant_info.key.to_bytes(),
+        mint: mint_info.key.to_bytes(),
+        token: seller_token_info.key.to_bytes(),
+        payer: Pubkey::default().to_bytes(),
+        expected_amount: amount,
+        paid_amount: 0,
+        tip: 0,
+        transfer_fee: 0,
+        withdrawn_amount: 0,
+        sequence,
+        items: vec![],
+        released_amount: 0,
+        created_slot: sysvars.slot()?,
+        sweep_bounty: 0,
+        order_id,
+        secret,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    order.pack(&mut order_info.try_borrow_mut_data()?);
+
+    // ensure order account is rent exempt
+    if !rent.is_exempt(order_info.lamports(), order_account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Pay Invoice
+///
+/// Pays the amount of an invoice (plus the transaction fee) and marks it as paid
+pub fn process_pay_invoice(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let seller_token_info = next_account_info(account_info_iter)?;
+    let buyer_token_info = next_account_info(account_info_iter)?;
+    let program_owner_info = next_account_info(account_info_iter)?;
+    let sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let possible_integrator_info = next_account_info(account_info_iter);
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let config_account = get_program_config(program_id, config_info)?;
+    check_migration_window(&config_account, timestamp)?;
+    let fee_accounts = FeeAccounts {
+        program_owner: program_owner_info,
+        sponsor: sponsor_info,
+        system_program: system_program_info,
+    };
+    let merchant_account = order_checks(
+        program_id,
+        signer_info,
+        merchant_info,
+        buyer_token_info,
+        mint_info,
+        &fee_accounts,
+        &config_account,
+    )?;
+    check_buyer_not_blocked(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        signer_info.key,
+    )?;
+    let possible_integrator_info = skip_blocked_buyer_address(
+        program_id,
+        account_info_iter,
+        merchant_info,
+        signer_info.key,
+        possible_integrator_info,
+    );
+    let possible_integrator_info = skip_sweep_bounty(
+        program_id,
+        account_info_iter,
+        merchant_info,
+        possible_integrator_info,
+    );
+    // ensure order account is owned by this program
+    check_program_owned(program_id, order_info, "order")?;
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if order_account.discriminator != Discriminator::OrderInvoice as u8 {
+        msg!("Error: Order is not an invoice");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the order payment token account and mint are the right ones
+    if seller_token_info.key.to_bytes() != order_account.token {
+        msg!("Error: Incorrect order token account");
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    if mint_info.key.to_bytes() != order_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    // ensure the invoice is still awaiting payment
+    if order_account.status != OrderStatus::Pending as u8 {
+        msg!("Error: Invoice is not awaiting payment");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    if let Some(expires_at) = get_order_expiry(&order_account) {
+        if timestamp >= expires_at {
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+    }
+
+    // Transfer invoice amount to the order token account...
+    let balance = unpack_token_account(seller_token_info, token_program_info.key)?.amount;
+    invoke(
+        &token::transfer_checked(
+            token_program_info.key,
+            buyer_token_info.key,
+            mint_info,
+            seller_token_info.key,
+            signer_info.key,
+            &[signer_info.key],
+            order_account.expected_amount,
+        )
+        .unwrap(),
+        &[
+            buyer_token_info.clone(),
+            mint_info.clone(),
+            seller_token_info.clone(),
+            signer_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+    // the mint may withhold part of the payment as a transfer fee
+    let received =
+        unpack_token_account(seller_token_info, token_program_info.key)?.amount - balance;
+    let (net_amount, _tip, transfer_fee) =
+        get_net_amounts(order_account.expected_amount, 0, received)?;
+
+    // invoices are never settled instantly, so they set aside the sweep bounty
+    // of the merchant (if any) out of the program owner fee
+    let (program_owner_fee, _sponsor_fee, _integrator_fee) = get_fees(
+        &merchant_account,
+        &config_account,
+        possible_integrator_info.is_ok(),
+    )?;
+    let sweep_bounty = get_sweep_bounty(&merchant_account, program_owner_fee);
+    set_aside_sweep_bounty(
+        program_id,
+        accounts,
+        merchant_info,
+        signer_info,
+        system_program_info,
+        sweep_bounty,
+    )?;
+    pay_fees(
+        program_id,
+        &merchant_account,
+        &config_account,
+        signer_info,
+        &fee_accounts,
+        possible_integrator_info,
+        sweep_bounty,
+    )?;
+    // count the payment towards the fee tier of the merchant
+    record_merchant_volume(merchant_info, net_amount)?;
+
+    // Updating order account information...
+    order_account.status = OrderStatus::Paid as u8;
+    order_account.payer = signer_info.key.to_bytes();
+    order_account.paid_amount = net_amount;
+    order_account.transfer_fee = transfer_fee;
+    order_account.modified = timestamp;
+    order_account.sweep_bounty = sweep_bounty;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+    emit_order_paid(order_info.key, &order_account);
+
+    Ok(())
+}
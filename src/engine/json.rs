@@ -15,6 +15,17 @@ pub struct Package {
     pub price: u64,
     /// the mint (currency) used for this package
     pub mint: String,
+    /// whether cancelling after the trial period refunds the unused part of the period
+    pub prorated_refunds: Option<bool>,
+    /// whether the trial period can be started without paying, in which case the
+    /// price is first paid when renewing at the end of the trial period
+    pub price_due_at_trial_end: Option<bool>,
+    /// how long (in seconds) after the end of a period that has not been renewed
+    /// the subscription is past due before it expires
+    pub grace_seconds: Option<i64>,
+    /// the price of a usage unit for metered packages, whose recorded usage is
+    /// settled at the end of each period on top of the package price
+    pub unit_price: Option<u64>,
 }
 
 #[derive(Serialize, Debug, Deserialize, PartialEq)]
@@ -29,6 +40,66 @@ pub struct OrderSubscription {
     pub subscription: String,
 }
 
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in order account data field to keep track of the fees paid for the order
+pub struct OrderFees {
+    /// the fee (in SOL lamports) paid to the program owner
+    pub program_owner: u64,
+    /// the fee (in SOL lamports) paid to the sponsor
+    pub sponsor: u64,
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in order account data field to keep track of the order token accounts
+/// of a chain checkout order paid in more than one mint
+pub struct OrderToken {
+    pub mint: String,
+    /// the order token account that holds the amount paid in this mint
+    pub token: String,
+    pub amount: u64,
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in order account data field to keep track of the coupon redeemed for the order
+pub struct OrderDiscount {
+    /// the coupon account
+    pub coupon: String,
+    pub code: String,
+    /// the amount taken off the order
+    pub amount: u64,
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in order account data field to keep track of the fiat amounts that were
+/// converted into the payment mint at an oracle price
+pub struct OrderFiatPrice {
+    /// the fiat amount (see FIAT_DECIMALS)
+    pub amount: u64,
+    /// the fiat tip (see FIAT_DECIMALS)
+    pub tip: u64,
+    /// the oracle price of the mint is price * 10^expo
+    pub price: i64,
+    pub expo: i32,
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in order account data field to keep track of the referrer of the order
+pub struct OrderReferral {
+    /// the wallet of the referrer
+    pub referrer: String,
+    /// the share (in basis points) of the amount paid that the referrer gets
+    pub fee: u64,
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+/// Used in program config data field to lower the fee of merchants with a big volume
+pub struct FeeTier {
+    /// the lifetime volume from which the tier applies
+    pub threshold: u64,
+    /// the fee (in SOL lamports) charged to merchants in this tier
+    pub fee: u64,
+}
+
 #[derive(Serialize, Debug, Deserialize, PartialEq)]
 /// Item
 ///
@@ -37,6 +108,14 @@ pub struct Item {
     pub price: u64,
     /// the mint (currency) used for this package
     pub mint: String,
+    /// the item is only sold while in stock, as counted in its item stock account
+    #[serde(default)]
+    pub track_stock: bool,
+    /// the collection (mint) of the NFTs whose holders this item is gated for
+    pub collection: Option<String>,
+    /// the discount (in basis points) that holders of an NFT of the collection get,
+    /// in which case the item is sold to anyone instead of holders only
+    pub holder_discount: Option<u64>,
 }
 
 pub type OrderItems = BTreeMap<String, u64>;
\ No newline at end of file
@@ -0,0 +1,309 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, DEFAULT_DATA, LINK},
+    engine::pay::{process_order, OrderArgs},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, LinkPayerAccount, MerchantAccount, PaymentLinkAccount, Serdes,
+    },
+    utils::get_payment_link_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the payment link account of a link id
+pub fn get_payment_link_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    link_id: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LINK.as_bytes(), &merchant.to_bytes(), link_id.as_bytes()],
+        program_id,
+    )
+}
+
+/// Get the address of the account that marks a wallet as having paid through a link
+pub fn get_link_payer_address(program_id: &Pubkey, link: &Pubkey, payer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LINK.as_bytes(), &link.to_bytes(), &payer.to_bytes()],
+        program_id,
+    )
+}
+
+/// Record a payment made through a payment link
+///
+/// Counts the payment and its amount, and counts the payer the first time that
+/// they pay through the link.  The payer pays for the rent of the account that
+/// marks them as having paid through the link.
+pub fn record_link_payment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo; 6],
+    amount: u64,
+    timestamp: UnixTimestamp,
+) -> ProgramResult {
+    let signer_info = &accounts[0];
+    let link_info = &accounts[1];
+    let link_payer_info = &accounts[2];
+    let merchant_info = &accounts[3];
+    let mint_info = &accounts[4];
+    let system_program_info = &accounts[5];
+
+    check_program_owned(program_id, link_info, "payment link")?;
+    let mut link_account = PaymentLinkAccount::unpack(&link_info.data.borrow())?;
+    if link_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !link_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if link_account.discriminator != Discriminator::PaymentLink as u8 {
+        msg!("Error: Invalid payment link account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the link belongs to this merchant and currency
+    if merchant_info.key.to_bytes() != link_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if mint_info.key.to_bytes() != link_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+
+    let (link_payer_address, bump_seed) =
+        get_link_payer_address(program_id, link_info.key, signer_info.key);
+    if link_payer_address != *link_payer_info.key {
+        msg!("Error: Link payer address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *link_payer_info.owner != *program_id {
+        // this is the first payment of this payer through the link
+        let link_bytes = link_info.key.to_bytes();
+        let payer_bytes = signer_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[LINK.as_bytes(), &link_bytes, &payer_bytes, &[bump_seed]];
+        let account_size = LinkPayerAccount::MIN_LEN;
+        // Fund the link payer account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                link_payer_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                link_payer_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the link payer account
+        invoke_signed(
+            &system_instruction::allocate(link_payer_info.key, account_size as u64),
+            &[link_payer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the link payer account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(link_payer_info.key, program_id),
+            &[link_payer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Saving link payer information...
+        let link_payer = LinkPayerAccount {
+            discriminator: Discriminator::LinkPayer as u8,
+            link: link_bytes,
+            payer: payer_bytes,
+            first_paid: timestamp,
+            version: ACCOUNT_VERSION,
+        };
+        link_payer.pack(&mut link_payer_info.try_borrow_mut_data()?);
+        link_account.unique_payers += 1;
+    }
+
+    // Updating payment link counters...
+    link_account.payments += 1;
+    link_account.volume += amount;
+    link_account.last_paid = timestamp;
+    link_account.pack(&mut link_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Create Payment Link
+///
+/// Creates a payment link account that keeps count of the payments made through
+/// a link shared by the merchant.  The link is for a fixed amount, or for any
+/// amount if the amount is 0.
+pub fn process_create_payment_link(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    link_id: String,
+    amount: u64,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let link_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can create payment links
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // the link id is used as a seed so it has to fit
+    if link_id.is_empty() || link_id.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The link id must be 1 to {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (link_address, bump_seed) =
+        get_payment_link_address(program_id, merchant_info.key, &link_id);
+    if link_address != *link_info.key {
+        msg!("Error: Payment link address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        LINK.as_bytes(),
+        &merchant_bytes,
+        link_id.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+
+    let account_size = get_payment_link_account_size(&link_id, &data);
+    // Fund the payment link account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            link_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            link_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the payment link account
+    invoke_signed(
+        &system_instruction::allocate(link_info.key, account_size as u64),
+        &[link_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the payment link account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(link_info.key, program_id),
+        &[link_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving payment link information...
+    let link = PaymentLinkAccount {
+        discriminator: Discriminator::PaymentLink as u8,
+        merchant: merchant_bytes,
+        mint: mint_info.key.to_bytes(),
+        created: timestamp,
+        last_paid: 0,
+        payments: 0,
+        volume: 0,
+        unique_payers: 0,
+        amount,
+        link_id,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    link.pack(&mut link_info.try_borrow_mut_data()?);
+
+    // ensure payment link account is rent exempt
+    if !rent.is_exempt(link_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Pay Link
+///
+/// Pays a payment link: the order is created from the payment link account so
+/// that the buyer does not need any checkout parameters from the merchant.  The
+/// order id is made of the link id and the number of the payment, and the order
+/// data is the data of the payment link.
+pub fn process_pay_link(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+    tip: u64,
+) -> ProgramResult {
+    // the payment link account follows the express checkout accounts
+    let link_info = accounts.get(13).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    check_program_owned(program_id, link_info, "payment link")?;
+    let link_account = PaymentLinkAccount::unpack(&link_info.data.borrow())?;
+    if link_account.discriminator != Discriminator::PaymentLink as u8 {
+        msg!("Error: Invalid payment link account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if link_account.amount > 0 && amount != link_account.amount {
+        msg!(
+            "Error: The payment link is for an amount of {:?}",
+            link_account.amount
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if amount == 0 {
+        msg!("Error: The amount must be greater than 0");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    // the merchant, the mint and the state of the link are checked when the
+    // payment is counted towards the link
+    let order_id = format!("{}-{}", link_account.link_id, link_account.payments + 1);
+    process_order(
+        program_id,
+        accounts,
+        sysvars,
+        OrderArgs {
+            amount,
+            order_id,
+            secret: String::from(""),
+            maybe_data: Some(link_account.data),
+        },
+        tip,
+        Option::None,
+    )
+}
@@ -0,0 +1,471 @@
+use crate::{
+    engine::common::{is_subscription_ended, transfer_sol},
+    engine::constants::{ACCOUNT_VERSION, MERCHANT, MIGRATION},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, MerchantAccount, MigrationAccount, OrderAccount, OrderStatus,
+        PackageAccount, Serdes, SubscriptionAccount,
+    },
+    utils::get_migration_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+
+/// Get the address of the migration account that holds an account while it is
+/// moved to a bigger account at the same address
+pub fn get_migration_address(program_id: &Pubkey, account: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MIGRATION.as_bytes(), &account.to_bytes()], program_id)
+}
+
+/// Ensure that an account is still in the legacy layout (version 0)
+fn check_legacy_layout(version: u8) -> ProgramResult {
+    if version != 0 {
+        msg!("Error: The account is already in the latest layout");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+/// Make room for the fields added after the legacy layout of an account (at
+/// least its version) by taking them off the padding at the end of its data
+///
+/// Returns false, leaving the data as it is, when it is not padded enough.
+fn take_padding(data: &mut String, added_len: usize) -> bool {
+    if !data.ends_with(&" ".repeat(added_len)) {
+        return false;
+    }
+    data.truncate(data.len() - added_len);
+    true
+}
+
+/// Refuse to migrate an account that is not padded enough and cannot be moved
+fn padding_too_small(added_len: usize) -> ProgramResult {
+    msg!(
+        "Error: The account data needs {:?} bytes of padding to make room for the latest layout",
+        added_len
+    );
+    Err(ProgramError::AccountDataTooSmall)
+}
+
+/// Count the references of a merchant being migrated, from its open orders and
+/// active subscriptions
+///
+/// Merchants in the legacy layout do not count their references (see
+/// update_merchant_references), so their owner passes the orders and
+/// subscriptions that still reference them, each of them once.
+fn count_merchant_references(
+    program_id: &Pubkey,
+    merchant_info: &AccountInfo,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+) -> Result<u64, ProgramError> {
+    let mut counted: Vec<Pubkey> = vec![];
+    for account_info in account_info_iter {
+        check_program_owned(program_id, account_info, "order or subscription")?;
+        if counted.contains(account_info.key) {
+            msg!("Error: {:?} is counted more than once", account_info.key);
+            return Err(ProgramError::InvalidArgument);
+        }
+        let discriminator = account_info.data.borrow().first().copied();
+        let (merchant, open) = match discriminator {
+            Some(value) if OrderAccount::DISCRIMINATORS.contains(&value) => {
+                let order_account = OrderAccount::unpack(&account_info.data.borrow())?;
+                let open = order_account.status != OrderStatus::Withdrawn as u8
+                    && order_account.status != OrderStatus::Cancelled as u8
+                    && order_account.status != OrderStatus::Expired as u8
+                    && order_account.status != OrderStatus::Refunded as u8;
+                (order_account.merchant, open)
+            }
+            Some(value) if SubscriptionAccount::DISCRIMINATORS.contains(&value) => {
+                let subscription_account =
+                    SubscriptionAccount::unpack(&account_info.data.borrow())?;
+                let open = !is_subscription_ended(&subscription_account);
+                (subscription_account.merchant, open)
+            }
+            _ => {
+                msg!("Error: Only open orders and active subscriptions reference a merchant");
+                return Err(ProgramError::InvalidAccountData);
+            }
+        };
+        if merchant != merchant_info.key.to_bytes() {
+            return Err(PaymentProcessorError::WrongMerchant.into());
+        }
+        if !open {
+            msg!(
+                "Error: {:?} no longer references the merchant",
+                account_info.key
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+        counted.push(*account_info.key);
+    }
+    Ok(counted.len() as u64)
+}
+
+/// Start moving an account to a bigger account at the same address
+///
+/// The account in the latest layout is held by the migration account, and the
+/// account is emptied (its lamports going back to the signer), so that it is
+/// removed at the end of the transaction and can be created again, in its new
+/// size, by another MigrateAccount (see complete_account_move).
+fn start_account_move<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    account_info: &AccountInfo<'a>,
+    possible_migration_info: Result<&AccountInfo<'a>, ProgramError>,
+    possible_system_program_info: Result<&AccountInfo<'a>, ProgramError>,
+    sysvars: &dyn SysvarProvider,
+    data: Vec<u8>,
+) -> ProgramResult {
+    let (migration_info, system_program_info) = match (
+        possible_migration_info,
+        possible_system_program_info,
+    ) {
+        (Ok(migration_info), Ok(system_program_info)) => (migration_info, system_program_info),
+        _ => {
+            msg!("Error: The account is not padded enough for the latest layout, and moving it needs the migration account and the System program");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let (migration_address, bump_seed) = get_migration_address(program_id, account_info.key);
+    if migration_address != *migration_info.key {
+        msg!("Error: Migration account address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let account_size = get_migration_account_size(&data);
+    if *migration_info.owner != *program_id {
+        let account_bytes = account_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[MIGRATION.as_bytes(), &account_bytes, &[bump_seed]];
+        // Fund the migration account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                migration_info.key,
+                sysvars.rent()?.minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                migration_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the migration account
+        invoke_signed(
+            &system_instruction::allocate(migration_info.key, account_size as u64),
+            &[migration_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the migration account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(migration_info.key, program_id),
+            &[migration_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+    } else if migration_info.data.borrow().first() != Some(&(Discriminator::Uninitialized as u8)) {
+        msg!("Error: The account is already being moved");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    // Saving migration account information...
+    MigrationAccount {
+        discriminator: Discriminator::Migration as u8,
+        account: account_info.key.to_bytes(),
+        data,
+        version: ACCOUNT_VERSION,
+    }
+    .pack(&mut migration_info.data.borrow_mut());
+
+    // Emptying the account, which is removed at the end of the transaction...
+    for byte in account_info.data.borrow_mut().iter_mut() {
+        *byte = 0;
+    }
+    transfer_sol(
+        account_info.clone(),
+        signer_info.clone(),
+        account_info.lamports(),
+    )
+}
+
+/// Complete moving an account to a bigger account at the same address
+///
+/// The account is created again at its address, by its owner, and the account
+/// in the latest layout held by the migration account is copied to it.  Merchant
+/// accounts are derived from their owner and seed, and subscription accounts from
+/// their owner, merchant and name, so only they can be moved.
+fn complete_account_move<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    account_info: &AccountInfo<'a>,
+    migration_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    sysvars: &dyn SysvarProvider,
+    seed: Option<String>,
+) -> ProgramResult {
+    check_program_owned(program_id, migration_info, "migration")?;
+    let mut migration_account = MigrationAccount::unpack(&migration_info.data.borrow())?;
+    if migration_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !migration_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if migration_account.account != account_info.key.to_bytes() {
+        msg!("Error: The migration account holds another account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let account_size = migration_account.data.len();
+    // Fund the account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            account_info.key,
+            sysvars
+                .rent()?
+                .minimum_balance(account_size)
+                .saturating_sub(account_info.lamports()),
+        ),
+        &[
+            signer_info.clone(),
+            account_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    match migration_account.data.first() {
+        Some(value) if MerchantAccount::DISCRIMINATORS.contains(value) => {
+            let merchant_account = MerchantAccount::unpack(&migration_account.data)?;
+            // ensure only the merchant owner can create the merchant account again
+            if signer_info.key.to_bytes() != merchant_account.owner {
+                return Err(PaymentProcessorError::WrongMerchant.into());
+            }
+            let seed = match &seed {
+                None => MERCHANT,
+                Some(value) => value,
+            };
+            if Pubkey::create_with_seed(signer_info.key, seed, program_id)? != *account_info.key {
+                msg!("Error: Merchant account address does not match seed derivation");
+                return Err(ProgramError::InvalidSeeds);
+            }
+            // Allocate space for the merchant account and assign it to the SolPayments program
+            invoke(
+                &system_instruction::allocate_with_seed(
+                    account_info.key,
+                    signer_info.key,
+                    seed,
+                    account_size as u64,
+                    program_id,
+                ),
+                &[
+                    account_info.clone(),
+                    signer_info.clone(),
+                    system_program_info.clone(),
+                ],
+            )?;
+        }
+        Some(value) if SubscriptionAccount::DISCRIMINATORS.contains(value) => {
+            let subscription_account = SubscriptionAccount::unpack(&migration_account.data)?;
+            // ensure only the subscriber can create the subscription account again
+            if signer_info.key.to_bytes() != subscription_account.owner {
+                msg!("Error: One can only migrate their own subscription");
+                return Err(PaymentProcessorError::NotOrderOwner.into());
+            }
+            let (subscription_address, bump_seed) = Pubkey::find_program_address(
+                &[
+                    &subscription_account.owner,
+                    &subscription_account.merchant,
+                    subscription_account.name.as_bytes(),
+                ],
+                program_id,
+            );
+            if subscription_address != *account_info.key {
+                msg!("Error: Subscription account address does not match seed derivation");
+                return Err(ProgramError::InvalidSeeds);
+            }
+            let signer_seeds: &[&[_]] = &[
+                &subscription_account.owner,
+                &subscription_account.merchant,
+                subscription_account.name.as_bytes(),
+                &[bump_seed],
+            ];
+            // Allocate space for the subscription account
+            invoke_signed(
+                &system_instruction::allocate(account_info.key, account_size as u64),
+                &[account_info.clone(), system_program_info.clone()],
+                &[signer_seeds],
+            )?;
+            // Assign the subscription account to the SolPayments program
+            invoke_signed(
+                &system_instruction::assign(account_info.key, program_id),
+                &[account_info.clone(), system_program_info.clone()],
+                &[signer_seeds],
+            )?;
+        }
+        _ => {
+            msg!("Error: Only merchant and subscription accounts can be moved");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // Copying the account in the latest layout...
+    account_info.data.borrow_mut()[..account_size].copy_from_slice(&migration_account.data);
+
+    // Closing the migration account...
+    migration_account.discriminator = Discriminator::Closed as u8;
+    migration_account.pack(&mut migration_info.data.borrow_mut());
+    transfer_sol(
+        migration_info.clone(),
+        signer_info.clone(),
+        migration_info.lamports(),
+    )
+}
+
+/// Migrate Account
+///
+/// Rewrites an account created before accounts had a version (i.e. in the
+/// legacy layout, read as version 0) in the latest layout, which adds fields
+/// after the data of the account and ends with the account version.  Accounts
+/// cannot be reallocated, so the room for these fields is taken off the padding
+/// at the end of the account data when there is enough of it.  Otherwise the
+/// merchant and subscription accounts are moved to a bigger account at the same
+/// address, in two steps (see start_account_move and complete_account_move) by
+/// their owner.  Merchant accounts are only migrated by their owner, who passes
+/// the orders and subscriptions that count towards their references (see
+/// count_merchant_references).  The other accounts keep being read and written in the legacy
+/// layout, and handlers refuse to set the added fields of accounts in it (see
+/// check_account_migrated).
+pub fn process_migrate_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    seed: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let account_info = next_account_info(account_info_iter)?;
+    let possible_migration_info = next_account_info(account_info_iter);
+    let possible_system_program_info = next_account_info(account_info_iter);
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // the account was emptied by the first step of a move, and is created again
+    if *account_info.owner == system_program::id() {
+        return complete_account_move(
+            program_id,
+            signer_info,
+            account_info,
+            possible_migration_info?,
+            possible_system_program_info?,
+            sysvars,
+            seed,
+        );
+    }
+    // ensure the account is owned by this program
+    check_program_owned(program_id, account_info, "the")?;
+    let discriminator = account_info.data.borrow().first().copied();
+    match discriminator {
+        None => Err(ProgramError::UninitializedAccount),
+        Some(value) if value == Discriminator::Uninitialized as u8 => {
+            Err(ProgramError::UninitializedAccount)
+        }
+        Some(value) if value == Discriminator::Closed as u8 => {
+            Err(PaymentProcessorError::ClosedAccount.into())
+        }
+        Some(value) if MerchantAccount::DISCRIMINATORS.contains(&value) => {
+            let mut merchant_account = MerchantAccount::unpack(&account_info.data.borrow())?;
+            check_legacy_layout(merchant_account.version)?;
+            // ensure only the merchant owner can migrate the merchant account,
+            // as they are the one who knows the orders and subscriptions to count
+            if signer_info.key.to_bytes() != merchant_account.owner {
+                return Err(PaymentProcessorError::WrongMerchant.into());
+            }
+            let has_room = take_padding(&mut merchant_account.data, MerchantAccount::ADDED_LEN);
+
+            // Updating merchant account layout...
+            merchant_account.version = ACCOUNT_VERSION;
+            merchant_account.references =
+                count_merchant_references(program_id, account_info, account_info_iter)?;
+            if has_room {
+                merchant_account.pack(&mut account_info.data.borrow_mut());
+                return Ok(());
+            }
+            start_account_move(
+                program_id,
+                signer_info,
+                account_info,
+                possible_migration_info,
+                possible_system_program_info,
+                sysvars,
+                merchant_account.to_account_data(),
+            )
+        }
+        Some(value) if OrderAccount::DISCRIMINATORS.contains(&value) => {
+            let mut order_account = OrderAccount::unpack(&account_info.data.borrow())?;
+            check_legacy_layout(order_account.version)?;
+            // order accounts are not derived from anything that they can be
+            // created again from, so they are not moved
+            if !take_padding(&mut order_account.data, OrderAccount::ADDED_LEN) {
+                return padding_too_small(OrderAccount::ADDED_LEN);
+            }
+
+            // Updating order account layout...
+            order_account.version = ACCOUNT_VERSION;
+            order_account.pack(&mut account_info.data.borrow_mut());
+            Ok(())
+        }
+        Some(value) if SubscriptionAccount::DISCRIMINATORS.contains(&value) => {
+            let mut subscription_account =
+                SubscriptionAccount::unpack(&account_info.data.borrow())?;
+            check_legacy_layout(subscription_account.version)?;
+            let has_room = take_padding(
+                &mut subscription_account.data,
+                SubscriptionAccount::ADDED_LEN,
+            );
+
+            // Updating subscription account layout...
+            subscription_account.version = ACCOUNT_VERSION;
+            if has_room {
+                subscription_account.pack(&mut account_info.data.borrow_mut());
+                return Ok(());
+            }
+            // ensure only the subscriber can move the subscription account
+            if signer_info.key.to_bytes() != subscription_account.owner {
+                msg!("Error: One can only migrate their own subscription");
+                return Err(PaymentProcessorError::NotOrderOwner.into());
+            }
+            start_account_move(
+                program_id,
+                signer_info,
+                account_info,
+                possible_migration_info,
+                possible_system_program_info,
+                sysvars,
+                subscription_account.to_account_data(),
+            )
+        }
+        Some(value) if value == Discriminator::Package as u8 => {
+            let mut package_account = PackageAccount::unpack(&account_info.data.borrow())?;
+            check_legacy_layout(package_account.version)?;
+            if !take_padding(&mut package_account.data, PackageAccount::ADDED_LEN) {
+                return padding_too_small(PackageAccount::ADDED_LEN);
+            }
+
+            // Updating package account layout...
+            package_account.version = ACCOUNT_VERSION;
+            package_account.pack(&mut account_info.data.borrow_mut());
+            Ok(())
+        }
+        Some(_value) => {
+            msg!("Error: Only merchant, order, subscription and package accounts can be migrated");
+            Err(ProgramError::InvalidAccountData)
+        }
+    }
+}
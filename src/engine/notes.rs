@@ -0,0 +1,213 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, MAX_NOTE_LEN, MAX_ORDER_NOTES, NOTES},
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_type, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, MerchantAccount, OrderAccount, OrderNoteAccount, OrderNotesAccount, Serdes,
+    },
+    utils::get_order_note_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the notes account of an order
+pub fn get_order_notes_address(program_id: &Pubkey, order: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NOTES.as_bytes(), &order.to_bytes()], program_id)
+}
+
+/// Get the address of a note in the notes of an order
+pub fn get_order_note_address(program_id: &Pubkey, order: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[NOTES.as_bytes(), &order.to_bytes(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Create an account owned by this program at a program derived address
+fn create_notes_account<'a>(
+    program_id: &Pubkey,
+    signer_info: &AccountInfo<'a>,
+    new_account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent: &Rent,
+    account_size: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    // Fund the account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            new_account_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            new_account_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the account
+    invoke_signed(
+        &system_instruction::allocate(new_account_info.key, account_size as u64),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(new_account_info.key, program_id),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    if !rent.is_exempt(new_account_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    Ok(())
+}
+
+/// Post Order Note
+///
+/// Lets the buyer or the merchant owner post a note on an order e.g. delivery
+/// instructions or the context of a dispute.  Notes are hashed or encrypted by
+/// their author beforehand.  Each note is kept in its own account along with
+/// who signed it and when, so that the notes of an order can serve as evidence
+/// later on.  The notes of an order are append-only and capped at
+/// MAX_ORDER_NOTES.
+pub fn process_post_order_note(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    note: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let notes_info = next_account_info(account_info_iter)?;
+    let note_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // the order account is not unpacked, its merchant and payer are read in place
+    let order_data = order_info.data.borrow();
+    check_account_type::<OrderAccount>(&order_data)?;
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != OrderAccount::MERCHANT.get(&order_data)? {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure only the buyer or the merchant owner can post notes
+    if signer_info.key.to_bytes() != OrderAccount::PAYER.get(&order_data)?
+        && signer_info.key.to_bytes() != merchant_account.owner
+    {
+        msg!("Error: Only the buyer or the merchant owner can post notes on an order");
+        return Err(PaymentProcessorError::NotOrderOwner.into());
+    }
+    // hashed or encrypted notes are posted encoded e.g. as base64
+    if note.is_empty() || note.len() > MAX_NOTE_LEN || !note.is_ascii() {
+        msg!(
+            "Error: The note must be 1 to {:?} ASCII characters long",
+            MAX_NOTE_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // get the notes of the order, which are created along with the first note
+    let (notes_address, notes_bump_seed) = get_order_notes_address(program_id, order_info.key);
+    if notes_address != *notes_info.key {
+        msg!("Error: Order notes address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let order_bytes = order_info.key.to_bytes();
+    let mut notes_account = if *notes_info.owner == *program_id {
+        let notes_account = OrderNotesAccount::unpack(&notes_info.data.borrow())?;
+        if notes_account.discriminator != Discriminator::OrderNotes as u8
+            || notes_account.order != order_bytes
+        {
+            msg!("Error: Invalid order notes account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        notes_account
+    } else {
+        create_notes_account(
+            program_id,
+            signer_info,
+            notes_info,
+            system_program_info,
+            rent,
+            OrderNotesAccount::MIN_LEN,
+            &[NOTES.as_bytes(), &order_bytes, &[notes_bump_seed]],
+        )?;
+        OrderNotesAccount {
+            discriminator: Discriminator::OrderNotes as u8,
+            order: order_bytes,
+            count: 0,
+            version: ACCOUNT_VERSION,
+        }
+    };
+    if notes_account.count >= MAX_ORDER_NOTES {
+        msg!(
+            "Error: No more than {:?} notes can be posted on an order",
+            MAX_ORDER_NOTES
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Recording the note...
+    let index = notes_account.count;
+    let (note_address, note_bump_seed) = get_order_note_address(program_id, order_info.key, index);
+    if note_address != *note_info.key {
+        msg!("Error: Order note address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    create_notes_account(
+        program_id,
+        signer_info,
+        note_info,
+        system_program_info,
+        rent,
+        get_order_note_account_size(&note),
+        &[
+            NOTES.as_bytes(),
+            &order_bytes,
+            &index.to_le_bytes(),
+            &[note_bump_seed],
+        ],
+    )?;
+    let note_account = OrderNoteAccount {
+        discriminator: Discriminator::OrderNote as u8,
+        order: order_bytes,
+        index,
+        author: signer_info.key.to_bytes(),
+        posted: timestamp,
+        note,
+        version: ACCOUNT_VERSION,
+    };
+    note_account.pack(&mut note_info.try_borrow_mut_data()?);
+
+    notes_account.count = index + 1;
+    notes_account.pack(&mut notes_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::{
+    engine::constants::MAX_OPERATORS,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Check whether a wallet can withdraw the payments of a merchant
+///
+/// i.e. it is the merchant owner or one of the operators of the merchant.
+pub fn is_withdrawal_authority(merchant_account: &MerchantAccount, wallet: &Pubkey) -> bool {
+    let wallet = wallet.to_bytes();
+    wallet == merchant_account.owner
+        || merchant_account.operators[..merchant_account.operator_count as usize].contains(&wallet)
+}
+
+/// Set Operators
+///
+/// Lets the merchant owner designate the wallets that can withdraw payments on
+/// its behalf, so that treasury operations do not need the owner key.  Operators
+/// cannot update the merchant or refund orders.  An empty list removes all the
+/// operators.
+pub fn process_set_operators(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    operators: Vec<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the operators
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    if operators.len() > MAX_OPERATORS {
+        msg!(
+            "Error: A merchant has at most {:?} operators",
+            MAX_OPERATORS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Updating merchant operators...
+    merchant_account.operator_count = operators.len() as u64;
+    merchant_account.operators = [[0; 32]; MAX_OPERATORS];
+    for (index, operator) in operators.iter().enumerate() {
+        merchant_account.operators[index] = operator.to_bytes();
+    }
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
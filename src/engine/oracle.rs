@@ -0,0 +1,278 @@
+use crate::{
+    engine::constants::{
+        ACCOUNT_VERSION, BASIS_POINTS, FIAT_DECIMALS, ORACLE, PYTH_MAGIC, PYTH_PRICE_ACCOUNT_LEN,
+        PYTH_PRICE_ACCOUNT_TYPE, PYTH_TRADING_STATUS, PYTH_VERSION,
+    },
+    engine::token::get_mint_decimals,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantAccount, PriceOracleAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+use std::convert::TryInto;
+
+/// A price read from a Pyth price account
+///
+/// The price of the mint is price * 10^expo, give or take conf * 10^expo.
+#[derive(Debug, PartialEq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: UnixTimestamp,
+}
+
+/// Get the address of the price oracle account of a merchant for a mint
+pub fn get_price_oracle_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ORACLE.as_bytes(), &merchant.to_bytes(), &mint.to_bytes()],
+        program_id,
+    )
+}
+
+/// Read the aggregate price held in a Pyth price account
+///
+/// Only prices that are being traded are accepted.
+pub fn get_pyth_price(price_feed_info: &AccountInfo<'_>) -> Result<OraclePrice, ProgramError> {
+    let data = price_feed_info.data.borrow();
+    if data.len() < PYTH_PRICE_ACCOUNT_LEN {
+        msg!("Error: Invalid Pyth price account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    // the account header is (magic, version, account type, size)
+    if read_u32(0) != PYTH_MAGIC
+        || read_u32(4) != PYTH_VERSION
+        || read_u32(8) != PYTH_PRICE_ACCOUNT_TYPE
+    {
+        msg!("Error: Invalid Pyth price account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // the aggregate price is (price, conf, status, corporate action, slot)
+    if read_u32(224) != PYTH_TRADING_STATUS {
+        msg!("Error: The oracle price is not being traded");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    Ok(OraclePrice {
+        price: read_u64(208) as i64,
+        conf: read_u64(216),
+        expo: read_u32(20) as i32,
+        publish_time: read_u64(96) as i64,
+    })
+}
+
+/// Convert a fiat amount into the mint at the oracle price
+///
+/// Rounds up so that the merchant never receives less than the fiat amount.
+pub fn convert_fiat_amount(
+    fiat_amount: u64,
+    oracle_price: &OraclePrice,
+    decimals: u8,
+) -> Result<u64, ProgramError> {
+    if oracle_price.price <= 0 {
+        msg!("Error: Invalid oracle price");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    match scale_fiat_amount(fiat_amount, oracle_price, decimals) {
+        None => {
+            msg!("Error: The converted amount is too large");
+            Err(ProgramError::InvalidArgument)
+        }
+        Some(value) => Ok(value),
+    }
+}
+
+/// amount = fiat amount * 10^decimals / (price * 10^expo * 10^FIAT_DECIMALS)
+fn scale_fiat_amount(fiat_amount: u64, oracle_price: &OraclePrice, decimals: u8) -> Option<u64> {
+    let mut numerator = (fiat_amount as u128).checked_mul(10u128.checked_pow(decimals as u32)?)?;
+    let mut denominator =
+        (oracle_price.price as u128).checked_mul(10u128.checked_pow(FIAT_DECIMALS)?)?;
+    let expo_scale = 10u128.checked_pow(oracle_price.expo.unsigned_abs())?;
+    if oracle_price.expo < 0 {
+        numerator = numerator.checked_mul(expo_scale)?;
+    } else {
+        denominator = denominator.checked_mul(expo_scale)?;
+    }
+    let amount = numerator.checked_add(denominator - 1)? / denominator;
+    amount.try_into().ok()
+}
+
+/// Get the oracle price that the merchant set for the mint
+///
+/// Fails if the oracle price is older than the max staleness or if its
+/// confidence interval is wider than the max slippage.
+pub fn get_oracle_price(
+    program_id: &Pubkey,
+    oracle_info: &AccountInfo<'_>,
+    price_feed_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    mint_info: &AccountInfo<'_>,
+    timestamp: UnixTimestamp,
+) -> Result<OraclePrice, ProgramError> {
+    check_program_owned(program_id, oracle_info, "price oracle")?;
+    let oracle_account = PriceOracleAccount::unpack(&oracle_info.data.borrow())?;
+    if oracle_account.discriminator != Discriminator::PriceOracle as u8 {
+        msg!("Error: Invalid price oracle account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if merchant_info.key.to_bytes() != oracle_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if mint_info.key.to_bytes() != oracle_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    if price_feed_info.key.to_bytes() != oracle_account.price_feed {
+        msg!("Error: Wrong price feed for price oracle account");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let oracle_price = get_pyth_price(price_feed_info)?;
+    if timestamp - oracle_price.publish_time > oracle_account.max_staleness {
+        msg!(
+            "Error: The oracle price is stale, published at {:?}",
+            oracle_price.publish_time
+        );
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if oracle_price.price <= 0
+        || oracle_price.conf as u128 * BASIS_POINTS as u128
+            > oracle_price.price as u128 * oracle_account.max_slippage as u128
+    {
+        msg!("Error: The oracle price is too uncertain");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    Ok(oracle_price)
+}
+
+/// Set Price Oracle
+///
+/// Lets the merchant owner price payments in a mint in fiat.  The Pyth price
+/// account that prices the mint is pinned, along with how stale and how
+/// uncertain its price may be for fiat amounts to be converted at it.
+pub fn process_set_price_oracle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_staleness: i64,
+    max_slippage: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let oracle_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let price_feed_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the price oracle
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if max_staleness < 0 || max_slippage > BASIS_POINTS {
+        msg!(
+            "Error: The max staleness cannot be negative and the max slippage must be 0 to {:?} basis points",
+            BASIS_POINTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    // ensure the mint is a mint and the price feed holds a price
+    get_mint_decimals(mint_info)?;
+    get_pyth_price(price_feed_info)?;
+
+    if *oracle_info.owner != *program_id {
+        let (oracle_address, bump_seed) =
+            get_price_oracle_address(program_id, merchant_info.key, mint_info.key);
+        if oracle_address != *oracle_info.key {
+            msg!("Error: Price oracle address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let mint_bytes = mint_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[
+            ORACLE.as_bytes(),
+            &merchant_bytes,
+            &mint_bytes,
+            &[bump_seed],
+        ];
+        let account_size = PriceOracleAccount::MIN_LEN;
+        // Fund the price oracle account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                oracle_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                oracle_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the price oracle account
+        invoke_signed(
+            &system_instruction::allocate(oracle_info.key, account_size as u64),
+            &[oracle_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the price oracle account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(oracle_info.key, program_id),
+            &[oracle_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(oracle_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+    } else {
+        let oracle_account = PriceOracleAccount::unpack(&oracle_info.data.borrow())?;
+        if oracle_account.discriminator != Discriminator::PriceOracle as u8
+            || oracle_account.merchant != merchant_info.key.to_bytes()
+            || oracle_account.mint != mint_info.key.to_bytes()
+        {
+            msg!("Error: Invalid price oracle account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // Saving price oracle information...
+    let oracle_account = PriceOracleAccount {
+        discriminator: Discriminator::PriceOracle as u8,
+        merchant: merchant_info.key.to_bytes(),
+        mint: mint_info.key.to_bytes(),
+        price_feed: price_feed_info.key.to_bytes(),
+        max_staleness,
+        max_slippage,
+        version: ACCOUNT_VERSION,
+    };
+    oracle_account.pack(&mut oracle_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
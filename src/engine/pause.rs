@@ -0,0 +1,61 @@
+use crate::{
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Ensure that a merchant is taking payments
+pub fn check_merchant_not_paused(merchant_account: &MerchantAccount) -> ProgramResult {
+    if merchant_account.is_paused {
+        msg!("Error: The merchant is paused");
+        return Err(PaymentProcessorError::MerchantPaused.into());
+    }
+    Ok(())
+}
+
+/// Pause or Resume Merchant
+///
+/// Lets the merchant owner stop taking checkouts and subscriptions for a while
+/// e.g. during maintenance or an incident, and start again.  Orders that were
+/// already paid can still be withdrawn, refunded or reclaimed while paused.
+pub fn process_set_merchant_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can pause or resume the merchant
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+
+    // Updating merchant pause switch...
+    merchant_account.is_paused = paused;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::{
+    engine::renew::get_subscription_account,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_signer},
+    error::PaymentProcessorError,
+    state::{Serdes, SubscriptionAccount, SubscriptionStatus},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Get a subscription account signed for by its owner
+fn get_owned_subscription_account(
+    program_id: &Pubkey,
+    owner_info: &AccountInfo<'_>,
+    subscription_info: &AccountInfo<'_>,
+) -> Result<SubscriptionAccount, ProgramError> {
+    // ensure signer can sign
+    check_signer(owner_info)?;
+    let subscription_account = get_subscription_account(program_id, subscription_info)?;
+    // ensure only the owner can pause or resume the subscription
+    if owner_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    Ok(subscription_account)
+}
+
+/// Pause Subscription
+///
+/// Lets the subscriber freeze the remaining period of an active subscription.
+/// The seconds left in the period are kept on the subscription account and the
+/// period ends right away, until the subscription is resumed.
+pub fn process_pause_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let mut subscription_account =
+        get_owned_subscription_account(program_id, owner_info, subscription_info)?;
+    // only subscriptions that have been paid for and are past their trial can be paused
+    if subscription_account.status != SubscriptionStatus::Initialized as u8
+        || timestamp >= subscription_account.period_end
+    {
+        msg!("Error: Only active subscriptions can be paused");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // subscriptions in the legacy layout have no room for the remaining period
+    check_account_migrated(subscription_account.version, "subscription")?;
+
+    // Freezing the remaining period...
+    subscription_account.status = SubscriptionStatus::Paused as u8;
+    subscription_account.remaining_seconds = subscription_account
+        .period_end
+        .checked_sub(timestamp)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    subscription_account.period_end = timestamp;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
+
+/// Resume Subscription
+///
+/// Lets the subscriber resume a paused subscription.  The period is re-anchored
+/// on the current time and ends after the seconds that were left when it was paused.
+pub fn process_resume_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    let mut subscription_account =
+        get_owned_subscription_account(program_id, owner_info, subscription_info)?;
+    if subscription_account.status != SubscriptionStatus::Paused as u8 {
+        msg!("Error: The subscription is not paused");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Re-anchoring the period on the current time...
+    subscription_account.status = SubscriptionStatus::Initialized as u8;
+    subscription_account.period_end = timestamp
+        .checked_add(subscription_account.remaining_seconds)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    subscription_account.remaining_seconds = 0;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
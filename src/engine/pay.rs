@@ -1,14 +1,60 @@
 use crate::{
     engine::{
-        common::create_program_owned_associated_token_account,
-        constants::{DEFAULT_DATA, INITIAL, PAID, PROGRAM_OWNER, SPONSOR_FEE},
-        json::{Item, OrderItems},
+        accepted_mints::check_accepted_mints,
+        blocklist::{check_buyer_not_blocked, skip_blocked_buyer_address},
+        bundle::{get_bundle_discount, next_bundle_accounts},
+        campaign::record_campaign_donation,
+        collection::{get_held_collection, get_holder_discount},
+        common::{
+            create_order_account, create_program_owned_associated_token_account,
+            get_installment_expected_amount, get_next_order_id, get_order_expiry,
+            get_program_account_discriminator, next_order_sequence, record_merchant_volume,
+            set_order_discount, set_order_fees, set_order_fiat_price, update_merchant_references,
+        },
+        config::{check_migration_window, get_program_config},
+        constants::{
+            ACCOUNT_VERSION, BASIS_POINTS, DEFAULT_DATA, INITIAL, MAX_SPLIT_RECIPIENTS, SPLITS,
+            TOKENS,
+        },
+        coupon::redeem_coupon,
+        freeze_policy::check_freezable_mints,
+        instant_settlement::{check_merchant_token, is_settled_instantly},
+        integrator::get_integrator_account,
+        json::{OrderFiatPrice, OrderItems, OrderReferral, OrderToken},
+        link::record_link_payment,
+        oracle::{convert_fiat_amount, get_oracle_price},
+        pause::check_merchant_not_paused,
+        receipt::{
+            get_receipt_name, get_receipt_uri, get_token_metadata_program_id, mint_receipt,
+            next_receipt_accounts, ReceiptMetadata,
+        },
+        referral::{get_referrer_account, set_order_referral},
+        screening::{get_screening_program, screen_payment, ScreeningRequest},
+        stats::record_token_accounts,
+        stock::take_item_stock,
+        store::{find_store, get_store_account, set_order_store},
+        sweep::{get_sweep_bounty, set_aside_sweep_bounty},
+        sysvars::SysvarProvider,
+        terms::{find_catalog_item, get_merchant_catalog, parse_catalog},
+        token::{self, get_mint_decimals, get_net_amounts, is_token_program, unpack_token_account},
+        validation::{
+            check_program_owned, check_program_owner, check_signer, check_sponsor,
+            check_token_account_mint,
+        },
     },
     error::PaymentProcessorError,
-    state::{Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
-    utils::{get_amounts, get_order_account_size},
+    events::emit_order_paid,
+    payment_id::{get_payment_id, get_recent_slot_hash, PAYMENT_ID_LOG_PREFIX},
+    state::{
+        CatalogItem, Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderLineItem,
+        OrderStatus, ProgramConfigAccount, PublicKey, Serdes,
+    },
+    utils::{
+        get_discounted_amounts, get_fees, get_order_account_size, get_order_items_size,
+        get_tolerated_range,
+    },
 };
-use serde_json::{json, Error as JSONError, Value};
+use serde_json::{json, Value};
 use solana_program::program_pack::Pack;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
@@ -19,11 +65,26 @@ use solana_program::{
     program_pack::IsInitialized,
     pubkey::Pubkey,
     system_instruction,
-    sysvar::{clock::Clock, rent::Rent, Sysvar},
+    sysvar::{self, rent::Rent, Sysvar},
 };
 use spl_token::{self, state::Account as TokenAccount};
-use std::collections::BTreeMap;
-use std::str::FromStr;
+use std::{borrow::Cow, collections::BTreeMap};
+
+/// The details of the order that a payment creates
+pub struct OrderArgs {
+    pub amount: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub maybe_data: Option<String>,
+}
+
+/// The accounts that the fees of a payment go to
+pub struct FeeAccounts<'a, 'b> {
+    pub program_owner: &'a AccountInfo<'b>,
+    pub sponsor: &'a AccountInfo<'b>,
+    /// the system program, which moves the fees
+    pub system_program: &'a AccountInfo<'b>,
+}
 
 /// Run checks for order processing
 pub fn order_checks(
@@ -32,18 +93,13 @@ pub fn order_checks(
     merchant_info: &AccountInfo<'_>,
     buyer_token_info: &AccountInfo<'_>,
     mint_info: &AccountInfo<'_>,
-    program_owner_info: &AccountInfo<'_>,
-    sponsor_info: &AccountInfo<'_>,
+    fee_accounts: &FeeAccounts<'_, '_>,
+    config_account: &ProgramConfigAccount,
 ) -> Result<MerchantAccount, ProgramError> {
     // ensure signer can sign
-    if !signer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    check_signer(signer_info)?;
     // ensure merchant account is owned by this program
-    if *merchant_info.owner != *program_id {
-        msg!("Error: Wrong owner for merchant account");
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    check_program_owned(program_id, merchant_info, "merchant")?;
     // get the merchant account
     let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
     if merchant_account.is_closed() {
@@ -52,25 +108,17 @@ pub fn order_checks(
     if !merchant_account.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
-    // ensure buyer token account is owned by token program
-    if *buyer_token_info.owner != spl_token::id() {
+    check_merchant_not_paused(&merchant_account)?;
+    // ensure buyer token account is owned by a token program
+    if !is_token_program(buyer_token_info.owner) {
         msg!("Error: Buyer token account not owned by Token Program");
         return Err(ProgramError::IncorrectProgramId);
     }
     // Get mint details and verify that they match token account
-    let buyer_token_data = TokenAccount::unpack(&buyer_token_info.data.borrow())?;
-    if *mint_info.key != buyer_token_data.mint {
-        return Err(PaymentProcessorError::MintNotEqual.into());
-    }
-    // check that provided program owner is correct
-    if *program_owner_info.key != Pubkey::from_str(PROGRAM_OWNER).unwrap() {
-        return Err(PaymentProcessorError::WrongProgramOwner.into());
-    }
-    // check that the provided sponsor is correct
-    if *sponsor_info.key != Pubkey::new_from_array(merchant_account.sponsor) {
-        msg!("Error: Sponsor account is incorrect");
-        return Err(PaymentProcessorError::WrongSponsor.into());
-    }
+    check_token_account_mint(buyer_token_info, buyer_token_info.owner, mint_info.key)?;
+    // check that provided program owner and sponsor are correct
+    check_program_owner(fee_accounts.program_owner, config_account)?;
+    check_sponsor(fee_accounts.sponsor, &merchant_account)?;
 
     Ok(merchant_account)
 }
@@ -85,49 +133,178 @@ pub fn order_checks(
 ///     id: quantity
 /// }
 /// e.g. {"item1", 1, "item2": 33}
+///
+/// Items may be priced in different mints, as long as at least one of them is
+/// priced in the checkout mint.  Returns the amount to take from the buyer in the
+/// checkout mint (see get_tolerated_amount) and the amount due in each of the
+/// other mints.
 pub fn chain_checkout_checks(
     merchant_account: &MerchantAccount,
     mint: &AccountInfo,
+    catalog: &[CatalogItem],
     order_items: &OrderItems,
     amount: u64,
-) -> ProgramResult {
+) -> Result<(u64, BTreeMap<String, u64>, OrderItems), ProgramError> {
     if merchant_account.discriminator != Discriminator::MerchantChainCheckout as u8 {
         msg!("Error: Invalid merchant account");
         return Err(PaymentProcessorError::InvalidMerchantData.into());
     }
 
-    let merchant_json_data: Result<BTreeMap<String, Item>, JSONError> =
-        serde_json::from_str(&merchant_account.data);
-
-    let registered_items = match merchant_json_data {
-        Err(_error) => return Err(PaymentProcessorError::InvalidMerchantData.into()),
-        Ok(data) => data,
-    };
-
-    let mut total_amount: u64 = 0;
+    // the amounts are added up per mint, keyed by the bytes of the mint since
+    // encoding a mint as a string costs more compute than the rest of the item
+    let mut amounts: BTreeMap<PublicKey, u64> = BTreeMap::new();
+    // the items whose stock is tracked
+    let mut stock_items: OrderItems = BTreeMap::new();
 
     for (key, quantity) in order_items.iter() {
-        let registered_item = match registered_items.get(key) {
+        let registered_item = match find_catalog_item(catalog, key) {
             None => {
                 msg!("Error: Invalid order item {:?}", key);
                 return Err(PaymentProcessorError::InvalidOrderData.into());
             }
             Some(value) => value,
         };
-        if registered_item.mint != mint.key.to_string() {
+
+        let item_amount = registered_item
+            .price
+            .checked_mul(*quantity)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+        let mint_amount = amounts.entry(registered_item.mint).or_insert(0);
+        *mint_amount = mint_amount
+            .checked_add(item_amount)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+        if registered_item.track_stock {
+            stock_items.insert(key.clone(), *quantity);
+        }
+    }
+
+    let total_amount = match amounts.remove(&mint.key.to_bytes()) {
+        None => {
             msg!(
                 "Error: Mint {:?} invalid for this order",
                 mint.key.to_string()
             );
             return Err(PaymentProcessorError::WrongMint.into());
         }
+        Some(value) => value,
+    };
+    // only the other mints are kept in the order data as strings
+    let other_mint_amounts = amounts
+        .into_iter()
+        .map(|(other_mint, other_amount)| {
+            (Pubkey::new_from_array(other_mint).to_string(), other_amount)
+        })
+        .collect();
+
+    let amount = get_tolerated_amount(merchant_account, total_amount, amount)?;
+
+    Ok((amount, other_mint_amounts, stock_items))
+}
 
-        total_amount = total_amount + (registered_item.price * quantity);
+/// Get the amount to take from the buyer for a price
+///
+/// Without a tolerance policy the amount has to cover the price.  With one, an
+/// underpayment within the tolerance is accepted and an overpayment beyond the
+/// tolerance is cut down to the price, leaving the excess with the buyer.
+pub fn get_tolerated_amount(
+    merchant_account: &MerchantAccount,
+    price: u64,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    let (min_amount, max_amount) = get_tolerated_range(price, merchant_account.tolerance)?;
+    if amount < min_amount {
+        msg!("Error: Insufficient amount, should be {:?}", price);
+        return Err(PaymentProcessorError::InsufficientPaid.into());
+    }
+    if merchant_account.tolerance > 0 && amount > max_amount {
+        msg!(
+            "Info: returning the excess of {:?} to the buyer",
+            amount - price
+        );
+        return Ok(price);
     }
+    Ok(amount)
+}
 
-    if total_amount > amount {
-        msg!("Error: Insufficient amount, should be {:?}", total_amount);
-        return Err(ProgramError::InsufficientFunds);
+/// Pay the transaction fee
+///
+/// The fee is shared between the integrator (if any), the program owner and the
+/// sponsor (if any).  The sweep bounty of the order (if any) is taken off the
+/// program owner fee, since it was set aside for the sweep crank instead (see
+/// set_aside_sweep_bounty).
+pub fn pay_fees<'a>(
+    program_id: &Pubkey,
+    merchant_account: &MerchantAccount,
+    config_account: &ProgramConfigAccount,
+    signer_info: &AccountInfo<'a>,
+    fee_accounts: &FeeAccounts<'_, 'a>,
+    possible_integrator_info: Result<&AccountInfo<'a>, ProgramError>,
+    sweep_bounty: u64,
+) -> ProgramResult {
+    let FeeAccounts {
+        program_owner: program_owner_info,
+        sponsor: sponsor_info,
+        system_program: system_program_info,
+    } = *fee_accounts;
+    let (program_owner_fee, sponsor_fee, integrator_fee) = get_fees(
+        merchant_account,
+        config_account,
+        possible_integrator_info.is_ok(),
+    )?;
+    let program_owner_fee = program_owner_fee
+        .checked_sub(sweep_bounty)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if let Ok(integrator_info) = possible_integrator_info {
+        // the integrator that facilitated the payment gets a share of the fee
+        get_integrator_account(program_id, integrator_info)?;
+        // Transferring integrator fee to the integrator account...
+        invoke(
+            &system_instruction::transfer(signer_info.key, integrator_info.key, integrator_fee),
+            &[
+                signer_info.clone(),
+                integrator_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+
+    if merchant_account.sponsor == config_account.owner {
+        // Transferring processing fee to the program owner...
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                program_owner_info.key,
+                program_owner_fee,
+            ),
+            &[
+                signer_info.clone(),
+                program_owner_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    } else {
+        // we need to pay both the program owner and the sponsor
+        // Transferring processing fee to the program owner and sponsor...
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                program_owner_info.key,
+                program_owner_fee,
+            ),
+            &[
+                signer_info.clone(),
+                program_owner_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        invoke(
+            &system_instruction::transfer(signer_info.key, sponsor_info.key, sponsor_fee),
+            &[
+                signer_info.clone(),
+                sponsor_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
     }
 
     Ok(())
@@ -137,12 +314,17 @@ pub fn chain_checkout_checks(
 pub fn process_order(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    amount: u64,
-    order_id: String,
-    secret: String,
-    maybe_data: Option<String>,
+    sysvars: &dyn SysvarProvider,
+    order: OrderArgs,
+    mut tip: u64,
     checkout_items: Option<OrderItems>,
 ) -> ProgramResult {
+    let OrderArgs {
+        mut amount,
+        order_id,
+        secret,
+        maybe_data,
+    } = order;
     let account_info_iter = &mut accounts.iter();
 
     let signer_info = next_account_info(account_info_iter)?;
@@ -157,19 +339,43 @@ pub fn process_order(
     let token_program_info = next_account_info(account_info_iter)?;
     let system_program_info = next_account_info(account_info_iter)?;
     let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
 
     let rent = &Rent::from_account_info(rent_sysvar_info)?;
-    let timestamp = Clock::get()?.unix_timestamp;
+    let timestamp = sysvars.unix_timestamp()?;
 
+    let config_account = get_program_config(program_id, config_info)?;
+    check_migration_window(&config_account, timestamp)?;
+    let fee_accounts = FeeAccounts {
+        program_owner: program_owner_info,
+        sponsor: sponsor_info,
+        system_program: system_program_info,
+    };
     let merchant_account = order_checks(
         program_id,
         signer_info,
         merchant_info,
         buyer_token_info,
         mint_info,
-        program_owner_info,
-        sponsor_info,
+        &fee_accounts,
+        &config_account,
     )?;
+    check_buyer_not_blocked(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        signer_info.key,
+    )?;
+
+    // the store of the merchant (if any) that the order is made at
+    let possible_store = match find_store(program_id, accounts) {
+        None => None,
+        Some(store_info) => Some((
+            store_info.key,
+            get_store_account(program_id, store_info, merchant_info)?,
+        )),
+    };
 
     // get data
     let mut data = match maybe_data {
@@ -179,151 +385,616 @@ pub fn process_order(
 
     let mut order_account_type = Discriminator::OrderExpressCheckout as u8;
 
+    // an express checkout order can be paid in installments
+    let mut order_status = OrderStatus::Paid as u8;
+    let mut expected_amount = amount;
+    if checkout_items.is_none() {
+        if let Some(value) = get_installment_expected_amount(&data) {
+            if value < amount {
+                msg!("Error: The expected amount is less than the amount paid");
+                return Err(PaymentProcessorError::InvalidOrderData.into());
+            }
+            if value > amount {
+                order_status = OrderStatus::Pending as u8;
+                expected_amount = value;
+            }
+        }
+    }
+
     // process chain checkout
-    if checkout_items.is_some() {
+    let mut other_mint_payments = vec![];
+    let mut stock_items = OrderItems::new();
+    let mut items = vec![];
+    let mut possible_checkout = None;
+    if let Some(order_items) = checkout_items {
         order_account_type = Discriminator::OrderChainCheckout as u8;
-        let order_items = checkout_items.unwrap();
-        chain_checkout_checks(&merchant_account, &mint_info.clone(), &order_items, amount)?;
-        if data == String::from(DEFAULT_DATA) {
-            data = json!({ PAID: order_items }).to_string();
-        } else {
-            // let possible_json_data: Result<BTreeMap<&str, Value>, JSONError> = serde_json::from_str(&data);
-            // let json_data = match possible_json_data {
-            let json_data: Value = match serde_json::from_str(&data) {
-                Err(_error) => return Err(PaymentProcessorError::InvalidOrderData.into()),
-                Ok(data) => data,
-            };
-            data = json!({
-                INITIAL: json_data,
-                PAID: order_items
+        // a store with a catalog of its own sells its items instead of the merchant's
+        let catalog = match &possible_store {
+            Some((_store, store_account)) if !store_account.catalog.is_empty() => {
+                Cow::Owned(parse_catalog(&store_account.catalog)?)
+            }
+            _ => get_merchant_catalog(&merchant_account)?,
+        };
+        let (tolerated_amount, other_mint_amounts, tracked_items) = chain_checkout_checks(
+            &merchant_account,
+            &mint_info.clone(),
+            &catalog,
+            &order_items,
+            amount,
+        )?;
+        stock_items = tracked_items;
+        amount = tolerated_amount;
+        expected_amount = tolerated_amount;
+        // items priced in other mints are paid into an order token account per mint
+        let mut order_tokens = vec![];
+        for (other_mint, other_amount) in other_mint_amounts.into_iter() {
+            let other_mint_info = next_account_info(account_info_iter)?;
+            let other_seller_token_info = next_account_info(account_info_iter)?;
+            let other_buyer_token_info = next_account_info(account_info_iter)?;
+            if other_mint_info.key.to_string() != other_mint {
+                msg!("Error: Expected mint {:?}", other_mint);
+                return Err(PaymentProcessorError::WrongMint.into());
+            }
+            check_token_account_mint(
+                other_buyer_token_info,
+                token_program_info.key,
+                other_mint_info.key,
+            )?;
+            order_tokens.push(OrderToken {
+                mint: other_mint,
+                token: other_seller_token_info.key.to_string(),
+                amount: other_amount,
+            });
+            other_mint_payments.push((
+                other_mint_info,
+                other_seller_token_info,
+                other_buyer_token_info,
+                other_amount,
+            ));
+        }
+        // the items are kept in the order account, next to the data supplied at checkout
+        items = order_items
+            .iter()
+            .map(|(name, quantity)| OrderLineItem {
+                name: name.clone(),
+                quantity: *quantity,
             })
-            .to_string();
+            .collect();
+        if !order_tokens.is_empty() || data != DEFAULT_DATA {
+            let mut json_data: Value = match serde_json::from_str(&data) {
+                Ok(Value::Object(data)) => Value::Object(data),
+                _ => return Err(PaymentProcessorError::InvalidOrderData.into()),
+            };
+            if !order_tokens.is_empty() {
+                json_data[TOKENS] = json!(order_tokens);
+            }
+            data = json_data.to_string();
+        }
+        possible_checkout = Some((catalog, order_items));
+    }
+    // the optional accounts that follow are told apart by their discriminator:
+    // the integrator account, the coupon account, the payment link account
+    // (followed by its payer account), the campaign account, the item stock
+    // accounts of the items sold, the price oracle account (followed by its
+    // price feed account) and the bundle account (followed by the bundle member
+    // account of the merchant, a subscription of the payer and the bundle member
+    // account of that subscription) and the referrer account of the wallet that
+    // referred the buyer.  The store account was looked up above and the
+    // accepted mints account of the merchant is looked up below.
+    // The SlotHashes sysvar asks for the payment id to be
+    // logged.  The token metadata program (followed by the receipt accounts)
+    // asks for a receipt NFT to be minted to the buyer.  A metadata account of the
+    // token metadata program (followed by the token account of the buyer for its
+    // mint) shows the NFT that the buyer holds for the items gated by a
+    // collection.  The screening program set in the program config (if any)
+    // and its accounts are used to screen the payment.  Other accounts not
+    // owned by this program are skipped e.g. the Solana Pay reference keys that
+    // mark the transaction
+    let possible_screening_program = get_screening_program(&config_account)?;
+    let mut possible_integrator_info = Err(ProgramError::NotEnoughAccountKeys);
+    let mut possible_coupon_info = None;
+    let mut possible_link_infos = None;
+    let mut possible_campaign_info = None;
+    let mut stock_infos = vec![];
+    let mut possible_oracle_infos = None;
+    let mut possible_bundle_accounts = None;
+    let mut possible_slot_hashes_info = None;
+    let mut possible_screening_program_info = None;
+    let mut possible_stats_info = None;
+    let mut possible_referrer_info = None;
+    let mut possible_receipt_accounts = None;
+    let mut possible_nft_infos = None;
+    let mut screening_infos = vec![
+        signer_info.clone(),
+        merchant_info.clone(),
+        buyer_token_info.clone(),
+    ];
+    while let Ok(account_info) = next_account_info(account_info_iter) {
+        if *account_info.key == sysvar::slot_hashes::id() {
+            possible_slot_hashes_info = Some(account_info);
+            continue;
+        }
+        if *account_info.key == get_token_metadata_program_id() {
+            possible_receipt_accounts =
+                Some(next_receipt_accounts(account_info, account_info_iter)?);
+            continue;
+        }
+        if *account_info.owner == get_token_metadata_program_id() {
+            let nft_token_info = next_account_info(account_info_iter)?;
+            possible_nft_infos = Some((account_info, nft_token_info));
+            continue;
+        }
+        if let Some(screening_program) = possible_screening_program {
+            if *account_info.key == screening_program {
+                possible_screening_program_info = Some(account_info);
+                continue;
+            }
+            if *account_info.owner == screening_program {
+                screening_infos.push(account_info.clone());
+                continue;
+            }
+        }
+        match get_program_account_discriminator(program_id, account_info) {
+            Some(value) if value == Discriminator::Coupon as u8 => {
+                possible_coupon_info = Some(account_info);
+            }
+            Some(value) if value == Discriminator::PaymentLink as u8 => {
+                let link_payer_info = next_account_info(account_info_iter)?;
+                possible_link_infos = Some((account_info, link_payer_info));
+            }
+            Some(value) if value == Discriminator::Campaign as u8 => {
+                possible_campaign_info = Some(account_info);
+            }
+            Some(value) if value == Discriminator::ItemStock as u8 => {
+                stock_infos.push(account_info);
+            }
+            Some(value) if value == Discriminator::PriceOracle as u8 => {
+                let price_feed_info = next_account_info(account_info_iter)?;
+                possible_oracle_infos = Some((account_info, price_feed_info));
+            }
+            Some(value) if value == Discriminator::MerchantStats as u8 => {
+                possible_stats_info = Some(account_info);
+            }
+            Some(value) if value == Discriminator::SweepBounty as u8 => {}
+            Some(value) if value == Discriminator::Store as u8 => {}
+            Some(value) if value == Discriminator::AcceptedMints as u8 => {}
+            Some(value) if value == Discriminator::Referrer as u8 => {
+                possible_referrer_info = Some(account_info);
+            }
+            Some(value) if value == Discriminator::Bundle as u8 => {
+                possible_bundle_accounts =
+                    Some(next_bundle_accounts(account_info, account_info_iter)?);
+            }
+            None => {}
+            _ => possible_integrator_info = Ok(account_info),
         }
     }
 
-    // create order account
-    let order_account_size = get_order_account_size(&order_id, &secret, &data);
-    // the order account amount includes the fee in SOL
-    let order_account_amount = Rent::default().minimum_balance(order_account_size);
-    invoke(
-        &system_instruction::create_account(
-            signer_info.key,
-            order_info.key,
-            order_account_amount,
-            order_account_size as u64,
+    // ensure the merchant accepts the mints that the order is paid in
+    let mut paid_mints = vec![mint_info.key];
+    let mut paid_mint_infos = vec![mint_info];
+    for (other_mint_info, _other_seller_token_info, _other_buyer_token_info, _other_amount) in
+        other_mint_payments.iter()
+    {
+        paid_mints.push(other_mint_info.key);
+        paid_mint_infos.push(*other_mint_info);
+    }
+    check_accepted_mints(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        &paid_mints,
+    )?;
+    // ensure the order token accounts cannot be frozen, if the merchant asks for it
+    check_freezable_mints(&merchant_account, &paid_mint_infos)?;
+
+    // convert the fiat amounts into the mint at the oracle price (if any)
+    if let Some((oracle_info, price_feed_info)) = possible_oracle_infos {
+        if order_account_type == Discriminator::OrderChainCheckout as u8 {
+            msg!("Error: Chain checkout items are priced in the mint");
+            return Err(PaymentProcessorError::InvalidOrderData.into());
+        }
+        let oracle_price = get_oracle_price(
             program_id,
-        ),
-        &[
-            signer_info.clone(),
-            order_info.clone(),
-            system_program_info.clone(),
-        ],
+            oracle_info,
+            price_feed_info,
+            merchant_info,
+            mint_info,
+            timestamp,
+        )?;
+        let decimals = get_mint_decimals(mint_info)?;
+        data = set_order_fiat_price(
+            data,
+            OrderFiatPrice {
+                amount,
+                tip,
+                price: oracle_price.price,
+                expo: oracle_price.expo,
+            },
+        );
+        amount = convert_fiat_amount(amount, &oracle_price, decimals)?;
+        expected_amount = convert_fiat_amount(expected_amount, &oracle_price, decimals)?;
+        tip = convert_fiat_amount(tip, &oracle_price, decimals)?;
+    }
+
+    // take the items sold out of stock, failing if any of them is sold out
+    for (item, quantity) in stock_items.iter() {
+        take_item_stock(program_id, merchant_info, &stock_infos, item, *quantity)?;
+    }
+
+    // have the screening program approve the payment before accepting it
+    if possible_screening_program.is_some() {
+        let screening_program_info = match possible_screening_program_info {
+            None => {
+                msg!("Error: The payment has to be screened by the screening program");
+                return Err(ProgramError::NotEnoughAccountKeys);
+            }
+            Some(value) => value,
+        };
+        screen_payment(
+            screening_program_info,
+            &screening_infos,
+            &ScreeningRequest {
+                payer: *signer_info.key,
+                merchant: *merchant_info.key,
+                mint: *mint_info.key,
+                amount,
+            },
+        )?;
+    }
+
+    // only sell the items gated by a collection to holders of an NFT of the collection
+    let mut possible_holder_discount = None;
+    if let Some((catalog, order_items)) = possible_checkout {
+        let possible_nft = match possible_nft_infos {
+            None => None,
+            Some((metadata_info, nft_token_info)) => Some((
+                metadata_info.key,
+                get_held_collection(signer_info, metadata_info, nft_token_info)?,
+            )),
+        };
+        possible_holder_discount =
+            get_holder_discount(&catalog, &order_items, mint_info.key, possible_nft)?;
+    }
+
+    // take the coupon, bundle or holder discount (if any) off the amount
+    let mut discount = 0;
+    if possible_coupon_info.is_some() && possible_bundle_accounts.is_some() {
+        msg!("Error: A coupon cannot be combined with a bundle discount");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if possible_holder_discount.is_some()
+        && (possible_coupon_info.is_some() || possible_bundle_accounts.is_some())
+    {
+        msg!("Error: A coupon cannot be combined with a holder discount");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if let Some(coupon_info) = possible_coupon_info {
+        let order_discount = redeem_coupon(
+            program_id,
+            coupon_info,
+            merchant_info,
+            mint_info,
+            amount,
+            timestamp,
+        )?;
+        discount = order_discount.amount;
+        data = set_order_discount(data, order_discount);
+    }
+    if let Some(bundle) = possible_bundle_accounts {
+        let order_discount = get_bundle_discount(
+            program_id,
+            &bundle,
+            signer_info,
+            merchant_info,
+            &data,
+            amount,
+            timestamp,
+        )?;
+        discount = order_discount.amount;
+        data = set_order_discount(data, order_discount);
+    }
+    if let Some(order_discount) = possible_holder_discount {
+        discount = order_discount.amount.min(amount);
+        data = set_order_discount(data, order_discount);
+    }
+    let (amount, expected_amount) = get_discounted_amounts(amount, expected_amount, discount)?;
+
+    // keep track of the fees so that they can be waived if the order is refunded
+    let (program_owner_fee, sponsor_fee, _integrator_fee) = get_fees(
+        &merchant_account,
+        &config_account,
+        possible_integrator_info.is_ok(),
     )?;
+    // record the store that the order was made at for per-store reporting
+    let data = match possible_store {
+        None => data,
+        Some((store, _store_account)) => set_order_store(data, store),
+    };
+    // record the referrer of the order so that it gets its share at withdrawal time
+    let data = match possible_referrer_info {
+        None => data,
+        Some(referrer_info) => {
+            let referrer_account = get_referrer_account(program_id, referrer_info, merchant_info)?;
+            set_order_referral(
+                data,
+                OrderReferral {
+                    referrer: Pubkey::new_from_array(referrer_account.wallet).to_string(),
+                    fee: referrer_account.referral_fee,
+                },
+            )
+        }
+    };
+
+    // the order of a merchant with instant settlement (if it can be) pays the
+    // merchant token accounts directly and is created already withdrawn
+    let is_instant = is_settled_instantly(&merchant_account, order_status, &data);
+    // the orders swept to the merchant set aside a bounty for the sweep crank
+    // out of the program owner fee
+    let sweep_bounty = if is_instant {
+        0
+    } else {
+        get_sweep_bounty(&merchant_account, program_owner_fee)
+    };
+    let data = set_order_fees(data, program_owner_fee - sweep_bounty, sponsor_fee);
+    if is_instant {
+        order_status = OrderStatus::Withdrawn as u8;
+    } else {
+        // count the order token accounts towards the open token accounts of the merchant
+        record_token_accounts(
+            program_id,
+            possible_stats_info,
+            &merchant_info.key.to_bytes(),
+            1 + other_mint_payments.len() as u64,
+            0,
+        )?;
+        // count the order towards the references of the merchant
+        update_merchant_references(merchant_info, true)?;
+    }
 
-    // next we are going to try and create a token account owned by the program
-    // but whose address is derived from the order account
-    // TODO: for subscriptions, should this use the subscription account as the base?
-    create_program_owned_associated_token_account(
+    // create order account
+    let order_account_size =
+        get_order_account_size(&order_id, &secret, &data) + get_order_items_size(&items);
+    create_order_account(
         program_id,
-        &[
-            signer_info.clone(),
-            order_info.clone(),
-            seller_token_info.clone(),
-            mint_info.clone(),
-            pda_info.clone(),
-            token_program_info.clone(),
-            system_program_info.clone(),
-            rent_sysvar_info.clone(),
-        ],
-        rent,
+        signer_info,
+        order_info,
+        system_program_info,
+        merchant_info.key,
+        &order_id,
+        order_account_size,
     )?;
 
-    // Transfer payment amount to associated seller token account...
+    if is_instant {
+        check_merchant_token(
+            &merchant_account,
+            seller_token_info,
+            mint_info,
+            token_program_info,
+        )?;
+    } else {
+        // next we are going to try and create a token account owned by the program
+        // but whose address is derived from the order account
+        // TODO: for subscriptions, should this use the subscription account as the base?
+        create_program_owned_associated_token_account(
+            program_id,
+            &[
+                signer_info.clone(),
+                order_info.clone(),
+                seller_token_info.clone(),
+                mint_info.clone(),
+                pda_info.clone(),
+                token_program_info.clone(),
+                system_program_info.clone(),
+                rent_sysvar_info.clone(),
+            ],
+            rent,
+        )?;
+    }
+    // the merchant token account of an instantly settled order may hold a balance
+    let balance = unpack_token_account(seller_token_info, token_program_info.key)?.amount;
+
+    // Transfer payment amount (and tip) to associated seller token account...
+    let payment_amount = amount
+        .checked_add(tip)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
     invoke(
-        &spl_token::instruction::transfer(
+        &token::transfer_checked(
             token_program_info.key,
             buyer_token_info.key,
+            mint_info,
             seller_token_info.key,
             signer_info.key,
-            &[&signer_info.key],
-            amount,
+            &[signer_info.key],
+            payment_amount,
         )
         .unwrap(),
         &[
             buyer_token_info.clone(),
+            mint_info.clone(),
             seller_token_info.clone(),
             signer_info.clone(),
             token_program_info.clone(),
         ],
     )?;
+    // the mint may withhold part of the payment as a transfer fee
+    let received =
+        unpack_token_account(seller_token_info, token_program_info.key)?.amount - balance;
+    let (amount, tip, transfer_fee) = get_net_amounts(amount, tip, received)?;
 
-    if Pubkey::new_from_array(merchant_account.sponsor) == Pubkey::from_str(PROGRAM_OWNER).unwrap()
+    // Transfer the amounts due in other mints to their own order token accounts...
+    for (other_mint_info, other_seller_token_info, other_buyer_token_info, other_amount) in
+        other_mint_payments.into_iter()
     {
-        // Transferring processing fee to the program owner...
+        if is_instant {
+            check_merchant_token(
+                &merchant_account,
+                other_seller_token_info,
+                other_mint_info,
+                token_program_info,
+            )?;
+        } else {
+            create_program_owned_associated_token_account(
+                program_id,
+                &[
+                    signer_info.clone(),
+                    order_info.clone(),
+                    other_seller_token_info.clone(),
+                    other_mint_info.clone(),
+                    pda_info.clone(),
+                    token_program_info.clone(),
+                    system_program_info.clone(),
+                    rent_sysvar_info.clone(),
+                ],
+                rent,
+            )?;
+        }
         invoke(
-            &system_instruction::transfer(
-                &signer_info.key,
-                program_owner_info.key,
-                merchant_account.fee,
-            ),
+            &token::transfer_checked(
+                token_program_info.key,
+                other_buyer_token_info.key,
+                other_mint_info,
+                other_seller_token_info.key,
+                signer_info.key,
+                &[signer_info.key],
+                other_amount,
+            )
+            .unwrap(),
             &[
+                other_buyer_token_info.clone(),
+                other_mint_info.clone(),
+                other_seller_token_info.clone(),
                 signer_info.clone(),
-                program_owner_info.clone(),
-                system_program_info.clone(),
+                token_program_info.clone(),
             ],
         )?;
-    } else {
-        // we need to pay both the program owner and the sponsor
-        let (program_owner_fee, sponsor_fee) = get_amounts(merchant_account.fee, SPONSOR_FEE);
-        // Transferring processing fee to the program owner and sponsor...
-        invoke(
-            &system_instruction::transfer(
-                &signer_info.key,
-                program_owner_info.key,
-                program_owner_fee,
-            ),
+    }
+
+    set_aside_sweep_bounty(
+        program_id,
+        accounts,
+        merchant_info,
+        signer_info,
+        system_program_info,
+        sweep_bounty,
+    )?;
+    pay_fees(
+        program_id,
+        &merchant_account,
+        &config_account,
+        signer_info,
+        &fee_accounts,
+        possible_integrator_info,
+        sweep_bounty,
+    )?;
+    // count the payment towards the fee tier of the merchant
+    record_merchant_volume(merchant_info, amount)?;
+
+    // count the payment towards the payment link that it was made through
+    if let Some((link_info, link_payer_info)) = possible_link_infos {
+        record_link_payment(
+            program_id,
             &[
                 signer_info.clone(),
-                program_owner_info.clone(),
+                link_info.clone(),
+                link_payer_info.clone(),
+                merchant_info.clone(),
+                mint_info.clone(),
                 system_program_info.clone(),
             ],
+            amount,
+            timestamp,
         )?;
-        invoke(
-            &system_instruction::transfer(&signer_info.key, sponsor_info.key, sponsor_fee),
-            &[
-                signer_info.clone(),
-                sponsor_info.clone(),
-                system_program_info.clone(),
-            ],
+    }
+
+    // add the payment to the total raised by the campaign
+    if let Some(campaign_info) = possible_campaign_info {
+        record_campaign_donation(program_id, campaign_info, merchant_info, mint_info, amount)?;
+    }
+
+    // mint a receipt of the payment to the buyer
+    if let Some(receipt_accounts) = possible_receipt_accounts {
+        let uri = get_receipt_uri(
+            &config_account,
+            merchant_info.key,
+            &order_id,
+            mint_info.key,
+            amount,
+            timestamp,
+        )?;
+        mint_receipt(
+            program_id,
+            &receipt_accounts,
+            signer_info,
+            pda_info,
+            system_program_info,
+            rent_sysvar_info,
+            ReceiptMetadata {
+                name: get_receipt_name(&order_id),
+                uri,
+            },
         )?;
     }
 
     // get the order account
     // TODO: ensure this account is not already initialized
     let mut order_account_data = order_info.try_borrow_mut_data()?;
+    // number the order in the order sequence of the merchant
+    let sequence = next_order_sequence(merchant_info)?;
     // Saving order information...
     let order = OrderAccount {
         discriminator: order_account_type,
-        status: OrderStatus::Paid as u8,
+        status: order_status,
         created: timestamp,
         modified: timestamp,
         merchant: merchant_info.key.to_bytes(),
         mint: mint_info.key.to_bytes(),
         token: seller_token_info.key.to_bytes(),
         payer: signer_info.key.to_bytes(),
-        expected_amount: amount,
+        expected_amount,
         paid_amount: amount,
+        tip,
+        transfer_fee,
+        withdrawn_amount: 0,
+        sequence,
+        items,
+        released_amount: 0,
+        created_slot: sysvars.slot()?,
+        sweep_bounty,
         order_id,
         secret,
         data,
+        version: ACCOUNT_VERSION,
     };
 
+    // an order paid when it has already expired could be reclaimed right away
+    if let Some(expires_at) = get_order_expiry(&order) {
+        if expires_at <= timestamp {
+            msg!("Error: Order expired at {:?}", expires_at);
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+    }
+
     order.pack(&mut order_account_data);
 
     // ensure order account is rent exempt
     if !rent.is_exempt(order_info.lamports(), order_account_size) {
         return Err(ProgramError::AccountNotRentExempt);
     }
+    // orders paid in installments are paid in full with their last installment
+    if order.status != OrderStatus::Pending as u8 {
+        emit_order_paid(order_info.key, &order);
+    }
+
+    if let Some(slot_hashes_info) = possible_slot_hashes_info {
+        let (slot, slot_hash) = get_recent_slot_hash(slot_hashes_info)?;
+        msg!(
+            "{}{}",
+            PAYMENT_ID_LOG_PREFIX,
+            get_payment_id(program_id, order_info.key, slot, &slot_hash)
+        );
+    }
 
     Ok(())
 }
@@ -331,19 +1002,103 @@ pub fn process_order(
 pub fn process_express_checkout(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    amount: u64,
-    order_id: String,
-    secret: String,
-    maybe_data: Option<String>,
+    sysvars: &dyn SysvarProvider,
+    order: OrderArgs,
+    tip: u64,
 ) -> ProgramResult {
-    process_order(
-        program_id,
-        accounts,
-        amount,
-        order_id,
-        secret,
-        maybe_data,
-        Option::None,
+    process_order(program_id, accounts, sysvars, order, tip, Option::None)?;
+    Ok(())
+}
+
+/// Process an express checkout paid for in SOL
+///
+/// The lamports are wrapped into a temporary WSOL token account which is used
+/// as the buyer token account and then closed once the order has been paid.
+pub fn process_express_checkout_sol(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    order: OrderArgs,
+    tip: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let _order_info = next_account_info(account_info_iter)?;
+    let _merchant_info = next_account_info(account_info_iter)?;
+    let _seller_token_info = next_account_info(account_info_iter)?;
+    let wsol_token_info = next_account_info(account_info_iter)?;
+    let _program_owner_info = next_account_info(account_info_iter)?;
+    let _sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let _pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure we are dealing with wrapped SOL
+    if *mint_info.key != spl_token::native_mint::id() {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    // the native mint belongs to the SPL Token program, Token-2022 has its own
+    if *token_program_info.key != spl_token::id() {
+        msg!("Error: SOL can only be wrapped with the SPL Token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    // create the temporary WSOL account holding the amount to be paid
+    let lamports = order
+        .amount
+        .checked_add(tip)
+        .and_then(|value| value.checked_add(rent.minimum_balance(TokenAccount::LEN)))
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    invoke(
+        &system_instruction::create_account(
+            signer_info.key,
+            wsol_token_info.key,
+            lamports,
+            TokenAccount::LEN as u64,
+            token_program_info.key,
+        ),
+        &[
+            signer_info.clone(),
+            wsol_token_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_account(
+            token_program_info.key,
+            wsol_token_info.key,
+            mint_info.key,
+            signer_info.key,
+        )?,
+        &[
+            wsol_token_info.clone(),
+            mint_info.clone(),
+            signer_info.clone(),
+            rent_sysvar_info.clone(),
+            token_program_info.clone(),
+        ],
+    )?;
+
+    process_order(program_id, accounts, sysvars, order, tip, Option::None)?;
+
+    // Close the temporary WSOL account and give back the rent
+    invoke(
+        &spl_token::instruction::close_account(
+            token_program_info.key,
+            wsol_token_info.key,
+            signer_info.key,
+            signer_info.key,
+            &[signer_info.key],
+        )?,
+        &[
+            wsol_token_info.clone(),
+            signer_info.clone(),
+            token_program_info.clone(),
+        ],
     )?;
     Ok(())
 }
@@ -351,18 +1106,234 @@ pub fn process_express_checkout(
 pub fn process_chain_checkout(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
     amount: u64,
     order_items: OrderItems,
     maybe_data: Option<String>,
 ) -> ProgramResult {
+    // the merchant account, which is checked when the order is processed
+    let merchant_info = accounts.get(2).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.try_borrow_data()?)?;
     process_order(
         program_id,
         accounts,
+        sysvars,
+        OrderArgs {
+            amount,
+            order_id: get_next_order_id(merchant_account.order_sequence)?,
+            secret: "".to_string(),
+            maybe_data,
+        },
+        0,
+        Some(order_items),
+    )?;
+    Ok(())
+}
+
+/// Process a checkout whose payment is split among several token accounts
+///
+/// Each recipient token account receives its share (in basis points) of the
+/// amount.  Any remainder left over after rounding goes to the first recipient.
+/// Since the payment is transferred straight to the recipients, the order is
+/// saved as already withdrawn.
+pub fn process_split_checkout(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    order: OrderArgs,
+    splits: Vec<u16>,
+) -> ProgramResult {
+    let OrderArgs {
         amount,
-        format!("{timestamp}", timestamp = Clock::get()?.unix_timestamp),
-        "".to_string(),
+        order_id,
+        secret,
         maybe_data,
-        Some(order_items),
+    } = order;
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let buyer_token_info = next_account_info(account_info_iter)?;
+    let program_owner_info = next_account_info(account_info_iter)?;
+    let sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let mut recipient_infos = vec![];
+    for _split in splits.iter() {
+        recipient_infos.push(next_account_info(account_info_iter)?);
+    }
+    let possible_integrator_info = next_account_info(account_info_iter);
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure the split schedule is valid
+    if splits.is_empty() || splits.len() > MAX_SPLIT_RECIPIENTS {
+        msg!(
+            "Error: A payment can be split among 1 to {:?} recipients",
+            MAX_SPLIT_RECIPIENTS
+        );
+        return Err(PaymentProcessorError::InvalidSplit.into());
+    }
+    let total_basis_points: u64 = splits.iter().map(|split| *split as u64).sum();
+    if total_basis_points != BASIS_POINTS {
+        msg!("Error: The splits must add up to {:?}", BASIS_POINTS);
+        return Err(PaymentProcessorError::InvalidSplit.into());
+    }
+
+    let config_account = get_program_config(program_id, config_info)?;
+    check_migration_window(&config_account, timestamp)?;
+    let fee_accounts = FeeAccounts {
+        program_owner: program_owner_info,
+        sponsor: sponsor_info,
+        system_program: system_program_info,
+    };
+    let merchant_account = order_checks(
+        program_id,
+        signer_info,
+        merchant_info,
+        buyer_token_info,
+        mint_info,
+        &fee_accounts,
+        &config_account,
+    )?;
+    check_buyer_not_blocked(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        signer_info.key,
     )?;
+    let possible_integrator_info = skip_blocked_buyer_address(
+        program_id,
+        account_info_iter,
+        merchant_info,
+        signer_info.key,
+        possible_integrator_info,
+    );
+
+    // work out how much each recipient gets
+    let mut split_amounts = vec![];
+    let mut recipients = vec![];
+    for (recipient_info, split) in recipient_infos.iter().zip(splits.iter()) {
+        // ensure recipient token account is owned by token program
+        check_token_account_mint(recipient_info, token_program_info.key, mint_info.key)?;
+        split_amounts.push(((amount as u128 * *split as u128) / BASIS_POINTS as u128) as u64);
+        recipients.push(json!([recipient_info.key.to_string(), split]));
+    }
+    let remainder = amount - split_amounts.iter().sum::<u64>();
+    split_amounts[0] += remainder;
+
+    // get data
+    let data = match maybe_data {
+        None => json!({ SPLITS: recipients }).to_string(),
+        Some(value) => {
+            let json_data: Value = match serde_json::from_str(&value) {
+                Err(_error) => return Err(PaymentProcessorError::InvalidOrderData.into()),
+                Ok(data) => data,
+            };
+            json!({
+                INITIAL: json_data,
+                SPLITS: recipients
+            })
+            .to_string()
+        }
+    };
+
+    // create order account
+    let order_account_size = get_order_account_size(&order_id, &secret, &data);
+    create_order_account(
+        program_id,
+        signer_info,
+        order_info,
+        system_program_info,
+        merchant_info.key,
+        &order_id,
+        order_account_size,
+    )?;
+
+    // Transferring each share of the payment to its recipient...
+    let mut received = 0;
+    for (recipient_info, split_amount) in recipient_infos.iter().zip(split_amounts.iter()) {
+        if *split_amount == 0 {
+            continue;
+        }
+        let balance = unpack_token_account(recipient_info, token_program_info.key)?.amount;
+        invoke(
+            &token::transfer_checked(
+                token_program_info.key,
+                buyer_token_info.key,
+                mint_info,
+                recipient_info.key,
+                signer_info.key,
+                &[signer_info.key],
+                *split_amount,
+            )
+            .unwrap(),
+            &[
+                buyer_token_info.clone(),
+                mint_info.clone(),
+                (*recipient_info).clone(),
+                signer_info.clone(),
+                token_program_info.clone(),
+            ],
+        )?;
+        // the mint may withhold part of each share as a transfer fee
+        let share = unpack_token_account(recipient_info, token_program_info.key)?.amount - balance;
+        received += share;
+    }
+    let (net_amount, _tip, transfer_fee) = get_net_amounts(amount, 0, received)?;
+
+    pay_fees(
+        program_id,
+        &merchant_account,
+        &config_account,
+        signer_info,
+        &fee_accounts,
+        possible_integrator_info,
+        0,
+    )?;
+    // count the payment towards the fee tier of the merchant
+    record_merchant_volume(merchant_info, net_amount)?;
+
+    // number the order in the order sequence of the merchant
+    let sequence = next_order_sequence(merchant_info)?;
+    // Saving order information...
+    let order = OrderAccount {
+        discriminator: Discriminator::OrderSplitCheckout as u8,
+        status: OrderStatus::Withdrawn as u8,
+        created: timestamp,
+        modified: timestamp,
+        merchant: merchant_info.key.to_bytes(),
+        mint: mint_info.key.to_bytes(),
+        token: recipient_infos[0].key.to_bytes(),
+        payer: signer_info.key.to_bytes(),
+        expected_amount: amount,
+        paid_amount: net_amount,
+        tip: 0,
+        transfer_fee,
+        withdrawn_amount: 0,
+        sequence,
+        items: vec![],
+        released_amount: 0,
+        created_slot: sysvars.slot()?,
+        sweep_bounty: 0,
+        order_id,
+        secret,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    order.pack(&mut order_info.try_borrow_mut_data()?);
+
+    // ensure order account is rent exempt
+    if !rent.is_exempt(order_info.lamports(), order_account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    emit_order_paid(order_info.key, &order);
+
     Ok(())
 }
@@ -0,0 +1,143 @@
+use crate::{
+    engine::associated_token::get_associated_token_address,
+    engine::constants::{BASIS_POINTS, MAX_PAYOUT_DESTINATIONS},
+    engine::token::unpack_token_account,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, PublicKey, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Get the payouts of a withdrawal of the merchant
+///
+/// Returns the token account that each wallet of the payout schedule is paid
+/// in, i.e. its associated token account for the mint, along with its share of
+/// the amount.  The token accounts are looked up among the accounts of the
+/// instruction, wherever they are.  The rounding remainder goes to the first
+/// wallet.  Merchants without a payout schedule have no payouts.
+pub fn get_payouts<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    merchant_account: &MerchantAccount,
+    mint: &PublicKey,
+    token_program_info: &AccountInfo<'b>,
+    amount: u64,
+) -> Result<Vec<(&'a AccountInfo<'b>, u64)>, ProgramError> {
+    let count = merchant_account.payout_count as usize;
+    let mut payouts = vec![];
+    for (wallet, share) in merchant_account.payout_wallets[..count]
+        .iter()
+        .zip(merchant_account.payout_shares[..count].iter())
+    {
+        let wallet = Pubkey::new_from_array(*wallet);
+        let payout_token_address = get_associated_token_address(
+            &wallet,
+            token_program_info.key,
+            &Pubkey::new_from_array(*mint),
+        );
+        let payout_token_info = match accounts
+            .iter()
+            .find(|account_info| *account_info.key == payout_token_address)
+        {
+            None => {
+                msg!(
+                    "Error: The token account of the payout wallet {:?} is missing",
+                    wallet
+                );
+                return Err(ProgramError::NotEnoughAccountKeys);
+            }
+            Some(value) => value,
+        };
+        let payout_token_data = unpack_token_account(payout_token_info, token_program_info.key)?;
+        if payout_token_data.owner != wallet {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if payout_token_data.mint.to_bytes() != *mint {
+            return Err(PaymentProcessorError::WrongMint.into());
+        }
+        payouts.push((
+            payout_token_info,
+            ((amount as u128 * *share as u128) / BASIS_POINTS as u128) as u64,
+        ));
+    }
+    let remainder = amount - payouts.iter().map(|payout| payout.1).sum::<u64>();
+    if let Some(first_payout) = payouts.first_mut() {
+        first_payout.1 += remainder;
+    }
+    Ok(payouts)
+}
+
+/// Set Payout Schedule
+///
+/// Lets the merchant owner split its withdrawals among several wallets, each
+/// getting a share (in basis points) of the amount withdrawn.  The shares have
+/// to add up to the whole amount.  An empty schedule has withdrawals pay a
+/// single token account again.
+pub fn process_set_payout_schedule(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    payouts: Vec<(Pubkey, u64)>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the payout schedule
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    if payouts.len() > MAX_PAYOUT_DESTINATIONS {
+        msg!(
+            "Error: A payout schedule has at most {:?} wallets",
+            MAX_PAYOUT_DESTINATIONS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    for (index, (wallet, share)) in payouts.iter().enumerate() {
+        if *share == 0
+            || *share > BASIS_POINTS
+            || payouts[..index].iter().any(|payout| payout.0 == *wallet)
+        {
+            msg!("Error: Each wallet of the payout schedule must get a share once");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    }
+    if !payouts.is_empty() && payouts.iter().map(|payout| payout.1).sum::<u64>() != BASIS_POINTS {
+        msg!(
+            "Error: The shares of the payout schedule must add up to {:?} basis points",
+            BASIS_POINTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Updating merchant payout schedule...
+    merchant_account.payout_count = payouts.len() as u64;
+    merchant_account.payout_wallets = [[0; 32]; MAX_PAYOUT_DESTINATIONS];
+    merchant_account.payout_shares = [0; MAX_PAYOUT_DESTINATIONS];
+    for (index, (wallet, share)) in payouts.iter().enumerate() {
+        merchant_account.payout_wallets[index] = wallet.to_bytes();
+        merchant_account.payout_shares[index] = *share;
+    }
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use crate::{
+    engine::associated_token::create_associated_token_account,
+    engine::constants::{
+        MAX_RECEIPT_NAME_LEN, MAX_RECEIPT_URI_LEN, PDA_SEED, RECEIPT_SYMBOL, RECEIPT_URI,
+        TOKEN_METADATA_PROGRAM_ID,
+    },
+    engine::validation::{check_pda, check_signer},
+    state::ProgramConfigAccount,
+};
+use borsh::BorshSerialize;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::Value;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction, sysvar,
+    sysvar::{rent::Rent, Sysvar},
+};
+use std::str::FromStr;
+
+/// the CreateMetadataAccountV3 instruction of the token metadata program
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+/// the CreateMasterEditionV3 instruction of the token metadata program
+const CREATE_MASTER_EDITION_V3: u8 = 17;
+
+/// Get the id of the Metaplex token metadata program
+pub fn get_token_metadata_program_id() -> Pubkey {
+    Pubkey::from_str(TOKEN_METADATA_PROGRAM_ID).unwrap()
+}
+
+/// Get the address of the metadata account of a mint
+pub fn get_metadata_address(mint: &Pubkey) -> Pubkey {
+    let token_metadata_program_id = get_token_metadata_program_id();
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            &token_metadata_program_id.to_bytes(),
+            &mint.to_bytes(),
+        ],
+        &token_metadata_program_id,
+    )
+    .0
+}
+
+/// Get the address of the master edition account of a mint
+pub fn get_master_edition_address(mint: &Pubkey) -> Pubkey {
+    let token_metadata_program_id = get_token_metadata_program_id();
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            &token_metadata_program_id.to_bytes(),
+            &mint.to_bytes(),
+            b"edition",
+        ],
+        &token_metadata_program_id,
+    )
+    .0
+}
+
+/// The metadata of a token, as the token metadata program expects it
+///
+/// The collection and uses of a receipt are never set, and None serializes the
+/// same whatever the type of the option.
+#[derive(BorshSerialize)]
+struct DataV2 {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<Vec<Creator>>,
+    collection: Option<u8>,
+    uses: Option<u8>,
+}
+
+#[derive(BorshSerialize)]
+struct Creator {
+    address: Pubkey,
+    verified: bool,
+    share: u8,
+}
+
+#[derive(BorshSerialize)]
+struct CreateMetadataAccountArgsV3 {
+    data: DataV2,
+    is_mutable: bool,
+    /// never set, like the collection
+    collection_details: Option<u8>,
+}
+
+#[derive(BorshSerialize)]
+struct CreateMasterEditionArgs {
+    max_supply: Option<u64>,
+}
+
+/// The accounts that a checkout needs to mint a receipt NFT to the buyer
+pub struct ReceiptAccounts<'a, 'b> {
+    pub token_metadata_program: &'a AccountInfo<'b>,
+    /// the new mint of the receipt, a signer
+    pub mint: &'a AccountInfo<'b>,
+    pub metadata: &'a AccountInfo<'b>,
+    pub master_edition: &'a AccountInfo<'b>,
+    /// the associated token account of the buyer for the receipt mint
+    pub token: &'a AccountInfo<'b>,
+    pub associated_token_program: &'a AccountInfo<'b>,
+    /// the SPL Token program (receipts are not minted with Token-2022)
+    pub token_program: &'a AccountInfo<'b>,
+}
+
+/// Get the receipt accounts that follow the token metadata program in a checkout
+pub fn next_receipt_accounts<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    token_metadata_program_info: &'a AccountInfo<'b>,
+    account_info_iter: &mut I,
+) -> Result<ReceiptAccounts<'a, 'b>, ProgramError> {
+    Ok(ReceiptAccounts {
+        token_metadata_program: token_metadata_program_info,
+        mint: next_account_info(account_info_iter)?,
+        metadata: next_account_info(account_info_iter)?,
+        master_edition: next_account_info(account_info_iter)?,
+        token: next_account_info(account_info_iter)?,
+        associated_token_program: next_account_info(account_info_iter)?,
+        token_program: next_account_info(account_info_iter)?,
+    })
+}
+
+/// Get the URI of the receipt of an order
+///
+/// This is the receipt URI set in the program config data, with the merchant,
+/// the order id, the mint, the amount paid and the time of the payment added
+/// as query parameters e.g. {"receipt_uri": "https://example.com/receipt"}
+pub fn get_receipt_uri(
+    config_account: &ProgramConfigAccount,
+    merchant: &Pubkey,
+    order_id: &str,
+    mint: &Pubkey,
+    amount: u64,
+    timestamp: UnixTimestamp,
+) -> Result<String, ProgramError> {
+    let config_json_data: Value = match serde_json::from_str(&config_account.data) {
+        Err(_error) => Value::Null,
+        Ok(data) => data,
+    };
+    let receipt_uri = match config_json_data[RECEIPT_URI].as_str() {
+        None => {
+            msg!("Error: The program config does not set a receipt URI");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Some(value) => value,
+    };
+    let uri = format!(
+        "{}?merchant={}&order_id={}&mint={}&amount={}&timestamp={}",
+        receipt_uri,
+        merchant,
+        utf8_percent_encode(order_id, NON_ALPHANUMERIC),
+        mint,
+        amount,
+        timestamp
+    );
+    if uri.len() > MAX_RECEIPT_URI_LEN {
+        msg!(
+            "Error: The receipt URI must be at most {:?} bytes long",
+            MAX_RECEIPT_URI_LEN
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(uri)
+}
+
+/// Get the name of the receipt of an order
+pub fn get_receipt_name(order_id: &str) -> String {
+    let mut name = format!("Receipt {}", order_id);
+    while name.len() > MAX_RECEIPT_NAME_LEN {
+        name.pop();
+    }
+    name
+}
+
+/// The name and uri of a receipt NFT (see get_receipt_name and get_receipt_uri)
+pub struct ReceiptMetadata {
+    pub name: String,
+    pub uri: String,
+}
+
+/// Mint a receipt NFT to the buyer
+///
+/// The receipt is a non-fungible token of a new mint with a master edition that
+/// cannot be printed.  Its metadata can't be changed and has the address derived
+/// from this program as its only (verified) creator, which proves that the
+/// receipt was minted by this program.
+pub fn mint_receipt<'a>(
+    program_id: &Pubkey,
+    receipt: &ReceiptAccounts<'_, 'a>,
+    buyer_info: &AccountInfo<'a>,
+    pda_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent_sysvar_info: &AccountInfo<'a>,
+    metadata: ReceiptMetadata,
+) -> ProgramResult {
+    let ReceiptMetadata { name, uri } = metadata;
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    if *receipt.token_program.key != spl_token::id() {
+        msg!("Error: Receipts are only minted with the SPL Token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_signer(receipt.mint)?;
+    if *receipt.metadata.key != get_metadata_address(receipt.mint.key)
+        || *receipt.master_edition.key != get_master_edition_address(receipt.mint.key)
+    {
+        msg!("Error: Receipt metadata address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let signer_seeds: &[&[_]] = &[PDA_SEED, &[pda_nonce]];
+
+    // Creating the receipt mint...
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    invoke(
+        &system_instruction::create_account(
+            buyer_info.key,
+            receipt.mint.key,
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        &[
+            buyer_info.clone(),
+            receipt.mint.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            receipt.mint.key,
+            &pda,
+            Some(&pda),
+            0,
+        )?,
+        &[
+            receipt.mint.clone(),
+            rent_sysvar_info.clone(),
+            receipt.token_program.clone(),
+        ],
+    )?;
+
+    // Minting the receipt to the buyer...
+    create_associated_token_account(&[
+        buyer_info.clone(),
+        receipt.token.clone(),
+        buyer_info.clone(),
+        receipt.mint.clone(),
+        system_program_info.clone(),
+        receipt.token_program.clone(),
+        rent_sysvar_info.clone(),
+        receipt.associated_token_program.clone(),
+    ])?;
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            &spl_token::id(),
+            receipt.mint.key,
+            receipt.token.key,
+            &pda,
+            &[],
+            1,
+        )?,
+        &[
+            receipt.mint.clone(),
+            receipt.token.clone(),
+            pda_info.clone(),
+            receipt.token_program.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    // Creating the receipt metadata...
+    let mut data = vec![CREATE_METADATA_ACCOUNT_V3];
+    data.extend(
+        CreateMetadataAccountArgsV3 {
+            data: DataV2 {
+                name,
+                symbol: String::from(RECEIPT_SYMBOL),
+                uri,
+                seller_fee_basis_points: 0,
+                creators: Some(vec![Creator {
+                    address: pda,
+                    verified: true,
+                    share: 100,
+                }]),
+                collection: None,
+                uses: None,
+            },
+            is_mutable: false,
+            collection_details: None,
+        }
+        .try_to_vec()?,
+    );
+    invoke_signed(
+        &Instruction {
+            program_id: *receipt.token_metadata_program.key,
+            accounts: vec![
+                AccountMeta::new(*receipt.metadata.key, false),
+                AccountMeta::new_readonly(*receipt.mint.key, false),
+                AccountMeta::new_readonly(pda, true),
+                AccountMeta::new(*buyer_info.key, true),
+                AccountMeta::new_readonly(pda, true),
+                AccountMeta::new_readonly(solana_program::system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+            data,
+        },
+        &[
+            receipt.metadata.clone(),
+            receipt.mint.clone(),
+            pda_info.clone(),
+            buyer_info.clone(),
+            system_program_info.clone(),
+            rent_sysvar_info.clone(),
+            receipt.token_metadata_program.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    // Creating the receipt master edition (which takes over the mint authority)...
+    let mut data = vec![CREATE_MASTER_EDITION_V3];
+    data.extend(
+        CreateMasterEditionArgs {
+            max_supply: Some(0),
+        }
+        .try_to_vec()?,
+    );
+    invoke_signed(
+        &Instruction {
+            program_id: *receipt.token_metadata_program.key,
+            accounts: vec![
+                AccountMeta::new(*receipt.master_edition.key, false),
+                AccountMeta::new(*receipt.mint.key, false),
+                AccountMeta::new_readonly(pda, true),
+                AccountMeta::new_readonly(pda, true),
+                AccountMeta::new(*buyer_info.key, true),
+                AccountMeta::new(*receipt.metadata.key, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(solana_program::system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+            ],
+            data,
+        },
+        &[
+            receipt.master_edition.clone(),
+            receipt.mint.clone(),
+            pda_info.clone(),
+            buyer_info.clone(),
+            receipt.metadata.clone(),
+            receipt.token_program.clone(),
+            system_program_info.clone(),
+            rent_sysvar_info.clone(),
+            receipt.token_metadata_program.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use crate::{
+    engine::common::{
+        get_order_balance, is_order_expired, transfer_order_tokens, update_merchant_references,
+    },
+    engine::constants::PDA_SEED,
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sweep::return_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        self, check_token_program, find_mint, has_withheld_transfer_fees, invoke_transfer,
+    },
+    engine::validation::{check_pda, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, OrderAccount, OrderStatus, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Reclaim Expired Order
+///
+/// Refunds the amount paid for an order that was not withdrawn before it expired
+pub fn process_reclaim_expired_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_token_info = next_account_info(account_info_iter)?;
+    let refund_token_info = next_account_info(account_info_iter)?;
+    let account_to_receive_sol_refund_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure order account is owned by this program
+    check_program_owned(program_id, order_info, "order")?;
+    // ensure token accounts are owned by token program
+    check_token_program(token_program_info)?;
+    if *order_token_info.owner != *token_program_info.key {
+        msg!("Error: Order token account must be owned by token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *refund_token_info.owner != *token_program_info.key {
+        msg!("Error: Refund token account must be owned by token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure the signer is the order payer
+    if signer_info.key.to_bytes() != order_account.payer {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // ensure the merchant account is the one of the order
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the order payment token account is the right one
+    if order_token_info.key.to_bytes() != order_account.token {
+        msg!("Error: Incorrect order token account");
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    // ensure order is still holding the payment (or the installments paid so far)
+    if order_account.status != OrderStatus::Paid as u8
+        && order_account.status != OrderStatus::Pending as u8
+    {
+        return Err(PaymentProcessorError::AlreadyWithdrawn.into());
+    }
+    // ensure the order has expired
+    if !is_order_expired(&order_account, timestamp) {
+        return Err(PaymentProcessorError::OrderNotExpired.into());
+    }
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
+    // Transferring payment (and tip) back to the payer...
+    invoke_transfer(
+        token_program_info,
+        order_token_info,
+        possible_mint_info,
+        refund_token_info,
+        pda_info,
+        get_order_balance(&order_account)?,
+        &[&[PDA_SEED, &[pda_nonce]]],
+    )?;
+    let mut closed = 0;
+    // Close the order token account since it will never be needed again
+    if !has_withheld_transfer_fees(order_token_info) {
+        invoke_signed(
+            &token::close_account(
+                token_program_info.key,
+                order_token_info.key,
+                account_to_receive_sol_refund_info.key,
+                &pda,
+                &[&pda],
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                order_token_info.clone(),
+                account_to_receive_sol_refund_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        closed += 1;
+    }
+    // Transferring the amounts paid in other mints (if any) back to the payer...
+    closed += transfer_order_tokens(
+        &order_account,
+        account_info_iter,
+        Option::None,
+        account_to_receive_sol_refund_info,
+        pda_info,
+        token_program_info,
+        pda_nonce,
+    )?;
+    // count the closed order token accounts towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        find_merchant_stats(program_id, account_info_iter),
+        &order_account.merchant,
+        0,
+        closed,
+    )?;
+
+    // the expired order no longer counts towards the references of the merchant
+    update_merchant_references(merchant_info, false)?;
+
+    // the bounty the order set aside for the sweep crank goes back to the program owner
+    return_sweep_bounty(program_id, accounts, &mut order_account)?;
+    // Updating order account information...
+    order_account.status = OrderStatus::Expired as u8;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    Ok(())
+}
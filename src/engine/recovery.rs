@@ -0,0 +1,503 @@
+use crate::{
+    engine::common::{get_program_account_discriminator, transfer_sol},
+    engine::constants::{
+        ACCOUNT_VERSION, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS, RECOVERY, TRANSFER,
+    },
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, MerchantAccount, MerchantRecoveryAccount, MerchantTransferAccount,
+        Serdes,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the recovery account of a merchant account
+pub fn get_merchant_recovery_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECOVERY.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Get the address of the transfer account of a merchant account
+pub fn get_merchant_transfer_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TRANSFER.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Get the merchant account
+fn get_merchant_account(
+    program_id: &Pubkey,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<MerchantAccount, ProgramError> {
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(merchant_account)
+}
+
+/// Get the recovery account of a merchant account
+fn get_merchant_recovery_account(
+    program_id: &Pubkey,
+    recovery_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<MerchantRecoveryAccount, ProgramError> {
+    check_program_owned(program_id, recovery_info, "merchant recovery")?;
+    let recovery_account = MerchantRecoveryAccount::unpack(&recovery_info.data.borrow())?;
+    if recovery_account.discriminator != Discriminator::MerchantRecovery as u8 {
+        msg!("Error: Invalid merchant recovery account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if merchant_info.key.to_bytes() != recovery_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(recovery_account)
+}
+
+/// Split the recovery account of a merchant off the end of the accounts of an
+/// instruction, if it is there
+pub fn split_merchant_recovery<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> (&'a [AccountInfo<'b>], Option<&'a AccountInfo<'b>>) {
+    match accounts.split_last() {
+        Some((last_info, other_accounts))
+            if get_program_account_discriminator(program_id, last_info)
+                == Some(Discriminator::MerchantRecovery as u8) =>
+        {
+            (other_accounts, Some(last_info))
+        }
+        _ => (accounts, None),
+    }
+}
+
+/// Record that the merchant owner is active
+///
+/// Any instruction signed by the merchant owner that carries the recovery
+/// account of the merchant shows that the owner still holds the owner key: the
+/// inactivity timeout starts over and a recovery that was started is cancelled,
+/// as when sending `SetMerchantRecovery` again.
+pub fn record_owner_activity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    recovery_info: &AccountInfo,
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let mut recovery_account = MerchantRecoveryAccount::unpack(&recovery_info.data.borrow())?;
+    let merchant_info = match accounts
+        .iter()
+        .find(|account_info| account_info.key.to_bytes() == recovery_account.merchant)
+    {
+        None => {
+            msg!("Error: The merchant account of the merchant recovery account is missing");
+            return Err(PaymentProcessorError::WrongMerchant.into());
+        }
+        Some(merchant_info) => merchant_info,
+    };
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    // ensure only the merchant owner shows activity
+    if !accounts.iter().any(|account_info| {
+        account_info.is_signer && account_info.key.to_bytes() == merchant_account.owner
+    }) {
+        msg!("Error: Only the merchant owner can show that they are active");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if recovery_account.recovery_started > 0 {
+        msg!("SolPayments: Merchant recovery cancelled");
+    }
+    recovery_account.last_active = sysvars.unix_timestamp()?;
+    recovery_account.recovery_started = 0;
+    recovery_account.pack(&mut recovery_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Remove the operators and the payout schedule set up by the owner of a merchant
+/// account, which is about to change hands
+fn reset_owner_settings(merchant_account: &mut MerchantAccount) {
+    merchant_account.operator_count = 0;
+    merchant_account.operators = [[0; 32]; MAX_OPERATORS];
+    merchant_account.payout_count = 0;
+    merchant_account.payout_wallets = [[0; 32]; MAX_PAYOUT_DESTINATIONS];
+    merchant_account.payout_shares = [0; MAX_PAYOUT_DESTINATIONS];
+}
+
+/// Set Merchant Recovery
+///
+/// Lets the merchant owner set the key that may take over the merchant account
+/// if the owner key is lost.  Sending this again, or any other instruction
+/// signed by the owner that carries the recovery account, shows that the owner
+/// still holds the owner key: the inactivity timeout starts over and a recovery
+/// that was started is cancelled.
+pub fn process_set_merchant_recovery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    recovery_key: Pubkey,
+    timeout: i64,
+    challenge_window: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let recovery_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let merchant_account = get_merchant_account(program_id, merchant_info)?;
+    // ensure only the merchant owner can set the recovery
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if timeout < 0 || challenge_window < 0 {
+        msg!("Error: The timeout and the challenge window cannot be negative");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if *recovery_info.owner != *program_id {
+        let (recovery_address, bump_seed) =
+            get_merchant_recovery_address(program_id, merchant_info.key);
+        if recovery_address != *recovery_info.key {
+            msg!("Error: Merchant recovery address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[RECOVERY.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = MerchantRecoveryAccount::MIN_LEN;
+        // Fund the merchant recovery account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                recovery_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                recovery_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the merchant recovery account
+        invoke_signed(
+            &system_instruction::allocate(recovery_info.key, account_size as u64),
+            &[recovery_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the merchant recovery account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(recovery_info.key, program_id),
+            &[recovery_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(recovery_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+    } else {
+        let recovery_account =
+            get_merchant_recovery_account(program_id, recovery_info, merchant_info)?;
+        if recovery_account.recovery_started > 0 {
+            msg!("SolPayments: Merchant recovery cancelled");
+        }
+    }
+
+    // Saving merchant recovery information...
+    let recovery = MerchantRecoveryAccount {
+        discriminator: Discriminator::MerchantRecovery as u8,
+        merchant: merchant_info.key.to_bytes(),
+        recovery_key: recovery_key.to_bytes(),
+        timeout,
+        challenge_window,
+        last_active: timestamp,
+        recovery_started: 0,
+        version: ACCOUNT_VERSION,
+    };
+    recovery.pack(&mut recovery_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Start Merchant Recovery
+///
+/// Lets the recovery key start taking over the merchant account once the owner
+/// has been inactive for the timeout.  The owner can still cancel the recovery
+/// during the challenge window.
+pub fn process_start_merchant_recovery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let recovery_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    get_merchant_account(program_id, merchant_info)?;
+    let mut recovery_account =
+        get_merchant_recovery_account(program_id, recovery_info, merchant_info)?;
+    // ensure only the recovery key can start the recovery
+    if signer_info.key.to_bytes() != recovery_account.recovery_key {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if recovery_account.recovery_started > 0 {
+        msg!("Error: The merchant recovery was already started");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if timestamp < recovery_account.last_active + recovery_account.timeout {
+        msg!(
+            "Error: The merchant owner was active until {:?}",
+            recovery_account.last_active
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Starting the challenge window...
+    recovery_account.recovery_started = timestamp;
+    recovery_account.pack(&mut recovery_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Complete Merchant Recovery
+///
+/// Makes the recovery key the owner of the merchant account once the challenge
+/// window has passed without the owner cancelling the recovery.  The operators
+/// and the payout schedule set up by the previous owner are removed, since they
+/// may be what the owner lost control of.
+pub fn process_complete_merchant_recovery(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let recovery_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut merchant_account = get_merchant_account(program_id, merchant_info)?;
+    let mut recovery_account =
+        get_merchant_recovery_account(program_id, recovery_info, merchant_info)?;
+    // ensure only the recovery key can complete the recovery
+    if signer_info.key.to_bytes() != recovery_account.recovery_key {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if recovery_account.recovery_started == 0 {
+        msg!("Error: The merchant recovery was not started");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if timestamp < recovery_account.recovery_started + recovery_account.challenge_window {
+        msg!(
+            "Error: The merchant recovery can be cancelled until {:?}",
+            recovery_account.recovery_started + recovery_account.challenge_window
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Handing the merchant account over to the recovery key...
+    merchant_account.owner = recovery_account.recovery_key;
+    reset_owner_settings(&mut merchant_account);
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+    // the new owner is active, and sets a recovery key of their own
+    recovery_account.last_active = timestamp;
+    recovery_account.recovery_started = 0;
+    recovery_account.pack(&mut recovery_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Propose Merchant Transfer
+///
+/// Lets the merchant owner propose to hand the merchant account over to a new
+/// owner e.g. to rotate wallets.  Nothing changes until the new owner accepts.
+/// Sending this again replaces the proposal.
+pub fn process_propose_merchant_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    new_owner: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let transfer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let merchant_account = get_merchant_account(program_id, merchant_info)?;
+    // ensure only the merchant owner can propose a transfer
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+
+    if *transfer_info.owner != *program_id {
+        let (transfer_address, bump_seed) =
+            get_merchant_transfer_address(program_id, merchant_info.key);
+        if transfer_address != *transfer_info.key {
+            msg!("Error: Merchant transfer address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[TRANSFER.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = MerchantTransferAccount::MIN_LEN;
+        // Fund the merchant transfer account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                transfer_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                transfer_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the merchant transfer account
+        invoke_signed(
+            &system_instruction::allocate(transfer_info.key, account_size as u64),
+            &[transfer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the merchant transfer account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(transfer_info.key, program_id),
+            &[transfer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(transfer_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+    } else {
+        let transfer_account = MerchantTransferAccount::unpack(&transfer_info.data.borrow())?;
+        if transfer_account.discriminator != Discriminator::MerchantTransfer as u8
+            || transfer_account.merchant != merchant_info.key.to_bytes()
+        {
+            msg!("Error: Invalid merchant transfer account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // Saving merchant transfer information...
+    let transfer_account = MerchantTransferAccount {
+        discriminator: Discriminator::MerchantTransfer as u8,
+        merchant: merchant_info.key.to_bytes(),
+        new_owner: new_owner.to_bytes(),
+        proposed: timestamp,
+        version: ACCOUNT_VERSION,
+    };
+    transfer_account.pack(&mut transfer_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Accept Merchant Transfer
+///
+/// Makes the new owner proposed by the merchant owner the owner of the merchant
+/// account, once the new owner signs.  The orders and subscriptions of the
+/// merchant are untouched.  The operators, the payout schedule and the recovery
+/// set up by the previous owner are removed, so that the previous owner keeps no
+/// way back in and is no longer paid.
+/// The rent of the transfer and recovery accounts goes back to the previous
+/// owner.
+pub fn process_accept_merchant_transfer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let transfer_info = next_account_info(account_info_iter)?;
+    let previous_owner_info = next_account_info(account_info_iter)?;
+    let recovery_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut merchant_account = get_merchant_account(program_id, merchant_info)?;
+    let (recovery_address, _bump_seed) =
+        get_merchant_recovery_address(program_id, merchant_info.key);
+    if recovery_address != *recovery_info.key {
+        msg!("Error: Merchant recovery address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    check_program_owned(program_id, transfer_info, "merchant transfer")?;
+    let mut transfer_account = MerchantTransferAccount::unpack(&transfer_info.data.borrow())?;
+    if transfer_account.discriminator != Discriminator::MerchantTransfer as u8 {
+        msg!("Error: Invalid merchant transfer account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if merchant_info.key.to_bytes() != transfer_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure only the proposed new owner can accept the transfer
+    if signer_info.key.to_bytes() != transfer_account.new_owner {
+        msg!("Error: Only the proposed new owner can accept the merchant transfer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if previous_owner_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+
+    // Handing the merchant account over to the new owner...
+    merchant_account.owner = transfer_account.new_owner;
+    reset_owner_settings(&mut merchant_account);
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+    // Closing the merchant recovery account, if the previous owner set one up...
+    if *recovery_info.owner == *program_id {
+        let mut recovery_account =
+            get_merchant_recovery_account(program_id, recovery_info, merchant_info)?;
+        recovery_account.discriminator = Discriminator::Closed as u8;
+        recovery_account.pack(&mut recovery_info.data.borrow_mut());
+        transfer_sol(
+            recovery_info.clone(),
+            previous_owner_info.clone(),
+            recovery_info.lamports(),
+        )?;
+    }
+    // Closing the merchant transfer account...
+    transfer_account.discriminator = Discriminator::Closed as u8;
+    transfer_account.pack(&mut transfer_info.data.borrow_mut());
+    transfer_sol(
+        transfer_info.clone(),
+        previous_owner_info.clone(),
+        transfer_info.lamports(),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use crate::{
+    engine::associated_token::get_associated_token_address,
+    engine::constants::{ACCOUNT_VERSION, BASIS_POINTS, ORDER_REFERRAL, REFERRER},
+    engine::json::OrderReferral,
+    engine::token::unpack_token_account,
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantAccount, OrderAccount, ReferrerAccount, Serdes},
+};
+use serde_json::Value;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+use std::str::FromStr;
+
+/// Get the address of the referrer account of a wallet for a merchant
+pub fn get_referrer_address(
+    program_id: &Pubkey,
+    merchant: &Pubkey,
+    wallet: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            REFERRER.as_bytes(),
+            &merchant.to_bytes(),
+            &wallet.to_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Get the referrer account of a merchant
+pub fn get_referrer_account(
+    program_id: &Pubkey,
+    referrer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<ReferrerAccount, ProgramError> {
+    check_program_owned(program_id, referrer_info, "referrer")?;
+    let referrer_account = ReferrerAccount::unpack(&referrer_info.data.borrow())?;
+    if referrer_account.discriminator != Discriminator::Referrer as u8 {
+        msg!("Error: Invalid referrer account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the referrer was taken on by this merchant
+    if merchant_info.key.to_bytes() != referrer_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(referrer_account)
+}
+
+/// Record the referrer of an order in the order data
+///
+/// The referrer is only recorded if the order data is a JSON object
+pub fn set_order_referral(data: String, order_referral: OrderReferral) -> String {
+    match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            json_data.insert(
+                String::from(ORDER_REFERRAL),
+                serde_json::to_value(order_referral).unwrap(),
+            );
+            Value::Object(json_data).to_string()
+        }
+        _ => data,
+    }
+}
+
+/// Get the referrer (if any) of an order
+pub fn get_order_referral(order_account: &OrderAccount) -> Option<OrderReferral> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    serde_json::from_value(json_data[ORDER_REFERRAL].clone()).ok()
+}
+
+/// Get the referral share of an amount paid
+pub fn get_referral_amount(amount: u64, order_referral: &OrderReferral) -> u64 {
+    ((amount as u128 * order_referral.fee as u128) / BASIS_POINTS as u128) as u64
+}
+
+/// Find the token account that the referral share of an order goes to
+///
+/// The token account is looked up among the accounts of the instruction,
+/// wherever it is.
+pub fn find_referral_token<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    order_referral: &OrderReferral,
+    order_account: &OrderAccount,
+    token_program_info: &AccountInfo<'b>,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let wallet = match Pubkey::from_str(&order_referral.referrer) {
+        Err(_error) => return Err(ProgramError::InvalidAccountData),
+        Ok(value) => value,
+    };
+    let referral_token_address = get_associated_token_address(
+        &wallet,
+        token_program_info.key,
+        &Pubkey::new_from_array(order_account.mint),
+    );
+    let referral_token_info = match accounts
+        .iter()
+        .find(|account_info| *account_info.key == referral_token_address)
+    {
+        None => {
+            msg!("Error: The token account of the referrer is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(value) => value,
+    };
+    let referral_token_data = unpack_token_account(referral_token_info, token_program_info.key)?;
+    if referral_token_data.owner != wallet {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if referral_token_data.mint.to_bytes() != order_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    Ok(referral_token_info)
+}
+
+/// Set Referrer
+///
+/// Lets the merchant owner take on a wallet as a referrer (affiliate) of the
+/// merchant, or change the share of the orders it refers that it gets.  Only
+/// wallets taken on by the merchant can be recorded as the referrer of an order,
+/// so buyers cannot refer themselves.
+pub fn process_set_referrer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    wallet: Pubkey,
+    referral_fee: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let referrer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can take on referrers
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if referral_fee > BASIS_POINTS {
+        msg!(
+            "Error: The referral fee cannot be more than {:?}",
+            BASIS_POINTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    if *referrer_info.owner != *program_id {
+        let (referrer_address, bump_seed) =
+            get_referrer_address(program_id, merchant_info.key, &wallet);
+        if referrer_address != *referrer_info.key {
+            msg!("Error: Referrer address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let wallet_bytes = wallet.to_bytes();
+        let signer_seeds: &[&[_]] = &[
+            REFERRER.as_bytes(),
+            &merchant_bytes,
+            &wallet_bytes,
+            &[bump_seed],
+        ];
+        let account_size = ReferrerAccount::MIN_LEN;
+        // Fund the referrer account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                referrer_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                referrer_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the referrer account
+        invoke_signed(
+            &system_instruction::allocate(referrer_info.key, account_size as u64),
+            &[referrer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the referrer account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(referrer_info.key, program_id),
+            &[referrer_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(referrer_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+    } else {
+        let referrer_account = get_referrer_account(program_id, referrer_info, merchant_info)?;
+        if referrer_account.wallet != wallet.to_bytes() {
+            msg!("Error: The referrer account is not the one of this wallet");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // Saving referrer information...
+    let referrer_account = ReferrerAccount {
+        discriminator: Discriminator::Referrer as u8,
+        merchant: merchant_info.key.to_bytes(),
+        wallet: wallet.to_bytes(),
+        referral_fee,
+        version: ACCOUNT_VERSION,
+    };
+    referrer_account.pack(&mut referrer_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
@@ -0,0 +1,315 @@
+use crate::{
+    engine::common::{get_order_balance, transfer_order_tokens, update_merchant_references},
+    engine::constants::{ACCOUNT_VERSION, PDA_SEED, REFUND},
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sweep::return_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        self, check_token_program, find_mint, has_withheld_transfer_fees, invoke_transfer,
+    },
+    engine::validation::{check_pda, check_program_owned, check_signer, check_token_account_mint},
+    error::PaymentProcessorError,
+    events::{emit_event, PaymentProcessorEvent},
+    state::{
+        Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, RefundCodeAccount,
+        Serdes,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::hashv,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the refund code account of an order
+pub fn get_refund_code_address(program_id: &Pubkey, order: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REFUND.as_bytes(), &order.to_bytes()], program_id)
+}
+
+/// Get the hash of a refund code as stored in the refund code account
+///
+/// The order account is hashed along with the code so that the same code does
+/// not give the same hash for two orders.
+pub fn get_refund_code_hash(order: &Pubkey, code: &str) -> [u8; 32] {
+    hashv(&[&order.to_bytes(), code.as_bytes()]).to_bytes()
+}
+
+/// Get an order that a refund code can be issued for or redeemed against
+fn get_refundable_order(
+    program_id: &Pubkey,
+    order_info: &AccountInfo<'_>,
+) -> Result<OrderAccount, ProgramError> {
+    check_program_owned(program_id, order_info, "order")?;
+    let order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order is still holding the payment (or the installments paid so far)
+    if order_account.status != OrderStatus::Paid as u8
+        && order_account.status != OrderStatus::Pending as u8
+        && order_account.status != OrderStatus::Releasable as u8
+    {
+        return Err(PaymentProcessorError::AlreadyWithdrawn.into());
+    }
+    Ok(order_account)
+}
+
+/// Issue Refund Code
+///
+/// Lets the merchant authorize the refund of an order that has not been
+/// withdrawn, by storing the hash of a single-use code that is handed to the
+/// buyer e.g. by customer support.
+pub fn process_issue_refund_code(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    code_hash: [u8; 32],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let refund_code_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can issue refund codes
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let order_account = get_refundable_order(program_id, order_info)?;
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let (refund_code_address, bump_seed) = get_refund_code_address(program_id, order_info.key);
+    if refund_code_address != *refund_code_info.key {
+        msg!("Error: Refund code address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let order_bytes = order_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[REFUND.as_bytes(), &order_bytes, &[bump_seed]];
+
+    let account_size = RefundCodeAccount::MIN_LEN;
+    // Fund the refund code account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            refund_code_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            refund_code_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the refund code account
+    invoke_signed(
+        &system_instruction::allocate(refund_code_info.key, account_size as u64),
+        &[refund_code_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the refund code account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(refund_code_info.key, program_id),
+        &[refund_code_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving refund code information...
+    let refund_code = RefundCodeAccount {
+        discriminator: Discriminator::RefundCode as u8,
+        merchant: merchant_info.key.to_bytes(),
+        order: order_bytes,
+        code_hash,
+        created: timestamp,
+        redeemed: 0,
+        version: ACCOUNT_VERSION,
+    };
+    refund_code.pack(&mut refund_code_info.try_borrow_mut_data()?);
+
+    // ensure refund code account is rent exempt
+    if !rent.is_exempt(refund_code_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Redeem Refund Code
+///
+/// Refunds the amount paid for an order to its payer, who presents the refund
+/// code issued by the merchant instead of the merchant signing the refund.
+pub fn process_redeem_refund_code(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    code: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_token_info = next_account_info(account_info_iter)?;
+    let refund_token_info = next_account_info(account_info_iter)?;
+    let refund_code_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut order_account = get_refundable_order(program_id, order_info)?;
+    // ensure the signer is the order payer
+    if signer_info.key.to_bytes() != order_account.payer {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // ensure the merchant account is the one of the order
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the order payment token account is the right one
+    if order_token_info.key.to_bytes() != order_account.token {
+        msg!("Error: Incorrect order token account");
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
+    // ensure the refund goes to the payer
+    check_token_program(token_program_info)?;
+    let refund_token_data = check_token_account_mint(
+        refund_token_info,
+        token_program_info.key,
+        &Pubkey::new_from_array(order_account.mint),
+    )?;
+    if refund_token_data.owner != *signer_info.key {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    // get the refund code account
+    check_program_owned(program_id, refund_code_info, "refund code")?;
+    let mut refund_code_account = RefundCodeAccount::unpack(&refund_code_info.data.borrow())?;
+    if refund_code_account.discriminator != Discriminator::RefundCode as u8 {
+        msg!("Error: Invalid refund code account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if refund_code_account.order != order_info.key.to_bytes()
+        || refund_code_account.merchant != order_account.merchant
+    {
+        msg!("Error: The refund code is for another order");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    // ensure the code is right and was not used already
+    if refund_code_account.redeemed != 0 {
+        msg!("Error: The refund code was already redeemed");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    if get_refund_code_hash(order_info.key, &code) != refund_code_account.code_hash {
+        msg!("Error: Invalid refund code");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Transferring payment (and tip) back to the payer...
+    let refund_amount = get_order_balance(&order_account)?;
+    invoke_transfer(
+        token_program_info,
+        order_token_info,
+        possible_mint_info,
+        refund_token_info,
+        pda_info,
+        refund_amount,
+        &[&[PDA_SEED, &[pda_nonce]]],
+    )?;
+    let mut closed = 0;
+    // Close the order token account since it will never be needed again.  The
+    // payer paid for it at checkout so the rent goes back to them
+    if !has_withheld_transfer_fees(order_token_info) {
+        invoke_signed(
+            &token::close_account(
+                token_program_info.key,
+                order_token_info.key,
+                signer_info.key,
+                &pda,
+                &[&pda],
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                order_token_info.clone(),
+                signer_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        closed += 1;
+    }
+    // Transferring the amounts paid in other mints (if any) back to the payer...
+    closed += transfer_order_tokens(
+        &order_account,
+        account_info_iter,
+        Some(signer_info.key),
+        signer_info,
+        pda_info,
+        token_program_info,
+        pda_nonce,
+    )?;
+    // count the closed order token accounts towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        find_merchant_stats(program_id, account_info_iter),
+        &order_account.merchant,
+        0,
+        closed,
+    )?;
+
+    // Marking the refund code as used...
+    refund_code_account.redeemed = timestamp;
+    refund_code_account.pack(&mut refund_code_info.data.borrow_mut());
+
+    // the refunded order no longer counts towards the references of the merchant
+    update_merchant_references(merchant_info, false)?;
+
+    // the bounty the order set aside for the sweep crank goes back to the program owner
+    return_sweep_bounty(program_id, accounts, &mut order_account)?;
+    // Updating order account information...
+    order_account.status = OrderStatus::Refunded as u8;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    emit_event(&PaymentProcessorEvent::Refunded {
+        order: order_info.key.to_bytes(),
+        amount: refund_amount,
+    });
+
+    Ok(())
+}
@@ -1,10 +1,18 @@
 use crate::{
+    engine::config::{check_sponsor_fee, get_program_config},
     engine::constants::{
-        DEFAULT_DATA, DEFAULT_FEE_IN_LAMPORTS, MERCHANT, MIN_FEE_IN_LAMPORTS, PROGRAM_OWNER, TRIAL,
+        ACCOUNT_VERSION, DEFAULT_DATA, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS, MERCHANT, TRIAL,
     },
     engine::json::{Item, Packages},
-    state::{Discriminator, MerchantAccount, Serdes},
-    utils::get_merchant_account_size,
+    engine::terms::{check_metadata, get_merchant_terms_type},
+    engine::validation::{check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    events::{emit_event, PaymentProcessorEvent},
+    state::{
+        CatalogItem, Discriminator, IsClosed, MerchantAccount, MerchantTerms, ProgramConfigAccount,
+        Serdes, SubscriptionPackage,
+    },
+    utils::{get_merchant_account_size, get_merchant_terms_size},
 };
 use serde_json::Error as JSONError;
 use solana_program::{
@@ -13,12 +21,55 @@ use solana_program::{
     msg,
     program::invoke,
     program_error::ProgramError,
+    program_pack::IsInitialized,
     pubkey::Pubkey,
     system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
 use std::collections::BTreeMap;
-use std::str::FromStr;
+
+/// Get the type (discriminator) of a merchant account from its data
+///
+/// Merchants whose data holds subscription packages or an item catalog are
+/// subscription or chain checkout merchants.
+pub fn get_merchant_account_type(data: &str) -> u8 {
+    let maybe_subscription_merchant: Result<Packages, JSONError> = serde_json::from_str(data);
+    match maybe_subscription_merchant {
+        Ok(_value) => {
+            if data.contains(TRIAL) {
+                Discriminator::MerchantSubscriptionWithTrial as u8
+            } else {
+                Discriminator::MerchantSubscription as u8
+            }
+        }
+        Err(_error) => {
+            let maybe_chain_checkout: Result<BTreeMap<String, Item>, JSONError> =
+                serde_json::from_str(data);
+            match maybe_chain_checkout {
+                Ok(_value) => Discriminator::MerchantChainCheckout as u8,
+                Err(_error) => Discriminator::Merchant as u8,
+            }
+        }
+    }
+}
+
+/// Get the fee of a merchant, which cannot be lower than the minimum fee
+fn get_merchant_fee(config_account: &ProgramConfigAccount, maybe_fee: Option<u64>) -> u64 {
+    match maybe_fee {
+        None => config_account.default_fee,
+        Some(value) => {
+            let mut result = value;
+            if result < config_account.min_fee {
+                msg!(
+                    "Info: setting minimum transaction fee of {:?}",
+                    config_account.min_fee
+                );
+                result = config_account.min_fee;
+            }
+            result
+        }
+    }
+}
 
 pub fn process_register_merchant(
     program_id: &Pubkey,
@@ -26,6 +77,116 @@ pub fn process_register_merchant(
     seed: Option<String>,
     maybe_fee: Option<u64>,
     maybe_data: Option<String>,
+    maybe_sponsor_fee: Option<u64>,
+) -> ProgramResult {
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+    let merchant = new_merchant_account(
+        // get merchant account type
+        get_merchant_account_type(&data),
+        vec![],
+        vec![],
+        vec![],
+        data,
+    );
+    register_merchant(
+        program_id,
+        accounts,
+        seed,
+        maybe_fee,
+        maybe_sponsor_fee,
+        merchant,
+    )
+}
+
+/// Register Merchant With Terms
+///
+/// Registers a merchant whose catalog or subscription packages are typed,
+/// instead of being held as JSON in the merchant data, along with its metadata.
+pub fn process_register_merchant_with_terms(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: Option<String>,
+    maybe_fee: Option<u64>,
+    terms: MerchantTerms,
+    metadata: Vec<u8>,
+    maybe_sponsor_fee: Option<u64>,
+) -> ProgramResult {
+    check_metadata(&metadata)?;
+    let merchant_account_type = get_merchant_terms_type(&terms)?;
+    let (catalog, packages) = match terms {
+        MerchantTerms::Checkout => (vec![], vec![]),
+        MerchantTerms::Catalog(mut catalog) => {
+            // keep the catalog sorted so that checkouts can search it
+            catalog.sort_by(|item, other_item| item.name.cmp(&other_item.name));
+            (catalog, vec![])
+        }
+        MerchantTerms::Packages(packages) => (vec![], packages),
+    };
+    let merchant = new_merchant_account(
+        merchant_account_type,
+        catalog,
+        packages,
+        metadata,
+        String::new(),
+    );
+    register_merchant(
+        program_id,
+        accounts,
+        seed,
+        maybe_fee,
+        maybe_sponsor_fee,
+        merchant,
+    )
+}
+
+/// Get a merchant account with the given terms and data, and no settings
+fn new_merchant_account(
+    discriminator: u8,
+    catalog: Vec<CatalogItem>,
+    packages: Vec<SubscriptionPackage>,
+    metadata: Vec<u8>,
+    data: String,
+) -> MerchantAccount {
+    MerchantAccount {
+        discriminator,
+        owner: [0; 32],
+        sponsor: [0; 32],
+        fee: 0,
+        tolerance: 0,
+        references: 0,
+        is_paused: false,
+        restricts_mints: false,
+        instant_settlement: false,
+        blocked_buyers: 0,
+        lifetime_volume: 0,
+        order_sequence: 0,
+        rejects_freezable_mints: false,
+        sponsor_fee: 0,
+        payout_count: 0,
+        payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+        payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+        operator_count: 0,
+        operators: [[0; 32]; MAX_OPERATORS],
+        sweep_threshold: 0,
+        sweep_delay: 0,
+        catalog,
+        packages,
+        metadata,
+        data,
+        version: ACCOUNT_VERSION,
+    }
+}
+
+fn register_merchant(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: Option<String>,
+    maybe_fee: Option<u64>,
+    maybe_sponsor_fee: Option<u64>,
+    mut merchant: MerchantAccount,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -33,19 +194,18 @@ pub fn process_register_merchant(
     let merchant_info = next_account_info(account_info_iter)?;
     let system_sysvar_info = next_account_info(account_info_iter)?;
     let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
     let possible_sponsor_info = next_account_info(account_info_iter);
     let rent = &Rent::from_account_info(rent_sysvar_info)?;
 
     // ensure signer can sign
-    if !signer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    check_signer(signer_info)?;
+    let config_account = get_program_config(program_id, config_info)?;
+    let sponsor_fee = maybe_sponsor_fee.unwrap_or(config_account.sponsor_fee);
+    check_sponsor_fee(sponsor_fee)?;
 
-    let data = match maybe_data {
-        None => String::from(DEFAULT_DATA),
-        Some(value) => value,
-    };
-    let account_size = get_merchant_account_size(&data);
+    let account_size = get_merchant_account_size(&merchant.data)
+        + get_merchant_terms_size(&merchant.catalog, &merchant.packages, &merchant.metadata);
 
     // Creating merchant account on chain...
     invoke(
@@ -55,7 +215,7 @@ pub fn process_register_merchant(
             signer_info.key,
             match &seed {
                 None => MERCHANT,
-                Some(value) => &value,
+                Some(value) => value,
             },
             Rent::default().minimum_balance(account_size),
             account_size as u64,
@@ -69,53 +229,17 @@ pub fn process_register_merchant(
         ],
     )?;
 
-    // get merchant account type
-    let maybe_subscription_merchant: Result<Packages, JSONError> = serde_json::from_str(&data);
-    let merchant_account_type: u8 = match maybe_subscription_merchant {
-        Ok(_value) => {
-            if data.contains(TRIAL) {
-                Discriminator::MerchantSubscriptionWithTrial as u8
-            } else {
-                Discriminator::MerchantSubscription as u8
-            }
-        }
-        Err(_error) => {
-            let maybe_chain_checkout: Result<BTreeMap<String, Item>, JSONError> =
-                serde_json::from_str(&data);
-            match maybe_chain_checkout {
-                Ok(_value) => Discriminator::MerchantChainCheckout as u8,
-                Err(_error) => Discriminator::Merchant as u8,
-            }
-        }
-    };
-
     // get the merchant account data
     // TODO: ensure this account is not already initialized
     let mut merchant_account_data = merchant_info.try_borrow_mut_data()?;
     // save it
-    let merchant = MerchantAccount {
-        discriminator: merchant_account_type,
-        owner: signer_info.key.to_bytes(),
-        sponsor: match possible_sponsor_info {
-            Ok(sponsor_info) => sponsor_info.key.to_bytes(),
-            Err(_error) => Pubkey::from_str(PROGRAM_OWNER).unwrap().to_bytes(),
-        },
-        fee: match maybe_fee {
-            None => DEFAULT_FEE_IN_LAMPORTS,
-            Some(value) => {
-                let mut result = value;
-                if result < MIN_FEE_IN_LAMPORTS {
-                    msg!(
-                        "Info: setting minimum transaction fee of {:?}",
-                        MIN_FEE_IN_LAMPORTS
-                    );
-                    result = MIN_FEE_IN_LAMPORTS;
-                }
-                result
-            }
-        },
-        data,
+    merchant.owner = signer_info.key.to_bytes();
+    merchant.sponsor = match possible_sponsor_info {
+        Ok(sponsor_info) => sponsor_info.key.to_bytes(),
+        Err(_error) => config_account.owner,
     };
+    merchant.fee = get_merchant_fee(&config_account, maybe_fee);
+    merchant.sponsor_fee = sponsor_fee;
 
     merchant.pack(&mut merchant_account_data);
 
@@ -124,5 +248,93 @@ pub fn process_register_merchant(
         return Err(ProgramError::AccountNotRentExempt);
     }
 
+    emit_event(&PaymentProcessorEvent::MerchantRegistered {
+        merchant: merchant_info.key.to_bytes(),
+        owner: merchant.owner,
+        sponsor: merchant.sponsor,
+    });
+
+    Ok(())
+}
+
+/// Update Merchant
+///
+/// Lets the merchant owner change the fee, the sponsor and the data of the
+/// merchant account.  Only the fields that are set are changed.
+///
+/// The merchant account keeps its size, since accounts cannot be resized by
+/// the runtime that this program targets.  New data that is shorter than the
+/// current data is padded with (JSON) whitespace, while data that does not fit
+/// is rejected.
+pub fn process_update_merchant(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    maybe_fee: Option<u64>,
+    maybe_sponsor: Option<Pubkey>,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can update the merchant
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let config_account = get_program_config(program_id, config_info)?;
+
+    if maybe_fee.is_some() {
+        merchant_account.fee = get_merchant_fee(&config_account, maybe_fee);
+    }
+    if let Some(sponsor) = maybe_sponsor {
+        merchant_account.sponsor = sponsor.to_bytes();
+    }
+    if let Some(mut data) = maybe_data {
+        let account_size = merchant_info.data_len();
+        let terms_size = get_merchant_terms_size(
+            &merchant_account.catalog,
+            &merchant_account.packages,
+            &merchant_account.metadata,
+        );
+        // merchant accounts in the legacy layout end before the added fields, and
+        // keep the legacy layout until MigrateAccount counts their references
+        let layout_size = if merchant_account.version == 0 {
+            account_size + MerchantAccount::ADDED_LEN
+        } else {
+            account_size
+        };
+        let data_size = get_merchant_account_size(&data) + terms_size;
+        if data.len() != data.chars().count() || data_size > layout_size {
+            msg!(
+                "Error: The merchant data must be ASCII and fit in {:?} bytes",
+                layout_size - get_merchant_account_size(&String::new()) - terms_size
+            );
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        // keep the account size and layout by padding the data
+        data.push_str(&" ".repeat(layout_size - data_size));
+        // the type of merchants with a typed catalog or packages does not change
+        if merchant_account.catalog.is_empty() && merchant_account.packages.is_empty() {
+            merchant_account.discriminator = get_merchant_account_type(&data);
+        }
+        merchant_account.data = data;
+    }
+
+    // Updating merchant information...
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
     Ok(())
 }
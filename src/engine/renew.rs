@@ -1,6 +1,22 @@
-use crate::engine::common::subscribe_checks;
+use crate::engine::auto_debit::collect_order;
+use crate::engine::common::{
+    check_package_mint, check_subscription_not_paused, get_next_order_id, get_order_discount,
+    get_package_of_subscription, is_subscription_ended, subscribe_checks,
+    update_merchant_references,
+};
+use crate::engine::constants::RENEWAL_INCENTIVE_IN_LAMPORTS;
+use crate::engine::guarantee::get_guaranteed_price;
+use crate::engine::json::OrderSubscription;
+use crate::engine::pay::OrderArgs;
+use crate::engine::sysvars::SysvarProvider;
+use crate::engine::validation::check_program_owned;
 use crate::error::PaymentProcessorError;
-use crate::state::{Discriminator, IsClosed, Serdes, SubscriptionAccount, SubscriptionStatus};
+use crate::events::{emit_event, PaymentProcessorEvent};
+use crate::state::{
+    AutoDebitAccount, Discriminator, IsClosed, MerchantAccount, Serdes, SubscriptionAccount,
+    SubscriptionStatus,
+};
+use crate::utils::get_renewal;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
@@ -8,28 +24,18 @@ use solana_program::{
     program_error::ProgramError,
     program_pack::IsInitialized,
     pubkey::Pubkey,
-    sysvar::{clock::Clock, Sysvar},
+    sysvar::{rent::Rent, Sysvar},
 };
 
-pub fn process_renew_subscription(
+/// Get a subscription account and ensure that it is valid
+pub fn get_subscription_account(
     program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    quantity: i64,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-
-    let signer_info = next_account_info(account_info_iter)?;
-    let subscription_info = next_account_info(account_info_iter)?;
-    let merchant_info = next_account_info(account_info_iter)?;
-    let order_info = next_account_info(account_info_iter)?;
-
+    subscription_info: &AccountInfo<'_>,
+) -> Result<SubscriptionAccount, ProgramError> {
     // ensure subscription account is owned by this program
-    if *subscription_info.owner != *program_id {
-        msg!("Error: Wrong owner for subscription account");
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    check_program_owned(program_id, subscription_info, "subscription")?;
     // get the subscription account
-    let mut subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
+    let subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
     if !subscription_account.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
@@ -40,35 +46,246 @@ pub fn process_renew_subscription(
         msg!("Error: Invalid subscription account");
         return Err(ProgramError::InvalidAccountData);
     }
-    let (order_account, package) = subscribe_checks(
+    Ok(subscription_account)
+}
+
+pub fn process_renew_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    quantity: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let possible_guarantee_info = next_account_info(account_info_iter);
+
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    // a paused subscription has to be resumed before it is renewed
+    check_subscription_not_paused(&subscription_account)?;
+    let (order_account, merchant_account) = subscribe_checks(
         program_id,
         signer_info,
         merchant_info,
         order_info,
         subscription_info,
-        &subscription_account.name,
     )?;
-    // ensure the amount paid is as expected
-    let expected_amount = (quantity as u64) * package.price;
-    if expected_amount > order_account.paid_amount {
+    // ensure only the subscription owner renews it
+    if signer_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        &merchant_account,
+    )?;
+    check_package_mint(&package, &order_account)?;
+    let timestamp = sysvars.unix_timestamp()?;
+    // the price may be held by a price guarantee - subscriptions to packages of
+    // the catalog of the merchant keep the price they subscribed at instead
+    let price = match possible_guarantee_info {
+        Ok(guarantee_info) if subscription_account.package == [0; 32] => get_guaranteed_price(
+            program_id,
+            guarantee_info,
+            merchant_info,
+            &subscription_account,
+            package.price,
+            timestamp,
+        )?,
+        _ => package.price,
+    };
+    // ensure the amount paid is as expected - a discount taken off the order
+    // (e.g. a bundle discount) counts towards the price.  The price is per seat
+    let (renewal_seconds, expected_amount) = get_renewal(
+        package.duration,
+        price,
+        subscription_account.seats,
+        quantity,
+    )?;
+    let discount = get_order_discount(&order_account).map_or(0, |value| value.amount);
+    let covered_amount = order_account
+        .paid_amount
+        .checked_add(order_account.transfer_fee)
+        .and_then(|value| value.checked_add(discount))
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if expected_amount > covered_amount {
         return Err(PaymentProcessorError::NotFullyPaid.into());
     }
-    // update subscription account
-    let timestamp = Clock::get()?.unix_timestamp;
-    if timestamp > subscription_account.period_end {
+    // update subscription account - renewing a subscription that is past due
+    // within its grace period carries on from the end of the unpaid period
+    let grace_end = subscription_account
+        .period_end
+        .saturating_add(package.grace_seconds.unwrap_or(0));
+    let period_end = if timestamp > grace_end {
         // had ended so we start a new period
         subscription_account.period_start = timestamp;
-        subscription_account.period_end = timestamp + (package.duration * quantity);
+        timestamp.checked_add(renewal_seconds)
     } else {
         // not yet ended so we add the time to the end of the current period
-        subscription_account.period_end =
-            subscription_account.period_end + (package.duration * quantity);
+        subscription_account.period_end.checked_add(renewal_seconds)
+    };
+    subscription_account.period_end = period_end.ok_or(PaymentProcessorError::AmountOverflow)?;
+    // a cancelled or expired subscription that is renewed counts towards the
+    // references of the merchant once more
+    if is_subscription_ended(&subscription_account) {
+        update_merchant_references(merchant_info, true)?;
     }
     subscription_account.status = SubscriptionStatus::Initialized as u8;
+    // the order pays for the last periods of the subscription
+    subscription_account.period_order = order_info.key.to_bytes();
     SubscriptionAccount::pack(
         &subscription_account,
         &mut subscription_info.data.borrow_mut(),
     );
+    emit_event(&PaymentProcessorEvent::Renewed {
+        subscription: subscription_info.key.to_bytes(),
+        period_end: subscription_account.period_end,
+    });
+
+    Ok(())
+}
+
+/// Renew Due Subscriptions
+///
+/// A permissionless crank that renews a subscription whose period has ended by
+/// pulling the package price (for each seat) through the auto-debit account of
+/// the subscriber.
+/// The payment is recorded as a paid order of the merchant, like CollectPayment.
+///
+/// The spare lamports of the auto-debit account (above what keeps it rent exempt)
+/// pay back the cranker for the rent and fees of the order, plus an incentive.
+pub fn process_renew_due_subscriptions(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let _seller_token_info = next_account_info(account_info_iter)?;
+    let _buyer_token_info = next_account_info(account_info_iter)?;
+    let auto_debit_info = next_account_info(account_info_iter)?;
+    let _program_owner_info = next_account_info(account_info_iter)?;
+    let _sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let _pda_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+    let _system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let _config_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+    let possible_guarantee_info = next_account_info(account_info_iter);
+
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    if is_subscription_ended(&subscription_account) {
+        msg!("Error: The subscription has been cancelled or has expired");
+        return Err(PaymentProcessorError::SubscriptionExpired.into());
+    }
+    check_subscription_not_paused(&subscription_account)?;
+    let timestamp = sysvars.unix_timestamp()?;
+    if timestamp < subscription_account.period_end {
+        msg!(
+            "Error: The subscription is not due before {:?}",
+            subscription_account.period_end
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if merchant_info.key.to_bytes() != subscription_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        &merchant_account,
+    )?;
+    if package.mint != mint_info.key.to_string() {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    // ensure the payment is pulled from the subscriber
+    check_program_owned(program_id, auto_debit_info, "auto-debit")?;
+    let auto_debit_account = AutoDebitAccount::unpack(&auto_debit_info.data.borrow())?;
+    if auto_debit_account.owner != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    // the price may be held by a price guarantee - subscriptions to packages of
+    // the catalog of the merchant keep the price they subscribed at instead
+    let price = match possible_guarantee_info {
+        Ok(guarantee_info) if subscription_account.package == [0; 32] => get_guaranteed_price(
+            program_id,
+            guarantee_info,
+            merchant_info,
+            &subscription_account,
+            package.price,
+            timestamp,
+        )?,
+        _ => package.price,
+    };
+
+    let signer_lamports = signer_info.lamports();
+    let order_subscription = OrderSubscription {
+        subscription: subscription_info.key.to_string(),
+    };
+    collect_order(
+        program_id,
+        accounts,
+        sysvars,
+        OrderArgs {
+            amount: price
+                .checked_mul(subscription_account.seats)
+                .ok_or(PaymentProcessorError::AmountOverflow)?,
+            order_id: format!(
+                "{}-{}",
+                subscription_account.name,
+                get_next_order_id(merchant_account.order_sequence)?
+            ),
+            secret: String::from(""),
+            maybe_data: Some(serde_json::to_string(&order_subscription).unwrap()),
+        },
+        false,
+    )?;
+
+    // start a new period
+    subscription_account.status = SubscriptionStatus::Initialized as u8;
+    subscription_account.period_start = timestamp;
+    subscription_account.period_end = timestamp
+        .checked_add(package.duration)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    subscription_account.period_order = order_info.key.to_bytes();
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+    emit_event(&PaymentProcessorEvent::Renewed {
+        subscription: subscription_info.key.to_bytes(),
+        period_end: subscription_account.period_end,
+    });
+
+    // Paying the cranker from the auto-debit account...
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let reward =
+        signer_lamports.saturating_sub(signer_info.lamports()) + RENEWAL_INCENTIVE_IN_LAMPORTS;
+    let spare_lamports = auto_debit_info
+        .lamports()
+        .saturating_sub(rent.minimum_balance(AutoDebitAccount::MIN_LEN));
+    if reward > spare_lamports {
+        msg!(
+            "Error: The auto-debit account needs {:?} more lamports",
+            reward - spare_lamports
+        );
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **auto_debit_info.try_borrow_mut_lamports()? -= reward;
+    **signer_info.try_borrow_mut_lamports()? += reward;
 
     Ok(())
 }
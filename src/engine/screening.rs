@@ -0,0 +1,73 @@
+use crate::{engine::constants::SCREENING_PROGRAM, state::ProgramConfigAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde_json::Value;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::str::FromStr;
+
+/// What a checkout asks the screening program to approve
+///
+/// The screening program receives this as its instruction data, along with the
+/// payer, the merchant account and the buyer token account (none of them
+/// writable or signing) followed by the accounts of the screening program that
+/// were included in the checkout e.g. an attestation that the payer was screened.
+/// It fails the instruction to reject the payment.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub struct ScreeningRequest {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    /// the mint (currency) that the payment is made in
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Get the screening program set in the program config data (if any)
+///
+/// e.g. {"screening_program": "<program id>"}
+pub fn get_screening_program(
+    config_account: &ProgramConfigAccount,
+) -> Result<Option<Pubkey>, ProgramError> {
+    let config_json_data: Value = match serde_json::from_str(&config_account.data) {
+        Err(_error) => return Ok(None),
+        Ok(data) => data,
+    };
+    match config_json_data[SCREENING_PROGRAM].as_str() {
+        None => Ok(None),
+        Some(value) => match Pubkey::from_str(value) {
+            Err(_error) => {
+                msg!("Error: Invalid screening program in program config");
+                Err(ProgramError::InvalidAccountData)
+            }
+            Ok(screening_program) => Ok(Some(screening_program)),
+        },
+    }
+}
+
+/// Have the screening program approve a payment
+pub fn screen_payment<'a>(
+    screening_program_info: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    request: &ScreeningRequest,
+) -> ProgramResult {
+    let mut account_metas = vec![];
+    let mut account_infos = vec![screening_program_info.clone()];
+    for account_info in accounts.iter() {
+        account_metas.push(AccountMeta::new_readonly(*account_info.key, false));
+        account_infos.push(account_info.clone());
+    }
+    invoke(
+        &Instruction {
+            program_id: *screening_program_info.key,
+            accounts: account_metas,
+            data: request.try_to_vec()?,
+        },
+        &account_infos,
+    )
+}
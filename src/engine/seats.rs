@@ -0,0 +1,190 @@
+use crate::{
+    engine::auto_debit::collect_order,
+    engine::common::{
+        check_subscription_not_paused, get_next_order_id, get_package_of_subscription,
+        get_subscription_merchant, is_subscription_ended,
+    },
+    engine::json::{OrderSubscription, Package},
+    engine::pay::OrderArgs,
+    engine::renew::get_subscription_account,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{AutoDebitAccount, Serdes, SubscriptionAccount, SubscriptionStatus},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::convert::TryFrom;
+
+/// Get the part of the period of a subscription that a change of seats is prorated over
+///
+/// This is what is left of the period, up to the package duration so that the
+/// trial period (if any) is not charged for.  Free trials have not been paid
+/// for, so changing their seats is not prorated at all.
+pub fn get_prorated_seconds(
+    subscription_account: &SubscriptionAccount,
+    package: &Package,
+    timestamp: UnixTimestamp,
+) -> i64 {
+    if package.duration <= 0
+        || subscription_account.status == SubscriptionStatus::Trialing as u8
+        || timestamp >= subscription_account.period_end
+    {
+        return 0;
+    }
+    std::cmp::min(
+        subscription_account.period_end.saturating_sub(timestamp),
+        package.duration,
+    )
+}
+
+/// Get the price of adding seats to a subscription for the rest of its period
+///
+/// A price out of range fails with AmountOverflow rather than wrapping around.
+pub fn get_prorated_seats_price(
+    package: &Package,
+    added_seats: u64,
+    prorated_seconds: i64,
+) -> Result<u64, ProgramError> {
+    if package.duration <= 0 || prorated_seconds <= 0 {
+        return Ok(0);
+    }
+    let price = (added_seats as u128)
+        .checked_mul(package.price as u128)
+        .and_then(|value| value.checked_mul(prorated_seconds as u128))
+        .ok_or(PaymentProcessorError::AmountOverflow)?
+        / package.duration as u128;
+    u64::try_from(price).map_err(|_error| PaymentProcessorError::AmountOverflow.into())
+}
+
+/// Update Seats
+///
+/// Lets the subscriber change the number of seats of a subscription.  Adding
+/// seats charges the package price of the new seats for the rest of the period,
+/// pulled through the auto-debit account of the subscriber into a new paid order.
+/// Removing seats credits the unused part of the period of the removed seats to
+/// the remaining seats by extending the period.
+pub fn process_update_seats(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    seats: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    if seats == 0 {
+        msg!("Error: A subscription needs at least one seat");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    // ensure only the owner can change the seats of the subscription
+    if signer_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    if is_subscription_ended(&subscription_account) {
+        msg!("Error: The subscription has been cancelled or has expired");
+        return Err(PaymentProcessorError::SubscriptionExpired.into());
+    }
+    check_subscription_not_paused(&subscription_account)?;
+    check_account_migrated(subscription_account.version, "subscription")?;
+    if merchant_info.key.to_bytes() != subscription_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let merchant_account = get_subscription_merchant(program_id, merchant_info)?;
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        &merchant_account,
+    )?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+    let prorated_seconds = get_prorated_seconds(&subscription_account, &package, timestamp);
+
+    if seats > subscription_account.seats {
+        let amount = get_prorated_seats_price(
+            &package,
+            seats - subscription_account.seats,
+            prorated_seconds,
+        )?;
+        if amount > 0 {
+            // the payment accounts follow those of the subscription, and are
+            // ordered for collect_order
+            let order_info = next_account_info(account_info_iter)?;
+            let mut payment_accounts = vec![
+                signer_info.clone(),
+                order_info.clone(),
+                merchant_info.clone(),
+            ];
+            payment_accounts.extend(account_info_iter.cloned());
+            if payment_accounts.len() < 14 {
+                return Err(ProgramError::NotEnoughAccountKeys);
+            }
+            let auto_debit_info = &payment_accounts[5];
+            let mint_info = &payment_accounts[8];
+            if package.mint != mint_info.key.to_string() {
+                return Err(PaymentProcessorError::WrongMint.into());
+            }
+            // ensure the payment is pulled from the subscriber
+            check_program_owned(program_id, auto_debit_info, "auto-debit")?;
+            let auto_debit_account = AutoDebitAccount::unpack(&auto_debit_info.data.borrow())?;
+            if auto_debit_account.owner != subscription_account.owner {
+                return Err(PaymentProcessorError::WrongPayer.into());
+            }
+            msg!("Info: Charging {:?} for the added seats", amount);
+            let order_subscription = OrderSubscription {
+                subscription: subscription_info.key.to_string(),
+            };
+            collect_order(
+                program_id,
+                &payment_accounts,
+                sysvars,
+                OrderArgs {
+                    amount,
+                    order_id: format!(
+                        "{}-seats-{}",
+                        subscription_account.name,
+                        get_next_order_id(merchant_account.order_sequence)?
+                    ),
+                    secret: String::from(""),
+                    maybe_data: Some(serde_json::to_string(&order_subscription).unwrap()),
+                },
+                false,
+            )?;
+        }
+    } else if seats < subscription_account.seats {
+        // the unused part of the period of the removed seats is spread over the remaining seats
+        let credit_seconds = ((subscription_account.seats - seats) as u128
+            * prorated_seconds as u128)
+            / seats as u128;
+        msg!(
+            "Info: Crediting {:?} seconds for the removed seats",
+            credit_seconds
+        );
+        subscription_account.period_end = i64::try_from(credit_seconds)
+            .ok()
+            .and_then(|value| subscription_account.period_end.checked_add(value))
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+    }
+
+    // Updating subscription account information...
+    subscription_account.seats = seats;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
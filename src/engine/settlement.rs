@@ -0,0 +1,340 @@
+use crate::{
+    engine::common::{get_order_balance, get_order_tokens, update_merchant_references},
+    engine::config::get_program_config,
+    engine::constants::{ACCOUNT_VERSION, PDA_SEED, SETTLEMENT},
+    engine::operators::is_withdrawal_authority,
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::swap::{get_swap_program, SwapAccounts, SwapProgram},
+    engine::sweep::return_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        self, check_token_program, get_mint_decimals, has_withheld_transfer_fees,
+        unpack_token_account,
+    },
+    engine::validation::{check_pda, check_program_owned, check_signer},
+    engine::withdraw::{check_order_withdrawable, check_trial_period},
+    error::PaymentProcessorError,
+    state::{
+        Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes,
+        SettlementAccount,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the settlement account of a merchant
+pub fn get_settlement_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SETTLEMENT.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Set Settlement Mint
+///
+/// Lets the merchant owner choose the mint (currency) that payments made in
+/// other mints are swapped into when they are withdrawn with WithdrawSettled.
+pub fn process_set_settlement_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let settlement_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the settlement mint
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the mint is a mint
+    get_mint_decimals(mint_info)?;
+
+    if *settlement_info.owner != *program_id {
+        let (settlement_address, bump_seed) = get_settlement_address(program_id, merchant_info.key);
+        if settlement_address != *settlement_info.key {
+            msg!("Error: Settlement address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[SETTLEMENT.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = SettlementAccount::MIN_LEN;
+        // Fund the settlement account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                settlement_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                settlement_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the settlement account
+        invoke_signed(
+            &system_instruction::allocate(settlement_info.key, account_size as u64),
+            &[settlement_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the settlement account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(settlement_info.key, program_id),
+            &[settlement_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(settlement_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+    } else {
+        let settlement_account = SettlementAccount::unpack(&settlement_info.data.borrow())?;
+        if settlement_account.discriminator != Discriminator::Settlement as u8
+            || settlement_account.merchant != merchant_info.key.to_bytes()
+        {
+            msg!("Error: Invalid settlement account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // Saving settlement information...
+    let settlement_account = SettlementAccount {
+        discriminator: Discriminator::Settlement as u8,
+        merchant: merchant_info.key.to_bytes(),
+        mint: mint_info.key.to_bytes(),
+        version: ACCOUNT_VERSION,
+    };
+    settlement_account.pack(&mut settlement_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
+
+/// Withdraw Settled
+///
+/// Withdraws the payment (and tip) of an order the same way as Withdraw, except
+/// that it is swapped into the settlement mint of the merchant on the way out,
+/// through the swap program set in the program config.  The merchant owner
+/// sets the minimum amount of the settlement mint to receive, which bounds the
+/// slippage of the swap.
+pub fn process_withdraw_settled(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    minimum_amount_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_payment_token_info = next_account_info(account_info_iter)?;
+    let merchant_token_info = next_account_info(account_info_iter)?;
+    let account_to_receive_sol_refund_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let settlement_info = next_account_info(account_info_iter)?;
+    let swap_program_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    // get the merchant account
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner (or an operator) can choose the minimum amount out
+    if !is_withdrawal_authority(&merchant_account, signer_info.key) {
+        msg!("Error: Only merchant account owner or operators can withdraw settled payments");
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the order payment token account is the right one
+    if order_payment_token_info.key.to_bytes() != order_account.token {
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    // only orders paid in a single mint can be settled
+    if !get_order_tokens(&order_account).is_empty() {
+        msg!("Error: Orders paid in several mints cannot be settled");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    // get the settlement account
+    let (settlement_address, _bump_seed) = get_settlement_address(program_id, merchant_info.key);
+    if settlement_address != *settlement_info.key || *settlement_info.owner != *program_id {
+        msg!("Error: Settlement address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let settlement_account = SettlementAccount::unpack(&settlement_info.data.borrow())?;
+    if settlement_account.discriminator != Discriminator::Settlement as u8 {
+        msg!("Error: Invalid settlement account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if settlement_account.mint == order_account.mint {
+        msg!("Error: Order is already paid in the settlement mint");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    // ensure the swap program is the one set in the program config
+    let config_account = get_program_config(program_id, config_info)?;
+    let swap_program = match get_swap_program(&config_account)? {
+        None => {
+            msg!("Error: No swap program is set in the program config");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Some(value) => value,
+    };
+    if swap_program.program_id != *swap_program_info.key {
+        msg!("Error: Wrong swap program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool_len = swap_program.pool_accounts_len();
+    // the pool accounts follow the swap program
+    let pool_infos = account_info_iter.as_slice();
+    if pool_infos.len() < pool_len {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let pool_infos = &pool_infos[..pool_len];
+    for _ in 0..pool_len {
+        next_account_info(account_info_iter)?;
+    }
+    // ensure the merchant token account is a settlement mint account of the merchant owner
+    check_token_program(token_program_info)?;
+    let merchant_token_data = unpack_token_account(merchant_token_info, token_program_info.key)?;
+    if merchant_token_data.owner != Pubkey::new_from_array(merchant_account.owner) {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if merchant_token_data.mint != Pubkey::new_from_array(settlement_account.mint) {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    check_order_withdrawable(&order_account, timestamp)?;
+    // check if this is for a subscription payment that has a trial period
+    if merchant_account.discriminator == Discriminator::MerchantSubscriptionWithTrial as u8 {
+        let subscription_info = next_account_info(account_info_iter)?;
+        check_trial_period(
+            program_id,
+            accounts,
+            subscription_info,
+            &merchant_account,
+            &order_account,
+            timestamp,
+        )?;
+    }
+    // Swapping payment (and tip) into the settlement mint...
+    let amount_in = get_order_balance(&order_account)?;
+    let swap_accounts = SwapAccounts {
+        authority: pda_info,
+        source: order_payment_token_info,
+        destination: merchant_token_info,
+        token_program: token_program_info,
+        pool: pool_infos,
+    };
+    let mut swap_account_infos = vec![
+        swap_program_info.clone(),
+        pda_info.clone(),
+        order_payment_token_info.clone(),
+        merchant_token_info.clone(),
+        token_program_info.clone(),
+    ];
+    swap_account_infos.extend_from_slice(pool_infos);
+    invoke_signed(
+        &swap_program.swap_instruction(&swap_accounts, amount_in, minimum_amount_out),
+        &swap_account_infos,
+        &[&[PDA_SEED, &[pda_nonce]]],
+    )?;
+    // ensure the merchant received at least the minimum amount out
+    let amount_out = unpack_token_account(merchant_token_info, token_program_info.key)?
+        .amount
+        .saturating_sub(merchant_token_data.amount);
+    if amount_out < minimum_amount_out {
+        msg!(
+            "Error: Swap returned {:?}, less than the minimum of {:?}",
+            amount_out,
+            minimum_amount_out
+        );
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    msg!(
+        "SolPayments: Settled {:?} into {:?} of {:?}",
+        amount_in,
+        amount_out,
+        Pubkey::new_from_array(settlement_account.mint)
+    );
+    // Close the order token account since it will never be needed again
+    if !has_withheld_transfer_fees(order_payment_token_info) {
+        invoke_signed(
+            &token::close_account(
+                token_program_info.key,
+                order_payment_token_info.key,
+                account_to_receive_sol_refund_info.key,
+                &pda,
+                &[&pda],
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                order_payment_token_info.clone(),
+                account_to_receive_sol_refund_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        // count the closed order token account towards the open token accounts of the merchant
+        record_token_accounts(
+            program_id,
+            find_merchant_stats(program_id, account_info_iter),
+            &order_account.merchant,
+            0,
+            1,
+        )?;
+    }
+
+    // the withdrawn order no longer counts towards the references of the merchant
+    update_merchant_references(merchant_info, false)?;
+
+    // the bounty the order set aside for the sweep crank goes back to the program owner
+    return_sweep_bounty(program_id, accounts, &mut order_account)?;
+    // Updating order account information...
+    order_account.status = OrderStatus::Withdrawn as u8;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    Ok(())
+}
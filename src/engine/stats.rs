@@ -0,0 +1,182 @@
+use crate::{
+    engine::common::get_program_account_discriminator,
+    engine::constants::{ACCOUNT_VERSION, MAX_OPEN_TOKEN_ACCOUNTS, STATS},
+    engine::validation::{check_merchant_owner, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, MerchantStatsAccount, PublicKey, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the stats account of a merchant
+pub fn get_merchant_stats_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATS.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Find the stats account of a merchant among the accounts that remain, if any
+pub fn find_merchant_stats<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    account_info_iter: &mut I,
+) -> Option<&'a AccountInfo<'b>> {
+    account_info_iter.find(|account_info| {
+        get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::MerchantStats as u8)
+    })
+}
+
+/// Count the program-owned token accounts opened and closed for the orders of
+/// a merchant towards the stats account of the merchant (if any)
+///
+/// Fails when opening the token accounts would take the merchant over the most
+/// token accounts that its orders can keep open.
+pub fn record_token_accounts(
+    program_id: &Pubkey,
+    possible_stats_info: Option<&AccountInfo<'_>>,
+    merchant: &PublicKey,
+    opened: u64,
+    closed: u64,
+) -> ProgramResult {
+    let stats_info = match possible_stats_info {
+        None => return Ok(()),
+        Some(stats_info) => stats_info,
+    };
+    check_program_owned(program_id, stats_info, "merchant stats")?;
+    let mut stats_account = MerchantStatsAccount::unpack(&stats_info.data.borrow())?;
+    if stats_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if stats_account.discriminator != Discriminator::MerchantStats as u8 {
+        msg!("Error: Invalid merchant stats account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the stats account belongs to the merchant of the order
+    if *merchant != stats_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let open_token_accounts = stats_account.open_token_accounts.saturating_sub(closed);
+    if opened > 0 && open_token_accounts + opened > stats_account.max_open_token_accounts {
+        msg!(
+            "Error: The merchant has {:?} open token accounts, close stale ones first",
+            open_token_accounts
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Updating merchant stats...
+    stats_account.open_token_accounts = open_token_accounts + opened;
+    stats_account.opened += opened;
+    stats_account.closed += closed;
+    stats_account.pack(&mut stats_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Set Merchant Stats
+///
+/// Lets the merchant owner start keeping count of the program-owned token
+/// accounts of its orders, and set the most of them that can be open at once.
+/// Sending this again changes the cap.  The merchant owner can also set the
+/// count of open token accounts e.g. to the one found by a hygiene report, since
+/// only the instructions that include the stats account are counted.
+pub fn process_set_merchant_stats(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_open_token_accounts: u64,
+    open_token_accounts: Option<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let stats_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure only the merchant owner can set the merchant stats (the merchant account is not
+    // unpacked, its owner is read in place)
+    check_merchant_owner(program_id, signer_info, merchant_info)?;
+    if max_open_token_accounts == 0 || max_open_token_accounts > MAX_OPEN_TOKEN_ACCOUNTS {
+        msg!(
+            "Error: The cap on open token accounts must be 1 to {:?}",
+            MAX_OPEN_TOKEN_ACCOUNTS
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut stats_account = if *stats_info.owner != *program_id {
+        let (stats_address, bump_seed) = get_merchant_stats_address(program_id, merchant_info.key);
+        if stats_address != *stats_info.key {
+            msg!("Error: Merchant stats address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[STATS.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = MerchantStatsAccount::MIN_LEN;
+        // Fund the merchant stats account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                stats_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                stats_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the merchant stats account
+        invoke_signed(
+            &system_instruction::allocate(stats_info.key, account_size as u64),
+            &[stats_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the merchant stats account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(stats_info.key, program_id),
+            &[stats_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(stats_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+        MerchantStatsAccount {
+            discriminator: Discriminator::MerchantStats as u8,
+            merchant: merchant_bytes,
+            open_token_accounts: 0,
+            max_open_token_accounts,
+            opened: 0,
+            closed: 0,
+            version: ACCOUNT_VERSION,
+        }
+    } else {
+        let stats_account = MerchantStatsAccount::unpack(&stats_info.data.borrow())?;
+        if stats_account.discriminator != Discriminator::MerchantStats as u8
+            || stats_account.merchant != merchant_info.key.to_bytes()
+        {
+            msg!("Error: Invalid merchant stats account");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        stats_account
+    };
+
+    // Saving merchant stats...
+    stats_account.max_open_token_accounts = max_open_token_accounts;
+    if let Some(open_token_accounts) = open_token_accounts {
+        stats_account.open_token_accounts = open_token_accounts;
+    }
+    stats_account.pack(&mut stats_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
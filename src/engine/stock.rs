@@ -0,0 +1,162 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, STOCK},
+    engine::validation::{check_merchant_owner, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, ItemStockAccount, Serdes},
+    utils::get_item_stock_account_size,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the address of the item stock account of an item in the item catalog
+pub fn get_item_stock_address(program_id: &Pubkey, merchant: &Pubkey, item: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STOCK.as_bytes(), &merchant.to_bytes(), item.as_bytes()],
+        program_id,
+    )
+}
+
+/// Take items sold out of stock
+///
+/// Fails if there are not enough of the item left, so that an item cannot be
+/// oversold.  The item stock account is looked for among the given accounts.
+pub fn take_item_stock(
+    program_id: &Pubkey,
+    merchant_info: &AccountInfo<'_>,
+    stock_infos: &[&AccountInfo<'_>],
+    item: &str,
+    quantity: u64,
+) -> ProgramResult {
+    let (item_stock_address, _bump_seed) =
+        get_item_stock_address(program_id, merchant_info.key, item);
+    let item_stock_info = match stock_infos
+        .iter()
+        .find(|account_info| *account_info.key == item_stock_address)
+    {
+        None => {
+            msg!("Error: Missing the item stock account of item {:?}", item);
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(value) => value,
+    };
+    let mut item_stock_account = ItemStockAccount::unpack(&item_stock_info.data.borrow())?;
+    if item_stock_account.stock < quantity {
+        msg!(
+            "Error: Item {:?} is sold out, {:?} left",
+            item,
+            item_stock_account.stock
+        );
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+
+    // Updating item stock...
+    item_stock_account.stock -= quantity;
+    item_stock_account.sold += quantity;
+    item_stock_account.pack(&mut item_stock_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Restock Item
+///
+/// Adds to the stock of an item in the item catalog of the merchant, creating
+/// its item stock account the first time.
+pub fn process_restock_item(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    item: String,
+    quantity: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let item_stock_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure only the merchant owner can restock items (the merchant account is not
+    // unpacked, its owner is read in place)
+    check_merchant_owner(program_id, signer_info, merchant_info)?;
+    // the item id is used as a seed so it has to fit
+    if item.is_empty() || item.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The item id must be 1 to {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (item_stock_address, bump_seed) =
+        get_item_stock_address(program_id, merchant_info.key, &item);
+    if item_stock_address != *item_stock_info.key {
+        msg!("Error: Item stock address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let item_stock_account = if *item_stock_info.owner == *program_id {
+        let mut item_stock_account = ItemStockAccount::unpack(&item_stock_info.data.borrow())?;
+        item_stock_account.stock += quantity;
+        item_stock_account
+    } else {
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[
+            STOCK.as_bytes(),
+            &merchant_bytes,
+            item.as_bytes(),
+            &[bump_seed],
+        ];
+        let account_size = get_item_stock_account_size(&item);
+        // Fund the item stock account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                item_stock_info.key,
+                Rent::default().minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                item_stock_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the item stock account
+        invoke_signed(
+            &system_instruction::allocate(item_stock_info.key, account_size as u64),
+            &[item_stock_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the item stock account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(item_stock_info.key, program_id),
+            &[item_stock_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        if !rent.is_exempt(item_stock_info.lamports(), account_size) {
+            return Err(ProgramError::AccountNotRentExempt);
+        }
+        ItemStockAccount {
+            discriminator: Discriminator::ItemStock as u8,
+            merchant: merchant_bytes,
+            stock: quantity,
+            sold: 0,
+            item,
+            version: ACCOUNT_VERSION,
+        }
+    };
+
+    // Saving item stock information...
+    item_stock_account.pack(&mut item_stock_info.try_borrow_mut_data()?);
+
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use crate::{
+    engine::common::{get_program_account_discriminator, transfer_sol},
+    engine::constants::{ACCOUNT_VERSION, DEFAULT_DATA, ORDER_STORE, STORE},
+    engine::sysvars::SysvarProvider,
+    engine::terms::parse_catalog,
+    engine::validation::{check_merchant_owner, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{Discriminator, IsClosed, OrderAccount, Serdes, StoreAccount},
+    utils::get_store_account_size,
+};
+use serde_json::{json, Value};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+use std::str::FromStr;
+
+/// Get the address of the store account of a store id
+pub fn get_store_address(program_id: &Pubkey, merchant: &Pubkey, store_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STORE.as_bytes(), &merchant.to_bytes(), store_id.as_bytes()],
+        program_id,
+    )
+}
+
+/// Find the store account (if any) among the accounts of an instruction
+///
+/// The store account is told apart by its discriminator, wherever it is.
+pub fn find_store<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account_info| {
+        get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::Store as u8)
+    })
+}
+
+/// Get the store account of a merchant
+pub fn get_store_account(
+    program_id: &Pubkey,
+    store_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> Result<StoreAccount, ProgramError> {
+    check_program_owned(program_id, store_info, "store")?;
+    let store_account = StoreAccount::unpack(&store_info.data.borrow())?;
+    if store_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !store_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if store_account.discriminator != Discriminator::Store as u8 {
+        msg!("Error: Invalid store account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the store belongs to this merchant
+    if merchant_info.key.to_bytes() != store_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(store_account)
+}
+
+/// Record the store that an order was made at in the order data
+///
+/// The store is only recorded if the order data is a JSON object
+pub fn set_order_store(data: String, store: &Pubkey) -> String {
+    match serde_json::from_str(&data) {
+        Ok(Value::Object(mut json_data)) => {
+            json_data.insert(String::from(ORDER_STORE), json!(store.to_string()));
+            Value::Object(json_data).to_string()
+        }
+        _ => data,
+    }
+}
+
+/// Get the store (if any) that an order was made at
+pub fn get_order_store(order_account: &OrderAccount) -> Option<Pubkey> {
+    let json_data: Value = match serde_json::from_str(&order_account.data) {
+        Err(_error) => return None,
+        Ok(data) => data,
+    };
+    Pubkey::from_str(json_data[ORDER_STORE].as_str()?).ok()
+}
+
+/// Check whether a wallet is the fee destination of the store an order was made at
+///
+/// The store account is looked up among the accounts of the instruction.
+pub fn is_store_fee_destination(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merchant_info: &AccountInfo<'_>,
+    order_account: &OrderAccount,
+    wallet: &Pubkey,
+) -> Result<bool, ProgramError> {
+    let store_info = match find_store(program_id, accounts) {
+        None => return Ok(false),
+        Some(value) => value,
+    };
+    if get_order_store(order_account) != Some(*store_info.key) {
+        return Ok(false);
+    }
+    let store_account = get_store_account(program_id, store_info, merchant_info)?;
+    Ok(store_account.fee_destination == wallet.to_bytes())
+}
+
+/// Create Store
+///
+/// Lets a merchant owner operate several stores under the one merchant account.
+/// Each store has its own catalog, data and fee destination i.e. the wallet that
+/// the payments made at the store can be withdrawn to.
+pub fn process_create_store(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    store_id: String,
+    fee_destination: Option<Pubkey>,
+    maybe_catalog: Option<String>,
+    maybe_data: Option<String>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let store_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure only the merchant owner can create stores (the merchant account is not
+    // unpacked, its owner is read in place)
+    check_merchant_owner(program_id, signer_info, merchant_info)?;
+    // the store id is used as a seed so it has to fit
+    if store_id.is_empty() || store_id.len() > MAX_SEED_LEN {
+        msg!(
+            "Error: The store id must be 1 to {:?} bytes long",
+            MAX_SEED_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (store_address, bump_seed) = get_store_address(program_id, merchant_info.key, &store_id);
+    if store_address != *store_info.key {
+        msg!("Error: Store address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let catalog = maybe_catalog.unwrap_or_default();
+    // ensure the catalog lists items the way a chain checkout merchant does
+    if !catalog.is_empty() && parse_catalog(&catalog).is_err() {
+        msg!("Error: Invalid store catalog");
+        return Err(PaymentProcessorError::InvalidMerchantData.into());
+    }
+    let data = match maybe_data {
+        None => String::from(DEFAULT_DATA),
+        Some(value) => value,
+    };
+    let fee_destination = match fee_destination {
+        None => signer_info.key.to_bytes(),
+        Some(value) => value.to_bytes(),
+    };
+
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let signer_seeds: &[&[_]] = &[
+        STORE.as_bytes(),
+        &merchant_bytes,
+        store_id.as_bytes(),
+        &[bump_seed],
+    ];
+
+    let account_size = get_store_account_size(&store_id, &catalog, &data);
+    // Fund the store account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            store_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            store_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the store account
+    invoke_signed(
+        &system_instruction::allocate(store_info.key, account_size as u64),
+        &[store_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the store account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(store_info.key, program_id),
+        &[store_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving store information...
+    let store = StoreAccount {
+        discriminator: Discriminator::Store as u8,
+        merchant: merchant_bytes,
+        fee_destination,
+        created: timestamp,
+        store_id,
+        catalog,
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    store.pack(&mut store_info.try_borrow_mut_data()?);
+
+    // ensure store account is rent exempt
+    if !rent.is_exempt(store_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    Ok(())
+}
+
+/// Close Store
+///
+/// Lets the merchant owner close a store, getting back the rent paid for its
+/// account.  The orders made at the store keep the store recorded in their data.
+pub fn process_close_store(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let store_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure only the merchant owner can close stores (the merchant account is not
+    // unpacked, its owner is read in place)
+    check_merchant_owner(program_id, signer_info, merchant_info)?;
+    get_store_account(program_id, store_info, merchant_info)?;
+
+    // Closing the store account...
+    store_info.data.borrow_mut().fill(0);
+    transfer_sol(
+        store_info.clone(),
+        signer_info.clone(),
+        store_info.lamports(),
+    )?;
+
+    Ok(())
+}
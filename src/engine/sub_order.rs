@@ -0,0 +1,272 @@
+use crate::{
+    engine::common::{
+        create_order_account, create_program_owned_associated_token_account, get_order_tokens,
+        is_order_expired, next_order_sequence, update_merchant_references,
+    },
+    engine::constants::{ACCOUNT_VERSION, DISCOUNT, FEES, FIAT, PARENT, PDA_SEED},
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::sysvars::SysvarProvider,
+    engine::token::{self, unpack_token_account},
+    engine::validation::{check_pda, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+    utils::get_order_account_size,
+};
+use serde_json::{json, Value};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{rent::Rent, Sysvar},
+};
+
+/// Get the order data of a sub-order
+///
+/// The sub-order keeps the order data of the parent order (e.g. its escrow
+/// timeout or expiry) except for the fees, discount and fiat price, which
+/// belong to the parent order.  The parent order is recorded under PARENT.
+pub fn get_sub_order_data(parent_data: &str, parent: &Pubkey) -> String {
+    let mut json_data = match serde_json::from_str(parent_data) {
+        Ok(Value::Object(json_data)) => json_data,
+        _ => serde_json::Map::new(),
+    };
+    for key in [FEES, DISCOUNT, FIAT].iter() {
+        json_data.remove(*key);
+    }
+    json_data.insert(String::from(PARENT), json!(parent.to_string()));
+    Value::Object(json_data).to_string()
+}
+
+/// Get the share of a value that goes with the part of the paid amount split off
+///
+/// Rounds up when round_up is set, and down otherwise.
+fn get_share(value: u64, amount: u64, paid_amount: u64, round_up: bool) -> u64 {
+    let numerator = value as u128 * amount as u128;
+    let mut share = numerator / paid_amount as u128;
+    if round_up && !numerator.is_multiple_of(paid_amount as u128) {
+        share += 1;
+    }
+    share as u64
+}
+
+/// Split Order
+///
+/// Lets the merchant owner split part of a paid order off into a sub-order
+/// e.g. when the order is shipped in parts.  The sub-order gets the amount
+/// split off along with a proportional share of the tip and of the expected
+/// amount, and the tokens that go with them are moved into its own order token
+/// account.  The parent order keeps the rest, so that nothing is created or lost
+/// in the split.  From then on each order is confirmed, refunded and withdrawn
+/// on its own.
+pub fn process_split_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+    order_id: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let sub_order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_token_info = next_account_info(account_info_iter)?;
+    let sub_order_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let possible_stats_info = find_merchant_stats(program_id, account_info_iter);
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can split orders
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the order token account and mint are the right ones
+    if order_token_info.key.to_bytes() != order_account.token {
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    if mint_info.key.to_bytes() != order_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    // only orders that are paid (and not yet withdrawn) can be split
+    if order_account.status != OrderStatus::Paid as u8
+        && order_account.status != OrderStatus::Releasable as u8
+    {
+        msg!("Error: Only paid orders can be split");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if order_account.status == OrderStatus::Paid as u8 {
+        // expired orders can only be reclaimed by the payer
+        if is_order_expired(&order_account, timestamp) {
+            msg!("Error: Order expired");
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+    }
+    // orders paid in several mints or with a transfer fee cannot be split evenly
+    if !get_order_tokens(&order_account).is_empty() || order_account.transfer_fee > 0 {
+        msg!("Error: Orders paid in several mints or with a transfer fee cannot be split");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if order_account.withdrawn_amount > 0 {
+        msg!("Error: Orders that were partly withdrawn cannot be split");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+    if amount == 0 || amount >= order_account.paid_amount {
+        msg!(
+            "Error: The amount split off must be 1 to {:?}",
+            order_account.paid_amount.saturating_sub(1)
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // the sub-order gets its share of the tip and of the expected amount
+    let tip = get_share(order_account.tip, amount, order_account.paid_amount, false);
+    let expected_amount = get_share(
+        order_account.expected_amount,
+        amount,
+        order_account.paid_amount,
+        true,
+    );
+
+    // count the sub-order token account towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        possible_stats_info,
+        &merchant_info.key.to_bytes(),
+        1,
+        0,
+    )?;
+    // count the sub-order towards the references of the merchant
+    update_merchant_references(merchant_info, true)?;
+
+    // create sub-order account
+    let data = get_sub_order_data(&order_account.data, order_info.key);
+    let sub_order_account_size = get_order_account_size(&order_id, &order_account.secret, &data);
+    create_order_account(
+        program_id,
+        signer_info,
+        sub_order_info,
+        system_program_info,
+        merchant_info.key,
+        &order_id,
+        sub_order_account_size,
+    )?;
+    create_program_owned_associated_token_account(
+        program_id,
+        &[
+            signer_info.clone(),
+            sub_order_info.clone(),
+            sub_order_token_info.clone(),
+            mint_info.clone(),
+            pda_info.clone(),
+            token_program_info.clone(),
+            system_program_info.clone(),
+            rent_sysvar_info.clone(),
+        ],
+        rent,
+    )?;
+
+    // Moving the share of the payment (and tip) to the sub-order...
+    let split_amount = amount
+        .checked_add(tip)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    invoke_signed(
+        &token::transfer_checked(
+            token_program_info.key,
+            order_token_info.key,
+            mint_info,
+            sub_order_token_info.key,
+            &pda,
+            &[&pda],
+            split_amount,
+        )
+        .unwrap(),
+        &[
+            token_program_info.clone(),
+            order_token_info.clone(),
+            mint_info.clone(),
+            sub_order_token_info.clone(),
+            pda_info.clone(),
+        ],
+        &[&[PDA_SEED, &[pda_nonce]]],
+    )?;
+    // ensure that the sub-order received all of it
+    let sub_order_token_data = unpack_token_account(sub_order_token_info, token_program_info.key)?;
+    if sub_order_token_data.amount != split_amount {
+        msg!("Error: The sub-order did not receive the amount split off");
+        return Err(PaymentProcessorError::InvalidOrderData.into());
+    }
+
+    // number the order in the order sequence of the merchant
+    let sequence = next_order_sequence(merchant_info)?;
+    // Saving sub-order information...
+    let sub_order_account = OrderAccount {
+        discriminator: order_account.discriminator,
+        status: order_account.status,
+        created: order_account.created,
+        modified: timestamp,
+        merchant: order_account.merchant,
+        mint: order_account.mint,
+        token: sub_order_token_info.key.to_bytes(),
+        payer: order_account.payer,
+        expected_amount,
+        paid_amount: amount,
+        tip,
+        transfer_fee: 0,
+        withdrawn_amount: 0,
+        sequence,
+        items: vec![],
+        released_amount: 0,
+        created_slot: sysvars.slot()?,
+        sweep_bounty: 0,
+        order_id,
+        secret: order_account.secret.clone(),
+        data,
+        version: ACCOUNT_VERSION,
+    };
+    sub_order_account.pack(&mut sub_order_info.try_borrow_mut_data()?);
+
+    // Updating order account information...
+    order_account.expected_amount -= expected_amount;
+    order_account.paid_amount -= amount;
+    order_account.tip -= tip;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    Ok(())
+}
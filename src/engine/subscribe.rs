@@ -1,46 +1,119 @@
-use crate::engine::common::subscribe_checks;
-use crate::engine::constants::DEFAULT_DATA;
+use crate::engine::common::{
+    check_package_mint, get_order_discount, get_order_received_amount, get_package_to_subscribe,
+    get_subscription_merchant, subscribe_checks, update_merchant_references,
+};
+use crate::engine::constants::{ACCOUNT_VERSION, DEFAULT_DATA};
+use crate::engine::json::Package;
+use crate::engine::sysvars::SysvarProvider;
+use crate::engine::validation::check_signer;
 use crate::error::PaymentProcessorError;
-use crate::state::{Discriminator, Serdes, SubscriptionAccount, SubscriptionStatus};
+use crate::events::{emit_event, PaymentProcessorEvent};
+use crate::state::{Discriminator, PublicKey, Serdes, SubscriptionAccount, SubscriptionStatus};
 use crate::utils::get_subscription_account_size;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
-    system_instruction,
-    sysvar::{clock::Clock, rent::Rent, Sysvar},
+    system_instruction, system_program,
+    sysvar::{rent::Rent, Sysvar},
 };
 
+/// run checks for starting a free trial without paying
+///
+/// Only packages with a trial period whose price is due at the end of the trial
+/// can be subscribed to without an order.
+fn free_trial_checks(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signer_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+    subscription_name: &str,
+) -> Result<(PublicKey, Package), ProgramError> {
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let merchant_account = get_subscription_merchant(program_id, merchant_info)?;
+    let (package_key, package) = get_package_to_subscribe(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        subscription_name,
+    )?;
+    if package.trial.unwrap_or(0) <= 0 || package.price_due_at_trial_end != Some(true) {
+        msg!("Error: The package has to be paid for upfront");
+        return Err(PaymentProcessorError::NotPaid.into());
+    }
+    Ok((package_key, package))
+}
+
 pub fn process_subscribe(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
     name: String,
     maybe_data: Option<String>,
+    maybe_seats: Option<u64>,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let signer_info = next_account_info(account_info_iter)?;
     let subscription_info = next_account_info(account_info_iter)?;
     let merchant_info = next_account_info(account_info_iter)?;
-    let order_info = next_account_info(account_info_iter)?;
-    let system_program_info = next_account_info(account_info_iter)?;
-    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let order_or_system_program_info = next_account_info(account_info_iter)?;
 
-    let (order_account, package) = subscribe_checks(
-        program_id,
-        signer_info,
-        merchant_info,
-        order_info,
-        subscription_info,
-        &name,
-    )?;
-
-    // ensure the amount paid is as expected
-    if package.price > order_account.paid_amount {
-        return Err(PaymentProcessorError::NotFullyPaid.into());
+    // the package price is per seat
+    let seats = maybe_seats.unwrap_or(1);
+    if seats == 0 {
+        msg!("Error: A subscription needs at least one seat");
+        return Err(ProgramError::InvalidArgument);
     }
+
+    // free trials whose price is due at the end of the trial are started without
+    // an order, in which case the order account is left out
+    let is_free_trial = *order_or_system_program_info.key == system_program::id();
+    let (package_key, package, system_program_info) = if is_free_trial {
+        let (package_key, package) =
+            free_trial_checks(program_id, accounts, signer_info, merchant_info, &name)?;
+        (package_key, package, order_or_system_program_info)
+    } else {
+        let (order_account, merchant_account) = subscribe_checks(
+            program_id,
+            signer_info,
+            merchant_info,
+            order_or_system_program_info,
+            subscription_info,
+        )?;
+        // ensure we have the right payer
+        if signer_info.key.to_bytes() != order_account.payer {
+            return Err(PaymentProcessorError::WrongPayer.into());
+        }
+        let (package_key, package) = get_package_to_subscribe(
+            program_id,
+            accounts,
+            merchant_info,
+            &merchant_account,
+            &name,
+        )?;
+        check_package_mint(&package, &order_account)?;
+        // ensure the amount paid is as expected - a discount taken off the order
+        // (e.g. a bundle discount) counts towards the price
+        let discount = get_order_discount(&order_account).map_or(0, |value| value.amount);
+        let price = package
+            .price
+            .checked_mul(seats)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let paid_amount = get_order_received_amount(&order_account)?
+            .checked_add(discount)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+        if price > paid_amount {
+            return Err(PaymentProcessorError::NotFullyPaid.into());
+        }
+        (package_key, package, next_account_info(account_info_iter)?)
+    };
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
     // get subscription account size
     let data = match maybe_data {
         None => String::from(DEFAULT_DATA),
@@ -54,7 +127,7 @@ pub fn process_subscribe(
         &[
             &signer_info.key.to_bytes(),
             &merchant_info.key.to_bytes(),
-            &name.as_bytes(),
+            name.as_bytes(),
         ],
         program_id,
     );
@@ -62,14 +135,14 @@ pub fn process_subscribe(
     let signer_seeds: &[&[_]] = &[
         &signer_info.key.to_bytes(),
         &merchant_info.key.to_bytes(),
-        &name.as_bytes(),
+        name.as_bytes(),
         &[bump_seed],
     ];
 
     // Fund the subscription account with the minimum balance to be rent exempt
     invoke(
         &system_instruction::transfer(
-            &signer_info.key,
+            signer_info.key,
             subscription_info.key,
             Rent::default().minimum_balance(account_size),
         ),
@@ -83,17 +156,17 @@ pub fn process_subscribe(
     invoke_signed(
         &system_instruction::allocate(subscription_info.key, account_size as u64),
         &[subscription_info.clone(), system_program_info.clone()],
-        &[&signer_seeds],
+        &[signer_seeds],
     )?;
     // Assign the subscription account to the SolPayments program
     invoke_signed(
-        &system_instruction::assign(subscription_info.key, &program_id),
+        &system_instruction::assign(subscription_info.key, program_id),
         &[subscription_info.clone(), system_program_info.clone()],
-        &[&signer_seeds],
+        &[signer_seeds],
     )?;
 
     let rent = &Rent::from_account_info(rent_sysvar_info)?;
-    let timestamp = Clock::get()?.unix_timestamp;
+    let timestamp = sysvars.unix_timestamp()?;
 
     // get the trial period duration
     let trial_duration: i64 = match package.trial {
@@ -103,24 +176,61 @@ pub fn process_subscribe(
     // get the subscription account
     // TODO: ensure this account is not already initialized
     let mut subscription_data = subscription_info.try_borrow_mut_data()?;
+    let trial_end = timestamp
+        .checked_add(trial_duration)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    // a free trial only lasts until the end of the trial period unless it is renewed
+    let (status, period_end) = if is_free_trial {
+        (SubscriptionStatus::Trialing, trial_end)
+    } else {
+        (
+            SubscriptionStatus::Initialized,
+            trial_end
+                .checked_add(package.duration)
+                .ok_or(PaymentProcessorError::AmountOverflow)?,
+        )
+    };
+    // the order pays for the first period, which free trials have none for yet
+    let period_order = if is_free_trial {
+        [0; 32]
+    } else {
+        order_or_system_program_info.key.to_bytes()
+    };
     // Saving subscription information...
     let subscription = SubscriptionAccount {
         discriminator: Discriminator::Subscription as u8,
-        status: SubscriptionStatus::Initialized as u8,
+        status: status as u8,
         owner: signer_info.key.to_bytes(),
         merchant: merchant_info.key.to_bytes(),
         name,
         joined: timestamp,
         period_start: timestamp,
-        period_end: timestamp + trial_duration + package.duration,
+        period_end,
+        remaining_seconds: 0,
+        usage_units: 0,
+        seats,
+        package: package_key,
+        price: package.price,
+        period_order,
         data,
+        version: ACCOUNT_VERSION,
     };
     subscription.pack(&mut subscription_data);
+    // count the subscription towards the references of the merchant
+    update_merchant_references(merchant_info, true)?;
 
     // ensure subscription account is rent exempt
     if !rent.is_exempt(subscription_info.lamports(), account_size) {
         return Err(ProgramError::AccountNotRentExempt);
     }
 
+    emit_event(&PaymentProcessorEvent::Subscribed {
+        subscription: subscription_info.key.to_bytes(),
+        merchant: subscription.merchant,
+        owner: subscription.owner,
+        name: subscription.name,
+        period_end,
+    });
+
     Ok(())
 }
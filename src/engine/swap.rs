@@ -0,0 +1,103 @@
+use crate::{engine::constants::SWAP_PROGRAM, state::ProgramConfigAccount};
+use serde_json::Value;
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::str::FromStr;
+
+/// The accounts that take part in a swap
+pub struct SwapAccounts<'a, 'b> {
+    /// the account allowed to move the tokens out of the source token account
+    pub authority: &'a AccountInfo<'b>,
+    /// the token account that the tokens are swapped from
+    pub source: &'a AccountInfo<'b>,
+    /// the token account that the swapped tokens go to
+    pub destination: &'a AccountInfo<'b>,
+    pub token_program: &'a AccountInfo<'b>,
+    /// the accounts of the pool (in the order that the swap program expects)
+    pub pool: &'a [AccountInfo<'b>],
+}
+
+/// A program that the payment of an order can be swapped through at withdrawal
+pub trait SwapProgram {
+    /// The number of pool accounts that the swap needs
+    fn pool_accounts_len(&self) -> usize;
+    /// Build the instruction that swaps amount_in of the source token account
+    /// into at least minimum_amount_out of the destination token account
+    fn swap_instruction(
+        &self,
+        accounts: &SwapAccounts,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Instruction;
+}
+
+/// A swap program with the SPL Token Swap interface e.g. Orca
+///
+/// The pool accounts are the swap (pool state) account, the swap authority,
+/// the pool token accounts of the source and destination mints, the pool mint
+/// and the pool fee account.
+pub struct TokenSwap {
+    pub program_id: Pubkey,
+}
+
+impl SwapProgram for TokenSwap {
+    fn pool_accounts_len(&self) -> usize {
+        6
+    }
+
+    fn swap_instruction(
+        &self,
+        accounts: &SwapAccounts,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Instruction {
+        let pool = accounts.pool;
+        // the Swap instruction is tag 1 followed by the two amounts
+        let mut data = vec![1u8];
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*pool[0].key, false),
+                AccountMeta::new_readonly(*pool[1].key, false),
+                AccountMeta::new_readonly(*accounts.authority.key, true),
+                AccountMeta::new(*accounts.source.key, false),
+                AccountMeta::new(*pool[2].key, false),
+                AccountMeta::new(*pool[3].key, false),
+                AccountMeta::new(*accounts.destination.key, false),
+                AccountMeta::new(*pool[4].key, false),
+                AccountMeta::new(*pool[5].key, false),
+                AccountMeta::new_readonly(*accounts.token_program.key, false),
+            ],
+            data,
+        }
+    }
+}
+
+/// Get the swap program set in the program config data (if any)
+///
+/// e.g. {"swap_program": "<program id>"}
+pub fn get_swap_program(
+    config_account: &ProgramConfigAccount,
+) -> Result<Option<TokenSwap>, ProgramError> {
+    let config_json_data: Value = match serde_json::from_str(&config_account.data) {
+        Err(_error) => return Ok(None),
+        Ok(data) => data,
+    };
+    match config_json_data[SWAP_PROGRAM].as_str() {
+        None => Ok(None),
+        Some(value) => match Pubkey::from_str(value) {
+            Err(_error) => {
+                msg!("Error: Invalid swap program in program config");
+                Err(ProgramError::InvalidAccountData)
+            }
+            Ok(program_id) => Ok(Some(TokenSwap { program_id })),
+        },
+    }
+}
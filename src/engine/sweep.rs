@@ -0,0 +1,580 @@
+use crate::{
+    engine::associated_token::get_associated_token_address,
+    engine::common::{
+        get_order_balance, get_order_tokens, get_program_account_discriminator, transfer_sol,
+        update_merchant_references,
+    },
+    engine::config::get_program_config,
+    engine::constants::{ACCOUNT_VERSION, PDA_SEED, SWEEP, SWEEP_BOUNTY_IN_LAMPORTS},
+    engine::referral::get_order_referral,
+    engine::stats::record_token_accounts,
+    engine::sysvars::SysvarProvider,
+    engine::token::{self, check_token_program, has_withheld_transfer_fees},
+    engine::validation::{check_account_migrated, check_pda, check_program_owned, check_signer},
+    engine::withdraw::check_order_withdrawable,
+    error::PaymentProcessorError,
+    events::{emit_event, PaymentProcessorEvent},
+    state::{
+        Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, PublicKey, Serdes,
+        SweepBountyAccount,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::{Slot, UnixTimestamp},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+};
+
+/// Get the address of the sweep bounty account of a merchant
+pub fn get_sweep_bounty_address(program_id: &Pubkey, merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SWEEP.as_bytes(), &merchant.to_bytes()], program_id)
+}
+
+/// Get the sweep bounty account of a merchant
+pub fn get_sweep_bounty_account(
+    program_id: &Pubkey,
+    sweep_bounty_info: &AccountInfo<'_>,
+    merchant: &PublicKey,
+) -> Result<SweepBountyAccount, ProgramError> {
+    check_program_owned(program_id, sweep_bounty_info, "sweep bounty")?;
+    let sweep_bounty_account = SweepBountyAccount::unpack(&sweep_bounty_info.data.borrow())?;
+    if sweep_bounty_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !sweep_bounty_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure the sweep bounty account belongs to the merchant
+    if *merchant != sweep_bounty_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(sweep_bounty_account)
+}
+
+/// Find the sweep bounty account among the accounts of an instruction, if any
+pub fn find_sweep_bounty<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account_info| {
+        get_program_account_discriminator(program_id, account_info)
+            == Some(Discriminator::SweepBounty as u8)
+    })
+}
+
+/// Skip the sweep bounty account of the merchant when it is passed where the
+/// (optional) integrator account goes
+pub fn skip_sweep_bounty<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    program_id: &Pubkey,
+    account_info_iter: &mut I,
+    merchant_info: &AccountInfo<'_>,
+    possible_integrator_info: Result<&'a AccountInfo<'b>, ProgramError>,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    match possible_integrator_info {
+        Ok(account_info)
+            if *account_info.key == get_sweep_bounty_address(program_id, merchant_info.key).0 =>
+        {
+            next_account_info(account_info_iter)
+        }
+        other => other,
+    }
+}
+
+/// Get the part of the program owner fee of an order that pays the sweep crank
+///
+/// It is only set aside for the orders of a merchant that has them swept, and
+/// it is held by the sweep bounty account of the merchant until the order is
+/// swept (see set_aside_sweep_bounty).
+pub fn get_sweep_bounty(merchant_account: &MerchantAccount, program_owner_fee: u64) -> u64 {
+    if merchant_account.sweep_threshold == 0 {
+        return 0;
+    }
+    SWEEP_BOUNTY_IN_LAMPORTS.min(program_owner_fee)
+}
+
+/// Set aside the sweep bounty of an order in the sweep bounty account of the merchant
+///
+/// The signer pays the bounty to the sweep bounty account instead of paying it
+/// to the program owner (see pay_fees).  The account only pays it out to the
+/// sweep crank, or back to the program owner when the order is settled without
+/// being swept (see return_sweep_bounty), so the merchant cannot get it.
+pub fn set_aside_sweep_bounty<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    merchant_info: &AccountInfo<'a>,
+    signer_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    bounty: u64,
+) -> ProgramResult {
+    if bounty == 0 {
+        return Ok(());
+    }
+    let sweep_bounty_info = match find_sweep_bounty(program_id, accounts) {
+        None => {
+            msg!("Error: Checkouts of merchants that have their orders swept include the sweep bounty account");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(sweep_bounty_info) => sweep_bounty_info,
+    };
+    let mut sweep_bounty_account =
+        get_sweep_bounty_account(program_id, sweep_bounty_info, &merchant_info.key.to_bytes())?;
+    // Transferring sweep bounty to the sweep bounty account...
+    invoke(
+        &system_instruction::transfer(signer_info.key, sweep_bounty_info.key, bounty),
+        &[
+            signer_info.clone(),
+            sweep_bounty_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+
+    // Updating sweep bounty account information...
+    sweep_bounty_account.held = sweep_bounty_account
+        .held
+        .checked_add(bounty)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    sweep_bounty_account.pack(&mut sweep_bounty_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Return the sweep bounty of an order that is settled without being swept
+///
+/// The bounty is moved from what the sweep bounty account of the merchant holds
+/// for the sweep crank to what it returns to the program owner (see
+/// ClaimSweepBounties).  Instructions that settle an order that set aside a
+/// bounty (withdrawals, refunds, cancellations...) include the sweep bounty
+/// account.
+pub fn return_sweep_bounty(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    order_account: &mut OrderAccount,
+) -> ProgramResult {
+    if order_account.sweep_bounty == 0 {
+        return Ok(());
+    }
+    let sweep_bounty_info = match find_sweep_bounty(program_id, accounts) {
+        None => {
+            msg!("Error: The sweep bounty account is needed to settle an order that holds a sweep bounty");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Some(sweep_bounty_info) => sweep_bounty_info,
+    };
+    let mut sweep_bounty_account =
+        get_sweep_bounty_account(program_id, sweep_bounty_info, &order_account.merchant)?;
+
+    // Updating sweep bounty account information...
+    sweep_bounty_account.held = sweep_bounty_account
+        .held
+        .checked_sub(order_account.sweep_bounty)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    sweep_bounty_account.returned = sweep_bounty_account
+        .returned
+        .checked_add(order_account.sweep_bounty)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    sweep_bounty_account.pack(&mut sweep_bounty_info.data.borrow_mut());
+    order_account.sweep_bounty = 0;
+
+    Ok(())
+}
+
+/// Get an order that the sweep crank can withdraw
+///
+/// Only orders that a withdrawal has nothing more to do for than to transfer
+/// the payment are swept, i.e. not the orders with a referrer or paid in
+/// several mints.  Expects the order account and then its token account.
+fn get_sweepable_order(
+    program_id: &Pubkey,
+    merchant_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+    mint_info: &AccountInfo<'_>,
+    order_pair: &[AccountInfo<'_>],
+    timestamp: UnixTimestamp,
+    slot: Slot,
+) -> Result<OrderAccount, ProgramError> {
+    let (order_info, order_token_info) = match order_pair {
+        [order_info, order_token_info] => (order_info, order_token_info),
+        _ => return Err(ProgramError::NotEnoughAccountKeys),
+    };
+    check_program_owned(program_id, order_info, "order")?;
+    let order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // ensure the order payment token account is the right one
+    if order_token_info.key.to_bytes() != order_account.token {
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    if mint_info.key.to_bytes() != order_account.mint {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    check_order_withdrawable(&order_account, timestamp)?;
+    let swept_at = order_account
+        .created_slot
+        .checked_add(merchant_account.sweep_delay)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if slot < swept_at {
+        msg!("Error: Order is not swept before slot {:?}", swept_at);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if get_order_referral(&order_account).is_some() || !get_order_tokens(&order_account).is_empty()
+    {
+        msg!("Error: Orders with a referrer or paid in several mints are not swept");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    Ok(order_account)
+}
+
+/// Set Sweep Policy
+///
+/// Lets the merchant owner have its paid orders swept to its associated token
+/// account by anyone running the Sweep crank, once the orders old enough hold
+/// at least the sweep threshold.  A threshold of 0 turns sweeping off.  The
+/// sweep bounty account of the merchant, which holds the bounties set aside by
+/// its orders, is created the first time.
+pub fn process_set_sweep_policy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    threshold: u64,
+    delay: Slot,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let sweep_bounty_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the sweep policy
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+
+    if *sweep_bounty_info.owner != *program_id {
+        let (sweep_bounty_address, bump_seed) =
+            get_sweep_bounty_address(program_id, merchant_info.key);
+        if sweep_bounty_address != *sweep_bounty_info.key {
+            msg!("Error: Sweep bounty address does not match seed derivation");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let merchant_bytes = merchant_info.key.to_bytes();
+        let signer_seeds: &[&[_]] = &[SWEEP.as_bytes(), &merchant_bytes, &[bump_seed]];
+        let account_size = SweepBountyAccount::MIN_LEN;
+        // Fund the sweep bounty account with the minimum balance to be rent exempt
+        invoke(
+            &system_instruction::transfer(
+                signer_info.key,
+                sweep_bounty_info.key,
+                sysvars.rent()?.minimum_balance(account_size),
+            ),
+            &[
+                signer_info.clone(),
+                sweep_bounty_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        // Allocate space for the sweep bounty account
+        invoke_signed(
+            &system_instruction::allocate(sweep_bounty_info.key, account_size as u64),
+            &[sweep_bounty_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        // Assign the sweep bounty account to the SolPayments program
+        invoke_signed(
+            &system_instruction::assign(sweep_bounty_info.key, program_id),
+            &[sweep_bounty_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        SweepBountyAccount {
+            discriminator: Discriminator::SweepBounty as u8,
+            merchant: merchant_bytes,
+            held: 0,
+            returned: 0,
+            version: ACCOUNT_VERSION,
+        }
+        .pack(&mut sweep_bounty_info.try_borrow_mut_data()?);
+    } else {
+        get_sweep_bounty_account(program_id, sweep_bounty_info, &merchant_info.key.to_bytes())?;
+    }
+
+    // Updating merchant sweep policy...
+    merchant_account.sweep_threshold = threshold;
+    merchant_account.sweep_delay = delay;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Sweep
+///
+/// A permissionless crank that withdraws the paid orders of a merchant in one
+/// mint to the associated token account of the merchant owner, once the orders
+/// that are older than the sweep delay (in slots) of the merchant hold at least
+/// its sweep threshold (in total).  The cranker gets the rent of the order token
+/// accounts that are closed, plus the bounty that each order set aside out of
+/// the program owner fee when it was paid, which the sweep bounty account of the
+/// merchant pays out (see set_aside_sweep_bounty).
+pub fn process_sweep(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let merchant_token_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let sweep_bounty_info = next_account_info(account_info_iter)?;
+    // each order account is followed by its order token account, and the
+    // merchant stats account (if any) comes last
+    let mut order_infos: &[AccountInfo] = account_info_iter.as_slice();
+    let mut possible_stats_info = None;
+    if let Some((last_info, other_infos)) = order_infos.split_last() {
+        if get_program_account_discriminator(program_id, last_info)
+            == Some(Discriminator::MerchantStats as u8)
+        {
+            possible_stats_info = Some(last_info);
+            order_infos = other_infos;
+        }
+    }
+    if order_infos.is_empty() || !order_infos.len().is_multiple_of(2) {
+        msg!("Error: Each order account must be followed by its order token account");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let timestamp = sysvars.unix_timestamp()?;
+    let slot = sysvars.slot()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if merchant_account.sweep_threshold == 0 {
+        msg!("Error: The merchant has not set a sweep threshold");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // the trial periods and payout schedules are left to the withdrawals of the merchant
+    if merchant_account.discriminator == Discriminator::MerchantSubscriptionWithTrial as u8
+        || merchant_account.payout_count > 0
+    {
+        msg!("Error: Merchants with a trial period or a payout schedule are not swept");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // check that provided pda is correct
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    // ensure the orders are swept to the associated token account of the merchant owner
+    check_token_program(token_program_info)?;
+    let merchant_token_address = get_associated_token_address(
+        &Pubkey::new_from_array(merchant_account.owner),
+        token_program_info.key,
+        mint_info.key,
+    );
+    if *merchant_token_info.key != merchant_token_address {
+        msg!("Error: Orders are swept to the associated token account of the merchant owner");
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    get_program_config(program_id, config_info)?;
+    let mut sweep_bounty_account =
+        get_sweep_bounty_account(program_id, sweep_bounty_info, &merchant_info.key.to_bytes())?;
+
+    // add up what the orders hold
+    let mut orders = vec![];
+    for (index, pair) in order_infos.chunks(2).enumerate() {
+        if order_infos[..index * 2]
+            .iter()
+            .any(|account_info| account_info.key == pair[0].key)
+        {
+            msg!("Error: Order {:?} is swept twice", pair[0].key);
+            return Err(PaymentProcessorError::AlreadyWithdrawn.into());
+        }
+        let order_account = get_sweepable_order(
+            program_id,
+            merchant_info,
+            &merchant_account,
+            mint_info,
+            pair,
+            timestamp,
+            slot,
+        )?;
+        let balance = get_order_balance(&order_account)?;
+        orders.push((order_account, balance));
+    }
+    let total = orders
+        .iter()
+        .try_fold(0u64, |total, (_order_account, balance)| {
+            total.checked_add(*balance)
+        })
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    if total < merchant_account.sweep_threshold {
+        msg!(
+            "Error: The orders hold {:?}, less than the sweep threshold of {:?}",
+            total,
+            merchant_account.sweep_threshold
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Sweeping the orders to the merchant...
+    msg!("Info: Sweeping {:?} to the merchant", total);
+    let mut closed = 0;
+    let mut bounty = 0u64;
+    for (pair, (mut order_account, balance)) in order_infos.chunks(2).zip(orders) {
+        let order_info = &pair[0];
+        let order_token_info = &pair[1];
+        invoke_signed(
+            &token::transfer_checked(
+                token_program_info.key,
+                order_token_info.key,
+                mint_info,
+                merchant_token_info.key,
+                &pda,
+                &[&pda],
+                balance,
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                order_token_info.clone(),
+                mint_info.clone(),
+                merchant_token_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        // Close the order token account since it will never be needed again
+        if !has_withheld_transfer_fees(order_token_info) {
+            invoke_signed(
+                &token::close_account(
+                    token_program_info.key,
+                    order_token_info.key,
+                    signer_info.key,
+                    &pda,
+                    &[&pda],
+                )
+                .unwrap(),
+                &[
+                    token_program_info.clone(),
+                    order_token_info.clone(),
+                    signer_info.clone(),
+                    pda_info.clone(),
+                ],
+                &[&[PDA_SEED, &[pda_nonce]]],
+            )?;
+            closed += 1;
+        }
+        // the withdrawn order no longer counts towards the references of the merchant
+        update_merchant_references(merchant_info, false)?;
+
+        // the cranker is paid the bounty that the order set aside
+        bounty = bounty
+            .checked_add(order_account.sweep_bounty)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
+
+        // Updating order account information...
+        order_account.status = OrderStatus::Withdrawn as u8;
+        order_account.modified = timestamp;
+        order_account.sweep_bounty = 0;
+        OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+        emit_event(&PaymentProcessorEvent::OrderWithdrawn {
+            order: order_info.key.to_bytes(),
+            merchant: merchant_info.key.to_bytes(),
+            amount: balance,
+        });
+    }
+    // count the closed order token accounts towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        possible_stats_info,
+        &merchant_info.key.to_bytes(),
+        0,
+        closed,
+    )?;
+
+    // Paying the cranker the sweep bounties of the orders...
+    sweep_bounty_account.held = sweep_bounty_account
+        .held
+        .checked_sub(bounty)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    sweep_bounty_account.pack(&mut sweep_bounty_info.data.borrow_mut());
+    transfer_sol(sweep_bounty_info.clone(), signer_info.clone(), bounty)?;
+
+    Ok(())
+}
+
+/// Claim Sweep Bounties
+///
+/// Lets the program owner claim back the sweep bounties of the orders of a
+/// merchant that were settled without being swept.
+pub fn process_claim_sweep_bounties(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let sweep_bounty_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let config_account = get_program_config(program_id, config_info)?;
+    // ensure only the program owner can claim the sweep bounties
+    if signer_info.key.to_bytes() != config_account.owner {
+        msg!("Error: Only the program owner can claim the sweep bounties");
+        return Err(PaymentProcessorError::WrongProgramOwner.into());
+    }
+    check_program_owned(program_id, sweep_bounty_info, "sweep bounty")?;
+    let mut sweep_bounty_account = SweepBountyAccount::unpack(&sweep_bounty_info.data.borrow())?;
+    if sweep_bounty_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !sweep_bounty_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    // Transferring the returned sweep bounties to the program owner...
+    let returned = sweep_bounty_account.returned;
+    sweep_bounty_account.returned = 0;
+    sweep_bounty_account.pack(&mut sweep_bounty_info.data.borrow_mut());
+    transfer_sol(sweep_bounty_info.clone(), signer_info.clone(), returned)?;
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use solana_program::{
+    clock::{Slot, UnixTimestamp},
+    program_error::ProgramError,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+};
+
+/// Provides the sysvars used by the instruction handlers
+///
+/// The processor uses ProgramSysvars which reads the sysvars from the runtime.
+/// Other implementations can be injected e.g. to run a handler at a fixed time.
+pub trait SysvarProvider {
+    /// the current unix timestamp
+    fn unix_timestamp(&self) -> Result<UnixTimestamp, ProgramError>;
+    /// the current slot
+    fn slot(&self) -> Result<Slot, ProgramError>;
+    /// the rent that accounts have to hold to be rent exempt
+    fn rent(&self) -> Result<Rent, ProgramError>;
+}
+
+/// Reads the sysvars from the runtime
+pub struct ProgramSysvars;
+
+impl SysvarProvider for ProgramSysvars {
+    fn unix_timestamp(&self) -> Result<UnixTimestamp, ProgramError> {
+        Ok(Clock::get()?.unix_timestamp)
+    }
+    fn slot(&self) -> Result<Slot, ProgramError> {
+        Ok(Clock::get()?.slot)
+    }
+    fn rent(&self) -> Result<Rent, ProgramError> {
+        Rent::get()
+    }
+}
+
+/// Always returns the same sysvar values
+#[cfg(any(test, feature = "handlers"))]
+pub struct FixedSysvars {
+    pub unix_timestamp: UnixTimestamp,
+    pub slot: Slot,
+}
+
+#[cfg(any(test, feature = "handlers"))]
+impl SysvarProvider for FixedSysvars {
+    fn unix_timestamp(&self) -> Result<UnixTimestamp, ProgramError> {
+        Ok(self.unix_timestamp)
+    }
+    fn slot(&self) -> Result<Slot, ProgramError> {
+        Ok(self.slot)
+    }
+    /// the default rent
+    fn rent(&self) -> Result<Rent, ProgramError> {
+        Ok(Rent::default())
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, DEFAULT_DATA, MAX_METADATA_LEN},
+    engine::json::{Item, Package, Packages},
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{
+        CatalogItem, Discriminator, IsClosed, MerchantAccount, MerchantTerms, Serdes,
+        SubscriptionPackage,
+    },
+    utils::{get_merchant_account_size, get_merchant_terms_size},
+};
+use serde_json::Error as JSONError;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+use std::{borrow::Cow, collections::BTreeMap, str::FromStr};
+
+fn parse_public_key(value: &str) -> Option<[u8; 32]> {
+    Pubkey::from_str(value).ok().map(|key| key.to_bytes())
+}
+
+/// Get the items of a catalog given as JSON, sorted by name
+///
+/// i.e. the catalog of a store, or the data of a chain checkout merchant that
+/// has not been migrated yet e.g. {"item1": {"price": 2000, "mint": "..."}}
+pub fn parse_catalog(catalog: &str) -> Result<Vec<CatalogItem>, ProgramError> {
+    let maybe_items: Result<BTreeMap<String, Item>, JSONError> = serde_json::from_str(catalog);
+    let items = match maybe_items {
+        Err(_error) => return Err(PaymentProcessorError::InvalidMerchantData.into()),
+        Ok(value) => value,
+    };
+    let mut result = vec![];
+    for (name, item) in items.into_iter() {
+        let mint = parse_public_key(&item.mint);
+        let collection = item.collection.as_deref().map(parse_public_key);
+        if mint.is_none() || collection == Some(None) {
+            msg!("Error: Invalid mint or collection for item {:?}", name);
+            return Err(PaymentProcessorError::InvalidMerchantData.into());
+        }
+        result.push(CatalogItem {
+            name,
+            price: item.price,
+            mint: mint.unwrap(),
+            track_stock: item.track_stock,
+            collection: collection.flatten(),
+            holder_discount: item.holder_discount,
+        });
+    }
+    Ok(result)
+}
+
+/// Get the catalog of a chain checkout merchant
+///
+/// Merchants that have not been migrated yet have their catalog read from the
+/// merchant data.  The typed catalog of the others is borrowed rather than
+/// copied, which saves a checkout from allocating every item of the catalog.
+pub fn get_merchant_catalog(
+    merchant_account: &MerchantAccount,
+) -> Result<Cow<'_, [CatalogItem]>, ProgramError> {
+    if merchant_account.catalog.is_empty() && !merchant_account.data.trim().is_empty() {
+        return Ok(Cow::Owned(parse_catalog(&merchant_account.data)?));
+    }
+    Ok(Cow::Borrowed(&merchant_account.catalog))
+}
+
+/// Find an item of a catalog by name
+///
+/// Catalogs are kept sorted by name (see parse_catalog and RegisterMerchantWithTerms)
+/// so the item is looked up with a binary search.  Typed catalogs registered
+/// before they were sorted are scanned when the search misses.
+pub fn find_catalog_item<'a>(catalog: &'a [CatalogItem], name: &str) -> Option<&'a CatalogItem> {
+    match catalog.binary_search_by(|item| item.name.as_str().cmp(name)) {
+        Ok(index) => Some(&catalog[index]),
+        Err(_index) => catalog.iter().find(|item| item.name == name),
+    }
+}
+
+/// Get the subscription packages of a merchant
+///
+/// Merchants that have not been migrated yet have their packages read from the
+/// merchant data e.g. {"packages": [...]}.
+pub fn get_merchant_packages(
+    merchant_account: &MerchantAccount,
+) -> Result<Vec<Package>, ProgramError> {
+    if merchant_account.packages.is_empty() && !merchant_account.data.trim().is_empty() {
+        let maybe_packages: Result<Packages, JSONError> =
+            serde_json::from_str(&merchant_account.data);
+        return match maybe_packages {
+            Err(_error) => Err(PaymentProcessorError::InvalidSubscriptionData.into()),
+            Ok(value) => Ok(value.packages),
+        };
+    }
+    Ok(merchant_account
+        .packages
+        .iter()
+        .map(|package| Package {
+            name: package.name.clone(),
+            trial: package.trial,
+            duration: package.duration,
+            price: package.price,
+            mint: Pubkey::new_from_array(package.mint).to_string(),
+            prorated_refunds: Some(package.prorated_refunds),
+            price_due_at_trial_end: Some(package.price_due_at_trial_end),
+            grace_seconds: package.grace_seconds,
+            unit_price: package.unit_price,
+        })
+        .collect())
+}
+
+fn get_typed_packages(packages: Vec<Package>) -> Result<Vec<SubscriptionPackage>, ProgramError> {
+    let mut result = vec![];
+    for package in packages.into_iter() {
+        let mint = match parse_public_key(&package.mint) {
+            None => {
+                msg!("Error: Invalid mint for package {:?}", package.name);
+                return Err(PaymentProcessorError::InvalidSubscriptionData.into());
+            }
+            Some(value) => value,
+        };
+        result.push(SubscriptionPackage {
+            name: package.name,
+            trial: package.trial,
+            duration: package.duration,
+            price: package.price,
+            mint,
+            prorated_refunds: package.prorated_refunds.unwrap_or(false),
+            price_due_at_trial_end: package.price_due_at_trial_end.unwrap_or(false),
+            grace_seconds: package.grace_seconds,
+            unit_price: package.unit_price,
+        });
+    }
+    Ok(result)
+}
+
+/// Get the type (discriminator) of a merchant account from its terms
+///
+/// The catalog items and the packages have to be named uniquely, since they are
+/// looked up by name.
+pub fn get_merchant_terms_type(terms: &MerchantTerms) -> Result<u8, ProgramError> {
+    let names: Vec<&String> = match terms {
+        MerchantTerms::Checkout => return Ok(Discriminator::Merchant as u8),
+        MerchantTerms::Catalog(catalog) => catalog.iter().map(|item| &item.name).collect(),
+        MerchantTerms::Packages(packages) => packages.iter().map(|package| &package.name).collect(),
+    };
+    for (index, name) in names.iter().enumerate() {
+        if names[..index].contains(name) {
+            msg!("Error: {:?} is named twice", name);
+            return Err(PaymentProcessorError::InvalidMerchantData.into());
+        }
+    }
+    match terms {
+        MerchantTerms::Packages(packages)
+            if packages
+                .iter()
+                .any(|package| package.trial.unwrap_or(0) > 0) =>
+        {
+            Ok(Discriminator::MerchantSubscriptionWithTrial as u8)
+        }
+        MerchantTerms::Packages(_packages) => Ok(Discriminator::MerchantSubscription as u8),
+        _ => Ok(Discriminator::MerchantChainCheckout as u8),
+    }
+}
+
+/// Ensure that the metadata of a merchant is not too long
+pub fn check_metadata(metadata: &[u8]) -> ProgramResult {
+    if metadata.len() > MAX_METADATA_LEN {
+        msg!(
+            "Error: The merchant metadata is at most {:?} bytes",
+            MAX_METADATA_LEN
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+/// Migrate Merchant Data
+///
+/// Moves the catalog or subscription packages that the merchant data holds as
+/// JSON into the typed catalog or packages of the merchant, so that checkouts and
+/// subscriptions no longer parse JSON.  The data of other merchants is moved into
+/// the merchant metadata, as long as it fits.  The merchant account keeps its
+/// size, the data that was moved out being replaced by padding.  Merchant
+/// accounts in the legacy layout have to be migrated first.
+pub fn process_migrate_merchant_data(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can migrate the merchant
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // the typed catalog and packages are added after the legacy layout, which
+    // MigrateAccount moves the merchant account out of (counting its references)
+    check_account_migrated(merchant_account.version, "merchant")?;
+    let data = merchant_account.data.trim().to_string();
+    if data.is_empty()
+        || !merchant_account.catalog.is_empty()
+        || !merchant_account.packages.is_empty()
+        || !merchant_account.metadata.is_empty()
+    {
+        msg!("Error: The merchant data has already been migrated");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Moving the merchant data...
+    match merchant_account.discriminator {
+        value if value == Discriminator::MerchantChainCheckout as u8 => {
+            merchant_account.catalog = parse_catalog(&data)?;
+        }
+        value
+            if value == Discriminator::MerchantSubscription as u8
+                || value == Discriminator::MerchantSubscriptionWithTrial as u8 =>
+        {
+            merchant_account.packages =
+                get_typed_packages(get_merchant_packages(&merchant_account)?)?;
+        }
+        _ if data == DEFAULT_DATA => {}
+        _ => {
+            check_metadata(data.as_bytes())?;
+            merchant_account.metadata = data.into_bytes();
+        }
+    }
+    // keep the account size by padding the data, in the latest layout
+    merchant_account.version = ACCOUNT_VERSION;
+    let account_size = merchant_info.data_len();
+    let data_size = get_merchant_account_size(&String::new())
+        + get_merchant_terms_size(
+            &merchant_account.catalog,
+            &merchant_account.packages,
+            &merchant_account.metadata,
+        );
+    if data_size > account_size {
+        msg!("Error: The migrated merchant data does not fit in the merchant account");
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    merchant_account.data = " ".repeat(account_size - data_size);
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
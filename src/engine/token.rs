@@ -0,0 +1,364 @@
+use crate::{
+    engine::constants::{
+        NON_TRANSFERABLE_EXTENSION, TOKEN_2022_PROGRAM_ID, TRANSFER_FEE_AMOUNT_EXTENSION,
+        TRANSFER_FEE_CONFIG_EXTENSION, TRANSFER_HOOK_EXTENSION,
+    },
+    error::PaymentProcessorError,
+};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+use spl_token::{
+    self,
+    state::{Account as TokenAccount, Mint},
+};
+use std::str::FromStr;
+
+/// Get the id of the Token-2022 program
+pub fn get_token_2022_program_id() -> Pubkey {
+    Pubkey::from_str(TOKEN_2022_PROGRAM_ID).unwrap()
+}
+
+/// Check whether a program is one of the token programs (SPL Token or Token-2022)
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == get_token_2022_program_id()
+}
+
+/// Ensure that the token program account is one of the token programs
+pub fn check_token_program(token_program_info: &AccountInfo<'_>) -> ProgramResult {
+    if !is_token_program(token_program_info.key) {
+        msg!("Error: Unknown token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Get the state of a token account of the given token program
+///
+/// Token-2022 accounts may hold extensions after the base account state, which
+/// are left out.
+pub fn unpack_token_account(
+    token_info: &AccountInfo<'_>,
+    token_program_id: &Pubkey,
+) -> Result<TokenAccount, ProgramError> {
+    if *token_info.owner != *token_program_id {
+        msg!("Error: Token account must be owned by token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = token_info.data.borrow();
+    if data.len() < TokenAccount::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let token_account = TokenAccount::unpack_from_slice(&data[..TokenAccount::LEN])?;
+    if !token_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(token_account)
+}
+
+/// Get the base state of a mint of either token program
+///
+/// Token-2022 mints may hold extensions after the base mint state, which are
+/// left out.
+fn unpack_mint(mint_info: &AccountInfo<'_>) -> Result<Mint, ProgramError> {
+    if !is_token_program(mint_info.owner) {
+        msg!("Error: Mint must be owned by token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = mint_info.data.borrow();
+    if data.len() < Mint::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mint = Mint::unpack_from_slice(&data[..Mint::LEN])?;
+    if !mint.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(mint)
+}
+
+/// Get the number of decimals of a mint of either token program
+pub fn get_mint_decimals(mint_info: &AccountInfo<'_>) -> Result<u8, ProgramError> {
+    Ok(unpack_mint(mint_info)?.decimals)
+}
+
+/// Check whether a mint of either token program has a freeze authority, which
+/// can freeze the token accounts holding the mint
+pub fn has_freeze_authority(mint_info: &AccountInfo<'_>) -> Result<bool, ProgramError> {
+    Ok(unpack_mint(mint_info)?.freeze_authority.is_some())
+}
+
+/// Get the part of the amount sent to an order token account that was withheld
+/// as a transfer fee, and split the rest between the payment and the tip
+///
+/// Returns (net amount, net tip, transfer fee).  Only Token-2022 mints with the
+/// transfer fee extension withhold anything.  The transfer fee is taken off the
+/// amount first so that the tip is left untouched whenever possible.  An amount
+/// and tip that add up to more than a u64 fail with AmountOverflow.
+pub fn get_net_amounts(
+    amount: u64,
+    tip: u64,
+    received: u64,
+) -> Result<(u64, u64, u64), ProgramError> {
+    let transfer_fee = amount
+        .checked_add(tip)
+        .ok_or(PaymentProcessorError::AmountOverflow)?
+        .saturating_sub(received);
+    let net_amount = amount.saturating_sub(transfer_fee);
+    let net_tip = received
+        .checked_sub(net_amount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    Ok((net_amount, net_tip, transfer_fee))
+}
+
+/// Get the Token-2022 extensions of a mint or token account as (type, value)
+///
+/// The extensions follow the base state and the account type as (type: u16,
+/// length: u16, value) entries.  Mints are padded to the size of a token
+/// account so that the account type is at the same offset in both.
+fn get_extensions(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut extensions = vec![];
+    let mut offset = TokenAccount::LEN + 1;
+    while offset + 4 <= data.len() {
+        let extension_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let length = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        match data.get(offset + 4..offset + 4 + length) {
+            None => break,
+            Some(value) => extensions.push((extension_type, value)),
+        }
+        offset = offset + 4 + length;
+    }
+    extensions
+}
+
+/// Check whether a Token-2022 token account holds transfer fees that were
+/// withheld from the transfers it received
+///
+/// Such an account cannot be closed until the fees are harvested to the mint.
+pub fn has_withheld_transfer_fees(token_info: &AccountInfo<'_>) -> bool {
+    if *token_info.owner != get_token_2022_program_id() {
+        return false;
+    }
+    let data = token_info.data.borrow();
+    get_extensions(&data)
+        .iter()
+        .any(|(extension_type, value)| match extension_type {
+            &TRANSFER_FEE_AMOUNT_EXTENSION if value.len() == 8 => {
+                let mut withheld_amount = [0u8; 8];
+                withheld_amount.copy_from_slice(value);
+                u64::from_le_bytes(withheld_amount) > 0
+            }
+            _ => false,
+        })
+}
+
+/// Get the length of the extension that the token accounts of a mint hold for
+/// an extension of the mint, if any
+fn get_account_extension_length(mint_extension: u16) -> Option<usize> {
+    match mint_extension {
+        // the transfer fees withheld from the transfers received
+        TRANSFER_FEE_CONFIG_EXTENSION => Some(8),
+        NON_TRANSFERABLE_EXTENSION => Some(0),
+        // whether the transfer hook is running
+        TRANSFER_HOOK_EXTENSION => Some(1),
+        _ => None,
+    }
+}
+
+/// Get the size of a token account of a mint of either token program
+///
+/// A Token-2022 token account cannot be initialized without room for the
+/// extensions that the extensions of its mint require, e.g. the transfer fees
+/// withheld in the account for mints with a transfer fee.
+pub fn get_token_account_size(mint_info: &AccountInfo<'_>) -> Result<usize, ProgramError> {
+    unpack_mint(mint_info)?;
+    if *mint_info.owner != get_token_2022_program_id() {
+        return Ok(TokenAccount::LEN);
+    }
+    let data = mint_info.data.borrow();
+    let extensions_size: usize = get_extensions(&data)
+        .iter()
+        .filter_map(|(extension_type, _value)| get_account_extension_length(*extension_type))
+        .map(|length| 4 + length)
+        .sum();
+    if extensions_size == 0 {
+        return Ok(TokenAccount::LEN);
+    }
+    // the account type comes before the extensions
+    Ok(TokenAccount::LEN + 1 + extensions_size)
+}
+
+/// Send an instruction built for the SPL Token program to the given token program
+///
+/// The instructions used by this program are the same in both token programs.
+fn with_token_program(
+    mut instruction: Instruction,
+    token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    if !is_token_program(token_program_id) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    instruction.program_id = *token_program_id;
+    Ok(instruction)
+}
+
+/// Creates a 'Transfer' instruction of the given token program
+pub fn transfer(
+    token_program_id: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    signer_pubkeys: &[&Pubkey],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::transfer(
+        &spl_token::id(),
+        source_pubkey,
+        destination_pubkey,
+        authority_pubkey,
+        signer_pubkeys,
+        amount,
+    )?;
+    with_token_program(instruction, token_program_id)
+}
+
+/// Creates a 'TransferChecked' instruction of the given token program, for the
+/// decimals of the mint
+pub fn transfer_checked(
+    token_program_id: &Pubkey,
+    source_pubkey: &Pubkey,
+    mint_info: &AccountInfo<'_>,
+    destination_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    signer_pubkeys: &[&Pubkey],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::transfer_checked(
+        &spl_token::id(),
+        source_pubkey,
+        mint_info.key,
+        destination_pubkey,
+        authority_pubkey,
+        signer_pubkeys,
+        amount,
+        get_mint_decimals(mint_info)?,
+    )?;
+    with_token_program(instruction, token_program_id)
+}
+
+/// Find a mint account among the accounts of an instruction
+///
+/// The instructions that were released before tokens were moved with
+/// 'TransferChecked' take the mint accounts of the order as optional accounts
+/// after all their other accounts, so that their other accounts keep their
+/// positions.
+pub fn find_mint<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    mint: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts
+        .iter()
+        .find(|account_info| account_info.key == mint)
+}
+
+/// Move tokens between two token accounts of the given token program
+///
+/// The tokens are moved with a 'TransferChecked' when the mint account is
+/// given, which Token-2022 requires for mints with a transfer fee, and with a
+/// 'Transfer' otherwise.
+pub fn invoke_transfer<'a>(
+    token_program_info: &AccountInfo<'a>,
+    source_info: &AccountInfo<'a>,
+    possible_mint_info: Option<&AccountInfo<'a>>,
+    destination_info: &AccountInfo<'a>,
+    authority_info: &AccountInfo<'a>,
+    amount: u64,
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut account_infos = vec![token_program_info.clone(), source_info.clone()];
+    let instruction = match possible_mint_info {
+        None => transfer(
+            token_program_info.key,
+            source_info.key,
+            destination_info.key,
+            authority_info.key,
+            &[authority_info.key],
+            amount,
+        )?,
+        Some(mint_info) => {
+            account_infos.push(mint_info.clone());
+            transfer_checked(
+                token_program_info.key,
+                source_info.key,
+                mint_info,
+                destination_info.key,
+                authority_info.key,
+                &[authority_info.key],
+                amount,
+            )?
+        }
+    };
+    account_infos.push(destination_info.clone());
+    account_infos.push(authority_info.clone());
+    invoke_signed(&instruction, &account_infos, signers_seeds)
+}
+
+/// Creates a 'CloseAccount' instruction of the given token program
+pub fn close_account(
+    token_program_id: &Pubkey,
+    account_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    signer_pubkeys: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::close_account(
+        &spl_token::id(),
+        account_pubkey,
+        destination_pubkey,
+        owner_pubkey,
+        signer_pubkeys,
+    )?;
+    with_token_program(instruction, token_program_id)
+}
+
+/// Creates an 'Approve' instruction of the given token program
+pub fn approve(
+    token_program_id: &Pubkey,
+    source_pubkey: &Pubkey,
+    delegate_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    signer_pubkeys: &[&Pubkey],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::approve(
+        &spl_token::id(),
+        source_pubkey,
+        delegate_pubkey,
+        owner_pubkey,
+        signer_pubkeys,
+        amount,
+    )?;
+    with_token_program(instruction, token_program_id)
+}
+
+/// Creates an 'InitializeAccount' instruction of the given token program
+pub fn initialize_account(
+    token_program_id: &Pubkey,
+    account_pubkey: &Pubkey,
+    mint_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::initialize_account(
+        &spl_token::id(),
+        account_pubkey,
+        mint_pubkey,
+        owner_pubkey,
+    )?;
+    with_token_program(instruction, token_program_id)
+}
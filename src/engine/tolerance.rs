@@ -0,0 +1,61 @@
+use crate::{
+    engine::constants::MAX_TOLERANCE,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Set Merchant Tolerance
+///
+/// Sets the amount (in basis points of the price) by which chain checkouts of
+/// the merchant may underpay or overpay.  Payments within the tolerance go
+/// through as they are, while the excess of larger overpayments stays with the
+/// buyer.
+pub fn process_set_merchant_tolerance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tolerance: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let mut merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner can set the tolerance
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    check_account_migrated(merchant_account.version, "merchant")?;
+    if tolerance > MAX_TOLERANCE {
+        msg!(
+            "Error: The tolerance must be 0 to {:?} basis points",
+            MAX_TOLERANCE
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // Updating merchant tolerance...
+    merchant_account.tolerance = tolerance;
+    merchant_account.pack(&mut merchant_info.data.borrow_mut());
+
+    Ok(())
+}
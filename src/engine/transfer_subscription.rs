@@ -0,0 +1,46 @@
+use crate::{
+    engine::renew::get_subscription_account,
+    engine::validation::check_signer,
+    error::PaymentProcessorError,
+    state::{Serdes, SubscriptionAccount},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    pubkey::Pubkey,
+};
+
+/// Transfer Subscription
+///
+/// Hands a subscription over to another wallet e.g. when the subscriber rotates
+/// wallets or gifts the subscription, keeping the time that has been paid for.
+/// Both the current owner and the new owner sign.  The address of the
+/// subscription account stays the same.
+pub fn process_transfer_subscription(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_info = next_account_info(account_info_iter)?;
+    let new_owner_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+
+    // ensure both owners can sign
+    check_signer(owner_info)?;
+    check_signer(new_owner_info)?;
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    // ensure only the current owner can hand the subscription over
+    if owner_info.key.to_bytes() != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+
+    // Handing the subscription over to the new owner...
+    subscription_account.owner = new_owner_info.key.to_bytes();
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use crate::{
+    engine::auto_debit::collect_order,
+    engine::common::{
+        check_subscription_not_paused, get_next_order_id, get_package_of_subscription,
+        is_subscription_ended,
+    },
+    engine::json::{OrderSubscription, Package},
+    engine::pay::OrderArgs,
+    engine::renew::get_subscription_account,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_account_migrated, check_program_owned, check_signer},
+    error::PaymentProcessorError,
+    state::{AutoDebitAccount, IsClosed, MerchantAccount, Serdes, SubscriptionAccount},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+/// Get the price of a usage unit of a metered subscription package
+pub fn get_unit_price(package: &Package) -> Result<u64, ProgramError> {
+    match package.unit_price {
+        None => {
+            msg!("Error: The subscription package is not metered");
+            Err(PaymentProcessorError::InvalidSubscriptionPackage.into())
+        }
+        Some(value) => Ok(value),
+    }
+}
+
+/// Get the merchant account of a subscription and its package
+fn get_subscription_merchant_package(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merchant_info: &AccountInfo<'_>,
+    subscription_account: &SubscriptionAccount,
+) -> Result<(MerchantAccount, Package), ProgramError> {
+    if merchant_info.key.to_bytes() != subscription_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        subscription_account,
+        &merchant_account,
+    )?;
+    Ok((merchant_account, package))
+}
+
+/// Record Usage
+///
+/// Lets the merchant owner add usage units to a subscription to a metered
+/// package.  The units accrue on the subscription account until they are settled.
+pub fn process_record_usage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    units: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    if is_subscription_ended(&subscription_account) {
+        msg!("Error: The subscription has been cancelled or has expired");
+        return Err(PaymentProcessorError::SubscriptionExpired.into());
+    }
+    check_subscription_not_paused(&subscription_account)?;
+    check_account_migrated(subscription_account.version, "subscription")?;
+    let (merchant_account, package) = get_subscription_merchant_package(
+        program_id,
+        accounts,
+        merchant_info,
+        &subscription_account,
+    )?;
+    // ensure only the merchant owner can record usage
+    if signer_info.key.to_bytes() != merchant_account.owner {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    get_unit_price(&package)?;
+
+    // Updating subscription account information...
+    subscription_account.usage_units = subscription_account
+        .usage_units
+        .checked_add(units)
+        .ok_or(ProgramError::InvalidArgument)?;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
+
+/// Settle Usage
+///
+/// Lets the merchant owner charge the usage recorded on a subscription once its
+/// period has ended.  The usage units times the unit price of the package are
+/// pulled through the auto-debit account of the subscriber into a new paid order,
+/// like CollectPayment, and the usage of the subscription starts over.
+pub fn process_settle_usage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _signer_info = next_account_info(account_info_iter)?;
+    let _order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let _seller_token_info = next_account_info(account_info_iter)?;
+    let _buyer_token_info = next_account_info(account_info_iter)?;
+    let auto_debit_info = next_account_info(account_info_iter)?;
+    let _program_owner_info = next_account_info(account_info_iter)?;
+    let _sponsor_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let _pda_info = next_account_info(account_info_iter)?;
+    let _token_program_info = next_account_info(account_info_iter)?;
+    let _system_program_info = next_account_info(account_info_iter)?;
+    let _rent_sysvar_info = next_account_info(account_info_iter)?;
+    let _config_info = next_account_info(account_info_iter)?;
+    let subscription_info = next_account_info(account_info_iter)?;
+
+    let mut subscription_account = get_subscription_account(program_id, subscription_info)?;
+    let timestamp = sysvars.unix_timestamp()?;
+    if timestamp < subscription_account.period_end {
+        msg!(
+            "Error: The usage cannot be settled before {:?}",
+            subscription_account.period_end
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if subscription_account.usage_units == 0 {
+        msg!("Error: There is no usage to settle");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let (merchant_account, package) = get_subscription_merchant_package(
+        program_id,
+        accounts,
+        merchant_info,
+        &subscription_account,
+    )?;
+    if package.mint != mint_info.key.to_string() {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    // ensure the payment is pulled from the subscriber
+    check_program_owned(program_id, auto_debit_info, "auto-debit")?;
+    let auto_debit_account = AutoDebitAccount::unpack(&auto_debit_info.data.borrow())?;
+    if auto_debit_account.owner != subscription_account.owner {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    let amount = subscription_account
+        .usage_units
+        .checked_mul(get_unit_price(&package)?)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    let order_subscription = OrderSubscription {
+        subscription: subscription_info.key.to_string(),
+    };
+    collect_order(
+        program_id,
+        accounts,
+        sysvars,
+        OrderArgs {
+            amount,
+            order_id: format!(
+                "{}-usage-{}",
+                subscription_account.name,
+                get_next_order_id(merchant_account.order_sequence)?
+            ),
+            secret: String::from(""),
+            maybe_data: Some(serde_json::to_string(&order_subscription).unwrap()),
+        },
+        true,
+    )?;
+
+    // Updating subscription account information...
+    subscription_account.usage_units = 0;
+    SubscriptionAccount::pack(
+        &subscription_account,
+        &mut subscription_info.data.borrow_mut(),
+    );
+
+    Ok(())
+}
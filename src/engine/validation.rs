@@ -0,0 +1,140 @@
+use crate::{
+    engine::token::unpack_token_account,
+    error::PaymentProcessorError,
+    state::{Discriminator, MerchantAccount, ProgramConfigAccount, Serdes, DISCRIMINATOR},
+};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+
+/// Ensure that an account signed the transaction
+pub fn check_signer(account_info: &AccountInfo<'_>) -> ProgramResult {
+    if !account_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Ensure that an account is owned by this program
+///
+/// The name of the account is only used in the error message.
+pub fn check_program_owned(
+    program_id: &Pubkey,
+    account_info: &AccountInfo<'_>,
+    name: &str,
+) -> ProgramResult {
+    if *account_info.owner != *program_id {
+        msg!("Error: Wrong owner for {} account", name);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Ensure that the data of an account is of the given type, reading its
+/// discriminator in place (see FixedField) rather than unpacking it
+///
+/// Returns the discriminator of the account.
+pub fn check_account_type<T: Serdes>(data: &[u8]) -> Result<u8, ProgramError> {
+    let discriminator = DISCRIMINATOR.get(data)?;
+    if discriminator == Discriminator::Closed as u8 {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if discriminator == Discriminator::Uninitialized as u8 {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if !T::DISCRIMINATORS.contains(&discriminator) {
+        return Err(PaymentProcessorError::WrongAccountType.into());
+    }
+    Ok(discriminator)
+}
+
+/// Ensure that an account is the owner of a merchant account, reading the owner
+/// in place for handlers that need nothing else from the merchant account
+pub fn check_merchant_owner(
+    program_id: &Pubkey,
+    owner_info: &AccountInfo<'_>,
+    merchant_info: &AccountInfo<'_>,
+) -> ProgramResult {
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let data = merchant_info.data.borrow();
+    check_account_type::<MerchantAccount>(&data)?;
+    if owner_info.key.to_bytes() != MerchantAccount::OWNER.get(&data)? {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(())
+}
+
+/// Ensure that an account is no longer in the legacy layout (version 0)
+///
+/// Accounts in the legacy layout have no room for the fields added after it, so
+/// these fields are only set once the account is migrated (see MigrateAccount).
+pub fn check_account_migrated(version: u8, name: &str) -> ProgramResult {
+    if version == 0 {
+        msg!("Error: The {} account has to be migrated first", name);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+/// Get the state of a token account of the given token program, ensuring that
+/// it holds the given mint
+pub fn check_token_account_mint(
+    token_info: &AccountInfo<'_>,
+    token_program_id: &Pubkey,
+    mint: &Pubkey,
+) -> Result<TokenAccount, ProgramError> {
+    let token_data = unpack_token_account(token_info, token_program_id)?;
+    if token_data.mint != *mint {
+        return Err(PaymentProcessorError::MintNotEqual.into());
+    }
+    Ok(token_data)
+}
+
+/// Ensure that an account is the program derived address of the given seeds
+///
+/// Returns the address and its bump seed.
+pub fn check_pda(
+    program_id: &Pubkey,
+    pda_info: &AccountInfo<'_>,
+    seeds: &[&[u8]],
+) -> Result<(Pubkey, u8), ProgramError> {
+    let (pda, bump_seed) = Pubkey::find_program_address(seeds, program_id);
+    if *pda_info.key != pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok((pda, bump_seed))
+}
+
+/// Ensure that an account is the given sysvar
+pub fn check_sysvar(account_info: &AccountInfo<'_>, sysvar_id: &Pubkey) -> ProgramResult {
+    if *account_info.key != *sysvar_id {
+        msg!("Error: Expected the {:?} sysvar", sysvar_id);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Ensure that an account is the program owner of the program config
+pub fn check_program_owner(
+    program_owner_info: &AccountInfo<'_>,
+    config_account: &ProgramConfigAccount,
+) -> ProgramResult {
+    if program_owner_info.key.to_bytes() != config_account.owner {
+        return Err(PaymentProcessorError::WrongProgramOwner.into());
+    }
+    Ok(())
+}
+
+/// Ensure that an account is the sponsor of a merchant
+pub fn check_sponsor(
+    sponsor_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+) -> ProgramResult {
+    if sponsor_info.key.to_bytes() != merchant_account.sponsor {
+        msg!("Error: Sponsor account is incorrect");
+        return Err(PaymentProcessorError::WrongSponsor.into());
+    }
+    Ok(())
+}
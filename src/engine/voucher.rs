@@ -0,0 +1,220 @@
+use crate::{
+    engine::constants::{ACCOUNT_VERSION, ED25519_PROGRAM_ID, VOUCHER},
+    engine::pay::{process_order, OrderArgs},
+    engine::sweep::find_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_sysvar},
+    error::PaymentProcessorError,
+    instruction::Voucher,
+    state::{Discriminator, IsClosed, MerchantAccount, Serdes, VoucherAccount},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{
+        self,
+        instructions::{load_current_index, load_instruction_at},
+        rent::Rent,
+        Sysvar,
+    },
+};
+use std::str::FromStr;
+
+/// the number of accounts that are passed on to process_order
+const ORDER_ACCOUNTS_LEN: usize = 13;
+
+/// Get the address of the nonce account of a voucher
+pub fn get_voucher_address(program_id: &Pubkey, merchant: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VOUCHER.as_bytes(), &merchant.to_bytes(), &nonce.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Get the public key and the message verified by an ed25519 program instruction
+///
+/// Only instructions that verify a single signature whose public key, signature
+/// and message are all found in the instruction itself are accepted.
+fn get_ed25519_verified_message(instruction: &Instruction) -> Option<(Pubkey, Vec<u8>)> {
+    let data = &instruction.data;
+    let read_u16 = |index: usize| -> Option<u16> {
+        Some(u16::from_le_bytes([*data.get(index)?, *data.get(index + 1)?]))
+    };
+    if *data.first()? != 1 {
+        return None;
+    }
+    let signature_instruction_index = read_u16(4)?;
+    let public_key_offset = read_u16(6)? as usize;
+    let public_key_instruction_index = read_u16(8)?;
+    let message_data_offset = read_u16(10)? as usize;
+    let message_data_size = read_u16(12)? as usize;
+    let message_instruction_index = read_u16(14)?;
+    if signature_instruction_index != u16::MAX
+        || public_key_instruction_index != u16::MAX
+        || message_instruction_index != u16::MAX
+    {
+        return None;
+    }
+    let public_key = data.get(public_key_offset..public_key_offset + 32)?;
+    let message = data.get(message_data_offset..message_data_offset + message_data_size)?;
+    Some((Pubkey::new(public_key), message.to_vec()))
+}
+
+/// Redeem Voucher
+///
+/// Pays for a voucher that was signed by the merchant owner while offline.  The
+/// voucher signature is checked by the ed25519 program in the instruction right
+/// before this one, and the nonce account ensures that each voucher is redeemed
+/// only once.
+pub fn process_redeem_voucher(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    voucher: Voucher,
+) -> ProgramResult {
+    if accounts.len() < ORDER_ACCOUNTS_LEN {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (order_accounts, voucher_accounts) = accounts.split_at(ORDER_ACCOUNTS_LEN);
+    let signer_info = &order_accounts[0];
+    let order_info = &order_accounts[1];
+    let merchant_info = &order_accounts[2];
+    let mint_info = &order_accounts[7];
+    let system_program_info = &order_accounts[10];
+    let rent_sysvar_info = &order_accounts[11];
+
+    let account_info_iter = &mut voucher_accounts.iter();
+    let voucher_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure merchant account is owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure the voucher is for this merchant and currency
+    if voucher.merchant != *merchant_info.key {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    if voucher.mint != *mint_info.key {
+        return Err(PaymentProcessorError::WrongMint.into());
+    }
+    if timestamp >= voucher.expires_at {
+        msg!("Error: Voucher expired at {:?}", voucher.expires_at);
+        return Err(PaymentProcessorError::OrderExpired.into());
+    }
+    // ensure the voucher was signed by the merchant owner
+    check_sysvar(instructions_sysvar_info, &sysvar::instructions::id())?;
+    let instructions_data = instructions_sysvar_info.data.borrow();
+    let current_index = load_current_index(&instructions_data) as usize;
+    if current_index == 0 {
+        return Err(PaymentProcessorError::VoucherSignatureInvalid.into());
+    }
+    let signature_instruction = load_instruction_at(current_index - 1, &instructions_data)
+        .map_err(|_error| ProgramError::InvalidAccountData)?;
+    if signature_instruction.program_id != Pubkey::from_str(ED25519_PROGRAM_ID).unwrap() {
+        return Err(PaymentProcessorError::VoucherSignatureInvalid.into());
+    }
+    match get_ed25519_verified_message(&signature_instruction) {
+        Some((public_key, message))
+            if public_key.to_bytes() == merchant_account.owner && message == voucher.message() => {}
+        _ => return Err(PaymentProcessorError::VoucherSignatureInvalid.into()),
+    }
+    // ensure the voucher has not been redeemed before
+    let (voucher_address, bump_seed) =
+        get_voucher_address(program_id, merchant_info.key, voucher.nonce);
+    if voucher_address != *voucher_info.key {
+        msg!("Error: Voucher address does not match seed derivation");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *voucher_info.owner == *program_id {
+        return Err(PaymentProcessorError::VoucherAlreadyRedeemed.into());
+    }
+    let merchant_bytes = merchant_info.key.to_bytes();
+    let nonce_bytes = voucher.nonce.to_le_bytes();
+    let signer_seeds: &[&[_]] = &[
+        VOUCHER.as_bytes(),
+        &merchant_bytes,
+        &nonce_bytes,
+        &[bump_seed],
+    ];
+
+    let account_size = VoucherAccount::MIN_LEN;
+    // Fund the voucher account with the minimum balance to be rent exempt
+    invoke(
+        &system_instruction::transfer(
+            signer_info.key,
+            voucher_info.key,
+            Rent::default().minimum_balance(account_size),
+        ),
+        &[
+            signer_info.clone(),
+            voucher_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    // Allocate space for the voucher account
+    invoke_signed(
+        &system_instruction::allocate(voucher_info.key, account_size as u64),
+        &[voucher_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    // Assign the voucher account to the SolPayments program
+    invoke_signed(
+        &system_instruction::assign(voucher_info.key, program_id),
+        &[voucher_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+
+    let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+    // Saving voucher information...
+    let voucher_account = VoucherAccount {
+        discriminator: Discriminator::Voucher as u8,
+        merchant: merchant_bytes,
+        order: order_info.key.to_bytes(),
+        nonce: voucher.nonce,
+        redeemed: timestamp,
+        version: ACCOUNT_VERSION,
+    };
+    voucher_account.pack(&mut voucher_info.try_borrow_mut_data()?);
+
+    // ensure voucher account is rent exempt
+    if !rent.is_exempt(voucher_info.lamports(), account_size) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    // the sweep bounty account of the merchant (if any) goes along with the order accounts
+    let order_accounts: Vec<AccountInfo> = order_accounts
+        .iter()
+        .chain(find_sweep_bounty(program_id, voucher_accounts))
+        .cloned()
+        .collect();
+    // Paying for the voucher...
+    process_order(
+        program_id,
+        &order_accounts,
+        sysvars,
+        OrderArgs {
+            amount: voucher.amount,
+            order_id: voucher.nonce.to_string(),
+            secret: String::from(""),
+            maybe_data: Option::None,
+        },
+        0,
+        Option::None,
+    )
+}
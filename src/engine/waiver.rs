@@ -0,0 +1,123 @@
+use crate::{
+    engine::common::get_order_fees,
+    engine::config::get_program_config,
+    engine::constants::FEE_WAIVER_PERIOD,
+    engine::sysvars::SysvarProvider,
+    engine::validation::{check_program_owned, check_signer, check_sponsor},
+    error::PaymentProcessorError,
+    state::{IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+};
+
+/// Waive Order Fees
+///
+/// Returns the program owner and sponsor fees of an order that was refunded in
+/// full shortly after it was paid, so that failed sales are not charged for.
+pub fn process_waive_order_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let sponsor_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let config_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure only the program owner can waive the fees
+    let config_account = get_program_config(program_id, config_info)?;
+    if signer_info.key.to_bytes() != config_account.owner {
+        return Err(PaymentProcessorError::WrongProgramOwner.into());
+    }
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    // get the merchant account
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // ensure the fees go back to the order payer
+    if payer_info.key.to_bytes() != order_account.payer {
+        return Err(PaymentProcessorError::WrongPayer.into());
+    }
+    check_sponsor(sponsor_info, &merchant_account)?;
+    // ensure the order was refunded in full
+    if order_account.status != OrderStatus::Expired as u8
+        && order_account.status != OrderStatus::Refunded as u8
+    {
+        msg!("Error: Only refunded orders can have their fees waived");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    // ensure the order was refunded shortly after it was paid
+    if order_account.modified - order_account.created > FEE_WAIVER_PERIOD {
+        msg!(
+            "Error: Fees can only be waived for orders refunded within {:?} seconds",
+            FEE_WAIVER_PERIOD
+        );
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    let fees = match get_order_fees(&order_account) {
+        None => return Err(PaymentProcessorError::InvalidOrderData.into()),
+        Some(value) => value,
+    };
+
+    // Returning the program owner fee to the payer...
+    invoke(
+        &system_instruction::transfer(signer_info.key, payer_info.key, fees.program_owner),
+        &[
+            signer_info.clone(),
+            payer_info.clone(),
+            system_program_info.clone(),
+        ],
+    )?;
+    if fees.sponsor > 0 {
+        // the sponsor has to agree to give back its share of the fee
+        check_signer(sponsor_info)?;
+        // Returning the sponsor fee to the payer...
+        invoke(
+            &system_instruction::transfer(sponsor_info.key, payer_info.key, fees.sponsor),
+            &[
+                sponsor_info.clone(),
+                payer_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+
+    // Updating order account information...
+    order_account.status = OrderStatus::FeesWaived as u8;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    Ok(())
+}
@@ -1,28 +1,226 @@
 use crate::{
-    engine::common::{get_subscription_package, transfer_sol, verify_subscription_order},
+    engine::associated_token::create_associated_token_account,
+    engine::common::{
+        check_subscription_not_paused, get_order_balance, get_order_escrow_timeout,
+        get_order_received_amount, get_package_of_subscription, is_order_expired,
+        transfer_order_tokens, transfer_sol, update_merchant_references, verify_subscription_order,
+    },
     engine::constants::PDA_SEED,
+    engine::json::OrderSubscription,
+    engine::operators::is_withdrawal_authority,
+    engine::payout::get_payouts,
+    engine::referral::{find_referral_token, get_order_referral, get_referral_amount},
+    engine::stats::{find_merchant_stats, record_token_accounts},
+    engine::store::is_store_fee_destination,
+    engine::sweep::return_sweep_bounty,
+    engine::sysvars::SysvarProvider,
+    engine::token::{
+        self, check_token_program, find_mint, has_withheld_transfer_fees, invoke_transfer,
+    },
+    engine::validation::{
+        check_account_migrated, check_pda, check_program_owned, check_signer,
+        check_token_account_mint,
+    },
     error::PaymentProcessorError,
+    events::{emit_event, PaymentProcessorEvent},
     state::{
         Discriminator, IsClosed, MerchantAccount, OrderAccount, OrderStatus, Serdes,
         SubscriptionAccount,
     },
 };
-use solana_program::program_pack::Pack;
+use serde_json::Error as JSONError;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
     entrypoint::ProgramResult,
     msg,
     program::invoke_signed,
     program_error::ProgramError,
     program_pack::IsInitialized,
     pubkey::Pubkey,
-    sysvar::{clock::Clock, Sysvar},
 };
-use spl_token::{self, state::Account as TokenAccount};
+
+/// Ensure that the payment of an order can be withdrawn
+///
+/// i.e. the order is fully paid, not yet withdrawn, not expired and not held
+/// in escrow.
+pub fn check_order_withdrawable(
+    order_account: &OrderAccount,
+    timestamp: UnixTimestamp,
+) -> ProgramResult {
+    // ensure order is not already paid out
+    if order_account.status == OrderStatus::Expired as u8 {
+        return Err(PaymentProcessorError::OrderExpired.into());
+    }
+    // ensure orders paid in installments are fully funded
+    if order_account.status == OrderStatus::Pending as u8
+        || get_order_received_amount(order_account)? < order_account.expected_amount
+    {
+        return Err(PaymentProcessorError::NotFullyPaid.into());
+    }
+    if order_account.status != OrderStatus::Paid as u8
+        && order_account.status != OrderStatus::Releasable as u8
+    {
+        return Err(PaymentProcessorError::AlreadyWithdrawn.into());
+    }
+    if order_account.status == OrderStatus::Paid as u8 {
+        // expired orders can only be reclaimed by the payer
+        if is_order_expired(order_account, timestamp) {
+            msg!("Error: Order expired");
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+        // escrow orders are held until the buyer confirms delivery or the timeout elapses
+        if let Some(escrow_timeout) = get_order_escrow_timeout(order_account) {
+            let released_at = order_account
+                .created
+                .checked_add(escrow_timeout)
+                .ok_or(PaymentProcessorError::AmountOverflow)?;
+            if timestamp < released_at {
+                return Err(PaymentProcessorError::OrderNotReleasable.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ensure that the subscription that an order was made for is past its trial period
+/// and is not paused
+pub fn check_trial_period(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    subscription_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+    order_account: &OrderAccount,
+    timestamp: UnixTimestamp,
+) -> ProgramResult {
+    // ensure subscription account is owned by this program
+    check_program_owned(program_id, subscription_info, "subscription")?;
+    // ensure this order is for this subscription
+    verify_subscription_order(subscription_info, order_account)?;
+    // get the subscription account
+    let subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
+    if subscription_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !subscription_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let package = get_package_of_subscription(
+        program_id,
+        accounts,
+        &subscription_account,
+        merchant_account,
+    )?;
+    // get the trial period duration
+    let trial_duration: i64 = package.trial.unwrap_or_default();
+    let trial_end = subscription_account
+        .joined
+        .checked_add(trial_duration)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    // don't allow withdrawal if still within trial period
+    if timestamp < trial_end {
+        return Err(PaymentProcessorError::CantWithdrawDuringTrial.into());
+    }
+    // the rest of the period of a paused subscription can still be refunded
+    check_subscription_not_paused(&subscription_account)?;
+    Ok(())
+}
+
+/// Get the wallet that the payment of an order is withdrawn to
+///
+/// i.e. the owner of the merchant token account, which must be the merchant
+/// owner, or the fee destination of the store that the order was made at.
+fn get_merchant_wallet(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    merchant_info: &AccountInfo<'_>,
+    merchant_account: &MerchantAccount,
+    order_account: &OrderAccount,
+    merchant_token_info: &AccountInfo<'_>,
+    token_program_info: &AccountInfo<'_>,
+) -> Result<Pubkey, ProgramError> {
+    let merchant_token_data = check_token_account_mint(
+        merchant_token_info,
+        token_program_info.key,
+        &Pubkey::new_from_array(order_account.mint),
+    )?;
+    // the payment of an order made at a store can also go to the fee destination of the store
+    let merchant_wallet = merchant_token_data.owner;
+    if merchant_wallet != Pubkey::new_from_array(merchant_account.owner)
+        && !is_store_fee_destination(
+            program_id,
+            accounts,
+            merchant_info,
+            order_account,
+            &merchant_wallet,
+        )?
+    {
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    Ok(merchant_wallet)
+}
+
+/// The accounts that a withdrawal takes the tokens of an order from
+struct WithdrawalSource<'a, 'b> {
+    order_payment_token: &'a AccountInfo<'b>,
+    /// the mint of the order, only needed to move tokens with TransferChecked
+    possible_mint: Option<&'a AccountInfo<'b>>,
+    pda: &'a AccountInfo<'b>,
+    pda_nonce: u8,
+    token_program: &'a AccountInfo<'b>,
+}
+
+/// Transfer an amount withdrawn from the order token account to the merchant
+///
+/// The amount is split among the wallets of the payout schedule (if any) of
+/// the merchant, unless it goes to the fee destination of a store.
+fn transfer_withdrawal<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    merchant_account: &MerchantAccount,
+    order_account: &OrderAccount,
+    source: &WithdrawalSource<'a, 'b>,
+    merchant_token_info: &'a AccountInfo<'b>,
+    merchant_wallet: &Pubkey,
+    amount: u64,
+) -> ProgramResult {
+    let WithdrawalSource {
+        order_payment_token: order_payment_token_info,
+        possible_mint: possible_mint_info,
+        pda: pda_info,
+        pda_nonce,
+        token_program: token_program_info,
+    } = *source;
+    let mut payouts = vec![];
+    if merchant_wallet.to_bytes() == merchant_account.owner {
+        payouts = get_payouts(
+            accounts,
+            merchant_account,
+            &order_account.mint,
+            token_program_info,
+            amount,
+        )?;
+    }
+    if payouts.is_empty() {
+        payouts.push((merchant_token_info, amount));
+    }
+    for (payout_token_info, payout_amount) in payouts {
+        invoke_transfer(
+            token_program_info,
+            order_payment_token_info,
+            possible_mint_info,
+            payout_token_info,
+            pda_info,
+            payout_amount,
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+    }
+    Ok(())
+}
 
 pub fn process_withdraw_payment(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
     close_order_account: bool,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
@@ -35,31 +233,15 @@ pub fn process_withdraw_payment(
     let pda_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
 
-    let timestamp = Clock::get()?.unix_timestamp;
+    let timestamp = sysvars.unix_timestamp()?;
 
     // ensure signer can sign
-    if !signer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    check_signer(signer_info)?;
     // ensure merchant and order accounts are owned by this program
-    if *merchant_info.owner != *program_id {
-        msg!("Error: Wrong owner for merchant account");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    if *order_info.owner != *program_id {
-        msg!("Error: Wrong owner for order account");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    // ensure buyer token account is owned by token program
-    if *merchant_token_info.owner != spl_token::id() {
-        msg!("Error: Token account must be owned by token program");
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
     // check that provided pda is correct
-    let (pda, pda_nonce) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
-    if pda_info.key != &pda {
-        return Err(ProgramError::InvalidSeeds);
-    }
+    let (pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
     // get the merchant account
     let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
     if merchant_account.is_closed() {
@@ -68,13 +250,6 @@ pub fn process_withdraw_payment(
     if !merchant_account.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
-    // ensure that the token account that we will withdraw to is owned by this
-    // merchant.  This ensures that anyone can call the withdraw instruction
-    // and the money will still go to the right place
-    let merchant_token_data = TokenAccount::unpack(&merchant_token_info.data.borrow())?;
-    if merchant_token_data.owner != Pubkey::new_from_array(merchant_account.owner) {
-        return Err(PaymentProcessorError::WrongMerchant.into());
-    }
     // get the order account
     let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
     if order_account.is_closed() {
@@ -89,82 +264,161 @@ pub fn process_withdraw_payment(
     }
     // ensure the order payment token account is the right one
     if order_payment_token_info.key.to_bytes() != order_account.token {
-        return Err(ProgramError::InvalidAccountData);
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
     }
-    // ensure order is not already paid out
-    if order_account.status != OrderStatus::Paid as u8 {
-        return Err(PaymentProcessorError::AlreadyWithdrawn.into());
+    // the merchant's associated token account is created if it does not exist yet,
+    // in which case the accounts needed to create it follow
+    check_token_program(token_program_info)?;
+    if *merchant_token_info.owner != *token_program_info.key && merchant_token_info.data_is_empty()
+    {
+        let merchant_wallet_info = next_account_info(account_info_iter)?;
+        let mint_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let associated_token_program_info = next_account_info(account_info_iter)?;
+        if merchant_wallet_info.key.to_bytes() != merchant_account.owner {
+            return Err(PaymentProcessorError::WrongMerchant.into());
+        }
+        if mint_info.key.to_bytes() != order_account.mint {
+            return Err(PaymentProcessorError::WrongMint.into());
+        }
+        create_associated_token_account(&[
+            signer_info.clone(),
+            merchant_token_info.clone(),
+            merchant_wallet_info.clone(),
+            mint_info.clone(),
+            system_program_info.clone(),
+            token_program_info.clone(),
+            rent_sysvar_info.clone(),
+            associated_token_program_info.clone(),
+        ])?;
     }
+    // ensure merchant token account is owned by token program and that the
+    // token account that we will withdraw to is owned by this merchant.  This
+    // ensures that anyone can call the withdraw instruction and the money will
+    // still go to the right place
+    let merchant_wallet = get_merchant_wallet(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        &order_account,
+        merchant_token_info,
+        token_program_info,
+    )?;
+    check_order_withdrawable(&order_account, timestamp)?;
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
     // check if this is for a subscription payment that has a trial period
     if merchant_account.discriminator == Discriminator::MerchantSubscriptionWithTrial as u8 {
         let subscription_info = next_account_info(account_info_iter)?;
-        // ensure subscription account is owned by this program
-        if *subscription_info.owner != *program_id {
-            msg!("Error: Wrong owner for subscription account");
-            return Err(ProgramError::IncorrectProgramId);
-        }
-        // ensure this order is for this subscription
-        verify_subscription_order(subscription_info, &order_account)?;
-        // get the subscription account
-        let subscription_account = SubscriptionAccount::unpack(&subscription_info.data.borrow())?;
-        if subscription_account.is_closed() {
-            return Err(PaymentProcessorError::ClosedAccount.into());
-        }
-        if !subscription_account.is_initialized() {
-            return Err(ProgramError::UninitializedAccount);
-        }
-        let package = get_subscription_package(&subscription_account.name, &merchant_account)?;
-        // get the trial period duration
-        let trial_duration: i64 = match package.trial {
-            None => 0,
-            Some(value) => value,
-        };
-        // don't allow withdrawal if still within trial period
-        if timestamp < (subscription_account.joined + trial_duration) {
-            return Err(PaymentProcessorError::CantWithdrawDuringTrial.into());
+        check_trial_period(
+            program_id,
+            accounts,
+            subscription_info,
+            &merchant_account,
+            &order_account,
+            timestamp,
+        )?;
+    }
+    // Transferring the referral share of the payment (if any) to the referrer...
+    let mut referral_amount = 0;
+    if let Some(order_referral) = get_order_referral(&order_account) {
+        referral_amount = get_referral_amount(order_account.paid_amount, &order_referral);
+        let referral_token_info = find_referral_token(
+            accounts,
+            &order_referral,
+            &order_account,
+            token_program_info,
+        )?;
+        if referral_amount > 0 {
+            msg!(
+                "Info: Paying {:?} to the referrer {:?}",
+                referral_amount,
+                order_referral.referrer
+            );
+            invoke_transfer(
+                token_program_info,
+                order_payment_token_info,
+                possible_mint_info,
+                referral_token_info,
+                pda_info,
+                referral_amount,
+                &[&[PDA_SEED, &[pda_nonce]]],
+            )?;
         }
     }
-    // Transferring payment to the merchant...
-    invoke_signed(
-        &spl_token::instruction::transfer(
-            token_program_info.key,
-            order_payment_token_info.key,
-            merchant_token_info.key,
-            &pda,
-            &[&pda],
+    // Transferring payment (and tip) to the merchant...
+    if order_account.tip > 0 {
+        msg!(
+            "Info: Withdrawing {:?} plus a tip of {:?}",
             order_account.paid_amount,
-        )
-        .unwrap(),
-        &[
-            token_program_info.clone(),
-            order_payment_token_info.clone(),
-            merchant_token_info.clone(),
-            pda_info.clone(),
-        ],
-        &[&[&PDA_SEED, &[pda_nonce]]],
+            order_account.tip
+        );
+    }
+    // the part of the payment already withdrawn with WithdrawAmount (if any) is left out
+    let withdrawal_amount = get_order_balance(&order_account)?
+        .checked_sub(referral_amount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    transfer_withdrawal(
+        accounts,
+        &merchant_account,
+        &order_account,
+        &WithdrawalSource {
+            order_payment_token: order_payment_token_info,
+            possible_mint: possible_mint_info,
+            pda: pda_info,
+            pda_nonce,
+            token_program: token_program_info,
+        },
+        merchant_token_info,
+        &merchant_wallet,
+        withdrawal_amount,
     )?;
+    let mut closed = 0;
     // Close the order token account since it will never be needed again
-    invoke_signed(
-        &spl_token::instruction::close_account(
-            token_program_info.key,
-            order_payment_token_info.key,
-            account_to_receive_sol_refund_info.key,
-            &pda,
-            &[&pda],
-        )
-        .unwrap(),
-        &[
-            token_program_info.clone(),
-            order_payment_token_info.clone(),
-            account_to_receive_sol_refund_info.clone(),
-            pda_info.clone(),
-        ],
-        &[&[&PDA_SEED, &[pda_nonce]]],
+    if !has_withheld_transfer_fees(order_payment_token_info) {
+        invoke_signed(
+            &token::close_account(
+                token_program_info.key,
+                order_payment_token_info.key,
+                account_to_receive_sol_refund_info.key,
+                &pda,
+                &[&pda],
+            )
+            .unwrap(),
+            &[
+                token_program_info.clone(),
+                order_payment_token_info.clone(),
+                account_to_receive_sol_refund_info.clone(),
+                pda_info.clone(),
+            ],
+            &[&[PDA_SEED, &[pda_nonce]]],
+        )?;
+        closed += 1;
+    }
+    // Transferring the amounts paid in other mints (if any) to the merchant...
+    closed += transfer_order_tokens(
+        &order_account,
+        account_info_iter,
+        Some(&merchant_wallet),
+        account_to_receive_sol_refund_info,
+        pda_info,
+        token_program_info,
+        pda_nonce,
+    )?;
+    // count the closed order token accounts towards the open token accounts of the merchant
+    record_token_accounts(
+        program_id,
+        find_merchant_stats(program_id, account_info_iter),
+        &order_account.merchant,
+        0,
+        closed,
     )?;
 
     if close_order_account {
-        if merchant_account.owner != signer_info.key.to_bytes() {
-            msg!("Error: Only merchant account owner can close order account");
+        if !is_withdrawal_authority(&merchant_account, signer_info.key) {
+            msg!("Error: Only merchant account owner or operators can close order account");
             return Err(ProgramError::MissingRequiredSignature);
         }
         // mark account as closed
@@ -177,10 +431,169 @@ pub fn process_withdraw_payment(
         )?;
     }
 
+    // the withdrawn order no longer counts towards the references of the merchant
+    update_merchant_references(merchant_info, false)?;
+
+    // the bounty the order set aside for the sweep crank goes back to the program owner
+    return_sweep_bounty(program_id, accounts, &mut order_account)?;
     // Updating order account information...
     order_account.status = OrderStatus::Withdrawn as u8;
     order_account.modified = timestamp;
     OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
 
+    emit_event(&PaymentProcessorEvent::OrderWithdrawn {
+        order: order_info.key.to_bytes(),
+        merchant: order_account.merchant,
+        amount: withdrawal_amount,
+    });
+
+    Ok(())
+}
+
+/// Withdraw Amount
+///
+/// Lets the merchant owner (or an operator) withdraw part of the payment of an
+/// order, e.g. the earned portion of a milestone order or the installments paid
+/// so far.  The rest stays in the order token account, where it can be
+/// withdrawn (with WithdrawAmount or Withdraw) or refunded later.
+pub fn process_withdraw_amount(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    sysvars: &dyn SysvarProvider,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let signer_info = next_account_info(account_info_iter)?;
+    let order_info = next_account_info(account_info_iter)?;
+    let merchant_info = next_account_info(account_info_iter)?;
+    let order_payment_token_info = next_account_info(account_info_iter)?;
+    let merchant_token_info = next_account_info(account_info_iter)?;
+    let pda_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    let timestamp = sysvars.unix_timestamp()?;
+
+    // ensure signer can sign
+    check_signer(signer_info)?;
+    // ensure merchant and order accounts are owned by this program
+    check_program_owned(program_id, merchant_info, "merchant")?;
+    check_program_owned(program_id, order_info, "order")?;
+    // check that provided pda is correct
+    let (_pda, pda_nonce) = check_pda(program_id, pda_info, &[PDA_SEED])?;
+    // get the merchant account
+    let merchant_account = MerchantAccount::unpack(&merchant_info.data.borrow())?;
+    if merchant_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !merchant_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure only the merchant owner (or an operator) can choose the amount withdrawn
+    if !is_withdrawal_authority(&merchant_account, signer_info.key) {
+        msg!("Error: Only merchant account owner or operators can withdraw part of an order");
+        return Err(PaymentProcessorError::WrongMerchant.into());
+    }
+    // get the order account
+    let mut order_account = OrderAccount::unpack(&order_info.data.borrow())?;
+    if order_account.is_closed() {
+        return Err(PaymentProcessorError::ClosedAccount.into());
+    }
+    if !order_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // ensure order belongs to this merchant
+    if merchant_info.key.to_bytes() != order_account.merchant {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // orders in the legacy layout have no room for the amount withdrawn so far
+    check_account_migrated(order_account.version, "order")?;
+    // ensure the order payment token account is the right one
+    if order_payment_token_info.key.to_bytes() != order_account.token {
+        return Err(PaymentProcessorError::SellerAccountMismatch.into());
+    }
+    check_token_program(token_program_info)?;
+    let merchant_wallet = get_merchant_wallet(
+        program_id,
+        accounts,
+        merchant_info,
+        &merchant_account,
+        &order_account,
+        merchant_token_info,
+        token_program_info,
+    )?;
+    let mut balance = get_order_balance(&order_account)?;
+    if order_account.status == OrderStatus::Pending as u8 {
+        // the installments paid so far can be withdrawn from before the order is fully paid
+        if is_order_expired(&order_account, timestamp) {
+            msg!("Error: Order expired");
+            return Err(PaymentProcessorError::OrderExpired.into());
+        }
+        if get_order_escrow_timeout(&order_account).is_some() {
+            return Err(PaymentProcessorError::OrderNotReleasable.into());
+        }
+    } else {
+        match check_order_withdrawable(&order_account, timestamp) {
+            // the part of an escrow order released by the buyer can be withdrawn
+            // while the rest is held
+            Err(error)
+                if error == PaymentProcessorError::OrderNotReleasable.into()
+                    && order_account.released_amount > order_account.withdrawn_amount =>
+            {
+                balance = order_account.released_amount - order_account.withdrawn_amount;
+            }
+            result => result?,
+        }
+    }
+    // the referral share and the prorated refunds of subscriptions are worked
+    // out from the whole payment, so these orders are withdrawn in full
+    let order_subscription: Result<OrderSubscription, JSONError> =
+        serde_json::from_str(&order_account.data);
+    if get_order_referral(&order_account).is_some() || order_subscription.is_ok() {
+        msg!("Error: Orders with a referrer or made for a subscription are withdrawn in full");
+        return Err(PaymentProcessorError::InvalidOrder.into());
+    }
+    if amount == 0 || amount > balance {
+        msg!("Error: The amount withdrawn must be 1 to {:?}", balance);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // the mint of the order is only needed to move tokens with TransferChecked
+    let possible_mint_info = find_mint(accounts, &Pubkey::new_from_array(order_account.mint));
+    // Transferring part of the payment to the merchant...
+    msg!(
+        "Info: Withdrawing {:?}, {:?} is left in the order",
+        amount,
+        balance - amount
+    );
+    transfer_withdrawal(
+        accounts,
+        &merchant_account,
+        &order_account,
+        &WithdrawalSource {
+            order_payment_token: order_payment_token_info,
+            possible_mint: possible_mint_info,
+            pda: pda_info,
+            pda_nonce,
+            token_program: token_program_info,
+        },
+        merchant_token_info,
+        &merchant_wallet,
+        amount,
+    )?;
+
+    // Updating order account information...
+    order_account.withdrawn_amount = order_account
+        .withdrawn_amount
+        .checked_add(amount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    order_account.modified = timestamp;
+    OrderAccount::pack(&order_account, &mut order_info.data.borrow_mut());
+
+    emit_event(&PaymentProcessorEvent::OrderWithdrawn {
+        order: order_info.key.to_bytes(),
+        merchant: order_account.merchant,
+        amount,
+    });
+
     Ok(())
 }
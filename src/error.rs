@@ -64,6 +64,72 @@ pub enum PaymentProcessorError {
     /// The Provided mint Is Wrong
     #[error("Error: The Provided mint Is Wrong")]
     WrongMint,
+    /// The Order Has Expired
+    #[error("Error: The Order Has Expired")]
+    OrderExpired,
+    /// The Order Has Not Expired
+    #[error("Error: The Order Has Not Expired")]
+    OrderNotExpired,
+    /// The Order Is Held In Escrow
+    #[error("Error: The Order Is Held In Escrow")]
+    OrderNotReleasable,
+    /// The Instance Is Not Trusted
+    #[error("Error: The Instance Is Not Trusted")]
+    UntrustedInstance,
+    /// The Payment Split Is Invalid
+    #[error("Error: The Payment Split Is Invalid")]
+    InvalidSplit,
+    /// The Voucher Has Already Been Redeemed
+    #[error("Error: The Voucher Has Already Been Redeemed")]
+    VoucherAlreadyRedeemed,
+    /// The Voucher Signature Is Invalid
+    #[error("Error: The Voucher Signature Is Invalid")]
+    VoucherSignatureInvalid,
+    /// Payments Are Paused While Accounts Are Migrated
+    #[error("Error: Payments Are Paused While Accounts Are Migrated")]
+    MigrationInProgress,
+    /// The Account Version Is Not Supported
+    #[error("Error: The Account Version Is Not Supported")]
+    UnsupportedVersion,
+    /// The Coupon Has No Uses Left
+    #[error("Error: The Coupon Has No Uses Left")]
+    CouponExhausted,
+    /// The Coupon Has Expired
+    #[error("Error: The Coupon Has Expired")]
+    CouponExpired,
+    /// The Merchant Is Paused
+    #[error("Error: The Merchant Is Paused")]
+    MerchantPaused,
+    /// The Buyer Is Blocked By The Merchant
+    #[error("Error: The Buyer Is Blocked By The Merchant")]
+    BuyerBlocked,
+    /// The Subscription Is Paused
+    #[error("Error: The Subscription Is Paused")]
+    SubscriptionPaused,
+    /// The Account Is Of The Wrong Type
+    #[error("Error: The Account Is Of The Wrong Type")]
+    WrongAccountType,
+    /// The Amount Paid Is Less Than The Price
+    #[error("Error: The Amount Paid Is Less Than The Price")]
+    InsufficientPaid,
+    /// The Subscription Has Been Cancelled Or Has Expired
+    #[error("Error: The Subscription Has Been Cancelled Or Has Expired")]
+    SubscriptionExpired,
+    /// The Signer Does Not Own The Order
+    #[error("Error: The Signer Does Not Own The Order")]
+    NotOrderOwner,
+    /// The Order Token Account Does Not Match The Order
+    #[error("Error: The Order Token Account Does Not Match The Order")]
+    SellerAccountMismatch,
+    /// An Amount Is Out Of Range
+    #[error("Error: An Amount Is Out Of Range")]
+    AmountOverflow,
+    /// The Mint Has A Freeze Authority
+    #[error("Error: The Mint Has A Freeze Authority")]
+    FreezableMint,
+    /// The Signer Is Not The Upgrade Authority Of The Program
+    #[error("Error: The Signer Is Not The Upgrade Authority Of The Program")]
+    NotUpgradeAuthority,
 }
 
 impl From<PaymentProcessorError> for ProgramError {
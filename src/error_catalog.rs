@@ -0,0 +1,346 @@
+//! Error message catalog for clients
+//!
+//! Meant to be used off-chain by checkout UIs to turn the custom error codes
+//! returned by the program into localized messages.  The catalog is generated
+//! from PaymentProcessorError so that it always covers every error code.
+
+use crate::error::PaymentProcessorError;
+use num_traits::FromPrimitive;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// the locale used when no message is available in the requested locale
+pub const DEFAULT_LOCALE: &str = "en";
+/// the prefix of the i18n keys of the error messages
+pub const I18N_KEY_PREFIX: &str = "sol_payments.error";
+
+/// Spanish messages, by error name
+const ES_MESSAGES: &[(&str, &str)] = &[
+    ("AlreadyWithdrawn", "El importe ya ha sido retirado"),
+    (
+        "CantWithdrawDuringTrial",
+        "No se puede retirar durante el periodo de prueba",
+    ),
+    ("ClosedAccount", "La cuenta ya está cerrada"),
+    ("CouponExhausted", "El cupón no tiene usos restantes"),
+    ("CouponExpired", "El cupón ha caducado"),
+    ("InvalidInstruction", "Instrucción no válida"),
+    (
+        "InvalidMerchantData",
+        "Los datos del comercio no son válidos",
+    ),
+    (
+        "InvalidSubscriptionData",
+        "Los datos de la suscripción no son válidos",
+    ),
+    (
+        "InvalidSubscriptionPackage",
+        "El plan de suscripción no es válido",
+    ),
+    ("InvalidOrder", "La cuenta del pedido no es válida"),
+    ("InvalidOrderData", "Los datos del pedido no son válidos"),
+    ("InvalidSplit", "El reparto del pago no es válido"),
+    (
+        "MigrationInProgress",
+        "Los pagos están en pausa mientras se migran las cuentas",
+    ),
+    (
+        "MintNotEqual",
+        "Las monedas del vendedor y del comprador no coinciden",
+    ),
+    ("NotFullyPaid", "El pago no se ha recibido por completo"),
+    ("NotPaid", "El pago aún no se ha realizado"),
+    ("OrderExpired", "El pedido ha caducado"),
+    ("OrderNotExpired", "El pedido no ha caducado"),
+    ("OrderNotReleasable", "El pedido está retenido en depósito"),
+    ("UntrustedInstance", "La instancia no es de confianza"),
+    (
+        "UnsupportedVersion",
+        "La versión de la cuenta no es compatible",
+    ),
+    ("VoucherAlreadyRedeemed", "El vale ya ha sido canjeado"),
+    ("VoucherSignatureInvalid", "La firma del vale no es válida"),
+    ("WrongMerchant", "El comercio indicado no es correcto"),
+    (
+        "WrongOrderAccount",
+        "La cuenta del pedido indicada no es correcta",
+    ),
+    ("WrongPayer", "El pagador no es correcto"),
+    (
+        "WrongProgramOwner",
+        "El propietario del programa indicado no es correcto",
+    ),
+    ("WrongSponsor", "El patrocinador indicado no es correcto"),
+    ("WrongMint", "La moneda indicada no es correcta"),
+    ("MerchantPaused", "El comercio está en pausa"),
+    (
+        "BuyerBlocked",
+        "El comprador está bloqueado por el comercio",
+    ),
+    ("SubscriptionPaused", "La suscripción está en pausa"),
+    ("WrongAccountType", "La cuenta no es del tipo correcto"),
+    (
+        "InsufficientPaid",
+        "El importe pagado es inferior al precio",
+    ),
+    (
+        "SubscriptionExpired",
+        "La suscripción ha sido cancelada o ha caducado",
+    ),
+    (
+        "NotOrderOwner",
+        "El firmante no es el propietario del pedido",
+    ),
+    (
+        "SellerAccountMismatch",
+        "La cuenta de tokens del pedido no corresponde al pedido",
+    ),
+    ("AmountOverflow", "Un importe está fuera de rango"),
+    (
+        "FreezableMint",
+        "La moneda tiene una autoridad de congelación",
+    ),
+    (
+        "NotUpgradeAuthority",
+        "El firmante no es la autoridad de actualización del programa",
+    ),
+];
+
+/// French messages, by error name
+const FR_MESSAGES: &[(&str, &str)] = &[
+    ("AlreadyWithdrawn", "Le montant a déjà été retiré"),
+    (
+        "CantWithdrawDuringTrial",
+        "Impossible de retirer pendant la période d'essai",
+    ),
+    ("ClosedAccount", "Le compte est déjà fermé"),
+    ("CouponExhausted", "Le coupon n'a plus d'utilisations"),
+    ("CouponExpired", "Le coupon a expiré"),
+    ("InvalidInstruction", "Instruction invalide"),
+    (
+        "InvalidMerchantData",
+        "Les données du marchand sont invalides",
+    ),
+    (
+        "InvalidSubscriptionData",
+        "Les données de l'abonnement sont invalides",
+    ),
+    (
+        "InvalidSubscriptionPackage",
+        "La formule d'abonnement est invalide",
+    ),
+    ("InvalidOrder", "Le compte de la commande est invalide"),
+    (
+        "InvalidOrderData",
+        "Les données de la commande sont invalides",
+    ),
+    ("InvalidSplit", "La répartition du paiement est invalide"),
+    (
+        "MigrationInProgress",
+        "Les paiements sont suspendus pendant la migration des comptes",
+    ),
+    (
+        "MintNotEqual",
+        "Les devises du vendeur et de l'acheteur ne sont pas les mêmes",
+    ),
+    ("NotFullyPaid", "Le paiement n'a pas été reçu en totalité"),
+    ("NotPaid", "Le paiement n'a pas encore été effectué"),
+    ("OrderExpired", "La commande a expiré"),
+    ("OrderNotExpired", "La commande n'a pas expiré"),
+    (
+        "OrderNotReleasable",
+        "La commande est retenue sous séquestre",
+    ),
+    ("UntrustedInstance", "L'instance n'est pas de confiance"),
+    (
+        "UnsupportedVersion",
+        "La version du compte n'est pas prise en charge",
+    ),
+    ("VoucherAlreadyRedeemed", "Le bon a déjà été utilisé"),
+    (
+        "VoucherSignatureInvalid",
+        "La signature du bon est invalide",
+    ),
+    ("WrongMerchant", "Le marchand indiqué est incorrect"),
+    (
+        "WrongOrderAccount",
+        "Le compte de commande indiqué est incorrect",
+    ),
+    ("WrongPayer", "Le payeur est incorrect"),
+    (
+        "WrongProgramOwner",
+        "Le propriétaire du programme indiqué est incorrect",
+    ),
+    ("WrongSponsor", "Le parrain indiqué est incorrect"),
+    ("WrongMint", "La devise indiquée est incorrecte"),
+    ("MerchantPaused", "Le marchand est en pause"),
+    ("BuyerBlocked", "L'acheteur est bloqué par le marchand"),
+    ("SubscriptionPaused", "L'abonnement est en pause"),
+    ("WrongAccountType", "Le compte n'est pas du bon type"),
+    ("InsufficientPaid", "Le montant payé est inférieur au prix"),
+    (
+        "SubscriptionExpired",
+        "L'abonnement a été annulé ou a expiré",
+    ),
+    (
+        "NotOrderOwner",
+        "Le signataire n'est pas le propriétaire de la commande",
+    ),
+    (
+        "SellerAccountMismatch",
+        "Le compte de jetons de la commande ne correspond pas à la commande",
+    ),
+    ("AmountOverflow", "Un montant est hors limites"),
+    ("FreezableMint", "La devise a une autorité de gel"),
+    (
+        "NotUpgradeAuthority",
+        "Le signataire n'est pas l'autorité de mise à jour du programme",
+    ),
+];
+
+/// The translated messages (other than the default locale), by locale
+const TRANSLATIONS: &[(&str, &[(&str, &str)])] = &[("es", ES_MESSAGES), ("fr", FR_MESSAGES)];
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// An entry of the error message catalog
+pub struct ErrorEntry {
+    /// the custom program error code
+    pub code: u32,
+    /// the name of the PaymentProcessorError variant
+    pub name: String,
+    /// the i18n key of the message e.g. sol_payments.error.wrong_mint
+    pub key: String,
+    /// the message, by locale
+    pub messages: BTreeMap<String, String>,
+}
+
+/// Convert the name of an error variant to snake case e.g. WrongMint -> wrong_mint
+fn to_snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (index, character) in name.chars().enumerate() {
+        if character.is_uppercase() && index > 0 {
+            result.push('_');
+        }
+        result.extend(character.to_lowercase());
+    }
+    result
+}
+
+fn get_error_entry(code: u32, error: &PaymentProcessorError) -> ErrorEntry {
+    let name = format!("{:?}", error);
+    let mut messages = BTreeMap::new();
+    let message = error.to_string();
+    messages.insert(
+        String::from(DEFAULT_LOCALE),
+        String::from(message.trim_start_matches("Error: ")),
+    );
+    for (locale, locale_messages) in TRANSLATIONS.iter() {
+        if let Some((_name, message)) = locale_messages.iter().find(|(key, _)| *key == name) {
+            messages.insert(String::from(*locale), String::from(*message));
+        }
+    }
+    ErrorEntry {
+        code,
+        key: format!("{}.{}", I18N_KEY_PREFIX, to_snake_case(&name)),
+        name,
+        messages,
+    }
+}
+
+/// Get the error message catalog
+///
+/// Has an entry for every PaymentProcessorError, ordered by error code
+pub fn error_catalog() -> Vec<ErrorEntry> {
+    let mut catalog = vec![];
+    let mut code = 0;
+    while let Some(error) = PaymentProcessorError::from_u32(code) {
+        catalog.push(get_error_entry(code, &error));
+        code += 1;
+    }
+    catalog
+}
+
+/// Get the error message catalog as a JSON string
+pub fn error_catalog_to_json() -> String {
+    serde_json::to_string(&error_catalog()).unwrap()
+}
+
+/// Parse an error code as shown by wallets and explorers e.g. "0x1b" or "27"
+pub fn parse_error_code(value: &str) -> Option<u32> {
+    let value = value.trim();
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Explain an error code in a locale
+///
+/// A locale with a region (e.g. es-MX) falls back to its language, and a
+/// language without translations falls back to the default locale.
+pub fn explain(error_code: u32, locale: &str) -> String {
+    let entry = match PaymentProcessorError::from_u32(error_code) {
+        None => return format!("Unknown error code {}", error_code),
+        Some(error) => get_error_entry(error_code, &error),
+    };
+    let language = locale.split(|c| c == '-' || c == '_').next().unwrap();
+    entry
+        .messages
+        .get(locale)
+        .or_else(|| entry.messages.get(&language.to_lowercase()))
+        .or_else(|| entry.messages.get(DEFAULT_LOCALE))
+        .unwrap()
+        .clone()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_error_catalog() {
+        let catalog = error_catalog();
+        assert_eq!(
+            PaymentProcessorError::NotUpgradeAuthority as usize + 1,
+            catalog.len()
+        );
+        // every error is translated in every locale
+        for entry in catalog.iter() {
+            assert_eq!(
+                TRANSLATIONS.len() + 1,
+                entry.messages.len(),
+                "{}",
+                entry.name
+            );
+        }
+        let entry = &catalog[PaymentProcessorError::WrongMint as usize];
+        assert_eq!("WrongMint", entry.name);
+        assert_eq!("sol_payments.error.wrong_mint", entry.key);
+        assert_eq!("The Provided mint Is Wrong", entry.messages["en"]);
+        // new errors are added at the end so that released codes never change
+        assert_eq!(17, PaymentProcessorError::WrongMint as u32);
+        assert_eq!(18, PaymentProcessorError::OrderExpired as u32);
+        assert_eq!(29, PaymentProcessorError::MerchantPaused as u32);
+
+        let json_value: serde_json::Value = serde_json::from_str(&error_catalog_to_json()).unwrap();
+        assert_eq!(0, json_value[0]["code"]);
+        assert_eq!("sol_payments.error.already_withdrawn", json_value[0]["key"]);
+    }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let code = PaymentProcessorError::CouponExpired as u32;
+        assert_eq!("The Coupon Has Expired", explain(code, "en"));
+        assert_eq!("El cupón ha caducado", explain(code, "es"));
+        assert_eq!("El cupón ha caducado", explain(code, "es-MX"));
+        assert_eq!("Le coupon a expiré", explain(code, "fr_FR"));
+        assert_eq!("The Coupon Has Expired", explain(code, "de"));
+        assert_eq!("Unknown error code 9999", explain(9999, "en"));
+        assert_eq!(Some(27), parse_error_code("0x1b"));
+        assert_eq!(Some(27), parse_error_code("27"));
+        assert_eq!(None, parse_error_code("oops"));
+    }
+}
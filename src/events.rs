@@ -0,0 +1,126 @@
+//! Structured events
+//!
+//! The state transitions that indexers and webhook services care about log an
+//! event, so that they do not have to work out what changed from snapshots of
+//! the accounts.  An event is a Borsh encoded PaymentProcessorEvent, base64
+//! encoded on a transaction log line that starts with EVENT_LOG_PREFIX (the
+//! program is built against a Solana version without sol_log_data).
+//!
+//! The schema is stable: the variants and their fields are never reordered or
+//! changed, new events are only ever added at the end.
+
+use crate::state::{OrderAccount, PublicKey};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{clock::UnixTimestamp, msg, pubkey::Pubkey};
+
+/// the transaction log line that holds an event
+pub const EVENT_LOG_PREFIX: &str = "SolPayments: Event ";
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum PaymentProcessorEvent {
+    MerchantRegistered {
+        merchant: PublicKey,
+        owner: PublicKey,
+        sponsor: PublicKey,
+    },
+    /// an order has been paid in full - amount is the amount paid for the order
+    /// (not counting the tip)
+    OrderPaid {
+        order: PublicKey,
+        merchant: PublicKey,
+        payer: PublicKey,
+        mint: PublicKey,
+        amount: u64,
+        tip: u64,
+    },
+    /// the amount is what was withdrawn by this withdrawal
+    OrderWithdrawn {
+        order: PublicKey,
+        merchant: PublicKey,
+        amount: u64,
+    },
+    Subscribed {
+        subscription: PublicKey,
+        merchant: PublicKey,
+        owner: PublicKey,
+        name: String,
+        period_end: UnixTimestamp,
+    },
+    Renewed {
+        subscription: PublicKey,
+        period_end: UnixTimestamp,
+    },
+    /// the refund is the amount paid back to the subscriber (if any)
+    Cancelled {
+        subscription: PublicKey,
+        refund: u64,
+    },
+    Refunded {
+        order: PublicKey,
+        amount: u64,
+    },
+}
+
+/// Log an event
+pub fn emit_event(event: &PaymentProcessorEvent) {
+    msg!(
+        "{}{}",
+        EVENT_LOG_PREFIX,
+        base64::encode(event.try_to_vec().unwrap())
+    );
+}
+
+/// Log that an order has been paid in full
+pub fn emit_order_paid(order: &Pubkey, order_account: &OrderAccount) {
+    emit_event(&PaymentProcessorEvent::OrderPaid {
+        order: order.to_bytes(),
+        merchant: order_account.merchant,
+        payer: order_account.payer,
+        mint: order_account.mint,
+        amount: order_account.paid_amount,
+        tip: order_account.tip,
+    });
+}
+
+/// Find the events in the logs of a transaction
+///
+/// Log lines that cannot be decoded are skipped.
+pub fn find_events(logs: &[String]) -> Vec<PaymentProcessorEvent> {
+    logs.iter()
+        .filter_map(|log| {
+            let index = log.find(EVENT_LOG_PREFIX)?;
+            let data = base64::decode(&log[index + EVENT_LOG_PREFIX.len()..]).ok()?;
+            PaymentProcessorEvent::try_from_slice(&data).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_find_events() {
+        let event = PaymentProcessorEvent::Refunded {
+            order: [1; 32],
+            amount: 2000,
+        };
+        let logs = vec![
+            String::from("Program log: SolPayments: RedeemRefundCode"),
+            format!(
+                "Program log: {}{}",
+                EVENT_LOG_PREFIX,
+                base64::encode(event.try_to_vec().unwrap())
+            ),
+            format!("Program log: {}not base64", EVENT_LOG_PREFIX),
+        ];
+        assert_eq!(vec![event], find_events(&logs));
+        // the first variant keeps its index in the schema
+        let registered = PaymentProcessorEvent::MerchantRegistered {
+            merchant: [0; 32],
+            owner: [0; 32],
+            sponsor: [0; 32],
+        };
+        assert_eq!(0, registered.try_to_vec().unwrap()[0]);
+    }
+}
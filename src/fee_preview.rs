@@ -0,0 +1,208 @@
+//! Fee previews for checkouts
+//!
+//! Meant to be used off-chain by wallets and checkout pages to show the buyer
+//! what a payment costs before it is sent.  The fees are worked out by
+//! `utils::get_fees`, the same code that the program runs when it charges them,
+//! from the merchant account and the program config account as they are on-chain.
+
+use crate::{
+    engine::json::OrderFees,
+    error::PaymentProcessorError,
+    state::{MerchantAccount, ProgramConfigAccount},
+    utils::get_fees,
+};
+use serde::Serialize;
+use solana_program::program_error::ProgramError;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// The transaction fee (in SOL lamports) that a checkout will be charged
+pub struct FeePreview {
+    pub program_owner_fee: u64,
+    pub sponsor_fee: u64,
+    pub integrator_fee: u64,
+    /// the fee paid by the signer of the checkout, on top of the order account rent
+    pub total_fee: u64,
+}
+
+impl FeePreview {
+    /// The fees that the order account keeps track of, so that they can be
+    /// waived if the order is refunded
+    pub fn order_fees(&self) -> OrderFees {
+        OrderFees {
+            program_owner: self.program_owner_fee,
+            sponsor: self.sponsor_fee,
+        }
+    }
+}
+
+/// Preview the transaction fee of a checkout for a merchant
+///
+/// Set with_integrator when the checkout includes an integrator account.
+pub fn preview_checkout_fees(
+    merchant_account: &MerchantAccount,
+    config_account: &ProgramConfigAccount,
+    with_integrator: bool,
+) -> Result<FeePreview, ProgramError> {
+    let (program_owner_fee, sponsor_fee, integrator_fee) =
+        get_fees(merchant_account, config_account, with_integrator)?;
+    Ok(FeePreview {
+        program_owner_fee,
+        sponsor_fee,
+        integrator_fee,
+        total_fee: program_owner_fee
+            .checked_add(sponsor_fee)
+            .and_then(|value| value.checked_add(integrator_fee))
+            .ok_or(PaymentProcessorError::AmountOverflow)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::engine::constants::{
+            ACCOUNT_VERSION, INTEGRATOR_FEE, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS, SPONSOR_FEE,
+        },
+        crate::state::Discriminator,
+        crate::utils::get_amounts,
+        solana_program::pubkey::Pubkey,
+        solana_program_test::*,
+    };
+
+    fn accounts(fee: u64, sponsored: bool) -> (MerchantAccount, ProgramConfigAccount) {
+        let program_owner = Pubkey::new_unique();
+        let sponsor = if sponsored {
+            Pubkey::new_unique()
+        } else {
+            program_owner
+        };
+        (
+            MerchantAccount {
+                discriminator: Discriminator::Merchant as u8,
+                owner: Pubkey::new_unique().to_bytes(),
+                sponsor: sponsor.to_bytes(),
+                fee,
+                tolerance: 0,
+                references: 0,
+                is_paused: false,
+                restricts_mints: false,
+                instant_settlement: false,
+                blocked_buyers: 0,
+                lifetime_volume: 0,
+                order_sequence: 0,
+                rejects_freezable_mints: false,
+                sponsor_fee: SPONSOR_FEE as u64,
+                payout_count: 0,
+                payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+                payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+                operator_count: 0,
+                operators: [[0; 32]; MAX_OPERATORS],
+                sweep_threshold: 0,
+                sweep_delay: 0,
+                catalog: vec![],
+                packages: vec![],
+                metadata: vec![],
+                data: String::from("{}"),
+                version: ACCOUNT_VERSION,
+            },
+            ProgramConfigAccount {
+                discriminator: Discriminator::ProgramConfig as u8,
+                state_version: 1,
+                owner: program_owner.to_bytes(),
+                authority: program_owner.to_bytes(),
+                default_fee: fee,
+                min_fee: 0,
+                sponsor_fee: SPONSOR_FEE as u64,
+                migration_ends: 0,
+                name: String::from("SolPayments"),
+                data: String::from("{}"),
+                version: ACCOUNT_VERSION,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_preview_checkout_fees() {
+        // without a sponsor the program owner gets the whole fee
+        let (merchant_account, config_account) = accounts(50000, false);
+        assert_eq!(
+            FeePreview {
+                program_owner_fee: 50000,
+                sponsor_fee: 0,
+                integrator_fee: 0,
+                total_fee: 50000,
+            },
+            preview_checkout_fees(&merchant_account, &config_account, false).unwrap()
+        );
+
+        // the sponsor gets its share of the fee
+        let (merchant_account, config_account) = accounts(50000, true);
+        let (program_owner_fee, sponsor_fee) = get_amounts(50000, SPONSOR_FEE).unwrap();
+        let preview = preview_checkout_fees(&merchant_account, &config_account, false).unwrap();
+        assert_eq!(program_owner_fee, preview.program_owner_fee);
+        assert_eq!(sponsor_fee, preview.sponsor_fee);
+        assert_eq!(50000, preview.total_fee);
+        assert_eq!(
+            OrderFees {
+                program_owner: program_owner_fee,
+                sponsor: sponsor_fee,
+            },
+            preview.order_fees()
+        );
+
+        // the integrator share comes out of the fee before the sponsor share
+        let (remaining_fee, integrator_fee) = get_amounts(50000, INTEGRATOR_FEE).unwrap();
+        let (program_owner_fee, sponsor_fee) = get_amounts(remaining_fee, SPONSOR_FEE).unwrap();
+        assert_eq!(
+            FeePreview {
+                program_owner_fee,
+                sponsor_fee,
+                integrator_fee,
+                total_fee: 50000,
+            },
+            preview_checkout_fees(&merchant_account, &config_account, true).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fee_tiers() {
+        let (mut merchant_account, mut config_account) = accounts(50000, false);
+        config_account.data = String::from(
+            r#"{"fee_tiers": [{"threshold": 1000000, "fee": 30000}, {"threshold": 5000000, "fee": 10000}]}"#,
+        );
+
+        // below the first threshold the merchant pays its own fee
+        merchant_account.lifetime_volume = 999999;
+        assert_eq!(
+            50000,
+            preview_checkout_fees(&merchant_account, &config_account, false)
+                .unwrap()
+                .total_fee
+        );
+
+        // the highest tier reached applies
+        merchant_account.lifetime_volume = 1000000;
+        assert_eq!(
+            30000,
+            preview_checkout_fees(&merchant_account, &config_account, false)
+                .unwrap()
+                .total_fee
+        );
+        merchant_account.lifetime_volume = 7000000;
+        assert_eq!(
+            10000,
+            preview_checkout_fees(&merchant_account, &config_account, false)
+                .unwrap()
+                .total_fee
+        );
+
+        // a merchant fee lower than the tier fee is kept
+        merchant_account.fee = 5000;
+        assert_eq!(
+            5000,
+            preview_checkout_fees(&merchant_account, &config_account, false)
+                .unwrap()
+                .total_fee
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! getProgramAccounts filters of the accounts of the program
+//!
+//! Meant to be used by indexers and dashboards to query the accounts of the
+//! program by their key fields, at the offsets given by the FixedField constants
+//! of `state`.  The filters of a query are and-ed together e.g. the paid orders
+//! of a merchant are found with the merchant orders filter and the paid order
+//! status filter.  Accounts of other types can match the same bytes, so the
+//! accounts found are unpacked (which checks their discriminator) before use.
+//!
+//! The offsets are those of accounts that start with the full discriminator;
+//! accounts in the legacy layout, which are not migrated yet, are found by the
+//! same filters passed to get_legacy_filter.
+
+use crate::state::{
+    Discriminator, MerchantAccount, OrderAccount, OrderStatus, SubscriptionAccount,
+    SubscriptionStatus, DISCRIMINATOR, DISCRIMINATOR_LEN, LEGACY_DISCRIMINATOR_LEN,
+};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+
+/// Get the filter of the accounts that have the bytes at the offset
+fn get_memcmp_filter(offset: usize, bytes: &[u8]) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp {
+        offset,
+        bytes: MemcmpEncodedBytes::Binary(bs58::encode(bytes).into_string()),
+        encoding: None,
+    })
+}
+
+/// Get the filter of the accounts of a type e.g. Discriminator::Subscription
+///
+/// Orders and merchants have more than one discriminator (see their Serdes
+/// DISCRIMINATORS), so they are better found by the filters below.
+pub fn get_discriminator_filter(discriminator: Discriminator) -> RpcFilterType {
+    get_memcmp_filter(DISCRIMINATOR.offset, &[discriminator as u8])
+}
+
+/// Get the filter of the merchant accounts of an owner
+pub fn get_merchant_owner_filter(owner: &Pubkey) -> RpcFilterType {
+    get_memcmp_filter(MerchantAccount::OWNER.offset, &owner.to_bytes())
+}
+
+/// Get the filter of the order accounts of a merchant
+pub fn get_merchant_orders_filter(merchant: &Pubkey) -> RpcFilterType {
+    get_memcmp_filter(OrderAccount::MERCHANT.offset, &merchant.to_bytes())
+}
+
+/// Get the filter of the order accounts with a status
+pub fn get_order_status_filter(status: OrderStatus) -> RpcFilterType {
+    get_memcmp_filter(OrderAccount::STATUS.offset, &[status as u8])
+}
+
+/// Get the filter of the subscription accounts of the subscribers of a merchant
+pub fn get_merchant_subscriptions_filter(merchant: &Pubkey) -> RpcFilterType {
+    get_memcmp_filter(SubscriptionAccount::MERCHANT.offset, &merchant.to_bytes())
+}
+
+/// Get the filter of the subscription accounts of a subscriber
+pub fn get_subscriber_subscriptions_filter(owner: &Pubkey) -> RpcFilterType {
+    get_memcmp_filter(SubscriptionAccount::OWNER.offset, &owner.to_bytes())
+}
+
+/// Get the filter of the subscription accounts with a status
+pub fn get_subscription_status_filter(status: SubscriptionStatus) -> RpcFilterType {
+    get_memcmp_filter(SubscriptionAccount::STATUS.offset, &[status as u8])
+}
+
+/// Get the filter of the accounts in the legacy layout that match the filter of
+/// the accounts in the latest layout, whose fields are further by the rest of the
+/// discriminator (see crate::state::get_account_discriminator)
+pub fn get_legacy_filter(filter: RpcFilterType) -> RpcFilterType {
+    match filter {
+        RpcFilterType::Memcmp(memcmp) if memcmp.offset >= DISCRIMINATOR_LEN => {
+            RpcFilterType::Memcmp(Memcmp {
+                offset: memcmp.offset + LEGACY_DISCRIMINATOR_LEN - DISCRIMINATOR_LEN,
+                ..memcmp
+            })
+        }
+        filter => filter,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::engine::constants::{ACCOUNT_VERSION, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS},
+        borsh::BorshSerialize,
+        solana_program_test::*,
+    };
+
+    /// Check whether an account matches a filter of a key or of a byte, as the
+    /// RPC server would
+    fn matches(filter: &RpcFilterType, data: &[u8]) -> bool {
+        let offset = match filter {
+            RpcFilterType::Memcmp(memcmp) => memcmp.offset,
+            _ => panic!("Not a memcmp filter"),
+        };
+        [1, 32].iter().any(|length| {
+            data.get(offset..offset + length)
+                .map(|bytes| *filter == get_memcmp_filter(offset, bytes))
+                .unwrap_or(false)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_merchant_filters() {
+        let owner = Pubkey::new_unique();
+        let merchant_account = MerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let data = merchant_account.to_account_data();
+
+        assert!(matches(&get_merchant_owner_filter(&owner), &data));
+        assert!(!matches(
+            &get_merchant_owner_filter(&Pubkey::new_unique()),
+            &data
+        ));
+        assert!(matches(
+            &get_discriminator_filter(Discriminator::Merchant),
+            &data
+        ));
+        assert!(!matches(
+            &get_discriminator_filter(Discriminator::Subscription),
+            &data
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_order_filters() {
+        let merchant = Pubkey::new_unique();
+        let order_account = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1,
+            modified: 2,
+            merchant: merchant.to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 1,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let data = order_account.to_account_data();
+
+        assert!(matches(&get_merchant_orders_filter(&merchant), &data));
+        assert!(!matches(
+            &get_merchant_orders_filter(&Pubkey::new_unique()),
+            &data
+        ));
+        assert!(matches(&get_order_status_filter(OrderStatus::Paid), &data));
+        assert!(!matches(
+            &get_order_status_filter(OrderStatus::Withdrawn),
+            &data
+        ));
+
+        // orders in the legacy layout are found by the legacy filters
+        let legacy_data = OrderAccount {
+            version: 0,
+            ..order_account
+        }
+        .to_account_data();
+        assert!(!matches(
+            &get_merchant_orders_filter(&merchant),
+            &legacy_data
+        ));
+        assert!(matches(
+            &get_legacy_filter(get_merchant_orders_filter(&merchant)),
+            &legacy_data
+        ));
+        assert!(matches(
+            &get_legacy_filter(get_order_status_filter(OrderStatus::Paid)),
+            &legacy_data
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_filters() {
+        let owner = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let subscription_account = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: owner.to_bytes(),
+            merchant: merchant.to_bytes(),
+            name: String::from("basic"),
+            joined: 1,
+            period_start: 1,
+            period_end: 2,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let data = subscription_account.to_account_data();
+
+        assert!(matches(
+            &get_merchant_subscriptions_filter(&merchant),
+            &data
+        ));
+        assert!(!matches(&get_merchant_subscriptions_filter(&owner), &data));
+        assert!(matches(&get_subscriber_subscriptions_filter(&owner), &data));
+        assert!(matches(
+            &get_subscription_status_filter(SubscriptionStatus::Initialized),
+            &data
+        ));
+        assert!(!matches(
+            &get_subscription_status_filter(SubscriptionStatus::Expired),
+            &data
+        ));
+        assert!(matches(
+            &get_discriminator_filter(Discriminator::Subscription),
+            &data
+        ));
+    }
+}
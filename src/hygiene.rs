@@ -0,0 +1,280 @@
+//! Hygiene reports on the program-owned token accounts of a merchant
+//!
+//! Meant to be used off-chain by merchants (or a sweeper acting for them) to find
+//! the orders that keep token accounts open, long before these number in the tens
+//! of thousands.  Each paid order keeps its order token accounts open until it is
+//! withdrawn, refunded, reclaimed or cancelled.  The orders can be fetched using
+//! getProgramAccounts with the filters found here, and the count kept on-chain by
+//! the merchant stats account (if any) is checked against them.
+
+use crate::{
+    engine::common::get_order_tokens,
+    engine::constants::{BASIS_POINTS, MAX_OPEN_TOKEN_ACCOUNTS},
+    state::{Discriminator, MerchantStatsAccount, OrderAccount, OrderStatus},
+};
+use serde::Serialize;
+use solana_program::{clock::UnixTimestamp, pubkey::Pubkey};
+
+/// the share (in basis points) of the cap on open token accounts past which a
+/// merchant should close stale ones
+pub const WARNING_USAGE: u64 = 8000;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// An order that keeps program-owned token accounts open
+pub struct OpenOrder {
+    /// the order account
+    pub order: String,
+    pub order_id: String,
+    pub status: u8,
+    /// the order token accounts that the order keeps open
+    pub token_accounts: Vec<String>,
+    pub modified: UnixTimestamp,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// The program-owned token accounts of a merchant
+pub struct HygieneReport {
+    pub merchant: String,
+    /// the token accounts kept open by the orders of the merchant
+    pub open_token_accounts: u64,
+    /// the count kept by the merchant stats account (if any)
+    pub recorded_open_token_accounts: Option<u64>,
+    pub max_open_token_accounts: u64,
+    /// the share (in basis points) of the cap that is in use
+    pub usage: u64,
+    /// the open orders that were not modified in a while, oldest first
+    pub stale_orders: Vec<OpenOrder>,
+}
+
+impl HygieneReport {
+    /// Whether the merchant should close stale token accounts
+    pub fn needs_attention(&self) -> bool {
+        self.usage >= WARNING_USAGE
+    }
+
+    /// Whether the count kept by the merchant stats account should be corrected
+    /// using SetMerchantStats
+    pub fn out_of_sync(&self) -> bool {
+        match self.recorded_open_token_accounts {
+            None => false,
+            Some(value) => value != self.open_token_accounts,
+        }
+    }
+}
+
+/// getProgramAccounts memcmp filters (as offset and bytes) that match the order
+/// accounts of a merchant
+///
+/// Orders have a discriminator per kind of order, so accounts that do not unpack
+/// as orders should be skipped.
+pub fn merchant_order_filters(merchant: &Pubkey) -> Vec<(usize, Vec<u8>)> {
+    // the merchant follows the discriminator, the status and the created and
+    // modified timestamps
+    vec![(18, merchant.to_bytes().to_vec())]
+}
+
+/// Get the hygiene report of a merchant
+///
+/// The orders are expected as (address, order account) pairs e.g. as returned by
+/// getProgramAccounts.  Orders that belong to other merchants are ignored.  Open
+/// orders that were not modified for stale_after seconds are reported as stale.
+/// Token accounts left open because they hold withheld transfer fees are not
+/// counted.
+pub fn hygiene_report(
+    merchant: &Pubkey,
+    possible_stats: Option<&MerchantStatsAccount>,
+    orders: &[(Pubkey, OrderAccount)],
+    now: UnixTimestamp,
+    stale_after: i64,
+) -> HygieneReport {
+    let mut open_token_accounts = 0;
+    let mut stale_orders = vec![];
+    for (address, order_account) in orders.iter() {
+        if order_account.merchant != merchant.to_bytes()
+            || order_account.discriminator == Discriminator::Closed as u8
+        {
+            continue;
+        }
+        if order_account.status != OrderStatus::Pending as u8
+            && order_account.status != OrderStatus::Paid as u8
+            && order_account.status != OrderStatus::Releasable as u8
+        {
+            continue;
+        }
+        let mut token_accounts = vec![Pubkey::new_from_array(order_account.token).to_string()];
+        for order_token in get_order_tokens(order_account) {
+            token_accounts.push(order_token.token);
+        }
+        open_token_accounts += token_accounts.len() as u64;
+        if now - order_account.modified >= stale_after {
+            stale_orders.push(OpenOrder {
+                order: address.to_string(),
+                order_id: order_account.order_id.clone(),
+                status: order_account.status,
+                token_accounts,
+                modified: order_account.modified,
+            });
+        }
+    }
+    stale_orders.sort_by(|a, b| a.modified.cmp(&b.modified));
+
+    let max_open_token_accounts = possible_stats.map_or(MAX_OPEN_TOKEN_ACCOUNTS, |value| {
+        value.max_open_token_accounts
+    });
+    HygieneReport {
+        merchant: merchant.to_string(),
+        open_token_accounts,
+        recorded_open_token_accounts: possible_stats.map(|value| value.open_token_accounts),
+        max_open_token_accounts,
+        usage: match max_open_token_accounts {
+            0 => BASIS_POINTS,
+            value => (open_token_accounts as u128 * BASIS_POINTS as u128 / value as u128) as u64,
+        },
+        stale_orders,
+    }
+}
+
+/// Get the hygiene report of a merchant as a JSON string
+pub fn hygiene_report_to_json(
+    merchant: &Pubkey,
+    possible_stats: Option<&MerchantStatsAccount>,
+    orders: &[(Pubkey, OrderAccount)],
+    now: UnixTimestamp,
+    stale_after: i64,
+) -> String {
+    serde_json::to_string(&hygiene_report(
+        merchant,
+        possible_stats,
+        orders,
+        now,
+        stale_after,
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::engine::constants::ACCOUNT_VERSION, solana_program_test::*};
+
+    fn order(
+        merchant: &Pubkey,
+        order_id: &str,
+        status: OrderStatus,
+        modified: i64,
+    ) -> OrderAccount {
+        OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: status as u8,
+            created: modified,
+            modified,
+            merchant: merchant.to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from(order_id),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hygiene_report() {
+        let merchant = Pubkey::new_unique();
+        let other_token = Pubkey::new_unique().to_string();
+        let mut chain_order = order(&merchant, "chain", OrderStatus::Paid, 100);
+        chain_order.discriminator = Discriminator::OrderChainCheckout as u8;
+        chain_order.data = format!(
+            r#"{{"_tokens": [{{"mint": "{}", "token": "{}", "amount": 5}}]}}"#,
+            Pubkey::new_unique(),
+            other_token
+        );
+        let orders = vec![
+            (Pubkey::new_unique(), chain_order),
+            (
+                Pubkey::new_unique(),
+                order(&merchant, "old", OrderStatus::Releasable, 50),
+            ),
+            (
+                Pubkey::new_unique(),
+                order(&merchant, "new", OrderStatus::Paid, 900),
+            ),
+            // withdrawn orders no longer keep token accounts open
+            (
+                Pubkey::new_unique(),
+                order(&merchant, "withdrawn", OrderStatus::Withdrawn, 10),
+            ),
+            // other merchants' orders are ignored
+            (
+                Pubkey::new_unique(),
+                order(&Pubkey::new_unique(), "other", OrderStatus::Paid, 10),
+            ),
+        ];
+        let stats = MerchantStatsAccount {
+            discriminator: Discriminator::MerchantStats as u8,
+            merchant: merchant.to_bytes(),
+            open_token_accounts: 4,
+            max_open_token_accounts: 5,
+            opened: 6,
+            closed: 2,
+            version: ACCOUNT_VERSION,
+        };
+
+        let report = hygiene_report(&merchant, Some(&stats), &orders, 1000, 500);
+        assert_eq!(4, report.open_token_accounts);
+        assert_eq!(Some(4), report.recorded_open_token_accounts);
+        assert_eq!(5, report.max_open_token_accounts);
+        assert_eq!(8000, report.usage);
+        assert!(report.needs_attention());
+        assert!(!report.out_of_sync());
+        assert_eq!(2, report.stale_orders.len());
+        assert_eq!("old", report.stale_orders[0].order_id);
+        assert_eq!(orders[1].0.to_string(), report.stale_orders[0].order);
+        assert_eq!("chain", report.stale_orders[1].order_id);
+        assert_eq!(2, report.stale_orders[1].token_accounts.len());
+        assert_eq!(other_token, report.stale_orders[1].token_accounts[1]);
+
+        // without a stats account the program-wide cap applies
+        let report = hygiene_report(&merchant, Option::None, &orders, 1000, 500);
+        assert_eq!(MAX_OPEN_TOKEN_ACCOUNTS, report.max_open_token_accounts);
+        assert_eq!(4, report.usage);
+        assert!(!report.needs_attention());
+        assert!(!report.out_of_sync());
+
+        let stats = MerchantStatsAccount {
+            open_token_accounts: 7,
+            ..stats
+        };
+        assert!(hygiene_report(&merchant, Some(&stats), &orders, 1000, 500).out_of_sync());
+
+        let json_value: serde_json::Value = serde_json::from_str(&hygiene_report_to_json(
+            &merchant,
+            Some(&stats),
+            &orders,
+            1000,
+            500,
+        ))
+        .unwrap();
+        assert_eq!(4, json_value["open_token_accounts"]);
+        assert_eq!("old", json_value["stale_orders"][0]["order_id"]);
+    }
+
+    #[tokio::test]
+    async fn test_filters() {
+        let merchant = Pubkey::new_unique();
+        assert_eq!(
+            vec![(18, merchant.to_bytes().to_vec())],
+            merchant_order_filters(&merchant)
+        );
+    }
+}
@@ -0,0 +1,503 @@
+//! Anchor compatible IDL
+//!
+//! Clients in other languages (e.g. TypeScript clients generated by Anchor or
+//! Solita) are generated from the IDL of the program, instead of hand-maintaining
+//! the accounts that each instruction expects.  The IDL is built from the typed
+//! definitions of the program, so it cannot drift from them:
+//!
+//! - the instructions and their arguments from the Borsh schema of
+//!   PaymentProcessorInstruction
+//! - the accounts and the types they use from the Borsh schemas of `state`
+//! - the errors from PaymentProcessorError, with their messages
+//!
+//! The accounts that an instruction expects have no typed definition, so they
+//! are read from the "Accounts expected" list documented on the instruction,
+//! which is matched to the instruction by the name of its variant.  Borsh gives
+//! the public keys of `state` (i.e. PublicKey) no name of their own, so every
+//! 32 byte array is given as a publicKey.
+//!
+//! The program does not use Anchor discriminators: an instruction starts with the
+//! index of its variant (given as the `discriminant` of the instruction, as in
+//! Shank IDLs) and an account starts with its Discriminator, which is followed by
+//! the rest of its 8-byte discriminator (see state::get_account_discriminator)
+//! outside the legacy layout.  The IDL is written by
+//! `cargo run --example idl --features off-chain`.
+
+use crate::{
+    error::PaymentProcessorError,
+    instruction::PaymentProcessorInstruction,
+    state::{
+        AcceptedMintsAccount, AttestationAccount, AutoDebitAccount, BlockedBuyerAccount,
+        BundleAccount, BundleMemberAccount, CampaignAccount, CouponAccount, FeeHistoryAccount,
+        FeeScheduleAccount, IntegratorAccount, ItemStockAccount, LinkPayerAccount, MerchantAccount,
+        MerchantRecoveryAccount, MerchantStatsAccount, MerchantTransferAccount, MigrationAccount,
+        OrderAccount, OrderNoteAccount, OrderNotesAccount, PackageAccount, PaymentLinkAccount,
+        PriceGuaranteeAccount, PriceOracleAccount, ProgramConfigAccount, ReferrerAccount,
+        RefundCodeAccount, Serdes, SettlementAccount, StoreAccount, SubscriptionAccount,
+        SweepBountyAccount, VoucherAccount,
+    },
+};
+use borsh::{
+    schema::{Declaration, Definition, Fields},
+    BorshSchema,
+};
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+
+const INSTRUCTION_SOURCE: &str = include_str!("instruction.rs");
+
+/// the header of the definition of the instructions
+const INSTRUCTION_ENUM: &str = "pub enum PaymentProcessorInstruction {";
+
+/// The Borsh definitions of the types of the program, by their declaration
+type Definitions = HashMap<Declaration, Definition>;
+
+/// Turn a snake_case or PascalCase name into camelCase
+fn to_camel_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+    for character in name.chars() {
+        if character == '_' || character == ' ' {
+            upper = !result.is_empty();
+        } else if upper {
+            result.extend(character.to_uppercase());
+            upper = false;
+        } else if result.is_empty() {
+            result.extend(character.to_lowercase());
+        } else {
+            result.push(character);
+        }
+    }
+    result
+}
+
+/// Add the definitions of an account type, returning its declaration
+fn add_account<T: Serdes + BorshSchema>(definitions: &mut Definitions) -> Declaration {
+    T::add_definitions_recursively(definitions);
+    T::declaration()
+}
+
+/// Get the IDL type of a Borsh declaration, collecting the types it refers to
+fn get_idl_type(
+    declaration: &str,
+    definitions: &Definitions,
+    defined: &mut BTreeSet<Declaration>,
+) -> Value {
+    match declaration {
+        "u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16" | "i32" | "i64" | "i128" | "bool"
+        | "string" => return json!(declaration),
+        "Pubkey" => return json!("publicKey"),
+        _ => {}
+    }
+    match definitions.get(declaration) {
+        Some(Definition::Array {
+            length: 32,
+            elements,
+        }) if elements == "u8" => {
+            json!("publicKey")
+        }
+        Some(Definition::Array { length, elements }) => {
+            json!({ "array": [get_idl_type(elements, definitions, defined), length] })
+        }
+        // a HashMap is a sequence of its entries, written sorted by key
+        Some(Definition::Sequence { elements }) if declaration.starts_with("HashMap<") => {
+            match definitions.get(elements) {
+                Some(Definition::Tuple { elements }) => {
+                    json!({ "hashMap": get_idl_types(elements, definitions, defined) })
+                }
+                _ => json!({ "vec": get_idl_type(elements, definitions, defined) }),
+            }
+        }
+        Some(Definition::Sequence { elements }) => {
+            json!({ "vec": get_idl_type(elements, definitions, defined) })
+        }
+        Some(Definition::Tuple { elements }) => {
+            json!({ "tuple": get_idl_types(elements, definitions, defined) })
+        }
+        Some(Definition::Enum { variants }) if declaration.starts_with("Option<") => {
+            // the variants of an option are None and Some
+            json!({ "option": get_idl_type(&variants[1].1, definitions, defined) })
+        }
+        _ => {
+            defined.insert(declaration.to_string());
+            json!({ "defined": declaration })
+        }
+    }
+}
+
+/// Get the IDL types of a list of Borsh declarations
+fn get_idl_types(
+    declarations: &[Declaration],
+    definitions: &Definitions,
+    defined: &mut BTreeSet<Declaration>,
+) -> Vec<Value> {
+    declarations
+        .iter()
+        .map(|declaration| get_idl_type(declaration, definitions, defined))
+        .collect()
+}
+
+/// Get the IDL fields of a struct, or of an enum variant
+fn get_idl_fields(
+    fields: &Fields,
+    definitions: &Definitions,
+    defined: &mut BTreeSet<Declaration>,
+) -> Vec<Value> {
+    match fields {
+        Fields::NamedFields(fields) => fields
+            .iter()
+            .map(|(name, declaration)| {
+                json!({
+                    "name": to_camel_case(name),
+                    "type": get_idl_type(declaration, definitions, defined),
+                })
+            })
+            .collect(),
+        Fields::UnnamedFields(declarations) => get_idl_types(declarations, definitions, defined),
+        Fields::Empty => vec![],
+    }
+}
+
+/// Get the fields of the struct of a Borsh declaration e.g. of an enum variant
+fn get_struct_fields<'a>(declaration: &str, definitions: &'a Definitions) -> &'a Fields {
+    match definitions.get(declaration) {
+        Some(Definition::Struct { fields }) => fields,
+        _ => &Fields::Empty,
+    }
+}
+
+/// Get the IDL of a type used by the instructions or the accounts
+fn get_idl_type_definition(
+    declaration: &str,
+    definitions: &Definitions,
+    defined: &mut BTreeSet<Declaration>,
+) -> Option<Value> {
+    match definitions.get(declaration)? {
+        Definition::Struct { fields } => Some(json!({
+            "name": declaration,
+            "type": {
+                "kind": "struct",
+                "fields": get_idl_fields(fields, definitions, defined),
+            },
+        })),
+        Definition::Enum { variants } => {
+            let variants: Vec<Value> = variants
+                .iter()
+                .map(|(name, variant)| {
+                    let fields = get_idl_fields(
+                        get_struct_fields(variant, definitions),
+                        definitions,
+                        defined,
+                    );
+                    if fields.is_empty() {
+                        json!({ "name": name })
+                    } else {
+                        json!({ "name": name, "fields": fields })
+                    }
+                })
+                .collect();
+            Some(json!({
+                "name": declaration,
+                "type": { "kind": "enum", "variants": variants },
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Get the doc comment of each instruction, by the name of its variant
+fn get_instruction_docs() -> HashMap<String, Vec<String>> {
+    let mut result = HashMap::new();
+    let mut docs = vec![];
+    for line in INSTRUCTION_SOURCE
+        .lines()
+        .skip_while(|line| !line.starts_with(INSTRUCTION_ENUM))
+        .skip(1)
+        .take_while(|line| *line != "}")
+    {
+        // the docs of the fields of the variants are indented further
+        if let Some(doc) = line.strip_prefix("    ///") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+        } else if let Some(variant) = line
+            .strip_prefix("    ")
+            .filter(|rest| rest.starts_with(|character: char| character.is_ascii_uppercase()))
+        {
+            let name_end = variant
+                .find(|character: char| !character.is_alphanumeric())
+                .unwrap_or(variant.len());
+            result.insert(variant[..name_end].to_string(), std::mem::take(&mut docs));
+        }
+    }
+    result
+}
+
+/// Get the accounts that an instruction expects from its doc comment
+///
+/// e.g. "1. `[writable]` The merchant account.  Owned by this program".  The
+/// account is named after the start of its description e.g. "merchantAccount".
+fn get_idl_accounts(docs: &[String]) -> Vec<Value> {
+    let mut names: Vec<String> = vec![];
+    let mut accounts = vec![];
+    for doc in docs.iter() {
+        let doc = doc.trim();
+        let rest = match doc.split_once(". `[") {
+            Some((index, rest)) if index.parse::<u64>().is_ok() => rest,
+            _ => continue,
+        };
+        let (flags, description) = match rest.split_once("]`") {
+            None => continue,
+            Some(value) => value,
+        };
+        let flags: Vec<&str> = flags.split(',').map(|flag| flag.trim()).collect();
+        let description = description.trim();
+        let title = description
+            .split(" - ")
+            .next()
+            .unwrap_or("")
+            .split(|character| character == '.' || character == '(' || character == ',')
+            .next()
+            .unwrap_or("")
+            .replace('\'', "")
+            .replace('-', " ");
+        let words: Vec<&str> = title
+            .split_whitespace()
+            .skip_while(|word| ["the", "this", "a", "an"].contains(&word.to_lowercase().as_str()))
+            .collect();
+        let mut name = to_camel_case(&words.join(" "));
+        if names.contains(&name) {
+            name = format!("{}{}", name, accounts.len());
+        }
+        names.push(name.clone());
+        accounts.push(json!({
+            "name": name,
+            "isMut": flags.contains(&"writable"),
+            "isSigner": flags.contains(&"signer"),
+            "isOptional": flags.contains(&"optional"),
+            "docs": [description],
+        }));
+    }
+    accounts
+}
+
+/// Get the IDL of the program
+pub fn get_idl() -> Value {
+    let mut definitions = Definitions::new();
+    PaymentProcessorInstruction::add_definitions_recursively(&mut definitions);
+    let account_declarations = vec![
+        add_account::<ProgramConfigAccount>(&mut definitions),
+        add_account::<MerchantAccount>(&mut definitions),
+        add_account::<OrderAccount>(&mut definitions),
+        add_account::<SubscriptionAccount>(&mut definitions),
+        add_account::<IntegratorAccount>(&mut definitions),
+        add_account::<AttestationAccount>(&mut definitions),
+        add_account::<VoucherAccount>(&mut definitions),
+        add_account::<CouponAccount>(&mut definitions),
+        add_account::<PaymentLinkAccount>(&mut definitions),
+        add_account::<LinkPayerAccount>(&mut definitions),
+        add_account::<PriceGuaranteeAccount>(&mut definitions),
+        add_account::<AutoDebitAccount>(&mut definitions),
+        add_account::<FeeHistoryAccount>(&mut definitions),
+        add_account::<FeeScheduleAccount>(&mut definitions),
+        add_account::<CampaignAccount>(&mut definitions),
+        add_account::<RefundCodeAccount>(&mut definitions),
+        add_account::<MerchantRecoveryAccount>(&mut definitions),
+        add_account::<MerchantTransferAccount>(&mut definitions),
+        add_account::<MerchantStatsAccount>(&mut definitions),
+        add_account::<SweepBountyAccount>(&mut definitions),
+        add_account::<MigrationAccount>(&mut definitions),
+        add_account::<StoreAccount>(&mut definitions),
+        add_account::<AcceptedMintsAccount>(&mut definitions),
+        add_account::<BlockedBuyerAccount>(&mut definitions),
+        add_account::<ReferrerAccount>(&mut definitions),
+        add_account::<PackageAccount>(&mut definitions),
+        add_account::<PriceOracleAccount>(&mut definitions),
+        add_account::<BundleAccount>(&mut definitions),
+        add_account::<BundleMemberAccount>(&mut definitions),
+        add_account::<OrderNotesAccount>(&mut definitions),
+        add_account::<OrderNoteAccount>(&mut definitions),
+        add_account::<SettlementAccount>(&mut definitions),
+        add_account::<ItemStockAccount>(&mut definitions),
+    ];
+    let mut defined = BTreeSet::new();
+
+    let instruction_docs = get_instruction_docs();
+    let variants = match definitions.get(&PaymentProcessorInstruction::declaration()) {
+        Some(Definition::Enum { variants }) => variants.clone(),
+        _ => vec![],
+    };
+    let instructions: Vec<Value> = variants
+        .iter()
+        .enumerate()
+        .map(|(index, (name, variant))| {
+            let docs = instruction_docs.get(name).cloned().unwrap_or_default();
+            let args = get_idl_fields(
+                get_struct_fields(variant, &definitions),
+                &definitions,
+                &mut defined,
+            );
+            json!({
+                "name": to_camel_case(name),
+                "docs": docs,
+                "accounts": get_idl_accounts(&docs),
+                "args": args,
+                "discriminant": { "type": "u8", "value": index },
+            })
+        })
+        .collect();
+
+    let accounts: Vec<Value> = account_declarations
+        .iter()
+        .filter_map(|declaration| get_idl_type_definition(declaration, &definitions, &mut defined))
+        .collect();
+
+    // the types referred to, and the types that those refer to
+    let mut types = vec![];
+    let mut done = BTreeSet::new();
+    loop {
+        let declaration = match defined.difference(&done).next() {
+            None => break,
+            Some(value) => value.clone(),
+        };
+        done.insert(declaration.clone());
+        if let Some(type_definition) =
+            get_idl_type_definition(&declaration, &definitions, &mut defined)
+        {
+            types.push(type_definition);
+        }
+    }
+
+    let errors: Vec<Value> = (0..)
+        .map_while(PaymentProcessorError::from_u32)
+        .enumerate()
+        .map(|(code, error)| {
+            let msg = error.to_string();
+            json!({
+                "code": code,
+                "name": format!("{:?}", error),
+                "msg": msg.trim_start_matches("Error: "),
+            })
+        })
+        .collect();
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "name": env!("CARGO_PKG_NAME").replace('-', "_"),
+        "instructions": instructions,
+        "accounts": accounts,
+        "types": types,
+        "errors": errors,
+        // the layout of Shank IDLs, i.e. of programs that do not use Anchor
+        "metadata": { "origin": "shank" },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*, crate::engine::json::OrderItems, borsh::BorshSerialize, solana_program_test::*,
+    };
+
+    fn find<'a>(items: &'a Value, name: &str) -> &'a Value {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["name"] == name)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_idl() {
+        let idl = get_idl();
+        assert_eq!("sol_payment_processor", idl["name"]);
+
+        // the discriminant of an instruction is the first byte of its data
+        let instruction = find(&idl["instructions"], "setFreezePolicy");
+        let data = PaymentProcessorInstruction::SetFreezePolicy {
+            rejects_freezable_mints: true,
+        }
+        .try_to_vec()
+        .unwrap();
+        assert_eq!(json!(data[0]), instruction["discriminant"]["value"]);
+        assert_eq!(
+            json!([{ "name": "rejectsFreezableMints", "type": "bool" }]),
+            instruction["args"]
+        );
+        let accounts = instruction["accounts"].as_array().unwrap();
+        assert_eq!(2, accounts.len());
+        assert_eq!(
+            (json!("merchantOwner"), json!(true), json!(false)),
+            (
+                accounts[0]["name"].clone(),
+                accounts[0]["isSigner"].clone(),
+                accounts[0]["isMut"].clone()
+            )
+        );
+        assert_eq!(
+            (json!("merchantAccount"), json!(false), json!(true)),
+            (
+                accounts[1]["name"].clone(),
+                accounts[1]["isSigner"].clone(),
+                accounts[1]["isMut"].clone()
+            )
+        );
+        // every instruction finds its documented accounts
+        for instruction in idl["instructions"].as_array().unwrap() {
+            assert!(
+                !instruction["accounts"].as_array().unwrap().is_empty(),
+                "{}",
+                instruction["name"]
+            );
+        }
+
+        let register = find(&idl["instructions"], "registerMerchant");
+        assert_eq!(json!(0), register["discriminant"]["value"]);
+        assert_eq!(json!(true), register["accounts"][5]["isOptional"]);
+        assert_eq!(
+            json!({ "hashMap": ["string", "u64"] }),
+            find(
+                &find(&idl["instructions"], "chainCheckout")["args"],
+                "orderItems"
+            )["type"]
+        );
+
+        // the arrays of the accounts are sized by the constants of the program
+        let merchant = find(&idl["accounts"], "MerchantAccount");
+        assert_eq!(
+            json!({ "array": ["publicKey", 3] }),
+            find(&merchant["type"]["fields"], "operators")["type"]
+        );
+        // the types used by the instructions and the accounts are defined
+        assert_eq!(
+            json!("enum"),
+            find(&idl["types"], "MerchantTerms")["type"]["kind"]
+        );
+        assert_eq!(
+            json!("struct"),
+            find(&idl["types"], "CatalogItem")["type"]["kind"]
+        );
+        assert_eq!(
+            json!("struct"),
+            find(&idl["types"], "Voucher")["type"]["kind"]
+        );
+
+        let error = find(&idl["errors"], "FreezableMint");
+        assert_eq!(
+            json!(PaymentProcessorError::FreezableMint as u32),
+            error["code"]
+        );
+        assert!(!error["msg"].as_str().unwrap().starts_with("Error"));
+    }
+
+    #[test]
+    fn test_order_items_layout() {
+        // the order items of a chain checkout are written as OrderItems would be
+        let mut order_items = OrderItems::new();
+        order_items.insert(String::from("zebra"), 2);
+        order_items.insert(String::from("apple"), 1);
+        order_items.insert(String::from("mango"), 3);
+        let map: HashMap<String, u64> = order_items.clone().into_iter().collect();
+        assert_eq!(order_items.try_to_vec().unwrap(), map.try_to_vec().unwrap());
+    }
+}
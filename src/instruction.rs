@@ -1,14 +1,69 @@
-use crate::engine::json::OrderItems;
-use borsh::{BorshDeserialize, BorshSerialize};
+use crate::engine::{
+    accepted_mints::get_accepted_mints_address,
+    associated_token::{
+        get_associated_token_address, get_associated_token_program_id, get_order_token_address,
+    },
+    attestation::get_attestation_address,
+    auto_debit::get_auto_debit_address,
+    blocklist::get_blocked_buyer_address,
+    bundle::{get_bundle_address, get_bundle_member_address},
+    campaign::get_campaign_address,
+    catalog::get_package_address,
+    config::{get_program_config_address, get_program_data_address},
+    constants::{ED25519_PROGRAM_ID, PDA_SEED, VOUCHER},
+    coupon::get_coupon_address,
+    fee_history::{get_fee_history_address, get_fee_schedule_address},
+    guarantee::get_price_guarantee_address,
+    json::OrderItems,
+    link::{get_link_payer_address, get_payment_link_address},
+    migrate::get_migration_address,
+    notes::{get_order_note_address, get_order_notes_address},
+    oracle::get_price_oracle_address,
+    receipt::{get_master_edition_address, get_metadata_address, get_token_metadata_program_id},
+    recovery::{get_merchant_recovery_address, get_merchant_transfer_address},
+    referral::get_referrer_address,
+    refund::{get_refund_code_address, get_refund_code_hash},
+    settlement::get_settlement_address,
+    stats::get_merchant_stats_address,
+    stock::get_item_stock_address,
+    store::get_store_address,
+    sweep::get_sweep_bounty_address,
+    voucher::get_voucher_address,
+};
+use crate::state::{DiscountType, MerchantTerms};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
+    clock::UnixTimestamp,
     instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
     sysvar,
 };
 use spl_token::{self};
-use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A payment voucher issued (and signed) by a merchant while offline
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
+pub struct Voucher {
+    /// the merchant account
+    pub merchant: Pubkey,
+    /// the token mint account - represents the 'currency' being used
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// the voucher cannot be redeemed from this timestamp on
+    pub expires_at: UnixTimestamp,
+    /// unique (per merchant) number that ensures the voucher is redeemed only once
+    pub nonce: u64,
+}
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+impl Voucher {
+    /// The message that the merchant owner signs
+    pub fn message(&self) -> Vec<u8> {
+        [VOUCHER.as_bytes(), &self.try_to_vec().unwrap()[..]].concat()
+    }
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub enum PaymentProcessorInstruction {
     /// Register for a merchant account.
     ///
@@ -18,7 +73,8 @@ pub enum PaymentProcessorInstruction {
     /// 1. `[writable]` The merchant account.  Owned by this program
     /// 2. `[]` System program
     /// 3. `[]` The rent sysvar
-    /// 4. `[optional]` The sponsor account
+    /// 4. `[]` The program config account
+    /// 5. `[optional]` The sponsor account
     RegisterMerchant {
         /// the seed used when creating the account
         #[allow(dead_code)] // not dead code..
@@ -29,17 +85,62 @@ pub enum PaymentProcessorInstruction {
         /// arbitrary merchant data (maybe as a JSON string)
         #[allow(dead_code)] // not dead code..
         data: Option<String>,
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: Option<u64>,
     },
     /// Express Checkout
     ///
     /// Meant to be used to process payments initialized by systems that reside off-chain
     /// such as traditional e-commerce software.
     ///
+    /// An order can be made to expire by including an expiry timestamp in the data
+    /// e.g. {"expires_at": 1625097600}, which has to be in the future.  Once expired, the
+    /// order can no longer be withdrawn and the payer may reclaim the payment using
+    /// ReclaimExpiredOrder.
+    ///
+    /// An order can be held in escrow by including an escrow timeout (in seconds) in the
+    /// data e.g. {"escrow": 1209600}.  Escrow orders cannot be withdrawn until the buyer
+    /// sends a ConfirmDelivery instruction or the escrow timeout elapses.
+    ///
+    /// A coupon of the merchant can be redeemed by including the coupon account.  The
+    /// discount is taken off the amount and recorded in the order data.
+    ///
+    /// A payment made through a payment link is counted towards the link by including
+    /// the payment link account and the link payer account.
+    ///
+    /// When the program config data names a screening program e.g.
+    /// {"screening_program": "<program id>"}, the payment has to be approved by it:
+    /// the screening program account is required, followed by the accounts of the
+    /// screening program that it needs (see crate::engine::screening).
+    ///
+    /// The amount and the tip can be priced in fiat by including the price oracle
+    /// account that the merchant set for the mint (see SetPriceOracle) and its price
+    /// feed account.  They are then fiat amounts with FIAT_DECIMALS decimals, which
+    /// are converted into the mint at the oracle price and recorded in the order data.
+    ///
+    /// An order for a subscription package that takes part in a bundle (see CreateBundle)
+    /// gets the bundle discount by including the bundle account, the bundle member account
+    /// of the merchant, an active subscription of the payer to the package of another
+    /// merchant in the bundle and the bundle member account of that merchant.
+    ///
+    /// When the program config data sets a receipt URI e.g.
+    /// {"receipt_uri": "https://example.com/receipt"}, a receipt NFT of the payment
+    /// can be minted to the buyer by including the token metadata program followed by
+    /// the accounts of the receipt (see use_receipt and crate::engine::receipt).
+    ///
+    /// The order account is the address derived from the merchant account and the
+    /// order id (see crate::engine::common::get_order_address).  An order can only
+    /// be made once for an order id, so that a checkout sent twice is not paid twice.
+    /// The same goes for all the instructions that create an order.
+    ///
+    /// The optional accounts can each be left out.
+    ///
     /// Accounts expected:
     ///
     /// 0. `[signer]` The account of the person initializing the transaction
     /// 1. `[writable]` The order account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
     /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
     /// 4. `[writable]` The buyer token account
     /// 5. `[writable]` The program owner account (where we will send program owner fee)
@@ -49,9 +150,25 @@ pub enum PaymentProcessorInstruction {
     /// 9. `[]` The token program
     /// 10. `[]` The System program
     /// 11. `[]` The rent sysvar
+    /// 12. `[]` The program config account
+    /// 13. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 14. `[writable, optional]` The coupon account (whose discount is taken off the amount)
+    /// 15. `[writable, optional]` The payment link account (that the payment is counted towards)
+    /// 16. `[writable, optional]` The link payer account - derived from the payment link account and the signer, and required with the payment link account
+    /// 17. `[optional]` The price oracle account (that the fiat amounts are converted at)
+    /// 18. `[optional]` The Pyth price feed account - the one set in the price oracle account, and required with the price oracle account
+    /// 19. `[optional]` The bundle account (whose discount is taken off the amount)
+    /// 20. `[optional]` The bundle member account of the merchant - required with the bundle account
+    /// 21. `[optional]` The subscription account of the payer to another package in the bundle - required with the bundle account
+    /// 22. `[optional]` The bundle member account of the merchant of that subscription - required with the bundle account
+    /// 23. `[writable, optional]` The merchant stats account (that the order token accounts are counted towards)
+    /// 24. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
     ExpressCheckout {
         #[allow(dead_code)] // not dead code..
         amount: u64,
+        /// an optional tip paid on top of the amount
+        #[allow(dead_code)] // not dead code..
+        tip: u64,
         /// the external order id (as in issued by the merchant)
         #[allow(dead_code)] // not dead code..
         order_id: String,
@@ -73,11 +190,35 @@ pub enum PaymentProcessorInstruction {
     /// items for which payment can be made.  See the engine::json::Item struct as well
     /// as the chain checkout tests for more on how this works.
     ///
+    /// Just like ExpressCheckout, an expiry timestamp and an escrow timeout can be
+    /// included in the data.
+    ///
+    /// Items whose stock is tracked (see RestockItem) are taken out of stock, and the
+    /// checkout fails if any of them is sold out.
+    ///
+    /// The order items may be priced in several mints.  The amount covers the items
+    /// priced in the checkout mint, and the items priced in each other mint are paid
+    /// in full into an order token account of their own.
+    ///
+    /// A chain checkout has no order id of its own: its order id is the number that
+    /// the order gets in the order sequence of the merchant, i.e. the order sequence
+    /// of the merchant account plus one (see
+    /// crate::engine::common::get_next_order_id), which the order account is
+    /// derived from.
+    ///
+    /// Just like ExpressCheckout, a coupon can be redeemed by including the coupon
+    /// account and the payment can be counted towards a payment link.
+    ///
+    /// Items gated by a collection are only sold to buyers who show an NFT of the
+    /// collection, by including its metadata account and their token account of
+    /// the NFT (see use_collection_nft).  Items with a holder discount are sold to
+    /// anyone, and the discount is taken off for holders in place of a coupon.
+    ///
     /// Accounts expected:
     ///
     /// 0. `[signer]` The account of the person initializing the transaction
     /// 1. `[writable]` The order account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
     /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
     /// 4. `[writable]` The buyer token account
     /// 5. `[writable]` The program owner account (where we will send program owner fee)
@@ -87,12 +228,26 @@ pub enum PaymentProcessorInstruction {
     /// 9. `[]` The token program
     /// 10. `[]` The System program
     /// 11. `[]` The rent sysvar
+    /// 12. `[]` The program config account
+    /// 13. For each other mint (sorted by address), three accounts:
+    ///     `[]` the mint,
+    ///     `[writable]` the seller token account of that mint (derived like account 3),
+    ///     `[writable]` the buyer token account of that mint
+    /// 14. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 15. `[writable, optional]` The coupon account (whose discount is taken off the amount)
+    /// 16. `[writable, optional]` The payment link account (that the payment is counted towards)
+    /// 17. `[writable, optional]` The link payer account - derived from the payment link account and the signer, and required with the payment link account
+    /// 18. `[writable, optional]` The item stock accounts - required for each order item whose stock is tracked
+    /// 19. `[writable, optional]` The merchant stats account (that the order token accounts are counted towards)
+    /// 20. `[optional]` The metadata account of an NFT of the buyer - required for the items gated by its collection
+    /// 21. `[optional]` The token account of the buyer that holds the NFT - required with the metadata account
+    /// 22. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
     ChainCheckout {
         #[allow(dead_code)] // not dead code..
         amount: u64,
         /// the external order id (as in issued by the merchant)
         #[allow(dead_code)] // not dead code..
-        order_items: BTreeMap<String, u64>, // use this instead of OrderItems for readability in API
+        order_items: HashMap<String, u64>, // not OrderItems, which has no Borsh schema (both are written sorted by key)
         /// arbitrary merchant data (maybe as a JSON string)
         #[allow(dead_code)] // not dead code..
         data: Option<String>,
@@ -106,12 +261,28 @@ pub enum PaymentProcessorInstruction {
     ///
     /// 0. `[signer]` The account of the person initializing the transaction
     /// 1. `[writable]` The order account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
     /// 3. `[writable]` The order token account (where the money was put during payment)
     /// 4. `[writable]` The merchant token account (where we will withdraw to)
     /// 5. `[writable]` This account receives the refunded SOL after closing order token account
     /// 6. `[]` This program's derived address
     /// 7. `[]` The token program
+    /// 8. Only if the merchant token account is an associated token account that
+    ///    does not exist yet, the accounts needed to create it (paid for by the signer):
+    ///    `[]` the merchant owner account,
+    ///    `[]` the token mint account of the order,
+    ///    `[]` the System program,
+    ///    `[]` the rent sysvar,
+    ///    `[]` the associated token account program
+    /// 9. `[optional]` The subscription account (for merchants with a trial period)
+    /// 10. For each other mint of a chain checkout order, two accounts:
+    ///     `[writable]` the order token account of that mint,
+    ///     `[writable]` the merchant token account of that mint
+    /// 11. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    /// 12. `[writable, optional]` The referrer token account (the associated token account of the referrer of the order) - required for orders with a referrer
+    /// 13. `[writable, optional]` The payout token accounts (the associated token account of each wallet of the payout schedule) - required for merchants with a payout schedule, in which case the payment goes to them instead of the merchant token account
+    /// 14. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order goes back to) - required for orders that hold a sweep bounty
+    /// 15. `[optional]` The token mint accounts of the mints of the order (see use_transfer_checked) - required for Token-2022 mints that are only moved with TransferChecked, e.g. mints with a transfer fee
     Withdraw {
         /// should we close the order account?
         /// can be sent as 0 for false; 1 for true from a dApp
@@ -128,14 +299,22 @@ pub enum PaymentProcessorInstruction {
     /// by a Subscribe instruction.  The actual payment is made in the ExpressCheckout instruction
     /// and subsequently thr subscription is activated in the Subscribe instruction.
     ///
+    /// Packages with a trial period and `price_due_at_trial_end` can be subscribed to
+    /// without an order, leaving out the order account.  The subscription is then
+    /// `Trialing` until the end of the trial period, and the first RenewSubscription
+    /// pays for it.
+    ///
+    /// The package price is per seat, so the order has to pay for all the seats.
+    ///
     /// Accounts expected:
     ///
     /// 0. `[signer]` The account of the person initializing the transaction
     /// 1. `[writable]` The subscription account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
-    /// 3. `[]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[]` The order account.  Owned by this program.  Left out for free trials
     /// 4. `[]` The System program
     /// 5. `[]` The rent sysvar
+    /// 6. `[optional]` The package account - required for packages of the catalog of the merchant (see CreatePackage)
     Subscribe {
         /// the subscription package name
         #[allow(dead_code)] // not dead code..
@@ -143,6 +322,9 @@ pub enum PaymentProcessorInstruction {
         /// arbitrary merchant data (maybe as a JSON string)
         #[allow(dead_code)] // not dead code..
         data: Option<String>,
+        /// the number of seats (one if not provided)
+        #[allow(dead_code)] // not dead code..
+        seats: Option<u64>,
     },
     /// Renew a subscription
     ///
@@ -153,10 +335,11 @@ pub enum PaymentProcessorInstruction {
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 0. `[signer]` The subscription owner
     /// 1. `[writable]` The subscription account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
     /// 3. `[]` The order account.  Owned by this program
+    /// 4. `[optional]` The price guarantee account of the subscription package
     RenewSubscription {
         /// the number of periods to renew e.g. if the subscription period is a year
         /// you can choose to renew for 1 year, 2 years, n years, etc
@@ -168,271 +351,5033 @@ pub enum PaymentProcessorInstruction {
     /// If a CancelSubscription instruction is sent during the trial period of a
     /// subscription, the amount initially paid for the subscription will be refunded in
     /// full.
+    /// After the trial period, subscriptions to packages with `prorated_refunds`
+    /// get back the part of the amount paid for the unused part of the period,
+    /// computed from the clock and the package duration, and end right away.  The
+    /// rest of the order can still be withdrawn by the merchant.  The refund comes
+    /// out of the order that paid for the current period (the last one that the
+    /// subscription was subscribed or renewed with).
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 0. `[signer]` The subscription owner, who may not be the order payer once the subscription was transferred
     /// 1. `[writable]` The subscription account.  Owned by this program
-    /// 2. `[]` The merchant account.  Owned by this program
-    /// 3. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order account that paid for the current period.  Owned by this program
     /// 4. `[writable]` The order token account - this is where the amount was paid into. Owned by this program
-    /// 5. `[writable]` The refund token account - this is where the refund will go
+    /// 5. `[writable]` The refund token account of the subscription owner - this is where the refund will go
     /// 6. `[writable]` This account receives the refunded SOL after closing order token account
     /// 7. `[]` This program's derived address
     /// 8. `[]` The token program
+    /// 9. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    /// 10. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order goes back to) - required for orders that hold a sweep bounty
+    /// 11. `[optional]` The token mint account of the order (see use_transfer_checked) - required for Token-2022 mints that are only moved with TransferChecked, e.g. mints with a transfer fee
     CancelSubscription,
-}
-
-/// Creates an 'RegisterMerchant' instruction.
-pub fn register_merchant(
-    program_id: Pubkey,
-    signer: Pubkey,
-    merchant: Pubkey,
-    seed: Option<String>,
-    fee: Option<u64>,
-    data: Option<String>,
-    sponsor: Option<&Pubkey>,
-) -> Instruction {
-    let mut account_metas = vec![
-        AccountMeta::new(signer, true),
-        AccountMeta::new(merchant, false),
-        AccountMeta::new_readonly(solana_program::system_program::id(), false),
-        AccountMeta::new_readonly(sysvar::rent::id(), false),
-    ];
-
-    if let Some(sponsor) = sponsor {
-        account_metas.push(AccountMeta::new_readonly(*sponsor, false));
-    }
-
-    Instruction {
-        program_id,
-        accounts: account_metas,
-        data: PaymentProcessorInstruction::RegisterMerchant { seed, fee, data }
-            .try_to_vec()
-            .unwrap(),
-    }
-}
-
-/// Creates an 'ExpressCheckout' instruction.
-pub fn express_checkout(
-    program_id: Pubkey,
-    signer: Pubkey,
-    order: Pubkey,
-    merchant: Pubkey,
-    seller_token: Pubkey,
-    buyer_token: Pubkey,
-    mint: Pubkey,
-    program_owner: Pubkey,
-    sponsor: Pubkey,
-    pda: Pubkey,
-    amount: u64,
-    order_id: String,
-    secret: String,
-    data: Option<String>,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(signer, true),
-            AccountMeta::new(order, true),
-            AccountMeta::new_readonly(merchant, false),
-            AccountMeta::new(seller_token, false),
-            AccountMeta::new(buyer_token, false),
-            AccountMeta::new(program_owner, false),
-            AccountMeta::new(sponsor, false),
-            AccountMeta::new_readonly(mint, false),
-            AccountMeta::new_readonly(pda, false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-            AccountMeta::new_readonly(solana_program::system_program::id(), false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-        ],
-        data: PaymentProcessorInstruction::ExpressCheckout {
-            amount,
-            order_id,
-            secret,
-            data,
-        }
-        .try_to_vec()
-        .unwrap(),
-    }
-}
-
-/// Creates an 'ChainCheckout' instruction.
-pub fn chain_checkout(
-    program_id: Pubkey,
-    signer: Pubkey,
-    order: Pubkey,
-    merchant: Pubkey,
-    seller_token: Pubkey,
-    buyer_token: Pubkey,
-    mint: Pubkey,
-    program_owner: Pubkey,
-    sponsor: Pubkey,
-    pda: Pubkey,
-    amount: u64,
-    order_items: OrderItems,
-    data: Option<String>,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(signer, true),
-            AccountMeta::new(order, true),
-            AccountMeta::new_readonly(merchant, false),
-            AccountMeta::new(seller_token, false),
-            AccountMeta::new(buyer_token, false),
-            AccountMeta::new(program_owner, false),
-            AccountMeta::new(sponsor, false),
-            AccountMeta::new_readonly(mint, false),
-            AccountMeta::new_readonly(pda, false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-            AccountMeta::new_readonly(solana_program::system_program::id(), false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-        ],
-        data: PaymentProcessorInstruction::ChainCheckout {
-            amount,
-            order_items,
-            data,
-        }
-        .try_to_vec()
-        .unwrap(),
-    }
-}
-
-/// Creates an 'Withdraw' instruction.
-pub fn withdraw(
-    program_id: Pubkey,
-    signer: Pubkey,
-    order: Pubkey,
-    merchant: Pubkey,
-    order_payment_token: Pubkey,
-    merchant_token: Pubkey,
-    account_to_receive_sol_refund: Pubkey,
-    pda: Pubkey,
-    subscription: Option<Pubkey>,
-    close_order_account: bool,
-) -> Instruction {
-    let mut account_metas = vec![
-        AccountMeta::new(signer, true),
-        AccountMeta::new(order, false),
-        AccountMeta::new_readonly(merchant, false),
-        AccountMeta::new(order_payment_token, false),
-        AccountMeta::new(merchant_token, false),
-        AccountMeta::new(account_to_receive_sol_refund, false),
-        AccountMeta::new_readonly(pda, false),
-        AccountMeta::new_readonly(spl_token::id(), false),
-    ];
-
-    if let Some(subscription) = subscription {
-        account_metas.push(AccountMeta::new_readonly(subscription, false));
-    }
-
-    Instruction {
-        program_id,
-        accounts: account_metas,
-        data: PaymentProcessorInstruction::Withdraw {
-            close_order_account,
-        }
-        .try_to_vec()
-        .unwrap(),
-    }
-}
-
-/// creates a 'Subscribe' instruction
-pub fn subscribe(
-    program_id: Pubkey,
-    signer: Pubkey,
-    subscription: Pubkey,
-    merchant: Pubkey,
-    order: Pubkey,
-    name: String,
-    data: Option<String>,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(signer, true),
-            AccountMeta::new(subscription, false),
-            AccountMeta::new_readonly(merchant, false),
-            AccountMeta::new_readonly(order, false),
-            AccountMeta::new_readonly(solana_program::system_program::id(), false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-        ],
-        data: PaymentProcessorInstruction::Subscribe { name, data }
-            .try_to_vec()
-            .unwrap(),
-    }
-}
-
-/// creates a 'RenewSubscription' instruction
-pub fn renew_subscription(
-    program_id: Pubkey,
-    signer: Pubkey,
-    subscription: Pubkey,
-    merchant: Pubkey,
-    order: Pubkey,
-    quantity: i64,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(signer, true),
-            AccountMeta::new(subscription, false),
-            AccountMeta::new_readonly(merchant, false),
-            AccountMeta::new_readonly(order, false),
-        ],
-        data: PaymentProcessorInstruction::RenewSubscription { quantity }
-            .try_to_vec()
-            .unwrap(),
-    }
-}
-
-/// creates a 'CancelSubscription' instruction
-pub fn cancel_subscription(
-    program_id: Pubkey,
-    signer: Pubkey,
-    subscription: Pubkey,
-    merchant: Pubkey,
-    order: Pubkey,
-    order_token: Pubkey,
-    refund_token: Pubkey,
-    account_to_receive_sol_refund: Pubkey,
-    pda: Pubkey,
-) -> Instruction {
-    Instruction {
-        program_id,
-        accounts: vec![
-            AccountMeta::new(signer, true),
-            AccountMeta::new(subscription, false),
-            AccountMeta::new_readonly(merchant, false),
-            AccountMeta::new(order, false),
-            AccountMeta::new(order_token, false),
-            AccountMeta::new(refund_token, false),
-            AccountMeta::new(account_to_receive_sol_refund, false),
-            AccountMeta::new_readonly(pda, false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
-        data: PaymentProcessorInstruction::CancelSubscription
-            .try_to_vec()
-            .unwrap(),
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use {
+    /// Reclaim an expired order
+    ///
+    /// Refunds the amount paid for an order that expired before the merchant
+    /// withdrew it.  Only the order payer can reclaim the payment.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The order payer
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order token account - this is where the amount was paid into. Owned by this program
+    /// 4. `[writable]` The refund token account - this is where the refund will go
+    /// 5. `[writable]` This account receives the refunded SOL after closing order token account
+    /// 6. `[]` This program's derived address
+    /// 7. `[]` The token program
+    /// 8. For each other mint of a chain checkout order, two accounts:
+    ///    `[writable]` the order token account of that mint,
+    ///    `[writable]` the refund token account of that mint
+    /// 9. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    /// 10. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order goes back to) - required for orders that hold a sweep bounty
+    /// 11. `[optional]` The token mint accounts of the mints of the order (see use_transfer_checked) - required for Token-2022 mints that are only moved with TransferChecked, e.g. mints with a transfer fee
+    ReclaimExpiredOrder,
+    /// Express Checkout paid for in SOL
+    ///
+    /// Works just like ExpressCheckout except that the buyer pays directly in SOL.
+    /// The amount is wrapped into a temporary WSOL token account which is closed
+    /// once the payment has been made.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable, signer]` The temporary WSOL token account.  Created and closed by this instruction
+    /// 5. `[writable]` The program owner account (where we will send program owner fee)
+    /// 6. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 7. `[]` The native mint account
+    /// 8. `[]` This program's derived address
+    /// 9. `[]` The token program
+    /// 10. `[]` The System program
+    /// 11. `[]` The rent sysvar
+    /// 12. `[]` The program config account
+    /// 13. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 14. `[writable, optional]` The coupon account (whose discount is taken off the amount)
+    /// 15. `[writable, optional]` The payment link account (that the payment is counted towards)
+    /// 16. `[writable, optional]` The link payer account - derived from the payment link account and the signer, and required with the payment link account
+    /// 17. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    ExpressCheckoutSol {
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// an optional tip paid on top of the amount
+        #[allow(dead_code)] // not dead code..
+        tip: u64,
+        /// the external order id (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+        // An extra field that can store an encrypted (ot not encrypted) string
+        // that the merchant can use to assert if a transaction is authentic
+        #[allow(dead_code)] // not dead code..
+        secret: String,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Register an integrator
+    ///
+    /// Integrators are wallets, apps and plugins that facilitate payments.  When the
+    /// integrator account is included in a checkout instruction the integrator
+    /// receives a share of the transaction fee.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person registering the integrator
+    /// 1. `[writable]` The integrator account.  Owned by this program and derived from the app id
+    /// 2. `[]` System program
+    /// 3. `[]` The rent sysvar
+    RegisterIntegrator {
+        /// the id of the app (e.g. wallet or plugin) that facilitates payments
+        #[allow(dead_code)] // not dead code..
+        app_id: String,
+    },
+    /// Withdraw the fees accrued by an integrator
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The integrator owner
+    /// 1. `[writable]` The integrator account.  Owned by this program
+    /// 2. `[writable]` The account to receive the fees
+    /// 3. `[]` The rent sysvar
+    WithdrawIntegratorFees,
+    /// Confirm delivery of an escrow order
+    ///
+    /// Sent by the buyer once the goods have been received.  This releases the payment
+    /// so that the merchant can withdraw it.  The buyer can instead release part of
+    /// it (e.g. for a delivered milestone), which the merchant can withdraw with
+    /// WithdrawAmount while the rest stays held until it is released as well or
+    /// the escrow timeout elapses.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The order payer
+    /// 1. `[writable]` The order account.  Owned by this program
+    ConfirmDelivery {
+        /// the amount released, on top of the amount released so far - None
+        /// releases the whole payment
+        #[allow(dead_code)] // not dead code..
+        amount: Option<u64>,
+    },
+    /// Initialize the program
+    ///
+    /// Meant to be sent once, right after the program is deployed.  It creates the
+    /// program config account which holds the program owner, the default fees and
+    /// the branding of this instance of the program.  This makes it possible to
+    /// deploy the program under any program id.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The upgrade authority of the program (becomes the program authority)
+    /// 1. `[writable]` The program config account.  Owned by this program and derived from the program id
+    /// 2. `[]` The program owner account (where program owner fees will be sent)
+    /// 3. `[]` System program
+    /// 4. `[]` The rent sysvar
+    /// 5. `[]` The program data account of the program.  Owned by the upgradeable BPF loader
+    InitializeProgram {
+        /// the fee (in SOL lamports) charged when a merchant does not set one
+        #[allow(dead_code)] // not dead code..
+        default_fee: Option<u64>,
+        /// the minimum fee (in SOL lamports) that a merchant can set
+        #[allow(dead_code)] // not dead code..
+        min_fee: Option<u64>,
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: Option<u64>,
+        /// the name under which this instance of the program is branded
+        #[allow(dead_code)] // not dead code..
+        name: String,
+        /// arbitrary branding data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Export an attestation of a merchant's order history and subscription standing
+    ///
+    /// Meant to be used by merchants moving to another instance of this program.  The
+    /// attestation account can only be written by this program, so the other instance
+    /// can rely on it (see ImportAttestation) without trusting the merchant's claims.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The attestation account.  Owned by this program and derived from the merchant account
+    /// 3. `[]` System program
+    /// 4. `[]` The rent sysvar
+    /// 5. .. `[]` The order and subscription accounts of the merchant, sorted by address
+    ExportAttestation,
+    /// Import an attestation issued by another instance of this program
+    ///
+    /// The issuing instance must be listed in the program config data under
+    /// "trusted_instances".
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[]` The attestation account.  Owned by the issuing instance
+    /// 3. `[writable]` The attestation account.  Owned by this program and derived from the merchant account
+    /// 4. `[]` The program config account
+    /// 5. `[]` System program
+    /// 6. `[]` The rent sysvar
+    ImportAttestation,
+    /// Split Checkout
+    ///
+    /// Works like ExpressCheckout except that the payment is split among several
+    /// recipient token accounts (e.g. a marketplace and a vendor).  Each recipient
+    /// gets its share in basis points and the shares must add up to 10000.  The
+    /// payment goes straight to the recipients so there is nothing to withdraw.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The buyer token account
+    /// 4. `[writable]` The program owner account (where we will send program owner fee)
+    /// 5. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 6. `[]` The token mint account - represents the 'currency' being used
+    /// 7. `[]` The token program
+    /// 8. `[]` The System program
+    /// 9. `[]` The rent sysvar
+    /// 10. `[]` The program config account
+    /// 11. .. `[writable]` The recipient token accounts, one for each split
+    /// 12. `[writable, optional]` The integrator account (where we will send integrator fee), at 11 + number of splits
+    SplitCheckout {
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// the external order id (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+        // An extra field that can store an encrypted (ot not encrypted) string
+        // that the merchant can use to assert if a transaction is authentic
+        #[allow(dead_code)] // not dead code..
+        secret: String,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+        /// the share (in basis points) of each recipient token account
+        #[allow(dead_code)] // not dead code..
+        splits: Vec<u16>,
+    },
+    /// Pay an installment
+    ///
+    /// An ExpressCheckout order can be paid in installments by including the full
+    /// amount in the data e.g. {"expected_amount": 5000000}.  The amount paid at
+    /// checkout is the first installment and the order stays Pending until the
+    /// remaining installments have been paid using this instruction.  Like
+    /// checkouts, installments fail while the merchant is paused or has blocked
+    /// the buyer.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The order payer
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The order token account - this is where the installments are paid into. Owned by this program
+    /// 3. `[writable]` The buyer token account
+    /// 4. `[]` The token program
+    /// 5. `[]` The merchant account of the order.  Owned by this program
+    /// 6. `[optional]` The token mint account of the order (see use_transfer_checked) - required for Token-2022 mints that are only moved with TransferChecked, e.g. mints with a transfer fee
+    PayInstallment {
+        /// the amount of this installment
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+    },
+    /// Redeem a voucher
+    ///
+    /// Pays for a voucher that the merchant owner signed while offline, e.g. at events
+    /// and pop-up stores with flaky connectivity.  The instruction right before this one
+    /// must be an ed25519 program instruction that verifies the merchant owner's
+    /// signature of the voucher message (see Voucher::message).  An order is created
+    /// just like with ExpressCheckout, with the voucher nonce as the order id.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. - 12. The same accounts as ExpressCheckout (without the integrator account)
+    /// 13. `[writable]` The voucher account.  Owned by this program and derived from the merchant account and the nonce
+    /// 14. `[]` The instructions sysvar
+    /// 15. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    RedeemVoucher {
+        /// the voucher being redeemed
+        #[allow(dead_code)] // not dead code..
+        voucher: Voucher,
+    },
+    /// Create an invoice
+    ///
+    /// Lets the merchant create an order ahead of payment.  The order is Pending until
+    /// it is paid (by any wallet) using PayInvoice.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[]` The token mint account - represents the 'currency' being used
+    /// 5. `[]` This program's derived address
+    /// 6. `[]` The token program
+    /// 7. `[]` The System program
+    /// 8. `[]` The rent sysvar
+    /// 9. `[writable, optional]` The merchant stats account (that the order token accounts are counted towards)
+    CreateInvoice {
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// the external order id (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+        // An extra field that can store an encrypted (ot not encrypted) string
+        // that the merchant can use to assert if a transaction is authentic
+        #[allow(dead_code)] // not dead code..
+        secret: String,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Pay an invoice
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person paying the invoice
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The buyer token account
+    /// 5. `[writable]` The program owner account (where we will send program owner fee)
+    /// 6. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 7. `[]` The token mint account - represents the 'currency' being used
+    /// 8. `[]` The token program
+    /// 9. `[]` The System program
+    /// 10. `[]` The program config account
+    /// 11. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 12. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    PayInvoice,
+    /// Set the migration window
+    ///
+    /// Sent by the program authority around a program upgrade.  Payments are paused
+    /// until `ends_at` so that accounts can be migrated, and the program state is
+    /// marked with the version that the accounts were migrated to.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The program authority (the account that initialized the program)
+    /// 1. `[writable]` The program config account
+    SetMigrationWindow {
+        /// payments are paused until this time - a time in the past ends the window
+        #[allow(dead_code)] // not dead code..
+        ends_at: UnixTimestamp,
+        /// the version of the program state once the migration is done
+        #[allow(dead_code)] // not dead code..
+        version: u8,
+    },
+    /// Waive the fees of a refunded order
+    ///
+    /// Sent by the program owner to give the fees paid for an order back to the
+    /// payer when the order was refunded in full shortly after it was paid.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The program owner account
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The sponsor account - has to sign if it got a share of the fee
+    /// 4. `[writable]` The order payer account (where the fees will be sent)
+    /// 5. `[]` The program config account
+    /// 6. `[]` The System program
+    WaiveOrderFees,
+    /// Create a coupon
+    ///
+    /// Lets the merchant create a discount code that buyers can redeem in
+    /// ExpressCheckout and ChainCheckout until it expires or runs out of uses.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The coupon account.  Owned by this program and derived from the merchant account and the code
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    CreateCoupon {
+        /// the discount code given out to buyers
+        #[allow(dead_code)] // not dead code..
+        code: String,
+        /// one of state::DiscountType
+        #[allow(dead_code)] // not dead code..
+        discount_type: u8,
+        /// basis points for a percentage discount, or the amount for a fixed discount
+        #[allow(dead_code)] // not dead code..
+        discount: u64,
+        /// the mint (currency) that the coupon is for - required for a fixed discount
+        #[allow(dead_code)] // not dead code..
+        mint: Option<Pubkey>,
+        #[allow(dead_code)] // not dead code..
+        max_uses: u64,
+        #[allow(dead_code)] // not dead code..
+        expires_at: UnixTimestamp,
+    },
+    /// Create a payment link
+    ///
+    /// Lets the merchant create an account that counts the payments, the volume and
+    /// the unique payers of a payment link that is shared e.g. on social media.
+    /// Checkouts made through the link include the payment link account.  The link
+    /// can be paid with PayLink, for a fixed amount or for any amount.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The payment link account.  Owned by this program and derived from the merchant account and the link id
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[]` The token mint account - represents the 'currency' that the link is paid in
+    /// 4. `[]` The System program
+    /// 5. `[]` The rent sysvar
+    CreatePaymentLink {
+        /// the id of the link (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        link_id: String,
+        /// the amount to be paid through the link - 0 means that any amount can be paid
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Set the merchant tolerance
+    ///
+    /// Lets the merchant accept chain checkouts that underpay by up to `tolerance`
+    /// basis points of the price.  Overpayments by more than that are cut down to
+    /// the price so that the excess stays in the buyer token account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    SetMerchantTolerance {
+        /// the tolerance in basis points of the price - 0 turns the policy off
+        #[allow(dead_code)] // not dead code..
+        tolerance: u64,
+    },
+    /// Guarantee the price of a subscription package
+    ///
+    /// Lets the merchant commit that the price of a package will not increase
+    /// before `guaranteed_until` for the subscriptions that exist at the time.
+    /// RenewSubscription charges these subscriptions no more than the guaranteed
+    /// price when it includes the price guarantee account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The price guarantee account.  Owned by this program and derived from the merchant account and the package name
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    GuaranteePackagePrice {
+        /// the subscription package name
+        #[allow(dead_code)] // not dead code..
+        package: String,
+        #[allow(dead_code)] // not dead code..
+        guaranteed_until: UnixTimestamp,
+    },
+    /// Approve auto-debit
+    ///
+    /// Lets the merchant collect payments from the buyer token account without
+    /// the buyer signing each one.  The auto-debit account becomes the delegate
+    /// of the buyer token account for `allowance`.  Approving again replaces the
+    /// allowance, and revoking the delegate through the token program stops it.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The buyer, owner of the buyer token account
+    /// 1. `[writable]` The auto-debit account.  Owned by this program and derived from the merchant account and the buyer token account
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The buyer token account
+    /// 4. `[]` The token program
+    /// 5. `[]` The System program
+    /// 6. `[]` The rent sysvar
+    ApproveAutoDebit {
+        /// the total amount that the merchant can collect
+        #[allow(dead_code)] // not dead code..
+        allowance: u64,
+        /// the most that the merchant can collect at once
+        #[allow(dead_code)] // not dead code..
+        max_amount: u64,
+        /// the minimum number of seconds between two collections
+        #[allow(dead_code)] // not dead code..
+        interval: i64,
+    },
+    /// Collect a payment approved by the buyer
+    ///
+    /// Pulls `amount` from a buyer token account through its auto-debit account
+    /// and records it as a paid order of the merchant.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The buyer token account
+    /// 5. `[writable]` The auto-debit account.  Owned by this program
+    /// 6. `[writable]` The program owner account (where we will send program owner fee)
+    /// 7. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 8. `[]` The token mint account - represents the 'currency' being used
+    /// 9. `[]` This program's derived address
+    /// 10. `[]` The token program
+    /// 11. `[]` The System program
+    /// 12. `[]` The rent sysvar
+    /// 13. `[]` The program config account
+    /// 14. `[writable, optional]` The merchant stats account (that the order token accounts are counted towards)
+    /// 15. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    CollectPayment {
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// the external order id
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+        /// the pass phrase for the order
+        #[allow(dead_code)] // not dead code..
+        secret: String,
+        /// more optional data
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Renew a subscription that is due
+    ///
+    /// Anyone can send this once the period of a subscription has ended.  The
+    /// package price is pulled through the auto-debit account of the subscriber
+    /// into a new paid order and the subscription starts a new period.  The spare
+    /// lamports of the auto-debit account pay back the rent and fees of the order
+    /// to the signer, plus a small incentive.
+    ///
+    /// The order id of the new order is `<package name>-<number>`, where the number
+    /// is the one that the order gets in the order sequence of the merchant (see
+    /// crate::engine::common::get_next_order_id).
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person initializing the transaction
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The subscriber token account
+    /// 5. `[writable]` The auto-debit account of the subscriber token account.  Owned by this program
+    /// 6. `[writable]` The program owner account (where we will send program owner fee)
+    /// 7. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 8. `[]` The token mint account - represents the 'currency' being used
+    /// 9. `[]` This program's derived address
+    /// 10. `[]` The token program
+    /// 11. `[]` The System program
+    /// 12. `[]` The rent sysvar
+    /// 13. `[]` The program config account
+    /// 14. `[writable]` The subscription account.  Owned by this program
+    /// 15. `[optional]` The price guarantee account of the subscription package
+    RenewDueSubscriptions,
+    /// Set the fee schedule
+    ///
+    /// Lets the program authority change the fees of the program config.  The fee
+    /// schedule being replaced is appended to the fee history, along with the slots
+    /// during which it applied.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The program authority
+    /// 1. `[writable]` The program config account
+    /// 2. `[writable]` The fee history account.  Owned by this program and derived from the program id
+    /// 3. `[writable]` The next fee schedule account.  Owned by this program and derived from its index in the fee history
+    /// 4. `[]` The System program
+    /// 5. `[]` The rent sysvar
+    SetFeeSchedule {
+        /// the fee (in SOL lamports) charged when a merchant does not set one
+        #[allow(dead_code)] // not dead code..
+        default_fee: u64,
+        /// the minimum fee (in SOL lamports) that a merchant can set
+        #[allow(dead_code)] // not dead code..
+        min_fee: u64,
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: u64,
+    },
+    /// Pay a payment link
+    ///
+    /// Lets a buyer pay a payment link without any other checkout parameters from
+    /// the merchant.  The order is an express checkout order whose order id is made
+    /// of the link id and the number of the payment, and whose data is the data of
+    /// the payment link.  The payment is counted towards the link.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The buyer token account
+    /// 5. `[writable]` The program owner account (where we will send program owner fee)
+    /// 6. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 7. `[]` The token mint account - represents the 'currency' being used
+    /// 8. `[]` This program's derived address
+    /// 9. `[]` The token program
+    /// 10. `[]` The System program
+    /// 11. `[]` The rent sysvar
+    /// 12. `[]` The program config account
+    /// 13. `[writable]` The payment link account.  Owned by this program
+    /// 14. `[writable]` The link payer account - derived from the payment link account and the signer
+    /// 15. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 16. `[writable, optional]` The coupon account (whose discount is taken off the amount)
+    /// 17. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    PayLink {
+        /// the amount to pay - must be the amount of the link unless it is for any amount
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        #[allow(dead_code)] // not dead code..
+        tip: u64,
+    },
+    /// Create a campaign
+    ///
+    /// Lets the merchant create an account that adds up the donations made to a
+    /// campaign e.g. a fundraiser, towards an optional goal.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The campaign account.  Owned by this program and derived from the merchant account and the campaign id
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[]` The token mint account - represents the 'currency' that donations are made in
+    /// 4. `[]` The System program
+    /// 5. `[]` The rent sysvar
+    CreateCampaign {
+        /// the id of the campaign (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        campaign_id: String,
+        /// the amount that the campaign aims to raise - 0 means that there is no goal
+        #[allow(dead_code)] // not dead code..
+        goal: u64,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Donate
+    ///
+    /// An express checkout for whatever amount the donor chooses.  The order has no
+    /// expected amount other than the amount donated and is marked as a donation in
+    /// its data.  The donation is added to the total raised by a campaign of the
+    /// merchant by including the campaign account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person initializing the transaction
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The buyer token account
+    /// 5. `[writable]` The program owner account (where we will send program owner fee)
+    /// 6. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 7. `[]` The token mint account - represents the 'currency' being used
+    /// 8. `[]` This program's derived address
+    /// 9. `[]` The token program
+    /// 10. `[]` The System program
+    /// 11. `[]` The rent sysvar
+    /// 12. `[]` The program config account
+    /// 13. `[writable, optional]` The integrator account (where we will send integrator fee)
+    /// 14. `[writable, optional]` The campaign account (that the donation is added to)
+    /// 15. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order is set aside in) - required for merchants that have their orders swept
+    Donate {
+        /// the amount donated
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// the order id (as in issued by the merchant, for the donation receipt)
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+        // An extra field that can store an encrypted (ot not encrypted) string
+        // that the merchant can use to assert if a transaction is authentic
+        #[allow(dead_code)] // not dead code..
+        secret: String,
+        /// arbitrary donor data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Issue a refund code
+    ///
+    /// Lets the merchant authorize the refund of an order that has not been
+    /// withdrawn without having to sign the refund itself.  Only the hash of the
+    /// single-use code is stored (see get_refund_code_hash); the code is handed to
+    /// the buyer e.g. by customer support, who redeems it with RedeemRefundCode.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The refund code account.  Owned by this program and derived from the order account
+    /// 2. `[]` The order account.  Owned by this program
+    /// 3. `[]` The merchant account.  Owned by this program
+    /// 4. `[]` The System program
+    /// 5. `[]` The rent sysvar
+    IssueRefundCode {
+        /// the hash of the refund code
+        #[allow(dead_code)] // not dead code..
+        code_hash: [u8; 32],
+    },
+    /// Redeem a refund code
+    ///
+    /// Lets the payer of an order get the amount paid (and the tip) back by
+    /// presenting the refund code issued by the merchant.  The code can only be
+    /// used once and the order is marked as refunded.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The order payer
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order token account (where the money was put during payment)
+    /// 4. `[writable]` The refund token account of the payer (where the money will be refunded to)
+    /// 5. `[writable]` The refund code account.  Owned by this program
+    /// 6. `[]` This program's derived address
+    /// 7. `[]` The token program
+    ///
+    /// For a chain checkout order paid in more than one mint, each of its other
+    /// order token accounts follows, along with the token account of the payer that
+    /// it is refunded to.  The merchant stats account (that the closed order token
+    /// accounts are counted towards) can come last, as can the sweep bounty account
+    /// of the merchant (that the sweep bounty of the order goes back to) - required
+    /// for orders that hold a sweep bounty.  The token mint accounts of the mints of
+    /// the order (see use_transfer_checked) are required for Token-2022 mints that
+    /// are only moved with TransferChecked, e.g. mints with a transfer fee.
+    RedeemRefundCode {
+        /// the refund code issued by the merchant
+        #[allow(dead_code)] // not dead code..
+        code: String,
+    },
+    /// Set the merchant recovery
+    ///
+    /// Lets the merchant owner set a recovery key that may take over the merchant
+    /// account if the owner is inactive for `timeout` seconds, e.g. because the
+    /// owner key was lost.  Sending it again starts the timeout over and cancels a
+    /// recovery that was started, as does any other instruction signed by the
+    /// owner that is followed by the writable merchant recovery account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant recovery account.  Owned by this program and derived from the merchant account
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    SetMerchantRecovery {
+        /// the key that may take over the merchant account
+        #[allow(dead_code)] // not dead code..
+        recovery_key: Pubkey,
+        /// how long (in seconds) the owner has to be inactive before recovery can start
+        #[allow(dead_code)] // not dead code..
+        timeout: i64,
+        /// how long (in seconds) the owner has to cancel a recovery once started
+        #[allow(dead_code)] // not dead code..
+        challenge_window: i64,
+    },
+    /// Start the merchant recovery
+    ///
+    /// Lets the recovery key start taking over the merchant account once the owner
+    /// has been inactive for the timeout.  This starts the challenge window.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The recovery key
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant recovery account.  Owned by this program
+    StartMerchantRecovery,
+    /// Complete the merchant recovery
+    ///
+    /// Makes the recovery key the owner of the merchant account once the challenge
+    /// window has passed without the owner cancelling the recovery.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The recovery key
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant recovery account.  Owned by this program
+    CompleteMerchantRecovery,
+    /// Restock an item
+    ///
+    /// Adds to the stock of an item in the item catalog of the merchant.  The item
+    /// stock account is created the first time an item is restocked.  Items marked
+    /// with track_stock in the item catalog can only be sold while in stock.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The item stock account.  Owned by this program
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    RestockItem {
+        /// the item id (as in the item catalog of the merchant)
+        #[allow(dead_code)] // not dead code..
+        item: String,
+        /// how many of the item to add to the stock
+        #[allow(dead_code)] // not dead code..
+        quantity: u64,
+    },
+    /// Set the price oracle of a mint
+    ///
+    /// Lets the merchant owner price express checkouts paid in the mint in fiat.  The
+    /// Pyth price feed account that prices the mint is pinned in a price oracle
+    /// account, along with how stale and how uncertain its price may be.  Sending this
+    /// again updates the price oracle account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The price oracle account.  Owned by this program
+    /// 3. `[]` The token mint account
+    /// 4. `[]` The Pyth price feed account of the mint
+    /// 5. `[]` The System program
+    /// 6. `[]` The rent sysvar
+    SetPriceOracle {
+        /// how old (in seconds) the oracle price may be
+        #[allow(dead_code)] // not dead code..
+        max_staleness: i64,
+        /// how wide (in basis points of the price) the confidence interval of the
+        /// oracle price may be
+        #[allow(dead_code)] // not dead code..
+        max_slippage: u64,
+    },
+    /// Create a bundle
+    ///
+    /// Creates a bundle account that merchants can join with one of their subscription
+    /// packages.  A subscriber of a package in the bundle gets the bundle discount off
+    /// the packages of the other merchants in the bundle, see ExpressCheckout.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The bundle authority - decides which merchants take part in the bundle
+    /// 1. `[writable]` The bundle account.  Owned by this program
+    /// 2. `[]` The System program
+    /// 3. `[]` The rent sysvar
+    CreateBundle {
+        /// the bundle id (unique per bundle authority)
+        #[allow(dead_code)] // not dead code..
+        bundle_id: String,
+        /// the discount (in basis points) taken off each package of the bundle
+        #[allow(dead_code)] // not dead code..
+        discount: u64,
+    },
+    /// Join a bundle
+    ///
+    /// Adds a subscription package of the merchant to the bundle.  Both the merchant
+    /// owner and the bundle authority have to sign.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[signer]` The bundle authority
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The bundle account.  Owned by this program
+    /// 4. `[writable]` The bundle member account.  Owned by this program
+    /// 5. `[]` The System program
+    /// 6. `[]` The rent sysvar
+    JoinBundle {
+        /// the name of the subscription package that takes part in the bundle
+        #[allow(dead_code)] // not dead code..
+        package: String,
+    },
+    /// Set the settlement mint of a merchant
+    ///
+    /// Chooses the mint (currency) that the merchant prefers to be paid out in.  Payments
+    /// made in other mints can then be swapped into it on withdrawal, see WithdrawSettled.
+    /// Sending this again updates the settlement account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The settlement account.  Owned by this program
+    /// 3. `[]` The token mint account of the settlement mint
+    /// 4. `[]` The System program
+    /// 5. `[]` The rent sysvar
+    SetSettlementMint,
+    /// Withdraw funds for a particular order in the settlement mint
+    ///
+    /// Like Withdraw, except that the payment (and tip) is swapped into the settlement
+    /// mint of the merchant through the swap program set in the program config data
+    /// e.g. {"swap_program": "<program id>"}.  The swap program must have the SPL Token
+    /// Swap interface.  Only the merchant owner can withdraw settled payments, and only
+    /// for orders paid in a single mint.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order token account (where the money was put during payment)
+    /// 4. `[writable]` The merchant token account of the settlement mint (where we will withdraw to)
+    /// 5. `[writable]` This account receives the refunded SOL after closing order token account
+    /// 6. `[]` This program's derived address
+    /// 7. `[]` The token program
+    /// 8. `[]` The program config account
+    /// 9. `[]` The settlement account.  Owned by this program
+    /// 10. `[]` The swap program
+    /// 11. `[]` The swap (pool state) account
+    /// 12. `[]` The swap authority
+    /// 13. `[writable]` The pool token account of the order mint
+    /// 14. `[writable]` The pool token account of the settlement mint
+    /// 15. `[writable]` The pool mint
+    /// 16. `[writable]` The pool fee account
+    /// 17. `[]` (optional) The subscription account, if the merchant offers a trial period
+    /// 18. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    /// 19. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order goes back to) - required for orders that hold a sweep bounty
+    WithdrawSettled {
+        /// the minimum amount of the settlement mint to receive
+        #[allow(dead_code)] // not dead code..
+        minimum_amount_out: u64,
+    },
+    /// Post a note on an order
+    ///
+    /// Lets the buyer or the merchant owner add a note (hashed or encrypted beforehand)
+    /// to the notes of an order e.g. delivery instructions or the context of a dispute.
+    /// Each note is kept in its own account along with its author and the time it was
+    /// posted.  Notes cannot be changed or removed, and an order takes no more than
+    /// MAX_ORDER_NOTES notes.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The buyer or the merchant owner
+    /// 1. `[]` The order account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order notes account.  Owned by this program
+    /// 4. `[writable]` The order note account of the next note.  Owned by this program
+    /// 5. `[]` The System program
+    /// 6. `[]` The rent sysvar
+    PostOrderNote {
+        /// the note, encoded as ASCII e.g. base64
+        #[allow(dead_code)] // not dead code..
+        note: String,
+    },
+    /// Update a merchant
+    ///
+    /// Lets the merchant owner change the fee, the sponsor and the data of the merchant
+    /// account.  Only the fields that are set are changed.  A fee below the minimum fee
+    /// of the program config is raised to it, as in RegisterMerchant.
+    ///
+    /// The merchant account keeps its size: new data must be ASCII and fit in the space
+    /// taken by the current data, and is padded with whitespace when it is shorter.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[]` The program config account
+    UpdateMerchant {
+        /// the new fee (in SOL lamports) charged for transactions
+        #[allow(dead_code)] // not dead code..
+        fee: Option<u64>,
+        /// the new sponsor of the merchant
+        #[allow(dead_code)] // not dead code..
+        sponsor: Option<Pubkey>,
+        /// the new merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Split part of a paid order off into a sub-order
+    ///
+    /// Lets the merchant owner split an order that is shipped in parts.  The sub-order
+    /// gets `amount` of the paid amount along with a proportional share of the tip and
+    /// of the expected amount, and the tokens that go with them are moved to its own
+    /// order token account.  The parent order keeps the rest.  Each order is then
+    /// confirmed, refunded and withdrawn on its own.  The sub-order keeps the order data
+    /// of the parent order, minus its fees, and records the parent order under `_parent`.
+    ///
+    /// Only paid orders paid in a single mint without a transfer fee can be split.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner - pays for the sub-order accounts
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The sub-order account (to be created) - derived from the merchant account and the order id of the sub-order
+    /// 3. `[writable]` The merchant account.  Owned by this program
+    /// 4. `[writable]` The order token account
+    /// 5. `[writable]` The sub-order token account (to be created)
+    /// 6. `[]` The token mint account of the order
+    /// 7. `[]` This program's derived address
+    /// 8. `[]` The token program
+    /// 9. `[]` The System program
+    /// 10. `[]` The rent sysvar
+    /// 11. `[writable, optional]` The merchant stats account (that the order token accounts are counted towards)
+    SplitOrder {
+        /// the part of the paid amount that goes to the sub-order
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+        /// the order id of the sub-order
+        #[allow(dead_code)] // not dead code..
+        order_id: String,
+    },
+    /// Propose to transfer a merchant account to a new owner
+    ///
+    /// Nothing changes until the new owner sends an `AcceptMerchantTransfer`
+    /// instruction.  Sending this again replaces the proposal.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner - pays for the merchant transfer account
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant transfer account (created if needed)
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    ProposeMerchantTransfer {
+        /// the proposed new owner of the merchant account
+        #[allow(dead_code)] // not dead code..
+        new_owner: Pubkey,
+    },
+    /// Accept the transfer of a merchant account proposed by its owner
+    ///
+    /// Makes the signer the owner of the merchant account.  The operators and the
+    /// recovery set up by the previous owner are removed, so the new owner should
+    /// send `SetOperators` and `SetMerchantRecovery` instructions of their own.
+    /// The rent of the merchant transfer and recovery accounts goes back to the
+    /// previous owner.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The proposed new owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant transfer account
+    /// 3. `[writable]` The previous owner of the merchant account
+    /// 4. `[writable]` The merchant recovery account, whether or not it was created.  Derived from the merchant account
+    AcceptMerchantTransfer,
+    /// Keep count of the program-owned token accounts of the orders of a merchant
+    ///
+    /// Creates (or updates) the stats account of the merchant, and sets the most
+    /// order token accounts that the merchant can keep open at once.  Checkouts
+    /// that include the stats account fail once the cap is reached, until stale
+    /// orders are withdrawn (or otherwise closed).  Only the instructions that
+    /// include the stats account are counted, so `open_token_accounts` lets the
+    /// merchant owner correct the count e.g. from a hygiene report.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner - pays for the merchant stats account
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The merchant stats account (created if needed)
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    SetMerchantStats {
+        /// the most order token accounts that can be open at once, up to MAX_OPEN_TOKEN_ACCOUNTS
+        #[allow(dead_code)] // not dead code..
+        max_open_token_accounts: u64,
+        /// the number of order token accounts that are open
+        #[allow(dead_code)] // not dead code..
+        open_token_accounts: Option<u64>,
+    },
+    /// Close a merchant account and get back its rent
+    ///
+    /// The merchant account keeps count of its open orders (i.e. neither withdrawn,
+    /// refunded, reclaimed nor cancelled) and active subscriptions, and can only be
+    /// closed once there are none.  Unpaid invoices count as open orders.
+    /// Subscriptions whose period ended without being renewed can be released by
+    /// including them.  The data of the merchant account is zeroed and its lamports
+    /// go to the merchant owner.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner - receives the rent of the merchant account
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable, optional]` Any number of lapsed subscription accounts of the merchant
+    CloseMerchant,
+    /// Close the account of a settled order and get back its rent
+    ///
+    /// Only orders that are withdrawn, cancelled, reclaimed, refunded or had their
+    /// fees waived can be closed, and only by the merchant owner.  Order token
+    /// accounts that were left open when the order was settled (e.g. because they
+    /// held withheld transfer fees, which must be harvested first) can be closed
+    /// along the way by including them, as long as they are empty.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The account that receives the rent of the closed accounts
+    /// 4. `[]` This program's derived address
+    /// 5. `[]` The token program
+    /// 6. `[writable, optional]` Any number of order token accounts of the order that are still open
+    /// 7. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    /// 8. `[writable, optional]` The sweep bounty account of the merchant (that the sweep bounty of the order goes back to) - required for orders that hold a sweep bounty
+    CloseOrder,
+    /// Create a store
+    ///
+    /// Lets the merchant owner operate several stores under the one merchant
+    /// account.  Each store has a catalog of its own (used by chain checkouts made
+    /// at the store instead of the items of the merchant), its own data and a fee
+    /// destination i.e. the wallet that the payments made at the store can be
+    /// withdrawn to.  Checkouts made at a store include the store account, which is
+    /// recorded in the order data under `_store` for per-store reporting.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The store account.  Owned by this program and derived from the merchant account and the store id
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    CreateStore {
+        /// the id of the store (as in issued by the merchant)
+        #[allow(dead_code)] // not dead code..
+        store_id: String,
+        /// the wallet that the payments made at the store can be withdrawn to -
+        /// defaults to the merchant owner
+        #[allow(dead_code)] // not dead code..
+        fee_destination: Option<Pubkey>,
+        /// the items sold at the store, as the data of a chain checkout merchant
+        #[allow(dead_code)] // not dead code..
+        catalog: Option<String>,
+        /// arbitrary merchant data (maybe as a JSON string)
+        #[allow(dead_code)] // not dead code..
+        data: Option<String>,
+    },
+    /// Close a store and get back its rent
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner - receives the rent of the store account
+    /// 1. `[writable]` The store account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    CloseStore,
+    /// Pause a merchant
+    ///
+    /// While the merchant is paused, its checkouts (including invoice payments,
+    /// auto-debit collections and renewals) and subscriptions fail with
+    /// MerchantPaused.  Orders already paid can still be withdrawn and refunded.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    PauseMerchant,
+    /// Resume a paused merchant
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    ResumeMerchant,
+    /// Set the mints that a merchant accepts payments in
+    ///
+    /// Checkouts of the merchant in any other mint then fail, and have to include
+    /// the accepted mints account of the merchant.  An empty list lets the
+    /// merchant take payments in any mint again.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The accepted mints account (created if needed)
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    SetAcceptedMints {
+        /// the mints accepted, up to MAX_ACCEPTED_MINTS
+        #[allow(dead_code)] // not dead code..
+        mints: Vec<Pubkey>,
+    },
+    /// Block a buyer from checking out with a merchant
+    ///
+    /// While the merchant has blocked buyers, its checkouts (including split
+    /// checkouts, invoice payments and installments) have to include the blocked buyer address
+    /// of the buyer, and fail with BuyerBlocked if the buyer is blocked.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The blocked buyer account (to be created)
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    BlockBuyer {
+        /// the wallet to block
+        #[allow(dead_code)] // not dead code..
+        buyer: Pubkey,
+    },
+    /// Unblock a buyer and get back the rent of its blocked buyer account
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner - receives the rent of the blocked buyer account
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The blocked buyer account.  Owned by this program
+    UnblockBuyer,
+    /// Update the program config
+    ///
+    /// Sent by the program authority to change the program owner or the fee
+    /// schedule.  Fees that are not given are left as they are.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The program authority (the account that initialized the program)
+    /// 1. `[writable]` The program config account
+    /// 2. `[optional]` The new program owner account (where program owner fees will be sent)
+    UpdateConfig {
+        /// the fee (in SOL lamports) charged when a merchant does not set one
+        #[allow(dead_code)] // not dead code..
+        default_fee: Option<u64>,
+        /// the minimum fee (in SOL lamports) that a merchant can set
+        #[allow(dead_code)] // not dead code..
+        min_fee: Option<u64>,
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: Option<u64>,
+    },
+    /// Set the share of the transaction fee that the sponsor of a merchant gets
+    ///
+    /// Sent by the program authority.  Merchants get the sponsor fee of the program
+    /// config when they register unless they ask for another one.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The program authority (the account that initialized the program)
+    /// 1. `[]` The program config account
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    SetMerchantSponsorFee {
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: u64,
+    },
+    /// Take on a wallet as a referrer (affiliate) of a merchant
+    ///
+    /// Also used to change the referral share of a referrer.  Checkouts that
+    /// include the referrer account record the referrer and its share on the order
+    /// under `_referral`, and withdrawing the order pays that share of the amount
+    /// paid to the associated token account of the referrer.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The referrer account.  Owned by this program and derived from the merchant account and the referrer wallet
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    SetReferrer {
+        /// the wallet of the referrer
+        #[allow(dead_code)] // not dead code..
+        wallet: Pubkey,
+        /// the share (in basis points) of the amount paid that the referrer gets
+        #[allow(dead_code)] // not dead code..
+        referral_fee: u64,
+    },
+    /// Pause an active subscription
+    ///
+    /// The seconds left in the period are kept as `remaining_seconds` and the
+    /// subscription is `Paused` until it is resumed.  Paused subscriptions cannot
+    /// be renewed or expire, and the orders paid for them cannot be withdrawn by
+    /// merchants whose packages have trial periods.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The owner of the subscription
+    /// 1. `[writable]` The subscription account.  Owned by this program
+    PauseSubscription,
+    /// Resume a paused subscription
+    ///
+    /// The period of the subscription ends `remaining_seconds` after the time it is
+    /// resumed at.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The owner of the subscription
+    /// 1. `[writable]` The subscription account.  Owned by this program
+    ResumeSubscription,
+    /// Move a subscription that has not been renewed to past due or expired
+    ///
+    /// Can be sent by anyone.  Within the `grace_seconds` of its package after the
+    /// end of its period the subscription becomes `PastDue`, which services can
+    /// still treat as active while a renewal is retried.  After that it becomes
+    /// `Expired` and no longer counts towards the references of the merchant.
+    /// Renewing a past due subscription carries on from the end of the unpaid period.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The subscription account.  Owned by this program
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    ExpireSubscription,
+    /// Hand a subscription over to another wallet
+    ///
+    /// e.g. when the subscriber rotates wallets or gifts the subscription.  The time
+    /// that has been paid for is kept and the subscription account keeps its address.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The current owner of the subscription
+    /// 1. `[signer]` The new owner of the subscription
+    /// 2. `[writable]` The subscription account.  Owned by this program
+    TransferSubscription,
+    /// Record usage on a subscription to a metered package
+    ///
+    /// Sent by the merchant owner.  The usage units accrue on the subscription
+    /// account until they are charged with SettleUsage.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The subscription account.  Owned by this program
+    RecordUsage {
+        /// the usage units to add
+        #[allow(dead_code)] // not dead code..
+        units: u64,
+    },
+    /// Charge the usage recorded on a subscription once its period has ended
+    ///
+    /// Sent by the merchant owner.  The usage units times the `unit_price` of the
+    /// package are pulled through the auto-debit account of the subscriber into a
+    /// new paid order, like CollectPayment, and the usage starts over.  The order id
+    /// of the order is `<package name>-usage-<number>` (see RenewDueSubscriptions).
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 4. `[writable]` The subscriber token account
+    /// 5. `[writable]` The auto-debit account of the subscriber token account.  Owned by this program
+    /// 6. `[writable]` The program owner account (where we will send program owner fee)
+    /// 7. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 8. `[]` The token mint account - represents the 'currency' being used
+    /// 9. `[]` This program's derived address
+    /// 10. `[]` The token program
+    /// 11. `[]` The System program
+    /// 12. `[]` The rent sysvar
+    /// 13. `[]` The program config account
+    /// 14. `[writable]` The subscription account.  Owned by this program
+    SettleUsage,
+    /// Change the number of seats of a subscription
+    ///
+    /// Sent by the owner of the subscription.  Adding seats charges the package
+    /// price of the new seats for the rest of the period, pulled through the
+    /// auto-debit account of the subscriber into a new paid order.  Removing seats
+    /// extends the period by the unused part of the period of the removed seats,
+    /// spread over the remaining seats.  Changing the seats of a free trial is free.
+    /// The order id of the order of added seats is `<package name>-seats-<number>`
+    /// (see RenewDueSubscriptions).
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The owner of the subscription
+    /// 1. `[writable]` The subscription account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    ///
+    /// When seats are added, followed by:
+    ///
+    /// 3. `[writable]` The order account.  Owned by this program
+    /// 4. `[writable]` The seller token account - this is where the amount paid will go. Owned by this program
+    /// 5. `[writable]` The subscriber token account
+    /// 6. `[writable]` The auto-debit account of the subscriber token account.  Owned by this program
+    /// 7. `[writable]` The program owner account (where we will send program owner fee)
+    /// 8. `[writable]` The sponsor account (where we will send sponsor fee)
+    /// 9. `[]` The token mint account - represents the 'currency' being used
+    /// 10. `[]` This program's derived address
+    /// 11. `[]` The token program
+    /// 12. `[]` The System program
+    /// 13. `[]` The rent sysvar
+    /// 14. `[]` The program config account
+    UpdateSeats {
+        /// the new number of seats
+        #[allow(dead_code)] // not dead code..
+        seats: u64,
+    },
+    /// Add a subscription package to the catalog of a merchant
+    ///
+    /// The package gets an account of its own instead of an entry in the merchant
+    /// data, which still has to hold a list of packages (that may be empty).
+    /// Subscriptions to the package keep the price they subscribed at when the
+    /// package is updated.  The instructions about these subscriptions need the
+    /// package account as an extra account (see use_catalog_package).
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The package account.  Owned by this program and derived from the merchant account and the package name
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    /// 3. `[]` The System program
+    /// 4. `[]` The rent sysvar
+    CreatePackage {
+        /// the package as a JSON string (see engine::json::Package)
+        #[allow(dead_code)] // not dead code..
+        data: String,
+    },
+    /// Update a subscription package of the catalog of a merchant
+    ///
+    /// New subscribers get the updated package while existing subscribers keep
+    /// the price they subscribed at.  The package name cannot change, and the
+    /// package has to fit in the package account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The package account.  Owned by this program
+    /// 2. `[writable]` The merchant account.  Owned by this program
+    UpdatePackage {
+        /// the package as a JSON string (see engine::json::Package)
+        #[allow(dead_code)] // not dead code..
+        data: String,
+    },
+    /// Retire a subscription package of the catalog of a merchant
+    ///
+    /// Retired packages take no new subscriptions, while existing subscribers keep renewing.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The package account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    RetirePackage,
+    /// Close the account of a subscription that has ended
+    ///
+    /// Can be sent by anyone once the subscription is `Cancelled` or `Expired` and
+    /// `SUBSCRIPTION_RETENTION_PERIOD` has passed since the end of its period.  The
+    /// rent of the subscription account goes back to the subscription owner.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The subscription account.  Owned by this program
+    /// 1. `[writable]` The subscription owner (where the rent goes)
+    CloseSubscription,
+    /// Set the merchant instant settlement
+    ///
+    /// Lets the merchant have checkouts pay the merchant token account (account 3
+    /// of the checkout) directly instead of an order token account, in which case
+    /// the order is created already `Withdrawn`.  Orders paid in installments,
+    /// held in escrow, that expire, that were referred or that are made for a
+    /// subscription are still paid into an order token account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    SetInstantSettlement {
+        /// whether checkouts of the merchant are settled instantly
+        #[allow(dead_code)] // not dead code..
+        instant_settlement: bool,
+    },
+    /// Set the merchant payout schedule
+    ///
+    /// Lets the merchant split its withdrawals among up to MAX_PAYOUT_DESTINATIONS
+    /// wallets e.g. 80% to an operating wallet and 20% to a savings wallet.  The
+    /// shares are in basis points and have to add up to the whole amount.
+    /// Withdrawals then pay the associated token accounts of the wallets (see
+    /// use_payout_schedule) instead of the merchant token account.  An empty
+    /// schedule turns the split off.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    SetPayoutSchedule {
+        /// the wallets and their shares in basis points
+        #[allow(dead_code)] // not dead code..
+        payouts: Vec<(Pubkey, u64)>,
+    },
+    /// Set the merchant operators
+    ///
+    /// Lets the merchant owner designate up to MAX_OPERATORS wallets that can
+    /// withdraw payments on its behalf wherever a withdrawal needs the merchant
+    /// owner to sign i.e. to close the order account when withdrawing, to
+    /// withdraw settled payments and to withdraw part of an order.  Operators
+    /// cannot update the merchant or refund orders.  An empty list removes all the
+    /// operators.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    SetOperators {
+        /// the operator wallets, up to MAX_OPERATORS
+        #[allow(dead_code)] // not dead code..
+        operators: Vec<Pubkey>,
+    },
+    /// Withdraw part of the funds of an order
+    ///
+    /// Lets the merchant owner (or an operator) withdraw only part of the payment
+    /// of an order, e.g. the earned portion of a milestone or escrow order once it
+    /// is released, or the installments paid so far.  The rest stays in the order
+    /// token account and can be withdrawn or refunded later: Withdraw withdraws
+    /// whatever is left.  The amounts withdrawn are counted in the withdrawn
+    /// amount of the order.  Orders with a referrer or made for a subscription are
+    /// only withdrawn in full.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner or one of its operators
+    /// 1. `[writable]` The order account.  Owned by this program
+    /// 2. `[]` The merchant account.  Owned by this program
+    /// 3. `[writable]` The order token account (where the money was put during payment)
+    /// 4. `[writable]` The merchant token account (where we will withdraw to)
+    /// 5. `[]` This program's derived address
+    /// 6. `[]` The token program
+    /// 7. `[writable, optional]` The payout token accounts (the associated token account of each wallet of the payout schedule) - required for merchants with a payout schedule, in which case the amount goes to them instead of the merchant token account
+    /// 8. `[optional]` The token mint account of the order (see use_transfer_checked) - required for Token-2022 mints that are only moved with TransferChecked, e.g. mints with a transfer fee
+    WithdrawAmount {
+        /// the amount withdrawn, at most what the order token account holds for the order
+        #[allow(dead_code)] // not dead code..
+        amount: u64,
+    },
+    /// Set the merchant sweep policy
+    ///
+    /// Lets the merchant owner have its paid orders withdrawn by the Sweep crank
+    /// once the orders old enough hold at least the threshold (in total).  A
+    /// threshold of 0 turns sweeping off.  The sweep bounty account of the
+    /// merchant is created the first time: checkouts of a merchant that has its
+    /// orders swept include it (see use_sweep_bounty), as do the instructions
+    /// that settle their orders otherwise.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The sweep bounty account.  Derived from the merchant account
+    /// 3. `[]` The System program
+    SetSweepPolicy {
+        /// the least amount (in total) that the orders swept at once must hold
+        #[allow(dead_code)] // not dead code..
+        threshold: u64,
+        /// the slots that an order waits after it was created before it is swept
+        #[allow(dead_code)] // not dead code..
+        delay: u64,
+    },
+    /// Sweep the paid orders of a merchant
+    ///
+    /// A permissionless crank that withdraws paid orders of a merchant in one
+    /// mint to the associated token account of the merchant owner.  The orders
+    /// must be older than the sweep delay of the merchant and hold at least its
+    /// sweep threshold (in total).  Orders with a referrer or paid in several
+    /// mints, and the orders of merchants with a trial period or a payout
+    /// schedule, are left to Withdraw.  The signer gets the rent of the order
+    /// token accounts that are closed, plus the bounty (at most
+    /// SWEEP_BOUNTY_IN_LAMPORTS) that each order set aside out of the program
+    /// owner fee paid for it, which the sweep bounty account pays out.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person running the crank
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[writable]` The associated token account of the merchant owner for the mint
+    /// 3. `[]` The token mint account of the orders
+    /// 4. `[]` This program's derived address
+    /// 5. `[]` The token program
+    /// 6. `[]` The program config account
+    /// 7. `[writable]` The sweep bounty account of the merchant
+    /// 8. For each order swept, two accounts:
+    ///    `[writable]` the order account,
+    ///    `[writable]` the order token account
+    /// 9. `[writable, optional]` The merchant stats account (that the closed order token accounts are counted towards)
+    Sweep,
+    /// Register for a merchant account whose catalog or subscription packages are typed
+    ///
+    /// Unlike RegisterMerchant, where they are held as JSON in the merchant data,
+    /// the catalog or packages are read by checkouts and subscriptions without
+    /// parsing them.  The metadata is free-form and not read by the program.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person initializing the merchant account
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    /// 2. `[]` System program
+    /// 3. `[]` The rent sysvar
+    /// 4. `[]` The program config account
+    /// 5. `[optional]` The sponsor account
+    RegisterMerchantWithTerms {
+        /// the seed used when creating the account
+        #[allow(dead_code)] // not dead code..
+        seed: Option<String>,
+        /// the amount (in SOL lamports) that will be charged as a fee
+        #[allow(dead_code)] // not dead code..
+        fee: Option<u64>,
+        /// what the merchant sells
+        #[allow(dead_code)] // not dead code..
+        terms: MerchantTerms,
+        /// at most MAX_METADATA_LEN bytes
+        #[allow(dead_code)] // not dead code..
+        metadata: Vec<u8>,
+        /// the sponsor share (in tenths of a percent) of the transaction fee
+        #[allow(dead_code)] // not dead code..
+        sponsor_fee: Option<u64>,
+    },
+    /// Migrate the merchant data
+    ///
+    /// Moves the catalog or subscription packages that the merchant data holds as
+    /// JSON into the typed catalog or packages of the merchant, or the data of other
+    /// merchants into the merchant metadata.  The merchant account keeps its size.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    MigrateMerchantData,
+    /// Migrate an account to the latest layout
+    ///
+    /// Rewrites an account created before accounts had a version in the latest
+    /// layout, making room for the fields added after the legacy layout, and for
+    /// the rest of its discriminator (see get_account_discriminator), from the
+    /// padding of its data.  Merchant, order, subscription and package accounts are
+    /// migrated when their data is padded enough, the others keep working in the
+    /// legacy layout, where the added fields cannot be set.
+    ///
+    /// Merchant and subscription accounts that are not padded enough are moved to
+    /// a bigger account at the same address by their owner, with this instruction
+    /// sent twice, in separate transactions: the first one copies the account to
+    /// the migration account and empties it (so that it is removed), the second
+    /// one creates it again in its new size and copies it back.  The account does
+    /// not exist in between.
+    ///
+    /// Merchant accounts are migrated by their owner, who includes the open
+    /// orders (i.e. neither withdrawn, refunded, reclaimed nor cancelled) and
+    /// active subscriptions of the merchant, which the merchant account in the
+    /// legacy layout did not count (see CloseMerchant).  Merchant accounts in the
+    /// legacy layout take no new orders or subscriptions until they are migrated.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the signer - the merchant owner for merchant accounts, or the subscriber for subscription accounts that are moved, who pays for the migration account and the new account, and gets back the lamports of the old one
+    /// 1. `[writable]` The account to migrate.  Owned by this program (until the second transaction of a move)
+    /// 2. `[writable, optional]` The migration account - derived from the account to migrate, and required for accounts that are moved or followed by the accounts below
+    /// 3. `[optional]` The System program - required for accounts that are moved or followed by the accounts below
+    /// 4. `[optional]` Any number of open order and active subscription accounts of a merchant account being migrated, each of them once
+    MigrateAccount {
+        /// the seed that a merchant account that is moved was registered with
+        /// (MERCHANT when there is none)
+        #[allow(dead_code)] // not dead code..
+        seed: Option<String>,
+    },
+    /// Set the merchant freeze policy
+    ///
+    /// Lets the merchant reject checkouts in mints with a freeze authority, which
+    /// could freeze the order token accounts before they are withdrawn.  The mints
+    /// of the accepted mints account of the merchant (if any) are still accepted.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The merchant owner
+    /// 1. `[writable]` The merchant account.  Owned by this program
+    SetFreezePolicy {
+        #[allow(dead_code)] // not dead code..
+        rejects_freezable_mints: bool,
+    },
+    /// Claim the sweep bounties returned to the program owner
+    ///
+    /// The orders of a merchant that has them swept set aside a sweep bounty out
+    /// of the program owner fee.  The ones settled without being swept (i.e.
+    /// withdrawn, refunded, reclaimed, cancelled or closed) return it to the
+    /// program owner, who claims it from the sweep bounty account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The program owner
+    /// 1. `[writable]` The sweep bounty account of the merchant.  Owned by this program
+    /// 2. `[]` The program config account
+    ClaimSweepBounties,
+}
+
+/// The arguments of register_merchant
+pub struct RegisterMerchantArgs<'a> {
+    pub signer: Pubkey,
+    pub merchant: Pubkey,
+    pub seed: Option<String>,
+    pub fee: Option<u64>,
+    pub data: Option<String>,
+    pub sponsor: Option<&'a Pubkey>,
+    pub sponsor_fee: Option<u64>,
+}
+
+/// Creates an 'RegisterMerchant' instruction.
+pub fn register_merchant(program_id: Pubkey, args: RegisterMerchantArgs<'_>) -> Instruction {
+    let RegisterMerchantArgs {
+        signer,
+        merchant,
+        seed,
+        fee,
+        data,
+        sponsor,
+        sponsor_fee,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    if let Some(sponsor) = sponsor {
+        account_metas.push(AccountMeta::new_readonly(*sponsor, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::RegisterMerchant {
+            seed,
+            fee,
+            data,
+            sponsor_fee,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of express_checkout
+pub struct ExpressCheckoutArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    pub link: Option<Pubkey>,
+    pub amount: u64,
+    pub tip: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'ExpressCheckout' instruction.
+pub fn express_checkout(program_id: Pubkey, args: ExpressCheckoutArgs) -> Instruction {
+    let ExpressCheckoutArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        pda,
+        integrator,
+        coupon,
+        link,
+        amount,
+        tip,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(seller_token, false),
+        AccountMeta::new(buyer_token, false),
+        AccountMeta::new(program_owner, false),
+        AccountMeta::new(sponsor, false),
+        AccountMeta::new_readonly(mint, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    if let Some(integrator) = integrator {
+        account_metas.push(AccountMeta::new(integrator, false));
+    }
+
+    if let Some(coupon) = coupon {
+        account_metas.push(AccountMeta::new(coupon, false));
+    }
+
+    if let Some(link) = link {
+        let (link_payer, _bump_seed) = get_link_payer_address(&program_id, &link, &signer);
+        account_metas.push(AccountMeta::new(link, false));
+        account_metas.push(AccountMeta::new(link_payer, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::ExpressCheckout {
+            amount,
+            tip,
+            order_id,
+            secret,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of express_checkout_from_wallet
+pub struct ExpressCheckoutFromWalletArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    pub link: Option<Pubkey>,
+    pub amount: u64,
+    pub tip: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'ExpressCheckout' instruction paid from the associated token
+/// account of the signer.
+///
+/// The order token account and this program's derived address are derived as well.
+pub fn express_checkout_from_wallet(
+    program_id: Pubkey,
+    args: ExpressCheckoutFromWalletArgs,
+) -> Instruction {
+    let ExpressCheckoutFromWalletArgs {
+        signer,
+        order,
+        merchant,
+        mint,
+        program_owner,
+        sponsor,
+        integrator,
+        coupon,
+        link,
+        amount,
+        tip,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    express_checkout(
+        program_id,
+        ExpressCheckoutArgs {
+            signer,
+            order,
+            merchant,
+            seller_token: get_order_token_address(&program_id, &order, &mint),
+            buyer_token: get_associated_token_address(&signer, &spl_token::id(), &mint),
+            mint,
+            program_owner,
+            sponsor,
+            pda,
+            integrator,
+            coupon,
+            link,
+            amount,
+            tip,
+            order_id,
+            secret,
+            data,
+        },
+    )
+}
+
+/// The arguments of chain_checkout
+pub struct ChainCheckoutArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    pub link: Option<Pubkey>,
+    pub amount: u64,
+    pub order_items: OrderItems,
+    pub data: Option<String>,
+    pub other_mints: Vec<(Pubkey, Pubkey)>,
+}
+
+/// Creates an 'ChainCheckout' instruction.
+pub fn chain_checkout(program_id: Pubkey, args: ChainCheckoutArgs) -> Instruction {
+    let ChainCheckoutArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        pda,
+        integrator,
+        coupon,
+        link,
+        amount,
+        order_items,
+        data,
+        other_mints,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(seller_token, false),
+        AccountMeta::new(buyer_token, false),
+        AccountMeta::new(program_owner, false),
+        AccountMeta::new(sponsor, false),
+        AccountMeta::new_readonly(mint, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    // other_mints holds the mint and buyer token account of each other mint
+    for (other_mint, other_buyer_token) in other_mints {
+        let (other_seller_token, _bump_seed) = Pubkey::find_program_address(
+            &[
+                &order.to_bytes(),
+                &spl_token::id().to_bytes(),
+                &other_mint.to_bytes(),
+            ],
+            &program_id,
+        );
+        account_metas.push(AccountMeta::new_readonly(other_mint, false));
+        account_metas.push(AccountMeta::new(other_seller_token, false));
+        account_metas.push(AccountMeta::new(other_buyer_token, false));
+    }
+
+    if let Some(integrator) = integrator {
+        account_metas.push(AccountMeta::new(integrator, false));
+    }
+
+    if let Some(coupon) = coupon {
+        account_metas.push(AccountMeta::new(coupon, false));
+    }
+
+    if let Some(link) = link {
+        let (link_payer, _bump_seed) = get_link_payer_address(&program_id, &link, &signer);
+        account_metas.push(AccountMeta::new(link, false));
+        account_metas.push(AccountMeta::new(link_payer, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::ChainCheckout {
+            amount,
+            order_items: order_items.into_iter().collect(),
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of withdraw
+pub struct WithdrawArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub order_payment_token: Pubkey,
+    pub merchant_token: Pubkey,
+    pub account_to_receive_sol_refund: Pubkey,
+    pub pda: Pubkey,
+    pub subscription: Option<Pubkey>,
+    pub close_order_account: bool,
+    pub other_tokens: Vec<(Pubkey, Pubkey)>,
+}
+
+/// Creates an 'Withdraw' instruction.
+pub fn withdraw(program_id: Pubkey, args: WithdrawArgs) -> Instruction {
+    let WithdrawArgs {
+        signer,
+        order,
+        merchant,
+        order_payment_token,
+        merchant_token,
+        account_to_receive_sol_refund,
+        pda,
+        subscription,
+        close_order_account,
+        other_tokens,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(order_payment_token, false),
+        AccountMeta::new(merchant_token, false),
+        AccountMeta::new(account_to_receive_sol_refund, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    if let Some(subscription) = subscription {
+        account_metas.push(AccountMeta::new_readonly(subscription, false));
+    }
+
+    // other_tokens holds the order token and merchant token accounts of each other mint
+    for (order_token, merchant_token) in other_tokens {
+        account_metas.push(AccountMeta::new(order_token, false));
+        account_metas.push(AccountMeta::new(merchant_token, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::Withdraw {
+            close_order_account,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of withdraw_to_wallet
+pub struct WithdrawToWalletArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub merchant_owner: Pubkey,
+    pub mint: Pubkey,
+    pub account_to_receive_sol_refund: Pubkey,
+    pub subscription: Option<Pubkey>,
+    pub close_order_account: bool,
+    pub create_merchant_token: bool,
+    pub other_tokens: Vec<(Pubkey, Pubkey)>,
+}
+
+/// Creates an 'Withdraw' instruction that pays out to the associated token
+/// account of the merchant owner.
+///
+/// Set create_merchant_token to have the associated token account created (and
+/// paid for by the signer) when it does not exist yet.
+pub fn withdraw_to_wallet(program_id: Pubkey, args: WithdrawToWalletArgs) -> Instruction {
+    let WithdrawToWalletArgs {
+        signer,
+        order,
+        merchant,
+        merchant_owner,
+        mint,
+        account_to_receive_sol_refund,
+        subscription,
+        close_order_account,
+        create_merchant_token,
+        other_tokens,
+    } = args;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    let mut instruction = withdraw(
+        program_id,
+        WithdrawArgs {
+            signer,
+            order,
+            merchant,
+            order_payment_token: get_order_token_address(&program_id, &order, &mint),
+            merchant_token: get_associated_token_address(&merchant_owner, &spl_token::id(), &mint),
+            account_to_receive_sol_refund,
+            pda,
+            subscription,
+            close_order_account,
+            other_tokens,
+        },
+    );
+
+    if create_merchant_token {
+        // the accounts needed to create the merchant token account follow the token program
+        instruction.accounts.splice(
+            8..8,
+            vec![
+                AccountMeta::new_readonly(merchant_owner, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(solana_program::system_program::id(), false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(get_associated_token_program_id(), false),
+            ],
+        );
+    }
+
+    instruction
+}
+
+/// The arguments of subscribe
+pub struct SubscribeArgs {
+    pub signer: Pubkey,
+    pub subscription: Pubkey,
+    pub merchant: Pubkey,
+    pub order: Pubkey,
+    pub name: String,
+    pub data: Option<String>,
+    pub seats: Option<u64>,
+}
+
+/// creates a 'Subscribe' instruction
+pub fn subscribe(program_id: Pubkey, args: SubscribeArgs) -> Instruction {
+    let SubscribeArgs {
+        signer,
+        subscription,
+        merchant,
+        order,
+        name,
+        data,
+        seats,
+    } = args;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new_readonly(order, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::Subscribe { name, data, seats }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// creates a 'RenewSubscription' instruction
+pub fn renew_subscription(
+    program_id: Pubkey,
+    signer: Pubkey,
+    subscription: Pubkey,
+    merchant: Pubkey,
+    order: Pubkey,
+    price_guarantee: Option<Pubkey>,
+    quantity: i64,
+) -> Instruction {
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(subscription, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new_readonly(order, false),
+    ];
+
+    if let Some(price_guarantee) = price_guarantee {
+        account_metas.push(AccountMeta::new_readonly(price_guarantee, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::RenewSubscription { quantity }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of cancel_subscription
+pub struct CancelSubscriptionArgs {
+    pub signer: Pubkey,
+    pub subscription: Pubkey,
+    pub merchant: Pubkey,
+    pub order: Pubkey,
+    pub order_token: Pubkey,
+    pub refund_token: Pubkey,
+    pub account_to_receive_sol_refund: Pubkey,
+    pub pda: Pubkey,
+}
+
+/// creates a 'CancelSubscription' instruction
+pub fn cancel_subscription(program_id: Pubkey, args: CancelSubscriptionArgs) -> Instruction {
+    let CancelSubscriptionArgs {
+        signer,
+        subscription,
+        merchant,
+        order,
+        order_token,
+        refund_token,
+        account_to_receive_sol_refund,
+        pda,
+    } = args;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(order, false),
+            AccountMeta::new(order_token, false),
+            AccountMeta::new(refund_token, false),
+            AccountMeta::new(account_to_receive_sol_refund, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CancelSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of reclaim_expired_order
+pub struct ReclaimExpiredOrderArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub order_token: Pubkey,
+    pub refund_token: Pubkey,
+    pub account_to_receive_sol_refund: Pubkey,
+    pub pda: Pubkey,
+    pub other_tokens: Vec<(Pubkey, Pubkey)>,
+}
+
+/// creates a 'ReclaimExpiredOrder' instruction
+pub fn reclaim_expired_order(program_id: Pubkey, args: ReclaimExpiredOrderArgs) -> Instruction {
+    let ReclaimExpiredOrderArgs {
+        signer,
+        order,
+        merchant,
+        order_token,
+        refund_token,
+        account_to_receive_sol_refund,
+        pda,
+        other_tokens,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(order_token, false),
+        AccountMeta::new(refund_token, false),
+        AccountMeta::new(account_to_receive_sol_refund, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+
+    // other_tokens holds the order token and refund token accounts of each other mint
+    for (order_token, refund_token) in other_tokens {
+        account_metas.push(AccountMeta::new(order_token, false));
+        account_metas.push(AccountMeta::new(refund_token, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::ReclaimExpiredOrder
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of express_checkout_sol
+pub struct ExpressCheckoutSolArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub wsol_token: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    pub link: Option<Pubkey>,
+    pub amount: u64,
+    pub tip: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'ExpressCheckoutSol' instruction.
+pub fn express_checkout_sol(program_id: Pubkey, args: ExpressCheckoutSolArgs) -> Instruction {
+    let ExpressCheckoutSolArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        wsol_token,
+        program_owner,
+        sponsor,
+        pda,
+        integrator,
+        coupon,
+        link,
+        amount,
+        tip,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(seller_token, false),
+        AccountMeta::new(wsol_token, true),
+        AccountMeta::new(program_owner, false),
+        AccountMeta::new(sponsor, false),
+        AccountMeta::new_readonly(spl_token::native_mint::id(), false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    if let Some(integrator) = integrator {
+        account_metas.push(AccountMeta::new(integrator, false));
+    }
+
+    if let Some(coupon) = coupon {
+        account_metas.push(AccountMeta::new(coupon, false));
+    }
+
+    if let Some(link) = link {
+        let (link_payer, _bump_seed) = get_link_payer_address(&program_id, &link, &signer);
+        account_metas.push(AccountMeta::new(link, false));
+        account_metas.push(AccountMeta::new(link_payer, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::ExpressCheckoutSol {
+            amount,
+            tip,
+            order_id,
+            secret,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'RegisterIntegrator' instruction.
+pub fn register_integrator(
+    program_id: Pubkey,
+    signer: Pubkey,
+    integrator: Pubkey,
+    app_id: String,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(integrator, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::RegisterIntegrator { app_id }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'WithdrawIntegratorFees' instruction.
+pub fn withdraw_integrator_fees(
+    program_id: Pubkey,
+    signer: Pubkey,
+    integrator: Pubkey,
+    destination: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(integrator, false),
+            AccountMeta::new(destination, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::WithdrawIntegratorFees
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// creates a 'ConfirmDelivery' instruction
+pub fn confirm_delivery(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    amount: Option<u64>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+        ],
+        data: PaymentProcessorInstruction::ConfirmDelivery { amount }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of initialize_program
+pub struct InitializeProgramArgs {
+    pub signer: Pubkey,
+    pub program_owner: Pubkey,
+    pub default_fee: Option<u64>,
+    pub min_fee: Option<u64>,
+    pub sponsor_fee: Option<u64>,
+    pub name: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'InitializeProgram' instruction.
+pub fn initialize_program(program_id: Pubkey, args: InitializeProgramArgs) -> Instruction {
+    let InitializeProgramArgs {
+        signer,
+        program_owner,
+        default_fee,
+        min_fee,
+        sponsor_fee,
+        name,
+        data,
+    } = args;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(get_program_config_address(&program_id).0, false),
+            AccountMeta::new_readonly(program_owner, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(get_program_data_address(&program_id), false),
+        ],
+        data: PaymentProcessorInstruction::InitializeProgram {
+            default_fee,
+            min_fee,
+            sponsor_fee,
+            name,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'ExportAttestation' instruction.
+pub fn export_attestation(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    attested_accounts: Vec<Pubkey>,
+) -> Instruction {
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new_readonly(merchant, false),
+        AccountMeta::new(get_attestation_address(&program_id, &merchant).0, false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    let mut attested_accounts = attested_accounts;
+    attested_accounts.sort();
+    for attested_account in attested_accounts {
+        account_metas.push(AccountMeta::new_readonly(attested_account, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::ExportAttestation
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'ImportAttestation' instruction.
+pub fn import_attestation(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    source_attestation: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(source_attestation, false),
+            AccountMeta::new(get_attestation_address(&program_id, &merchant).0, false),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::ImportAttestation
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of split_checkout
+pub struct SplitCheckoutArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub recipients: Vec<(Pubkey, u16)>,
+    pub integrator: Option<Pubkey>,
+    pub amount: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'SplitCheckout' instruction.
+pub fn split_checkout(program_id: Pubkey, args: SplitCheckoutArgs) -> Instruction {
+    let SplitCheckoutArgs {
+        signer,
+        order,
+        merchant,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        recipients,
+        integrator,
+        amount,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(buyer_token, false),
+        AccountMeta::new(program_owner, false),
+        AccountMeta::new(sponsor, false),
+        AccountMeta::new_readonly(mint, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    let mut splits = vec![];
+    for (recipient, split) in recipients {
+        account_metas.push(AccountMeta::new(recipient, false));
+        splits.push(split);
+    }
+
+    if let Some(integrator) = integrator {
+        account_metas.push(AccountMeta::new(integrator, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::SplitCheckout {
+            amount,
+            order_id,
+            secret,
+            data,
+            splits,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// creates a 'PayInstallment' instruction
+pub fn pay_installment(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    order_token: Pubkey,
+    buyer_token: Pubkey,
+    merchant: Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(order_token, false),
+            AccountMeta::new(buyer_token, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::PayInstallment { amount }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an ed25519 program instruction that verifies a voucher signature.
+pub fn verify_voucher_signature(
+    merchant_owner: Pubkey,
+    signature: [u8; 64],
+    voucher: &Voucher,
+) -> Instruction {
+    let message = voucher.message();
+    // the offsets are followed by the public key, the signature and the message
+    let public_key_offset: u16 = 16;
+    let signature_offset: u16 = public_key_offset + 32;
+    let message_data_offset: u16 = signature_offset + 64;
+    let mut data = vec![1, 0];
+    for value in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_data_offset,
+        message.len() as u16,
+        u16::MAX,
+    ]
+    .iter()
+    {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&merchant_owner.to_bytes());
+    data.extend_from_slice(&signature);
+    data.extend_from_slice(&message);
+
+    Instruction {
+        program_id: Pubkey::from_str(ED25519_PROGRAM_ID).unwrap(),
+        accounts: vec![],
+        data,
+    }
+}
+
+/// The arguments of redeem_voucher
+pub struct RedeemVoucherArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub voucher: Voucher,
+}
+
+/// Creates an 'RedeemVoucher' instruction.
+pub fn redeem_voucher(program_id: Pubkey, args: RedeemVoucherArgs) -> Instruction {
+    let RedeemVoucherArgs {
+        signer,
+        order,
+        seller_token,
+        buyer_token,
+        program_owner,
+        sponsor,
+        pda,
+        voucher,
+    } = args;
+    let (voucher_address, _bump_seed) =
+        get_voucher_address(&program_id, &voucher.merchant, voucher.nonce);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(voucher.merchant, false),
+            AccountMeta::new(seller_token, false),
+            AccountMeta::new(buyer_token, false),
+            AccountMeta::new(program_owner, false),
+            AccountMeta::new(sponsor, false),
+            AccountMeta::new_readonly(voucher.mint, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+            AccountMeta::new(voucher_address, false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        data: PaymentProcessorInstruction::RedeemVoucher { voucher }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of create_invoice
+pub struct CreateInvoiceArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub mint: Pubkey,
+    pub pda: Pubkey,
+    pub amount: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'CreateInvoice' instruction.
+pub fn create_invoice(program_id: Pubkey, args: CreateInvoiceArgs) -> Instruction {
+    let CreateInvoiceArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        mint,
+        pda,
+        amount,
+        order_id,
+        secret,
+        data,
+    } = args;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(seller_token, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreateInvoice {
+            amount,
+            order_id,
+            secret,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of pay_invoice
+pub struct PayInvoiceArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub integrator: Option<Pubkey>,
+}
+
+/// Creates an 'PayInvoice' instruction.
+pub fn pay_invoice(program_id: Pubkey, args: PayInvoiceArgs) -> Instruction {
+    let PayInvoiceArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        integrator,
+    } = args;
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(seller_token, false),
+        AccountMeta::new(buyer_token, false),
+        AccountMeta::new(program_owner, false),
+        AccountMeta::new(sponsor, false),
+        AccountMeta::new_readonly(mint, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+    ];
+
+    if let Some(integrator) = integrator {
+        account_metas.push(AccountMeta::new(integrator, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::PayInvoice
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetMigrationWindow' instruction.
+pub fn set_migration_window(
+    program_id: Pubkey,
+    signer: Pubkey,
+    ends_at: UnixTimestamp,
+    version: u8,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(get_program_config_address(&program_id).0, false),
+        ],
+        data: PaymentProcessorInstruction::SetMigrationWindow { ends_at, version }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'WaiveOrderFees' instruction.
+pub fn waive_order_fees(
+    program_id: Pubkey,
+    program_owner: Pubkey,
+    order: Pubkey,
+    merchant: Pubkey,
+    sponsor: Pubkey,
+    payer: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(program_owner, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(sponsor, true),
+            AccountMeta::new(payer, false),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+        data: PaymentProcessorInstruction::WaiveOrderFees
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of create_coupon
+pub struct CreateCouponArgs {
+    pub signer: Pubkey,
+    pub merchant: Pubkey,
+    pub code: String,
+    pub discount_type: DiscountType,
+    pub discount: u64,
+    pub mint: Option<Pubkey>,
+    pub max_uses: u64,
+    pub expires_at: UnixTimestamp,
+}
+
+/// Creates an 'CreateCoupon' instruction.
+pub fn create_coupon(program_id: Pubkey, args: CreateCouponArgs) -> Instruction {
+    let CreateCouponArgs {
+        signer,
+        merchant,
+        code,
+        discount_type,
+        discount,
+        mint,
+        max_uses,
+        expires_at,
+    } = args;
+    let (coupon, _bump_seed) = get_coupon_address(&program_id, &merchant, &code);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(coupon, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreateCoupon {
+            code,
+            discount_type: discount_type as u8,
+            discount,
+            mint,
+            max_uses,
+            expires_at,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'CreatePaymentLink' instruction.
+pub fn create_payment_link(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    mint: Pubkey,
+    link_id: String,
+    amount: u64,
+    data: Option<String>,
+) -> Instruction {
+    let (link, _bump_seed) = get_payment_link_address(&program_id, &merchant, &link_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(link, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreatePaymentLink {
+            link_id,
+            amount,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'SetMerchantTolerance' instruction.
+pub fn set_merchant_tolerance(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    tolerance: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetMerchantTolerance { tolerance }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetInstantSettlement' instruction.
+pub fn set_instant_settlement(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    instant_settlement: bool,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetInstantSettlement { instant_settlement }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetPayoutSchedule' instruction.
+pub fn set_payout_schedule(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    payouts: Vec<(Pubkey, u64)>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetPayoutSchedule { payouts }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetOperators' instruction.
+pub fn set_operators(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    operators: Vec<Pubkey>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetOperators { operators }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of withdraw_amount
+pub struct WithdrawAmountArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub order_payment_token: Pubkey,
+    pub merchant_token: Pubkey,
+    pub pda: Pubkey,
+    pub amount: u64,
+}
+
+/// Creates an 'WithdrawAmount' instruction.
+pub fn withdraw_amount(program_id: Pubkey, args: WithdrawAmountArgs) -> Instruction {
+    let WithdrawAmountArgs {
+        signer,
+        order,
+        merchant,
+        order_payment_token,
+        merchant_token,
+        pda,
+        amount,
+    } = args;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(order_payment_token, false),
+            AccountMeta::new(merchant_token, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: PaymentProcessorInstruction::WithdrawAmount { amount }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetSweepPolicy' instruction.
+pub fn set_sweep_policy(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    threshold: u64,
+    delay: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(get_sweep_bounty_address(&program_id, &merchant).0, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetSweepPolicy { threshold, delay }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'Sweep' instruction.
+///
+/// orders holds the order account and the order token account of each order
+/// swept.
+pub fn sweep(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    merchant_owner: Pubkey,
+    mint: Pubkey,
+    orders: Vec<(Pubkey, Pubkey)>,
+) -> Instruction {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(
+            get_associated_token_address(&merchant_owner, &spl_token::id(), &mint),
+            false,
+        ),
+        AccountMeta::new_readonly(mint, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+        AccountMeta::new(get_sweep_bounty_address(&program_id, &merchant).0, false),
+    ];
+
+    for (order, order_token) in orders {
+        account_metas.push(AccountMeta::new(order, false));
+        account_metas.push(AccountMeta::new(order_token, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::Sweep.try_to_vec().unwrap(),
+    }
+}
+
+/// Creates an 'ClaimSweepBounties' instruction.
+pub fn claim_sweep_bounties(program_id: Pubkey, signer: Pubkey, merchant: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(get_sweep_bounty_address(&program_id, &merchant).0, false),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+        ],
+        data: PaymentProcessorInstruction::ClaimSweepBounties
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of register_merchant_with_terms
+pub struct RegisterMerchantWithTermsArgs<'a> {
+    pub signer: Pubkey,
+    pub merchant: Pubkey,
+    pub seed: Option<String>,
+    pub fee: Option<u64>,
+    pub terms: MerchantTerms,
+    pub metadata: Vec<u8>,
+    pub sponsor: Option<&'a Pubkey>,
+    pub sponsor_fee: Option<u64>,
+}
+
+/// Creates an 'RegisterMerchantWithTerms' instruction.
+pub fn register_merchant_with_terms(
+    program_id: Pubkey,
+    args: RegisterMerchantWithTermsArgs<'_>,
+) -> Instruction {
+    let RegisterMerchantWithTermsArgs {
+        signer,
+        merchant,
+        seed,
+        fee,
+        terms,
+        metadata,
+        sponsor,
+        sponsor_fee,
+    } = args;
+    let mut instruction = register_merchant(
+        program_id,
+        RegisterMerchantArgs {
+            signer,
+            merchant,
+            seed: seed.clone(),
+            fee,
+            data: Option::None,
+            sponsor,
+            sponsor_fee,
+        },
+    );
+    instruction.data = PaymentProcessorInstruction::RegisterMerchantWithTerms {
+        seed,
+        fee,
+        terms,
+        metadata,
+        sponsor_fee,
+    }
+    .try_to_vec()
+    .unwrap();
+    instruction
+}
+
+/// Creates an 'MigrateMerchantData' instruction.
+pub fn migrate_merchant_data(program_id: Pubkey, signer: Pubkey, merchant: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::MigrateMerchantData
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'MigrateAccount' instruction.
+pub fn migrate_account(
+    program_id: Pubkey,
+    signer: Pubkey,
+    account: Pubkey,
+    seed: Option<String>,
+    references: Vec<Pubkey>,
+) -> Instruction {
+    let (migration, _bump_seed) = get_migration_address(&program_id, &account);
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(account, false),
+        AccountMeta::new(migration, false),
+        AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    ];
+    for reference in references {
+        account_metas.push(AccountMeta::new_readonly(reference, false));
+    }
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::MigrateAccount { seed }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetFreezePolicy' instruction.
+pub fn set_freeze_policy(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    rejects_freezable_mints: bool,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetFreezePolicy {
+            rejects_freezable_mints,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'GuaranteePackagePrice' instruction.
+pub fn guarantee_package_price(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    package: String,
+    guaranteed_until: UnixTimestamp,
+) -> Instruction {
+    let (price_guarantee, _bump_seed) =
+        get_price_guarantee_address(&program_id, &merchant, &package);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(price_guarantee, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::GuaranteePackagePrice {
+            package,
+            guaranteed_until,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'ApproveAutoDebit' instruction.
+pub fn approve_auto_debit(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    buyer_token: Pubkey,
+    allowance: u64,
+    max_amount: u64,
+    interval: i64,
+) -> Instruction {
+    let (auto_debit, _bump_seed) = get_auto_debit_address(&program_id, &merchant, &buyer_token);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(auto_debit, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(buyer_token, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::ApproveAutoDebit {
+            allowance,
+            max_amount,
+            interval,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of collect_payment
+pub struct CollectPaymentArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub amount: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'CollectPayment' instruction.
+pub fn collect_payment(program_id: Pubkey, args: CollectPaymentArgs) -> Instruction {
+    let CollectPaymentArgs {
+        signer,
+        order,
+        merchant,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        amount,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    let (auto_debit, _bump_seed) = get_auto_debit_address(&program_id, &merchant, &buyer_token);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(get_order_token_address(&program_id, &order, &mint), false),
+            AccountMeta::new(buyer_token, false),
+            AccountMeta::new(auto_debit, false),
+            AccountMeta::new(program_owner, false),
+            AccountMeta::new(sponsor, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+        ],
+        data: PaymentProcessorInstruction::CollectPayment {
+            amount,
+            order_id,
+            secret,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of renew_due_subscriptions
+pub struct RenewDueSubscriptionsArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub subscription: Pubkey,
+    pub subscriber_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub guarantee: Option<Pubkey>,
+}
+
+/// Creates an 'RenewDueSubscriptions' instruction.
+pub fn renew_due_subscriptions(program_id: Pubkey, args: RenewDueSubscriptionsArgs) -> Instruction {
+    let RenewDueSubscriptionsArgs {
+        signer,
+        order,
+        merchant,
+        subscription,
+        subscriber_token,
+        mint,
+        program_owner,
+        sponsor,
+        guarantee,
+    } = args;
+    let mut instruction = collect_payment(
+        program_id,
+        CollectPaymentArgs {
+            signer,
+            order,
+            merchant,
+            buyer_token: subscriber_token,
+            mint,
+            program_owner,
+            sponsor,
+            amount: 0,
+            order_id: String::from(""),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    instruction
+        .accounts
+        .push(AccountMeta::new(subscription, false));
+    if let Some(guarantee) = guarantee {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(guarantee, false));
+    }
+    instruction.data = PaymentProcessorInstruction::RenewDueSubscriptions
+        .try_to_vec()
+        .unwrap();
+    instruction
+}
+
+/// Creates an 'SetFeeSchedule' instruction.
+///
+/// index is the number of fee schedules in the fee history so far (see
+/// FeeHistoryAccount) i.e. 0 the first time.
+pub fn set_fee_schedule(
+    program_id: Pubkey,
+    signer: Pubkey,
+    index: u64,
+    default_fee: u64,
+    min_fee: u64,
+    sponsor_fee: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(get_program_config_address(&program_id).0, false),
+            AccountMeta::new(get_fee_history_address(&program_id).0, false),
+            AccountMeta::new(get_fee_schedule_address(&program_id, index).0, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetFeeSchedule {
+            default_fee,
+            min_fee,
+            sponsor_fee,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of pay_link
+pub struct PayLinkArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub link: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub coupon: Option<Pubkey>,
+    pub amount: u64,
+    pub tip: u64,
+}
+
+/// Creates an 'PayLink' instruction.
+pub fn pay_link(program_id: Pubkey, args: PayLinkArgs) -> Instruction {
+    let PayLinkArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        pda,
+        link,
+        integrator,
+        coupon,
+        amount,
+        tip,
+    } = args;
+    let mut instruction = express_checkout(
+        program_id,
+        ExpressCheckoutArgs {
+            signer,
+            order,
+            merchant,
+            seller_token,
+            buyer_token,
+            mint,
+            program_owner,
+            sponsor,
+            pda,
+            integrator: Option::None,
+            coupon: Option::None,
+            link: Some(link),
+            amount,
+            tip,
+            order_id: String::from(""),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    if let Some(integrator) = integrator {
+        instruction
+            .accounts
+            .push(AccountMeta::new(integrator, false));
+    }
+    if let Some(coupon) = coupon {
+        instruction.accounts.push(AccountMeta::new(coupon, false));
+    }
+    instruction.data = PaymentProcessorInstruction::PayLink { amount, tip }
+        .try_to_vec()
+        .unwrap();
+    instruction
+}
+
+/// Creates an 'CreateCampaign' instruction.
+pub fn create_campaign(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    mint: Pubkey,
+    campaign_id: String,
+    goal: u64,
+    data: Option<String>,
+) -> Instruction {
+    let (campaign, _bump_seed) = get_campaign_address(&program_id, &merchant, &campaign_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(campaign, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreateCampaign {
+            campaign_id,
+            goal,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// The arguments of donate
+pub struct DonateArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub seller_token: Pubkey,
+    pub buyer_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub pda: Pubkey,
+    pub integrator: Option<Pubkey>,
+    pub campaign: Option<Pubkey>,
+    pub amount: u64,
+    pub order_id: String,
+    pub secret: String,
+    pub data: Option<String>,
+}
+
+/// Creates an 'Donate' instruction.
+pub fn donate(program_id: Pubkey, args: DonateArgs) -> Instruction {
+    let DonateArgs {
+        signer,
+        order,
+        merchant,
+        seller_token,
+        buyer_token,
+        mint,
+        program_owner,
+        sponsor,
+        pda,
+        integrator,
+        campaign,
+        amount,
+        order_id,
+        secret,
+        data,
+    } = args;
+    let mut instruction = express_checkout(
+        program_id,
+        ExpressCheckoutArgs {
+            signer,
+            order,
+            merchant,
+            seller_token,
+            buyer_token,
+            mint,
+            program_owner,
+            sponsor,
+            pda,
+            integrator,
+            coupon: Option::None,
+            link: Option::None,
+            amount,
+            tip: 0,
+            order_id: String::from(""),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    if let Some(campaign) = campaign {
+        instruction.accounts.push(AccountMeta::new(campaign, false));
+    }
+    instruction.data = PaymentProcessorInstruction::Donate {
+        amount,
+        order_id,
+        secret,
+        data,
+    }
+    .try_to_vec()
+    .unwrap();
+    instruction
+}
+
+/// Creates an 'IssueRefundCode' instruction.
+///
+/// Only the hash of the code is sent, see get_refund_code_hash.
+pub fn issue_refund_code(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    merchant: Pubkey,
+    code: &str,
+) -> Instruction {
+    let (refund_code, _bump_seed) = get_refund_code_address(&program_id, &order);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(refund_code, false),
+            AccountMeta::new_readonly(order, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::IssueRefundCode {
+            code_hash: get_refund_code_hash(&order, code),
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'RedeemRefundCode' instruction.
+pub fn redeem_refund_code(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    merchant: Pubkey,
+    order_token: Pubkey,
+    refund_token: Pubkey,
+    code: String,
+) -> Instruction {
+    let (refund_code, _bump_seed) = get_refund_code_address(&program_id, &order);
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(order_token, false),
+            AccountMeta::new(refund_token, false),
+            AccountMeta::new(refund_code, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: PaymentProcessorInstruction::RedeemRefundCode { code }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetMerchantRecovery' instruction.
+pub fn set_merchant_recovery(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    recovery_key: Pubkey,
+    timeout: i64,
+    challenge_window: i64,
+) -> Instruction {
+    let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(recovery, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetMerchantRecovery {
+            recovery_key,
+            timeout,
+            challenge_window,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'StartMerchantRecovery' instruction.
+pub fn start_merchant_recovery(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+) -> Instruction {
+    let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(recovery, false),
+        ],
+        data: PaymentProcessorInstruction::StartMerchantRecovery
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'CompleteMerchantRecovery' instruction.
+pub fn complete_merchant_recovery(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+) -> Instruction {
+    let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(recovery, false),
+        ],
+        data: PaymentProcessorInstruction::CompleteMerchantRecovery
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'RestockItem' instruction.
+pub fn restock_item(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    item: String,
+    quantity: u64,
+) -> Instruction {
+    let (item_stock, _bump_seed) = get_item_stock_address(&program_id, &merchant, &item);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(item_stock, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::RestockItem { item, quantity }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetPriceOracle' instruction.
+pub fn set_price_oracle(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    mint: Pubkey,
+    price_feed: Pubkey,
+    max_staleness: i64,
+    max_slippage: u64,
+) -> Instruction {
+    let (oracle, _bump_seed) = get_price_oracle_address(&program_id, &merchant, &mint);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(oracle, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(price_feed, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetPriceOracle {
+            max_staleness,
+            max_slippage,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'CreateBundle' instruction.
+pub fn create_bundle(
+    program_id: Pubkey,
+    signer: Pubkey,
+    bundle_id: String,
+    discount: u64,
+) -> Instruction {
+    let (bundle, _bump_seed) = get_bundle_address(&program_id, &signer, &bundle_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(bundle, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreateBundle {
+            bundle_id,
+            discount,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'JoinBundle' instruction.
+pub fn join_bundle(
+    program_id: Pubkey,
+    signer: Pubkey,
+    authority: Pubkey,
+    merchant: Pubkey,
+    bundle: Pubkey,
+    package: String,
+) -> Instruction {
+    let (member, _bump_seed) = get_bundle_member_address(&program_id, &bundle, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(bundle, false),
+            AccountMeta::new(member, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::JoinBundle { package }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetSettlementMint' instruction.
+pub fn set_settlement_mint(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    let (settlement, _bump_seed) = get_settlement_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(settlement, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetSettlementMint
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of withdraw_settled
+pub struct WithdrawSettledArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub order_payment_token: Pubkey,
+    pub merchant_token: Pubkey,
+    pub account_to_receive_sol_refund: Pubkey,
+    pub swap_program: Pubkey,
+    pub pool: Vec<Pubkey>,
+    pub subscription: Option<Pubkey>,
+    pub minimum_amount_out: u64,
+}
+
+/// Creates an 'WithdrawSettled' instruction.
+///
+/// pool holds the swap, swap authority, pool source, pool destination, pool
+/// mint and pool fee accounts, in that order.
+pub fn withdraw_settled(program_id: Pubkey, args: WithdrawSettledArgs) -> Instruction {
+    let WithdrawSettledArgs {
+        signer,
+        order,
+        merchant,
+        order_payment_token,
+        merchant_token,
+        account_to_receive_sol_refund,
+        swap_program,
+        pool,
+        subscription,
+        minimum_amount_out,
+    } = args;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    let (config, _bump_seed) = get_program_config_address(&program_id);
+    let (settlement, _bump_seed) = get_settlement_address(&program_id, &merchant);
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new(merchant, false),
+        AccountMeta::new(order_payment_token, false),
+        AccountMeta::new(merchant_token, false),
+        AccountMeta::new(account_to_receive_sol_refund, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(settlement, false),
+        AccountMeta::new_readonly(swap_program, false),
+    ];
+    // the swap and swap authority are read only, the rest of the pool is written to
+    for (index, pool_account) in pool.into_iter().enumerate() {
+        if index < 2 {
+            account_metas.push(AccountMeta::new_readonly(pool_account, false));
+        } else {
+            account_metas.push(AccountMeta::new(pool_account, false));
+        }
+    }
+    if let Some(subscription) = subscription {
+        account_metas.push(AccountMeta::new_readonly(subscription, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::WithdrawSettled { minimum_amount_out }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'PostOrderNote' instruction.
+///
+/// index is the number of notes already posted on the order.
+pub fn post_order_note(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    merchant: Pubkey,
+    index: u64,
+    note: String,
+) -> Instruction {
+    let (notes, _bump_seed) = get_order_notes_address(&program_id, &order);
+    let (order_note, _bump_seed) = get_order_note_address(&program_id, &order, index);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(order, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(notes, false),
+            AccountMeta::new(order_note, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::PostOrderNote { note }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'UpdateMerchant' instruction.
+pub fn update_merchant(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    fee: Option<u64>,
+    sponsor: Option<Pubkey>,
+    data: Option<String>,
+) -> Instruction {
+    let (config, _bump_seed) = get_program_config_address(&program_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new_readonly(config, false),
+        ],
+        data: PaymentProcessorInstruction::UpdateMerchant { fee, sponsor, data }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of split_order
+pub struct SplitOrderArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub sub_order: Pubkey,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub order_id: String,
+}
+
+/// Creates an 'SplitOrder' instruction.
+pub fn split_order(program_id: Pubkey, args: SplitOrderArgs) -> Instruction {
+    let SplitOrderArgs {
+        signer,
+        order,
+        sub_order,
+        merchant,
+        mint,
+        amount,
+        order_id,
+    } = args;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(order, false),
+            AccountMeta::new(sub_order, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(get_order_token_address(&program_id, &order, &mint), false),
+            AccountMeta::new(
+                get_order_token_address(&program_id, &sub_order, &mint),
+                false,
+            ),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SplitOrder { amount, order_id }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'ProposeMerchantTransfer' instruction.
+pub fn propose_merchant_transfer(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    new_owner: Pubkey,
+) -> Instruction {
+    let (transfer, _bump_seed) = get_merchant_transfer_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(transfer, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::ProposeMerchantTransfer { new_owner }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'AcceptMerchantTransfer' instruction.
+pub fn accept_merchant_transfer(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    previous_owner: Pubkey,
+) -> Instruction {
+    let (transfer, _bump_seed) = get_merchant_transfer_address(&program_id, &merchant);
+    let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(transfer, false),
+            AccountMeta::new(previous_owner, false),
+            AccountMeta::new(recovery, false),
+        ],
+        data: PaymentProcessorInstruction::AcceptMerchantTransfer
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetMerchantStats' instruction.
+pub fn set_merchant_stats(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    max_open_token_accounts: u64,
+    open_token_accounts: Option<u64>,
+) -> Instruction {
+    let (stats, _bump_seed) = get_merchant_stats_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(stats, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetMerchantStats {
+            max_open_token_accounts,
+            open_token_accounts,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'CloseMerchant' instruction.
+pub fn close_merchant(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    lapsed_subscriptions: Vec<Pubkey>,
+) -> Instruction {
+    let mut account_metas = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(merchant, false),
+    ];
+    for subscription in lapsed_subscriptions {
+        account_metas.push(AccountMeta::new(subscription, false));
+    }
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::CloseMerchant
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'CloseOrder' instruction.
+pub fn close_order(
+    program_id: Pubkey,
+    signer: Pubkey,
+    order: Pubkey,
+    merchant: Pubkey,
+    recipient: Pubkey,
+    pda: Pubkey,
+    order_tokens: Vec<Pubkey>,
+) -> Instruction {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(signer, true),
+        AccountMeta::new(order, false),
+        AccountMeta::new_readonly(merchant, false),
+        AccountMeta::new(recipient, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for order_token in order_tokens {
+        account_metas.push(AccountMeta::new(order_token, false));
+    }
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::CloseOrder
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'CreateStore' instruction.
+pub fn create_store(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    store_id: String,
+    fee_destination: Option<Pubkey>,
+    catalog: Option<String>,
+    data: Option<String>,
+) -> Instruction {
+    let (store, _bump_seed) = get_store_address(&program_id, &merchant, &store_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(store, false),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreateStore {
+            store_id,
+            fee_destination,
+            catalog,
+            data,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'CloseStore' instruction.
+pub fn close_store(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    store_id: &str,
+) -> Instruction {
+    let (store, _bump_seed) = get_store_address(&program_id, &merchant, store_id);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(store, false),
+            AccountMeta::new_readonly(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::CloseStore
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'PauseMerchant' instruction.
+pub fn pause_merchant(program_id: Pubkey, signer: Pubkey, merchant: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::PauseMerchant
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'ResumeMerchant' instruction.
+pub fn resume_merchant(program_id: Pubkey, signer: Pubkey, merchant: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::ResumeMerchant
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetAcceptedMints' instruction.
+pub fn set_accepted_mints(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    mints: Vec<Pubkey>,
+) -> Instruction {
+    let (accepted_mints, _bump_seed) = get_accepted_mints_address(&program_id, &merchant);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(accepted_mints, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetAcceptedMints { mints }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'BlockBuyer' instruction.
+pub fn block_buyer(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    buyer: Pubkey,
+) -> Instruction {
+    let (blocked_buyer, _bump_seed) = get_blocked_buyer_address(&program_id, &merchant, &buyer);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(blocked_buyer, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::BlockBuyer { buyer }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'UnblockBuyer' instruction.
+pub fn unblock_buyer(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    buyer: Pubkey,
+) -> Instruction {
+    let (blocked_buyer, _bump_seed) = get_blocked_buyer_address(&program_id, &merchant, &buyer);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new(blocked_buyer, false),
+        ],
+        data: PaymentProcessorInstruction::UnblockBuyer
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'UpdateConfig' instruction.
+pub fn update_config(
+    program_id: Pubkey,
+    signer: Pubkey,
+    program_owner: Option<Pubkey>,
+    default_fee: Option<u64>,
+    min_fee: Option<u64>,
+    sponsor_fee: Option<u64>,
+) -> Instruction {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(signer, true),
+        AccountMeta::new(get_program_config_address(&program_id).0, false),
+    ];
+    if let Some(program_owner) = program_owner {
+        account_metas.push(AccountMeta::new_readonly(program_owner, false));
+    }
+    Instruction {
+        program_id,
+        accounts: account_metas,
+        data: PaymentProcessorInstruction::UpdateConfig {
+            default_fee,
+            min_fee,
+            sponsor_fee,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'SetMerchantSponsorFee' instruction.
+pub fn set_merchant_sponsor_fee(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    sponsor_fee: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new_readonly(get_program_config_address(&program_id).0, false),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::SetMerchantSponsorFee { sponsor_fee }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'SetReferrer' instruction.
+pub fn set_referrer(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    wallet: Pubkey,
+    referral_fee: u64,
+) -> Instruction {
+    let (referrer, _bump_seed) = get_referrer_address(&program_id, &merchant, &wallet);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(referrer, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::SetReferrer {
+            wallet,
+            referral_fee,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates a 'Subscribe' instruction that starts a free trial without an order
+pub fn start_free_trial(
+    program_id: Pubkey,
+    signer: Pubkey,
+    subscription: Pubkey,
+    merchant: Pubkey,
+    name: String,
+    data: Option<String>,
+    seats: Option<u64>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::Subscribe { name, data, seats }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'ExpireSubscription' instruction.
+pub fn expire_subscription(
+    program_id: Pubkey,
+    subscription: Pubkey,
+    merchant: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::ExpireSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'CloseSubscription' instruction.
+pub fn close_subscription(program_id: Pubkey, subscription: Pubkey, owner: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(owner, false),
+        ],
+        data: PaymentProcessorInstruction::CloseSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'TransferSubscription' instruction.
+pub fn transfer_subscription(
+    program_id: Pubkey,
+    owner: Pubkey,
+    new_owner: Pubkey,
+    subscription: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(owner, true),
+            AccountMeta::new_readonly(new_owner, true),
+            AccountMeta::new(subscription, false),
+        ],
+        data: PaymentProcessorInstruction::TransferSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'PauseSubscription' instruction.
+pub fn pause_subscription(program_id: Pubkey, owner: Pubkey, subscription: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(owner, true),
+            AccountMeta::new(subscription, false),
+        ],
+        data: PaymentProcessorInstruction::PauseSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'ResumeSubscription' instruction.
+pub fn resume_subscription(program_id: Pubkey, owner: Pubkey, subscription: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(owner, true),
+            AccountMeta::new(subscription, false),
+        ],
+        data: PaymentProcessorInstruction::ResumeSubscription
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'RecordUsage' instruction.
+pub fn record_usage(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    subscription: Pubkey,
+    units: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new_readonly(merchant, false),
+            AccountMeta::new(subscription, false),
+        ],
+        data: PaymentProcessorInstruction::RecordUsage { units }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of settle_usage
+pub struct SettleUsageArgs {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub subscription: Pubkey,
+    pub subscriber_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+}
+
+/// Creates a 'SettleUsage' instruction.
+pub fn settle_usage(program_id: Pubkey, args: SettleUsageArgs) -> Instruction {
+    let SettleUsageArgs {
+        signer,
+        order,
+        merchant,
+        subscription,
+        subscriber_token,
+        mint,
+        program_owner,
+        sponsor,
+    } = args;
+    let mut instruction = collect_payment(
+        program_id,
+        CollectPaymentArgs {
+            signer,
+            order,
+            merchant,
+            buyer_token: subscriber_token,
+            mint,
+            program_owner,
+            sponsor,
+            amount: 0,
+            order_id: String::from(""),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    instruction
+        .accounts
+        .push(AccountMeta::new(subscription, false));
+    instruction.data = PaymentProcessorInstruction::SettleUsage
+        .try_to_vec()
+        .unwrap();
+    instruction
+}
+
+/// Creates an 'UpdateSeats' instruction that removes seats (or adds seats that are free).
+pub fn update_seats(
+    program_id: Pubkey,
+    owner: Pubkey,
+    subscription: Pubkey,
+    merchant: Pubkey,
+    seats: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(owner, true),
+            AccountMeta::new(subscription, false),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::UpdateSeats { seats }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// The arguments of add_seats
+pub struct AddSeatsArgs {
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub merchant: Pubkey,
+    pub subscription: Pubkey,
+    pub subscriber_token: Pubkey,
+    pub mint: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub seats: u64,
+}
+
+/// Creates an 'UpdateSeats' instruction that adds seats, paid for through the
+/// auto-debit account of the subscriber token account.
+pub fn add_seats(program_id: Pubkey, args: AddSeatsArgs) -> Instruction {
+    let AddSeatsArgs {
+        owner,
+        order,
+        merchant,
+        subscription,
+        subscriber_token,
+        mint,
+        program_owner,
+        sponsor,
+        seats,
+    } = args;
+    let mut instruction = collect_payment(
+        program_id,
+        CollectPaymentArgs {
+            signer: owner,
+            order,
+            merchant,
+            buyer_token: subscriber_token,
+            mint,
+            program_owner,
+            sponsor,
+            amount: 0,
+            order_id: String::from(""),
+            secret: String::from(""),
+            data: Option::None,
+        },
+    );
+    // the subscription account goes before the payment accounts
+    instruction
+        .accounts
+        .insert(1, AccountMeta::new(subscription, false));
+    // and the merchant account comes right after it
+    let merchant_meta = instruction.accounts.remove(3);
+    instruction.accounts.insert(2, merchant_meta);
+    instruction.data = PaymentProcessorInstruction::UpdateSeats { seats }
+        .try_to_vec()
+        .unwrap();
+    instruction
+}
+
+/// Creates an 'CreatePackage' instruction.
+pub fn create_package(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    name: &str,
+    data: String,
+) -> Instruction {
+    let (package, _bump_seed) = get_package_address(&program_id, &merchant, name);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(signer, true),
+            AccountMeta::new(package, false),
+            AccountMeta::new(merchant, false),
+            AccountMeta::new_readonly(solana_program::system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: PaymentProcessorInstruction::CreatePackage { data }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'UpdatePackage' instruction.
+pub fn update_package(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    name: &str,
+    data: String,
+) -> Instruction {
+    let (package, _bump_seed) = get_package_address(&program_id, &merchant, name);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(package, false),
+            AccountMeta::new(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::UpdatePackage { data }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates an 'RetirePackage' instruction.
+pub fn retire_package(
+    program_id: Pubkey,
+    signer: Pubkey,
+    merchant: Pubkey,
+    name: &str,
+) -> Instruction {
+    let (package, _bump_seed) = get_package_address(&program_id, &merchant, name);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(package, false),
+            AccountMeta::new_readonly(merchant, false),
+        ],
+        data: PaymentProcessorInstruction::RetirePackage
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Make a subscription instruction created by one of the above use a package of
+/// the catalog of the merchant
+///
+/// Appends the package account, needed by the instructions about subscriptions
+/// to packages of the catalog of the merchant.
+pub fn use_catalog_package(
+    mut instruction: Instruction,
+    merchant: Pubkey,
+    name: &str,
+) -> Instruction {
+    let (package, _bump_seed) = get_package_address(&instruction.program_id, &merchant, name);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(package, false));
+    instruction
+}
+
+/// Make a chain checkout instruction created by one of the above take items out of stock
+///
+/// Appends the item stock accounts of the given items, needed for each order
+/// item whose stock is tracked.
+pub fn use_item_stock(
+    mut instruction: Instruction,
+    merchant: Pubkey,
+    items: Vec<&str>,
+) -> Instruction {
+    for item in items {
+        let (item_stock, _bump_seed) =
+            get_item_stock_address(&instruction.program_id, &merchant, item);
+        instruction
+            .accounts
+            .push(AccountMeta::new(item_stock, false));
+    }
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above mint a receipt NFT to the buyer
+///
+/// Appends the token metadata program followed by the accounts of the receipt.
+/// The receipt mint is a new account that has to sign the transaction.
+pub fn use_receipt(mut instruction: Instruction, receipt_mint: Pubkey) -> Instruction {
+    let buyer = instruction.accounts[0].pubkey;
+    let receipt_accounts = vec![
+        AccountMeta::new_readonly(get_token_metadata_program_id(), false),
+        AccountMeta::new(receipt_mint, true),
+        AccountMeta::new(get_metadata_address(&receipt_mint), false),
+        AccountMeta::new(get_master_edition_address(&receipt_mint), false),
+        AccountMeta::new(
+            get_associated_token_address(&buyer, &spl_token::id(), &receipt_mint),
+            false,
+        ),
+        AccountMeta::new_readonly(get_associated_token_program_id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    instruction.accounts.extend(receipt_accounts);
+    instruction
+}
+
+/// Make a chain checkout instruction created by one of the above show an NFT of the buyer
+///
+/// Appends the metadata account of the NFT and the associated token account of
+/// the buyer that holds it, for the items gated by the collection of the NFT.
+pub fn use_collection_nft(mut instruction: Instruction, nft_mint: Pubkey) -> Instruction {
+    let buyer = instruction.accounts[0].pubkey;
+    instruction.accounts.push(AccountMeta::new_readonly(
+        get_metadata_address(&nft_mint),
+        false,
+    ));
+    instruction.accounts.push(AccountMeta::new_readonly(
+        get_associated_token_address(&buyer, &spl_token::id(), &nft_mint),
+        false,
+    ));
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above go through screening
+///
+/// Appends the screening program set in the program config along with the
+/// accounts of the screening program that it needs to screen the payment.
+pub fn use_screening_program(
+    mut instruction: Instruction,
+    screening_program_id: Pubkey,
+    screening_accounts: Vec<Pubkey>,
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(screening_program_id, false));
+    for account in screening_accounts {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(account, false));
+    }
+    instruction
+}
+
+/// Make an express checkout instruction created by one of the above priced in fiat
+///
+/// Appends the price oracle account that the merchant set for the mint and its
+/// price feed account, so that the amount and the tip are converted from fiat
+/// into the mint at the oracle price.
+pub fn use_price_oracle(
+    mut instruction: Instruction,
+    merchant: Pubkey,
+    mint: Pubkey,
+    price_feed: Pubkey,
+) -> Instruction {
+    let (oracle, _bump_seed) = get_price_oracle_address(&instruction.program_id, &merchant, &mint);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(oracle, false));
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(price_feed, false));
+    instruction
+}
+
+/// Make an express checkout instruction created by one of the above get a bundle discount
+///
+/// Appends the bundle account and the bundle member account of the merchant,
+/// followed by the subscription of the payer to the package of another merchant
+/// in the bundle and the bundle member account of that merchant.
+pub fn use_bundle(
+    mut instruction: Instruction,
+    bundle: Pubkey,
+    merchant: Pubkey,
+    subscription: Pubkey,
+    other_merchant: Pubkey,
+) -> Instruction {
+    let program_id = instruction.program_id;
+    let (member, _bump_seed) = get_bundle_member_address(&program_id, &bundle, &merchant);
+    let (other_member, _bump_seed) =
+        get_bundle_member_address(&program_id, &bundle, &other_merchant);
+    for account in [bundle, member, subscription, other_member] {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(account, false));
+    }
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above log its payment id
+///
+/// Appends the SlotHashes sysvar which the payment id is derived from, see
+/// crate::payment_id::find_payment_id to read it from the transaction logs.
+pub fn log_payment_id(mut instruction: Instruction) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(sysvar::slot_hashes::id(), false));
+    instruction
+}
+
+/// Make an instruction created by one of the above count the order token accounts
+/// that it opens or closes towards the stats account of the merchant
+///
+/// Appends the merchant stats account, which is expected to come last, so call
+/// this after any of the other functions here.
+pub fn track_token_accounts(mut instruction: Instruction, merchant: Pubkey) -> Instruction {
+    let (stats, _bump_seed) = get_merchant_stats_address(&instruction.program_id, &merchant);
+    instruction.accounts.push(AccountMeta::new(stats, false));
+    instruction
+}
+
+/// Make an instruction created by one of the above set aside or settle the
+/// sweep bounty of an order
+///
+/// Appends the sweep bounty account of the merchant, which checkouts of a
+/// merchant that has its orders swept set aside the bounty of the order in, and
+/// which the instructions that settle an order that holds a bounty (withdrawals,
+/// refunds, reclaims, cancellations, settlements and CloseOrder)
+/// return it to the program owner in.
+pub fn use_sweep_bounty(mut instruction: Instruction, merchant: Pubkey) -> Instruction {
+    let (sweep_bounty, _bump_seed) = get_sweep_bounty_address(&instruction.program_id, &merchant);
+    instruction
+        .accounts
+        .push(AccountMeta::new(sweep_bounty, false));
+    instruction
+}
+
+/// Make a checkout or withdraw instruction created by one of the above go through a store
+///
+/// Appends the store account, which checkouts record on the order and withdrawals
+/// accept the fee destination of.
+pub fn use_store(mut instruction: Instruction, merchant: Pubkey, store_id: &str) -> Instruction {
+    let (store, _bump_seed) = get_store_address(&instruction.program_id, &merchant, store_id);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(store, false));
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above check the accepted mints
+///
+/// Appends the accepted mints account of the merchant, which checkouts of
+/// merchants that restrict the mints they accept have to include.
+pub fn use_accepted_mints(mut instruction: Instruction, merchant: Pubkey) -> Instruction {
+    let (accepted_mints, _bump_seed) =
+        get_accepted_mints_address(&instruction.program_id, &merchant);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(accepted_mints, false));
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above show the buyer is not blocked
+///
+/// Appends the blocked buyer address of the buyer, which checkouts of merchants
+/// that have blocked buyers have to include.  For split checkouts and invoice
+/// payments that go through an integrator, do this before adding the integrator.
+pub fn use_blocked_buyer(
+    mut instruction: Instruction,
+    merchant: Pubkey,
+    buyer: Pubkey,
+) -> Instruction {
+    let (blocked_buyer, _bump_seed) =
+        get_blocked_buyer_address(&instruction.program_id, &merchant, &buyer);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(blocked_buyer, false));
+    instruction
+}
+
+/// Make a checkout instruction created by one of the above record a referrer
+///
+/// Appends the referrer account of the wallet, which the merchant must have taken
+/// on as a referrer with SetReferrer.
+pub fn use_referrer(mut instruction: Instruction, merchant: Pubkey, wallet: Pubkey) -> Instruction {
+    let (referrer, _bump_seed) = get_referrer_address(&instruction.program_id, &merchant, &wallet);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(referrer, false));
+    instruction
+}
+
+/// Make a withdraw instruction created by one of the above pay the referrer of the order
+///
+/// Appends the token account that the referral share goes to i.e. the associated
+/// token account of the referrer wallet for the order mint, which must exist.
+pub fn pay_referral(
+    mut instruction: Instruction,
+    wallet: Pubkey,
+    token_program_id: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    instruction.accounts.push(AccountMeta::new(
+        get_associated_token_address(&wallet, &token_program_id, &mint),
+        false,
+    ));
+    instruction
+}
+
+/// Make a withdraw instruction created by one of the above pay the payout schedule
+///
+/// Appends the associated token account of each wallet of the payout schedule
+/// of the merchant for the mint of the order.
+pub fn use_payout_schedule(
+    mut instruction: Instruction,
+    wallets: Vec<Pubkey>,
+    token_program_id: Pubkey,
+    mint: Pubkey,
+) -> Instruction {
+    for wallet in wallets {
+        instruction.accounts.push(AccountMeta::new(
+            get_associated_token_address(&wallet, &token_program_id, &mint),
+            false,
+        ));
+    }
+    instruction
+}
+
+/// Make an instruction created by one of the above and signed by the merchant
+/// owner show that the owner is active
+///
+/// Appends the recovery account of the merchant, whose inactivity timeout then
+/// starts over.
+pub fn with_merchant_recovery(mut instruction: Instruction, merchant: Pubkey) -> Instruction {
+    let (recovery, _bump_seed) = get_merchant_recovery_address(&instruction.program_id, &merchant);
+    instruction.accounts.push(AccountMeta::new(recovery, false));
+    instruction
+}
+
+/// Make an instruction created by one of the above use another token program
+///
+/// e.g. to pay with a Token-2022 mint, replaces the SPL Token program account
+/// with the given one.
+pub fn use_token_program(mut instruction: Instruction, token_program_id: Pubkey) -> Instruction {
+    for account in instruction.accounts.iter_mut() {
+        if account.pubkey == spl_token::id() {
+            account.pubkey = token_program_id;
+        }
+    }
+    instruction
+}
+
+/// Make an instruction created by one of the above that moves tokens out of an
+/// order (or into it, for installments) move them with TransferChecked
+///
+/// Appends the mint accounts of the order, which Token-2022 mints that are only
+/// moved with TransferChecked (e.g. mints with a transfer fee) require.  The
+/// mints are found by their address, so they only have to come after the
+/// accounts that are expected in order, e.g. the other order token accounts of
+/// a chain checkout order.  Without them tokens are moved with Transfer.
+pub fn use_transfer_checked(mut instruction: Instruction, mints: Vec<Pubkey>) -> Instruction {
+    for mint in mints {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(mint, false));
+    }
+    instruction
+}
+
+#[cfg(test)]
+mod test {
+    use {
         super::*,
+        crate::engine::associated_token::create_associated_token_account_instruction,
+        crate::engine::common::{
+            get_next_order_id, get_order_address, get_order_discount, get_order_fees,
+            get_order_fiat_price, get_order_tokens, set_order_fees,
+        },
         crate::engine::constants::{
-            DEFAULT_FEE_IN_LAMPORTS, INITIAL, MERCHANT, MIN_FEE_IN_LAMPORTS, PAID, PDA_SEED,
-            PROGRAM_OWNER, SPONSOR_FEE,
+            DEFAULT_FEE_IN_LAMPORTS, DEFAULT_PROGRAM_NAME, DONATION, INTEGRATOR, INTEGRATOR_FEE,
+            MAX_METADATA_LEN, MAX_OPEN_TOKEN_ACCOUNTS, MAX_OPERATORS, MERCHANT,
+            MIN_FEE_IN_LAMPORTS, PDA_SEED, PROGRAM_VERSION, RECEIPT_URI,
+            RENEWAL_INCENTIVE_IN_LAMPORTS, SCREENING_PROGRAM, SPLITS, SPONSOR_FEE, SWAP_PROGRAM,
+            SWEEP_BOUNTY_IN_LAMPORTS, TRUSTED_INSTANCES,
+        },
+        crate::engine::json::{OrderDiscount, OrderFees, OrderReferral, OrderToken},
+        crate::engine::referral::get_order_referral,
+        crate::engine::screening::ScreeningRequest,
+        crate::engine::store::get_order_store,
+        crate::engine::token::{
+            get_mint_decimals, get_token_2022_program_id, get_token_account_size,
         },
         crate::error::PaymentProcessorError,
+        crate::fee_preview::preview_checkout_fees,
         crate::instruction::PaymentProcessorInstruction,
         crate::state::{
-            MerchantAccount, OrderAccount, OrderStatus, Serdes, SubscriptionAccount,
-            SubscriptionStatus,
+            AcceptedMintsAccount, AttestationAccount, AutoDebitAccount, BlockedBuyerAccount,
+            BundleAccount, CampaignAccount, CatalogItem, CouponAccount, Discriminator,
+            FeeHistoryAccount, FeeScheduleAccount, IntegratorAccount, ItemStockAccount,
+            LinkPayerAccount, MerchantAccount, MerchantRecoveryAccount, MerchantStatsAccount,
+            MerchantTransferAccount, OrderAccount, OrderLineItem, OrderNoteAccount,
+            OrderNotesAccount, OrderStatus, PaymentLinkAccount, PriceGuaranteeAccount,
+            PriceOracleAccount, ProgramConfigAccount, ReferrerAccount, RefundCodeAccount, Serdes,
+            SettlementAccount, StoreAccount, SubscriptionAccount, SubscriptionPackage,
+            SubscriptionStatus, SweepBountyAccount, VoucherAccount,
+        },
+        crate::test_support::{
+            add_program_data, get_test_program_id, get_test_upgrade_authority, get_unix_timestamp,
+            program_test, warp_seconds,
+        },
+        crate::utils::{
+            get_amounts, get_fees, get_integrator_account_size, get_order_account_size,
         },
-        crate::utils::{get_amounts, get_order_account_size},
         assert_matches::*,
         serde_json::{json, Value},
         solana_program::{
@@ -452,182 +5397,8584 @@ mod test {
             instruction::{initialize_account, initialize_mint, mint_to},
             state::{Account as TokenAccount, Mint},
         },
+        std::collections::BTreeMap,
+        std::convert::TryInto,
         std::str::FromStr,
     };
 
-    type MerchantResult = (Pubkey, Pubkey, BanksClient, Keypair, Hash);
+    /// the program owner of the instance of the program that is being tested
+    const PROGRAM_OWNER: &str = "mosh782eoKyPca9eotWfepHVSKavjDMBjNkNE3Gge6Z";
+
+    type MerchantResult = (Pubkey, Pubkey, BanksClient, Keypair, Hash);
+
+    fn create_mint_transaction(
+        payer: &Keypair,
+        mint: &Keypair,
+        mint_authority: &Keypair,
+        recent_blockhash: Hash,
+    ) -> Transaction {
+        let instructions = [
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &mint.pubkey(),
+                Rent::default().minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_mint(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &mint_authority.pubkey(),
+                None,
+                0,
+            )
+            .unwrap(),
+        ];
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        transaction.partial_sign(&[payer, mint], recent_blockhash);
+        transaction
+    }
+
+    fn create_token_account_transaction(
+        payer: &Keypair,
+        mint: &Keypair,
+        recent_blockhash: Hash,
+        token_account: &Keypair,
+        token_account_owner: &Pubkey,
+        amount: u64,
+    ) -> Transaction {
+        let instructions = [
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &token_account.pubkey(),
+                Rent::default().minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_account(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                &mint.pubkey(),
+                token_account_owner,
+            )
+            .unwrap(),
+            mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &token_account.pubkey(),
+                token_account_owner,
+                &[&payer.pubkey()],
+                amount,
+            )
+            .unwrap(),
+        ];
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        transaction.partial_sign(&[payer, token_account], recent_blockhash);
+        transaction
+    }
+
+    async fn create_merchant_account(
+        seed: Option<String>,
+        fee: Option<u64>,
+        sponsor: Option<&Pubkey>,
+        data: Option<String>,
+    ) -> MerchantResult {
+        create_merchant_account_with_program_owner(
+            &Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+            seed,
+            fee,
+            sponsor,
+            data,
+        )
+        .await
+    }
+
+    async fn create_merchant_account_with_program_owner(
+        program_owner: &Pubkey,
+        seed: Option<String>,
+        fee: Option<u64>,
+        sponsor: Option<&Pubkey>,
+        data: Option<String>,
+    ) -> MerchantResult {
+        let (mut banks_client, payer, recent_blockhash) = program_test().start().await;
+        let merchant_acc_pubkey = register_test_merchant(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            program_owner,
+            TestMerchant {
+                seed,
+                fee,
+                sponsor,
+                data,
+            },
+        )
+        .await;
+        (
+            get_test_program_id(),
+            merchant_acc_pubkey,
+            banks_client,
+            payer,
+            recent_blockhash,
+        )
+    }
+
+    /// Same as create_merchant_account, in a test validator that can be warped
+    /// (see warp_seconds)
+    ///
+    /// The blockhash of the merchant result has to be refreshed from the context
+    /// after each warp.
+    async fn create_merchant_account_with_context(
+        seed: Option<String>,
+        fee: Option<u64>,
+        sponsor: Option<&Pubkey>,
+        data: Option<String>,
+    ) -> (ProgramTestContext, MerchantResult) {
+        create_merchant_account_with_context_and_program_owner(
+            &Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+            seed,
+            fee,
+            sponsor,
+            data,
+        )
+        .await
+    }
+
+    async fn create_merchant_account_with_context_and_program_owner(
+        program_owner: &Pubkey,
+        seed: Option<String>,
+        fee: Option<u64>,
+        sponsor: Option<&Pubkey>,
+        data: Option<String>,
+    ) -> (ProgramTestContext, MerchantResult) {
+        let mut context = program_test().start_with_context().await;
+        let payer = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+        let merchant_acc_pubkey = register_test_merchant(
+            &mut context.banks_client,
+            &payer,
+            context.last_blockhash,
+            program_owner,
+            TestMerchant {
+                seed,
+                fee,
+                sponsor,
+                data,
+            },
+        )
+        .await;
+        let merchant_result = (
+            get_test_program_id(),
+            merchant_acc_pubkey,
+            context.banks_client.clone(),
+            payer,
+            context.last_blockhash,
+        );
+        (context, merchant_result)
+    }
+
+    /// The merchant that register_test_merchant registers
+    struct TestMerchant<'a> {
+        seed: Option<String>,
+        fee: Option<u64>,
+        sponsor: Option<&'a Pubkey>,
+        data: Option<String>,
+    }
+
+    /// Initialize the program and register a merchant in the test validator
+    async fn register_test_merchant(
+        banks_client: &mut BanksClient,
+        payer: &Keypair,
+        recent_blockhash: Hash,
+        program_owner: &Pubkey,
+        merchant: TestMerchant<'_>,
+    ) -> Pubkey {
+        let TestMerchant {
+            seed,
+            fee,
+            sponsor,
+            data,
+        } = merchant;
+        let program_id = get_test_program_id();
+
+        let real_seed = match &seed {
+            None => MERCHANT,
+            Some(value) => value,
+        };
+
+        // first we create a public key for the merchant account
+        let merchant_acc_pubkey =
+            Pubkey::create_with_seed(&payer.pubkey(), real_seed, &program_id).unwrap();
+
+        // then initialize the program and call register merchant ix
+        let upgrade_authority = get_test_upgrade_authority();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner: *program_owner,
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Option::None,
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant: merchant_acc_pubkey,
+                        seed: Some(real_seed.to_string()),
+                        fee,
+                        data,
+                        sponsor,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        merchant_acc_pubkey
+    }
+
+    /// Prepare the accounts of an order of a merchant, whose order account is
+    /// derived from the order id (or, for a chain checkout, from the order
+    /// sequence of the merchant)
+    async fn prepare_order(
+        program_id: &Pubkey,
+        merchant: &Pubkey,
+        order_id: Option<&str>,
+        mint: &Pubkey,
+        banks_client: &mut BanksClient,
+    ) -> (Pubkey, Pubkey, Pubkey, MerchantAccount) {
+        let merchant_account = banks_client.get_account(*merchant).await;
+        let merchant_data = match merchant_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match MerchantAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+
+        let order_id = match order_id {
+            Some(value) => value.to_string(),
+            None => get_next_order_id(merchant_data.order_sequence).unwrap(),
+        };
+        let (order_acc_pubkey, _bump_seed) = get_order_address(program_id, merchant, &order_id);
+
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+
+        let (seller_token, _bump_seed) = Pubkey::find_program_address(
+            &[
+                &order_acc_pubkey.to_bytes(),
+                &spl_token::id().to_bytes(),
+                &mint.to_bytes(),
+            ],
+            program_id,
+        );
+
+        (order_acc_pubkey, seller_token, pda, merchant_data)
+    }
+
+    async fn create_token_account(
+        amount: u64,
+        mint_keypair: &Keypair,
+        merchant_result: &mut MerchantResult,
+    ) -> Keypair {
+        // next create token account for test
+        let buyer_token_keypair = Keypair::new();
+
+        // create and initialize mint
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_mint_transaction(
+                    &merchant_result.3,
+                    mint_keypair,
+                    &merchant_result.3,
+                    merchant_result.4
+                ))
+                .await,
+            Ok(())
+        );
+        // create and initialize buyer token account
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    mint_keypair,
+                    merchant_result.4,
+                    &buyer_token_keypair,
+                    &merchant_result.3.pubkey(),
+                    amount + 2000000,
+                ))
+                .await,
+            Ok(())
+        );
+
+        buyer_token_keypair
+    }
+
+    async fn create_order_express_checkout(
+        amount: u64,
+        order_id: &String,
+        secret: &String,
+        data: Option<String>,
+        merchant_result: &mut MerchantResult,
+        mint_keypair: &Keypair,
+    ) -> (Pubkey, Pubkey) {
+        let buyer_token_keypair = create_token_account(amount, mint_keypair, merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some(order_id),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+
+        // call express checkout ix
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                merchant_result.0,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: (&order_id).to_string(),
+                    secret: (&secret).to_string(),
+                    data,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            &mut merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        (order_acc_pubkey, seller_token)
+    }
+
+    async fn create_chain_checkout_transaction(
+        amount: u64,
+        order_items: &OrderItems,
+        data: Option<String>,
+        merchant_result: &mut MerchantResult,
+        mint_keypair: &Keypair,
+    ) -> Result<(Pubkey, Pubkey), TransportError> {
+        let buyer_token_keypair = create_token_account(amount, mint_keypair, merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            None,
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let order_items = order_items.clone();
+
+        // call chain checkout ix
+        let mut transaction = Transaction::new_with_payer(
+            &[chain_checkout(
+                merchant_result.0,
+                ChainCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    order_items,
+                    data,
+                    other_mints: vec![],
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        let _result = merchant_result.2.process_transaction(transaction).await?;
+        Ok((order_acc_pubkey, seller_token))
+    }
+
+    async fn create_order_chain_checkout(
+        amount: u64,
+        order_items: &OrderItems,
+        data: Option<String>,
+        merchant_result: &mut MerchantResult,
+        mint_keypair: &Keypair,
+    ) -> (Pubkey, Pubkey) {
+        let transaction = create_chain_checkout_transaction(
+            amount,
+            order_items,
+            data,
+            merchant_result,
+            mint_keypair,
+        )
+        .await;
+
+        assert!(transaction.is_ok());
+        transaction.unwrap()
+    }
+
+    async fn run_merchant_tests(result: MerchantResult) -> MerchantAccount {
+        let program_id = result.0;
+        let merchant = result.1;
+        let mut banks_client = result.2;
+        let payer = result.3;
+        // test contents of merchant account
+        let merchant_account = banks_client.get_account(merchant).await;
+        let merchant_account = match merchant_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(merchant_account.owner, program_id);
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data);
+        let merchant_data = match merchant_data {
+            Ok(data) => data,
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(true, merchant_data.is_initialized());
+        assert_eq!(payer.pubkey(), Pubkey::new_from_array(merchant_data.owner));
+
+        merchant_data
+    }
+
+    #[tokio::test]
+    async fn test_register_merchant() {
+        let result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let merchant_data = run_merchant_tests(result).await;
+        assert_eq!(DEFAULT_FEE_IN_LAMPORTS, merchant_data.fee);
+        assert_eq!(String::from("{}"), merchant_data.data);
+    }
+
+    #[tokio::test]
+    async fn test_register_merchant_with_seed() {
+        let result = create_merchant_account(
+            Some(String::from("mosh")),
+            Option::None,
+            Option::None,
+            Option::None,
+        )
+        .await;
+        let merchant = result.1;
+        let payer = result.3;
+        let program_id = result.0;
+        assert_eq!(
+            merchant,
+            Pubkey::create_with_seed(&payer.pubkey(), "mosh", &program_id).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    /// assert that the minimum fee is used when custom fee too low
+    async fn test_register_merchant_fee_default() {
+        let result =
+            create_merchant_account(Option::None, Some(10), Option::None, Option::None).await;
+        let merchant_data = run_merchant_tests(result).await;
+        assert_eq!(MIN_FEE_IN_LAMPORTS, merchant_data.fee);
+    }
+
+    #[tokio::test]
+    async fn test_register_merchant_with_all_stuff() {
+        let seed = String::from("mosh");
+        let sponsor_pk = Pubkey::new_unique();
+        let data = String::from(
+            r#"{"code":200,"success":true,"payload":{"features":["awesome","easyAPI","lowLearningCurve"]}}"#,
+        );
+        let datas = data.clone();
+        let result =
+            create_merchant_account(Some(seed), Some(90000), Some(&sponsor_pk), Some(data)).await;
+        let merchant_data = run_merchant_tests(result).await;
+        assert_eq!(datas, merchant_data.data);
+        assert_eq!(90000, merchant_data.fee);
+        assert_eq!(sponsor_pk, Pubkey::new_from_array(merchant_data.sponsor));
+        // just for sanity verify that you can get some of the JSON values
+        let json_value: Value = serde_json::from_str(&merchant_data.data).unwrap();
+        assert_eq!(200, json_value["code"]);
+        assert_eq!(true, json_value["success"]);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_program() {
+        let (program_id, _merchant, mut banks_client, payer, recent_blockhash) =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let (config_pubkey, _bump_seed) = get_program_config_address(&program_id);
+        let config_account = banks_client.get_account(config_pubkey).await;
+        let config_account = match config_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(config_account.owner, program_id);
+        let config_data = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+        assert!(config_data.is_initialized());
+        assert_eq!(
+            Pubkey::from_str(PROGRAM_OWNER).unwrap().to_bytes(),
+            config_data.owner
+        );
+        assert_eq!(DEFAULT_FEE_IN_LAMPORTS, config_data.default_fee);
+        assert_eq!(MIN_FEE_IN_LAMPORTS, config_data.min_fee);
+        assert_eq!(SPONSOR_FEE as u64, config_data.sponsor_fee);
+        assert_eq!(PROGRAM_VERSION, config_data.state_version);
+        assert_eq!(
+            get_test_upgrade_authority().pubkey().to_bytes(),
+            config_data.authority
+        );
+        assert_eq!(0, config_data.migration_ends);
+        assert_eq!(String::from(DEFAULT_PROGRAM_NAME), config_data.name);
+        assert_eq!(String::from("{}"), config_data.data);
+
+        // the program cannot be initialized a second time
+        let upgrade_authority = get_test_upgrade_authority();
+        let mut transaction = Transaction::new_with_payer(
+            &[initialize_program(
+                program_id,
+                InitializeProgramArgs {
+                    signer: upgrade_authority.pubkey(),
+                    program_owner: payer.pubkey(),
+                    default_fee: Some(1),
+                    min_fee: Some(1),
+                    sponsor_fee: Some(1),
+                    name: String::from("Hijacked"),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_initialize_program_not_upgrade_authority() {
+        let program_id = get_test_program_id();
+        let (mut banks_client, payer, recent_blockhash) = program_test().start().await;
+
+        // anybody but the upgrade authority is turned away, so that nobody can
+        // front-run the deployment and pick the program owner
+        let mut transaction = Transaction::new_with_payer(
+            &[initialize_program(
+                program_id,
+                InitializeProgramArgs {
+                    signer: payer.pubkey(),
+                    program_owner: payer.pubkey(),
+                    default_fee: Option::None,
+                    min_fee: Option::None,
+                    sponsor_fee: Option::None,
+                    name: String::from(DEFAULT_PROGRAM_NAME),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::NotUpgradeAuthority as u32)
+            )
+        );
+        let (config_pubkey, _bump_seed) = get_program_config_address(&program_id);
+        assert_eq!(None, banks_client.get_account(config_pubkey).await.unwrap());
+    }
+
+    async fn run_common_checkout_tests(
+        amount: u64,
+        merchant_result: &mut MerchantResult,
+        order_acc_pubkey: &Pubkey,
+        seller_account_pubkey: &Pubkey,
+        mint_keypair: &Keypair,
+    ) -> OrderAccount {
+        // program_id => merchant_result.0;
+        // merchant_account_pubkey => merchant_result.1;
+        // banks_client => merchant_result.2;
+        // payer => merchant_result.3;
+
+        let order_account = merchant_result.2.get_account(*order_acc_pubkey).await;
+        let order_account = match order_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(order_account.owner, merchant_result.0,);
+
+        let order_data = OrderAccount::unpack(&order_account.data);
+        let order_data = match order_data {
+            Ok(data) => data,
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(true, order_data.is_initialized());
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(merchant_result.1.to_bytes(), order_data.merchant);
+        assert_eq!(mint_keypair.pubkey().to_bytes(), order_data.mint);
+        assert_eq!(seller_account_pubkey.to_bytes(), order_data.token);
+        assert_eq!(merchant_result.3.pubkey().to_bytes(), order_data.payer);
+        assert_eq!(amount, order_data.expected_amount);
+        assert_eq!(amount, order_data.paid_amount);
+        // SPL Token mints do not withhold transfer fees
+        assert_eq!(0, order_data.transfer_fee);
+        assert_eq!(
+            order_account.lamports,
+            Rent::default().minimum_balance(get_order_account_size(
+                &order_data.order_id,
+                &order_data.secret,
+                &order_data.data,
+            ))
+        );
+
+        // test contents of seller token account
+        let seller_token_account = merchant_result.2.get_account(*seller_account_pubkey).await;
+        let seller_token_account = match seller_token_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        let seller_account_data = spl_token::state::Account::unpack(&seller_token_account.data);
+        let seller_account_data = match seller_account_data {
+            Ok(data) => data,
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &merchant_result.0);
+        assert_eq!(amount, seller_account_data.amount);
+        assert_eq!(pda, seller_account_data.owner);
+        assert_eq!(mint_keypair.pubkey(), seller_account_data.mint);
+
+        // test that sponsor was saved okay
+        let merchant_account = merchant_result.2.get_account(merchant_result.1).await;
+        let merchant_data = match merchant_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match MerchantAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+
+        let program_owner_key = Pubkey::from_str(PROGRAM_OWNER).unwrap();
+        let sponsor = Pubkey::new_from_array(merchant_data.sponsor);
+
+        let program_owner_account = merchant_result.2.get_account(program_owner_key).await;
+        let program_owner_account = match program_owner_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+
+        if sponsor == program_owner_key {
+            // test contents of program owner account
+            assert_eq!(merchant_data.fee, program_owner_account.lamports);
+            assert_eq!(
+                Some(OrderFees {
+                    program_owner: merchant_data.fee,
+                    sponsor: 0
+                }),
+                get_order_fees(&order_data)
+            );
+        } else {
+            // test contents of program owner account and sponsor account
+            let (program_owner_fee, sponsor_fee) =
+                get_amounts(merchant_data.fee, SPONSOR_FEE).unwrap();
+            let sponsor_account = merchant_result.2.get_account(sponsor).await;
+            let sponsor_account = match sponsor_account {
+                Ok(data) => match data {
+                    None => panic!("Oo"),
+                    Some(value) => value,
+                },
+                Err(error) => panic!("Problem: {:?}", error),
+            };
+            assert_eq!(program_owner_fee, program_owner_account.lamports);
+            assert_eq!(sponsor_fee, sponsor_account.lamports);
+            assert_eq!(
+                Some(OrderFees {
+                    program_owner: program_owner_fee,
+                    sponsor: sponsor_fee
+                }),
+                get_order_fees(&order_data)
+            );
+        }
+
+        // test that the fees charged are the ones that were previewed
+        let (config, _bump_seed) = get_program_config_address(&merchant_result.0);
+        let config_account = merchant_result.2.get_account(config).await;
+        let config_data =
+            ProgramConfigAccount::unpack(&config_account.unwrap().unwrap().data).unwrap();
+        let preview = preview_checkout_fees(&merchant_data, &config_data, false).unwrap();
+        assert_eq!(Some(preview.order_fees()), get_order_fees(&order_data));
+        assert_eq!(preview.program_owner_fee, program_owner_account.lamports);
+        assert_eq!(merchant_data.fee, preview.total_fee);
+
+        order_data
+    }
+
+    /// The order that a checkout is expected to create
+    struct ExpectedOrder {
+        order_id: String,
+        secret: String,
+        data: Option<String>,
+    }
+
+    async fn run_checkout_tests(
+        amount: u64,
+        expected: ExpectedOrder,
+        merchant_result: &mut MerchantResult,
+        order_acc_pubkey: &Pubkey,
+        seller_account_pubkey: &Pubkey,
+        mint_keypair: &Keypair,
+    ) {
+        let ExpectedOrder {
+            order_id,
+            secret,
+            data,
+        } = expected;
+        let order_data = run_common_checkout_tests(
+            amount,
+            merchant_result,
+            order_acc_pubkey,
+            seller_account_pubkey,
+            mint_keypair,
+        )
+        .await;
+
+        let data_string = match data {
+            None => String::from("{}"),
+            Some(value) => value,
+        };
+        let fees = get_order_fees(&order_data).unwrap();
+        assert_eq!(order_id, order_data.order_id);
+        assert_eq!(secret, order_data.secret);
+        assert_eq!(
+            set_order_fees(data_string, fees.program_owner, fees.sponsor),
+            order_data.data
+        );
+    }
+
+    async fn run_chain_checkout_tests(
+        amount: u64,
+        order_items: &OrderItems,
+        data: Option<String>,
+        merchant_result: &mut MerchantResult,
+        order_acc_pubkey: &Pubkey,
+        seller_account_pubkey: &Pubkey,
+        mint_keypair: &Keypair,
+    ) {
+        // test contents of order account
+        let order_data = run_common_checkout_tests(
+            amount,
+            merchant_result,
+            order_acc_pubkey,
+            seller_account_pubkey,
+            mint_keypair,
+        )
+        .await;
+        let fees = get_order_fees(&order_data).unwrap();
+        let data_string = match data {
+            None => String::from("{}"),
+            Some(value) => value,
+        };
+        assert_eq!(
+            set_order_fees(data_string, fees.program_owner, fees.sponsor),
+            order_data.data
+        );
+        let items: Vec<OrderLineItem> = order_items
+            .iter()
+            .map(|(name, quantity)| OrderLineItem {
+                name: name.clone(),
+                quantity: *quantity,
+            })
+            .collect();
+        assert_eq!(items, order_data.items);
+    }
+
+    #[tokio::test]
+    async fn test_chain_checkout() {
+        let mint_keypair = Keypair::new();
+        let amount: u64 = 2000000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+        order_items.insert("3".to_string(), 1);
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_key}"}},
+            "2": {{"price": 3000000, "mint": "{mint_key}"}},
+            "3": {{"price": 4000000, "mint": "{mint_key}"}},
+            "4": {{"price": 4000000, "mint": "{mint_key}"}},
+            "5": {{"price": 4000000, "mint": "{mint_key}"}}
+        }}"#,
+            mint_key = mint_keypair.pubkey()
+        );
+
+        let mut merchant_result = create_merchant_account(
+            Some("chain".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        run_chain_checkout_tests(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_keypair,
+        )
+        .await;
+    }
+
+    #[cfg(feature = "test-bpf")]
+    #[tokio::test]
+    /// a chain checkout fits in CHAIN_CHECKOUT_COMPUTE_BUDGET when the program
+    /// runs as BPF, even for a merchant whose catalog is still JSON
+    async fn test_chain_checkout_compute_budget() {
+        let mint_keypair = Keypair::new();
+        let amount: u64 = 2000000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+        order_items.insert("3".to_string(), 2);
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_key}"}},
+            "2": {{"price": 3000000, "mint": "{mint_key}"}},
+            "3": {{"price": 4000000, "mint": "{mint_key}"}},
+            "4": {{"price": 4000000, "mint": "{mint_key}"}},
+            "5": {{"price": 4000000, "mint": "{mint_key}"}}
+        }}"#,
+            mint_key = mint_keypair.pubkey()
+        );
+
+        let mut program_test = program_test();
+        program_test.set_bpf_compute_max_units(crate::test_support::CHAIN_CHECKOUT_COMPUTE_BUDGET);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let merchant = register_test_merchant(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+            TestMerchant {
+                seed: Some("chain".to_string()),
+                fee: Option::None,
+                sponsor: Option::None,
+                data: Some(merchant_data),
+            },
+        )
+        .await;
+        let mut merchant_result = (
+            get_test_program_id(),
+            merchant,
+            banks_client,
+            payer,
+            recent_blockhash,
+        );
+        // fails if the checkout runs out of compute units
+        create_order_chain_checkout(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_chain_checkout_with_data() {
+        let mint_keypair = Keypair::new();
+        let amount: u64 = 2000000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_key}"}},
+            "2": {{"price": 3000000, "mint": "{mint_key}"}}
+        }}"#,
+            mint_key = mint_keypair.pubkey()
+        );
+
+        let mut merchant_result = create_merchant_account(
+            Some("chain2".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
+            amount,
+            &order_items,
+            Some(String::from(r#"{"foo": "bar"}"#)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        run_chain_checkout_tests(
+            amount,
+            &order_items,
+            Some(String::from(r#"{"foo": "bar"}"#)),
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_keypair,
+        )
+        .await;
+    }
+
+    async fn chain_checkout_failing_test_helper(
+        order_item_id: u8,
+        paid_amount: u64,
+        input_mint: &Keypair,
+        registered_item_id: u8,
+        expected_amount: u64,
+        registered_mint: &Keypair,
+        expected_error: InstructionError,
+    ) -> bool {
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert(format!("{}", order_item_id), 1);
+
+        let mut merchant_data = String::from("5");
+
+        if registered_item_id != 0 {
+            merchant_data = format!(
+                r#"{{"{registered_item_id}": {{"price": {expected_amount}, "mint": "{mint_key}"}}}}"#,
+                registered_item_id = registered_item_id,
+                expected_amount = expected_amount,
+                mint_key = registered_mint.pubkey()
+            );
+        }
+
+        let mut merchant_result = create_merchant_account(
+            Some("test".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+
+        match create_chain_checkout_transaction(
+            paid_amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            input_mint,
+        )
+        .await
+        {
+            Err(error) => {
+                assert_eq!(
+                    error.unwrap(),
+                    TransactionError::InstructionError(0, expected_error)
+                );
+            }
+            Ok(_value) => panic!("Oo... we expect an error"),
+        };
+
+        true
+    }
+
+    #[tokio::test]
+    async fn test_chain_checkout_failure() {
+        let mint_a = Keypair::new();
+        let mint_b = Keypair::new();
+
+        // insufficient funds
+        assert!(
+            chain_checkout_failing_test_helper(
+                1,       // id of item being ordered
+                20,      // amount to pay
+                &mint_a, // mint being used for payment
+                1,       // registered item id
+                30,      // expected amount
+                &mint_a, // expected mint
+                InstructionError::Custom(PaymentProcessorError::InsufficientPaid as u32)
+            )
+            .await
+        );
+
+        // wrong item id in order
+        assert!(
+            chain_checkout_failing_test_helper(
+                7,       // id of item being ordered
+                20,      // amount to pay
+                &mint_a, // mint being used for payment
+                1,       // registered item id
+                30,      // expected amount
+                &mint_a, // expected mint
+                InstructionError::Custom(PaymentProcessorError::InvalidOrderData as u32)
+            )
+            .await
+        );
+
+        // wrong mint in order
+        assert!(
+            chain_checkout_failing_test_helper(
+                1,       // id of item being ordered
+                20,      // amount to pay
+                &mint_a, // mint being used for payment
+                1,       // registered item id
+                20,      // expected amount
+                &mint_b, // expected mint
+                InstructionError::Custom(PaymentProcessorError::WrongMint as u32)
+            )
+            .await
+        );
+
+        // invalid merchant data
+        assert!(
+            chain_checkout_failing_test_helper(
+                1,       // id of item being ordered
+                20,      // amount to pay
+                &mint_a, // mint being used for payment
+                0,       // registered item id
+                20,      // expected amount
+                &mint_a, // expected mint
+                InstructionError::Custom(PaymentProcessorError::InvalidMerchantData as u32)
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_checkout_collection_gated() {
+        let mint_a = Keypair::new();
+        let mint_b = Keypair::new();
+        let amount: u64 = 2000000;
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_a}", "collection": "{collection}"}},
+            "2": {{"price": 2000000, "mint": "{mint_b}", "collection": "{collection}", "holder_discount": 1000}}
+        }}"#,
+            mint_a = mint_a.pubkey(),
+            mint_b = mint_b.pubkey(),
+            collection = Pubkey::new_unique()
+        );
+        let mut merchant_result = create_merchant_account(
+            Some("chain".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+
+        // a gated item is not sold to a buyer who shows no NFT of the collection
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+        match create_chain_checkout_transaction(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_a,
+        )
+        .await
+        {
+            Err(error) => {
+                assert_eq!(
+                    error.unwrap(),
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(PaymentProcessorError::InvalidOrderData as u32)
+                    )
+                );
+            }
+            Ok(_value) => panic!("Oo... we expect an error"),
+        };
+
+        // an item with a holder discount is sold to anyone at the full price
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("2".to_string(), 1);
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_b,
+        )
+        .await;
+        run_chain_checkout_tests(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_b,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout() {
+        let amount: u64 = 2000000000;
+        let order_id = String::from("1337");
+        let secret = String::from("hunter2");
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_express_checkout(
+            amount,
+            &order_id,
+            &secret,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        run_checkout_tests(
+            amount,
+            ExpectedOrder {
+                order_id,
+                secret,
+                data: Option::None,
+            },
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_keypair,
+        )
+        .await;
+    }
+
+    /// Try an express checkout that pays its fees to the given program owner and sponsor
+    async fn fee_accounts_checkout(
+        merchant_result: &mut MerchantResult,
+        program_owner: Pubkey,
+        sponsor: Option<Pubkey>,
+    ) -> Result<(), TransportError> {
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(2000000, &mint_keypair, merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("fees"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                merchant_result.0,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner,
+                    sponsor: sponsor.unwrap_or(Pubkey::new_from_array(merchant_data.sponsor)),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000000,
+                    tip: 0,
+                    order_id: String::from("fees"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        merchant_result.2.process_transaction(transaction).await
+    }
+
+    #[tokio::test]
+    /// the processing fee cannot be redirected by substituting the fee accounts
+    async fn test_checkout_fee_accounts() {
+        let sponsor_pk = Pubkey::new_unique();
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Some(&sponsor_pk), Option::None)
+                .await;
+        let attacker = Pubkey::new_unique();
+
+        assert_eq!(
+            fee_accounts_checkout(&mut merchant_result, attacker, Option::None)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongProgramOwner as u32)
+            )
+        );
+        assert_eq!(
+            fee_accounts_checkout(
+                &mut merchant_result,
+                Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                Some(attacker)
+            )
+            .await
+            .unwrap_err()
+            .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongSponsor as u32)
+            )
+        );
+        assert_matches!(
+            fee_accounts_checkout(
+                &mut merchant_result,
+                Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                Option::None
+            )
+            .await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_order_sequence() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mut orders = vec![];
+        for order_id in ["1", "2"].iter() {
+            let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+                2000000,
+                &String::from(*order_id),
+                &String::from(""),
+                Option::None,
+                &mut merchant_result,
+                &Keypair::new(),
+            )
+            .await;
+            orders.push(order_acc_pubkey);
+        }
+        // orders are numbered in the order they were created
+        for (index, order_acc_pubkey) in orders.iter().enumerate() {
+            let order_account = merchant_result
+                .2
+                .get_account(*order_acc_pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+            assert_eq!(index as u64 + 1, order_data.sequence);
+        }
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant_result.1)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(2, merchant_data.order_sequence);
+    }
+
+    #[tokio::test]
+    /// test checkout with all merchant options
+    async fn test_express_checkout_with_all_options() {
+        let sponsor_pk = Pubkey::new_unique();
+        let amount: u64 = 2000000000;
+        let order_id = String::from("123-SQT-MX");
+        let secret = String::from("supersecret");
+        let mut merchant_result = create_merchant_account(
+            Some(String::from("Oo")),
+            Some(123456),
+            Some(&sponsor_pk),
+            Some(String::from(r#"{"foo": "bar"}"#)),
+        )
+        .await;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_express_checkout(
+            amount,
+            &order_id,
+            &secret,
+            Some(String::from(r#"{"a": "b"}"#)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        run_checkout_tests(
+            amount,
+            ExpectedOrder {
+                order_id,
+                secret,
+                data: Some(String::from(r#"{"a": "b"}"#)),
+            },
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_keypair,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_with_tip() {
+        let amount: u64 = 2000000;
+        let tip: u64 = 150000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("TIP-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let checkout_transaction = |tip: u64| {
+            let mut transaction = Transaction::new_with_payer(
+                &[express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer.pubkey(),
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount,
+                        tip,
+                        order_id: String::from("TIP-1"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                )],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[payer], recent_blockhash);
+            transaction
+        };
+
+        // a tip that takes the payment past u64::MAX does not wrap around to a free checkout
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(checkout_transaction(u64::MAX - amount + 1))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::AmountOverflow as u32)
+            )
+        );
+
+        // call express checkout ix with a tip
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(checkout_transaction(tip))
+                .await,
+            Ok(())
+        );
+
+        // the tip is recorded separately from the amount
+        let order_account = merchant_result.2.get_account(order_acc_pubkey).await;
+        let order_data = match order_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match OrderAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(tip, order_data.tip);
+        // but paid into the same token account
+        let seller_token_account = merchant_result.2.get_account(seller_token).await;
+        let seller_token_data = match seller_token_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match TokenAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(amount + tip, seller_token_data.amount);
+    }
+
+    #[tokio::test]
+    async fn test_split_checkout() {
+        let amount: u64 = 1000001;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, _seller_token, _pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("SPLIT-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        // create the marketplace and vendor token accounts
+        let marketplace_token_keypair = Keypair::new();
+        let vendor_token_keypair = Keypair::new();
+        for token_keypair in [&marketplace_token_keypair, &vendor_token_keypair].iter() {
+            assert_matches!(
+                merchant_result
+                    .2
+                    .process_transaction(create_token_account_transaction(
+                        &merchant_result.3,
+                        &mint_keypair,
+                        merchant_result.4,
+                        token_keypair,
+                        &Pubkey::new_unique(),
+                        0,
+                    ))
+                    .await,
+                Ok(())
+            );
+        }
+
+        // the splits must add up to 10000 basis points
+        let mut transaction = Transaction::new_with_payer(
+            &[split_checkout(
+                merchant_result.0,
+                SplitCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    recipients: vec![
+                        (marketplace_token_keypair.pubkey(), 5000),
+                        (vendor_token_keypair.pubkey(), 4000),
+                    ],
+                    integrator: Option::None,
+                    amount,
+                    order_id: String::from("SPLIT-1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidSplit as u32)
+            )
+        );
+
+        // call split checkout ix
+        let mut transaction = Transaction::new_with_payer(
+            &[split_checkout(
+                merchant_result.0,
+                SplitCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    recipients: vec![
+                        (marketplace_token_keypair.pubkey(), 7000),
+                        (vendor_token_keypair.pubkey(), 3000),
+                    ],
+                    integrator: Option::None,
+                    amount,
+                    order_id: String::from("SPLIT-1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the order is saved as already withdrawn
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        let json_data: Value = serde_json::from_str(&order_data.data).unwrap();
+        assert_eq!(
+            json!([
+                [marketplace_token_keypair.pubkey().to_string(), 7000],
+                [vendor_token_keypair.pubkey().to_string(), 3000]
+            ]),
+            json_data[SPLITS]
+        );
+        // the remainder goes to the first recipient
+        for (token_keypair, expected_amount) in [
+            (&marketplace_token_keypair, 700001),
+            (&vendor_token_keypair, 300000),
+        ]
+        .iter()
+        {
+            let token_account = merchant_result
+                .2
+                .get_account(token_keypair.pubkey())
+                .await
+                .unwrap()
+                .unwrap();
+            let token_data = TokenAccount::unpack(&token_account.data).unwrap();
+            assert_eq!(*expected_amount, token_data.amount);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_sol() {
+        let amount: u64 = 1000000;
+        let order_id = String::from("SOL-1");
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let native_mint = spl_token::native_mint::id();
+        let wsol_token_keypair = Keypair::new();
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some(&order_id),
+            &native_mint,
+            &mut merchant_result.2,
+        )
+        .await;
+
+        // call express checkout sol ix
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout_sol(
+                merchant_result.0,
+                ExpressCheckoutSolArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    wsol_token: wsol_token_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: order_id.clone(),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(
+            &[&merchant_result.3, &wsol_token_keypair],
+            merchant_result.4,
+        );
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the temporary WSOL account was closed
+        assert!(merchant_result
+            .2
+            .get_account(wsol_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .is_none());
+        // the order was paid in wrapped SOL
+        let order_account = merchant_result.2.get_account(order_acc_pubkey).await;
+        let order_data = match order_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match OrderAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(native_mint.to_bytes(), order_data.mint);
+        assert_eq!(order_id, order_data.order_id);
+        assert_eq!(amount, order_data.paid_amount);
+        let seller_token_account = merchant_result.2.get_account(seller_token).await;
+        let seller_token_data = match seller_token_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match TokenAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(amount, seller_token_data.amount);
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_with_integrator() {
+        let amount: u64 = 2000000000;
+        let app_id = String::from("phantom-wallet");
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let (integrator, _bump_seed) =
+            Pubkey::find_program_address(&[INTEGRATOR.as_bytes(), app_id.as_bytes()], &program_id);
+
+        // call register integrator ix
+        let mut transaction = Transaction::new_with_payer(
+            &[register_integrator(
+                program_id,
+                merchant_result.3.pubkey(),
+                integrator,
+                app_id.clone(),
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // call express checkout ix with the integrator
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant_result.1,
+            Some("INT-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Some(integrator),
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("INT-1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the fee was shared between the integrator and the program owner
+        let (program_owner_fee, integrator_fee) =
+            get_amounts(merchant_data.fee, INTEGRATOR_FEE).unwrap();
+        let integrator_rent = Rent::default().minimum_balance(get_integrator_account_size(&app_id));
+        let integrator_account = merchant_result.2.get_account(integrator).await;
+        let integrator_account = match integrator_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => value,
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(
+            integrator_rent + integrator_fee,
+            integrator_account.lamports
+        );
+        let integrator_data = match IntegratorAccount::unpack(&integrator_account.data) {
+            Ok(data) => data,
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(app_id, integrator_data.app_id);
+        assert_eq!(
+            merchant_result.3.pubkey(),
+            Pubkey::new_from_array(integrator_data.owner)
+        );
+        let program_owner_account = merchant_result
+            .2
+            .get_account(Pubkey::from_str(PROGRAM_OWNER).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(program_owner_fee, program_owner_account.unwrap().lamports);
+
+        // call withdraw integrator fees ix
+        let destination = Pubkey::new_unique();
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_integrator_fees(
+                program_id,
+                merchant_result.3.pubkey(),
+                integrator,
+                destination,
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let destination_account = merchant_result.2.get_account(destination).await.unwrap();
+        assert_eq!(integrator_fee, destination_account.unwrap().lamports);
+        let integrator_account = merchant_result.2.get_account(integrator).await.unwrap();
+        assert_eq!(integrator_rent, integrator_account.unwrap().lamports);
+    }
+
+    async fn run_order_token_account_refund_tests(
+        order_payment_token_acc: &Option<solana_sdk::account::Account>,
+        account_to_receive_sol_refund_before: &Option<solana_sdk::account::Account>,
+        account_to_receive_sol_refund_after: &Option<solana_sdk::account::Account>,
+        previous_order_account: &Option<solana_sdk::account::Account>,
+    ) {
+        // order token account is closed
+        assert!(order_payment_token_acc.is_none());
+        let order_account_rent = match previous_order_account {
+            None => 0,
+            Some(account) => account.lamports,
+        };
+        match account_to_receive_sol_refund_before {
+            None => panic!("Oo"),
+            Some(account_before) => match account_to_receive_sol_refund_after {
+                None => panic!("Oo"),
+                Some(account_after) => {
+                    // the before balance has increased by the rent amount of both token and order account
+                    assert_eq!(
+                        account_before.lamports,
+                        account_after.lamports
+                            - (Rent::default().minimum_balance(TokenAccount::LEN)
+                                + order_account_rent)
+                    );
+                }
+            },
+        };
+    }
+
+    async fn withdraw_helper(
+        amount: u64,
+        close_order_account: bool,
+    ) -> (
+        BanksClient,
+        Option<solana_sdk::account::Account>,
+        Pubkey,
+        Pubkey,
+        Option<solana_sdk::account::Account>,
+        Option<solana_sdk::account::Account>,
+    ) {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let merchant_token_keypair = Keypair::new();
+        let order_id = String::from("PD17CUSZ75");
+        let secret = String::from("i love oov");
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+            amount,
+            &order_id,
+            &secret,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant_account_pubkey = merchant_result.1;
+        let mut banks_client = merchant_result.2;
+        let payer = merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+
+        // create and initialize merchant token account
+        assert_matches!(
+            banks_client
+                .process_transaction(create_token_account_transaction(
+                    &payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &merchant_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        let (order_payment_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
+            &[
+                &order_acc_pubkey.to_bytes(),
+                &spl_token::id().to_bytes(),
+                &mint_keypair.pubkey().to_bytes(),
+            ],
+            &program_id,
+        );
+
+        let account_to_receive_sol_refund_pubkey = Pubkey::from_str(PROGRAM_OWNER).unwrap();
+        let account_to_receive_sol_refund_before = banks_client
+            .get_account(account_to_receive_sol_refund_pubkey)
+            .await
+            .unwrap();
+
+        let previous_order_account = banks_client.get_account(order_acc_pubkey).await;
+        let previous_order_account = match previous_order_account {
+            Err(error) => panic!("Problem: {:?}", error),
+            Ok(value) => value,
+        };
+
+        // call withdraw ix
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_account_pubkey,
+                    order_payment_token: order_payment_token_acc_pubkey,
+                    merchant_token: merchant_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: account_to_receive_sol_refund_pubkey,
+                    pda,
+                    subscription: Option::None,
+                    close_order_account,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+
+        // test contents of merchant token account
+        let merchant_token_account = banks_client
+            .get_account(merchant_token_keypair.pubkey())
+            .await;
+        let merchant_account_data = match merchant_token_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match spl_token::state::Account::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(amount, merchant_account_data.amount);
+
+        let order_account = banks_client.get_account(order_acc_pubkey).await;
+        let order_account = match order_account {
+            Err(error) => panic!("Problem: {:?}", error),
+            Ok(value) => value,
+        };
+
+        (
+            banks_client,
+            order_account,
+            order_payment_token_acc_pubkey,
+            account_to_receive_sol_refund_pubkey,
+            account_to_receive_sol_refund_before,
+            previous_order_account,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_withdraw() {
+        let amount: u64 = 1234567890;
+        let (
+            mut banks_client,
+            order_account,
+            order_payment_token_acc_pubkey,
+            account_to_receive_sol_refund_pubkey,
+            account_to_receive_sol_refund_before,
+            _previous_order_account,
+        ) = withdraw_helper(amount, false).await;
+        // test contents of order account
+        let order_data = match order_account.clone() {
+            None => panic!("Oo"),
+            Some(value) => match OrderAccount::unpack(&value.data) {
+                Ok(data) => data,
+                Err(error) => panic!("Problem: {:?}", error),
+            },
+        };
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+        assert_eq!(amount, order_data.expected_amount);
+        assert_eq!(amount, order_data.paid_amount);
+        // test that token account was closed and that the refund was sent to expected account
+        let order_payment_token_acc = banks_client
+            .get_account(order_payment_token_acc_pubkey)
+            .await
+            .unwrap();
+        let account_to_receive_sol_refund_after = banks_client
+            .get_account(account_to_receive_sol_refund_pubkey)
+            .await
+            .unwrap();
+        run_order_token_account_refund_tests(
+            &order_payment_token_acc,
+            &account_to_receive_sol_refund_before,
+            &account_to_receive_sol_refund_after,
+            &Option::None,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_close_order_account() {
+        let amount: u64 = 10001;
+        let (
+            mut banks_client,
+            order_account,
+            order_payment_token_acc_pubkey,
+            account_to_receive_sol_refund_pubkey,
+            account_to_receive_sol_refund_before,
+            previous_order_account,
+        ) = withdraw_helper(amount, true).await;
+        // test closure of order account
+        assert!(order_account.is_none());
+        // test that accounts were closed and that refunds sent to expected account
+        let order_payment_token_acc = banks_client
+            .get_account(order_payment_token_acc_pubkey)
+            .await
+            .unwrap();
+        let account_to_receive_sol_refund_after = banks_client
+            .get_account(account_to_receive_sol_refund_pubkey)
+            .await
+            .unwrap();
+        run_order_token_account_refund_tests(
+            &order_payment_token_acc,
+            &account_to_receive_sol_refund_before,
+            &account_to_receive_sol_refund_after,
+            &previous_order_account,
+        )
+        .await;
+    }
+
+    async fn run_subscribe_tests(
+        amount: u64,
+        package_name: &str,
+        merchant_data: &str,
+        mint_keypair: &Keypair,
+    ) -> (
+        Result<(), TransportError>,
+        Option<(SubscriptionAccount, MerchantResult, Pubkey, Pubkey)>,
+    ) {
+        let mut merchant_result = create_merchant_account(
+            Some(String::from("subscription test")),
+            Option::None,
+            Option::None,
+            Some(String::from(merchant_data)),
+        )
+        .await;
+
+        let (subscription, _bump_seed) = Pubkey::find_program_address(
+            &[
+                &merchant_result.3.pubkey().to_bytes(), // payer
+                &merchant_result.1.to_bytes(),          // merchant
+                package_name.as_bytes(),
+            ],
+            &merchant_result.0, // program id
+        );
+
+        let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription.to_string());
+
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+            amount,
+            &String::from(package_name),
+            &String::from(""),
+            Some(order_data),
+            &mut merchant_result,
+            mint_keypair,
+        )
+        .await;
+
+        let program_id = merchant_result.0;
+        let merchant_account_pubkey = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+
+        // call subscribe ix
+        let mut transaction = Transaction::new_with_payer(
+            &[subscribe(
+                program_id,
+                SubscribeArgs {
+                    signer: payer.pubkey(),
+                    subscription,
+                    merchant: merchant_account_pubkey,
+                    order: order_acc_pubkey,
+                    name: String::from(package_name),
+                    data: Option::None,
+                    seats: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+
+        let result = merchant_result.2.process_transaction(transaction).await;
+
+        if result.is_ok() {
+            // test contents of subscription token account
+            let subscription_account = &merchant_result.2.get_account(subscription).await;
+            let subscription_data = match subscription_account {
+                Ok(data) => match data {
+                    None => panic!("Oo"),
+                    Some(value) => match SubscriptionAccount::unpack(&value.data) {
+                        Ok(data) => data,
+                        Err(error) => panic!("Problem: {:?}", error),
+                    },
+                },
+                Err(error) => panic!("Problem: {:?}", error),
+            };
+            assert_eq!(
+                (SubscriptionStatus::Initialized as u8),
+                subscription_data.status
+            );
+            assert_eq!(String::from(package_name), subscription_data.name);
+            assert_eq!(
+                payer.pubkey(),
+                Pubkey::new_from_array(subscription_data.owner)
+            );
+            assert_eq!(
+                merchant_account_pubkey,
+                Pubkey::new_from_array(subscription_data.merchant)
+            );
+            assert_eq!(String::from("{}"), subscription_data.data);
+
+            return (
+                result,
+                Some((
+                    subscription_data,
+                    merchant_result,
+                    order_acc_pubkey,
+                    subscription,
+                )),
+            );
+        }
+
+        (result, Option::None)
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let mint_keypair = Keypair::new();
+        let packages = format!(
+            r#"{{"packages":[{{"name":"basic","price":1000000,"duration":720,"mint":"{mint}"}},{{"name":"annual","price":11000000,"duration":262800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string()
+        );
+        assert!(
+            (run_subscribe_tests(1000000, "basic", &packages, &mint_keypair).await)
+                .0
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    /// test what happens when there are 0 packages
+    async fn test_subscribe_no_packages() {
+        let mint_keypair = Keypair::new();
+        let packages = r#"{"packages":[]}"#;
+        assert!(
+            (run_subscribe_tests(1337, "basic", packages, &mint_keypair).await)
+                .0
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    /// test what happens when there are duplicate packages
+    async fn test_subscribe_duplicate_packages() {
+        let mint_keypair = Keypair::new();
+        let packages = format!(
+            r#"{{"packages":[{{"name":"a","price":100,"duration":720,"mint":"{mint}"}},{{"name":"a","price":222,"duration":262800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string()
+        );
+
+        let result = run_subscribe_tests(100, "a", &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+
+        let _ = match result.1 {
+            None => (),
+            Some(value) => {
+                let subscription_account = value.0;
+                // use the duration of the first package in the array to check
+                // that the subscription was created using the first array element
+                assert_eq!(
+                    720,
+                    subscription_account.period_end - subscription_account.period_start
+                );
+                ()
+            }
+        };
+    }
+
+    #[tokio::test]
+    /// test what happens when the package is not found
+    async fn test_subscribe_package_not_found() {
+        let mint_keypair = Keypair::new();
+        let packages = format!(
+            r#"{{"packages":[{{"name":"a","price":100,"duration":720,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string()
+        );
+        assert!(
+            (run_subscribe_tests(100, "zz", &packages, &mint_keypair).await)
+                .0
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    /// test what happens when there is no packages object in the JSON
+    async fn test_subscribe_no_packages_json() {
+        let mint_keypair = Keypair::new();
+        assert!(
+            (run_subscribe_tests(250, "package", r#"{}"#, &mint_keypair).await)
+                .0
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    /// test what happens when there is no valid JSON
+    async fn test_subscribe_no_json() {
+        let mint_keypair = Keypair::new();
+        assert!(
+            (run_subscribe_tests(250, "package", "what is?", &mint_keypair).await)
+                .0
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    /// test what happens when the amount paid is insufficient
+    async fn test_subscribe_not_enough_paid() {
+        let mint_keypair = Keypair::new();
+        let packages = format!(
+            r#"{{"packages":[{{"name":"basic","price":100,"duration":720,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string()
+        );
+        assert!(
+            (run_subscribe_tests(10, "Netflix-basic", &packages, &mint_keypair).await)
+                .0
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscription_renewal() {
+        let mint_keypair = Keypair::new();
+        let name = "short";
+        // create a package that lasts only 1 second
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":999999,"duration":1,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string(),
+            name = name
+        );
+        let result = run_subscribe_tests(1000000, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let subscribe_result = result.1;
+        let _ = match subscribe_result {
+            None => (),
+            Some(mut subscribe_result) => {
+                let subscription_account = subscribe_result.0;
+                let subscription = subscribe_result.3; // the subscription pubkey
+
+                let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription.to_string());
+
+                let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+                    999999 * 600,
+                    &format!("{name}", name = name),
+                    &String::from(""),
+                    Some(order_data),
+                    &mut subscribe_result.1,
+                    &mint_keypair,
+                )
+                .await;
+
+                // call subscription  ix
+                let mut transaction = Transaction::new_with_payer(
+                    &[renew_subscription(
+                        subscribe_result.1 .0,          // program_id,
+                        subscribe_result.1 .3.pubkey(), // payer,
+                        subscription,
+                        Pubkey::new_from_array(subscription_account.merchant),
+                        order_acc_pubkey,
+                        Option::None,
+                        600,
+                    )],
+                    Some(&subscribe_result.1 .3.pubkey()),
+                );
+                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
+                assert_matches!(
+                    subscribe_result.1 .2.process_transaction(transaction).await,
+                    Ok(())
+                );
+
+                // assert that period end has been updated
+                let subscription_account2 = subscribe_result.1 .2.get_account(subscription).await;
+                let subscription_account2 = match subscription_account2 {
+                    Ok(data) => match data {
+                        None => panic!("Oo"),
+                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
+                            Ok(data) => data,
+                            Err(error) => panic!("Problem: {:?}", error),
+                        },
+                    },
+                    Err(error) => panic!("Problem: {:?}", error),
+                };
+                assert_eq!(
+                    // the new period_end is equal to the old period_end + (1 * 600)
+                    subscription_account.period_end + 600,
+                    subscription_account2.period_end
+                );
+
+                return ();
+            }
+        };
+    }
+
+    async fn run_subscription_withdrawal_tests(
+        name: &str,
+        packages: &str,
+        mint_keypair: &Keypair,
+        error_expected: bool,
+    ) {
+        // create the subscription
+        let result = run_subscribe_tests(1000000, name, packages, mint_keypair).await;
+        assert!(result.0.is_ok());
+        let subscribe_result = result.1;
+        let _ = match subscribe_result {
+            None => (),
+            Some(mut subscribe_result) => {
+                let subscription = subscribe_result.3; // the subscription pubkey
+                let order_acc_pubkey = subscribe_result.2;
+                let merchant_token_keypair = Keypair::new();
+                let (pda, _bump_seed) =
+                    Pubkey::find_program_address(&[PDA_SEED], &subscribe_result.1 .0);
+
+                // create and initialize merchant token account
+                assert_matches!(
+                    subscribe_result
+                        .1
+                         .2
+                        .process_transaction(create_token_account_transaction(
+                            &subscribe_result.1 .3,
+                            mint_keypair,
+                            subscribe_result.1 .4, // recent_blockhash
+                            &merchant_token_keypair,
+                            &subscribe_result.1 .3.pubkey(), // payer,
+                            0,
+                        ))
+                        .await,
+                    Ok(())
+                );
+                let (order_payment_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
+                    &[
+                        &order_acc_pubkey.to_bytes(),
+                        &spl_token::id().to_bytes(),
+                        &mint_keypair.pubkey().to_bytes(),
+                    ],
+                    &subscribe_result.1 .0, // program_id
+                );
+
+                // call withdraw ix
+                let mut transaction = Transaction::new_with_payer(
+                    &[withdraw(
+                        subscribe_result.1 .0, // program_id
+                        WithdrawArgs {
+                            signer: subscribe_result.1 .3.pubkey(), // payer,
+                            order: order_acc_pubkey,
+                            merchant: subscribe_result.1 .1, // the merchant pubkey
+                            order_payment_token: order_payment_token_acc_pubkey,
+                            merchant_token: merchant_token_keypair.pubkey(),
+                            account_to_receive_sol_refund: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            pda,
+                            subscription: Some(subscription),
+                            close_order_account: false,
+                            other_tokens: vec![],
+                        },
+                    )],
+                    Some(&subscribe_result.1 .3.pubkey()),
+                );
+                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
+
+                if error_expected {
+                    assert!(subscribe_result
+                        .1
+                         .2
+                        .process_transaction(transaction)
+                        .await
+                        .is_err());
+                } else {
+                    assert!(subscribe_result
+                        .1
+                         .2
+                        .process_transaction(transaction)
+                        .await
+                        .is_ok());
+                }
+
+                return ();
+            }
+        };
+    }
+
+    #[tokio::test]
+    async fn test_subscription_lapses() {
+        let mint_keypair = Keypair::new();
+        let name = "hourly";
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":1000,"duration":3600,"grace_seconds":600,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
+            name = name
+        );
+        let (mut context, mut merchant_result) = create_merchant_account_with_context(
+            Some(String::from("lapse test")),
+            Option::None,
+            Option::None,
+            Some(packages),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let (subscription, _bump_seed) = Pubkey::find_program_address(
+            &[&payer.to_bytes(), &merchant.to_bytes(), name.as_bytes()],
+            &program_id,
+        );
+        let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription);
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+            1000,
+            &String::from(name),
+            &String::from(""),
+            Some(order_data),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[subscribe(
+                program_id,
+                SubscribeArgs {
+                    signer: payer,
+                    subscription,
+                    merchant,
+                    order: order_acc_pubkey,
+                    name: String::from(name),
+                    data: Option::None,
+                    seats: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let get_subscription = |mut banks_client: BanksClient| async move {
+            let subscription_account = banks_client.get_account(subscription).await;
+            SubscriptionAccount::unpack(&subscription_account.unwrap().unwrap().data).unwrap()
+        };
+        let subscription_data = get_subscription(context.banks_client.clone()).await;
+        assert!(
+            subscription_data.period_end
+                > get_unix_timestamp(&mut context.banks_client).await + 3000
+        );
+
+        // the subscription does not lapse before the end of its period
+        let mut transaction = Transaction::new_with_payer(
+            &[expire_subscription(program_id, subscription, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], context.last_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+
+        // it is past due within the grace period of the package
+        let seconds_left =
+            subscription_data.period_end - get_unix_timestamp(&mut context.banks_client).await;
+        let timestamp = warp_seconds(&mut context, seconds_left).await;
+        assert!(timestamp < subscription_data.period_end + 600);
+        let mut transaction = Transaction::new_with_payer(
+            &[expire_subscription(program_id, subscription, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], context.last_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_eq!(
+            SubscriptionStatus::PastDue as u8,
+            get_subscription(context.banks_client.clone()).await.status
+        );
+
+        // and expires after that
+        warp_seconds(&mut context, 600).await;
+        let mut transaction = Transaction::new_with_payer(
+            &[expire_subscription(program_id, subscription, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], context.last_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_eq!(
+            SubscriptionStatus::Expired as u8,
+            get_subscription(context.banks_client.clone()).await.status
+        );
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_during_trial() {
+        let mint_keypair = Keypair::new();
+        let name = "trialFirst";
+        // create a package that has a short trial period
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":99,"trial":0,"duration":604800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string(),
+            name = name
+        );
+        // withdraw goes okay
+        run_subscription_withdrawal_tests(name, &packages, &mint_keypair, false).await;
+    }
+
+    #[tokio::test]
+    async fn test_cannot_withdraw_during_trial() {
+        let mint_keypair = Keypair::new();
+        let name = "try1st";
+        // create a package that has a week long trial period
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":99,"trial":604800,"duration":604800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string(),
+            name = name
+        );
+        // withdrawal errors out as you cant withdraw during trial
+        run_subscription_withdrawal_tests(name, &packages, &mint_keypair, true).await;
+    }
+
+    async fn run_subscription_cancel_tests(
+        amount: u64,
+        name: &str,
+        packages: &str,
+        mint_keypair: &Keypair,
+    ) -> Option<(
+        SubscriptionAccount,
+        Option<solana_sdk::account::Account>,
+        Option<solana_sdk::account::Account>,
+        spl_token::state::Account,
+        SubscriptionAccount,
+        Option<solana_sdk::account::Account>,
+        Option<solana_sdk::account::Account>,
+        Option<solana_sdk::account::Account>,
+    )> {
+        // create the subscription
+        let result = run_subscribe_tests(amount, name, packages, mint_keypair).await;
+        assert!(result.0.is_ok());
+        let subscribe_result = result.1;
+        match subscribe_result {
+            None => Option::None,
+            Some(mut subscribe_result) => {
+                let subscription = subscribe_result.3; // the subscription pubkey
+
+                let previous_subscription_account =
+                    subscribe_result.1 .2.get_account(subscription).await;
+                let previous_subscription_account = match previous_subscription_account {
+                    Ok(data) => match data {
+                        None => panic!("Oo"),
+                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
+                            Ok(data) => data,
+                            Err(error) => panic!("Problem: {:?}", error),
+                        },
+                    },
+                    Err(error) => panic!("Problem: {:?}", error),
+                };
+
+                let order_acc_pubkey = subscribe_result.2;
+                let previous_order_account =
+                    subscribe_result.1 .2.get_account(order_acc_pubkey).await;
+                let previous_order_account = match previous_order_account {
+                    Err(error) => panic!("Problem: {:?}", error),
+                    Ok(value) => value,
+                };
+
+                let refund_token_acc_keypair = Keypair::new();
+                let (pda, _bump_seed) =
+                    Pubkey::find_program_address(&[PDA_SEED], &subscribe_result.1 .0);
+
+                // create and initialize refund token account
+                assert_matches!(
+                    subscribe_result
+                        .1
+                         .2
+                        .process_transaction(create_token_account_transaction(
+                            &subscribe_result.1 .3,
+                            mint_keypair,
+                            subscribe_result.1 .4, // recent_blockhash
+                            &refund_token_acc_keypair,
+                            &subscribe_result.1 .3.pubkey(), // payer,
+                            0,
+                        ))
+                        .await,
+                    Ok(())
+                );
+                let (order_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
+                    &[
+                        &order_acc_pubkey.to_bytes(),
+                        &spl_token::id().to_bytes(),
+                        &mint_keypair.pubkey().to_bytes(),
+                    ],
+                    &subscribe_result.1 .0, // program_id
+                );
+
+                let account_to_receive_sol_refund_pubkey = Pubkey::from_str(PROGRAM_OWNER).unwrap();
+                let account_to_receive_sol_refund_before = subscribe_result
+                    .1
+                     .2
+                    .get_account(account_to_receive_sol_refund_pubkey)
+                    .await
+                    .unwrap();
+
+                // call cancel ix
+                let mut transaction = Transaction::new_with_payer(
+                    &[cancel_subscription(
+                        subscribe_result.1 .0, // program_id
+                        CancelSubscriptionArgs {
+                            signer: subscribe_result.1 .3.pubkey(), // payer,
+                            subscription,
+                            merchant: subscribe_result.1 .1, // the merchant pubkey
+                            order: order_acc_pubkey,
+                            order_token: order_token_acc_pubkey,
+                            refund_token: refund_token_acc_keypair.pubkey(),
+                            account_to_receive_sol_refund: account_to_receive_sol_refund_pubkey,
+                            pda,
+                        },
+                    )],
+                    Some(&subscribe_result.1 .3.pubkey()),
+                );
+                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
+
+                let _cancel_result = subscribe_result.1 .2.process_transaction(transaction).await;
+
+                let subscription_account = subscribe_result.1 .2.get_account(subscription).await;
+                let subscription_account = match subscription_account {
+                    Ok(data) => match data {
+                        None => panic!("Oo"),
+                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
+                            Ok(data) => data,
+                            Err(error) => panic!("Problem: {:?}", error),
+                        },
+                    },
+                    Err(error) => panic!("Problem: {:?}", error),
+                };
+                let order_account = subscribe_result.1 .2.get_account(order_acc_pubkey).await;
+                let order_account = match order_account {
+                    Ok(value) => value,
+                    Err(error) => panic!("Problem: {:?}", error),
+                };
+                let order_token_account = subscribe_result
+                    .1
+                     .2
+                    .get_account(order_token_acc_pubkey)
+                    .await
+                    .unwrap();
+                let refund_token_account = subscribe_result
+                    .1
+                     .2
+                    .get_account(refund_token_acc_keypair.pubkey())
+                    .await;
+                let refund_token_account = match refund_token_account {
+                    Ok(data) => match data {
+                        None => panic!("Oo"),
+                        Some(value) => match TokenAccount::unpack(&value.data) {
+                            Ok(data) => data,
+                            Err(error) => panic!("Problem: {:?}", error),
+                        },
+                    },
+                    Err(error) => panic!("Problem: {:?}", error),
+                };
+
+                let account_to_receive_sol_refund_after = subscribe_result
+                    .1
+                     .2
+                    .get_account(account_to_receive_sol_refund_pubkey)
+                    .await
+                    .unwrap();
+
+                Some((
+                    subscription_account,
+                    order_account,
+                    order_token_account,
+                    refund_token_account,
+                    previous_subscription_account,
+                    previous_order_account,
+                    account_to_receive_sol_refund_before,
+                    account_to_receive_sol_refund_after,
+                ))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_subscription_during_trial() {
+        let mint_keypair = Keypair::new();
+        let name = "trialFirst";
+        // create a package that has a short trial period
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":6699,"trial":604800,"duration":604800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string(),
+            name = name
+        );
+        // cancel goes okay
+        let result = run_subscription_cancel_tests(6699, name, &packages, &mint_keypair)
+            .await
+            .unwrap();
+        let (
+            subscription_account,
+            order_account,
+            order_token_account,
+            refund_token_account,
+            previous_subscription_account,
+            previous_order_account,
+            account_to_receive_sol_refund_before,
+            account_to_receive_sol_refund_after,
+        ) = result;
+        // subscription was canceled
+        assert_eq!(
+            SubscriptionStatus::Initialized as u8,
+            previous_subscription_account.status
+        );
+        assert_eq!(
+            SubscriptionStatus::Cancelled as u8,
+            subscription_account.status
+        );
+        // period end has changed to an earlier time
+        assert!(previous_subscription_account.period_end > subscription_account.period_end);
+        // order account was closed
+        assert!(order_account.is_none());
+        // amount was withdrawn
+        assert_eq!(6699, refund_token_account.amount);
+        // order token account was closed and SOL refunded
+        run_order_token_account_refund_tests(
+            &order_token_account,
+            &account_to_receive_sol_refund_before,
+            &account_to_receive_sol_refund_after,
+            &previous_order_account,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_subscription_after_trial() {
+        let mint_keypair = Keypair::new();
+        let name = "trialFirst";
+        // create a package that has a short trial period
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":1337,"trial":0,"duration":604800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string(),
+            name = name
+        );
+        // cancel goes okay but no refund
+        let result = run_subscription_cancel_tests(1337, name, &packages, &mint_keypair)
+            .await
+            .unwrap();
+        let (
+            subscription_account,
+            order_account,
+            order_token_account,
+            refund_token_account,
+            previous_subscription_account,
+            previous_order_account,
+            account_to_receive_sol_refund_before,
+            account_to_receive_sol_refund_after,
+        ) = result;
+        // subscription was canceled
+        assert_eq!(
+            SubscriptionStatus::Initialized as u8,
+            previous_subscription_account.status
+        );
+        assert_eq!(
+            SubscriptionStatus::Cancelled as u8,
+            subscription_account.status
+        );
+        assert_eq!(
+            previous_subscription_account.period_end,
+            subscription_account.period_end
+        );
+        // order account was not changed
+        let order_account = match order_account {
+            None => panic!("Oo"),
+            Some(value) => match OrderAccount::unpack(&value.data) {
+                Ok(data) => data,
+                Err(error) => panic!("Problem: {:?}", error),
+            },
+        };
+        let previous_order_account = match previous_order_account {
+            None => panic!("Oo"),
+            Some(value) => match OrderAccount::unpack(&value.data) {
+                Ok(data) => data,
+                Err(error) => panic!("Problem: {:?}", error),
+            },
+        };
+        assert_eq!(order_account, previous_order_account);
+        assert_eq!(OrderStatus::Paid as u8, order_account.status);
+        // nothing was refunded
+        assert_eq!(0, refund_token_account.amount);
+        let order_token_account = match order_token_account {
+            None => panic!("Oo"),
+            Some(value) => match TokenAccount::unpack(&value.data) {
+                Ok(data) => data,
+                Err(error) => panic!("Problem: {:?}", error),
+            },
+        };
+        assert_eq!(order_account.paid_amount, order_token_account.amount);
+        match account_to_receive_sol_refund_before {
+            None => panic!("Oo"),
+            Some(account_before) => match account_to_receive_sol_refund_after {
+                None => panic!("Oo"),
+                Some(account_after) => {
+                    assert_eq!(account_before.lamports, account_after.lamports);
+                }
+            },
+        };
+    }
+
+    async fn run_expired_order_tests(
+        amount: u64,
+        expires_in: i64,
+        seconds: i64,
+    ) -> (
+        Result<(), TransportError>,
+        Result<(), TransportError>,
+        OrderAccount,
+        spl_token::state::Account,
+    ) {
+        let (mut context, mut merchant_result) = create_merchant_account_with_context(
+            Option::None,
+            Option::None,
+            Option::None,
+            Option::None,
+        )
+        .await;
+        let expires_at = get_unix_timestamp(&mut context.banks_client).await + expires_in;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, order_token_acc_pubkey) = create_order_express_checkout(
+            amount,
+            &String::from("EXP-1"),
+            &String::from(""),
+            Some(format!(r#"{{"expires_at": {}}}"#, expires_at)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let merchant_token_keypair = Keypair::new();
+        let refund_token_keypair = Keypair::new();
+
+        // create and initialize merchant token account
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &merchant_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        // create and initialize refund token account
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &refund_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        // let the given seconds pass
+        let recent_blockhash = if seconds > 0 {
+            warp_seconds(&mut context, seconds).await;
+            context.last_blockhash
+        } else {
+            recent_blockhash
+        };
+
+        // call reclaim ix
+        let mut transaction = Transaction::new_with_payer(
+            &[reclaim_expired_order(
+                program_id,
+                ReclaimExpiredOrderArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    order_token: order_token_acc_pubkey,
+                    refund_token: refund_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: payer.pubkey(),
+                    pda,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        let reclaim_result = merchant_result.2.process_transaction(transaction).await;
+
+        // call withdraw ix
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    order_payment_token: order_token_acc_pubkey,
+                    merchant_token: merchant_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    pda,
+                    subscription: Option::None,
+                    close_order_account: false,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        let withdraw_result = merchant_result.2.process_transaction(transaction).await;
+
+        let order_account = merchant_result.2.get_account(order_acc_pubkey).await;
+        let order_data = match order_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match OrderAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        let refund_token_account = merchant_result
+            .2
+            .get_account(refund_token_keypair.pubkey())
+            .await;
+        let refund_token_data = match refund_token_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match TokenAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+
+        (
+            reclaim_result,
+            withdraw_result,
+            order_data,
+            refund_token_data,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_expired_order() {
+        let amount: u64 = 4200;
+        let (reclaim_result, withdraw_result, order_data, refund_token_data) =
+            run_expired_order_tests(amount, 600, 600).await;
+        // the payer got their money back
+        assert!(reclaim_result.is_ok());
+        assert_eq!(OrderStatus::Expired as u8, order_data.status);
+        assert_eq!(amount, refund_token_data.amount);
+        // and the merchant can no longer withdraw
+        assert_eq!(
+            withdraw_result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::OrderExpired as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cannot_reclaim_order_before_expiry() {
+        let amount: u64 = 4200;
+        let (reclaim_result, withdraw_result, order_data, refund_token_data) =
+            run_expired_order_tests(amount, 3600, 0).await;
+        assert_eq!(
+            reclaim_result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::OrderNotExpired as u32)
+            )
+        );
+        assert_eq!(0, refund_token_data.amount);
+        // the merchant can still withdraw
+        assert!(withdraw_result.is_ok());
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+    }
+
+    #[tokio::test]
+    async fn test_cannot_pay_expired_order() {
+        let amount: u64 = 4200;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("EXP-2"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let timestamp = get_unix_timestamp(&mut merchant_result.2).await;
+
+        // an order that expires before it is paid could be reclaimed by the payer right away
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                merchant_result.0,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("EXP-2"),
+                    secret: String::from(""),
+                    data: Some(format!(r#"{{"expires_at": {}}}"#, timestamp)),
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::OrderExpired as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_escrow_withdraw_after_delivery_confirmed() {
+        let amount: u64 = 500000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, order_token_acc_pubkey) = create_order_express_checkout(
+            amount,
+            &String::from("ESC-1"),
+            &String::from(""),
+            Some(String::from(r#"{"escrow": 1209600}"#)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let merchant_token_keypair = Keypair::new();
+
+        // create and initialize merchant token account
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &merchant_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+
+        // withdrawal is refused while the order is held in escrow
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    order_payment_token: order_token_acc_pubkey,
+                    merchant_token: merchant_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    pda,
+                    subscription: Option::None,
+                    close_order_account: false,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::OrderNotReleasable as u32)
+            )
+        );
+
+        // call confirm delivery ix
+        let mut transaction = Transaction::new_with_payer(
+            &[confirm_delivery(
+                program_id,
+                payer.pubkey(),
+                order_acc_pubkey,
+                None,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result.2.get_account(order_acc_pubkey).await;
+        let order_data = match order_account {
+            Ok(data) => match data {
+                None => panic!("Oo"),
+                Some(value) => match OrderAccount::unpack(&value.data) {
+                    Ok(data) => data,
+                    Err(error) => panic!("Problem: {:?}", error),
+                },
+            },
+            Err(error) => panic!("Problem: {:?}", error),
+        };
+        assert_eq!(OrderStatus::Releasable as u8, order_data.status);
+
+        // now the withdrawal goes through
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    order_payment_token: order_token_acc_pubkey,
+                    merchant_token: merchant_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    pda,
+                    subscription: Option::None,
+                    close_order_account: true,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert!(merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_export_attestation() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+            2000000,
+            &String::from("1337"),
+            &String::from("hunter2"),
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+
+        // call export attestation ix
+        let mut transaction = Transaction::new_with_payer(
+            &[export_attestation(
+                program_id,
+                payer.pubkey(),
+                merchant,
+                vec![order_acc_pubkey],
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (attestation_pubkey, _bump_seed) = get_attestation_address(&program_id, &merchant);
+        let attestation_account = merchant_result
+            .2
+            .get_account(attestation_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attestation_account.owner, program_id);
+        let attestation = AttestationAccount::unpack(&attestation_account.data).unwrap();
+        assert!(attestation.is_initialized());
+        assert_eq!(program_id.to_bytes(), attestation.source);
+        assert_eq!(merchant.to_bytes(), attestation.merchant);
+        assert_eq!(payer.pubkey().to_bytes(), attestation.owner);
+        assert_eq!(1, attestation.order_count);
+        assert_eq!(0, attestation.subscription_count);
+        assert_ne!([0; 32], attestation.digest);
+
+        // the same account cannot be attested twice
+        let mut transaction = Transaction::new_with_payer(
+            &[Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(merchant, false),
+                    AccountMeta::new(attestation_pubkey, false),
+                    AccountMeta::new_readonly(solana_program::system_program::id(), false),
+                    AccountMeta::new_readonly(sysvar::rent::id(), false),
+                    AccountMeta::new_readonly(order_acc_pubkey, false),
+                    AccountMeta::new_readonly(order_acc_pubkey, false),
+                ],
+                data: PaymentProcessorInstruction::ExportAttestation
+                    .try_to_vec()
+                    .unwrap(),
+            }],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_attestation() {
+        let source_program_id =
+            Pubkey::from_str("mosh111111111111111111111111111111111111111").unwrap();
+        let program_id = Pubkey::new_unique();
+        let untrusting_program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "sol_payment_processor",
+            source_program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_program(
+            "sol_payment_processor",
+            program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_program(
+            "sol_payment_processor",
+            untrusting_program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        for program_id in &[source_program_id, program_id, untrusting_program_id] {
+            add_program_data(&mut program_test, program_id);
+        }
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let upgrade_authority = get_test_upgrade_authority();
+        let program_owner = Pubkey::from_str(PROGRAM_OWNER).unwrap();
+
+        // register a merchant on the source instance and export an attestation
+        let source_merchant =
+            Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &source_program_id).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    source_program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner,
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Option::None,
+                    },
+                ),
+                register_merchant(
+                    source_program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant: source_merchant,
+                        seed: Option::None,
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+                export_attestation(source_program_id, payer.pubkey(), source_merchant, vec![]),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let (source_attestation, _bump_seed) =
+            get_attestation_address(&source_program_id, &source_merchant);
+
+        // import it on an instance that trusts the source instance
+        let merchant = Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &program_id).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner,
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from("Partner Payments"),
+                        data: Some(
+                            json!({ TRUSTED_INSTANCES: [source_program_id.to_string()] })
+                                .to_string(),
+                        ),
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant,
+                        seed: Option::None,
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+                import_attestation(program_id, payer.pubkey(), merchant, source_attestation),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let (attestation_pubkey, _bump_seed) = get_attestation_address(&program_id, &merchant);
+        let attestation_account = banks_client
+            .get_account(attestation_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attestation_account.owner, program_id);
+        let attestation = AttestationAccount::unpack(&attestation_account.data).unwrap();
+        assert_eq!(source_program_id.to_bytes(), attestation.source);
+        assert_eq!(source_merchant.to_bytes(), attestation.merchant);
+        assert_eq!(payer.pubkey().to_bytes(), attestation.owner);
+
+        // an instance that does not trust the source instance rejects it
+        let untrusting_merchant =
+            Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &untrusting_program_id).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    untrusting_program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner,
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Option::None,
+                    },
+                ),
+                register_merchant(
+                    untrusting_program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant: untrusting_merchant,
+                        seed: Option::None,
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+                import_attestation(
+                    untrusting_program_id,
+                    payer.pubkey(),
+                    untrusting_merchant,
+                    source_attestation,
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                2,
+                InstructionError::Custom(PaymentProcessorError::UntrustedInstance as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pay_installments() {
+        let amount: u64 = 500000;
+        let expected_amount: u64 = 1500000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(expected_amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("INST-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+
+        // pay the first installment at checkout
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("INST-1"),
+                    secret: String::from(""),
+                    data: Some(json!({ "expected_amount": expected_amount }).to_string()),
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Pending as u8, order_data.status);
+        assert_eq!(expected_amount, order_data.expected_amount);
+        assert_eq!(amount, order_data.paid_amount);
+
+        // create and initialize merchant token account
+        let merchant_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &merchant_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+
+        // the order cannot be withdrawn until it is fully funded
+        let withdraw_transaction = |close_order_account: bool| {
+            let mut transaction = Transaction::new_with_payer(
+                &[withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: payer.pubkey(),
+                        order: order_acc_pubkey,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: merchant_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        pda,
+                        subscription: Option::None,
+                        close_order_account,
+                        other_tokens: vec![],
+                    },
+                )],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[payer], recent_blockhash);
+            transaction
+        };
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(withdraw_transaction(false))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::NotFullyPaid as u32)
+            )
+        );
+
+        // an installment cannot overpay the order
+        let installment_transaction = |installment: u64| {
+            let mut transaction = Transaction::new_with_payer(
+                &[pay_installment(
+                    program_id,
+                    payer.pubkey(),
+                    order_acc_pubkey,
+                    seller_token,
+                    buyer_token_keypair.pubkey(),
+                    merchant,
+                    installment,
+                )],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[payer], recent_blockhash);
+            transaction
+        };
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(installment_transaction(expected_amount))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+
+        // installments are only paid into the order token account of the order
+        let mut transaction = Transaction::new_with_payer(
+            &[pay_installment(
+                program_id,
+                payer.pubkey(),
+                order_acc_pubkey,
+                merchant_token_keypair.pubkey(),
+                buyer_token_keypair.pubkey(),
+                merchant,
+                400000,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::SellerAccountMismatch as u32)
+            )
+        );
+
+        // no installments are taken while the merchant is paused
+        let mut transaction = Transaction::new_with_payer(
+            &[pause_merchant(program_id, payer.pubkey(), merchant)],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(installment_transaction(400000))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::MerchantPaused as u32)
+            )
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[resume_merchant(program_id, payer.pubkey(), merchant)],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // nor from a buyer blocked by the merchant
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                block_buyer(program_id, payer.pubkey(), merchant, payer.pubkey()),
+                use_blocked_buyer(
+                    pay_installment(
+                        program_id,
+                        payer.pubkey(),
+                        order_acc_pubkey,
+                        seller_token,
+                        buyer_token_keypair.pubkey(),
+                        merchant,
+                        400000,
+                    ),
+                    merchant,
+                    payer.pubkey(),
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(PaymentProcessorError::BuyerBlocked as u32)
+            )
+        );
+
+        // pay the remaining installments
+        for installment in [400000, 600000].iter() {
+            assert_matches!(
+                merchant_result
+                    .2
+                    .process_transaction(installment_transaction(*installment))
+                    .await,
+                Ok(())
+            );
+        }
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(expected_amount, order_data.paid_amount);
+
+        // now the withdrawal goes through
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(withdraw_transaction(true))
+                .await,
+            Ok(())
+        );
+        let merchant_token_account = merchant_result
+            .2
+            .get_account(merchant_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_token_data = TokenAccount::unpack(&merchant_token_account.data).unwrap();
+        assert_eq!(expected_amount, merchant_token_data.amount);
+    }
+
+    #[tokio::test]
+    async fn test_redeem_voucher() {
+        let amount: u64 = 750000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("42"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+
+        // the merchant owner signs the voucher while offline
+        let voucher = Voucher {
+            merchant: merchant_result.1,
+            mint: mint_keypair.pubkey(),
+            amount,
+            expires_at: 32503680000,
+            nonce: 42,
+        };
+        let mut signature = [0; 64];
+        signature.copy_from_slice(payer.sign_message(&voucher.message()).as_ref());
+        let redeem_transaction = |redeemed_voucher: &Voucher| {
+            let mut transaction = Transaction::new_with_payer(
+                &[
+                    verify_voucher_signature(payer.pubkey(), signature, &voucher),
+                    redeem_voucher(
+                        program_id,
+                        RedeemVoucherArgs {
+                            signer: payer.pubkey(),
+                            order: order_acc_pubkey,
+                            seller_token,
+                            buyer_token: buyer_token_keypair.pubkey(),
+                            program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                            pda,
+                            voucher: redeemed_voucher.clone(),
+                        },
+                    ),
+                ],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[payer], recent_blockhash);
+            transaction
+        };
+
+        // the voucher cannot be tampered with
+        let tampered_voucher = Voucher {
+            amount: 1,
+            ..voucher.clone()
+        };
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(&tampered_voucher))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(PaymentProcessorError::VoucherSignatureInvalid as u32)
+            )
+        );
+
+        // redeem the voucher
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(&voucher))
+                .await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(String::from("42"), order_data.order_id);
+        let (voucher_pubkey, _bump_seed) =
+            get_voucher_address(&program_id, &merchant_result.1, voucher.nonce);
+        let voucher_account = merchant_result
+            .2
+            .get_account(voucher_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let voucher_data = VoucherAccount::unpack(&voucher_account.data).unwrap();
+        assert_eq!(order_acc_pubkey.to_bytes(), voucher_data.order);
+
+        // the voucher cannot be redeemed twice
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(&voucher))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(PaymentProcessorError::VoucherAlreadyRedeemed as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invoice() {
+        let amount: u64 = 1200000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("INV-001"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+
+        // the merchant creates the invoice
+        let mut transaction = Transaction::new_with_payer(
+            &[create_invoice(
+                program_id,
+                CreateInvoiceArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    mint: mint_keypair.pubkey(),
+                    pda,
+                    amount,
+                    order_id: String::from("INV-001"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Pending as u8, order_data.status);
+        assert_eq!(amount, order_data.expected_amount);
+        assert_eq!(0, order_data.paid_amount);
+        assert_eq!(String::from("INV-001"), order_data.order_id);
+
+        // and then it is paid
+        let program_owner_account = merchant_result
+            .2
+            .get_account(Pubkey::from_str(PROGRAM_OWNER).unwrap())
+            .await
+            .unwrap();
+        let program_owner_balance = match program_owner_account {
+            None => 0,
+            Some(value) => value.lamports,
+        };
+        let mut transaction = Transaction::new_with_payer(
+            &[pay_invoice(
+                program_id,
+                PayInvoiceArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    integrator: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(payer.pubkey().to_bytes(), order_data.payer);
+        let seller_token_account = merchant_result
+            .2
+            .get_account(seller_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let seller_token_data = TokenAccount::unpack(&seller_token_account.data).unwrap();
+        assert_eq!(amount, seller_token_data.amount);
+        // the fee was paid
+        let program_owner_account = merchant_result
+            .2
+            .get_account(Pubkey::from_str(PROGRAM_OWNER).unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            program_owner_balance + merchant_data.fee,
+            program_owner_account.lamports
+        );
+
+        // an invoice can only be paid once
+        let mut transaction = Transaction::new_with_payer(
+            &[pay_invoice(
+                program_id,
+                PayInvoiceArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    integrator: Option::None,
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(
+            &[payer],
+            merchant_result.2.get_recent_blockhash().await.unwrap(),
+        );
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidOrder as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migration_window() {
+        let amount: u64 = 2000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let upgrade_authority = get_test_upgrade_authority();
+        let ends_at: UnixTimestamp = 4102444800;
+
+        // the program authority starts the migration window
+        let mut transaction = Transaction::new_with_payer(
+            &[set_migration_window(
+                program_id,
+                upgrade_authority.pubkey(),
+                ends_at,
+                PROGRAM_VERSION,
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let config_account = merchant_result
+            .2
+            .get_account(get_program_config_address(&program_id).0)
+            .await
+            .unwrap()
+            .unwrap();
+        let config_data = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+        assert_eq!(ends_at, config_data.migration_ends);
+        assert_eq!(PROGRAM_VERSION, config_data.state_version);
+
+        // payments are paused during the migration window
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("1337"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant: merchant_result.1,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("1337"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::MigrationInProgress as u32)
+            )
+        );
+
+        // only the program authority can set the migration window
+        let impostor = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[set_migration_window(
+                program_id,
+                impostor.pubkey(),
+                0,
+                PROGRAM_VERSION,
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3, &impostor], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
+
+        // the program state cannot be marked newer than what the program understands
+        let mut transaction = Transaction::new_with_payer(
+            &[set_migration_window(
+                program_id,
+                upgrade_authority.pubkey(),
+                0,
+                PROGRAM_VERSION + 1,
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::UnsupportedVersion as u32)
+            )
+        );
+
+        // the program authority ends the migration window and payments resume
+        let mut transaction = Transaction::new_with_payer(
+            &[set_migration_window(
+                program_id,
+                upgrade_authority.pubkey(),
+                0,
+                PROGRAM_VERSION,
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        create_order_express_checkout(
+            amount,
+            &String::from("1337"),
+            &String::from(""),
+            Option::None,
+            &mut merchant_result,
+            &Keypair::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_waive_order_fees() {
+        let amount: u64 = 4200;
+        let program_owner = Keypair::new();
+        let (mut context, mut merchant_result) =
+            create_merchant_account_with_context_and_program_owner(
+                &program_owner.pubkey(),
+                Option::None,
+                Option::None,
+                Option::None,
+                Option::None,
+            )
+            .await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("REF-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let refund_token_keypair = Keypair::new();
+        let expires_at = get_unix_timestamp(&mut context.banks_client).await + 1;
+
+        // the order is paid
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer.pubkey(),
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: program_owner.pubkey(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount,
+                        tip: 0,
+                        order_id: String::from("REF-1"),
+                        secret: String::from(""),
+                        data: Some(format!(r#"{{"expires_at": {}}}"#, expires_at)),
+                    },
+                ),
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &refund_token_keypair.pubkey(),
+                    Rent::default().minimum_balance(TokenAccount::LEN),
+                    TokenAccount::LEN as u64,
+                    &spl_token::id(),
+                ),
+                initialize_account(
+                    &spl_token::id(),
+                    &refund_token_keypair.pubkey(),
+                    &mint_keypair.pubkey(),
+                    &payer.pubkey(),
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer, &refund_token_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // and refunded once it expires
+        warp_seconds(&mut context, 1).await;
+        let recent_blockhash = context.last_blockhash;
+        let mut transaction = Transaction::new_with_payer(
+            &[reclaim_expired_order(
+                program_id,
+                ReclaimExpiredOrderArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_token: seller_token,
+                    refund_token: refund_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: payer.pubkey(),
+                    pda,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Expired as u8, order_data.status);
+        assert_eq!(
+            Some(OrderFees {
+                program_owner: merchant_data.fee,
+                sponsor: 0
+            }),
+            get_order_fees(&order_data)
+        );
+        let program_owner_account = merchant_result
+            .2
+            .get_account(program_owner.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(merchant_data.fee, program_owner_account.lamports);
+
+        // only the program owner can waive the fees
+        let mut transaction = Transaction::new_with_payer(
+            &[waive_order_fees(
+                program_id,
+                payer.pubkey(),
+                order_acc_pubkey,
+                merchant,
+                Pubkey::new_from_array(merchant_data.sponsor),
+                payer.pubkey(),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer, &program_owner], recent_blockhash);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongProgramOwner as u32)
+            )
+        );
+
+        // the program owner gives the fee back to the payer
+        let mut transaction = Transaction::new_with_payer(
+            &[waive_order_fees(
+                program_id,
+                program_owner.pubkey(),
+                order_acc_pubkey,
+                merchant,
+                Pubkey::new_from_array(merchant_data.sponsor),
+                payer.pubkey(),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer, &program_owner], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::FeesWaived as u8, order_data.status);
+        assert_eq!(
+            None,
+            merchant_result
+                .2
+                .get_account(program_owner.pubkey())
+                .await
+                .unwrap()
+        );
+
+        // the fees can only be waived once
+        let mut transaction = Transaction::new_with_payer(
+            &[waive_order_fees(
+                program_id,
+                program_owner.pubkey(),
+                order_acc_pubkey,
+                merchant,
+                Pubkey::new_from_array(merchant_data.sponsor),
+                payer.pubkey(),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(
+            &[payer, &program_owner],
+            merchant_result.2.get_recent_blockhash().await.unwrap(),
+        );
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidOrder as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_checkout_multiple_mints() {
+        let mint_a = Keypair::new();
+        let mint_b = Keypair::new();
+        let amount: u64 = 2000000;
+        let other_amount: u64 = 6000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+        order_items.insert("2".to_string(), 2);
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_a}"}},
+            "2": {{"price": 3000000, "mint": "{mint_b}"}}
+        }}"#,
+            mint_a = mint_a.pubkey(),
+            mint_b = mint_b.pubkey()
+        );
+        let mut merchant_result = create_merchant_account(
+            Some("chain".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let buyer_token_a = create_token_account(amount, &mint_a, &mut merchant_result).await;
+        let buyer_token_b = create_token_account(other_amount, &mint_b, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            None,
+            &mint_a.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let (other_seller_token, _bump_seed) = Pubkey::find_program_address(
+            &[
+                &order_acc_pubkey.to_bytes(),
+                &spl_token::id().to_bytes(),
+                &mint_b.pubkey().to_bytes(),
+            ],
+            &program_id,
+        );
+
+        // the items are paid for in both mints
+        let mut transaction = Transaction::new_with_payer(
+            &[chain_checkout(
+                program_id,
+                ChainCheckoutArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_a.pubkey(),
+                    mint: mint_a.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    order_items,
+                    data: Option::None,
+                    other_mints: vec![(mint_b.pubkey(), buyer_token_b.pubkey())],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(
+            vec![OrderToken {
+                mint: mint_b.pubkey().to_string(),
+                token: other_seller_token.to_string(),
+                amount: other_amount,
+            }],
+            get_order_tokens(&order_data)
+        );
+        let other_seller_token_account = merchant_result
+            .2
+            .get_account(other_seller_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let other_seller_token_data =
+            TokenAccount::unpack(&other_seller_token_account.data).unwrap();
+        assert_eq!(other_amount, other_seller_token_data.amount);
+        assert_eq!(pda, other_seller_token_data.owner);
+
+        // the merchant withdraws the amounts paid in both mints
+        let merchant_token_a = Keypair::new();
+        let merchant_token_b = Keypair::new();
+        for (mint, merchant_token) in
+            [(&mint_a, &merchant_token_a), (&mint_b, &merchant_token_b)].iter()
+        {
+            assert_matches!(
+                merchant_result
+                    .2
+                    .process_transaction(create_token_account_transaction(
+                        payer,
+                        mint,
+                        recent_blockhash,
+                        merchant_token,
+                        &payer.pubkey(),
+                        0,
+                    ))
+                    .await,
+                Ok(())
+            );
+        }
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: merchant_token_a.pubkey(),
+                    account_to_receive_sol_refund: payer.pubkey(),
+                    pda,
+                    subscription: Option::None,
+                    close_order_account: false,
+                    other_tokens: vec![(other_seller_token, merchant_token_b.pubkey())],
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_token_b_account = merchant_result
+            .2
+            .get_account(merchant_token_b.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_token_b_data = TokenAccount::unpack(&merchant_token_b_account.data).unwrap();
+        assert_eq!(other_amount, merchant_token_b_data.amount);
+        // the order token account of the other mint was closed
+        assert_eq!(
+            None,
+            merchant_result
+                .2
+                .get_account(other_seller_token)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coupon() {
+        let amount: u64 = 1000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount * 2, &mint_keypair, &mut merchant_result).await;
+        let code = String::from("SAVE10");
+        let (coupon, _bump_seed) = get_coupon_address(&program_id, &merchant, &code);
+
+        // call create coupon ix for a 10% discount that can be used once
+        let mut transaction = Transaction::new_with_payer(
+            &[create_coupon(
+                program_id,
+                CreateCouponArgs {
+                    signer: merchant_result.3.pubkey(),
+                    merchant,
+                    code: code.clone(),
+                    discount_type: DiscountType::Percentage,
+                    discount: 1000,
+                    mint: Option::None,
+                    max_uses: 1,
+                    expires_at: 32503680000,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let coupon_account = merchant_result
+            .2
+            .get_account(coupon)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(program_id, coupon_account.owner);
+        let coupon_data = CouponAccount::unpack(&coupon_account.data).unwrap();
+        assert_eq!(Discriminator::Coupon as u8, coupon_data.discriminator);
+        assert_eq!(merchant.to_bytes(), coupon_data.merchant);
+        assert_eq!(Pubkey::default().to_bytes(), coupon_data.mint);
+        assert_eq!(1, coupon_data.remaining_uses);
+        assert_eq!(code, coupon_data.code);
+
+        // call express checkout ix with the coupon
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("COUPON-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Some(coupon),
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("COUPON-1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the discount was taken off the amount paid
+        let seller_token_account = merchant_result
+            .2
+            .get_account(seller_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let seller_token_data = TokenAccount::unpack(&seller_token_account.data).unwrap();
+        assert_eq!(900000, seller_token_data.amount);
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(900000, order_data.expected_amount);
+        assert_eq!(900000, order_data.paid_amount);
+        assert_eq!(
+            Some(OrderDiscount {
+                coupon: coupon.to_string(),
+                code: code.clone(),
+                amount: 100000,
+            }),
+            get_order_discount(&order_data)
+        );
+        let coupon_account = merchant_result
+            .2
+            .get_account(coupon)
+            .await
+            .unwrap()
+            .unwrap();
+        let coupon_data = CouponAccount::unpack(&coupon_account.data).unwrap();
+        assert_eq!(0, coupon_data.remaining_uses);
+
+        // the coupon cannot be used once it has run out of uses
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("COUPON-2"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Some(coupon),
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("COUPON-2"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::CouponExhausted as u32)
+            )
+        );
+
+        // a fixed discount can only be redeemed in its own mint
+        let other_code = String::from("FIVE-OFF");
+        let (other_coupon, _bump_seed) = get_coupon_address(&program_id, &merchant, &other_code);
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                create_coupon(
+                    program_id,
+                    CreateCouponArgs {
+                        signer: merchant_result.3.pubkey(),
+                        merchant,
+                        code: other_code,
+                        discount_type: DiscountType::Fixed,
+                        discount: 5000,
+                        mint: Some(Pubkey::new_unique()),
+                        max_uses: 10,
+                        expires_at: 32503680000,
+                    },
+                ),
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: merchant_result.3.pubkey(),
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Some(other_coupon),
+                        link: Option::None,
+                        amount,
+                        tip: 0,
+                        order_id: String::from("COUPON-2"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                ),
+            ],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(PaymentProcessorError::WrongMint as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_link() {
+        let amount: u64 = 1000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount * 2, &mint_keypair, &mut merchant_result).await;
+        let link_id = String::from("twitter");
+        let (link, _bump_seed) = get_payment_link_address(&program_id, &merchant, &link_id);
+        let (link_payer, _bump_seed) = get_link_payer_address(&program_id, &link, &payer);
+
+        // call create payment link ix
+        let mut transaction = Transaction::new_with_payer(
+            &[create_payment_link(
+                program_id,
+                payer,
+                merchant,
+                mint_keypair.pubkey(),
+                link_id.clone(),
+                0,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let link_account = merchant_result.2.get_account(link).await.unwrap().unwrap();
+        assert_eq!(program_id, link_account.owner);
+        let link_data = PaymentLinkAccount::unpack(&link_account.data).unwrap();
+        assert_eq!(Discriminator::PaymentLink as u8, link_data.discriminator);
+        assert_eq!(merchant.to_bytes(), link_data.merchant);
+        assert_eq!(mint_keypair.pubkey().to_bytes(), link_data.mint);
+        assert_eq!(0, link_data.payments);
+        assert_eq!(link_id, link_data.link_id);
+
+        // call express checkout ix through the payment link twice
+        for order_id in ["LINK-1", "LINK-2"].iter() {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some(*order_id),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Some(link),
+                        amount,
+                        tip: 0,
+                        order_id: String::from(*order_id),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            assert_matches!(
+                merchant_result.2.process_transaction(transaction).await,
+                Ok(())
+            );
+        }
+
+        // both payments were counted but the payer was only counted once
+        let link_account = merchant_result.2.get_account(link).await.unwrap().unwrap();
+        let link_data = PaymentLinkAccount::unpack(&link_account.data).unwrap();
+        assert_eq!(2, link_data.payments);
+        assert_eq!(amount * 2, link_data.volume);
+        assert_eq!(1, link_data.unique_payers);
+        assert!(link_data.last_paid > 0);
+        let link_payer_account = merchant_result
+            .2
+            .get_account(link_payer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(program_id, link_payer_account.owner);
+        let link_payer_data = LinkPayerAccount::unpack(&link_payer_account.data).unwrap();
+        assert_eq!(link.to_bytes(), link_payer_data.link);
+        assert_eq!(payer.to_bytes(), link_payer_data.payer);
+    }
+
+    #[tokio::test]
+    async fn test_merchant_tolerance() {
+        let mint_keypair = Keypair::new();
+        let price: u64 = 2000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 1);
+
+        let merchant_data = format!(
+            r#"{{"1": {{"price": {price}, "mint": "{mint_key}"}}}}"#,
+            price = price,
+            mint_key = mint_keypair.pubkey()
+        );
+        let mut merchant_result = create_merchant_account(
+            Some("tolerance".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+
+        let tolerance_transaction = |tolerance: u64, merchant_result: &MerchantResult| {
+            let mut transaction = Transaction::new_with_payer(
+                &[set_merchant_tolerance(
+                    program_id, payer, merchant, tolerance,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            transaction
+        };
+
+        // the tolerance cannot be set too high
+        let transaction = tolerance_transaction(2000, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+
+        // 1%
+        let transaction = tolerance_transaction(100, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(100, merchant_data.tolerance);
+
+        // an underpayment within the tolerance goes through
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_chain_checkout(
+            1990000,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(1990000, order_data.paid_amount);
+
+        // an underpayment beyond the tolerance fails
+        match create_chain_checkout_transaction(
+            1970000,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await
+        {
+            Err(error) => {
+                assert_eq!(
+                    error.unwrap(),
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(PaymentProcessorError::InsufficientPaid as u32)
+                    )
+                );
+            }
+            Ok(_value) => panic!("Oo... we expect an error"),
+        };
+
+        // the excess of an overpayment beyond the tolerance stays with the buyer
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
+            3000000,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(price, order_data.paid_amount);
+        assert_eq!(price, order_data.expected_amount);
+        let seller_token_account = merchant_result
+            .2
+            .get_account(seller_account_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let seller_token_data = TokenAccount::unpack(&seller_token_account.data).unwrap();
+        assert_eq!(price, seller_token_data.amount);
+    }
+
+    #[tokio::test]
+    async fn test_instant_settlement() {
+        let mint_keypair = Keypair::new();
+        let amount: u64 = 2000000;
+        let mut merchant_result = create_merchant_account(
+            Some("instant".to_string()),
+            Option::None,
+            Option::None,
+            Option::None,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+
+        let mut transaction = Transaction::new_with_payer(
+            &[set_instant_settlement(program_id, payer, merchant, true)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert!(merchant_data.instant_settlement);
+
+        // the checkout pays a token account of the merchant directly
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let merchant_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    &mint_keypair,
+                    merchant_result.4,
+                    &merchant_token_keypair,
+                    &payer,
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        let (order_acc_pubkey, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("instant-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token: merchant_token_keypair.pubkey(),
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("instant-1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the order is created already withdrawn and does not count towards the references
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(merchant_token_keypair.pubkey().to_bytes(), order_data.token);
+        let merchant_token_account = merchant_result
+            .2
+            .get_account(merchant_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_token_data = TokenAccount::unpack(&merchant_token_account.data).unwrap();
+        assert_eq!(amount, merchant_token_data.amount);
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(0, merchant_data.references);
+    }
+
+    #[tokio::test]
+    async fn test_associated_token_accounts() {
+        let amount: u64 = 2000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let buyer = Keypair::new();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        // creates the mint
+        create_token_account(0, &mint_keypair, &mut merchant_result).await;
+
+        // fund the buyer and their associated token account
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::transfer(&payer, &buyer.pubkey(), 1000000000),
+                create_associated_token_account_instruction(&payer, &buyer.pubkey(), &mint),
+                mint_to(
+                    &spl_token::id(),
+                    &mint,
+                    &get_associated_token_address(&buyer.pubkey(), &spl_token::id(), &mint),
+                    &payer,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // pay from the buyer's associated token account
+        let (order_acc_pubkey, _seller_token, _pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("ata"),
+            &mint,
+            &mut merchant_result.2,
+        )
+        .await;
+        let order = order_acc_pubkey;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout_from_wallet(
+                program_id,
+                ExpressCheckoutFromWalletArgs {
+                    signer: buyer.pubkey(),
+                    order,
+                    merchant,
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("ata"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &buyer], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(
+            get_order_token_address(&program_id, &order, &mint).to_bytes(),
+            order_data.token
+        );
+
+        // withdraw to the merchant's associated token account, which does not exist yet
+        let merchant_token = get_associated_token_address(&payer, &spl_token::id(), &mint);
+        assert_eq!(
+            None,
+            merchant_result.2.get_account(merchant_token).await.unwrap()
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_to_wallet(
+                program_id,
+                WithdrawToWalletArgs {
+                    signer: payer,
+                    order,
+                    merchant,
+                    merchant_owner: payer,
+                    mint,
+                    account_to_receive_sol_refund: payer,
+                    subscription: Option::None,
+                    close_order_account: false,
+                    create_merchant_token: true,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_token_account = merchant_result
+            .2
+            .get_account(merchant_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_token_data = TokenAccount::unpack(&merchant_token_account.data).unwrap();
+        assert_eq!(payer, merchant_token_data.owner);
+        assert_eq!(amount, merchant_token_data.amount);
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+    }
+
+    #[tokio::test]
+    async fn test_price_guarantee() {
+        let mint_keypair = Keypair::new();
+        let name = "monthly";
+        let price: u64 = 1000000;
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":{price},"duration":100,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
+            name = name,
+            price = price
+        );
+        let result = run_subscribe_tests(price, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let (subscription_account, mut merchant_result, _order, subscription) = result.1.unwrap();
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let guaranteed_until: UnixTimestamp = 4102444800;
+
+        let guarantee_transaction =
+            |package: &str, guaranteed_until: UnixTimestamp, merchant_result: &MerchantResult| {
+                let mut transaction = Transaction::new_with_payer(
+                    &[guarantee_package_price(
+                        program_id,
+                        payer,
+                        merchant,
+                        String::from(package),
+                        guaranteed_until,
+                    )],
+                    Some(&payer),
+                );
+                transaction.sign(&[&merchant_result.3], merchant_result.4);
+                transaction
+            };
+
+        // the guarantee has to end in the future
+        let transaction = guarantee_transaction(name, 1, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+        // the package has to exist
+        let transaction = guarantee_transaction("yearly", guaranteed_until, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidSubscriptionPackage as u32)
+            )
+        );
+
+        let transaction = guarantee_transaction(name, guaranteed_until, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (price_guarantee, _bump_seed) =
+            get_price_guarantee_address(&program_id, &merchant, name);
+        let guarantee_account = merchant_result
+            .2
+            .get_account(price_guarantee)
+            .await
+            .unwrap()
+            .unwrap();
+        let guarantee_data = PriceGuaranteeAccount::unpack(&guarantee_account.data).unwrap();
+        assert_eq!(
+            Discriminator::PriceGuarantee as u8,
+            guarantee_data.discriminator
+        );
+        assert_eq!(merchant.to_bytes(), guarantee_data.merchant);
+        assert_eq!(price, guarantee_data.price);
+        assert_eq!(guaranteed_until, guarantee_data.guaranteed_until);
+        assert_eq!(String::from(name), guarantee_data.package);
+        assert!(subscription_account.joined <= guarantee_data.created);
+
+        // renew the subscription under the guarantee
+        let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription);
+        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
+            price * 2,
+            &String::from("renewal"),
+            &String::from(""),
+            Some(order_data),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[renew_subscription(
+                program_id,
+                payer,
+                subscription,
+                merchant,
+                order_acc_pubkey,
+                Some(price_guarantee),
+                2,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let subscription_account2 = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account2.data).unwrap();
+        assert_eq!(
+            subscription_account.period_end + 200,
+            subscription_data.period_end
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_2022_program() {
+        let amount: u64 = 2000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("2022"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let instruction = express_checkout(
+            merchant_result.0,
+            ExpressCheckoutArgs {
+                signer: merchant_result.3.pubkey(),
+                order: order_acc_pubkey,
+                merchant: merchant_result.1,
+                seller_token,
+                buyer_token: buyer_token_keypair.pubkey(),
+                mint: mint_keypair.pubkey(),
+                program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                pda,
+                integrator: Option::None,
+                coupon: Option::None,
+                link: Option::None,
+                amount,
+                tip: 0,
+                order_id: String::from("2022"),
+                secret: String::from(""),
+                data: Option::None,
+            },
+        );
+
+        // the builders can target the Token-2022 program
+        let token_2022 = get_token_2022_program_id();
+        let token_2022_instruction = use_token_program(instruction.clone(), token_2022);
+        assert_eq!(token_2022, token_2022_instruction.accounts[9].pubkey);
+        assert!(!token_2022_instruction
+            .accounts
+            .iter()
+            .any(|account| account.pubkey == spl_token::id()));
+
+        // but no other program is accepted as the token program
+        let mut transaction = Transaction::new_with_payer(
+            &[use_token_program(instruction.clone(), Pubkey::new_unique())],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)
+        );
+
+        // the SPL Token program still works as before
+        let mut transaction = Transaction::new_with_payer(
+            &[use_token_program(instruction, spl_token::id())],
+            Some(&merchant_result.3.pubkey()),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_token_2022_transfer_fee_mint() {
+        let token_2022 = get_token_2022_program_id();
+        let mint = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        // a Token-2022 mint is padded to the size of a token account and holds
+        // its extensions after the account type
+        let mut mint_data = vec![0u8; TokenAccount::LEN];
+        Mint::pack(mint, &mut mint_data[..Mint::LEN]).unwrap();
+        mint_data.push(1);
+        // the transfer fee config, whose token accounts withhold the transfer fees
+        mint_data.extend_from_slice(&1u16.to_le_bytes());
+        mint_data.extend_from_slice(&108u16.to_le_bytes());
+        mint_data.extend_from_slice(&[0u8; 108]);
+
+        let mint_key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mint_info = solana_program::account_info::AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut lamports,
+            &mut mint_data,
+            &token_2022,
+            false,
+            0,
+        );
+        // room for the account type and the withheld transfer fees
+        assert_eq!(
+            TokenAccount::LEN + 1 + 4 + 8,
+            get_token_account_size(&mint_info).unwrap()
+        );
+        assert_eq!(6, get_mint_decimals(&mint_info).unwrap());
+
+        // the same mint without extensions gets a plain token account
+        let mut spl_mint_data = vec![0u8; Mint::LEN];
+        Mint::pack(mint, &mut spl_mint_data).unwrap();
+        let mut spl_lamports = 0;
+        let spl_token_id = spl_token::id();
+        let spl_mint_info = solana_program::account_info::AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut spl_lamports,
+            &mut spl_mint_data,
+            &spl_token_id,
+            false,
+            0,
+        );
+        assert_eq!(
+            TokenAccount::LEN,
+            get_token_account_size(&spl_mint_info).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_debit() {
+        let amount: u64 = 1000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let buyer_token = buyer_token_keypair.pubkey();
+        let (auto_debit, _bump_seed) = get_auto_debit_address(&program_id, &merchant, &buyer_token);
+
+        // the maximum amount cannot exceed the allowance
+        let mut transaction = Transaction::new_with_payer(
+            &[approve_auto_debit(
+                program_id,
+                payer,
+                merchant,
+                buyer_token,
+                amount,
+                amount + 1,
+                3600,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+
+        let mut transaction = Transaction::new_with_payer(
+            &[approve_auto_debit(
+                program_id,
+                payer,
+                merchant,
+                buyer_token,
+                amount,
+                amount / 2,
+                3600,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let auto_debit_account = merchant_result
+            .2
+            .get_account(auto_debit)
+            .await
+            .unwrap()
+            .unwrap();
+        let auto_debit_data = AutoDebitAccount::unpack(&auto_debit_account.data).unwrap();
+        assert_eq!(
+            Discriminator::AutoDebit as u8,
+            auto_debit_data.discriminator
+        );
+        assert_eq!(merchant.to_bytes(), auto_debit_data.merchant);
+        assert_eq!(payer.to_bytes(), auto_debit_data.owner);
+        assert_eq!(buyer_token.to_bytes(), auto_debit_data.token);
+        assert_eq!(mint_keypair.pubkey().to_bytes(), auto_debit_data.mint);
+        assert_eq!(amount, auto_debit_data.allowance);
+        assert_eq!(amount / 2, auto_debit_data.max_amount);
+        assert_eq!(3600, auto_debit_data.interval);
+        assert_eq!(0, auto_debit_data.last_collected);
+        let buyer_token_account = merchant_result
+            .2
+            .get_account(buyer_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let buyer_token_data = TokenAccount::unpack(&buyer_token_account.data).unwrap();
+        assert_eq!(auto_debit, buyer_token_data.delegate.unwrap());
+        assert_eq!(amount, buyer_token_data.delegated_amount);
+
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        let collect_transaction =
+            |order_id: &str, amount: u64, merchant_result: &MerchantResult| {
+                let mut transaction = Transaction::new_with_payer(
+                    &[collect_payment(
+                        program_id,
+                        CollectPaymentArgs {
+                            signer: payer,
+                            order: get_order_address(&program_id, &merchant, order_id).0,
+                            merchant,
+                            buyer_token,
+                            mint: mint_keypair.pubkey(),
+                            program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                            amount,
+                            order_id: String::from(order_id),
+                            secret: String::from(""),
+                            data: Option::None,
+                        },
+                    )],
+                    Some(&payer),
+                );
+                transaction.sign(&[&merchant_result.3], merchant_result.4);
+                transaction
+            };
+
+        // the merchant cannot collect more than the maximum amount at once
+        let transaction = collect_transaction("auto-debit-1", amount, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+
+        let transaction = collect_transaction("auto-debit-1", amount / 2, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (order, _bump_seed) = get_order_address(&program_id, &merchant, "auto-debit-1");
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(
+            Discriminator::OrderExpressCheckout as u8,
+            order_data.discriminator
+        );
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(payer.to_bytes(), order_data.payer);
+        assert_eq!(amount / 2, order_data.expected_amount);
+        assert_eq!(amount / 2, order_data.paid_amount);
+        let seller_token_account = merchant_result
+            .2
+            .get_account(Pubkey::new_from_array(order_data.token))
+            .await
+            .unwrap()
+            .unwrap();
+        let seller_token_data = TokenAccount::unpack(&seller_token_account.data).unwrap();
+        assert_eq!(amount / 2, seller_token_data.amount);
+        let auto_debit_account = merchant_result
+            .2
+            .get_account(auto_debit)
+            .await
+            .unwrap()
+            .unwrap();
+        let auto_debit_data = AutoDebitAccount::unpack(&auto_debit_account.data).unwrap();
+        assert_eq!(amount / 2, auto_debit_data.allowance);
+        assert_eq!(order_data.created, auto_debit_data.last_collected);
+
+        // the next payment cannot be collected before the interval has passed
+        let transaction = collect_transaction("auto-debit-2", amount / 2, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_renew_due_subscriptions() {
+        let mint_keypair = Keypair::new();
+        let name = "daily";
+        let price: u64 = 1000000;
+        // a package that ends as soon as it starts so that it is due right away
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":{price},"duration":0,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
+            name = name,
+            price = price
+        );
+        let result = run_subscribe_tests(price, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let (_subscription_account, mut merchant_result, _order, subscription) = result.1.unwrap();
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+
+        // the subscriber lets the merchant pull the price once a day
+        let subscriber_token_keypair = Keypair::new();
+        let subscriber_token = subscriber_token_keypair.pubkey();
+        let mut transaction = create_token_account_transaction(
+            &merchant_result.3,
+            &mint_keypair,
+            merchant_result.4,
+            &subscriber_token_keypair,
+            &payer,
+            price * 2,
+        );
+        transaction.sign(
+            &[&merchant_result.3, &subscriber_token_keypair],
+            merchant_result.4,
+        );
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[approve_auto_debit(
+                program_id,
+                payer,
+                merchant,
+                subscriber_token,
+                price * 2,
+                price,
+                86400,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (auto_debit, _bump_seed) =
+            get_auto_debit_address(&program_id, &merchant, &subscriber_token);
+
+        // the renewals are the next orders of the merchant
+        let renewal_order = |order_sequence: u64| {
+            let order_id = format!("{}-{}", name, get_next_order_id(order_sequence).unwrap());
+            get_order_address(&program_id, &merchant, &order_id).0
+        };
+        let crank_transaction = |order: Pubkey, merchant_result: &MerchantResult| {
+            let mut transaction = Transaction::new_with_payer(
+                &[renew_due_subscriptions(
+                    program_id,
+                    RenewDueSubscriptionsArgs {
+                        signer: payer,
+                        order,
+                        merchant,
+                        subscription,
+                        subscriber_token,
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        guarantee: Option::None,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            transaction
+        };
+
+        // the auto-debit account has nothing to pay the cranker with yet
+        let order = renewal_order(merchant_data.order_sequence);
+        let transaction = crank_transaction(order, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InsufficientFunds)
+        );
+
+        let mut transaction = Transaction::new_with_payer(
+            &[system_instruction::transfer(&payer, &auto_debit, 100000000)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let auto_debit_lamports = merchant_result.2.get_balance(auto_debit).await.unwrap();
+
+        let transaction = crank_transaction(order, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(payer.to_bytes(), order_data.payer);
+        assert_eq!(price, order_data.paid_amount);
+        let order_json_data: Value = serde_json::from_str(&order_data.data).unwrap();
+        assert_eq!(
+            subscription.to_string(),
+            order_json_data["subscription"].as_str().unwrap()
+        );
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(order_data.created, subscription_data.period_start);
+        assert_eq!(order_data.created, subscription_data.period_end);
+        // the cranker got back the rent of the order accounts, the fees and the incentive
+        let reward = auto_debit_lamports - merchant_result.2.get_balance(auto_debit).await.unwrap();
+        assert!(reward > order_account.lamports + RENEWAL_INCENTIVE_IN_LAMPORTS);
+
+        // the subscription is still due but the subscriber allows one payment a day
+        let transaction = crank_transaction(
+            renewal_order(merchant_data.order_sequence + 1),
+            &merchant_result,
+        );
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fee_schedule_history() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let upgrade_authority = get_test_upgrade_authority();
+        let authority = upgrade_authority.pubkey();
+        let config_address = get_program_config_address(&program_id).0;
+        let config_account = merchant_result
+            .2
+            .get_account(config_address)
+            .await
+            .unwrap()
+            .unwrap();
+        let initial_config = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+
+        let fee_schedule_transaction =
+            |index: u64, default_fee: u64, min_fee: u64, merchant_result: &MerchantResult| {
+                let mut transaction = Transaction::new_with_payer(
+                    &[set_fee_schedule(
+                        program_id,
+                        authority,
+                        index,
+                        default_fee,
+                        min_fee,
+                        5,
+                    )],
+                    Some(&merchant_result.3.pubkey()),
+                );
+                transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+                transaction
+            };
+
+        let transaction = fee_schedule_transaction(0, 600000, 60000, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let config_account = merchant_result
+            .2
+            .get_account(config_address)
+            .await
+            .unwrap()
+            .unwrap();
+        let config_data = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+        assert_eq!(600000, config_data.default_fee);
+        assert_eq!(60000, config_data.min_fee);
+        assert_eq!(5, config_data.sponsor_fee);
+
+        // the fee schedule that was replaced is in the fee history
+        let fee_history_account = merchant_result
+            .2
+            .get_account(get_fee_history_address(&program_id).0)
+            .await
+            .unwrap()
+            .unwrap();
+        let fee_history_data = FeeHistoryAccount::unpack(&fee_history_account.data).unwrap();
+        assert_eq!(1, fee_history_data.count);
+        let fee_schedule_account = merchant_result
+            .2
+            .get_account(get_fee_schedule_address(&program_id, 0).0)
+            .await
+            .unwrap()
+            .unwrap();
+        let first_schedule = FeeScheduleAccount::unpack(&fee_schedule_account.data).unwrap();
+        assert_eq!(
+            Discriminator::FeeSchedule as u8,
+            first_schedule.discriminator
+        );
+        assert_eq!(0, first_schedule.index);
+        assert_eq!(initial_config.default_fee, first_schedule.default_fee);
+        assert_eq!(initial_config.min_fee, first_schedule.min_fee);
+        assert_eq!(initial_config.sponsor_fee, first_schedule.sponsor_fee);
+        assert_eq!(0, first_schedule.effective_from);
+        assert_eq!(fee_history_data.since, first_schedule.effective_until);
+
+        // the default fee cannot be less than the minimum fee
+        let transaction = fee_schedule_transaction(1, 60000, 600000, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+        // the fee history cannot be written over
+        let transaction = fee_schedule_transaction(0, 700000, 60000, &merchant_result);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+        );
+
+        let transaction = fee_schedule_transaction(1, 700000, 60000, &merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let fee_schedule_account = merchant_result
+            .2
+            .get_account(get_fee_schedule_address(&program_id, 1).0)
+            .await
+            .unwrap()
+            .unwrap();
+        let second_schedule = FeeScheduleAccount::unpack(&fee_schedule_account.data).unwrap();
+        assert_eq!(1, second_schedule.index);
+        assert_eq!(600000, second_schedule.default_fee);
+        assert_eq!(
+            first_schedule.effective_until,
+            second_schedule.effective_from
+        );
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_with_reference() {
+        let amount: u64 = 2000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("reference"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut instruction = express_checkout(
+            merchant_result.0,
+            ExpressCheckoutArgs {
+                signer: merchant_result.3.pubkey(),
+                order: order_acc_pubkey,
+                merchant: merchant_result.1,
+                seller_token,
+                buyer_token: buyer_token_keypair.pubkey(),
+                mint: mint_keypair.pubkey(),
+                program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                pda,
+                integrator: Option::None,
+                coupon: Option::None,
+                link: Option::None,
+                amount,
+                tip: 0,
+                order_id: String::from("reference"),
+                secret: String::from(""),
+                data: Option::None,
+            },
+        );
+        // a Solana Pay reference key is not mistaken for an integrator account
+        let reference = Pubkey::new_unique();
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(reference, false));
+        let mut transaction =
+            Transaction::new_with_payer(&[instruction], Some(&merchant_result.3.pubkey()));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_logs_payment_id() {
+        let amount: u64 = 2000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &merchant_result.0,
+            &merchant_result.1,
+            Some("payment-id"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        // the SlotHashes sysvar is not mistaken for an integrator account
+        let instruction = log_payment_id(express_checkout(
+            merchant_result.0,
+            ExpressCheckoutArgs {
+                signer: merchant_result.3.pubkey(),
+                order: order_acc_pubkey,
+                merchant: merchant_result.1,
+                seller_token,
+                buyer_token: buyer_token_keypair.pubkey(),
+                mint: mint_keypair.pubkey(),
+                program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                pda,
+                integrator: Option::None,
+                coupon: Option::None,
+                link: Option::None,
+                amount,
+                tip: 0,
+                order_id: String::from("payment-id"),
+                secret: String::from(""),
+                data: Option::None,
+            },
+        ));
+        assert_eq!(
+            AccountMeta::new_readonly(sysvar::slot_hashes::id(), false),
+            *instruction.accounts.last().unwrap()
+        );
+        let mut transaction =
+            Transaction::new_with_payer(&[instruction], Some(&merchant_result.3.pubkey()));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+    }
+
+    #[tokio::test]
+    async fn test_pay_link() {
+        let amount: u64 = 1000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount * 2, &mint_keypair, &mut merchant_result).await;
+        let link_id = String::from("t-shirt");
+        let link_data = String::from(r#"{"item": "t-shirt"}"#);
+        let (link, _bump_seed) = get_payment_link_address(&program_id, &merchant, &link_id);
+
+        // call create payment link ix for a fixed amount
+        let mut transaction = Transaction::new_with_payer(
+            &[create_payment_link(
+                program_id,
+                payer,
+                merchant,
+                mint_keypair.pubkey(),
+                link_id.clone(),
+                amount,
+                Some(link_data.clone()),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let link_account = merchant_result.2.get_account(link).await.unwrap().unwrap();
+        let link_account_data = PaymentLinkAccount::unpack(&link_account.data).unwrap();
+        assert_eq!(amount, link_account_data.amount);
+
+        let mut order = Pubkey::default();
+        // the link can only be paid its amount
+        for pay_amount in [amount - 1, amount].iter() {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some(&format!("{}-1", link_id)),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[pay_link(
+                    program_id,
+                    PayLinkArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        link,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        amount: *pay_amount,
+                        tip: 0,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            if *pay_amount != amount {
+                assert!(result.is_err());
+                continue;
+            }
+            assert_matches!(result, Ok(()));
+            order = order_acc_pubkey;
+        }
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(amount, order_data.paid_amount);
+        assert_eq!(String::from("t-shirt-1"), order_data.order_id);
+        assert_eq!(
+            Some(json!("t-shirt")),
+            serde_json::from_str::<Value>(&order_data.data)
+                .unwrap()
+                .get("item")
+                .cloned()
+        );
+        let link_account = merchant_result.2.get_account(link).await.unwrap().unwrap();
+        let link_account_data = PaymentLinkAccount::unpack(&link_account.data).unwrap();
+        assert_eq!(1, link_account_data.payments);
+        assert_eq!(amount, link_account_data.volume);
+    }
+
+    #[tokio::test]
+    async fn test_donate_to_campaign() {
+        let amount: u64 = 1000000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount * 3, &mint_keypair, &mut merchant_result).await;
+        let campaign_id = String::from("relief");
+        let (campaign, _bump_seed) = get_campaign_address(&program_id, &merchant, &campaign_id);
+
+        // call create campaign ix
+        let mut transaction = Transaction::new_with_payer(
+            &[create_campaign(
+                program_id,
+                payer,
+                merchant,
+                mint_keypair.pubkey(),
+                campaign_id.clone(),
+                amount * 2,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // donations are for any amount but never paid in installments
+        let donations = vec![
+            (amount / 2, String::from(r#"{"name": "anonymous"}"#), true),
+            (
+                amount,
+                format!(r#"{{"expected_amount": {}}}"#, amount * 2),
+                false,
+            ),
+            (amount * 2, String::from("{}"), true),
+        ];
+        for (index, (donation, data, succeeds)) in donations.into_iter().enumerate() {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some(&format!("DONATION-{}", index)),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[donate(
+                    program_id,
+                    DonateArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        campaign: Some(campaign),
+                        amount: donation,
+                        order_id: format!("DONATION-{}", index),
+                        secret: String::from(""),
+                        data: Some(data),
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            if !succeeds {
+                assert!(result.is_err());
+                continue;
+            }
+            assert_matches!(result, Ok(()));
+            let order_account = merchant_result
+                .2
+                .get_account(order_acc_pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+            assert_eq!(OrderStatus::Paid as u8, order_data.status);
+            assert_eq!(donation, order_data.expected_amount);
+            assert_eq!(donation, order_data.paid_amount);
+            let json_data: Value = serde_json::from_str(&order_data.data).unwrap();
+            assert_eq!(json!(true), json_data[DONATION]);
+        }
+
+        // the donations went past the goal of the campaign
+        let campaign_account = merchant_result
+            .2
+            .get_account(campaign)
+            .await
+            .unwrap()
+            .unwrap();
+        let campaign_data = CampaignAccount::unpack(&campaign_account.data).unwrap();
+        assert_eq!(Discriminator::Campaign as u8, campaign_data.discriminator);
+        assert_eq!(campaign_id, campaign_data.campaign_id);
+        assert_eq!(amount * 2, campaign_data.goal);
+        assert_eq!(2, campaign_data.donations);
+        assert_eq!(amount / 2 + amount * 2, campaign_data.raised);
+        assert!(campaign_data.raised >= campaign_data.goal);
+    }
+
+    #[tokio::test]
+    async fn test_redeem_refund_code() {
+        let amount: u64 = 500000;
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let mint_keypair = Keypair::new();
+        let (order_acc_pubkey, order_token_acc_pubkey) = create_order_express_checkout(
+            amount,
+            &String::from("REFUND-1"),
+            &String::from(""),
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let payer = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let refund_token_keypair = Keypair::new();
+        let (refund_code, _bump_seed) = get_refund_code_address(&program_id, &order_acc_pubkey);
+
+        // create and initialize the refund token account of the payer
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &refund_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+
+        // call issue refund code ix
+        let mut transaction = Transaction::new_with_payer(
+            &[issue_refund_code(
+                program_id,
+                payer.pubkey(),
+                order_acc_pubkey,
+                merchant_result.1,
+                "SUPPORT-4821",
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[payer], recent_blockhash);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let refund_code_account = merchant_result
+            .2
+            .get_account(refund_code)
+            .await
+            .unwrap()
+            .unwrap();
+        let refund_code_data = RefundCodeAccount::unpack(&refund_code_account.data).unwrap();
+        assert_eq!(order_acc_pubkey.to_bytes(), refund_code_data.order);
+        assert_eq!(
+            get_refund_code_hash(&order_acc_pubkey, "SUPPORT-4821"),
+            refund_code_data.code_hash
+        );
+        assert_eq!(0, refund_code_data.redeemed);
+
+        // only the right code can be redeemed, and only once
+        let merchant = merchant_result.1;
+        let redeem_transaction = |code: &str, refund_token: Pubkey| {
+            let mut transaction = Transaction::new_with_payer(
+                &[redeem_refund_code(
+                    program_id,
+                    payer.pubkey(),
+                    order_acc_pubkey,
+                    merchant,
+                    order_token_acc_pubkey,
+                    refund_token,
+                    String::from(code),
+                )],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[payer], recent_blockhash);
+            transaction
+        };
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(
+                    "SUPPORT-4822",
+                    refund_token_keypair.pubkey()
+                ))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(
+                    "SUPPORT-4821",
+                    refund_token_keypair.pubkey()
+                ))
+                .await,
+            Ok(())
+        );
+        let other_refund_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    payer,
+                    &mint_keypair,
+                    recent_blockhash,
+                    &other_refund_token_keypair,
+                    &payer.pubkey(),
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(redeem_transaction(
+                    "SUPPORT-4821",
+                    other_refund_token_keypair.pubkey()
+                ))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::AlreadyWithdrawn as u32)
+            )
+        );
+
+        // the payer got their money back
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Refunded as u8, order_data.status);
+        let refund_token_account = merchant_result
+            .2
+            .get_account(refund_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let refund_token_data = TokenAccount::unpack(&refund_token_account.data).unwrap();
+        assert_eq!(amount, refund_token_data.amount);
+        let refund_code_account = merchant_result
+            .2
+            .get_account(refund_code)
+            .await
+            .unwrap()
+            .unwrap();
+        let refund_code_data = RefundCodeAccount::unpack(&refund_code_account.data).unwrap();
+        assert!(refund_code_data.redeemed > 0);
+    }
+
+    /// a screening program that only approves payments up to 1000000
+    fn process_screening(
+        _program_id: &Pubkey,
+        accounts: &[solana_program::account_info::AccountInfo],
+        instruction_data: &[u8],
+    ) -> solana_program::entrypoint::ProgramResult {
+        let request = ScreeningRequest::try_from_slice(instruction_data).unwrap();
+        if *accounts[0].key != request.payer || request.amount > 1000000 {
+            return Err(solana_program::program_error::ProgramError::Custom(0));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_checkout_screening() {
+        let program_id = Pubkey::from_str("mosh111111111111111111111111111111111111111").unwrap();
+        let screening_program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "sol_payment_processor",
+            program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_program(
+            "screening",
+            screening_program_id,
+            processor!(process_screening),
+        );
+        add_program_data(&mut program_test, &program_id);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let upgrade_authority = get_test_upgrade_authority();
+        let merchant = Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &program_id).unwrap();
+
+        // call initialize program ix with a screening program and register merchant ix
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Some(
+                            json!({ SCREENING_PROGRAM: screening_program_id.to_string() })
+                                .to_string(),
+                        ),
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant,
+                        seed: Some(String::from(MERCHANT)),
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let mut merchant_result: MerchantResult =
+            (program_id, merchant, banks_client, payer, recent_blockhash);
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000000, &mint_keypair, &mut merchant_result).await;
+
+        // only screened payments within the limit of the screening program go through
+        for (amount, screened, succeeds) in [
+            (1000000, false, false),
+            (2000000, true, false),
+            (1000000, true, true),
+        ]
+        .iter()
+        {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some("SCREENED"),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut instruction = express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: merchant_result.3.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: *amount,
+                    tip: 0,
+                    order_id: String::from("SCREENED"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            );
+            if *screened {
+                instruction = use_screening_program(instruction, screening_program_id, vec![]);
+            }
+            let mut transaction =
+                Transaction::new_with_payer(&[instruction], Some(&merchant_result.3.pubkey()));
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            assert_eq!(*succeeds, result.is_ok());
+        }
+    }
+
+    /// a token metadata program that keeps the metadata it is sent as is, once the
+    /// mint authority and the update authority have signed
+    ///
+    /// The metadata account is added to program-test up front, as program-test
+    /// cannot grow accounts in cross-program invocations.
+    fn process_token_metadata(
+        program_id: &Pubkey,
+        accounts: &[solana_program::account_info::AccountInfo],
+        instruction_data: &[u8],
+    ) -> solana_program::entrypoint::ProgramResult {
+        if !accounts[2].is_signer || !accounts[3].is_signer {
+            return Err(solana_program::program_error::ProgramError::MissingRequiredSignature);
+        }
+        // the master edition is not kept
+        if instruction_data[0] != 33 {
+            return Ok(());
+        }
+        let (metadata, _bump_seed) = Pubkey::find_program_address(
+            &[
+                b"metadata",
+                &program_id.to_bytes(),
+                &accounts[1].key.to_bytes(),
+            ],
+            program_id,
+        );
+        assert_eq!(metadata, *accounts[0].key);
+        let data = &instruction_data[1..];
+        accounts[0].data.borrow_mut()[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_checkout_receipt() {
+        let amount: u64 = 1000000;
+        let program_id = Pubkey::from_str("mosh111111111111111111111111111111111111111").unwrap();
+        let mut program_test = ProgramTest::new(
+            "sol_payment_processor",
+            program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_program(
+            "token_metadata",
+            get_token_metadata_program_id(),
+            processor!(process_token_metadata),
+        );
+        add_program_data(&mut program_test, &program_id);
+        // room for the metadata of the receipt, as the token metadata program makes
+        let receipt_mint = Keypair::new();
+        program_test.add_account(
+            get_metadata_address(&receipt_mint.pubkey()),
+            solana_sdk::account::Account {
+                lamports: Rent::default().minimum_balance(679),
+                data: vec![0; 679],
+                owner: get_token_metadata_program_id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let upgrade_authority = get_test_upgrade_authority();
+        let merchant = Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &program_id).unwrap();
+
+        // call initialize program ix with a receipt URI and register merchant ix
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Some(
+                            json!({ RECEIPT_URI: "https://example.com/receipt" }).to_string(),
+                        ),
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant,
+                        seed: Some(String::from(MERCHANT)),
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let mut merchant_result: MerchantResult =
+            (program_id, merchant, banks_client, payer, recent_blockhash);
+        let buyer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("RECEIPT-1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+
+        // pay and get a receipt
+        let mut transaction = Transaction::new_with_payer(
+            &[use_receipt(
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: buyer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount,
+                        tip: 0,
+                        order_id: String::from("RECEIPT-1"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                ),
+                receipt_mint.pubkey(),
+            )],
+            Some(&buyer),
+        );
+        transaction.sign(&[&merchant_result.3, &receipt_mint], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the receipt mint is a new mint of a single token
+        let receipt_mint_account = merchant_result
+            .2
+            .get_account(receipt_mint.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(spl_token::id(), receipt_mint_account.owner);
+        let receipt_mint_data = Mint::unpack(&receipt_mint_account.data).unwrap();
+        assert_eq!(0, receipt_mint_data.decimals);
+        assert_eq!(1, receipt_mint_data.supply);
+
+        // which the buyer holds
+        let receipt_token_account = merchant_result
+            .2
+            .get_account(get_associated_token_address(
+                &buyer,
+                &spl_token::id(),
+                &receipt_mint.pubkey(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        let receipt_token_data = TokenAccount::unpack(&receipt_token_account.data).unwrap();
+        assert_eq!(buyer, receipt_token_data.owner);
+        assert_eq!(receipt_mint.pubkey(), receipt_token_data.mint);
+        assert_eq!(1, receipt_token_data.amount);
+
+        // with the metadata of the order, created by the program's derived address
+        let metadata_account = merchant_result
+            .2
+            .get_account(get_metadata_address(&receipt_mint.pubkey()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(get_token_metadata_program_id(), metadata_account.owner);
+        let mut metadata = &metadata_account.data[..];
+        let name = String::deserialize(&mut metadata).unwrap();
+        let symbol = String::deserialize(&mut metadata).unwrap();
+        let uri = String::deserialize(&mut metadata).unwrap();
+        let _seller_fee_basis_points = u16::deserialize(&mut metadata).unwrap();
+        let creators = Option::<Vec<(Pubkey, bool, u8)>>::deserialize(&mut metadata).unwrap();
+        assert_eq!("Receipt RECEIPT-1", name);
+        assert_eq!("RCPT", symbol);
+        assert_eq!(
+            format!(
+                "https://example.com/receipt?merchant={}&order_id=RECEIPT%2D1&mint={}&amount={}",
+                merchant,
+                mint_keypair.pubkey(),
+                amount
+            ),
+            uri[..uri.find("&timestamp=").unwrap()]
+        );
+        assert_eq!(Some(vec![(pda, true, 100)]), creators);
+        // and cannot be changed
+        let _collection = Option::<u8>::deserialize(&mut metadata).unwrap();
+        let _uses = Option::<u8>::deserialize(&mut metadata).unwrap();
+        assert!(!bool::deserialize(&mut metadata).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_merchant_recovery() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let recovery_keypair = Keypair::new();
+        let recovery_key = recovery_keypair.pubkey();
+
+        // call set merchant recovery ix with a timeout of an hour
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_recovery(
+                program_id,
+                payer,
+                merchant,
+                recovery_key,
+                3600,
+                0,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the recovery cannot start while the owner is active
+        let mut transaction = Transaction::new_with_payer(
+            &[start_merchant_recovery(program_id, recovery_key, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &recovery_keypair], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+
+        // with no timeout, the recovery key takes over the merchant account
+        let operator = Pubkey::new_unique();
+        let payout_wallet = Pubkey::new_unique();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_operators(program_id, payer, merchant, vec![operator]),
+                set_payout_schedule(program_id, payer, merchant, vec![(payout_wallet, 10000)]),
+                set_merchant_recovery(program_id, payer, merchant, recovery_key, 0, 0),
+                start_merchant_recovery(program_id, recovery_key, merchant),
+                complete_merchant_recovery(program_id, recovery_key, merchant),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &recovery_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(recovery_key.to_bytes(), merchant_data.owner);
+        // without the operators and the payout schedule of the previous owner
+        assert_eq!(0, merchant_data.operator_count);
+        assert!(!merchant_data.operators.contains(&operator.to_bytes()));
+        assert_eq!(0, merchant_data.payout_count);
+        assert!(!merchant_data
+            .payout_wallets
+            .contains(&payout_wallet.to_bytes()));
+        let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+        let recovery_account = merchant_result
+            .2
+            .get_account(recovery)
+            .await
+            .unwrap()
+            .unwrap();
+        let recovery_data = MerchantRecoveryAccount::unpack(&recovery_account.data).unwrap();
+        assert_eq!(0, recovery_data.recovery_started);
+
+        // the former owner can no longer set the recovery
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_recovery(
+                program_id, payer, merchant, payer, 0, 0,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongMerchant as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merchant_recovery_owner_activity() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let recovery_keypair = Keypair::new();
+        let recovery_key = recovery_keypair.pubkey();
+        let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
+
+        // the recovery key starts the recovery right away
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_merchant_recovery(program_id, payer, merchant, recovery_key, 0, 3600),
+                start_merchant_recovery(program_id, recovery_key, merchant),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &recovery_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // any instruction signed by the owner cancels the recovery
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                with_merchant_recovery(pause_merchant(program_id, payer, merchant), merchant),
+                with_merchant_recovery(resume_merchant(program_id, payer, merchant), merchant),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let recovery_account = merchant_result
+            .2
+            .get_account(recovery)
+            .await
+            .unwrap()
+            .unwrap();
+        let recovery_data = MerchantRecoveryAccount::unpack(&recovery_account.data).unwrap();
+        assert_eq!(0, recovery_data.recovery_started);
+        assert!(recovery_data.last_active > 0);
+
+        // so the recovery key cannot take over the merchant account
+        let mut transaction = Transaction::new_with_payer(
+            &[complete_merchant_recovery(
+                program_id,
+                recovery_key,
+                merchant,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &recovery_keypair], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_item_stock() {
+        let mint_keypair = Keypair::new();
+        let price: u64 = 1000000;
+
+        let merchant_data = format!(
+            r#"{{"1": {{"price": {price}, "mint": "{mint_key}", "track_stock": true}}}}"#,
+            price = price,
+            mint_key = mint_keypair.pubkey()
+        );
+        let mut merchant_result = create_merchant_account(
+            Some("stock".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let (item_stock, _bump_seed) = get_item_stock_address(&program_id, &merchant, "1");
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 2);
+
+        // a tracked item cannot be sold without its item stock account
+        match create_chain_checkout_transaction(
+            price * 2,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await
+        {
+            Err(error) => {
+                assert_eq!(
+                    error.unwrap(),
+                    TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+                );
+            }
+            Ok(_value) => panic!("Oo... we expect an error"),
+        };
+
+        // restock the item
+        let mut transaction = Transaction::new_with_payer(
+            &[restock_item(
+                program_id,
+                payer,
+                merchant,
+                "1".to_string(),
+                2,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let item_stock_account = merchant_result
+            .2
+            .get_account(item_stock)
+            .await
+            .unwrap()
+            .unwrap();
+        let item_stock_data = ItemStockAccount::unpack(&item_stock_account.data).unwrap();
+        assert_eq!(
+            Discriminator::ItemStock as u8,
+            item_stock_data.discriminator
+        );
+        assert_eq!(merchant.to_bytes(), item_stock_data.merchant);
+        assert_eq!(2, item_stock_data.stock);
+        assert_eq!(0, item_stock_data.sold);
+        assert_eq!("1", item_stock_data.item);
+
+        for (quantity, expect_success) in [(2, true), (1, false)] {
+            let mut order_items: OrderItems = BTreeMap::new();
+            order_items.insert("1".to_string(), quantity);
+            let amount = price * quantity;
+            let buyer_token_keypair =
+                create_token_account(amount, &mint_keypair, &mut merchant_result).await;
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                None,
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[use_item_stock(
+                    chain_checkout(
+                        program_id,
+                        ChainCheckoutArgs {
+                            signer: payer,
+                            order: order_acc_pubkey,
+                            merchant,
+                            seller_token,
+                            buyer_token: buyer_token_keypair.pubkey(),
+                            mint: mint_keypair.pubkey(),
+                            program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                            pda,
+                            integrator: Option::None,
+                            coupon: Option::None,
+                            link: Option::None,
+                            amount,
+                            order_items,
+                            data: Option::None,
+                            other_mints: vec![],
+                        },
+                    ),
+                    merchant,
+                    vec!["1"],
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            if expect_success {
+                assert_matches!(result, Ok(()));
+            } else {
+                // the item is sold out
+                assert_eq!(
+                    result.unwrap_err().unwrap(),
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(PaymentProcessorError::InvalidOrderData as u32)
+                    )
+                );
+            }
+        }
+
+        let item_stock_account = merchant_result
+            .2
+            .get_account(item_stock)
+            .await
+            .unwrap()
+            .unwrap();
+        let item_stock_data = ItemStockAccount::unpack(&item_stock_account.data).unwrap();
+        assert_eq!(0, item_stock_data.stock);
+        assert_eq!(2, item_stock_data.sold);
+    }
+
+    /// a Pyth price account of a price of price * 10^-8, give or take conf * 10^-8
+    fn create_pyth_price_account(
+        price: i64,
+        conf: u64,
+        publish_time: i64,
+    ) -> solana_sdk::account::Account {
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data[20..24].copy_from_slice(&(-8i32).to_le_bytes());
+        data[96..104].copy_from_slice(&publish_time.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[216..224].copy_from_slice(&conf.to_le_bytes());
+        data[224..228].copy_from_slice(&1u32.to_le_bytes());
+        solana_sdk::account::Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_price_oracle() {
+        let program_id = Pubkey::from_str("mosh111111111111111111111111111111111111111").unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        // $2.50, $2.50 but an hour old and $2.50 give or take 2%
+        let price_feed = Pubkey::new_unique();
+        let stale_price_feed = Pubkey::new_unique();
+        let uncertain_price_feed = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "sol_payment_processor",
+            program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_account(
+            price_feed,
+            create_pyth_price_account(250000000, 100000, now),
+        );
+        program_test.add_account(
+            stale_price_feed,
+            create_pyth_price_account(250000000, 100000, now - 3600),
+        );
+        program_test.add_account(
+            uncertain_price_feed,
+            create_pyth_price_account(250000000, 5000000, now),
+        );
+        add_program_data(&mut program_test, &program_id);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let upgrade_authority = get_test_upgrade_authority();
+        let merchant = Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &program_id).unwrap();
+
+        // call initialize program ix and register merchant ix
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Option::None,
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant,
+                        seed: Some(String::from(MERCHANT)),
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let mut merchant_result: MerchantResult =
+            (program_id, merchant, banks_client, payer, recent_blockhash);
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000000, &mint_keypair, &mut merchant_result).await;
+        let (oracle, _bump_seed) =
+            get_price_oracle_address(&program_id, &merchant, &mint_keypair.pubkey());
+
+        // set each price feed, then check out $10 with a $1 tip at the given price feed
+        for (feed, checkout_feed, max_slippage, expected_error) in [
+            (price_feed, price_feed, 100, Option::None),
+            (
+                price_feed,
+                stale_price_feed,
+                200,
+                Some(InstructionError::InvalidArgument),
+            ),
+            (
+                stale_price_feed,
+                stale_price_feed,
+                100,
+                Some(InstructionError::Custom(
+                    PaymentProcessorError::InvalidOrderData as u32,
+                )),
+            ),
+            (
+                uncertain_price_feed,
+                uncertain_price_feed,
+                150,
+                Some(InstructionError::Custom(
+                    PaymentProcessorError::InvalidOrderData as u32,
+                )),
+            ),
+        ]
+        .iter()
+        {
+            let mut transaction = Transaction::new_with_payer(
+                &[set_price_oracle(
+                    program_id,
+                    payer,
+                    merchant,
+                    mint_keypair.pubkey(),
+                    *feed,
+                    600,
+                    *max_slippage,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            assert_matches!(
+                merchant_result.2.process_transaction(transaction).await,
+                Ok(())
+            );
+            let oracle_account = merchant_result
+                .2
+                .get_account(oracle)
+                .await
+                .unwrap()
+                .unwrap();
+            let oracle_data = PriceOracleAccount::unpack(&oracle_account.data).unwrap();
+            assert_eq!(Discriminator::PriceOracle as u8, oracle_data.discriminator);
+            assert_eq!(feed.to_bytes(), oracle_data.price_feed);
+            assert_eq!(*max_slippage, oracle_data.max_slippage);
+
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some("FIAT"),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let instruction = use_price_oracle(
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount: 10000000,
+                        tip: 1000000,
+                        order_id: String::from("FIAT"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                ),
+                merchant,
+                mint_keypair.pubkey(),
+                *checkout_feed,
+            );
+            let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer));
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            match expected_error {
+                Some(error) => assert_eq!(
+                    result.unwrap_err().unwrap(),
+                    TransactionError::InstructionError(0, error.clone())
+                ),
+                None => {
+                    assert_matches!(result, Ok(()));
+                    // 10 / 2.5 = 4 and 1 / 2.5 = 0.4 rounded up to 1
+                    let order_account = merchant_result
+                        .2
+                        .get_account(order_acc_pubkey)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+                    assert_eq!(4, order_data.expected_amount);
+                    assert_eq!(4, order_data.paid_amount);
+                    assert_eq!(1, order_data.tip);
+                    let fiat_price = get_order_fiat_price(&order_data).unwrap();
+                    assert_eq!(10000000, fiat_price.amount);
+                    assert_eq!(1000000, fiat_price.tip);
+                    assert_eq!(250000000, fiat_price.price);
+                    assert_eq!(-8, fiat_price.expo);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_bundle() {
+        let mint_keypair = Keypair::new();
+        let packages_a = format!(
+            r#"{{"packages":[{{"name":"basic","price":1000000,"duration":720,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey()
+        );
+        let packages_b = format!(
+            r#"{{"packages":[{{"name":"premium","price":2000000,"duration":720,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey()
+        );
+        let mut merchant_result = create_merchant_account(
+            Some(String::from("bundle-a")),
+            Option::None,
+            Option::None,
+            Some(packages_a),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant_a = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let merchant_b = Pubkey::create_with_seed(&payer, "bundle-b", &program_id).unwrap();
+        let (bundle, _bump_seed) = get_bundle_address(&program_id, &payer, "duo");
+        let get_subscription_address = |merchant: &Pubkey, name: &str| {
+            Pubkey::find_program_address(
+                &[&payer.to_bytes(), &merchant.to_bytes(), name.as_bytes()],
+                &program_id,
+            )
+            .0
+        };
+        let subscription_a = get_subscription_address(&merchant_a, "basic");
+        let subscription_b = get_subscription_address(&merchant_b, "premium");
+
+        // register merchant b, create a bundle with a 20% discount and have both merchants join
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer,
+                        merchant: merchant_b,
+                        seed: Some(String::from("bundle-b")),
+                        fee: Option::None,
+                        data: Some(packages_b),
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+                create_bundle(program_id, payer, String::from("duo"), 2000),
+                join_bundle(
+                    program_id,
+                    payer,
+                    payer,
+                    merchant_a,
+                    bundle,
+                    String::from("basic"),
+                ),
+                join_bundle(
+                    program_id,
+                    payer,
+                    payer,
+                    merchant_b,
+                    bundle,
+                    String::from("premium"),
+                ),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let bundle_account = merchant_result
+            .2
+            .get_account(bundle)
+            .await
+            .unwrap()
+            .unwrap();
+        let bundle_data = BundleAccount::unpack(&bundle_account.data).unwrap();
+        assert_eq!(Discriminator::Bundle as u8, bundle_data.discriminator);
+        assert_eq!(2000, bundle_data.discount);
+        assert_eq!(2, bundle_data.members);
+
+        // subscribe to the package of merchant a in full
+        let (order_a, _seller_token) = create_order_express_checkout(
+            1000000,
+            &String::from("bundle-a"),
+            &String::from(""),
+            Some(format!(r#"{{"subscription": "{}"}}"#, subscription_a)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[subscribe(
+                program_id,
+                SubscribeArgs {
+                    signer: payer,
+                    subscription: subscription_a,
+                    merchant: merchant_a,
+                    order: order_a,
+                    name: String::from("basic"),
+                    data: Option::None,
+                    seats: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the package of merchant b is discounted for subscribers of merchant a, but the
+        // subscription to merchant a does not discount merchant a itself
+        let buyer_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    &mint_keypair,
+                    merchant_result.4,
+                    &buyer_token_keypair,
+                    &payer,
+                    3000000,
+                ))
+                .await,
+            Ok(())
+        );
+        for (merchant, other_merchant, subscription, amount, succeeds) in [
+            (merchant_a, merchant_a, subscription_a, 1000000, false),
+            (merchant_b, merchant_a, subscription_b, 2000000, true),
+        ]
+        .iter()
+        {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                merchant,
+                Some("bundle"),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let instruction = use_bundle(
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant: *merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount: *amount,
+                        tip: 0,
+                        order_id: String::from("bundle"),
+                        secret: String::from(""),
+                        data: Some(format!(r#"{{"subscription": "{}"}}"#, subscription)),
+                    },
+                ),
+                bundle,
+                *merchant,
+                subscription_a,
+                *other_merchant,
+            );
+            let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer));
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            if !*succeeds {
+                assert_eq!(
+                    result.unwrap_err().unwrap(),
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(PaymentProcessorError::InvalidOrderData as u32)
+                    )
+                );
+                continue;
+            }
+            assert_matches!(result, Ok(()));
+            let order_account = merchant_result
+                .2
+                .get_account(order_acc_pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+            assert_eq!(1600000, order_data.paid_amount);
+            assert_eq!(
+                Some(OrderDiscount {
+                    coupon: bundle.to_string(),
+                    code: String::from("duo"),
+                    amount: 400000,
+                }),
+                get_order_discount(&order_data)
+            );
+
+            // the discount counts towards the price of the package
+            let mut transaction = Transaction::new_with_payer(
+                &[subscribe(
+                    program_id,
+                    SubscribeArgs {
+                        signer: payer,
+                        subscription: subscription_b,
+                        merchant: merchant_b,
+                        order: order_acc_pubkey,
+                        name: String::from("premium"),
+                        data: Option::None,
+                        seats: Option::None,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            assert_matches!(
+                merchant_result.2.process_transaction(transaction).await,
+                Ok(())
+            );
+            let subscription_account = merchant_result
+                .2
+                .get_account(subscription_b)
+                .await
+                .unwrap()
+                .unwrap();
+            let subscription_data =
+                SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+            assert_eq!(merchant_b.to_bytes(), subscription_data.merchant);
+            assert_eq!(String::from("premium"), subscription_data.name);
+        }
+    }
+
+    /// a swap program that pays out twice the amount swapped from the pool
+    /// token account of the settlement mint, owned by its authority [swap]
+    fn process_swap(
+        program_id: &Pubkey,
+        accounts: &[solana_program::account_info::AccountInfo],
+        instruction_data: &[u8],
+    ) -> solana_program::entrypoint::ProgramResult {
+        let amount_in = u64::from_le_bytes(instruction_data[1..9].try_into().unwrap());
+        let minimum_amount_out = u64::from_le_bytes(instruction_data[9..17].try_into().unwrap());
+        let amount_out = amount_in * 2;
+        if amount_out < minimum_amount_out {
+            return Err(solana_program::program_error::ProgramError::Custom(0));
+        }
+        let swap = accounts[0].key.to_bytes();
+        let (_authority, bump_seed) = Pubkey::find_program_address(&[&swap], program_id);
+        solana_program::program::invoke(
+            &spl_token::instruction::transfer(
+                accounts[9].key,
+                accounts[3].key,
+                accounts[4].key,
+                accounts[2].key,
+                &[],
+                amount_in,
+            )
+            .unwrap(),
+            &[
+                accounts[3].clone(),
+                accounts[4].clone(),
+                accounts[2].clone(),
+                accounts[9].clone(),
+            ],
+        )?;
+        solana_program::program::invoke_signed(
+            &spl_token::instruction::transfer(
+                accounts[9].key,
+                accounts[5].key,
+                accounts[6].key,
+                accounts[1].key,
+                &[],
+                amount_out,
+            )
+            .unwrap(),
+            &[
+                accounts[5].clone(),
+                accounts[6].clone(),
+                accounts[1].clone(),
+                accounts[9].clone(),
+            ],
+            &[&[&swap, &[bump_seed]]],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_settled() {
+        let program_id = Pubkey::from_str("mosh111111111111111111111111111111111111111").unwrap();
+        let swap_program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "sol_payment_processor",
+            program_id,
+            processor!(PaymentProcessorInstruction::process),
+        );
+        program_test.add_program("swap", swap_program_id, processor!(process_swap));
+        add_program_data(&mut program_test, &program_id);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let upgrade_authority = get_test_upgrade_authority();
+        let merchant = Pubkey::create_with_seed(&payer.pubkey(), MERCHANT, &program_id).unwrap();
+
+        // call initialize program ix with a swap program and register merchant ix
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                initialize_program(
+                    program_id,
+                    InitializeProgramArgs {
+                        signer: upgrade_authority.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        default_fee: Option::None,
+                        min_fee: Option::None,
+                        sponsor_fee: Option::None,
+                        name: String::from(DEFAULT_PROGRAM_NAME),
+                        data: Some(
+                            json!({ SWAP_PROGRAM: swap_program_id.to_string() }).to_string(),
+                        ),
+                    },
+                ),
+                register_merchant(
+                    program_id,
+                    RegisterMerchantArgs {
+                        signer: payer.pubkey(),
+                        merchant,
+                        seed: Some(String::from(MERCHANT)),
+                        fee: Option::None,
+                        data: Option::None,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
+                    },
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let mut merchant_result: MerchantResult =
+            (program_id, merchant, banks_client, payer, recent_blockhash);
+        let mint_keypair = Keypair::new();
+        let (order, seller_token) = create_order_express_checkout(
+            1000000,
+            &String::from("SETTLED"),
+            &String::from(""),
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        // create the settlement mint, the pool token accounts and the merchant token account
+        let settlement_mint_keypair = Keypair::new();
+        let swap = Pubkey::new_unique();
+        let (swap_authority, _bump_seed) =
+            Pubkey::find_program_address(&[&swap.to_bytes()], &swap_program_id);
+        let pool_source_keypair = Keypair::new();
+        let pool_destination_keypair = Keypair::new();
+        let merchant_token_keypair = Keypair::new();
+        let payer = merchant_result.3.pubkey();
+        for transaction in [
+            create_mint_transaction(
+                &merchant_result.3,
+                &settlement_mint_keypair,
+                &merchant_result.3,
+                merchant_result.4,
+            ),
+            create_token_account_transaction(
+                &merchant_result.3,
+                &mint_keypair,
+                merchant_result.4,
+                &pool_source_keypair,
+                &swap_authority,
+                0,
+            ),
+            create_token_account_transaction(
+                &merchant_result.3,
+                &settlement_mint_keypair,
+                merchant_result.4,
+                &pool_destination_keypair,
+                &swap_authority,
+                10000000,
+            ),
+            create_token_account_transaction(
+                &merchant_result.3,
+                &settlement_mint_keypair,
+                merchant_result.4,
+                &merchant_token_keypair,
+                &payer,
+                0,
+            ),
+        ]
+        .iter()
+        {
+            assert_matches!(
+                merchant_result
+                    .2
+                    .process_transaction(transaction.clone())
+                    .await,
+                Ok(())
+            );
+        }
+
+        // call set settlement mint ix
+        let mut transaction = Transaction::new_with_payer(
+            &[set_settlement_mint(
+                program_id,
+                payer,
+                merchant,
+                settlement_mint_keypair.pubkey(),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (settlement, _bump_seed) = get_settlement_address(&program_id, &merchant);
+        let settlement_account = merchant_result.2.get_account(settlement).await;
+        let settlement_data = SettlementAccount::unpack(&settlement_account.unwrap().unwrap().data);
+        assert_eq!(
+            settlement_mint_keypair.pubkey().to_bytes(),
+            settlement_data.unwrap().mint
+        );
+
+        // the swap fails when it pays out less than the minimum amount out
+        let pool = vec![
+            swap,
+            swap_authority,
+            pool_source_keypair.pubkey(),
+            pool_destination_keypair.pubkey(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        for (minimum_amount_out, succeeds) in [(3000000, false), (2000000, true)].iter() {
+            let mut transaction = Transaction::new_with_payer(
+                &[withdraw_settled(
+                    program_id,
+                    WithdrawSettledArgs {
+                        signer: payer,
+                        order,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: merchant_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: payer,
+                        swap_program: swap_program_id,
+                        pool: pool.clone(),
+                        subscription: Option::None,
+                        minimum_amount_out: *minimum_amount_out,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            assert_eq!(*succeeds, result.is_ok());
+        }
+
+        // the merchant got paid in the settlement mint
+        let merchant_token_account = merchant_result
+            .2
+            .get_account(merchant_token_keypair.pubkey())
+            .await;
+        let merchant_token_data =
+            TokenAccount::unpack(&merchant_token_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(2000000, merchant_token_data.amount);
+        let pool_source_account = merchant_result
+            .2
+            .get_account(pool_source_keypair.pubkey())
+            .await;
+        let pool_source_data =
+            TokenAccount::unpack(&pool_source_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(1000000, pool_source_data.amount);
+        let order_account = merchant_result.2.get_account(order).await;
+        let order_data = OrderAccount::unpack(&order_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+        // the order token account was closed
+        let order_token_account = merchant_result.2.get_account(seller_token).await;
+        assert_eq!(None, order_token_account.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_post_order_note() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let (order, _seller_token) = create_order_express_checkout(
+            1000000,
+            &String::from("NOTED"),
+            &String::from(""),
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        // the buyer posts the first note
+        let mut transaction = Transaction::new_with_payer(
+            &[post_order_note(
+                program_id,
+                payer,
+                order,
+                merchant,
+                0,
+                String::from("bGVhdmUgYXQgdGhlIGRvb3I="),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // nobody else can post notes on the order
+        let stranger_keypair = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::transfer(&payer, &stranger_keypair.pubkey(), 10000000),
+                post_order_note(
+                    program_id,
+                    stranger_keypair.pubkey(),
+                    order,
+                    merchant,
+                    1,
+                    String::from("c3BhbQ=="),
+                ),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &stranger_keypair], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
+
+        // notes are appended, so the first one cannot be posted again
+        let mut transaction = Transaction::new_with_payer(
+            &[post_order_note(
+                program_id,
+                payer,
+                order,
+                merchant,
+                0,
+                String::from("b3ZlcndyaXR0ZW4="),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
+
+        let (notes, _bump_seed) = get_order_notes_address(&program_id, &order);
+        let notes_account = merchant_result.2.get_account(notes).await;
+        let notes_data = OrderNotesAccount::unpack(&notes_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(Discriminator::OrderNotes as u8, notes_data.discriminator);
+        assert_eq!(order.to_bytes(), notes_data.order);
+        assert_eq!(1, notes_data.count);
+
+        let (order_note, _bump_seed) = get_order_note_address(&program_id, &order, 0);
+        let note_account = merchant_result.2.get_account(order_note).await;
+        let note_data = OrderNoteAccount::unpack(&note_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(Discriminator::OrderNote as u8, note_data.discriminator);
+        assert_eq!(0, note_data.index);
+        assert_eq!(payer.to_bytes(), note_data.author);
+        assert!(note_data.posted > 0);
+        assert_eq!(String::from("bGVhdmUgYXQgdGhlIGRvb3I="), note_data.note);
+    }
+
+    #[tokio::test]
+    async fn test_update_merchant() {
+        let mut merchant_result = create_merchant_account(
+            Option::None,
+            Some(20000),
+            Option::None,
+            Some(String::from(r#"{"name": "The Corner Shop"}"#)),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let sponsor = Pubkey::new_unique();
+        let merchant_size = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap()
+            .data
+            .len();
+
+        // only the fields that are set are updated
+        let mut transaction = Transaction::new_with_payer(
+            &[update_merchant(
+                program_id,
+                payer,
+                merchant,
+                Some(DEFAULT_FEE_IN_LAMPORTS * 2),
+                Some(sponsor),
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(DEFAULT_FEE_IN_LAMPORTS * 2, merchant_data.fee);
+        assert_eq!(sponsor.to_bytes(), merchant_data.sponsor);
+        assert_eq!(
+            String::from(r#"{"name": "The Corner Shop"}"#),
+            merchant_data.data
+        );
+
+        // shorter data is padded so that the merchant account keeps its size
+        let mut transaction = Transaction::new_with_payer(
+            &[update_merchant(
+                program_id,
+                payer,
+                merchant,
+                Option::None,
+                Option::None,
+                Some(String::from(r#"{"name": "Shop"}"#)),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(merchant_size, merchant_account.data.len());
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(DEFAULT_FEE_IN_LAMPORTS * 2, merchant_data.fee);
+        assert_eq!(Discriminator::Merchant as u8, merchant_data.discriminator);
+        let json_data: Value = serde_json::from_str(&merchant_data.data).unwrap();
+        assert_eq!(json!({"name": "Shop"}), json_data);
 
-    fn create_mint_transaction(
-        payer: &Keypair,
-        mint: &Keypair,
-        mint_authority: &Keypair,
-        recent_blockhash: Hash,
-    ) -> Transaction {
-        let instructions = [
-            system_instruction::create_account(
-                &payer.pubkey(),
-                &mint.pubkey(),
-                Rent::default().minimum_balance(Mint::LEN),
-                Mint::LEN as u64,
-                &spl_token::id(),
+        // data that does not fit is rejected, and so is anyone but the merchant owner
+        let stranger_keypair = Keypair::new();
+        for (signer, data) in [
+            (
+                &merchant_result.3,
+                r#"{"name": "The Corner Shop & Bakery"}"#,
             ),
-            initialize_mint(
-                &spl_token::id(),
-                &mint.pubkey(),
-                &mint_authority.pubkey(),
-                None,
-                0,
-            )
-            .unwrap(),
-        ];
-        let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
-        transaction.partial_sign(&[payer, mint], recent_blockhash);
-        transaction
+            (&stranger_keypair, r#"{"name": "Mine"}"#),
+        ]
+        .iter()
+        {
+            let mut transaction = Transaction::new_with_payer(
+                &[update_merchant(
+                    program_id,
+                    signer.pubkey(),
+                    merchant,
+                    Option::None,
+                    Option::None,
+                    Some(String::from(*data)),
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3, *signer], merchant_result.4);
+            assert!(merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .is_err());
+        }
     }
 
-    fn create_token_account_transaction(
-        payer: &Keypair,
-        mint: &Keypair,
-        recent_blockhash: Hash,
-        token_account: &Keypair,
-        token_account_owner: &Pubkey,
-        amount: u64,
-    ) -> Transaction {
-        let instructions = [
-            system_instruction::create_account(
-                &payer.pubkey(),
-                &token_account.pubkey(),
-                Rent::default().minimum_balance(TokenAccount::LEN),
-                TokenAccount::LEN as u64,
-                &spl_token::id(),
-            ),
-            initialize_account(
-                &spl_token::id(),
-                &token_account.pubkey(),
-                &mint.pubkey(),
-                token_account_owner,
-            )
-            .unwrap(),
-            mint_to(
-                &spl_token::id(),
-                &mint.pubkey(),
-                &token_account.pubkey(),
-                token_account_owner,
-                &[&payer.pubkey()],
-                amount,
-            )
-            .unwrap(),
-        ];
-        let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
-        transaction.partial_sign(&[payer, token_account], recent_blockhash);
-        transaction
+    #[tokio::test]
+    async fn test_split_order() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let (order, order_token) = create_order_express_checkout(
+            1000000,
+            &String::from("SHIPMENT"),
+            &String::from(""),
+            Some(String::from(r#"{"escrow": 1209600}"#)),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+
+        // the merchant cannot split off the whole order
+        let (sub_order, _bump_seed) = get_order_address(&program_id, &merchant, "SHIPMENT-2");
+        for (amount, succeeds) in [(1000000, false), (400000, true)].iter() {
+            let mut transaction = Transaction::new_with_payer(
+                &[split_order(
+                    program_id,
+                    SplitOrderArgs {
+                        signer: payer,
+                        order,
+                        sub_order,
+                        merchant,
+                        mint: mint_keypair.pubkey(),
+                        amount: *amount,
+                        order_id: String::from("SHIPMENT-2"),
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            assert_eq!(*succeeds, result.is_ok());
+        }
+
+        // the paid amount and the tokens are shared between the two orders
+        let sub_order_token =
+            get_order_token_address(&program_id, &sub_order, &mint_keypair.pubkey());
+        for (key, token, paid_amount) in [
+            (order, order_token, 600000),
+            (sub_order, sub_order_token, 400000),
+        ]
+        .iter()
+        {
+            let order_account = merchant_result.2.get_account(*key).await;
+            let order_data = OrderAccount::unpack(&order_account.unwrap().unwrap().data).unwrap();
+            assert_eq!(OrderStatus::Paid as u8, order_data.status);
+            assert_eq!(*paid_amount, order_data.paid_amount);
+            assert_eq!(*paid_amount, order_data.expected_amount);
+            assert_eq!(token.to_bytes(), order_data.token);
+            let token_account = merchant_result.2.get_account(*token).await;
+            let token_data = TokenAccount::unpack(&token_account.unwrap().unwrap().data).unwrap();
+            assert_eq!(*paid_amount, token_data.amount);
+        }
+        let sub_order_account = merchant_result.2.get_account(sub_order).await;
+        let sub_order_data =
+            OrderAccount::unpack(&sub_order_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(String::from("SHIPMENT-2"), sub_order_data.order_id);
+        assert_eq!(None, get_order_fees(&sub_order_data));
+        let json_data: Value = serde_json::from_str(&sub_order_data.data).unwrap();
+        assert_eq!(1209600, json_data["escrow"]);
+        assert_eq!(order.to_string(), json_data["_parent"]);
+
+        // the buyer confirms the delivery of the sub-order only
+        let mut transaction = Transaction::new_with_payer(
+            &[confirm_delivery(program_id, payer, sub_order, None)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result.2.get_account(order).await;
+        let order_data = OrderAccount::unpack(&order_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        let sub_order_account = merchant_result.2.get_account(sub_order).await;
+        let sub_order_data =
+            OrderAccount::unpack(&sub_order_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(OrderStatus::Releasable as u8, sub_order_data.status);
     }
 
-    async fn create_merchant_account(
-        seed: Option<String>,
-        fee: Option<u64>,
-        sponsor: Option<&Pubkey>,
-        data: Option<String>,
-    ) -> MerchantResult {
-        let program_id = Pubkey::from_str(&"mosh111111111111111111111111111111111111111").unwrap();
+    #[tokio::test]
+    async fn test_merchant_transfer() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let new_owner_keypair = Keypair::new();
+        let new_owner = new_owner_keypair.pubkey();
+        let stranger_keypair = Keypair::new();
+        let (transfer, _bump_seed) = get_merchant_transfer_address(&program_id, &merchant);
+        let (recovery, _bump_seed) = get_merchant_recovery_address(&program_id, &merchant);
 
-        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
-            "sol_payment_processor",
-            program_id,
-            processor!(PaymentProcessorInstruction::process),
-        )
-        .start()
-        .await;
+        // the previous owner has an operator and a recovery key
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_operators(program_id, payer, merchant, vec![stranger_keypair.pubkey()]),
+                set_merchant_recovery(
+                    program_id,
+                    payer,
+                    merchant,
+                    stranger_keypair.pubkey(),
+                    3600,
+                    3600,
+                ),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
 
-        let real_seed = match &seed {
-            None => MERCHANT,
-            Some(value) => &value,
-        };
+        // only the merchant owner can propose a transfer
+        let mut transaction = Transaction::new_with_payer(
+            &[propose_merchant_transfer(
+                program_id,
+                stranger_keypair.pubkey(),
+                merchant,
+                stranger_keypair.pubkey(),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &stranger_keypair], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
 
-        // first we create a public key for the merchant account
-        let merchant_acc_pubkey =
-            Pubkey::create_with_seed(&payer.pubkey(), real_seed, &program_id).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[propose_merchant_transfer(
+                program_id, payer, merchant, new_owner,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let transfer_account = merchant_result.2.get_account(transfer).await;
+        let transfer_data =
+            MerchantTransferAccount::unpack(&transfer_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(
+            Discriminator::MerchantTransfer as u8,
+            transfer_data.discriminator
+        );
+        assert_eq!(merchant.to_bytes(), transfer_data.merchant);
+        assert_eq!(new_owner.to_bytes(), transfer_data.new_owner);
+
+        // the merchant account is untouched until the new owner accepts
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(payer.to_bytes(), merchant_data.owner);
 
-        // then call register merchant ix
+        // only the proposed new owner can accept the transfer
         let mut transaction = Transaction::new_with_payer(
-            &[register_merchant(
+            &[accept_merchant_transfer(
                 program_id,
-                payer.pubkey(),
-                merchant_acc_pubkey,
-                Some(real_seed.to_string()),
-                fee,
-                data,
-                sponsor,
+                stranger_keypair.pubkey(),
+                merchant,
+                payer,
             )],
-            Some(&payer.pubkey()),
+            Some(&payer),
         );
-        transaction.sign(&[&payer], recent_blockhash);
-        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
-        return (
-            program_id,
-            merchant_acc_pubkey,
-            banks_client,
-            payer,
-            recent_blockhash,
+        transaction.sign(&[&merchant_result.3, &stranger_keypair], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
         );
-    }
 
-    async fn prepare_order(
-        program_id: &Pubkey,
-        merchant: &Pubkey,
-        mint: &Pubkey,
-        banks_client: &mut BanksClient,
-    ) -> (Keypair, Pubkey, Pubkey, MerchantAccount) {
-        let order_acc_keypair = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[accept_merchant_transfer(
+                program_id, new_owner, merchant, payer,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(new_owner.to_bytes(), merchant_data.owner);
+        // without the operators of the previous owner
+        assert_eq!(0, merchant_data.operator_count);
+        assert!(!merchant_data
+            .operators
+            .contains(&stranger_keypair.pubkey().to_bytes()));
 
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        // the merchant transfer and recovery accounts are closed
+        let transfer_account = merchant_result.2.get_account(transfer).await.unwrap();
+        assert!(transfer_account.is_none());
+        let recovery_account = merchant_result.2.get_account(recovery).await.unwrap();
+        assert!(recovery_account.is_none());
 
-        let (seller_token, _bump_seed) = Pubkey::find_program_address(
+        // the previous owner can no longer update the merchant account
+        let mut transaction = Transaction::new_with_payer(
+            &[update_merchant(
+                program_id,
+                payer,
+                merchant,
+                Some(DEFAULT_FEE_IN_LAMPORTS * 2),
+                Option::None,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_after_merchant_transfer() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let new_owner_keypair = Keypair::new();
+        let new_owner = new_owner_keypair.pubkey();
+        let payout_wallet = Pubkey::new_unique();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
+        let new_owner_token_keypair = Keypair::new();
+
+        // the previous owner is paid out to a wallet of their own
+        let mut transaction = Transaction::new_with_payer(
             &[
-                &order_acc_keypair.pubkey().to_bytes(),
-                &spl_token::id().to_bytes(),
-                &mint.to_bytes(),
+                set_payout_schedule(program_id, payer, merchant, vec![(payout_wallet, 10000)]),
+                create_associated_token_account_instruction(&payer, &payout_wallet, &mint),
             ],
-            program_id,
+            Some(&payer),
         );
-
-        let merchant_account = banks_client.get_account(*merchant).await;
-        let merchant_data = match merchant_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => match MerchantAccount::unpack(&value.data) {
-                    Ok(data) => data,
-                    Err(error) => panic!("Problem: {:?}", error),
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    &mint_keypair,
+                    merchant_result.4,
+                    &new_owner_token_keypair,
+                    &new_owner,
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
                 },
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
 
-        (order_acc_keypair, seller_token, pda, merchant_data)
-    }
+        // the merchant account is handed over before the order is withdrawn
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                propose_merchant_transfer(program_id, payer, merchant, new_owner),
+                accept_merchant_transfer(program_id, new_owner, merchant, payer),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(0, merchant_data.payout_count);
+        assert!(!merchant_data
+            .payout_wallets
+            .contains(&payout_wallet.to_bytes()));
 
-    async fn create_token_account(
-        amount: u64,
-        mint_keypair: &Keypair,
-        merchant_result: &mut MerchantResult,
-    ) -> Keypair {
-        // next create token account for test
-        let buyer_token_keypair = Keypair::new();
+        // the withdrawal goes to the new owner alone, even with the payout
+        // wallet of the previous owner passed along
+        let mut transaction = Transaction::new_with_payer(
+            &[use_payout_schedule(
+                withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: new_owner,
+                        order: order_acc_pubkey,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: new_owner_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: new_owner,
+                        pda,
+                        subscription: Option::None,
+                        close_order_account: false,
+                        other_tokens: vec![],
+                    },
+                ),
+                vec![payout_wallet],
+                spl_token::id(),
+                mint,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner_keypair], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        for (token, amount) in [
+            (new_owner_token_keypair.pubkey(), 2000),
+            (
+                get_associated_token_address(&payout_wallet, &spl_token::id(), &mint),
+                0,
+            ),
+        ] {
+            let token_account = merchant_result.2.get_account(token).await.unwrap().unwrap();
+            let token_data = TokenAccount::unpack(&token_account.data).unwrap();
+            assert_eq!(amount, token_data.amount);
+        }
+    }
 
-        // create and initialize mint
+    #[tokio::test]
+    async fn test_merchant_stats() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let (stats, _bump_seed) = get_merchant_stats_address(&program_id, &merchant);
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+        let merchant_token_keypair = Keypair::new();
         assert_matches!(
             merchant_result
                 .2
-                .process_transaction(create_mint_transaction(
+                .process_transaction(create_token_account_transaction(
                     &merchant_result.3,
                     &mint_keypair,
-                    &merchant_result.3,
-                    merchant_result.4
+                    merchant_result.4,
+                    &merchant_token_keypair,
+                    &payer,
+                    0,
                 ))
                 .await,
             Ok(())
         );
-        // create and initialize buyer token account
+
+        // the cap cannot be 0, nor above MAX_OPEN_TOKEN_ACCOUNTS
+        for (max_open_token_accounts, succeeds) in
+            [(0, false), (MAX_OPEN_TOKEN_ACCOUNTS + 1, false), (1, true)].iter()
+        {
+            let mut transaction = Transaction::new_with_payer(
+                &[set_merchant_stats(
+                    program_id,
+                    payer,
+                    merchant,
+                    *max_open_token_accounts,
+                    Option::None,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            assert_eq!(*succeeds, result.is_ok());
+        }
+
+        // checkouts that include the stats account are counted, up to the cap
+        let mut orders = vec![];
+        for (order_id, succeeds) in [("1", true), ("2", false)].iter() {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some(*order_id),
+                &mint_keypair.pubkey(),
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[track_token_accounts(
+                    express_checkout(
+                        program_id,
+                        ExpressCheckoutArgs {
+                            signer: payer,
+                            order: order_acc_pubkey,
+                            merchant,
+                            seller_token,
+                            buyer_token: buyer_token_keypair.pubkey(),
+                            mint: mint_keypair.pubkey(),
+                            program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                            pda,
+                            integrator: Option::None,
+                            coupon: Option::None,
+                            link: Option::None,
+                            amount: 1000,
+                            tip: 0,
+                            order_id: String::from(*order_id),
+                            secret: String::from(""),
+                            data: Option::None,
+                        },
+                    ),
+                    merchant,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            let result = merchant_result.2.process_transaction(transaction).await;
+            assert_eq!(*succeeds, result.is_ok());
+            orders.push((order_acc_pubkey, seller_token, pda));
+        }
+        let stats_account = merchant_result.2.get_account(stats).await;
+        let stats_data =
+            MerchantStatsAccount::unpack(&stats_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(Discriminator::MerchantStats as u8, stats_data.discriminator);
+        assert_eq!(merchant.to_bytes(), stats_data.merchant);
+        assert_eq!(1, stats_data.open_token_accounts);
+        assert_eq!(1, stats_data.max_open_token_accounts);
+        assert_eq!(1, stats_data.opened);
+
+        // withdrawing the order closes its token account, which is counted too
+        let (order, order_token, pda) = orders[0];
+        let mut transaction = Transaction::new_with_payer(
+            &[track_token_accounts(
+                withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: payer,
+                        order,
+                        merchant,
+                        order_payment_token: order_token,
+                        merchant_token: merchant_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: payer,
+                        pda,
+                        subscription: Option::None,
+                        close_order_account: false,
+                        other_tokens: vec![],
+                    },
+                ),
+                merchant,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let stats_account = merchant_result.2.get_account(stats).await;
+        let stats_data =
+            MerchantStatsAccount::unpack(&stats_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(0, stats_data.open_token_accounts);
+        assert_eq!(1, stats_data.closed);
+
+        // the merchant owner can correct the count, e.g. from a hygiene report
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_stats(program_id, payer, merchant, 10, Some(3))],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let stats_account = merchant_result.2.get_account(stats).await;
+        let stats_data =
+            MerchantStatsAccount::unpack(&stats_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(3, stats_data.open_token_accounts);
+        assert_eq!(10, stats_data.max_open_token_accounts);
+        assert_eq!(1, stats_data.opened);
+        assert_eq!(1, stats_data.closed);
+    }
+
+    #[tokio::test]
+    async fn test_close_merchant() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+        let merchant_token_keypair = Keypair::new();
         assert_matches!(
             merchant_result
                 .2
@@ -635,1542 +13982,3079 @@ mod test {
                     &merchant_result.3,
                     &mint_keypair,
                     merchant_result.4,
-                    &buyer_token_keypair,
-                    &merchant_result.3.pubkey(),
-                    amount + 2000000,
+                    &merchant_token_keypair,
+                    &payer,
+                    0,
                 ))
                 .await,
             Ok(())
         );
 
-        buyer_token_keypair
-    }
-
-    async fn create_order_express_checkout(
-        amount: u64,
-        order_id: &String,
-        secret: &String,
-        data: Option<String>,
-        merchant_result: &mut MerchantResult,
-        mint_keypair: &Keypair,
-    ) -> (Pubkey, Pubkey) {
-        let buyer_token_keypair = create_token_account(amount, mint_keypair, merchant_result).await;
-        let (order_acc_keypair, seller_token, pda, merchant_data) = prepare_order(
-            &merchant_result.0,
-            &merchant_result.1,
+        // the order counts towards the references of the merchant
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
             &mint_keypair.pubkey(),
             &mut merchant_result.2,
         )
         .await;
-
-        // call express checkout ix
+        assert_eq!(0, merchant_data.references);
         let mut transaction = Transaction::new_with_payer(
             &[express_checkout(
-                merchant_result.0,
-                merchant_result.3.pubkey(),
-                order_acc_keypair.pubkey(),
-                merchant_result.1,
-                seller_token,
-                buyer_token_keypair.pubkey(),
-                mint_keypair.pubkey(),
-                Pubkey::from_str(PROGRAM_OWNER).unwrap(),
-                Pubkey::new_from_array(merchant_data.sponsor),
-                pda,
-                amount,
-                (&order_id).to_string(),
-                (&secret).to_string(),
-                data,
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 1000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
             )],
-            Some(&merchant_result.3.pubkey()),
+            Some(&payer),
         );
-        transaction.sign(&[&merchant_result.3, &order_acc_keypair], merchant_result.4);
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
         assert_matches!(
-            &mut merchant_result.2.process_transaction(transaction).await,
+            merchant_result.2.process_transaction(transaction).await,
             Ok(())
         );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert_eq!(1, merchant_data.references);
 
-        (order_acc_keypair.pubkey(), seller_token)
-    }
-
-    async fn create_chain_checkout_transaction(
-        amount: u64,
-        order_items: &OrderItems,
-        data: Option<String>,
-        merchant_result: &mut MerchantResult,
-        mint_keypair: &Keypair,
-    ) -> Result<(Pubkey, Pubkey), TransportError> {
-        let buyer_token_keypair = create_token_account(amount, mint_keypair, merchant_result).await;
-        let (order_acc_keypair, seller_token, pda, merchant_data) = prepare_order(
-            &merchant_result.0,
-            &merchant_result.1,
-            &mint_keypair.pubkey(),
-            &mut merchant_result.2,
-        )
-        .await;
-        let order_items = order_items.clone();
-
-        // call chain checkout ix
+        // the merchant account cannot be closed while the order is open
         let mut transaction = Transaction::new_with_payer(
-            &[chain_checkout(
-                merchant_result.0,
-                merchant_result.3.pubkey(),
-                order_acc_keypair.pubkey(),
-                merchant_result.1,
-                seller_token,
-                buyer_token_keypair.pubkey(),
-                mint_keypair.pubkey(),
-                Pubkey::from_str(PROGRAM_OWNER).unwrap(),
-                Pubkey::new_from_array(merchant_data.sponsor),
-                pda,
-                amount,
-                order_items,
-                data,
-            )],
-            Some(&merchant_result.3.pubkey()),
+            &[close_merchant(program_id, payer, merchant, vec![])],
+            Some(&payer),
         );
-        transaction.sign(&[&merchant_result.3, &order_acc_keypair], merchant_result.4);
-        let _result = merchant_result.2.process_transaction(transaction).await?;
-        Ok((order_acc_keypair.pubkey(), seller_token))
-    }
-
-    async fn create_order_chain_checkout(
-        amount: u64,
-        order_items: &OrderItems,
-        data: Option<String>,
-        merchant_result: &mut MerchantResult,
-        mint_keypair: &Keypair,
-    ) -> (Pubkey, Pubkey) {
-        let transaction = create_chain_checkout_transaction(
-            amount,
-            order_items,
-            data,
-            merchant_result,
-            mint_keypair,
-        )
-        .await;
-
-        assert!(transaction.is_ok());
-        transaction.unwrap()
-    }
-
-    async fn run_merchant_tests(result: MerchantResult) -> MerchantAccount {
-        let program_id = result.0;
-        let merchant = result.1;
-        let mut banks_client = result.2;
-        let payer = result.3;
-        // test contents of merchant account
-        let merchant_account = banks_client.get_account(merchant).await;
-        let merchant_account = match merchant_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => value,
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        assert_eq!(merchant_account.owner, program_id);
-        let merchant_data = MerchantAccount::unpack(&merchant_account.data);
-        let merchant_data = match merchant_data {
-            Ok(data) => data,
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        assert_eq!(true, merchant_data.is_initialized());
-        assert_eq!(payer.pubkey(), Pubkey::new_from_array(merchant_data.owner));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
 
-        merchant_data
+        // once the order is withdrawn the merchant account can be closed, and its
+        // rent goes to the owner
+        let payer_lamports = merchant_result.2.get_balance(payer).await.unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: merchant_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: payer,
+                        pda,
+                        subscription: Option::None,
+                        close_order_account: false,
+                        other_tokens: vec![],
+                    },
+                ),
+                close_merchant(program_id, payer, merchant, vec![]),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_eq!(None, merchant_result.2.get_account(merchant).await.unwrap());
+        assert!(merchant_result.2.get_balance(payer).await.unwrap() > payer_lamports);
     }
 
     #[tokio::test]
-    async fn test_register_merchant() {
-        let result =
+    async fn test_close_order() {
+        let mut merchant_result =
             create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
-        let merchant_data = run_merchant_tests(result).await;
-        assert_eq!(DEFAULT_FEE_IN_LAMPORTS, merchant_data.fee);
-        assert_eq!(String::from("{}"), merchant_data.data);
-    }
-
-    #[tokio::test]
-    async fn test_register_merchant_with_seed() {
-        let result = create_merchant_account(
-            Some(String::from("mosh")),
-            Option::None,
-            Option::None,
-            Option::None,
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+        let merchant_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    &mint_keypair,
+                    merchant_result.4,
+                    &merchant_token_keypair,
+                    &payer,
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
         .await;
-        let merchant = result.1;
-        let payer = result.3;
-        let program_id = result.0;
-        assert_eq!(
-            merchant,
-            Pubkey::create_with_seed(&payer.pubkey(), "mosh", &program_id).unwrap()
-        );
-    }
-
-    #[tokio::test]
-    /// assert that the minimum fee is used when custom fee too low
-    async fn test_register_merchant_fee_default() {
-        let result =
-            create_merchant_account(Option::None, Some(10), Option::None, Option::None).await;
-        let merchant_data = run_merchant_tests(result).await;
-        assert_eq!(MIN_FEE_IN_LAMPORTS, merchant_data.fee);
-    }
-
-    #[tokio::test]
-    async fn test_register_merchant_with_all_stuff() {
-        let seed = String::from("mosh");
-        let sponsor_pk = Pubkey::new_unique();
-        let data = String::from(
-            r#"{"code":200,"success":true,"payload":{"features":["awesome","easyAPI","lowLearningCurve"]}}"#,
-        );
-        let datas = data.clone();
-        let result =
-            create_merchant_account(Some(seed), Some(90000), Some(&sponsor_pk), Some(data)).await;
-        let merchant_data = run_merchant_tests(result).await;
-        assert_eq!(datas, merchant_data.data);
-        assert_eq!(90000, merchant_data.fee);
-        assert_eq!(sponsor_pk, Pubkey::new_from_array(merchant_data.sponsor));
-        // just for sanity verify that you can get some of the JSON values
-        let json_value: Value = serde_json::from_str(&merchant_data.data).unwrap();
-        assert_eq!(200, json_value["code"]);
-        assert_eq!(true, json_value["success"]);
-    }
-
-    async fn run_common_checkout_tests(
-        amount: u64,
-        merchant_result: &mut MerchantResult,
-        order_acc_pubkey: &Pubkey,
-        seller_account_pubkey: &Pubkey,
-        mint_keypair: &Keypair,
-    ) -> OrderAccount {
-        // program_id => merchant_result.0;
-        // merchant_account_pubkey => merchant_result.1;
-        // banks_client => merchant_result.2;
-        // payer => merchant_result.3;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 1000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_lamports = merchant_result
+            .2
+            .get_balance(order_acc_pubkey)
+            .await
+            .unwrap();
+        let recipient = Pubkey::new_unique();
 
-        let order_account = merchant_result.2.get_account(*order_acc_pubkey).await;
-        let order_account = match order_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => value,
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        assert_eq!(order_account.owner, merchant_result.0,);
+        // the order cannot be closed before it is settled
+        let mut transaction = Transaction::new_with_payer(
+            &[close_order(
+                program_id,
+                payer,
+                order_acc_pubkey,
+                merchant,
+                recipient,
+                pda,
+                vec![],
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
 
-        let order_data = OrderAccount::unpack(&order_account.data);
-        let order_data = match order_data {
-            Ok(data) => data,
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        assert_eq!(true, order_data.is_initialized());
-        assert_eq!(OrderStatus::Paid as u8, order_data.status);
-        assert_eq!(merchant_result.1.to_bytes(), order_data.merchant);
-        assert_eq!(mint_keypair.pubkey().to_bytes(), order_data.mint);
-        assert_eq!(seller_account_pubkey.to_bytes(), order_data.token);
-        assert_eq!(merchant_result.3.pubkey().to_bytes(), order_data.payer);
-        assert_eq!(amount, order_data.expected_amount);
-        assert_eq!(amount, order_data.paid_amount);
+        // once withdrawn, the order account is closed and its rent goes to the recipient
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: merchant_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: payer,
+                        pda,
+                        subscription: Option::None,
+                        close_order_account: false,
+                        other_tokens: vec![],
+                    },
+                ),
+                close_order(
+                    program_id,
+                    payer,
+                    order_acc_pubkey,
+                    merchant,
+                    recipient,
+                    pda,
+                    vec![],
+                ),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
         assert_eq!(
-            order_account.lamports,
-            Rent::default().minimum_balance(get_order_account_size(
-                &order_data.order_id,
-                &order_data.secret,
-                &order_data.data,
-            ))
+            None,
+            merchant_result
+                .2
+                .get_account(order_acc_pubkey)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            order_lamports,
+            merchant_result.2.get_balance(recipient).await.unwrap()
         );
+    }
 
-        // test contents of seller token account
-        let seller_token_account = merchant_result.2.get_account(*seller_account_pubkey).await;
-        let seller_token_account = match seller_token_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => value,
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        let seller_account_data = spl_token::state::Account::unpack(&seller_token_account.data);
-        let seller_account_data = match seller_account_data {
-            Ok(data) => data,
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &merchant_result.0);
-        assert_eq!(amount, seller_account_data.amount);
-        assert_eq!(pda, seller_account_data.owner);
-        assert_eq!(mint_keypair.pubkey(), seller_account_data.mint);
+    #[tokio::test]
+    async fn test_stores() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let fee_destination = Pubkey::new_unique();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+        let store_token_keypair = Keypair::new();
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(create_token_account_transaction(
+                    &merchant_result.3,
+                    &mint_keypair,
+                    merchant_result.4,
+                    &store_token_keypair,
+                    &fee_destination,
+                    0,
+                ))
+                .await,
+            Ok(())
+        );
 
-        // test that sponsor was saved okay
-        let merchant_account = merchant_result.2.get_account(merchant_result.1).await;
-        let merchant_data = match merchant_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => match MerchantAccount::unpack(&value.data) {
-                    Ok(data) => data,
-                    Err(error) => panic!("Problem: {:?}", error),
-                },
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
+        // the merchant opens a store that is paid out to another wallet
+        let (store, _bump_seed) = get_store_address(&program_id, &merchant, "downtown");
+        let mut transaction = Transaction::new_with_payer(
+            &[create_store(
+                program_id,
+                payer,
+                merchant,
+                String::from("downtown"),
+                Some(fee_destination),
+                Option::None,
+                Some(String::from(r#"{"city": "Nairobi"}"#)),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let store_account = merchant_result.2.get_account(store).await.unwrap().unwrap();
+        let store_data = StoreAccount::unpack(&store_account.data).unwrap();
+        assert_eq!(Discriminator::Store as u8, store_data.discriminator);
+        assert_eq!(merchant.to_bytes(), store_data.merchant);
+        assert_eq!(fee_destination.to_bytes(), store_data.fee_destination);
+        assert_eq!(String::from("downtown"), store_data.store_id);
+        assert_eq!(String::from(""), store_data.catalog);
+        assert_eq!(String::from(r#"{"city": "Nairobi"}"#), store_data.data);
 
-        let program_owner_key = Pubkey::from_str(PROGRAM_OWNER).unwrap();
-        let sponsor = Pubkey::new_from_array(merchant_data.sponsor);
+        // a checkout made at the store records the store on the order
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[use_store(
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount: 1000,
+                        tip: 0,
+                        order_id: String::from("1"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                ),
+                merchant,
+                "downtown",
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(Some(store), get_order_store(&order_data));
 
-        let program_owner_account = merchant_result.2.get_account(program_owner_key).await;
-        let program_owner_account = match program_owner_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => value,
+        // the payment can only go to the fee destination of the store along with the store
+        let instruction = withdraw(
+            program_id,
+            WithdrawArgs {
+                signer: payer,
+                order: order_acc_pubkey,
+                merchant,
+                order_payment_token: seller_token,
+                merchant_token: store_token_keypair.pubkey(),
+                account_to_receive_sol_refund: payer,
+                pda,
+                subscription: Option::None,
+                close_order_account: false,
+                other_tokens: vec![],
             },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-
-        if sponsor == program_owner_key {
-            // test contents of program owner account
-            assert_eq!(merchant_data.fee, program_owner_account.lamports);
-        } else {
-            // test contents of program owner account and sponsor account
-            let (program_owner_fee, sponsor_fee) = get_amounts(merchant_data.fee, SPONSOR_FEE);
-            let sponsor_account = merchant_result.2.get_account(sponsor).await;
-            let sponsor_account = match sponsor_account {
-                Ok(data) => match data {
-                    None => panic!("Oo"),
-                    Some(value) => value,
-                },
-                Err(error) => panic!("Problem: {:?}", error),
-            };
-            assert_eq!(program_owner_fee, program_owner_account.lamports);
-            assert_eq!(sponsor_fee, sponsor_account.lamports);
-        }
+        );
+        let mut transaction =
+            Transaction::new_with_payer(std::slice::from_ref(&instruction), Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert!(merchant_result
+            .2
+            .process_transaction(transaction)
+            .await
+            .is_err());
+        let mut transaction = Transaction::new_with_payer(
+            &[use_store(instruction, merchant, "downtown")],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let store_token_account = merchant_result
+            .2
+            .get_account(store_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let store_token_data =
+            spl_token::state::Account::unpack(&store_token_account.data).unwrap();
+        assert_eq!(1000, store_token_data.amount);
 
-        order_data
+        // the store can be closed to get its rent back
+        let mut transaction = Transaction::new_with_payer(
+            &[close_store(program_id, payer, merchant, "downtown")],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert_eq!(None, merchant_result.2.get_account(store).await.unwrap());
     }
 
-    async fn run_checkout_tests(
-        amount: u64,
-        order_id: String,
-        secret: String,
-        data: Option<String>,
-        merchant_result: &mut MerchantResult,
-        order_acc_pubkey: &Pubkey,
-        seller_account_pubkey: &Pubkey,
-        mint_keypair: &Keypair,
-    ) {
-        let order_data = run_common_checkout_tests(
-            amount,
-            merchant_result,
-            order_acc_pubkey,
-            seller_account_pubkey,
-            mint_keypair,
-        )
-        .await;
+    #[tokio::test]
+    async fn test_pause_merchant() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
 
-        let data_string = match data {
-            None => String::from("{}"),
-            Some(value) => value,
-        };
-        assert_eq!(order_id, order_data.order_id);
-        assert_eq!(secret, order_data.secret);
-        assert_eq!(data_string, order_data.data);
-    }
+        // only the merchant owner can pause the merchant
+        let someone = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[pause_merchant(program_id, someone.pubkey(), merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &someone], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongMerchant as u32)
+            )
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[pause_merchant(program_id, payer, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert!(merchant_data.is_paused);
 
-    async fn run_chain_checkout_tests(
-        amount: u64,
-        order_items: &OrderItems,
-        data: Option<String>,
-        merchant_result: &mut MerchantResult,
-        order_acc_pubkey: &Pubkey,
-        seller_account_pubkey: &Pubkey,
-        mint_keypair: &Keypair,
-    ) {
-        // test contents of order account
-        let order_data = run_common_checkout_tests(
-            amount,
-            merchant_result,
-            order_acc_pubkey,
-            seller_account_pubkey,
-            mint_keypair,
+        // checkouts fail while the merchant is paused
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
         .await;
-        match data {
-            None => {
-                assert_eq!(json!({ PAID: order_items }).to_string(), order_data.data);
-            }
-            Some(value) => {
-                let json_data: Value = match serde_json::from_str(&value) {
-                    Err(error) => panic!("Problem: {:?}", error),
-                    Ok(data) => data,
-                };
-                assert_eq!(
-                    json!({ INITIAL: json_data, PAID: order_items }).to_string(),
-                    order_data.data
-                );
-            }
-        }
+        let checkout = express_checkout(
+            program_id,
+            ExpressCheckoutArgs {
+                signer: payer,
+                order: order_acc_pubkey,
+                merchant,
+                seller_token,
+                buyer_token: buyer_token_keypair.pubkey(),
+                mint: mint_keypair.pubkey(),
+                program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                pda,
+                integrator: Option::None,
+                coupon: Option::None,
+                link: Option::None,
+                amount: 1000,
+                tip: 0,
+                order_id: String::from("1"),
+                secret: String::from(""),
+                data: Option::None,
+            },
+        );
+        let mut transaction =
+            Transaction::new_with_payer(std::slice::from_ref(&checkout), Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::MerchantPaused as u32)
+            )
+        );
+
+        // and go through again once it is resumed
+        let mut transaction = Transaction::new_with_payer(
+            &[resume_merchant(program_id, payer, merchant), checkout],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result.2.get_account(merchant).await;
+        let merchant_data =
+            MerchantAccount::unpack(&merchant_account.unwrap().unwrap().data).unwrap();
+        assert!(!merchant_data.is_paused);
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
     }
 
     #[tokio::test]
-    async fn test_chain_checkout() {
-        let mint_keypair = Keypair::new();
-        let amount: u64 = 2000000000;
-
-        let mut order_items: OrderItems = BTreeMap::new();
-        order_items.insert("1".to_string(), 1);
-        order_items.insert("3".to_string(), 1);
+    async fn test_accepted_mints() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let accepted_mint_keypair = Keypair::new();
+        let accepted_token_keypair =
+            create_token_account(3000, &accepted_mint_keypair, &mut merchant_result).await;
+        let other_mint_keypair = Keypair::new();
+        let other_token_keypair =
+            create_token_account(3000, &other_mint_keypair, &mut merchant_result).await;
 
-        let merchant_data = format!(
-            r#"{{
-            "1": {{"price": 2000000, "mint": "{mint_key}"}},
-            "2": {{"price": 3000000, "mint": "{mint_key}"}},
-            "3": {{"price": 4000000, "mint": "{mint_key}"}},
-            "4": {{"price": 4000000, "mint": "{mint_key}"}},
-            "5": {{"price": 4000000, "mint": "{mint_key}"}}
-        }}"#,
-            mint_key = mint_keypair.pubkey()
+        // the merchant only accepts one of the mints
+        let mut transaction = Transaction::new_with_payer(
+            &[set_accepted_mints(
+                program_id,
+                payer,
+                merchant,
+                vec![accepted_mint_keypair.pubkey()],
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (accepted_mints, _bump_seed) = get_accepted_mints_address(&program_id, &merchant);
+        let accepted_mints_account = merchant_result
+            .2
+            .get_account(accepted_mints)
+            .await
+            .unwrap()
+            .unwrap();
+        let accepted_mints_data =
+            AcceptedMintsAccount::unpack(&accepted_mints_account.data).unwrap();
+        assert_eq!(1, accepted_mints_data.count);
+        assert_eq!(
+            accepted_mint_keypair.pubkey().to_bytes(),
+            accepted_mints_data.mints[0]
         );
 
-        let mut merchant_result = create_merchant_account(
-            Some("chain".to_string()),
-            Option::None,
-            Option::None,
-            Some(merchant_data),
-        )
-        .await;
-        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
-            amount,
-            &order_items,
-            Option::None,
-            &mut merchant_result,
-            &mint_keypair,
+        let (order_acc_pubkey, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &accepted_mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
         .await;
+        assert!(merchant_data.restricts_mints);
+        let checkout = |mint: Pubkey, buyer_token: Pubkey| {
+            express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token: get_order_token_address(&program_id, &order_acc_pubkey, &mint),
+                    buyer_token,
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 1000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )
+        };
 
-        run_chain_checkout_tests(
-            amount,
-            &order_items,
-            Option::None,
-            &mut merchant_result,
-            &order_acc_pubkey,
-            &seller_account_pubkey,
-            &mint_keypair,
-        )
-        .await;
+        // a payment in another mint is rejected
+        let mut transaction = Transaction::new_with_payer(
+            &[use_accepted_mints(
+                checkout(other_mint_keypair.pubkey(), other_token_keypair.pubkey()),
+                merchant,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongMint as u32)
+            )
+        );
+
+        // the accepted mints account cannot be left out
+        let mut transaction = Transaction::new_with_payer(
+            &[checkout(
+                accepted_mint_keypair.pubkey(),
+                accepted_token_keypair.pubkey(),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+
+        // a payment in the accepted mint goes through
+        let mut transaction = Transaction::new_with_payer(
+            &[use_accepted_mints(
+                checkout(
+                    accepted_mint_keypair.pubkey(),
+                    accepted_token_keypair.pubkey(),
+                ),
+                merchant,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
     }
 
     #[tokio::test]
-    async fn test_chain_checkout_with_data() {
+    async fn test_block_buyer() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
         let mint_keypair = Keypair::new();
-        let amount: u64 = 2000000000;
-
-        let mut order_items: OrderItems = BTreeMap::new();
-        order_items.insert("1".to_string(), 1);
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
 
-        let merchant_data = format!(
-            r#"{{
-            "1": {{"price": 2000000, "mint": "{mint_key}"}},
-            "2": {{"price": 3000000, "mint": "{mint_key}"}}
-        }}"#,
-            mint_key = mint_keypair.pubkey()
+        // the merchant blocks the buyer
+        let mut transaction = Transaction::new_with_payer(
+            &[block_buyer(program_id, payer, merchant, payer)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (blocked_buyer, _bump_seed) = get_blocked_buyer_address(&program_id, &merchant, &payer);
+        let blocked_buyer_account = merchant_result
+            .2
+            .get_account(blocked_buyer)
+            .await
+            .unwrap()
+            .unwrap();
+        let blocked_buyer_data = BlockedBuyerAccount::unpack(&blocked_buyer_account.data).unwrap();
+        assert_eq!(
+            Discriminator::BlockedBuyer as u8,
+            blocked_buyer_data.discriminator
         );
+        assert_eq!(merchant.to_bytes(), blocked_buyer_data.merchant);
+        assert_eq!(payer.to_bytes(), blocked_buyer_data.buyer);
 
-        let mut merchant_result = create_merchant_account(
-            Some("chain2".to_string()),
-            Option::None,
-            Option::None,
-            Some(merchant_data),
-        )
-        .await;
-        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
-            amount,
-            &order_items,
-            Some(String::from(r#"{"foo": "bar"}"#)),
-            &mut merchant_result,
-            &mint_keypair,
+        let (order_acc_pubkey, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
         .await;
+        assert_eq!(1, merchant_data.blocked_buyers);
+        let checkout = |amount: u64| {
+            express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token: get_order_token_address(
+                        &program_id,
+                        &order_acc_pubkey,
+                        &mint_keypair.pubkey(),
+                    ),
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )
+        };
 
-        run_chain_checkout_tests(
-            amount,
-            &order_items,
-            Some(String::from(r#"{"foo": "bar"}"#)),
-            &mut merchant_result,
-            &order_acc_pubkey,
-            &seller_account_pubkey,
-            &mint_keypair,
-        )
-        .await;
+        // the blocked buyer address cannot be left out
+        let mut transaction = Transaction::new_with_payer(&[checkout(1000)], Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+
+        // a checkout by the blocked buyer is rejected
+        let mut transaction = Transaction::new_with_payer(
+            &[use_blocked_buyer(checkout(1000), merchant, payer)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::BuyerBlocked as u32)
+            )
+        );
+
+        // once unblocked, the buyer can check out again
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                unblock_buyer(program_id, payer, merchant, payer),
+                checkout(2000),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        assert!(merchant_result
+            .2
+            .get_account(blocked_buyer)
+            .await
+            .unwrap()
+            .is_none());
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(0, merchant_data.blocked_buyers);
     }
 
-    async fn chain_checkout_failing_test_helper(
-        order_item_id: u8,
-        paid_amount: u64,
-        input_mint: &Keypair,
-        registered_item_id: u8,
-        expected_amount: u64,
-        registered_mint: &Keypair,
-        expected_error: InstructionError,
-    ) -> bool {
-        let mut order_items: OrderItems = BTreeMap::new();
-        order_items.insert(format!("{}", order_item_id), 1);
+    #[tokio::test]
+    async fn test_update_config() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let upgrade_authority = get_test_upgrade_authority();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+        let new_program_owner = Pubkey::new_unique();
 
-        let mut merchant_data = String::from("5");
+        // only the program authority can update the program config
+        let other_keypair = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[update_config(
+                program_id,
+                other_keypair.pubkey(),
+                Some(new_program_owner),
+                Option::None,
+                Option::None,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &other_keypair], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
 
-        if registered_item_id != 0 {
-            merchant_data = format!(
-                r#"{{"{registered_item_id}": {{"price": {expected_amount}, "mint": "{mint_key}"}}}}"#,
-                registered_item_id = registered_item_id,
-                expected_amount = expected_amount,
-                mint_key = registered_mint.pubkey()
-            );
-        }
+        // the default fee cannot go below the minimum fee
+        let mut transaction = Transaction::new_with_payer(
+            &[update_config(
+                program_id,
+                upgrade_authority.pubkey(),
+                Option::None,
+                Option::None,
+                Some(DEFAULT_FEE_IN_LAMPORTS + 1),
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
 
-        let mut merchant_result = create_merchant_account(
-            Some("test".to_string()),
-            Option::None,
-            Option::None,
-            Some(merchant_data),
+        let mut transaction = Transaction::new_with_payer(
+            &[update_config(
+                program_id,
+                upgrade_authority.pubkey(),
+                Some(new_program_owner),
+                Some(DEFAULT_FEE_IN_LAMPORTS * 2),
+                Option::None,
+                Some(500),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &upgrade_authority], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let (config_pubkey, _bump_seed) = get_program_config_address(&program_id);
+        let config_account = merchant_result
+            .2
+            .get_account(config_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let config_data = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+        assert_eq!(new_program_owner.to_bytes(), config_data.owner);
+        assert_eq!(DEFAULT_FEE_IN_LAMPORTS * 2, config_data.default_fee);
+        assert_eq!(MIN_FEE_IN_LAMPORTS, config_data.min_fee);
+        assert_eq!(500, config_data.sponsor_fee);
+        assert_eq!(upgrade_authority.pubkey().to_bytes(), config_data.authority);
+
+        // checkouts now have to pay the new program owner
+        let (order_acc_pubkey, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
         .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token: get_order_token_address(
+                        &program_id,
+                        &order_acc_pubkey,
+                        &mint_keypair.pubkey(),
+                    ),
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 1000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongProgramOwner as u32)
+            )
+        );
+    }
 
-        match create_chain_checkout_transaction(
-            paid_amount,
-            &order_items,
-            Option::None,
-            &mut merchant_result,
-            &input_mint,
+    #[tokio::test]
+    async fn test_lifetime_volume() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let buyer_token_keypair =
+            create_token_account(3000, &mint_keypair, &mut merchant_result).await;
+
+        let (order_acc_pubkey, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint_keypair.pubkey(),
+            &mut merchant_result.2,
         )
-        .await
-        {
-            Err(error) => {
-                assert_eq!(
-                    error.unwrap(),
-                    TransactionError::InstructionError(0, expected_error)
-                );
-            }
-            Ok(_value) => panic!("Oo... we expect an error"),
-        };
+        .await;
+        assert_eq!(0, merchant_data.lifetime_volume);
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token: get_order_token_address(
+                        &program_id,
+                        &order_acc_pubkey,
+                        &mint_keypair.pubkey(),
+                    ),
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint: mint_keypair.pubkey(),
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 1000,
+                    tip: 200,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
 
-        true
+        // the amount paid (but not the tip) counts towards the fee tier of the merchant
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(1000, merchant_data.lifetime_volume);
     }
 
     #[tokio::test]
-    async fn test_chain_checkout_failure() {
-        let mint_a = Keypair::new();
-        let mint_b = Keypair::new();
+    async fn test_merchant_sponsor_fee() {
+        let (program_id, merchant, mut banks_client, payer, recent_blockhash) =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let merchant_account = banks_client.get_account(merchant).await.unwrap().unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(SPONSOR_FEE as u64, merchant_data.sponsor_fee);
 
-        // insufficient funds
-        assert!(
-            chain_checkout_failing_test_helper(
-                1,       // id of item being ordered
-                20,      // amount to pay
-                &mint_a, // mint being used for payment
-                1,       // registered item id
-                30,      // expected amount
-                &mint_a, // expected mint
-                InstructionError::InsufficientFunds
-            )
-            .await
+        // a merchant can ask for another sponsor fee when registering
+        let sponsor = Pubkey::new_unique();
+        let sponsored_merchant =
+            Pubkey::create_with_seed(&payer.pubkey(), "sponsored", &program_id).unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[register_merchant(
+                program_id,
+                RegisterMerchantArgs {
+                    signer: payer.pubkey(),
+                    merchant: sponsored_merchant,
+                    seed: Some(String::from("sponsored")),
+                    fee: Option::None,
+                    data: Option::None,
+                    sponsor: Some(&sponsor),
+                    sponsor_fee: Some(200),
+                },
+            )],
+            Some(&payer.pubkey()),
         );
-
-        // wrong item id in order
-        assert!(
-            chain_checkout_failing_test_helper(
-                7,       // id of item being ordered
-                20,      // amount to pay
-                &mint_a, // mint being used for payment
-                1,       // registered item id
-                30,      // expected amount
-                &mint_a, // expected mint
-                InstructionError::Custom(PaymentProcessorError::InvalidOrderData as u32)
-            )
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let merchant_account = banks_client
+            .get_account(sponsored_merchant)
             .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(200, merchant_data.sponsor_fee);
+
+        // only the program authority can change it
+        let upgrade_authority = get_test_upgrade_authority();
+        let other_keypair = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_sponsor_fee(
+                program_id,
+                other_keypair.pubkey(),
+                sponsored_merchant,
+                100,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &other_keypair], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
         );
 
-        // wrong mint in order
-        assert!(
-            chain_checkout_failing_test_helper(
-                1,       // id of item being ordered
-                20,      // amount to pay
-                &mint_a, // mint being used for payment
-                1,       // registered item id
-                20,      // expected amount
-                &mint_b, // expected mint
-                InstructionError::Custom(PaymentProcessorError::WrongMint as u32)
-            )
-            .await
+        // the sponsor cannot get more than the whole fee
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_sponsor_fee(
+                program_id,
+                upgrade_authority.pubkey(),
+                sponsored_merchant,
+                1001,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
         );
 
-        // invalid merchant data
-        assert!(
-            chain_checkout_failing_test_helper(
-                1,       // id of item being ordered
-                20,      // amount to pay
-                &mint_a, // mint being used for payment
-                0,       // registered item id
-                20,      // expected amount
-                &mint_a, // expected mint
-                InstructionError::Custom(PaymentProcessorError::InvalidMerchantData as u32)
-            )
+        let mut transaction = Transaction::new_with_payer(
+            &[set_merchant_sponsor_fee(
+                program_id,
+                upgrade_authority.pubkey(),
+                sponsored_merchant,
+                100,
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &upgrade_authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let merchant_account = banks_client
+            .get_account(sponsored_merchant)
             .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(100, merchant_data.sponsor_fee);
+        let config_account = banks_client
+            .get_account(get_program_config_address(&program_id).0)
+            .await
+            .unwrap()
+            .unwrap();
+        let config_data = ProgramConfigAccount::unpack(&config_account.data).unwrap();
+        let (program_owner_fee, sponsor_fee, _integrator_fee) =
+            get_fees(&merchant_data, &config_data, false).unwrap();
+        assert_eq!(
+            get_amounts(merchant_data.fee, 100),
+            Ok((program_owner_fee, sponsor_fee))
         );
     }
 
     #[tokio::test]
-    async fn test_express_checkout() {
-        let amount: u64 = 2000000000;
-        let order_id = String::from("1337");
-        let secret = String::from("hunter2");
+    async fn test_referral() {
         let mut merchant_result =
             create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let referrer_wallet = Pubkey::new_unique();
         let mint_keypair = Keypair::new();
-        let (order_acc_pubkey, seller_account_pubkey) = create_order_express_checkout(
-            amount,
-            &order_id,
-            &secret,
-            Option::None,
-            &mut merchant_result,
-            &mint_keypair,
-        )
-        .await;
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
 
-        run_checkout_tests(
-            amount,
-            order_id,
-            secret,
-            Option::None,
-            &mut merchant_result,
-            &order_acc_pubkey,
-            &seller_account_pubkey,
-            &mint_keypair,
-        )
-        .await;
-    }
+        // the referral share cannot be more than the whole amount paid
+        let mut transaction = Transaction::new_with_payer(
+            &[set_referrer(
+                program_id,
+                payer,
+                merchant,
+                referrer_wallet,
+                10001,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
 
-    #[tokio::test]
-    /// test checkout with all merchant options
-    async fn test_express_checkout_with_all_options() {
-        let sponsor_pk = Pubkey::new_unique();
-        let amount: u64 = 2000000000;
-        let order_id = String::from("123-SQT-MX");
-        let secret = String::from("supersecret");
-        let mut merchant_result = create_merchant_account(
-            Some(String::from("Oo")),
-            Some(123456),
-            Some(&sponsor_pk),
-            Some(String::from(r#"{"foo": "bar"}"#)),
-        )
-        .await;
-        let mint_keypair = Keypair::new();
-        let (order_acc_pubkey, seller_account_pubkey) = create_order_express_checkout(
-            amount,
-            &order_id,
-            &secret,
-            Some(String::from(r#"{"a": "b"}"#)),
-            &mut merchant_result,
-            &mint_keypair,
-        )
-        .await;
-        run_checkout_tests(
-            amount,
-            order_id,
-            secret,
-            Some(String::from(r#"{"a": "b"}"#)),
-            &mut merchant_result,
-            &order_acc_pubkey,
-            &seller_account_pubkey,
-            &mint_keypair,
+        // the merchant takes on a referrer that gets 5% of the orders it refers
+        let (referrer, _bump_seed) = get_referrer_address(&program_id, &merchant, &referrer_wallet);
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_referrer(program_id, payer, merchant, referrer_wallet, 500),
+                create_associated_token_account_instruction(&payer, &referrer_wallet, &mint),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let referrer_account = merchant_result
+            .2
+            .get_account(referrer)
+            .await
+            .unwrap()
+            .unwrap();
+        let referrer_data = ReferrerAccount::unpack(&referrer_account.data).unwrap();
+        assert_eq!(Discriminator::Referrer as u8, referrer_data.discriminator);
+        assert_eq!(merchant.to_bytes(), referrer_data.merchant);
+        assert_eq!(referrer_wallet.to_bytes(), referrer_data.wallet);
+        assert_eq!(500, referrer_data.referral_fee);
+
+        // a checkout that includes the referrer account records the referrer on the order
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
         )
         .await;
-    }
+        let mut transaction = Transaction::new_with_payer(
+            &[use_referrer(
+                express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order: order_acc_pubkey,
+                        merchant,
+                        seller_token,
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint,
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount: 2000,
+                        tip: 0,
+                        order_id: String::from("1"),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                ),
+                merchant,
+                referrer_wallet,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(
+            Some(OrderReferral {
+                referrer: referrer_wallet.to_string(),
+                fee: 500,
+            }),
+            get_order_referral(&order_data)
+        );
 
-    async fn run_order_token_account_refund_tests(
-        order_payment_token_acc: &Option<solana_sdk::account::Account>,
-        account_to_receive_sol_refund_before: &Option<solana_sdk::account::Account>,
-        account_to_receive_sol_refund_after: &Option<solana_sdk::account::Account>,
-        previous_order_account: &Option<solana_sdk::account::Account>,
-    ) {
-        // order token account is closed
-        assert!(order_payment_token_acc.is_none());
-        let order_account_rent = match previous_order_account {
-            None => 0,
-            Some(account) => account.lamports,
-        };
-        match account_to_receive_sol_refund_before {
-            None => panic!("Oo"),
-            Some(account_before) => match account_to_receive_sol_refund_after {
-                None => panic!("Oo"),
-                Some(account_after) => {
-                    // the before balance has increased by the rent amount of both token and order account
-                    assert_eq!(
-                        account_before.lamports,
-                        account_after.lamports
-                            - (Rent::default().minimum_balance(TokenAccount::LEN)
-                                + order_account_rent)
-                    );
-                }
+        // the order cannot be withdrawn without paying the referrer
+        let instruction = withdraw(
+            program_id,
+            WithdrawArgs {
+                signer: payer,
+                order: order_acc_pubkey,
+                merchant,
+                order_payment_token: seller_token,
+                merchant_token: buyer_token_keypair.pubkey(),
+                account_to_receive_sol_refund: payer,
+                pda,
+                subscription: Option::None,
+                close_order_account: false,
+                other_tokens: vec![],
             },
-        };
-    }
-
-    async fn withdraw_helper(
-        amount: u64,
-        close_order_account: bool,
-    ) -> (
-        BanksClient,
-        Option<solana_sdk::account::Account>,
-        Pubkey,
-        Pubkey,
-        Option<solana_sdk::account::Account>,
-        Option<solana_sdk::account::Account>,
-    ) {
+        );
+        let mut transaction =
+            Transaction::new_with_payer(std::slice::from_ref(&instruction), Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[pay_referral(
+                instruction,
+                referrer_wallet,
+                spl_token::id(),
+                mint,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let referrer_token_account = merchant_result
+            .2
+            .get_account(get_associated_token_address(
+                &referrer_wallet,
+                &spl_token::id(),
+                &mint,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        let referrer_token_data = TokenAccount::unpack(&referrer_token_account.data).unwrap();
+        assert_eq!(100, referrer_token_data.amount);
+        let buyer_token_account = merchant_result
+            .2
+            .get_account(buyer_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let buyer_token_data = TokenAccount::unpack(&buyer_token_account.data).unwrap();
+        assert_eq!(2000000 + 1900, buyer_token_data.amount);
+    }
+
+    #[tokio::test]
+    async fn test_payout_schedule() {
         let mut merchant_result =
             create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
-        let merchant_token_keypair = Keypair::new();
-        let order_id = String::from("PD17CUSZ75");
-        let secret = String::from("i love oov");
-        let mint_keypair = Keypair::new();
-        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
-            amount,
-            &order_id,
-            &secret,
-            Option::None,
-            &mut merchant_result,
-            &mint_keypair,
-        )
-        .await;
         let program_id = merchant_result.0;
-        let merchant_account_pubkey = merchant_result.1;
-        let mut banks_client = merchant_result.2;
-        let payer = merchant_result.3;
-        let recent_blockhash = merchant_result.4;
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let operating_wallet = Pubkey::new_unique();
+        let savings_wallet = Pubkey::new_unique();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
 
-        // create and initialize merchant token account
-        assert_matches!(
-            banks_client
-                .process_transaction(create_token_account_transaction(
-                    &payer,
-                    &mint_keypair,
-                    recent_blockhash,
-                    &merchant_token_keypair,
-                    &payer.pubkey(),
-                    0,
-                ))
-                .await,
-            Ok(())
+        // the shares have to add up to the whole amount
+        let mut transaction = Transaction::new_with_payer(
+            &[set_payout_schedule(
+                program_id,
+                payer,
+                merchant,
+                vec![(operating_wallet, 8000), (savings_wallet, 1000)],
+            )],
+            Some(&payer),
         );
-        let (order_payment_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+
+        // 80% to the operating wallet and 20% to the savings wallet
+        let mut transaction = Transaction::new_with_payer(
             &[
-                &order_acc_pubkey.to_bytes(),
-                &spl_token::id().to_bytes(),
-                &mint_keypair.pubkey().to_bytes(),
+                set_payout_schedule(
+                    program_id,
+                    payer,
+                    merchant,
+                    vec![(operating_wallet, 8000), (savings_wallet, 2000)],
+                ),
+                create_associated_token_account_instruction(&payer, &operating_wallet, &mint),
+                create_associated_token_account_instruction(&payer, &savings_wallet, &mint),
             ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(2, merchant_data.payout_count);
+        assert_eq!(operating_wallet.to_bytes(), merchant_data.payout_wallets[0]);
+        assert_eq!(2000, merchant_data.payout_shares[1]);
+
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
             &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
         );
 
-        let account_to_receive_sol_refund_pubkey = Pubkey::from_str(PROGRAM_OWNER).unwrap();
-        let account_to_receive_sol_refund_before = banks_client
-            .get_account(account_to_receive_sol_refund_pubkey)
+        // the order cannot be withdrawn without the token accounts of the payout schedule
+        let instruction = withdraw(
+            program_id,
+            WithdrawArgs {
+                signer: payer,
+                order: order_acc_pubkey,
+                merchant,
+                order_payment_token: seller_token,
+                merchant_token: buyer_token_keypair.pubkey(),
+                account_to_receive_sol_refund: payer,
+                pda,
+                subscription: Option::None,
+                close_order_account: false,
+                other_tokens: vec![],
+            },
+        );
+        let mut transaction =
+            Transaction::new_with_payer(std::slice::from_ref(&instruction), Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[use_payout_schedule(
+                instruction,
+                vec![operating_wallet, savings_wallet],
+                spl_token::id(),
+                mint,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        for (wallet, amount) in [(operating_wallet, 1600), (savings_wallet, 400)] {
+            let payout_token_account = merchant_result
+                .2
+                .get_account(get_associated_token_address(
+                    &wallet,
+                    &spl_token::id(),
+                    &mint,
+                ))
+                .await
+                .unwrap()
+                .unwrap();
+            let payout_token_data = TokenAccount::unpack(&payout_token_account.data).unwrap();
+            assert_eq!(amount, payout_token_data.amount);
+        }
+        // nothing went to the merchant token account
+        let buyer_token_account = merchant_result
+            .2
+            .get_account(buyer_token_keypair.pubkey())
             .await
+            .unwrap()
             .unwrap();
+        let buyer_token_data = TokenAccount::unpack(&buyer_token_account.data).unwrap();
+        assert_eq!(2000000, buyer_token_data.amount);
+    }
 
-        let previous_order_account = banks_client.get_account(order_acc_pubkey).await;
-        let previous_order_account = match previous_order_account {
-            Err(error) => panic!("Problem: {:?}", error),
-            Ok(value) => value,
-        };
+    #[tokio::test]
+    async fn test_operators() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let operator = Keypair::new();
+        let stranger = Keypair::new();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
 
-        // call withdraw ix
+        // a merchant has at most MAX_OPERATORS operators
         let mut transaction = Transaction::new_with_payer(
-            &[withdraw(
+            &[set_operators(
                 program_id,
-                payer.pubkey(),
-                order_acc_pubkey,
-                merchant_account_pubkey,
-                order_payment_token_acc_pubkey,
-                merchant_token_keypair.pubkey(),
-                account_to_receive_sol_refund_pubkey,
-                pda,
-                Option::None,
-                close_order_account,
+                payer,
+                merchant,
+                vec![Pubkey::new_unique(); MAX_OPERATORS + 1],
             )],
-            Some(&payer.pubkey()),
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
         );
-        transaction.sign(&[&payer], recent_blockhash);
-        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
-
-        // test contents of merchant token account
-        let merchant_token_account = banks_client
-            .get_account(merchant_token_keypair.pubkey())
-            .await;
-        let merchant_account_data = match merchant_token_account {
-            Ok(data) => match data {
-                None => panic!("Oo"),
-                Some(value) => match spl_token::state::Account::unpack(&value.data) {
-                    Ok(data) => data,
-                    Err(error) => panic!("Problem: {:?}", error),
-                },
-            },
-            Err(error) => panic!("Problem: {:?}", error),
-        };
-        assert_eq!(amount, merchant_account_data.amount);
 
-        let order_account = banks_client.get_account(order_acc_pubkey).await;
-        let order_account = match order_account {
-            Err(error) => panic!("Problem: {:?}", error),
-            Ok(value) => value,
-        };
+        let mut transaction = Transaction::new_with_payer(
+            &[set_operators(
+                program_id,
+                payer,
+                merchant,
+                vec![operator.pubkey()],
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(1, merchant_data.operator_count);
+        assert_eq!(operator.pubkey().to_bytes(), merchant_data.operators[0]);
 
-        (
-            banks_client,
-            order_account,
-            order_payment_token_acc_pubkey,
-            account_to_receive_sol_refund_pubkey,
-            account_to_receive_sol_refund_before,
-            previous_order_account,
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
         )
-    }
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
 
-    #[tokio::test]
-    async fn test_withdraw() {
-        let amount: u64 = 1234567890;
-        let (
-            mut banks_client,
-            order_account,
-            order_payment_token_acc_pubkey,
-            account_to_receive_sol_refund_pubkey,
-            account_to_receive_sol_refund_before,
-            _previous_order_account,
-        ) = withdraw_helper(amount, false).await;
-        // test contents of order account
-        let order_data = match order_account.clone() {
-            None => panic!("Oo"),
-            Some(value) => match OrderAccount::unpack(&value.data) {
-                Ok(data) => data,
-                Err(error) => panic!("Problem: {:?}", error),
-            },
+        // only the merchant owner and its operators can close the order account
+        let withdraw_transaction = |signer: &Keypair, merchant_result: &MerchantResult| {
+            let mut transaction = Transaction::new_with_payer(
+                &[withdraw(
+                    program_id,
+                    WithdrawArgs {
+                        signer: signer.pubkey(),
+                        order: order_acc_pubkey,
+                        merchant,
+                        order_payment_token: seller_token,
+                        merchant_token: buyer_token_keypair.pubkey(),
+                        account_to_receive_sol_refund: payer,
+                        pda,
+                        subscription: Option::None,
+                        close_order_account: true,
+                        other_tokens: vec![],
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3, signer], merchant_result.4);
+            transaction
         };
-        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
-        assert_eq!(amount, order_data.expected_amount);
-        assert_eq!(amount, order_data.paid_amount);
-        // test that token account was closed and that the refund was sent to expected account
-        let order_payment_token_acc = banks_client
-            .get_account(order_payment_token_acc_pubkey)
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(withdraw_transaction(&stranger, &merchant_result))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(withdraw_transaction(&operator, &merchant_result))
+                .await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
             .await
             .unwrap();
-        let account_to_receive_sol_refund_after = banks_client
-            .get_account(account_to_receive_sol_refund_pubkey)
+        assert!(order_account.is_none());
+        let buyer_token_account = merchant_result
+            .2
+            .get_account(buyer_token_keypair.pubkey())
             .await
+            .unwrap()
             .unwrap();
-        run_order_token_account_refund_tests(
-            &order_payment_token_acc,
-            &account_to_receive_sol_refund_before,
-            &account_to_receive_sol_refund_after,
-            &Option::None,
-        )
-        .await;
+        let buyer_token_data = TokenAccount::unpack(&buyer_token_account.data).unwrap();
+        assert_eq!(2002000, buyer_token_data.amount);
     }
 
     #[tokio::test]
-    async fn test_withdraw_close_order_account() {
-        let amount: u64 = 10001;
-        let (
-            mut banks_client,
-            order_account,
-            order_payment_token_acc_pubkey,
-            account_to_receive_sol_refund_pubkey,
-            account_to_receive_sol_refund_before,
-            previous_order_account,
-        ) = withdraw_helper(amount, true).await;
-        // test closure of order account
-        assert!(order_account.is_none());
-        // test that accounts were closed and that refunds sent to expected account
-        let order_payment_token_acc = banks_client
-            .get_account(order_payment_token_acc_pubkey)
+    async fn test_withdraw_amount() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let stranger = Keypair::new();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
+
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Option::None,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // only the merchant owner and its operators choose the amount withdrawn
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: stranger.pubkey(),
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 500,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &stranger], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongMerchant as u32)
+            )
+        );
+
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 500,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
             .await
+            .unwrap()
             .unwrap();
-        let account_to_receive_sol_refund_after = banks_client
-            .get_account(account_to_receive_sol_refund_pubkey)
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(2000, order_data.paid_amount);
+        assert_eq!(500, order_data.withdrawn_amount);
+        let order_token_account = merchant_result
+            .2
+            .get_account(seller_token)
             .await
+            .unwrap()
             .unwrap();
-        run_order_token_account_refund_tests(
-            &order_payment_token_acc,
-            &account_to_receive_sol_refund_before,
-            &account_to_receive_sol_refund_after,
-            &previous_order_account,
-        )
-        .await;
-    }
+        let order_token_data = TokenAccount::unpack(&order_token_account.data).unwrap();
+        assert_eq!(1500, order_token_data.amount);
 
-    async fn run_subscribe_tests(
-        amount: u64,
-        package_name: &str,
-        merchant_data: &str,
-        mint_keypair: &Keypair,
-    ) -> (
-        Result<(), TransportError>,
-        Option<(SubscriptionAccount, MerchantResult, Pubkey, Pubkey)>,
-    ) {
-        let mut merchant_result = create_merchant_account(
-            Some(String::from("subscription test")),
-            Option::None,
-            Option::None,
-            Some(String::from(merchant_data)),
-        )
-        .await;
+        // no more than what is left can be withdrawn
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 1501,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
 
-        let (subscription, _bump_seed) = Pubkey::find_program_address(
-            &[
-                &merchant_result.3.pubkey().to_bytes(), // payer
-                &merchant_result.1.to_bytes(),          // merchant
-                &package_name.as_bytes(),
-            ],
-            &merchant_result.0, // program id
+        // withdrawing the order withdraws what is left
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw(
+                program_id,
+                WithdrawArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    account_to_receive_sol_refund: payer,
+                    pda,
+                    subscription: Option::None,
+                    close_order_account: false,
+                    other_tokens: vec![],
+                },
+            )],
+            Some(&payer),
         );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+        let buyer_token_account = merchant_result
+            .2
+            .get_account(buyer_token_keypair.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let buyer_token_data = TokenAccount::unpack(&buyer_token_account.data).unwrap();
+        assert_eq!(2002000, buyer_token_data.amount);
+    }
 
-        let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription.to_string());
+    #[tokio::test]
+    async fn test_withdraw_amount_after_partial_release() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let mint_keypair = Keypair::new();
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(2000, &mint_keypair, &mut merchant_result).await;
 
-        let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
-            amount,
-            &String::from(package_name),
-            &String::from(""),
-            Some(order_data),
-            &mut merchant_result,
-            &mint_keypair,
+        let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("1"),
+            &mint,
+            &mut merchant_result.2,
         )
         .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[express_checkout(
+                program_id,
+                ExpressCheckoutArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    seller_token,
+                    buyer_token: buyer_token_keypair.pubkey(),
+                    mint,
+                    program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                    sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    pda,
+                    integrator: Option::None,
+                    coupon: Option::None,
+                    link: Option::None,
+                    amount: 2000,
+                    tip: 0,
+                    order_id: String::from("1"),
+                    secret: String::from(""),
+                    data: Some(String::from(r#"{"escrow": 1209600}"#)),
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
 
-        let program_id = merchant_result.0;
-        let merchant_account_pubkey = merchant_result.1;
-        let payer = &merchant_result.3;
-        let recent_blockhash = merchant_result.4;
+        // nothing can be withdrawn while the whole payment is held
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 500,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::OrderNotReleasable as u32)
+            )
+        );
 
-        // call subscribe ix
+        // the buyer releases the first milestone
         let mut transaction = Transaction::new_with_payer(
-            &[subscribe(
+            &[confirm_delivery(
                 program_id,
-                payer.pubkey(),
-                subscription,
-                merchant_account_pubkey,
+                payer,
                 order_acc_pubkey,
-                String::from(package_name),
-                Option::None,
+                Some(800),
             )],
-            Some(&payer.pubkey()),
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
         );
-        transaction.sign(&[payer], recent_blockhash);
-
-        let result = merchant_result.2.process_transaction(transaction).await;
 
-        if result.is_ok() {
-            // test contents of subscription token account
-            let subscription_account = &merchant_result.2.get_account(subscription).await;
-            let subscription_data = match subscription_account {
-                Ok(data) => match data {
-                    None => panic!("Oo"),
-                    Some(value) => match SubscriptionAccount::unpack(&value.data) {
-                        Ok(data) => data,
-                        Err(error) => panic!("Problem: {:?}", error),
-                    },
+        // only the part released can be withdrawn
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 900,
                 },
-                Err(error) => panic!("Problem: {:?}", error),
-            };
-            assert_eq!(
-                (SubscriptionStatus::Initialized as u8),
-                subscription_data.status
-            );
-            assert_eq!(String::from(package_name), subscription_data.name);
-            assert_eq!(
-                payer.pubkey(),
-                Pubkey::new_from_array(subscription_data.owner)
-            );
-            assert_eq!(
-                merchant_account_pubkey,
-                Pubkey::new_from_array(subscription_data.merchant)
-            );
-            assert_eq!(String::from("{}"), subscription_data.data);
-
-            return (
-                result,
-                Some((
-                    subscription_data,
-                    merchant_result,
-                    order_acc_pubkey,
-                    subscription,
-                )),
-            );
-        }
-
-        (result, Option::None)
-    }
-
-    #[tokio::test]
-    async fn test_subscribe() {
-        let mint_keypair = Keypair::new();
-        let packages = format!(
-            r#"{{"packages":[{{"name":"basic","price":1000000,"duration":720,"mint":"{mint}"}},{{"name":"annual","price":11000000,"duration":262800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string()
+            )],
+            Some(&payer),
         );
-        assert!(
-            (run_subscribe_tests(1000000, "basic", &packages, &mint_keypair).await)
-                .0
-                .is_ok()
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
         );
-    }
 
-    #[tokio::test]
-    /// test what happens when there are 0 packages
-    async fn test_subscribe_no_packages() {
-        let mint_keypair = Keypair::new();
-        let packages = r#"{"packages":[]}"#;
-        assert!(
-            (run_subscribe_tests(1337, "basic", packages, &mint_keypair).await)
-                .0
-                .is_err()
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_amount(
+                program_id,
+                WithdrawAmountArgs {
+                    signer: payer,
+                    order: order_acc_pubkey,
+                    merchant,
+                    order_payment_token: seller_token,
+                    merchant_token: buyer_token_keypair.pubkey(),
+                    pda,
+                    amount: 800,
+                },
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let order_account = merchant_result
+            .2
+            .get_account(order_acc_pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(
+            (800, 800),
+            (order_data.released_amount, order_data.withdrawn_amount)
         );
+        let order_token_account = merchant_result
+            .2
+            .get_account(seller_token)
+            .await
+            .unwrap()
+            .unwrap();
+        let order_token_data = TokenAccount::unpack(&order_token_account.data).unwrap();
+        assert_eq!(1200, order_token_data.amount);
     }
 
     #[tokio::test]
-    /// test what happens when there are duplicate packages
-    async fn test_subscribe_duplicate_packages() {
+    async fn test_sweep() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
         let mint_keypair = Keypair::new();
-        let packages = format!(
-            r#"{{"packages":[{{"name":"a","price":100,"duration":720,"mint":"{mint}"}},{{"name":"a","price":222,"duration":262800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string()
-        );
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(4000, &mint_keypair, &mut merchant_result).await;
 
-        let result = run_subscribe_tests(100, "a", &packages, &mint_keypair).await;
-        assert!(result.0.is_ok());
+        // orders are swept once they hold 3000 (in total)
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_sweep_policy(program_id, payer, merchant, 3000, 0),
+                create_associated_token_account_instruction(&payer, &payer, &mint),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(3000, merchant_data.sweep_threshold);
 
-        let _ = match result.1 {
-            None => (),
-            Some(value) => {
-                let subscription_account = value.0;
-                // use the duration of the first package in the array to check
-                // that the subscription was created using the first array element
-                assert_eq!(
-                    720,
-                    subscription_account.period_end - subscription_account.period_start
-                );
-                ()
-            }
-        };
-    }
+        let mut orders = vec![];
+        for order_id in ["1", "2"] {
+            let (order_acc_pubkey, seller_token, pda, merchant_data) = prepare_order(
+                &program_id,
+                &merchant,
+                Some(order_id),
+                &mint,
+                &mut merchant_result.2,
+            )
+            .await;
+            let mut transaction = Transaction::new_with_payer(
+                &[use_sweep_bounty(
+                    express_checkout(
+                        program_id,
+                        ExpressCheckoutArgs {
+                            signer: payer,
+                            order: order_acc_pubkey,
+                            merchant,
+                            seller_token,
+                            buyer_token: buyer_token_keypair.pubkey(),
+                            mint,
+                            program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                            sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                            pda,
+                            integrator: Option::None,
+                            coupon: Option::None,
+                            link: Option::None,
+                            amount: 2000,
+                            tip: 0,
+                            order_id: String::from(order_id),
+                            secret: String::from(""),
+                            data: Option::None,
+                        },
+                    ),
+                    merchant,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            assert_matches!(
+                merchant_result.2.process_transaction(transaction).await,
+                Ok(())
+            );
+            let order_account = merchant_result
+                .2
+                .get_account(order_acc_pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+            assert!(order_data.created_slot > 0);
+            assert_eq!(SWEEP_BOUNTY_IN_LAMPORTS, order_data.sweep_bounty);
+            orders.push((order_acc_pubkey, seller_token));
+        }
+        // the sweep bounty account holds the bounties of both orders on top of its rent
+        let (sweep_bounty, _) = get_sweep_bounty_address(&program_id, &merchant);
+        let sweep_bounty_account = merchant_result
+            .2
+            .get_account(sweep_bounty)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Rent::default().minimum_balance(sweep_bounty_account.data.len())
+                + 2 * SWEEP_BOUNTY_IN_LAMPORTS,
+            sweep_bounty_account.lamports
+        );
 
-    #[tokio::test]
-    /// test what happens when the package is not found
-    async fn test_subscribe_package_not_found() {
-        let mint_keypair = Keypair::new();
-        let packages = format!(
-            r#"{{"packages":[{{"name":"a","price":100,"duration":720,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string()
+        // the orders are not swept before the sweep delay (in slots) is over
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                set_sweep_policy(program_id, payer, merchant, 3000, 1000000),
+                sweep(program_id, payer, merchant, payer, mint, orders.clone()),
+            ],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(1, InstructionError::InvalidInstructionData)
         );
-        assert!(
-            (run_subscribe_tests(100, "zz", &packages, &mint_keypair).await)
-                .0
-                .is_err()
+
+        // a single order does not hold enough to be swept
+        let mut transaction = Transaction::new_with_payer(
+            &[sweep(
+                program_id,
+                payer,
+                merchant,
+                payer,
+                mint,
+                vec![orders[0]],
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
         );
-    }
 
-    #[tokio::test]
-    /// test what happens when there is no packages object in the JSON
-    async fn test_subscribe_no_packages_json() {
-        let mint_keypair = Keypair::new();
-        assert!(
-            (run_subscribe_tests(250, "package", r#"{}"#, &mint_keypair).await)
-                .0
-                .is_err()
+        let mut transaction = Transaction::new_with_payer(
+            &[sweep(
+                program_id,
+                payer,
+                merchant,
+                payer,
+                mint,
+                orders.clone(),
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        for (order, order_token) in orders {
+            let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+            let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+            assert_eq!(OrderStatus::Withdrawn as u8, order_data.status);
+            assert_eq!(0, order_data.sweep_bounty);
+            let order_token_account = merchant_result.2.get_account(order_token).await.unwrap();
+            assert!(order_token_account.is_none());
+        }
+        let merchant_token_account = merchant_result
+            .2
+            .get_account(get_associated_token_address(
+                &payer,
+                &spl_token::id(),
+                &mint,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        let merchant_token_data = TokenAccount::unpack(&merchant_token_account.data).unwrap();
+        assert_eq!(4000, merchant_token_data.amount);
+        // the cranker took the sweep bounties
+        let sweep_bounty_account = merchant_result
+            .2
+            .get_account(sweep_bounty)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Rent::default().minimum_balance(sweep_bounty_account.data.len()),
+            sweep_bounty_account.lamports
         );
+        let sweep_bounty_data = SweepBountyAccount::unpack(&sweep_bounty_account.data).unwrap();
+        assert_eq!((0, 0), (sweep_bounty_data.held, sweep_bounty_data.returned));
     }
 
     #[tokio::test]
-    /// test what happens when there is no valid JSON
-    async fn test_subscribe_no_json() {
+    async fn test_order_address() {
+        let mut merchant_result =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
         let mint_keypair = Keypair::new();
-        assert!(
-            (run_subscribe_tests(250, "package", "what is?", &mint_keypair).await)
-                .0
-                .is_err()
+        let mint = mint_keypair.pubkey();
+        let buyer_token_keypair =
+            create_token_account(4000, &mint_keypair, &mut merchant_result).await;
+        let (order, _seller_token, pda, merchant_data) = prepare_order(
+            &program_id,
+            &merchant,
+            Some("order-1"),
+            &mint,
+            &mut merchant_result.2,
+        )
+        .await;
+        let (_, _, mut banks_client, payer_keypair, recent_blockhash) = merchant_result;
+        let checkout = |order_id: &str, amount: u64| {
+            let mut transaction = Transaction::new_with_payer(
+                &[express_checkout(
+                    program_id,
+                    ExpressCheckoutArgs {
+                        signer: payer,
+                        order,
+                        merchant,
+                        seller_token: get_order_token_address(&program_id, &order, &mint),
+                        buyer_token: buyer_token_keypair.pubkey(),
+                        mint,
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                        pda,
+                        integrator: Option::None,
+                        coupon: Option::None,
+                        link: Option::None,
+                        amount,
+                        tip: 0,
+                        order_id: String::from(order_id),
+                        secret: String::from(""),
+                        data: Option::None,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&payer_keypair], recent_blockhash);
+            transaction
+        };
+
+        // the order account must be derived from the order id
+        assert_eq!(
+            banks_client
+                .process_transaction(checkout("order-2", 2000))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
         );
-    }
 
-    #[tokio::test]
-    /// test what happens when the amount paid is insufficient
-    async fn test_subscribe_not_enough_paid() {
-        let mint_keypair = Keypair::new();
-        let packages = format!(
-            r#"{{"packages":[{{"name":"basic","price":100,"duration":720,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string()
+        assert_matches!(
+            banks_client
+                .process_transaction(checkout("order-1", 2000))
+                .await,
+            Ok(())
         );
-        assert!(
-            (run_subscribe_tests(10, "Netflix-basic", &packages, &mint_keypair).await)
-                .0
-                .is_err()
+        let order_account = banks_client.get_account(order).await.unwrap().unwrap();
+        assert_eq!(program_id, order_account.owner);
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(String::from("order-1"), order_data.order_id);
+        assert_eq!(2000, order_data.paid_amount);
+
+        // the same order cannot be paid twice
+        assert_eq!(
+            banks_client
+                .process_transaction(checkout("order-1", 1000))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidOrder as u32)
+            )
         );
     }
 
     #[tokio::test]
-    async fn test_subscription_renewal() {
+    async fn test_migrate_merchant_data() {
         let mint_keypair = Keypair::new();
-        let name = "short";
-        // create a package that lasts only 1 second
-        let packages = format!(
-            r#"{{"packages":[{{"name":"{name}","price":999999,"duration":1,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string(),
-            name = name
+        let amount: u64 = 2000000000;
+
+        let mut order_items: OrderItems = BTreeMap::new();
+        order_items.insert("1".to_string(), 2);
+
+        let merchant_data = format!(
+            r#"{{
+            "1": {{"price": 2000000, "mint": "{mint_key}"}},
+            "2": {{"price": 3000000, "mint": "{mint_key}", "track_stock": true}}
+        }}"#,
+            mint_key = mint_keypair.pubkey()
         );
-        let result = run_subscribe_tests(1000000, name, &packages, &mint_keypair).await;
-        assert!(result.0.is_ok());
-        let subscribe_result = result.1;
-        let _ = match subscribe_result {
-            None => (),
-            Some(mut subscribe_result) => {
-                let subscription_account = subscribe_result.0;
-                let subscription = subscribe_result.3; // the subscription pubkey
+        let mut merchant_result = create_merchant_account(
+            Some("chain".to_string()),
+            Option::None,
+            Option::None,
+            Some(merchant_data),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let account_size = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap()
+            .data
+            .len();
 
-                let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription.to_string());
+        // only the merchant owner can migrate the merchant
+        let stranger = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[migrate_merchant_data(
+                program_id,
+                stranger.pubkey(),
+                merchant,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &stranger], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongMerchant as u32)
+            )
+        );
 
-                let (order_acc_pubkey, _seller_account_pubkey) = create_order_express_checkout(
-                    999999 * 600,
-                    &format!("{name}", name = name),
-                    &String::from(""),
-                    Some(order_data),
-                    &mut subscribe_result.1,
-                    &mint_keypair,
-                )
-                .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[migrate_merchant_data(program_id, payer, merchant)],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account_size, merchant_account.data.len());
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(
+            Discriminator::MerchantChainCheckout as u8,
+            merchant_data.discriminator
+        );
+        assert_eq!(
+            vec![
+                CatalogItem {
+                    name: String::from("1"),
+                    price: 2000000,
+                    mint: mint_keypair.pubkey().to_bytes(),
+                    track_stock: false,
+                    collection: Option::None,
+                    holder_discount: Option::None,
+                },
+                CatalogItem {
+                    name: String::from("2"),
+                    price: 3000000,
+                    mint: mint_keypair.pubkey().to_bytes(),
+                    track_stock: true,
+                    collection: Option::None,
+                    holder_discount: Option::None,
+                },
+            ],
+            merchant_data.catalog
+        );
+        assert_eq!(String::from(""), merchant_data.data.trim());
 
-                // call subscription  ix
-                let mut transaction = Transaction::new_with_payer(
-                    &[renew_subscription(
-                        subscribe_result.1 .0,          // program_id,
-                        subscribe_result.1 .3.pubkey(), // payer,
-                        subscription,
-                        Pubkey::new_from_array(subscription_account.merchant),
-                        order_acc_pubkey,
-                        600,
-                    )],
-                    Some(&subscribe_result.1 .3.pubkey()),
-                );
-                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
-                assert_matches!(
-                    subscribe_result.1 .2.process_transaction(transaction).await,
-                    Ok(())
-                );
+        // chain checkouts are priced from the typed catalog
+        let (order_acc_pubkey, seller_account_pubkey) = create_order_chain_checkout(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        run_chain_checkout_tests(
+            amount,
+            &order_items,
+            Option::None,
+            &mut merchant_result,
+            &order_acc_pubkey,
+            &seller_account_pubkey,
+            &mint_keypair,
+        )
+        .await;
+    }
 
-                // assert that period end has been updated
-                let subscription_account2 = subscribe_result.1 .2.get_account(subscription).await;
-                let subscription_account2 = match subscription_account2 {
-                    Ok(data) => match data {
-                        None => panic!("Oo"),
-                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
-                            Ok(data) => data,
-                            Err(error) => panic!("Problem: {:?}", error),
-                        },
+    #[tokio::test]
+    async fn test_register_merchant_with_terms() {
+        let (program_id, _merchant, mut banks_client, payer_keypair, recent_blockhash) =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let payer = payer_keypair.pubkey();
+        let packages = vec![SubscriptionPackage {
+            name: String::from("basic"),
+            trial: Some(3600),
+            duration: 2592000,
+            price: 1000000,
+            mint: Pubkey::new_unique().to_bytes(),
+            prorated_refunds: false,
+            price_due_at_trial_end: false,
+            grace_seconds: Option::None,
+            unit_price: Option::None,
+        }];
+        let metadata = br#"{"website": "example.com"}"#.to_vec();
+        let register = |seed: &str, terms: MerchantTerms, metadata: Vec<u8>| {
+            let mut transaction = Transaction::new_with_payer(
+                &[register_merchant_with_terms(
+                    program_id,
+                    RegisterMerchantWithTermsArgs {
+                        signer: payer,
+                        merchant: Pubkey::create_with_seed(&payer, seed, &program_id).unwrap(),
+                        seed: Some(String::from(seed)),
+                        fee: Option::None,
+                        terms,
+                        metadata,
+                        sponsor: Option::None,
+                        sponsor_fee: Option::None,
                     },
-                    Err(error) => panic!("Problem: {:?}", error),
-                };
-                assert_eq!(
-                    // the new period_end is equal to the old period_end + (1 * 600)
-                    subscription_account.period_end + 600,
-                    subscription_account2.period_end
-                );
-
-                return ();
-            }
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&payer_keypair], recent_blockhash);
+            transaction
         };
-    }
 
-    async fn run_subscription_withdrawal_tests(
-        name: &str,
-        packages: &str,
-        mint_keypair: &Keypair,
-        error_expected: bool,
-    ) {
-        // create the subscription
-        let result = run_subscribe_tests(1000000, name, &packages, &mint_keypair).await;
-        assert!(result.0.is_ok());
-        let subscribe_result = result.1;
-        let _ = match subscribe_result {
-            None => (),
-            Some(mut subscribe_result) => {
-                let subscription = subscribe_result.3; // the subscription pubkey
-                let order_acc_pubkey = subscribe_result.2;
-                let merchant_token_keypair = Keypair::new();
-                let (pda, _bump_seed) =
-                    Pubkey::find_program_address(&[PDA_SEED], &subscribe_result.1 .0);
+        let transaction = register(
+            "packages",
+            MerchantTerms::Packages(packages.clone()),
+            metadata.clone(),
+        );
+        assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
+        let merchant = Pubkey::create_with_seed(&payer, "packages", &program_id).unwrap();
+        let merchant_account = banks_client.get_account(merchant).await.unwrap().unwrap();
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+        assert_eq!(
+            Discriminator::MerchantSubscriptionWithTrial as u8,
+            merchant_data.discriminator
+        );
+        assert_eq!(packages, merchant_data.packages);
+        assert!(merchant_data.catalog.is_empty());
+        assert_eq!(metadata, merchant_data.metadata);
+        assert_eq!(String::from(""), merchant_data.data);
 
-                // create and initialize merchant token account
-                assert_matches!(
-                    subscribe_result
-                        .1
-                         .2
-                        .process_transaction(create_token_account_transaction(
-                            &subscribe_result.1 .3,
-                            &mint_keypair,
-                            subscribe_result.1 .4, // recent_blockhash
-                            &merchant_token_keypair,
-                            &subscribe_result.1 .3.pubkey(), // payer,
-                            0,
-                        ))
-                        .await,
-                    Ok(())
-                );
-                let (order_payment_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
-                    &[
-                        &order_acc_pubkey.to_bytes(),
-                        &spl_token::id().to_bytes(),
-                        &mint_keypair.pubkey().to_bytes(),
-                    ],
-                    &subscribe_result.1 .0, // program_id
-                );
+        // catalog items are looked up by name
+        let item = CatalogItem {
+            name: String::from("1"),
+            price: 2000000,
+            mint: Pubkey::new_unique().to_bytes(),
+            track_stock: false,
+            collection: Option::None,
+            holder_discount: Option::None,
+        };
+        let transaction = register(
+            "catalog",
+            MerchantTerms::Catalog(vec![item.clone(), item]),
+            vec![],
+        );
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::InvalidMerchantData as u32)
+            )
+        );
 
-                // call withdraw ix
-                let mut transaction = Transaction::new_with_payer(
-                    &[withdraw(
-                        subscribe_result.1 .0,          // program_id
-                        subscribe_result.1 .3.pubkey(), // payer,
-                        order_acc_pubkey,
-                        subscribe_result.1 .1, // the merchant pubkey
-                        order_payment_token_acc_pubkey,
-                        merchant_token_keypair.pubkey(),
-                        Pubkey::from_str(PROGRAM_OWNER).unwrap(),
-                        pda,
-                        Some(subscription),
-                        false,
-                    )],
-                    Some(&subscribe_result.1 .3.pubkey()),
-                );
-                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
+        let transaction = register(
+            "metadata",
+            MerchantTerms::Checkout,
+            vec![0; MAX_METADATA_LEN + 1],
+        );
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidInstructionData)
+        );
+    }
 
-                if error_expected {
-                    assert!(subscribe_result
-                        .1
-                         .2
-                        .process_transaction(transaction)
-                        .await
-                        .is_err());
-                } else {
-                    assert!(subscribe_result
-                        .1
-                         .2
-                        .process_transaction(transaction)
-                        .await
-                        .is_ok());
-                }
+    #[tokio::test]
+    async fn test_wrong_account_type() {
+        let (program_id, merchant, mut banks_client, payer_keypair, recent_blockhash) =
+            create_merchant_account(Option::None, Option::None, Option::None, Option::None).await;
+        let payer = payer_keypair.pubkey();
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
 
-                return ();
-            }
-        };
+        // the merchant account cannot be passed for an order account
+        let mut transaction = Transaction::new_with_payer(
+            &[close_order(
+                program_id,
+                payer,
+                merchant,
+                merchant,
+                payer,
+                pda,
+                vec![],
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&payer_keypair], recent_blockhash);
+        assert_eq!(
+            banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongAccountType as u32)
+            )
+        );
     }
 
     #[tokio::test]
-    async fn test_withdraw_during_trial() {
+    async fn test_cancel_subscription_with_prorated_refund() {
         let mint_keypair = Keypair::new();
-        let name = "trialFirst";
-        // create a package that has a short trial period
+        let name = "prorated";
+        // the price of the package is one token per second of the period
         let packages = format!(
-            r#"{{"packages":[{{"name":"{name}","price":99,"trial":0,"duration":604800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string(),
+            r#"{{"packages":[{{"name":"{name}","price":604800,"trial":0,"duration":604800,"mint":"{mint}","prorated_refunds":true}}]}}"#,
+            mint = mint_keypair.pubkey(),
             name = name
         );
-        // withdraw goes okay
-        run_subscription_withdrawal_tests(name, &packages, &mint_keypair, false).await;
+        let result = run_subscription_cancel_tests(604800, name, &packages, &mint_keypair)
+            .await
+            .unwrap();
+        let (
+            subscription_account,
+            order_account,
+            _order_token_account,
+            refund_token_account,
+            previous_subscription_account,
+            _previous_order_account,
+            _account_to_receive_sol_refund_before,
+            _account_to_receive_sol_refund_after,
+        ) = result;
+        // the subscription ends right away
+        assert_eq!(
+            SubscriptionStatus::Cancelled as u8,
+            subscription_account.status
+        );
+        assert!(previous_subscription_account.period_end > subscription_account.period_end);
+        // the unused part of the period was refunded
+        let unused_duration =
+            (previous_subscription_account.period_end - subscription_account.period_end) as u64;
+        assert!(unused_duration > 0);
+        assert_eq!(unused_duration, refund_token_account.amount);
+        // the rest of the order is left for the merchant to withdraw
+        let order_account = OrderAccount::unpack(&order_account.unwrap().data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_account.status);
+        assert_eq!(604800 - unused_duration, order_account.paid_amount);
+        assert_eq!(604800 - unused_duration, order_account.expected_amount);
     }
 
     #[tokio::test]
-    async fn test_cannot_withdraw_during_trial() {
+    async fn test_free_trial() {
         let mint_keypair = Keypair::new();
-        let name = "try1st";
-        // create a package that has a week long trial period
         let packages = format!(
-            r#"{{"packages":[{{"name":"{name}","price":99,"trial":604800,"duration":604800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string(),
-            name = name
+            r#"{{"packages":[{{"name":"free","price":500,"trial":604800,"duration":604800,"mint":"{mint}","price_due_at_trial_end":true}},{{"name":"upfront","price":500,"trial":604800,"duration":604800,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey().to_string()
         );
-        // withdrawal errors out as you cant withdraw during trial
-        run_subscription_withdrawal_tests(name, &packages, &mint_keypair, true).await;
-    }
-
-    async fn run_subscription_cancel_tests(
-        amount: u64,
-        name: &str,
-        packages: &str,
-        mint_keypair: &Keypair,
-    ) -> Option<(
-        SubscriptionAccount,
-        Option<solana_sdk::account::Account>,
-        Option<solana_sdk::account::Account>,
-        spl_token::state::Account,
-        SubscriptionAccount,
-        Option<solana_sdk::account::Account>,
-        Option<solana_sdk::account::Account>,
-        Option<solana_sdk::account::Account>,
-    )> {
-        // create the subscription
-        let result = run_subscribe_tests(amount, name, &packages, &mint_keypair).await;
-        assert!(result.0.is_ok());
-        let subscribe_result = result.1;
-        match subscribe_result {
-            None => Option::None,
-            Some(mut subscribe_result) => {
-                let subscription = subscribe_result.3; // the subscription pubkey
-
-                let previous_subscription_account =
-                    subscribe_result.1 .2.get_account(subscription).await;
-                let previous_subscription_account = match previous_subscription_account {
-                    Ok(data) => match data {
-                        None => panic!("Oo"),
-                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
-                            Ok(data) => data,
-                            Err(error) => panic!("Problem: {:?}", error),
-                        },
-                    },
-                    Err(error) => panic!("Problem: {:?}", error),
-                };
-
-                let order_acc_pubkey = subscribe_result.2;
-                let previous_order_account =
-                    subscribe_result.1 .2.get_account(order_acc_pubkey).await;
-                let previous_order_account = match previous_order_account {
-                    Err(error) => panic!("Problem: {:?}", error),
-                    Ok(value) => value,
-                };
-
-                let refund_token_acc_keypair = Keypair::new();
-                let (pda, _bump_seed) =
-                    Pubkey::find_program_address(&[PDA_SEED], &subscribe_result.1 .0);
-
-                // create and initialize refund token account
-                assert_matches!(
-                    subscribe_result
-                        .1
-                         .2
-                        .process_transaction(create_token_account_transaction(
-                            &subscribe_result.1 .3,
-                            &mint_keypair,
-                            subscribe_result.1 .4, // recent_blockhash
-                            &refund_token_acc_keypair,
-                            &subscribe_result.1 .3.pubkey(), // payer,
-                            0,
-                        ))
-                        .await,
-                    Ok(())
-                );
-                let (order_token_acc_pubkey, _bump_seed) = Pubkey::find_program_address(
-                    &[
-                        &order_acc_pubkey.to_bytes(),
-                        &spl_token::id().to_bytes(),
-                        &mint_keypair.pubkey().to_bytes(),
-                    ],
-                    &subscribe_result.1 .0, // program_id
-                );
-
-                let account_to_receive_sol_refund_pubkey = Pubkey::from_str(PROGRAM_OWNER).unwrap();
-                let account_to_receive_sol_refund_before = subscribe_result
-                    .1
-                     .2
-                    .get_account(account_to_receive_sol_refund_pubkey)
-                    .await
-                    .unwrap();
-
-                // call cancel ix
-                let mut transaction = Transaction::new_with_payer(
-                    &[cancel_subscription(
-                        subscribe_result.1 .0,          // program_id
-                        subscribe_result.1 .3.pubkey(), // payer,
-                        subscription,
-                        subscribe_result.1 .1, // the merchant pubkey
-                        order_acc_pubkey,
-                        order_token_acc_pubkey,
-                        refund_token_acc_keypair.pubkey(),
-                        account_to_receive_sol_refund_pubkey,
-                        pda,
-                    )],
-                    Some(&subscribe_result.1 .3.pubkey()),
-                );
-                transaction.sign(&[&subscribe_result.1 .3], subscribe_result.1 .4);
-
-                let _cancel_result = subscribe_result.1 .2.process_transaction(transaction).await;
+        let mut merchant_result = create_merchant_account(
+            Some(String::from("subscription test")),
+            Option::None,
+            Option::None,
+            Some(packages),
+        )
+        .await;
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
 
-                let subscription_account = subscribe_result.1 .2.get_account(subscription).await;
-                let subscription_account = match subscription_account {
-                    Ok(data) => match data {
-                        None => panic!("Oo"),
-                        Some(value) => match SubscriptionAccount::unpack(&value.data) {
-                            Ok(data) => data,
-                            Err(error) => panic!("Problem: {:?}", error),
-                        },
-                    },
-                    Err(error) => panic!("Problem: {:?}", error),
-                };
-                let order_account = subscribe_result.1 .2.get_account(order_acc_pubkey).await;
-                let order_account = match order_account {
-                    Ok(value) => value,
-                    Err(error) => panic!("Problem: {:?}", error),
-                };
-                let order_token_account = subscribe_result
-                    .1
-                     .2
-                    .get_account(order_token_acc_pubkey)
-                    .await
-                    .unwrap();
-                let refund_token_account = subscribe_result
-                    .1
-                     .2
-                    .get_account(refund_token_acc_keypair.pubkey())
-                    .await;
-                let refund_token_account = match refund_token_account {
-                    Ok(data) => match data {
-                        None => panic!("Oo"),
-                        Some(value) => match TokenAccount::unpack(&value.data) {
-                            Ok(data) => data,
-                            Err(error) => panic!("Problem: {:?}", error),
-                        },
-                    },
-                    Err(error) => panic!("Problem: {:?}", error),
-                };
+        // packages whose price is not due at the end of the trial have to be paid upfront
+        let (subscription, _bump_seed) = Pubkey::find_program_address(
+            &[&payer.to_bytes(), &merchant.to_bytes(), b"upfront"],
+            &program_id,
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[start_free_trial(
+                program_id,
+                payer,
+                subscription,
+                merchant,
+                String::from("upfront"),
+                Option::None,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::NotPaid as u32)
+            )
+        );
 
-                let account_to_receive_sol_refund_after = subscribe_result
-                    .1
-                     .2
-                    .get_account(account_to_receive_sol_refund_pubkey)
-                    .await
-                    .unwrap();
+        // the free trial starts without an order
+        let (subscription, _bump_seed) = Pubkey::find_program_address(
+            &[&payer.to_bytes(), &merchant.to_bytes(), b"free"],
+            &program_id,
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[start_free_trial(
+                program_id,
+                payer,
+                subscription,
+                merchant,
+                String::from("free"),
+                Option::None,
+                Option::None,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(SubscriptionStatus::Trialing as u8, subscription_data.status);
+        assert_eq!(
+            subscription_data.joined + 604800,
+            subscription_data.period_end
+        );
 
-                Some((
-                    subscription_account,
-                    order_account,
-                    order_token_account,
-                    refund_token_account,
-                    previous_subscription_account,
-                    previous_order_account,
-                    account_to_receive_sol_refund_before,
-                    account_to_receive_sol_refund_after,
-                ))
-            }
-        }
+        // the first renewal pays for the subscription
+        let order_data = format!(r#"{{"subscription": "{}"}}"#, subscription);
+        let (order, _seller_token) = create_order_express_checkout(
+            500,
+            &String::from("free"),
+            &String::from(""),
+            Some(order_data),
+            &mut merchant_result,
+            &mint_keypair,
+        )
+        .await;
+        let mut transaction = Transaction::new_with_payer(
+            &[renew_subscription(
+                program_id,
+                payer,
+                subscription,
+                merchant,
+                order,
+                Option::None,
+                1,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let renewed_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(SubscriptionStatus::Initialized as u8, renewed_data.status);
+        assert_eq!(
+            subscription_data.period_end + 604800,
+            renewed_data.period_end
+        );
     }
 
     #[tokio::test]
-    async fn test_cancel_subscription_during_trial() {
+    async fn test_transfer_subscription() {
         let mint_keypair = Keypair::new();
-        let name = "trialFirst";
-        // create a package that has a short trial period
+        let name = "giftable";
         let packages = format!(
-            r#"{{"packages":[{{"name":"{name}","price":6699,"trial":604800,"duration":604800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string(),
+            r#"{{"packages":[{{"name":"{name}","price":100,"duration":720,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
             name = name
         );
-        // cancel goes okay
-        let result = run_subscription_cancel_tests(6699, name, &packages, &mint_keypair)
+        let result = run_subscribe_tests(100, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let (subscription_data, mut merchant_result, _order, subscription) = result.1.unwrap();
+        let program_id = merchant_result.0;
+        let payer = merchant_result.3.pubkey();
+        let new_owner = Keypair::new();
+
+        // the new owner has to agree
+        let mut instruction =
+            transfer_subscription(program_id, payer, new_owner.pubkey(), subscription);
+        instruction.accounts[1].is_signer = false;
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer));
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
+
+        let mut transaction = Transaction::new_with_payer(
+            &[transfer_subscription(
+                program_id,
+                payer,
+                new_owner.pubkey(),
+                subscription,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
             .await
+            .unwrap()
             .unwrap();
-        let (
-            subscription_account,
-            order_account,
-            order_token_account,
-            refund_token_account,
-            previous_subscription_account,
-            previous_order_account,
-            account_to_receive_sol_refund_before,
-            account_to_receive_sol_refund_after,
-        ) = result;
-        // subscription was canceled
+        let transferred_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(new_owner.pubkey().to_bytes(), transferred_data.owner);
+        // the paid time is kept
+        assert_eq!(subscription_data.period_end, transferred_data.period_end);
+        assert_eq!(subscription_data.status, transferred_data.status);
+
+        // the previous owner can no longer hand it over
+        let other_owner = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[transfer_subscription(
+                program_id,
+                payer,
+                other_owner.pubkey(),
+                subscription,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &other_owner], merchant_result.4);
         assert_eq!(
-            SubscriptionStatus::Initialized as u8,
-            previous_subscription_account.status
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongPayer as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_transferred_subscription() {
+        let mint_keypair = Keypair::new();
+        let name = "handed-over";
+        let price: u64 = 1000000;
+        let packages = format!(
+            r#"{{"packages":[{{"name":"{name}","price":{price},"duration":86400,"prorated_refunds":true,"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
+            name = name,
+            price = price
+        );
+        let result = run_subscribe_tests(price, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let (_subscription_data, mut merchant_result, order, subscription) = result.1.unwrap();
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let order_token = get_order_token_address(&program_id, &order, &mint_keypair.pubkey());
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let new_owner = Keypair::new();
+
+        // the payer hands the subscription over
+        let mut transaction = Transaction::new_with_payer(
+            &[transfer_subscription(
+                program_id,
+                payer,
+                new_owner.pubkey(),
+                subscription,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // both owners have a token account to be refunded to
+        let payer_token = Keypair::new();
+        let new_owner_token = Keypair::new();
+        for (token, owner) in [
+            (&payer_token, payer),
+            (&new_owner_token, new_owner.pubkey()),
+        ]
+        .iter()
+        {
+            assert_matches!(
+                merchant_result
+                    .2
+                    .process_transaction(create_token_account_transaction(
+                        &merchant_result.3,
+                        &mint_keypair,
+                        merchant_result.4,
+                        token,
+                        owner,
+                        0,
+                    ))
+                    .await,
+                Ok(())
+            );
+        }
+        let payer_keypair = &merchant_result.3;
+        let recent_blockhash = merchant_result.4;
+        let cancel_transaction = |signer: &Keypair, refund_token: Pubkey| {
+            let mut transaction = Transaction::new_with_payer(
+                &[cancel_subscription(
+                    program_id,
+                    CancelSubscriptionArgs {
+                        signer: signer.pubkey(),
+                        subscription,
+                        merchant,
+                        order,
+                        order_token,
+                        refund_token,
+                        account_to_receive_sol_refund: payer,
+                        pda,
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[payer_keypair, signer], recent_blockhash);
+            transaction
+        };
+
+        // the payer of the order can no longer cancel the subscription
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(cancel_transaction(&merchant_result.3, payer_token.pubkey()))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::NotOrderOwner as u32)
+            )
         );
+        // nor have the new owner's refund sent to them
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(cancel_transaction(&new_owner, payer_token.pubkey()))
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::WrongPayer as u32)
+            )
+        );
+
+        // the new owner cancels and gets the unused part of the period back
+        assert_matches!(
+            merchant_result
+                .2
+                .process_transaction(cancel_transaction(&new_owner, new_owner_token.pubkey()))
+                .await,
+            Ok(())
+        );
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
         assert_eq!(
             SubscriptionStatus::Cancelled as u8,
-            subscription_account.status
+            subscription_data.status
+        );
+        let new_owner_token_account = merchant_result
+            .2
+            .get_account(new_owner_token.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+        let new_owner_token_data = TokenAccount::unpack(&new_owner_token_account.data).unwrap();
+        assert!(new_owner_token_data.amount > 0);
+
+        // the cancelled subscription cannot take more seats
+        let mut transaction = Transaction::new_with_payer(
+            &[update_seats(
+                program_id,
+                new_owner.pubkey(),
+                subscription,
+                merchant,
+                2,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3, &new_owner], merchant_result.4);
+        assert_eq!(
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(PaymentProcessorError::SubscriptionExpired as u32)
+            )
         );
-        // period end has changed to an earlier time
-        assert!(previous_subscription_account.period_end > subscription_account.period_end);
-        // order account was closed
-        assert!(order_account.is_none());
-        // amount was withdrawn
-        assert_eq!(6699, refund_token_account.amount);
-        // order token account was closed and SOL refunded
-        run_order_token_account_refund_tests(
-            &order_token_account,
-            &account_to_receive_sol_refund_before,
-            &account_to_receive_sol_refund_after,
-            &previous_order_account,
-        )
-        .await;
     }
 
     #[tokio::test]
-    async fn test_cancel_subscription_after_trial() {
+    async fn test_metered_usage() {
         let mint_keypair = Keypair::new();
-        let name = "trialFirst";
-        // create a package that has a short trial period
+        let name = "metered";
+        let price: u64 = 1000000;
+        let unit_price: u64 = 2000;
+        // a package that ends as soon as it starts so that the usage can be settled right away
         let packages = format!(
-            r#"{{"packages":[{{"name":"{name}","price":1337,"trial":0,"duration":604800,"mint":"{mint}"}}]}}"#,
-            mint = mint_keypair.pubkey().to_string(),
-            name = name
+            r#"{{"packages":[{{"name":"{name}","price":{price},"duration":0,"unit_price":{unit_price},"mint":"{mint}"}}]}}"#,
+            mint = mint_keypair.pubkey(),
+            name = name,
+            price = price,
+            unit_price = unit_price
         );
-        // cancel goes okay but no refund
-        let result = run_subscription_cancel_tests(1337, name, &packages, &mint_keypair)
+        let result = run_subscribe_tests(price, name, &packages, &mint_keypair).await;
+        assert!(result.0.is_ok());
+        let (_subscription_account, mut merchant_result, _order, subscription) = result.1.unwrap();
+        let program_id = merchant_result.0;
+        let merchant = merchant_result.1;
+        let payer = merchant_result.3.pubkey();
+        let merchant_account = merchant_result
+            .2
+            .get_account(merchant)
             .await
+            .unwrap()
             .unwrap();
-        let (
-            subscription_account,
-            order_account,
-            order_token_account,
-            refund_token_account,
-            previous_subscription_account,
-            previous_order_account,
-            account_to_receive_sol_refund_before,
-            account_to_receive_sol_refund_after,
-        ) = result;
-        // subscription was canceled
-        assert_eq!(
-            SubscriptionStatus::Initialized as u8,
-            previous_subscription_account.status
+        let merchant_data = MerchantAccount::unpack(&merchant_account.data).unwrap();
+
+        // the subscriber lets the merchant pull the usage
+        let subscriber_token_keypair = Keypair::new();
+        let subscriber_token = subscriber_token_keypair.pubkey();
+        let mut transaction = create_token_account_transaction(
+            &merchant_result.3,
+            &mint_keypair,
+            merchant_result.4,
+            &subscriber_token_keypair,
+            &payer,
+            price,
+        );
+        transaction.sign(
+            &[&merchant_result.3, &subscriber_token_keypair],
+            merchant_result.4,
+        );
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[approve_auto_debit(
+                program_id,
+                payer,
+                merchant,
+                subscriber_token,
+                price,
+                price,
+                86400,
+            )],
+            Some(&payer),
+        );
+        transaction.sign(&[&merchant_result.3], merchant_result.4);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
+        );
+
+        // the settlement is the next order of the merchant
+        let order_id = format!(
+            "{}-usage-{}",
+            name,
+            get_next_order_id(merchant_data.order_sequence).unwrap()
         );
+        let (order, _bump_seed) = get_order_address(&program_id, &merchant, &order_id);
+        let settle_transaction = |merchant_result: &MerchantResult| {
+            let mut transaction = Transaction::new_with_payer(
+                &[settle_usage(
+                    program_id,
+                    SettleUsageArgs {
+                        signer: payer,
+                        order,
+                        merchant,
+                        subscription,
+                        subscriber_token,
+                        mint: mint_keypair.pubkey(),
+                        program_owner: Pubkey::from_str(PROGRAM_OWNER).unwrap(),
+                        sponsor: Pubkey::new_from_array(merchant_data.sponsor),
+                    },
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            transaction
+        };
+
+        // there is no usage to settle yet
+        let transaction = settle_transaction(&merchant_result);
         assert_eq!(
-            SubscriptionStatus::Cancelled as u8,
-            subscription_account.status
+            merchant_result
+                .2
+                .process_transaction(transaction)
+                .await
+                .unwrap_err()
+                .unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+
+        for units in [3, 4].iter() {
+            let mut transaction = Transaction::new_with_payer(
+                &[record_usage(
+                    program_id,
+                    payer,
+                    merchant,
+                    subscription,
+                    *units,
+                )],
+                Some(&payer),
+            );
+            transaction.sign(&[&merchant_result.3], merchant_result.4);
+            assert_matches!(
+                merchant_result.2.process_transaction(transaction).await,
+                Ok(())
+            );
+        }
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(7, subscription_data.usage_units);
+
+        let transaction = settle_transaction(&merchant_result);
+        assert_matches!(
+            merchant_result.2.process_transaction(transaction).await,
+            Ok(())
         );
+        let order_account = merchant_result.2.get_account(order).await.unwrap().unwrap();
+        let order_data = OrderAccount::unpack(&order_account.data).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order_data.status);
+        assert_eq!(7 * unit_price, order_data.paid_amount);
+        let order_json_data: Value = serde_json::from_str(&order_data.data).unwrap();
         assert_eq!(
-            previous_subscription_account.period_end,
-            subscription_account.period_end
+            subscription.to_string(),
+            order_json_data["subscription"].as_str().unwrap()
         );
-        // order account was not changed
-        let order_account = match order_account {
-            None => panic!("Oo"),
-            Some(value) => match OrderAccount::unpack(&value.data) {
-                Ok(data) => data,
-                Err(error) => panic!("Problem: {:?}", error),
-            },
-        };
-        let previous_order_account = match previous_order_account {
-            None => panic!("Oo"),
-            Some(value) => match OrderAccount::unpack(&value.data) {
-                Ok(data) => data,
-                Err(error) => panic!("Problem: {:?}", error),
-            },
-        };
-        assert_eq!(order_account, previous_order_account);
-        assert_eq!(OrderStatus::Paid as u8, order_account.status);
-        // nothing was refunded
-        assert_eq!(0, refund_token_account.amount);
-        let order_token_account = match order_token_account {
-            None => panic!("Oo"),
-            Some(value) => match TokenAccount::unpack(&value.data) {
-                Ok(data) => data,
-                Err(error) => panic!("Problem: {:?}", error),
-            },
-        };
-        assert_eq!(order_account.paid_amount, order_token_account.amount);
-        match account_to_receive_sol_refund_before {
-            None => panic!("Oo"),
-            Some(account_before) => match account_to_receive_sol_refund_after {
-                None => panic!("Oo"),
-                Some(account_after) => {
-                    assert_eq!(account_before.lamports, account_after.lamports);
-                }
-            },
-        };
+        let subscription_account = merchant_result
+            .2
+            .get_account(subscription)
+            .await
+            .unwrap()
+            .unwrap();
+        let subscription_data = SubscriptionAccount::unpack(&subscription_account.data).unwrap();
+        assert_eq!(0, subscription_data.usage_units);
     }
 }
@@ -0,0 +1,420 @@
+//! Exchange format for partially signed invoice transactions
+//!
+//! Meant to be used off-chain to send invoices by email or chat.  The merchant
+//! builds the `PayInvoice` transaction for the buyer, signs it as fee payer and
+//! sends it as a single string (e.g. in a link).  The buyer's wallet shows the
+//! invoice details, adds the buyer's signature and submits the transaction.
+//!
+//! Invoices are usually paid long after a recent blockhash has expired, so the
+//! transaction is expected to use a durable nonce (see `invoice_message`).
+
+use serde::{Deserialize, Serialize};
+use solana_program::{
+    clock::UnixTimestamp, hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey,
+};
+use thiserror::Error;
+
+/// the version of the exchange format
+pub const INVOICE_EXCHANGE_VERSION: u8 = 1;
+/// the size of a transaction signature
+const SIGNATURE_LEN: usize = 64;
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum InvoiceExchangeError {
+    /// Invalid encoding
+    #[error("Error: The invoice is not encoded properly")]
+    InvalidEncoding,
+    /// Unsupported version
+    #[error("Error: Unsupported invoice version {0}")]
+    UnsupportedVersion(u8),
+    /// Invalid transaction
+    #[error("Error: Invalid invoice transaction")]
+    InvalidTransaction,
+    /// The metadata does not match the transaction
+    #[error("Error: The invoice details do not match the transaction")]
+    MetadataMismatch,
+    /// Not a signer of the transaction
+    #[error("Error: {0} is not a signer of the invoice transaction")]
+    UnknownSigner(String),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// The invoice details shown to the buyer before paying
+pub struct InvoiceMetadata {
+    /// the invoice order account
+    pub order: String,
+    pub merchant: String,
+    pub mint: String,
+    pub amount: u64,
+    pub order_id: String,
+    pub description: Option<String>,
+    pub expires_at: Option<UnixTimestamp>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// A partially signed invoice transaction and its details
+pub struct InvoiceExchange {
+    pub version: u8,
+    /// the transaction in wire format, base64 encoded.  Missing signatures are
+    /// all zeroes.
+    pub transaction: String,
+    pub metadata: InvoiceMetadata,
+}
+
+/// A transaction in wire format, split into its signatures and its message
+struct WireTransaction {
+    signatures: Vec<[u8; SIGNATURE_LEN]>,
+    message: Vec<u8>,
+}
+
+fn encode_compact_u16(value: usize, output: &mut Vec<u8>) {
+    let mut rest = value;
+    loop {
+        let byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+        if rest == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+/// Returns the value and the number of bytes it takes
+fn decode_compact_u16(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0;
+    for (index, byte) in data.iter().take(3).enumerate() {
+        value |= ((*byte & 0x7f) as usize) << (index * 7);
+        if *byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+impl WireTransaction {
+    fn unpack(data: &[u8]) -> Result<WireTransaction, InvoiceExchangeError> {
+        let (count, offset) =
+            decode_compact_u16(data).ok_or(InvoiceExchangeError::InvalidTransaction)?;
+        let message_offset = offset + count * SIGNATURE_LEN;
+        if data.len() <= message_offset {
+            return Err(InvoiceExchangeError::InvalidTransaction);
+        }
+        let signatures = data[offset..message_offset]
+            .chunks(SIGNATURE_LEN)
+            .map(|chunk| {
+                let mut signature = [0; SIGNATURE_LEN];
+                signature.copy_from_slice(chunk);
+                signature
+            })
+            .collect();
+        let transaction = WireTransaction {
+            signatures,
+            message: data[message_offset..].to_vec(),
+        };
+        // the message header starts with the number of required signatures
+        if transaction.message[0] as usize != count {
+            return Err(InvoiceExchangeError::InvalidTransaction);
+        }
+        transaction.account_keys()?;
+        Ok(transaction)
+    }
+
+    fn pack(&self) -> Vec<u8> {
+        let mut data = vec![];
+        encode_compact_u16(self.signatures.len(), &mut data);
+        for signature in self.signatures.iter() {
+            data.extend_from_slice(signature);
+        }
+        data.extend_from_slice(&self.message);
+        data
+    }
+
+    /// the account keys of the message, which follow the 3 byte message header
+    fn account_keys(&self) -> Result<Vec<Pubkey>, InvoiceExchangeError> {
+        let data = self.message.get(3..).unwrap_or(&[]);
+        let (count, offset) =
+            decode_compact_u16(data).ok_or(InvoiceExchangeError::InvalidTransaction)?;
+        let keys = data
+            .get(offset..offset + count * 32)
+            .ok_or(InvoiceExchangeError::InvalidTransaction)?;
+        Ok(keys.chunks(32).map(Pubkey::new).collect())
+    }
+
+    /// the signers, in the order of the signatures
+    fn signers(&self) -> Result<Vec<Pubkey>, InvoiceExchangeError> {
+        let mut keys = self.account_keys()?;
+        keys.truncate(self.signatures.len());
+        Ok(keys)
+    }
+}
+
+/// Build the message of an invoice transaction that uses a durable nonce
+///
+/// instruction is usually a `PayInvoice` instruction.  The nonce is the hash
+/// currently stored in the nonce account, and the nonce authority has to sign
+/// the transaction along with the fee payer and the buyer.
+pub fn invoice_message(
+    instruction: Instruction,
+    fee_payer: &Pubkey,
+    nonce_account: &Pubkey,
+    nonce_authority: &Pubkey,
+    nonce: Hash,
+) -> Message {
+    let mut message = Message::new_with_nonce(
+        vec![instruction],
+        Some(fee_payer),
+        nonce_account,
+        nonce_authority,
+    );
+    message.recent_blockhash = nonce;
+    message
+}
+
+/// Create the string that is sent to the buyer
+///
+/// signatures are the signatures of the message (as serialized by
+/// `Message::serialize`) that the merchant already has, e.g. the fee payer's.
+pub fn create_invoice_exchange(
+    message: &Message,
+    signatures: &[(Pubkey, [u8; SIGNATURE_LEN])],
+    metadata: InvoiceMetadata,
+) -> Result<String, InvoiceExchangeError> {
+    let signer_count = message.header.num_required_signatures as usize;
+    let mut transaction = WireTransaction {
+        signatures: vec![[0; SIGNATURE_LEN]; signer_count],
+        message: message.serialize(),
+    };
+    for (signer, signature) in signatures.iter() {
+        match message.account_keys[..signer_count]
+            .iter()
+            .position(|key| key == signer)
+        {
+            None => return Err(InvoiceExchangeError::UnknownSigner(signer.to_string())),
+            Some(index) => transaction.signatures[index] = *signature,
+        }
+    }
+    let exchange = InvoiceExchange {
+        version: INVOICE_EXCHANGE_VERSION,
+        transaction: base64::encode(transaction.pack()),
+        metadata,
+    };
+    exchange.check_metadata(&transaction)?;
+    Ok(base64::encode_config(
+        serde_json::to_string(&exchange).unwrap(),
+        base64::URL_SAFE_NO_PAD,
+    ))
+}
+
+/// Parse the string received by the buyer
+pub fn parse_invoice_exchange(value: &str) -> Result<InvoiceExchange, InvoiceExchangeError> {
+    let json_data = base64::decode_config(value.trim(), base64::URL_SAFE_NO_PAD)
+        .map_err(|_| InvoiceExchangeError::InvalidEncoding)?;
+    let exchange: InvoiceExchange =
+        serde_json::from_slice(&json_data).map_err(|_| InvoiceExchangeError::InvalidEncoding)?;
+    if exchange.version != INVOICE_EXCHANGE_VERSION {
+        return Err(InvoiceExchangeError::UnsupportedVersion(exchange.version));
+    }
+    let transaction = exchange.wire_transaction()?;
+    exchange.check_metadata(&transaction)?;
+    Ok(exchange)
+}
+
+impl InvoiceExchange {
+    fn wire_transaction(&self) -> Result<WireTransaction, InvoiceExchangeError> {
+        let data = base64::decode(&self.transaction)
+            .map_err(|_| InvoiceExchangeError::InvalidTransaction)?;
+        WireTransaction::unpack(&data)
+    }
+
+    /// ensure the order and the merchant shown to the buyer are the ones paid
+    fn check_metadata(&self, transaction: &WireTransaction) -> Result<(), InvoiceExchangeError> {
+        let account_keys: Vec<String> = transaction
+            .account_keys()?
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        if !account_keys.contains(&self.metadata.order)
+            || !account_keys.contains(&self.metadata.merchant)
+        {
+            return Err(InvoiceExchangeError::MetadataMismatch);
+        }
+        Ok(())
+    }
+
+    /// The bytes that the missing signers sign
+    pub fn message_data(&self) -> Result<Vec<u8>, InvoiceExchangeError> {
+        Ok(self.wire_transaction()?.message)
+    }
+
+    /// The signers that have not signed the transaction yet
+    pub fn missing_signers(&self) -> Result<Vec<Pubkey>, InvoiceExchangeError> {
+        let transaction = self.wire_transaction()?;
+        Ok(transaction
+            .signers()?
+            .into_iter()
+            .zip(transaction.signatures.iter())
+            .filter(|(_signer, signature)| signature.iter().all(|byte| *byte == 0))
+            .map(|(signer, _signature)| signer)
+            .collect())
+    }
+
+    /// Add the signature of a signer e.g. the buyer's
+    ///
+    /// Once every signer has signed, the transaction can be submitted as is with
+    /// sendTransaction and the base64 encoding.
+    pub fn add_signature(
+        &mut self,
+        signer: &Pubkey,
+        signature: [u8; SIGNATURE_LEN],
+    ) -> Result<(), InvoiceExchangeError> {
+        let mut transaction = self.wire_transaction()?;
+        match transaction.signers()?.iter().position(|key| key == signer) {
+            None => return Err(InvoiceExchangeError::UnknownSigner(signer.to_string())),
+            Some(index) => transaction.signatures[index] = signature,
+        }
+        self.transaction = base64::encode(transaction.pack());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::instruction::{pay_invoice, PayInvoiceArgs},
+        solana_program::system_instruction,
+        solana_program_test::*,
+    };
+
+    fn metadata(order: &Pubkey, merchant: &Pubkey) -> InvoiceMetadata {
+        InvoiceMetadata {
+            order: order.to_string(),
+            merchant: merchant.to_string(),
+            mint: spl_token::native_mint::id().to_string(),
+            amount: 5000,
+            order_id: String::from("INV-1"),
+            description: Some(String::from("Consulting")),
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoice_exchange() {
+        let merchant_owner = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let nonce_account = Pubkey::new_unique();
+        let instruction = pay_invoice(
+            Pubkey::new_unique(),
+            PayInvoiceArgs {
+                signer: buyer,
+                order,
+                merchant,
+                seller_token: Pubkey::new_unique(),
+                buyer_token: Pubkey::new_unique(),
+                mint: Pubkey::new_unique(),
+                program_owner: Pubkey::new_unique(),
+                sponsor: Pubkey::new_unique(),
+                integrator: None,
+            },
+        );
+        let message = invoice_message(
+            instruction,
+            &merchant_owner,
+            &nonce_account,
+            &merchant_owner,
+            Hash::new_unique(),
+        );
+        // the durable nonce is advanced first
+        assert_eq!(
+            system_instruction::advance_nonce_account(&nonce_account, &merchant_owner).data,
+            message.instructions[0].data
+        );
+
+        // the merchant signs as fee payer
+        let value = create_invoice_exchange(
+            &message,
+            &[(merchant_owner, [1; 64])],
+            metadata(&order, &merchant),
+        )
+        .unwrap();
+        let mut exchange = parse_invoice_exchange(&value).unwrap();
+        assert_eq!(metadata(&order, &merchant), exchange.metadata);
+        assert_eq!(message.serialize(), exchange.message_data().unwrap());
+        assert_eq!(vec![buyer], exchange.missing_signers().unwrap());
+
+        // the buyer completes the transaction
+        exchange.add_signature(&buyer, [2; 64]).unwrap();
+        assert!(exchange.missing_signers().unwrap().is_empty());
+        let transaction = base64::decode(&exchange.transaction).unwrap();
+        assert_eq!(2, transaction[0]);
+        assert_eq!([1; 64][..], transaction[1..65]);
+        assert_eq!([2; 64][..], transaction[65..129]);
+        assert_eq!(message.serialize(), transaction[129..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_invoice_exchange_errors() {
+        let merchant_owner = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let stranger = Pubkey::new_unique();
+        let instruction = pay_invoice(
+            Pubkey::new_unique(),
+            PayInvoiceArgs {
+                signer: Pubkey::new_unique(),
+                order,
+                merchant,
+                seller_token: Pubkey::new_unique(),
+                buyer_token: Pubkey::new_unique(),
+                mint: Pubkey::new_unique(),
+                program_owner: Pubkey::new_unique(),
+                sponsor: Pubkey::new_unique(),
+                integrator: None,
+            },
+        );
+        let message = invoice_message(
+            instruction,
+            &merchant_owner,
+            &Pubkey::new_unique(),
+            &merchant_owner,
+            Hash::new_unique(),
+        );
+        assert_eq!(
+            Err(InvoiceExchangeError::UnknownSigner(stranger.to_string())),
+            create_invoice_exchange(
+                &message,
+                &[(stranger, [1; 64])],
+                metadata(&order, &merchant)
+            )
+        );
+        // the invoice details must match the transaction
+        assert_eq!(
+            Err(InvoiceExchangeError::MetadataMismatch),
+            create_invoice_exchange(&message, &[], metadata(&stranger, &merchant))
+        );
+        assert_eq!(
+            Err(InvoiceExchangeError::InvalidEncoding),
+            parse_invoice_exchange("not an invoice")
+        );
+        let mut exchange = parse_invoice_exchange(
+            &create_invoice_exchange(&message, &[], metadata(&order, &merchant)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            Err(InvoiceExchangeError::UnknownSigner(stranger.to_string())),
+            exchange.add_signature(&stranger, [1; 64])
+        );
+        exchange.version = 2;
+        let value = base64::encode_config(
+            serde_json::to_string(&exchange).unwrap(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        assert_eq!(
+            Err(InvoiceExchangeError::UnsupportedVersion(2)),
+            parse_invoice_exchange(&value)
+        );
+    }
+}
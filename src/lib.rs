@@ -1,7 +1,42 @@
+#[cfg(feature = "off-chain")]
+pub mod analytics;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "off-chain")]
+pub mod deeplink;
 pub mod error;
+#[cfg(feature = "off-chain")]
+pub mod error_catalog;
 pub mod entrypoint;
+pub mod events;
+pub mod fee_preview;
+#[cfg(feature = "client")]
+pub mod filters;
+#[cfg(feature = "off-chain")]
+pub mod hygiene;
+#[cfg(feature = "off-chain")]
+pub mod idl;
 pub mod instruction;
+#[cfg(feature = "off-chain")]
+pub mod invoice_exchange;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "off-chain")]
+pub mod onboarding;
+pub mod payment_id;
+pub mod pda;
 pub mod processor;
+#[cfg(feature = "off-chain")]
+pub mod reconcile;
+pub mod settlement;
+#[cfg(feature = "off-chain")]
+pub mod solana_pay;
 pub mod state;
+#[cfg(test)]
+mod test_support;
 pub mod utils;
+#[cfg(feature = "off-chain")]
+pub mod verify;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 pub mod engine;
@@ -0,0 +1,188 @@
+//! Prometheus metrics for the settlement of a merchant's orders
+//!
+//! Meant to be used off-chain by the services that settle orders for merchants,
+//! to monitor payments settled, submission latency and RPC errors.  Register the
+//! metrics with the registry that the service already exports, or serve the
+//! output of `PrometheusMetrics::encode` on a metrics endpoint.
+
+use crate::settlement::{Settlement, SettlementMetrics};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use solana_program::pubkey::Pubkey;
+use std::time::Duration;
+use thiserror::Error;
+
+/// the prefix of the name of every metric
+pub const METRICS_NAMESPACE: &str = "solpayments";
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    /// The metrics could not be registered or encoded
+    #[error("Error: Prometheus {0}")]
+    Prometheus(#[from] prometheus::Error),
+    /// The encoded metrics are not valid text
+    #[error("Error: Invalid metrics encoding")]
+    InvalidEncoding,
+}
+
+/// Settlement metrics exported to Prometheus
+pub struct PrometheusMetrics {
+    registry: Registry,
+    /// settlement runs started
+    pub runs: IntCounter,
+    /// the calls to the cluster that failed, by call ("fetch" or "submit")
+    pub rpc_errors: IntCounterVec,
+    /// the orders withdrawn, by mint
+    pub payments_settled: IntCounterVec,
+    /// the amount withdrawn, by mint
+    pub amount_settled: IntCounterVec,
+    /// how long submitting a withdrawal took
+    pub submit_latency: Histogram,
+}
+
+impl PrometheusMetrics {
+    /// Create the metrics in a registry of their own
+    pub fn new() -> Result<Self, MetricsError> {
+        PrometheusMetrics::with_registry(Registry::new())
+    }
+
+    /// Create the metrics and register them with the given registry
+    pub fn with_registry(registry: Registry) -> Result<Self, MetricsError> {
+        let runs = IntCounter::with_opts(
+            Opts::new("settlement_runs_total", "Settlement runs started")
+                .namespace(METRICS_NAMESPACE),
+        )?;
+        let rpc_errors = IntCounterVec::new(
+            Opts::new("rpc_errors_total", "Calls to the cluster that failed")
+                .namespace(METRICS_NAMESPACE),
+            &["call"],
+        )?;
+        let payments_settled = IntCounterVec::new(
+            Opts::new("payments_settled_total", "Orders withdrawn").namespace(METRICS_NAMESPACE),
+            &["mint"],
+        )?;
+        let amount_settled = IntCounterVec::new(
+            Opts::new(
+                "amount_settled_total",
+                "Amount withdrawn in the smallest unit of the mint",
+            )
+            .namespace(METRICS_NAMESPACE),
+            &["mint"],
+        )?;
+        let submit_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "submit_latency_seconds",
+                "Time taken to submit a withdrawal and wait for it",
+            )
+            .namespace(METRICS_NAMESPACE),
+        )?;
+        registry.register(Box::new(runs.clone()))?;
+        registry.register(Box::new(rpc_errors.clone()))?;
+        registry.register(Box::new(payments_settled.clone()))?;
+        registry.register(Box::new(amount_settled.clone()))?;
+        registry.register(Box::new(submit_latency.clone()))?;
+        Ok(PrometheusMetrics {
+            registry,
+            runs,
+            rpc_errors,
+            payments_settled,
+            amount_settled,
+            submit_latency,
+        })
+    }
+
+    /// The registry holding the metrics
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn encode(&self) -> Result<String, MetricsError> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|_| MetricsError::InvalidEncoding)
+    }
+}
+
+impl SettlementMetrics for PrometheusMetrics {
+    fn run_started(&self, _merchant: &Pubkey) {
+        self.runs.inc();
+    }
+
+    fn run_failed(&self, _merchant: &Pubkey, _error: &str) {
+        self.rpc_errors.with_label_values(&["fetch"]).inc();
+    }
+
+    fn settled(&self, settlement: &Settlement) {
+        let mint = settlement.mint.to_string();
+        self.payments_settled.with_label_values(&[&mint]).inc();
+        self.amount_settled
+            .with_label_values(&[&mint])
+            .inc_by(settlement.amount);
+    }
+
+    fn settlement_failed(&self, _settlement: &Settlement, _error: &str) {
+        self.rpc_errors.with_label_values(&["submit"]).inc();
+    }
+
+    fn submitted(&self, _settlement: &Settlement, duration: Duration) {
+        self.submit_latency.observe(duration.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program::instruction::Instruction, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let metrics = PrometheusMetrics::new().unwrap();
+        let mint = Pubkey::new_unique();
+        let settlement = Settlement {
+            order: Pubkey::new_unique(),
+            mint,
+            amount: 1500,
+            instruction: Instruction {
+                program_id: Pubkey::new_unique(),
+                accounts: vec![],
+                data: vec![],
+            },
+        };
+        metrics.run_started(&Pubkey::new_unique());
+        metrics.submitted(&settlement, Duration::from_millis(20));
+        metrics.settled(&settlement);
+        metrics.settled(&settlement);
+        metrics.settlement_failed(&settlement, "blockhash not found");
+        metrics.run_failed(&Pubkey::new_unique(), "connection refused");
+
+        assert_eq!(1, metrics.runs.get());
+        assert_eq!(1, metrics.rpc_errors.with_label_values(&["fetch"]).get());
+        assert_eq!(1, metrics.rpc_errors.with_label_values(&["submit"]).get());
+        assert_eq!(
+            2,
+            metrics
+                .payments_settled
+                .with_label_values(&[&mint.to_string()])
+                .get()
+        );
+        assert_eq!(
+            3000,
+            metrics
+                .amount_settled
+                .with_label_values(&[&mint.to_string()])
+                .get()
+        );
+        assert_eq!(1, metrics.submit_latency.get_sample_count());
+
+        let encoded = metrics.encode().unwrap();
+        assert!(encoded.contains("solpayments_settlement_runs_total 1"));
+        assert!(encoded.contains(&format!(
+            "solpayments_payments_settled_total{{mint=\"{}\"}} 2",
+            mint
+        )));
+
+        // the metrics can only be registered once with a registry
+        assert!(PrometheusMetrics::with_registry(metrics.registry().clone()).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! Bulk onboarding of merchants from CSV
+//!
+//! Meant to be used off-chain by platforms that move many sellers onto SolPayments
+//! at once.  Each CSV row becomes a RegisterMerchant instruction signed by the
+//! platform wallet.  Merchant addresses are derived from the wallet and the seed,
+//! so rows whose merchant account already exists are skipped and an interrupted
+//! onboarding can simply be run again with the same CSV.
+
+use crate::{
+    engine::constants::MERCHANT,
+    instruction::{register_merchant, RegisterMerchantArgs},
+};
+use serde::Serialize;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum OnboardingError {
+    /// Invalid merchant seed
+    #[error("Error: Invalid merchant seed on line {0}")]
+    InvalidSeed(usize),
+    /// Seed used by an earlier line
+    #[error("Error: Duplicate merchant seed on line {0}")]
+    DuplicateSeed(usize),
+    /// Invalid fee
+    #[error("Error: Invalid fee on line {0}")]
+    InvalidFee(usize),
+    /// Invalid sponsor address
+    #[error("Error: Invalid sponsor on line {0}")]
+    InvalidSponsor(usize),
+    /// Merchant data is not JSON
+    #[error("Error: Invalid merchant data on line {0}")]
+    InvalidData(usize),
+}
+
+impl OnboardingError {
+    /// the CSV line that the error is about
+    pub fn line(&self) -> usize {
+        match self {
+            OnboardingError::InvalidSeed(line)
+            | OnboardingError::DuplicateSeed(line)
+            | OnboardingError::InvalidFee(line)
+            | OnboardingError::InvalidSponsor(line)
+            | OnboardingError::InvalidData(line) => *line,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A merchant to register
+pub struct MerchantRow {
+    /// the line of the CSV that the merchant was read from
+    pub line: usize,
+    pub seed: Option<String>,
+    pub fee: Option<u64>,
+    pub sponsor: Option<Pubkey>,
+    pub data: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// What has to be done to onboard a CSV row
+pub enum OnboardingAction {
+    /// the merchant has to be registered by sending this instruction
+    Register(Instruction),
+    /// the merchant account already exists
+    AlreadyRegistered,
+    /// the row cannot be onboarded
+    Invalid(OnboardingError),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A CSV row and what has to be done to onboard it
+pub struct OnboardingStep {
+    pub line: usize,
+    pub seed: Option<String>,
+    /// the address of the merchant account, when it can be derived
+    pub merchant: Option<Pubkey>,
+    pub action: OnboardingAction,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// The outcome of onboarding a CSV row
+pub struct OnboardingReport {
+    pub line: usize,
+    pub seed: Option<String>,
+    pub merchant: Option<String>,
+    /// one of registered, already_registered, pending or failed
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// Remove the CSV quotes around a column, if any
+fn unquote(column: &str) -> String {
+    if column.len() >= 2 && column.starts_with('"') && column.ends_with('"') {
+        column[1..column.len() - 1].replace("\"\"", "\"")
+    } else {
+        String::from(column)
+    }
+}
+
+/// Parse a single CSV line into a merchant
+fn parse_merchant_row(line: usize, text: &str) -> Result<MerchantRow, OnboardingError> {
+    let mut columns = text.splitn(4, ',');
+    let seed = columns.next().unwrap_or("").trim();
+    let fee = columns.next().unwrap_or("").trim();
+    let sponsor = columns.next().unwrap_or("").trim();
+    let data = unquote(columns.next().unwrap_or("").trim());
+    Ok(MerchantRow {
+        line,
+        seed: match seed {
+            "" => None,
+            value => Some(String::from(value)),
+        },
+        fee: match fee {
+            "" => None,
+            value => Some(
+                value
+                    .parse::<u64>()
+                    .map_err(|_error| OnboardingError::InvalidFee(line))?,
+            ),
+        },
+        sponsor: match sponsor {
+            "" => None,
+            value => Some(
+                Pubkey::from_str(value).map_err(|_error| OnboardingError::InvalidSponsor(line))?,
+            ),
+        },
+        data: match data.as_str() {
+            "" => None,
+            value => {
+                serde_json::from_str::<serde_json::Value>(value)
+                    .map_err(|_error| OnboardingError::InvalidData(line))?;
+                Some(data)
+            }
+        },
+    })
+}
+
+/// Parse the merchants to onboard from CSV
+///
+/// Each line looks like so: seed,fee,sponsor,data
+/// Only the seed is required, and an empty seed stands for the default merchant
+/// seed.  The data column is last so that it can hold JSON with or without CSV
+/// quotes.  A header line (starting with "seed") and empty lines are ignored, and
+/// invalid lines are returned as errors without stopping the parsing.
+pub fn parse_merchant_rows(csv: &str) -> Vec<Result<MerchantRow, OnboardingError>> {
+    let mut rows = vec![];
+    for (index, text) in csv.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if index == 0 && text.split(',').next() == Some("seed") {
+            // the header
+            continue;
+        }
+        rows.push(parse_merchant_row(index + 1, text));
+    }
+    rows
+}
+
+/// Plan the onboarding of the merchants found in the CSV
+///
+/// registered_merchants are the merchant accounts that already exist e.g. as
+/// returned by getProgramAccounts.  Every CSV row gets a step, in order, so that
+/// errors can be reported per row.
+pub fn plan_onboarding(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    csv: &str,
+    registered_merchants: &[Pubkey],
+) -> Vec<OnboardingStep> {
+    let registered_merchants: BTreeSet<&Pubkey> = registered_merchants.iter().collect();
+    let mut seen_merchants: BTreeSet<Pubkey> = BTreeSet::new();
+    let mut steps = vec![];
+    for maybe_row in parse_merchant_rows(csv) {
+        let row = match maybe_row {
+            Err(error) => {
+                steps.push(OnboardingStep {
+                    line: error.line(),
+                    seed: None,
+                    merchant: None,
+                    action: OnboardingAction::Invalid(error),
+                });
+                continue;
+            }
+            Ok(value) => value,
+        };
+        let seed = match &row.seed {
+            None => MERCHANT,
+            Some(value) => value.as_str(),
+        };
+        let (merchant, action) = match Pubkey::create_with_seed(owner, seed, program_id) {
+            Err(_error) => (
+                None,
+                OnboardingAction::Invalid(OnboardingError::InvalidSeed(row.line)),
+            ),
+            Ok(merchant) if !seen_merchants.insert(merchant) => (
+                Some(merchant),
+                OnboardingAction::Invalid(OnboardingError::DuplicateSeed(row.line)),
+            ),
+            Ok(merchant) if registered_merchants.contains(&merchant) => {
+                (Some(merchant), OnboardingAction::AlreadyRegistered)
+            }
+            Ok(merchant) => (
+                Some(merchant),
+                OnboardingAction::Register(register_merchant(
+                    *program_id,
+                    RegisterMerchantArgs {
+                        signer: *owner,
+                        merchant,
+                        seed: row.seed.clone(),
+                        fee: row.fee,
+                        data: row.data.clone(),
+                        sponsor: row.sponsor.as_ref(),
+                        sponsor_fee: None,
+                    },
+                )),
+            ),
+        };
+        steps.push(OnboardingStep {
+            line: row.line,
+            seed: row.seed,
+            merchant,
+            action,
+        });
+    }
+    steps
+}
+
+/// Report the outcome of each onboarding step
+///
+/// results holds the outcome of sending the Register instructions, keyed by CSV
+/// line.  Register steps without a result are reported as pending.
+pub fn onboarding_report(
+    steps: &[OnboardingStep],
+    results: &BTreeMap<usize, Result<(), String>>,
+) -> Vec<OnboardingReport> {
+    steps
+        .iter()
+        .map(|step| {
+            let (status, error) = match &step.action {
+                OnboardingAction::AlreadyRegistered => ("already_registered", None),
+                OnboardingAction::Invalid(error) => ("failed", Some(error.to_string())),
+                OnboardingAction::Register(_instruction) => match results.get(&step.line) {
+                    None => ("pending", None),
+                    Some(Ok(())) => ("registered", None),
+                    Some(Err(error)) => ("failed", Some(error.clone())),
+                },
+            };
+            OnboardingReport {
+                line: step.line,
+                seed: step.seed.clone(),
+                merchant: step.merchant.map(|merchant| merchant.to_string()),
+                status: String::from(status),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Report the outcome of each onboarding step as a JSON string
+pub fn onboarding_report_to_json(
+    steps: &[OnboardingStep],
+    results: &BTreeMap<usize, Result<(), String>>,
+) -> String {
+    serde_json::to_string(&onboarding_report(steps, results)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_parse_merchant_rows() {
+        let sponsor = Pubkey::new_unique();
+        let csv = format!(
+            "seed,fee,sponsor,data\nshop-1,,,\n\nshop-2,5000,{},\"{{\"\"code\"\": \"\"a,b\"\"}}\"\nshop-3,lots,,\nshop-4,,,{{\"code\": 1}}\n",
+            sponsor
+        );
+        assert_eq!(
+            vec![
+                Ok(MerchantRow {
+                    line: 2,
+                    seed: Some(String::from("shop-1")),
+                    fee: None,
+                    sponsor: None,
+                    data: None,
+                }),
+                Ok(MerchantRow {
+                    line: 4,
+                    seed: Some(String::from("shop-2")),
+                    fee: Some(5000),
+                    sponsor: Some(sponsor),
+                    data: Some(String::from(r#"{"code": "a,b"}"#)),
+                }),
+                Err(OnboardingError::InvalidFee(5)),
+                Ok(MerchantRow {
+                    line: 6,
+                    seed: Some(String::from("shop-4")),
+                    fee: None,
+                    sponsor: None,
+                    data: Some(String::from(r#"{"code": 1}"#)),
+                }),
+            ],
+            parse_merchant_rows(&csv)
+        );
+        assert_eq!(
+            vec![
+                Err(OnboardingError::InvalidSponsor(1)),
+                Err(OnboardingError::InvalidData(2)),
+            ],
+            parse_merchant_rows("shop-1,,nobody,\nshop-2,,,{not json\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_onboarding() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let merchant = |seed: &str| Pubkey::create_with_seed(&owner, seed, &program_id).unwrap();
+        let csv = "seed,fee,sponsor,data\nshop-1,,,\nshop-2,,,\nshop-1,,,\nshop-3,x,,\n,,,\nthis-seed-is-far-too-long-to-be-a-seed,,,";
+        let steps = plan_onboarding(&program_id, &owner, csv, &[merchant("shop-1")]);
+        assert_eq!(6, steps.len());
+        assert_eq!(OnboardingAction::AlreadyRegistered, steps[0].action);
+        assert_eq!(Some(merchant("shop-1")), steps[0].merchant);
+        assert_eq!(
+            OnboardingAction::Register(register_merchant(
+                program_id,
+                RegisterMerchantArgs {
+                    signer: owner,
+                    merchant: merchant("shop-2"),
+                    seed: Some(String::from("shop-2")),
+                    fee: None,
+                    data: None,
+                    sponsor: None,
+                    sponsor_fee: None,
+                },
+            )),
+            steps[1].action
+        );
+        assert_eq!(
+            OnboardingAction::Invalid(OnboardingError::DuplicateSeed(4)),
+            steps[2].action
+        );
+        assert_eq!(
+            OnboardingAction::Invalid(OnboardingError::InvalidFee(5)),
+            steps[3].action
+        );
+        // an empty seed registers the default merchant account of the owner
+        assert_eq!(Some(merchant(MERCHANT)), steps[4].merchant);
+        assert_eq!(
+            OnboardingAction::Invalid(OnboardingError::InvalidSeed(7)),
+            steps[5].action
+        );
+
+        // resuming after shop-2 and the default merchant were registered
+        let steps = plan_onboarding(
+            &program_id,
+            &owner,
+            csv,
+            &[merchant("shop-1"), merchant("shop-2"), merchant(MERCHANT)],
+        );
+        assert!(steps
+            .iter()
+            .all(|step| !matches!(step.action, OnboardingAction::Register(_))));
+    }
+
+    #[tokio::test]
+    async fn test_onboarding_report() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let steps = plan_onboarding(
+            &program_id,
+            &owner,
+            "shop-1,,,\nshop-2,,,\nshop-3,,,\nshop-4,x,,",
+            &[],
+        );
+        let mut results = BTreeMap::new();
+        results.insert(1, Ok(()));
+        results.insert(2, Err(String::from("insufficient funds")));
+        let json_value: serde_json::Value =
+            serde_json::from_str(&onboarding_report_to_json(&steps, &results)).unwrap();
+        assert_eq!("registered", json_value[0]["status"]);
+        assert_eq!(
+            Pubkey::create_with_seed(&owner, "shop-1", &program_id)
+                .unwrap()
+                .to_string(),
+            json_value[0]["merchant"]
+        );
+        assert_eq!("failed", json_value[1]["status"]);
+        assert_eq!("insufficient funds", json_value[1]["error"]);
+        assert_eq!("pending", json_value[2]["status"]);
+        assert_eq!("failed", json_value[3]["status"]);
+        assert_eq!("Error: Invalid fee on line 4", json_value[3]["error"]);
+    }
+}
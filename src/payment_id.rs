@@ -0,0 +1,158 @@
+//! Canonical payment identifiers
+//!
+//! A payment id names a payment the same way in every system that deals with it
+//! (the merchant's shop, the payment processor, accounting...).  It is derived
+//! from the program id, the order account and the most recent slot hash at the
+//! time of payment, so it is unique across instances of the program and across
+//! clusters, and anyone can recompute it from on-chain data.  It only uses
+//! characters that are safe in URLs and fits in the usual 64 character
+//! external reference fields of e-commerce systems.
+//!
+//! Checkouts that include the SlotHashes sysvar log the payment id (see
+//! PAYMENT_ID_LOG_PREFIX), so clients and indexers can read it from the
+//! transaction logs.
+
+use crate::engine::validation::check_sysvar;
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Slot,
+    hash::{hashv, Hash, HASH_BYTES},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar,
+};
+use std::convert::TryInto;
+
+/// every payment id starts with this
+pub const PAYMENT_ID_PREFIX: &str = "solpay-";
+/// the transaction log line that holds the payment id
+pub const PAYMENT_ID_LOG_PREFIX: &str = "SolPayments: PaymentId ";
+
+/// Get the payment id of an order
+///
+/// slot and slot_hash are the most recent entry of the SlotHashes sysvar when
+/// the order was paid
+pub fn get_payment_id(program_id: &Pubkey, order: &Pubkey, slot: Slot, slot_hash: &Hash) -> String {
+    let digest = hashv(&[
+        &program_id.to_bytes(),
+        &order.to_bytes(),
+        &slot.to_le_bytes(),
+        &slot_hash.to_bytes(),
+    ]);
+    format!("{}{}", PAYMENT_ID_PREFIX, digest)
+}
+
+/// Get the most recent slot and slot hash from the SlotHashes sysvar account
+pub fn get_recent_slot_hash(
+    slot_hashes_info: &AccountInfo<'_>,
+) -> Result<(Slot, Hash), ProgramError> {
+    check_sysvar(slot_hashes_info, &sysvar::slot_hashes::id())?;
+    let data = slot_hashes_info.try_borrow_data()?;
+    // the entries are preceded by their count and the most recent comes first
+    if data.len() < 16 + HASH_BYTES || data[..8] == [0; 8] {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let slot = Slot::from_le_bytes(data[8..16].try_into().unwrap());
+    let slot_hash = Hash::new(&data[16..16 + HASH_BYTES]);
+    Ok((slot, slot_hash))
+}
+
+/// Find the payment id in the logs of a checkout transaction
+pub fn find_payment_id(logs: &[String]) -> Option<String> {
+    logs.iter().find_map(|log| {
+        log.find(PAYMENT_ID_LOG_PREFIX)
+            .map(|index| String::from(&log[index + PAYMENT_ID_LOG_PREFIX.len()..]))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_get_payment_id() {
+        let program_id = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let slot_hash = Hash::new_unique();
+        let payment_id = get_payment_id(&program_id, &order, 42, &slot_hash);
+        assert!(payment_id.starts_with(PAYMENT_ID_PREFIX));
+        assert!(payment_id.len() <= 64);
+        assert!(payment_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-'));
+        assert_eq!(
+            payment_id,
+            get_payment_id(&program_id, &order, 42, &slot_hash)
+        );
+        // any of the parts makes for a different payment id
+        assert_ne!(
+            payment_id,
+            get_payment_id(&Pubkey::new_unique(), &order, 42, &slot_hash)
+        );
+        assert_ne!(
+            payment_id,
+            get_payment_id(&program_id, &Pubkey::new_unique(), 42, &slot_hash)
+        );
+        assert_ne!(
+            payment_id,
+            get_payment_id(&program_id, &order, 43, &slot_hash)
+        );
+        assert_ne!(
+            payment_id,
+            get_payment_id(&program_id, &order, 42, &Hash::new_unique())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_recent_slot_hash() {
+        let slot_hash = Hash::new_unique();
+        let mut data = vec![];
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(&99u64.to_le_bytes());
+        data.extend_from_slice(&slot_hash.to_bytes());
+        data.extend_from_slice(&98u64.to_le_bytes());
+        data.extend_from_slice(&Hash::new_unique().to_bytes());
+        let key = sysvar::slot_hashes::id();
+        let owner = sysvar::id();
+        let mut lamports = 0;
+        let slot_hashes_info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(Ok((99, slot_hash)), get_recent_slot_hash(&slot_hashes_info));
+
+        let other_key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut other_data = vec![0; 48];
+        let other_info = AccountInfo::new(
+            &other_key,
+            false,
+            false,
+            &mut lamports,
+            &mut other_data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            get_recent_slot_hash(&other_info)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_payment_id() {
+        let logs = vec![
+            String::from("Program log: SolPayments: ExpressCheckout"),
+            String::from("Program log: SolPayments: PaymentId solpay-abc"),
+        ];
+        assert_eq!(Some(String::from("solpay-abc")), find_payment_id(&logs));
+        assert_eq!(None, find_payment_id(&logs[..1]));
+    }
+}
@@ -0,0 +1,58 @@
+//! Addresses derived by the program
+//!
+//! Other programs that call the payment processor through CPI (marketplaces,
+//! games...) depend on this crate with the `no-entrypoint` feature, which leaves
+//! out the program entrypoint so that its symbol does not clash with theirs.
+//! The instruction handlers are only public with the `handlers` feature, so the
+//! functions that derive the addresses of the program accounts are re-exported
+//! here, next to the instruction builders (`instruction`), the account sizes
+//! (`utils`), the account layouts (`state`) and the errors (`error`).
+
+pub use crate::engine::{
+    accepted_mints::get_accepted_mints_address,
+    associated_token::{
+        get_associated_token_address, get_associated_token_program_id, get_order_token_address,
+    },
+    attestation::get_attestation_address,
+    auto_debit::get_auto_debit_address,
+    blocklist::get_blocked_buyer_address,
+    bundle::{get_bundle_address, get_bundle_member_address},
+    campaign::get_campaign_address,
+    catalog::get_package_address,
+    common::get_order_address,
+    config::{get_program_config_address, get_program_data_address},
+    constants::{MERCHANT, PDA_SEED},
+    coupon::get_coupon_address,
+    fee_history::{get_fee_history_address, get_fee_schedule_address},
+    guarantee::get_price_guarantee_address,
+    link::{get_link_payer_address, get_payment_link_address},
+    migrate::get_migration_address,
+    notes::{get_order_note_address, get_order_notes_address},
+    oracle::get_price_oracle_address,
+    receipt::{get_master_edition_address, get_metadata_address},
+    recovery::{get_merchant_recovery_address, get_merchant_transfer_address},
+    referral::get_referrer_address,
+    refund::{get_refund_code_address, get_refund_code_hash},
+    settlement::get_settlement_address,
+    stats::get_merchant_stats_address,
+    stock::get_item_stock_address,
+    store::get_store_address,
+    sweep::get_sweep_bounty_address,
+    voucher::get_voucher_address,
+};
+use solana_program::pubkey::{Pubkey, PubkeyError};
+
+/// Get the program derived address that owns the order token accounts
+pub fn get_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PDA_SEED], program_id)
+}
+
+/// Get the address of a merchant account registered with the given seed (or
+/// MERCHANT when there is none) by its owner
+pub fn get_merchant_address(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    seed: Option<&str>,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_with_seed(owner, seed.unwrap_or(MERCHANT), program_id)
+}
@@ -1,8 +1,72 @@
 use crate::{
+    engine::accepted_mints::process_set_accepted_mints,
+    engine::attestation::{process_export_attestation, process_import_attestation},
+    engine::auto_debit::{process_approve_auto_debit, process_collect_payment},
+    engine::blocklist::{process_block_buyer, process_unblock_buyer},
+    engine::bundle::{process_create_bundle, process_join_bundle},
+    engine::campaign::{process_create_campaign, process_donate},
     engine::cancel_subscription::process_cancel_subscription,
-    engine::pay::process_express_checkout, engine::pay::process_chain_checkout, engine::register::process_register_merchant,
-    engine::renew::process_renew_subscription, engine::subscribe::process_subscribe,
-    engine::withdraw::process_withdraw_payment, instruction::PaymentProcessorInstruction,
+    engine::catalog::{process_create_package, process_retire_package, process_update_package},
+    engine::close_merchant::process_close_merchant,
+    engine::close_order::process_close_order,
+    engine::close_subscription::process_close_subscription,
+    engine::config::{
+        process_initialize_program, process_set_merchant_sponsor_fee, process_set_migration_window,
+        process_update_config,
+    },
+    engine::coupon::{process_create_coupon, CouponTerms},
+    engine::escrow::process_confirm_delivery,
+    engine::expire::process_expire_subscription,
+    engine::fee_history::process_set_fee_schedule,
+    engine::freeze_policy::process_set_freeze_policy,
+    engine::guarantee::process_guarantee_package_price,
+    engine::installment::process_pay_installment,
+    engine::instant_settlement::process_set_instant_settlement,
+    engine::integrator::{process_register_integrator, process_withdraw_integrator_fees},
+    engine::invoice::{process_create_invoice, process_pay_invoice},
+    engine::link::{process_create_payment_link, process_pay_link},
+    engine::migrate::process_migrate_account,
+    engine::notes::process_post_order_note,
+    engine::operators::process_set_operators,
+    engine::oracle::process_set_price_oracle,
+    engine::pause::process_set_merchant_paused,
+    engine::pause_subscription::{process_pause_subscription, process_resume_subscription},
+    engine::pay::process_chain_checkout,
+    engine::pay::process_express_checkout,
+    engine::pay::process_express_checkout_sol,
+    engine::pay::process_split_checkout,
+    engine::pay::OrderArgs,
+    engine::payout::process_set_payout_schedule,
+    engine::reclaim::process_reclaim_expired_order,
+    engine::recovery::{
+        process_accept_merchant_transfer, process_complete_merchant_recovery,
+        process_propose_merchant_transfer, process_set_merchant_recovery,
+        process_start_merchant_recovery, record_owner_activity, split_merchant_recovery,
+    },
+    engine::referral::process_set_referrer,
+    engine::refund::{process_issue_refund_code, process_redeem_refund_code},
+    engine::register::{
+        process_register_merchant, process_register_merchant_with_terms, process_update_merchant,
+    },
+    engine::renew::process_renew_due_subscriptions,
+    engine::renew::process_renew_subscription,
+    engine::seats::process_update_seats,
+    engine::settlement::{process_set_settlement_mint, process_withdraw_settled},
+    engine::stats::process_set_merchant_stats,
+    engine::stock::process_restock_item,
+    engine::store::{process_close_store, process_create_store},
+    engine::sub_order::process_split_order,
+    engine::subscribe::process_subscribe,
+    engine::sweep::{process_claim_sweep_bounties, process_set_sweep_policy, process_sweep},
+    engine::sysvars::{ProgramSysvars, SysvarProvider},
+    engine::terms::process_migrate_merchant_data,
+    engine::tolerance::process_set_merchant_tolerance,
+    engine::transfer_subscription::process_transfer_subscription,
+    engine::usage::{process_record_usage, process_settle_usage},
+    engine::voucher::process_redeem_voucher,
+    engine::waiver::process_waive_order_fees,
+    engine::withdraw::{process_withdraw_amount, process_withdraw_payment},
+    instruction::PaymentProcessorInstruction,
 };
 use borsh::BorshDeserialize;
 use solana_program::{
@@ -17,21 +81,88 @@ impl PaymentProcessorInstruction {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let instruction = PaymentProcessorInstruction::try_from_slice(&instruction_data)
+        let instruction = PaymentProcessorInstruction::try_from_slice(instruction_data)
             .map_err(|_| ProgramError::InvalidInstructionData)?;
-        match instruction {
-            PaymentProcessorInstruction::RegisterMerchant { seed, fee, data } => {
+        instruction.run(program_id, accounts, &ProgramSysvars)
+    }
+
+    /// Routes the instruction to its handler with the given sysvars
+    ///
+    /// The sysvars are injected so that the handlers can be reused outside of the
+    /// program entrypoint e.g. by forks or in tests.
+    #[cfg(any(test, feature = "handlers"))]
+    pub fn dispatch(
+        self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        sysvars: &dyn SysvarProvider,
+    ) -> ProgramResult {
+        self.run(program_id, accounts, sysvars)
+    }
+
+    /// Routes the instruction to its handler
+    ///
+    /// The recovery account of a merchant may follow the accounts of the other
+    /// instructions, to record that the merchant owner who signed them is active.
+    fn run(
+        self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        sysvars: &dyn SysvarProvider,
+    ) -> ProgramResult {
+        let (accounts, possible_recovery_info) = match &self {
+            PaymentProcessorInstruction::SetMerchantRecovery { .. }
+            | PaymentProcessorInstruction::StartMerchantRecovery
+            | PaymentProcessorInstruction::CompleteMerchantRecovery
+            | PaymentProcessorInstruction::AcceptMerchantTransfer => (accounts, None),
+            _ => split_merchant_recovery(program_id, accounts),
+        };
+        self.route(program_id, accounts, sysvars)?;
+        match possible_recovery_info {
+            None => Ok(()),
+            Some(recovery_info) => {
+                record_owner_activity(program_id, accounts, recovery_info, sysvars)
+            }
+        }
+    }
+
+    /// Calls the handler of the instruction
+    fn route(
+        self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        sysvars: &dyn SysvarProvider,
+    ) -> ProgramResult {
+        match self {
+            PaymentProcessorInstruction::RegisterMerchant {
+                seed,
+                fee,
+                data,
+                sponsor_fee,
+            } => {
                 msg!("SolPayments: RegisterMerchant");
-                process_register_merchant(program_id, accounts, seed, fee, data)
+                process_register_merchant(program_id, accounts, seed, fee, data, sponsor_fee)
             }
             PaymentProcessorInstruction::ExpressCheckout {
                 amount,
+                tip,
                 order_id,
                 secret,
                 data,
             } => {
                 msg!("SolPayments: ExpressCheckout");
-                process_express_checkout(program_id, accounts, amount, order_id, secret, data)
+                process_express_checkout(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    OrderArgs {
+                        amount,
+                        order_id,
+                        secret,
+                        maybe_data: data,
+                    },
+                    tip,
+                )
             }
             PaymentProcessorInstruction::ChainCheckout {
                 amount,
@@ -39,24 +170,3087 @@ impl PaymentProcessorInstruction {
                 data,
             } => {
                 msg!("SolPayments: ChainCheckout");
-                process_chain_checkout(program_id, accounts, amount, order_items, data)
+                process_chain_checkout(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    amount,
+                    order_items.into_iter().collect(),
+                    data,
+                )
             }
-            PaymentProcessorInstruction::Withdraw { close_order_account } => {
+            PaymentProcessorInstruction::Withdraw {
+                close_order_account,
+            } => {
                 msg!("SolPayments: Withdraw");
-                process_withdraw_payment(program_id, accounts, close_order_account)
+                process_withdraw_payment(program_id, accounts, sysvars, close_order_account)
             }
-            PaymentProcessorInstruction::Subscribe { name, data } => {
+            PaymentProcessorInstruction::Subscribe { name, data, seats } => {
                 msg!("SolPayments: Subscribe");
-                process_subscribe(program_id, accounts, name, data)
+                process_subscribe(program_id, accounts, sysvars, name, data, seats)
             }
             PaymentProcessorInstruction::RenewSubscription { quantity } => {
                 msg!("SolPayments: RenewSubscription");
-                process_renew_subscription(program_id, accounts, quantity)
+                process_renew_subscription(program_id, accounts, sysvars, quantity)
             }
             PaymentProcessorInstruction::CancelSubscription => {
                 msg!("SolPayments: CancelSubscription");
-                process_cancel_subscription(program_id, accounts)
+                process_cancel_subscription(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::ReclaimExpiredOrder => {
+                msg!("SolPayments: ReclaimExpiredOrder");
+                process_reclaim_expired_order(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::ExpressCheckoutSol {
+                amount,
+                tip,
+                order_id,
+                secret,
+                data,
+            } => {
+                msg!("SolPayments: ExpressCheckoutSol");
+                process_express_checkout_sol(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    OrderArgs {
+                        amount,
+                        order_id,
+                        secret,
+                        maybe_data: data,
+                    },
+                    tip,
+                )
+            }
+            PaymentProcessorInstruction::RegisterIntegrator { app_id } => {
+                msg!("SolPayments: RegisterIntegrator");
+                process_register_integrator(program_id, accounts, app_id)
+            }
+            PaymentProcessorInstruction::WithdrawIntegratorFees => {
+                msg!("SolPayments: WithdrawIntegratorFees");
+                process_withdraw_integrator_fees(program_id, accounts)
+            }
+            PaymentProcessorInstruction::ConfirmDelivery { amount } => {
+                msg!("SolPayments: ConfirmDelivery");
+                process_confirm_delivery(program_id, accounts, sysvars, amount)
+            }
+            PaymentProcessorInstruction::InitializeProgram {
+                default_fee,
+                min_fee,
+                sponsor_fee,
+                name,
+                data,
+            } => {
+                msg!("SolPayments: InitializeProgram");
+                process_initialize_program(
+                    program_id,
+                    accounts,
+                    default_fee,
+                    min_fee,
+                    sponsor_fee,
+                    name,
+                    data,
+                )
+            }
+            PaymentProcessorInstruction::ExportAttestation => {
+                msg!("SolPayments: ExportAttestation");
+                process_export_attestation(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::ImportAttestation => {
+                msg!("SolPayments: ImportAttestation");
+                process_import_attestation(program_id, accounts)
+            }
+            PaymentProcessorInstruction::SplitCheckout {
+                amount,
+                order_id,
+                secret,
+                data,
+                splits,
+            } => {
+                msg!("SolPayments: SplitCheckout");
+                process_split_checkout(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    OrderArgs {
+                        amount,
+                        order_id,
+                        secret,
+                        maybe_data: data,
+                    },
+                    splits,
+                )
+            }
+            PaymentProcessorInstruction::PayInstallment { amount } => {
+                msg!("SolPayments: PayInstallment");
+                process_pay_installment(program_id, accounts, sysvars, amount)
+            }
+            PaymentProcessorInstruction::RedeemVoucher { voucher } => {
+                msg!("SolPayments: RedeemVoucher");
+                process_redeem_voucher(program_id, accounts, sysvars, voucher)
+            }
+            PaymentProcessorInstruction::CreateInvoice {
+                amount,
+                order_id,
+                secret,
+                data,
+            } => {
+                msg!("SolPayments: CreateInvoice");
+                process_create_invoice(
+                    program_id, accounts, sysvars, amount, order_id, secret, data,
+                )
+            }
+            PaymentProcessorInstruction::PayInvoice => {
+                msg!("SolPayments: PayInvoice");
+                process_pay_invoice(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::SetMigrationWindow { ends_at, version } => {
+                msg!("SolPayments: SetMigrationWindow");
+                process_set_migration_window(program_id, accounts, ends_at, version)
+            }
+            PaymentProcessorInstruction::WaiveOrderFees => {
+                msg!("SolPayments: WaiveOrderFees");
+                process_waive_order_fees(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::CreateCoupon {
+                code,
+                discount_type,
+                discount,
+                mint,
+                max_uses,
+                expires_at,
+            } => {
+                msg!("SolPayments: CreateCoupon");
+                process_create_coupon(
+                    program_id,
+                    accounts,
+                    code,
+                    CouponTerms {
+                        discount_type,
+                        discount,
+                        mint,
+                        max_uses,
+                        expires_at,
+                    },
+                )
+            }
+            PaymentProcessorInstruction::CreatePaymentLink {
+                link_id,
+                amount,
+                data,
+            } => {
+                msg!("SolPayments: CreatePaymentLink");
+                process_create_payment_link(program_id, accounts, sysvars, link_id, amount, data)
+            }
+            PaymentProcessorInstruction::SetMerchantTolerance { tolerance } => {
+                msg!("SolPayments: SetMerchantTolerance");
+                process_set_merchant_tolerance(program_id, accounts, tolerance)
+            }
+            PaymentProcessorInstruction::GuaranteePackagePrice {
+                package,
+                guaranteed_until,
+            } => {
+                msg!("SolPayments: GuaranteePackagePrice");
+                process_guarantee_package_price(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    package,
+                    guaranteed_until,
+                )
+            }
+            PaymentProcessorInstruction::ApproveAutoDebit {
+                allowance,
+                max_amount,
+                interval,
+            } => {
+                msg!("SolPayments: ApproveAutoDebit");
+                process_approve_auto_debit(
+                    program_id, accounts, sysvars, allowance, max_amount, interval,
+                )
+            }
+            PaymentProcessorInstruction::CollectPayment {
+                amount,
+                order_id,
+                secret,
+                data,
+            } => {
+                msg!("SolPayments: CollectPayment");
+                process_collect_payment(
+                    program_id, accounts, sysvars, amount, order_id, secret, data,
+                )
+            }
+            PaymentProcessorInstruction::RenewDueSubscriptions => {
+                msg!("SolPayments: RenewDueSubscriptions");
+                process_renew_due_subscriptions(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::SetFeeSchedule {
+                default_fee,
+                min_fee,
+                sponsor_fee,
+            } => {
+                msg!("SolPayments: SetFeeSchedule");
+                process_set_fee_schedule(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    default_fee,
+                    min_fee,
+                    sponsor_fee,
+                )
+            }
+            PaymentProcessorInstruction::PayLink { amount, tip } => {
+                msg!("SolPayments: PayLink");
+                process_pay_link(program_id, accounts, sysvars, amount, tip)
+            }
+            PaymentProcessorInstruction::CreateCampaign {
+                campaign_id,
+                goal,
+                data,
+            } => {
+                msg!("SolPayments: CreateCampaign");
+                process_create_campaign(program_id, accounts, sysvars, campaign_id, goal, data)
+            }
+            PaymentProcessorInstruction::Donate {
+                amount,
+                order_id,
+                secret,
+                data,
+            } => {
+                msg!("SolPayments: Donate");
+                process_donate(
+                    program_id, accounts, sysvars, amount, order_id, secret, data,
+                )
+            }
+            PaymentProcessorInstruction::IssueRefundCode { code_hash } => {
+                msg!("SolPayments: IssueRefundCode");
+                process_issue_refund_code(program_id, accounts, sysvars, code_hash)
+            }
+            PaymentProcessorInstruction::RedeemRefundCode { code } => {
+                msg!("SolPayments: RedeemRefundCode");
+                process_redeem_refund_code(program_id, accounts, sysvars, code)
+            }
+            PaymentProcessorInstruction::SetMerchantRecovery {
+                recovery_key,
+                timeout,
+                challenge_window,
+            } => {
+                msg!("SolPayments: SetMerchantRecovery");
+                process_set_merchant_recovery(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    recovery_key,
+                    timeout,
+                    challenge_window,
+                )
+            }
+            PaymentProcessorInstruction::StartMerchantRecovery => {
+                msg!("SolPayments: StartMerchantRecovery");
+                process_start_merchant_recovery(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::CompleteMerchantRecovery => {
+                msg!("SolPayments: CompleteMerchantRecovery");
+                process_complete_merchant_recovery(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::RestockItem { item, quantity } => {
+                msg!("SolPayments: RestockItem");
+                process_restock_item(program_id, accounts, item, quantity)
+            }
+            PaymentProcessorInstruction::SetPriceOracle {
+                max_staleness,
+                max_slippage,
+            } => {
+                msg!("SolPayments: SetPriceOracle");
+                process_set_price_oracle(program_id, accounts, max_staleness, max_slippage)
+            }
+            PaymentProcessorInstruction::CreateBundle {
+                bundle_id,
+                discount,
+            } => {
+                msg!("SolPayments: CreateBundle");
+                process_create_bundle(program_id, accounts, sysvars, bundle_id, discount)
+            }
+            PaymentProcessorInstruction::JoinBundle { package } => {
+                msg!("SolPayments: JoinBundle");
+                process_join_bundle(program_id, accounts, sysvars, package)
+            }
+            PaymentProcessorInstruction::SetSettlementMint => {
+                msg!("SolPayments: SetSettlementMint");
+                process_set_settlement_mint(program_id, accounts)
+            }
+            PaymentProcessorInstruction::WithdrawSettled { minimum_amount_out } => {
+                msg!("SolPayments: WithdrawSettled");
+                process_withdraw_settled(program_id, accounts, sysvars, minimum_amount_out)
+            }
+            PaymentProcessorInstruction::PostOrderNote { note } => {
+                msg!("SolPayments: PostOrderNote");
+                process_post_order_note(program_id, accounts, sysvars, note)
+            }
+            PaymentProcessorInstruction::UpdateMerchant { fee, sponsor, data } => {
+                msg!("SolPayments: UpdateMerchant");
+                process_update_merchant(program_id, accounts, fee, sponsor, data)
+            }
+            PaymentProcessorInstruction::SplitOrder { amount, order_id } => {
+                msg!("SolPayments: SplitOrder");
+                process_split_order(program_id, accounts, sysvars, amount, order_id)
+            }
+            PaymentProcessorInstruction::ProposeMerchantTransfer { new_owner } => {
+                msg!("SolPayments: ProposeMerchantTransfer");
+                process_propose_merchant_transfer(program_id, accounts, sysvars, new_owner)
+            }
+            PaymentProcessorInstruction::AcceptMerchantTransfer => {
+                msg!("SolPayments: AcceptMerchantTransfer");
+                process_accept_merchant_transfer(program_id, accounts)
+            }
+            PaymentProcessorInstruction::SetMerchantStats {
+                max_open_token_accounts,
+                open_token_accounts,
+            } => {
+                msg!("SolPayments: SetMerchantStats");
+                process_set_merchant_stats(
+                    program_id,
+                    accounts,
+                    max_open_token_accounts,
+                    open_token_accounts,
+                )
+            }
+            PaymentProcessorInstruction::CloseMerchant => {
+                msg!("SolPayments: CloseMerchant");
+                process_close_merchant(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::CloseOrder => {
+                msg!("SolPayments: CloseOrder");
+                process_close_order(program_id, accounts)
+            }
+            PaymentProcessorInstruction::CreateStore {
+                store_id,
+                fee_destination,
+                catalog,
+                data,
+            } => {
+                msg!("SolPayments: CreateStore");
+                process_create_store(
+                    program_id,
+                    accounts,
+                    sysvars,
+                    store_id,
+                    fee_destination,
+                    catalog,
+                    data,
+                )
+            }
+            PaymentProcessorInstruction::CloseStore => {
+                msg!("SolPayments: CloseStore");
+                process_close_store(program_id, accounts)
+            }
+            PaymentProcessorInstruction::PauseMerchant => {
+                msg!("SolPayments: PauseMerchant");
+                process_set_merchant_paused(program_id, accounts, true)
+            }
+            PaymentProcessorInstruction::ResumeMerchant => {
+                msg!("SolPayments: ResumeMerchant");
+                process_set_merchant_paused(program_id, accounts, false)
+            }
+            PaymentProcessorInstruction::SetAcceptedMints { mints } => {
+                msg!("SolPayments: SetAcceptedMints");
+                process_set_accepted_mints(program_id, accounts, mints)
+            }
+            PaymentProcessorInstruction::BlockBuyer { buyer } => {
+                msg!("SolPayments: BlockBuyer");
+                process_block_buyer(program_id, accounts, sysvars, buyer)
+            }
+            PaymentProcessorInstruction::UnblockBuyer => {
+                msg!("SolPayments: UnblockBuyer");
+                process_unblock_buyer(program_id, accounts)
+            }
+            PaymentProcessorInstruction::UpdateConfig {
+                default_fee,
+                min_fee,
+                sponsor_fee,
+            } => {
+                msg!("SolPayments: UpdateConfig");
+                process_update_config(program_id, accounts, default_fee, min_fee, sponsor_fee)
+            }
+            PaymentProcessorInstruction::SetMerchantSponsorFee { sponsor_fee } => {
+                msg!("SolPayments: SetMerchantSponsorFee");
+                process_set_merchant_sponsor_fee(program_id, accounts, sponsor_fee)
+            }
+            PaymentProcessorInstruction::SetReferrer {
+                wallet,
+                referral_fee,
+            } => {
+                msg!("SolPayments: SetReferrer");
+                process_set_referrer(program_id, accounts, wallet, referral_fee)
+            }
+            PaymentProcessorInstruction::PauseSubscription => {
+                msg!("SolPayments: PauseSubscription");
+                process_pause_subscription(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::ResumeSubscription => {
+                msg!("SolPayments: ResumeSubscription");
+                process_resume_subscription(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::ExpireSubscription => {
+                msg!("SolPayments: ExpireSubscription");
+                process_expire_subscription(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::TransferSubscription => {
+                msg!("SolPayments: TransferSubscription");
+                process_transfer_subscription(program_id, accounts)
+            }
+            PaymentProcessorInstruction::RecordUsage { units } => {
+                msg!("SolPayments: RecordUsage");
+                process_record_usage(program_id, accounts, units)
             }
+            PaymentProcessorInstruction::SettleUsage => {
+                msg!("SolPayments: SettleUsage");
+                process_settle_usage(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::UpdateSeats { seats } => {
+                msg!("SolPayments: UpdateSeats");
+                process_update_seats(program_id, accounts, sysvars, seats)
+            }
+            PaymentProcessorInstruction::CreatePackage { data } => {
+                msg!("SolPayments: CreatePackage");
+                process_create_package(program_id, accounts, sysvars, data)
+            }
+            PaymentProcessorInstruction::UpdatePackage { data } => {
+                msg!("SolPayments: UpdatePackage");
+                process_update_package(program_id, accounts, sysvars, data)
+            }
+            PaymentProcessorInstruction::RetirePackage => {
+                msg!("SolPayments: RetirePackage");
+                process_retire_package(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::CloseSubscription => {
+                msg!("SolPayments: CloseSubscription");
+                process_close_subscription(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::SetInstantSettlement { instant_settlement } => {
+                msg!("SolPayments: SetInstantSettlement");
+                process_set_instant_settlement(program_id, accounts, instant_settlement)
+            }
+            PaymentProcessorInstruction::SetPayoutSchedule { payouts } => {
+                msg!("SolPayments: SetPayoutSchedule");
+                process_set_payout_schedule(program_id, accounts, payouts)
+            }
+            PaymentProcessorInstruction::SetOperators { operators } => {
+                msg!("SolPayments: SetOperators");
+                process_set_operators(program_id, accounts, operators)
+            }
+            PaymentProcessorInstruction::WithdrawAmount { amount } => {
+                msg!("SolPayments: WithdrawAmount");
+                process_withdraw_amount(program_id, accounts, sysvars, amount)
+            }
+            PaymentProcessorInstruction::SetSweepPolicy { threshold, delay } => {
+                msg!("SolPayments: SetSweepPolicy");
+                process_set_sweep_policy(program_id, accounts, sysvars, threshold, delay)
+            }
+            PaymentProcessorInstruction::Sweep => {
+                msg!("SolPayments: Sweep");
+                process_sweep(program_id, accounts, sysvars)
+            }
+            PaymentProcessorInstruction::RegisterMerchantWithTerms {
+                seed,
+                fee,
+                terms,
+                metadata,
+                sponsor_fee,
+            } => {
+                msg!("SolPayments: RegisterMerchantWithTerms");
+                process_register_merchant_with_terms(
+                    program_id,
+                    accounts,
+                    seed,
+                    fee,
+                    terms,
+                    metadata,
+                    sponsor_fee,
+                )
+            }
+            PaymentProcessorInstruction::MigrateMerchantData => {
+                msg!("SolPayments: MigrateMerchantData");
+                process_migrate_merchant_data(program_id, accounts)
+            }
+            PaymentProcessorInstruction::MigrateAccount { seed } => {
+                msg!("SolPayments: MigrateAccount");
+                process_migrate_account(program_id, accounts, sysvars, seed)
+            }
+            PaymentProcessorInstruction::SetFreezePolicy {
+                rejects_freezable_mints,
+            } => {
+                msg!("SolPayments: SetFreezePolicy");
+                process_set_freeze_policy(program_id, accounts, rejects_freezable_mints)
+            }
+            PaymentProcessorInstruction::ClaimSweepBounties => {
+                msg!("SolPayments: ClaimSweepBounties");
+                process_claim_sweep_bounties(program_id, accounts)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::engine::cancel_subscription::get_prorated_refund,
+        crate::engine::catalog::get_package_address,
+        crate::engine::common::{
+            get_package_of_subscription, get_package_to_subscribe, is_order_expired,
+        },
+        crate::engine::config::get_program_config_address,
+        crate::engine::constants::{
+            ACCOUNT_VERSION, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS, MERCHANT, PDA_SEED,
+            PROGRAM_VERSION, SPONSOR_FEE, SUBSCRIPTION_RETENTION_PERIOD, SWEEP_BOUNTY_IN_LAMPORTS,
+        },
+        crate::engine::freeze_policy::check_freezable_mints,
+        crate::engine::json::Package,
+        crate::engine::migrate::get_migration_address,
+        crate::engine::seats::get_prorated_seats_price,
+        crate::engine::sweep::{get_sweep_bounty, get_sweep_bounty_address},
+        crate::engine::sysvars::FixedSysvars,
+        crate::engine::token::get_token_2022_program_id,
+        crate::engine::validation::{
+            check_account_migrated, check_account_type, check_pda, check_program_owned,
+            check_signer, check_sysvar,
+        },
+        crate::engine::withdraw::check_order_withdrawable,
+        crate::error::PaymentProcessorError,
+        crate::state::{
+            has_account_discriminator, Discriminator, MerchantAccount, MigrationAccount,
+            OrderAccount, OrderStatus, PackageAccount, ProgramConfigAccount, PublicKey, Serdes,
+            SubscriptionAccount, SubscriptionStatus, SweepBountyAccount,
+        },
+        crate::utils::{
+            get_merchant_account_size, get_migration_account_size, get_order_account_size,
+            get_package_account_size, get_subscription_account_size,
+        },
+        borsh::BorshSerialize,
+        proptest::prelude::*,
+        solana_program::{clock::UnixTimestamp, program_option::COption, program_pack::Pack},
+        solana_program_test::*,
+        spl_token::state::{Account as TokenAccount, AccountState, Mint},
+    };
+
+    #[tokio::test]
+    async fn test_dispatch_with_fixed_sysvars() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let payer = Pubkey::new_unique();
+        let order_key = Pubkey::new_unique();
+        let order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097600,
+            merchant: Pubkey::new_unique().to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: payer.to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from(r#"{"escrow": 3600}"#),
+            version: ACCOUNT_VERSION,
+        };
+        let mut order_data =
+            vec![0; get_order_account_size(&order.order_id, &order.secret, &order.data)];
+        order.pack(&mut order_data);
+        let mut payer_lamports = 0;
+        let mut payer_data = vec![];
+        let mut order_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &payer,
+                true,
+                true,
+                &mut payer_lamports,
+                &mut payer_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &order_key,
+                false,
+                true,
+                &mut order_lamports,
+                &mut order_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::ConfirmDelivery { amount: None }.dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp: 1625184000,
+                    slot: 0,
+                },
+            )
+        );
+        let order = OrderAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(OrderStatus::Releasable as u8, order.status);
+        assert_eq!(1625184000, order.modified);
+    }
+
+    #[tokio::test]
+    async fn test_partial_delivery_confirmation() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let payer = Pubkey::new_unique();
+        let order_key = Pubkey::new_unique();
+        let order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097600,
+            merchant: Pubkey::new_unique().to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: payer.to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 20,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from(r#"{"escrow": 3600}"#),
+            version: ACCOUNT_VERSION,
+        };
+        let mut order_data =
+            vec![0; get_order_account_size(&order.order_id, &order.secret, &order.data)];
+        order.pack(&mut order_data);
+        let mut payer_lamports = 0;
+        let mut payer_data = vec![];
+        let mut order_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &payer,
+                true,
+                true,
+                &mut payer_lamports,
+                &mut payer_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &order_key,
+                false,
+                true,
+                &mut order_lamports,
+                &mut order_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let sysvars = FixedSysvars {
+            unix_timestamp: 1625098000,
+            slot: 0,
+        };
+
+        // the buyer releases part of the payment, the rest stays held
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::ConfirmDelivery { amount: Some(50) }.dispatch(
+                &program_id,
+                &accounts,
+                &sysvars,
+            )
+        );
+        let order = OrderAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(OrderStatus::Paid as u8, order.status);
+        assert_eq!(50, order.released_amount);
+        assert_eq!(
+            Err(PaymentProcessorError::OrderNotReleasable.into()),
+            check_order_withdrawable(&order, sysvars.unix_timestamp)
+        );
+
+        // no more than the payment and tip can be released
+        for amount in [0, 71].iter() {
+            assert_eq!(
+                Err(ProgramError::InvalidInstructionData),
+                PaymentProcessorInstruction::ConfirmDelivery {
+                    amount: Some(*amount)
+                }
+                .dispatch(&program_id, &accounts, &sysvars)
+            );
+        }
+
+        // releasing the rest releases the order
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::ConfirmDelivery { amount: Some(70) }.dispatch(
+                &program_id,
+                &accounts,
+                &sysvars,
+            )
+        );
+        let order = OrderAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(OrderStatus::Releasable as u8, order.status);
+        assert_eq!(
+            Ok(()),
+            check_order_withdrawable(&order, sysvars.unix_timestamp)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_escrow_release_on_timeout() {
+        let mut order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097600,
+            merchant: Pubkey::new_unique().to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from(r#"{"escrow": 3600}"#),
+            version: ACCOUNT_VERSION,
+        };
+        // held until the escrow timeout elapses
+        assert_eq!(
+            Err(PaymentProcessorError::OrderNotReleasable.into()),
+            check_order_withdrawable(&order, 1625097600 + 3599)
+        );
+        // released once it has elapsed, without a delivery confirmation
+        assert_eq!(Ok(()), check_order_withdrawable(&order, 1625097600 + 3600));
+        // an escrow timeout that overflows the creation time is refused
+        order.data = format!(r#"{{"escrow": {}}}"#, i64::MAX);
+        assert_eq!(
+            Err(PaymentProcessorError::AmountOverflow.into()),
+            check_order_withdrawable(&order, i64::MAX)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_order_not_withdrawable() {
+        let mut order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097600,
+            merchant: Pubkey::new_unique().to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from(r#"{"expires_at": 1625101200}"#),
+            version: ACCOUNT_VERSION,
+        };
+        assert_eq!(Ok(()), check_order_withdrawable(&order, 1625101199));
+        // once expired, only the payer can reclaim the order
+        assert!(is_order_expired(&order, 1625101200));
+        assert_eq!(
+            Err(PaymentProcessorError::OrderExpired.into()),
+            check_order_withdrawable(&order, 1625101200)
+        );
+        // an order that was paid when it had already expired is not up for reclaim
+        order.data = String::from(r#"{"expires_at": 1625097600}"#);
+        assert!(!is_order_expired(&order, 1625101200));
+        assert_eq!(Ok(()), check_order_withdrawable(&order, 1625101200));
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_sol_token_program() {
+        let program_id = Pubkey::new_unique();
+        let token_2022_program_id = get_token_2022_program_id();
+        let native_mint = spl_token::native_mint::id();
+        let keys: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let mut lamports: Vec<u64> = vec![0; 12];
+        let mut data: Vec<Vec<u8>> = vec![vec![]; 12];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .enumerate()
+            .map(|(index, ((key, lamports), data))| {
+                let key = match index {
+                    7 => &native_mint,
+                    9 => &token_2022_program_id,
+                    _ => key,
+                };
+                AccountInfo::new(key, index == 0, true, lamports, data, &program_id, false, 0)
+            })
+            .collect();
+
+        // wrapped SOL cannot be paid through Token-2022
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            PaymentProcessorInstruction::ExpressCheckoutSol {
+                amount: 1000,
+                tip: 0,
+                order_id: String::from("1"),
+                secret: String::from(""),
+                data: None,
+            }
+            .dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp: 1625097600,
+                    slot: 0,
+                },
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expire_subscription() {
+        let program_id = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let subscription_key = Pubkey::new_unique();
+        let period_end = 1625097600;
+        let merchant = MerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 1,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: format!(
+                r#"{{"packages":[{{"name":"basic","price":100,"duration":720,"grace_seconds":3600,"mint":"{}"}}]}}"#,
+                Pubkey::new_unique()
+            ),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            merchant: merchant_key.to_bytes(),
+            name: String::from("basic"),
+            joined: period_end - 720,
+            period_start: period_end - 720,
+            period_end,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut subscription_data =
+            vec![0; get_subscription_account_size(&subscription.name, &subscription.data)];
+        subscription.pack(&mut subscription_data);
+        let mut merchant_lamports = 0;
+        let mut subscription_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &subscription_key,
+                false,
+                true,
+                &mut subscription_lamports,
+                &mut subscription_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &merchant_key,
+                false,
+                true,
+                &mut merchant_lamports,
+                &mut merchant_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let expire_at = |unix_timestamp| {
+            PaymentProcessorInstruction::ExpireSubscription.dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp,
+                    slot: 0,
+                },
+            )
+        };
+
+        // the subscription cannot expire during its period
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            expire_at(period_end - 1)
+        );
+        // it is past due within the grace period
+        assert_eq!(Ok(()), expire_at(period_end + 60));
+        let subscription = SubscriptionAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(SubscriptionStatus::PastDue as u8, subscription.status);
+        let merchant = MerchantAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(1, merchant.references);
+        // and expires after it
+        assert_eq!(Ok(()), expire_at(period_end + 3600));
+        let subscription = SubscriptionAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(SubscriptionStatus::Expired as u8, subscription.status);
+        let merchant = MerchantAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(0, merchant.references);
+        assert_eq!(
+            Err(PaymentProcessorError::SubscriptionExpired.into()),
+            expire_at(period_end + 7200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prorated_refund_from_period_order() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let token_program_id = spl_token::id();
+        let owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let subscription_key = Pubkey::new_unique();
+        let first_order_key = Pubkey::new_unique();
+        let order_key = Pubkey::new_unique();
+        let order_token_key = Pubkey::new_unique();
+        let refund_token_key = Pubkey::new_unique();
+        let mint_key = Pubkey::new_unique();
+        let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let period_start = 1625097600;
+        let merchant = MerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 1,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: format!(
+                r#"{{"packages":[{{"name":"basic","price":100,"duration":1000,"prorated_refunds":true,"mint":"{}"}}]}}"#,
+                mint_key
+            ),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        // the subscription was renewed with a second order, which pays for its
+        // current period
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: owner.to_bytes(),
+            merchant: merchant_key.to_bytes(),
+            name: String::from("basic"),
+            joined: period_start - 1000,
+            period_start,
+            period_end: period_start + 1000,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: [0; 32],
+            price: 100,
+            period_order: order_key.to_bytes(),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut subscription_data =
+            vec![0; get_subscription_account_size(&subscription.name, &subscription.data)];
+        subscription.pack(&mut subscription_data);
+        let order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: period_start,
+            modified: period_start,
+            merchant: merchant_key.to_bytes(),
+            mint: mint_key.to_bytes(),
+            token: order_token_key.to_bytes(),
+            payer: owner.to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 2,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("basic-2"),
+            secret: String::from(""),
+            data: format!(r#"{{"subscription":"{}"}}"#, subscription_key),
+            version: ACCOUNT_VERSION,
+        };
+        let mut order_data =
+            vec![0; get_order_account_size(&order.order_id, &order.secret, &order.data)];
+        order.pack(&mut order_data);
+        let mut first_order_data = order_data.clone();
+        let mut refund_token_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: mint_key,
+                owner,
+                amount: 0,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::None,
+                delegated_amount: 0,
+                close_authority: COption::None,
+            },
+            &mut refund_token_data,
+        )
+        .unwrap();
+        let mut mint_data = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                mint_authority: COption::None,
+                supply: 0,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut mint_data,
+        )
+        .unwrap();
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut subscription_lamports = 0;
+        let mut merchant_lamports = 0;
+        let mut first_order_lamports = 0;
+        let mut order_lamports = 0;
+        let mut order_token_lamports = 0;
+        let mut order_token_data = vec![];
+        let mut refund_token_lamports = 0;
+        let mut pda_lamports = 0;
+        let mut pda_data = vec![];
+        let mut token_program_lamports = 0;
+        let mut token_program_data = vec![];
+        let mut mint_lamports = 0;
+        let owner_info = AccountInfo::new(
+            &owner,
+            true,
+            true,
+            &mut owner_lamports,
+            &mut owner_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let subscription_info = AccountInfo::new(
+            &subscription_key,
+            false,
+            true,
+            &mut subscription_lamports,
+            &mut subscription_data,
+            &program_id,
+            false,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        let first_order_info = AccountInfo::new(
+            &first_order_key,
+            false,
+            true,
+            &mut first_order_lamports,
+            &mut first_order_data,
+            &program_id,
+            false,
+            0,
+        );
+        let order_info = AccountInfo::new(
+            &order_key,
+            false,
+            true,
+            &mut order_lamports,
+            &mut order_data,
+            &program_id,
+            false,
+            0,
+        );
+        let order_token_info = AccountInfo::new(
+            &order_token_key,
+            false,
+            true,
+            &mut order_token_lamports,
+            &mut order_token_data,
+            &token_program_id,
+            false,
+            0,
+        );
+        let refund_token_info = AccountInfo::new(
+            &refund_token_key,
+            false,
+            true,
+            &mut refund_token_lamports,
+            &mut refund_token_data,
+            &token_program_id,
+            false,
+            0,
+        );
+        let pda_info = AccountInfo::new(
+            &pda,
+            false,
+            false,
+            &mut pda_lamports,
+            &mut pda_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program_id,
+            false,
+            false,
+            &mut token_program_lamports,
+            &mut token_program_data,
+            &system_program_id,
+            true,
+            0,
+        );
+        let mint_info = AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut mint_lamports,
+            &mut mint_data,
+            &token_program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            owner_info.clone(),
+            subscription_info.clone(),
+            merchant_info.clone(),
+            order_info.clone(),
+            order_token_info,
+            refund_token_info,
+            owner_info,
+            pda_info,
+            token_program_info,
+            mint_info,
+        ];
+        let mut first_order_accounts = accounts.clone();
+        first_order_accounts[3] = first_order_info;
+        let cancel = |accounts: &[AccountInfo]| {
+            PaymentProcessorInstruction::CancelSubscription.dispatch(
+                &program_id,
+                accounts,
+                &FixedSysvars {
+                    unix_timestamp: period_start + 250,
+                    slot: 0,
+                },
+            )
+        };
+
+        // the first order paid for a past period
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            cancel(&first_order_accounts)
+        );
+        // the unused part of the current period is refunded from its order
+        assert_eq!(Ok(()), cancel(&accounts));
+        let order = OrderAccount::unpack(&order_info.data.borrow()).unwrap();
+        assert_eq!(25, order.paid_amount);
+        let subscription = SubscriptionAccount::unpack(&subscription_info.data.borrow()).unwrap();
+        assert_eq!(SubscriptionStatus::Cancelled as u8, subscription.status);
+        assert_eq!(period_start + 250, subscription.period_end);
+        let merchant = MerchantAccount::unpack(&merchant_info.data.borrow()).unwrap();
+        assert_eq!(0, merchant.references);
+    }
+
+    /// Get a subscription package with the given price and duration
+    fn get_package(price: u64, duration: i64) -> Package {
+        Package {
+            name: String::from("basic"),
+            trial: None,
+            duration,
+            price,
+            mint: Pubkey::new_unique().to_string(),
+            prorated_refunds: Some(true),
+            price_due_at_trial_end: None,
+            grace_seconds: None,
+            unit_price: None,
         }
     }
+
+    proptest! {
+        #[test]
+        fn test_prorated_refund_properties(
+            paid_amount in any::<u64>(),
+            price in any::<u64>(),
+            seats in any::<u64>(),
+            duration in any::<i64>(),
+            period_end in any::<i64>(),
+            timestamp in any::<i64>(),
+        ) {
+            let order = OrderAccount {
+                discriminator: Discriminator::OrderExpressCheckout as u8,
+                status: OrderStatus::Paid as u8,
+                created: 0,
+                modified: 0,
+                merchant: [0; 32],
+                mint: [0; 32],
+                token: [0; 32],
+                payer: [0; 32],
+                expected_amount: paid_amount,
+                paid_amount,
+                tip: 0,
+                transfer_fee: 0,
+                withdrawn_amount: 0,
+                sequence: 0,
+                items: vec![],
+                released_amount: 0,
+                created_slot: 0,
+                sweep_bounty: 0,
+                order_id: String::from("1"),
+                secret: String::from(""),
+                data: String::from("{}"),
+                version: ACCOUNT_VERSION,
+            };
+            let package = get_package(price, duration);
+            let refund = get_prorated_refund(&order, &package, seats, period_end, timestamp);
+            // the refund never takes more than what was paid, nor anything once
+            // the period is over
+            prop_assert!(refund <= paid_amount);
+            if timestamp >= period_end || duration <= 0 {
+                prop_assert_eq!(0, refund);
+            }
+        }
+
+        #[test]
+        fn test_prorated_seats_price_properties(
+            price in any::<u64>(),
+            duration in any::<i64>(),
+            added_seats in any::<u64>(),
+            prorated_seconds in any::<i64>(),
+        ) {
+            let package = get_package(price, duration);
+            let expected_price = if duration <= 0 || prorated_seconds <= 0 {
+                Some(0)
+            } else {
+                (added_seats as u128)
+                    .checked_mul(price as u128)
+                    .and_then(|value| value.checked_mul(prorated_seconds as u128))
+                    .map(|value| value / duration as u128)
+                    .filter(|value| *value <= u64::MAX as u128)
+            };
+            match get_prorated_seats_price(&package, added_seats, prorated_seconds) {
+                Ok(value) => prop_assert_eq!(expected_price, Some(value as u128)),
+                Err(error) => {
+                    prop_assert_eq!(None, expected_price);
+                    prop_assert_eq!(ProgramError::from(PaymentProcessorError::AmountOverflow), error);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_subscription() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let owner = Pubkey::new_unique();
+        let subscription_key = Pubkey::new_unique();
+        let period_end = 1625097600;
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: owner.to_bytes(),
+            merchant: Pubkey::new_unique().to_bytes(),
+            name: String::from("basic"),
+            joined: period_end - 720,
+            period_start: period_end - 720,
+            period_end,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut subscription_data =
+            vec![0; get_subscription_account_size(&subscription.name, &subscription.data)];
+        subscription.pack(&mut subscription_data);
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut subscription_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &owner,
+                true,
+                false,
+                &mut owner_lamports,
+                &mut owner_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &subscription_key,
+                false,
+                true,
+                &mut subscription_lamports,
+                &mut subscription_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let dispatch_at = |instruction: PaymentProcessorInstruction, unix_timestamp| {
+            instruction.dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp,
+                    slot: 0,
+                },
+            )
+        };
+        let pause_at = |unix_timestamp| {
+            dispatch_at(
+                PaymentProcessorInstruction::PauseSubscription,
+                unix_timestamp,
+            )
+        };
+        let resume_at = |unix_timestamp| {
+            dispatch_at(
+                PaymentProcessorInstruction::ResumeSubscription,
+                unix_timestamp,
+            )
+        };
+
+        // only paused subscriptions can be resumed
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            resume_at(period_end - 600)
+        );
+        // pausing freezes the rest of the period
+        assert_eq!(Ok(()), pause_at(period_end - 600));
+        let subscription = SubscriptionAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(SubscriptionStatus::Paused as u8, subscription.status);
+        assert_eq!(600, subscription.remaining_seconds);
+        assert_eq!(period_end - 600, subscription.period_end);
+        assert_eq!(Err(ProgramError::InvalidAccountData), pause_at(period_end));
+        // resuming carries on from the current time
+        assert_eq!(Ok(()), resume_at(period_end + 3600));
+        let subscription = SubscriptionAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(SubscriptionStatus::Initialized as u8, subscription.status);
+        assert_eq!(0, subscription.remaining_seconds);
+        assert_eq!(period_end + 4200, subscription.period_end);
+    }
+
+    #[tokio::test]
+    async fn test_update_seats() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let subscription_key = Pubkey::new_unique();
+        let period_end = 1625097600;
+        let merchant = MerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 1,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: format!(
+                r#"{{"packages":[{{"name":"team","price":100,"duration":720,"mint":"{}"}}]}}"#,
+                Pubkey::new_unique()
+            ),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: owner.to_bytes(),
+            merchant: merchant_key.to_bytes(),
+            name: String::from("team"),
+            joined: period_end - 720,
+            period_start: period_end - 720,
+            period_end,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 4,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut subscription_data =
+            vec![0; get_subscription_account_size(&subscription.name, &subscription.data)];
+        subscription.pack(&mut subscription_data);
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut merchant_lamports = 0;
+        let mut subscription_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &owner,
+                true,
+                true,
+                &mut owner_lamports,
+                &mut owner_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &subscription_key,
+                false,
+                true,
+                &mut subscription_lamports,
+                &mut subscription_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &merchant_key,
+                false,
+                true,
+                &mut merchant_lamports,
+                &mut merchant_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let update_seats_at = |seats, unix_timestamp| {
+            PaymentProcessorInstruction::UpdateSeats { seats }.dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp,
+                    slot: 0,
+                },
+            )
+        };
+
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            update_seats_at(0, period_end - 600)
+        );
+        // the unused part of the period of the removed seats goes to the other seats
+        assert_eq!(Ok(()), update_seats_at(2, period_end - 600));
+        let subscription = SubscriptionAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(2, subscription.seats);
+        assert_eq!(period_end + 600, subscription.period_end);
+        // added seats have to be paid for
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            update_seats_at(3, period_end - 600)
+        );
+        // unless the period has ended, in which case the next renewal pays for them
+        assert_eq!(Ok(()), update_seats_at(3, period_end + 600));
+        let subscription = SubscriptionAccount::unpack(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(3, subscription.seats);
+        assert_eq!(period_end + 600, subscription.period_end);
+    }
+
+    #[tokio::test]
+    async fn test_package_catalog() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let (package_key, _bump_seed) = get_package_address(&program_id, &merchant_key, "pro");
+        let mint = Pubkey::new_unique();
+        let package_data = |name, price| {
+            format!(
+                r#"{{"name":"{}","price":{},"duration":720,"mint":"{}"}}"#,
+                name, price, mint
+            )
+        };
+        let merchant = MerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 1,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from(r#"{"packages":[]}"#),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let package = PackageAccount {
+            discriminator: Discriminator::Package as u8,
+            merchant: merchant_key.to_bytes(),
+            is_retired: false,
+            modified: 1625097600,
+            name: String::from("pro"),
+            data: package_data("pro", 100),
+            version: ACCOUNT_VERSION,
+        };
+        let mut package_data_bytes =
+            vec![0; get_package_account_size(&package.name, &package.data)];
+        package.pack(&mut package_data_bytes);
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut package_lamports = 0;
+        let mut merchant_lamports = 0;
+        let accounts = vec![
+            AccountInfo::new(
+                &owner,
+                true,
+                true,
+                &mut owner_lamports,
+                &mut owner_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &package_key,
+                false,
+                true,
+                &mut package_lamports,
+                &mut package_data_bytes,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &merchant_key,
+                false,
+                true,
+                &mut merchant_lamports,
+                &mut merchant_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let sysvars = FixedSysvars {
+            unix_timestamp: 1625184000,
+            slot: 0,
+        };
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: Pubkey::new_unique().to_bytes(),
+            merchant: merchant_key.to_bytes(),
+            name: String::from("pro"),
+            joined: 1625097600,
+            period_start: 1625097600,
+            period_end: 1625098320,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: package_key.to_bytes(),
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+
+        // the package keeps its name and has to fit in its account
+        assert_eq!(
+            Err(PaymentProcessorError::InvalidSubscriptionPackage.into()),
+            PaymentProcessorInstruction::UpdatePackage {
+                data: package_data("max", 200),
+            }
+            .dispatch(&program_id, &accounts, &sysvars)
+        );
+        assert_eq!(
+            Err(ProgramError::AccountDataTooSmall),
+            PaymentProcessorInstruction::UpdatePackage {
+                data: package_data("pro", 1000),
+            }
+            .dispatch(&program_id, &accounts, &sysvars)
+        );
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::UpdatePackage {
+                data: package_data("pro", 200),
+            }
+            .dispatch(&program_id, &accounts, &sysvars)
+        );
+        // new subscribers get the updated price, existing ones keep theirs
+        let merchant = MerchantAccount::unpack(&accounts[2].data.borrow()).unwrap();
+        let (package, new_package) =
+            get_package_to_subscribe(&program_id, &accounts, &accounts[2], &merchant, "pro")
+                .unwrap();
+        assert_eq!(package_key.to_bytes(), package);
+        assert_eq!(200, new_package.price);
+        let existing_package =
+            get_package_of_subscription(&program_id, &accounts, &subscription, &merchant).unwrap();
+        assert_eq!(100, existing_package.price);
+
+        // retired packages take no new subscribers
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::RetirePackage.dispatch(&program_id, &accounts, &sysvars)
+        );
+        assert_eq!(
+            Err(PaymentProcessorError::InvalidSubscriptionPackage.into()),
+            get_package_to_subscribe(&program_id, &accounts, &accounts[2], &merchant, "pro")
+        );
+        assert!(
+            get_package_of_subscription(&program_id, &accounts, &subscription, &merchant).is_ok()
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            PaymentProcessorInstruction::RetirePackage.dispatch(&program_id, &accounts, &sysvars)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_subscription() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let owner = Pubkey::new_unique();
+        let subscription_key = Pubkey::new_unique();
+        let period_end = 1625097600;
+        let subscription = SubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: owner.to_bytes(),
+            merchant: Pubkey::new_unique().to_bytes(),
+            name: String::from("basic"),
+            joined: period_end - 720,
+            period_start: period_end - 720,
+            period_end,
+            remaining_seconds: 0,
+            usage_units: 0,
+            seats: 1,
+            package: [0; 32],
+            price: 100,
+            period_order: [0; 32],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut subscription_data =
+            vec![0; get_subscription_account_size(&subscription.name, &subscription.data)];
+        subscription.pack(&mut subscription_data);
+        let mut subscription_lamports = 1000;
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let accounts = vec![
+            AccountInfo::new(
+                &subscription_key,
+                false,
+                true,
+                &mut subscription_lamports,
+                &mut subscription_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &owner,
+                false,
+                true,
+                &mut owner_lamports,
+                &mut owner_data,
+                &system_program_id,
+                false,
+                0,
+            ),
+        ];
+        let close_at = |unix_timestamp| {
+            PaymentProcessorInstruction::CloseSubscription.dispatch(
+                &program_id,
+                &accounts,
+                &FixedSysvars {
+                    unix_timestamp,
+                    slot: 0,
+                },
+            )
+        };
+        let retained_until = period_end + SUBSCRIPTION_RETENTION_PERIOD;
+
+        // only subscriptions that have ended can be closed
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            close_at(retained_until)
+        );
+        let mut subscription = SubscriptionAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        subscription.status = SubscriptionStatus::Cancelled as u8;
+        subscription.pack(&mut accounts[0].data.borrow_mut());
+        // and only once the retention period is over
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            close_at(retained_until - 1)
+        );
+        assert_eq!(Ok(()), close_at(retained_until));
+        assert_eq!(0, accounts[0].lamports());
+        assert_eq!(1000, accounts[1].lamports());
+        assert!(accounts[0].data.borrow().iter().all(|byte| *byte == 0));
+        assert!(close_at(retained_until).is_err());
+    }
+
+    /// Send a MigrateAccount instruction for an account straight to the processor
+    fn migrate_account<'a>(
+        program_id: &Pubkey,
+        signer_info: &AccountInfo<'a>,
+        account_info: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        PaymentProcessorInstruction::MigrateAccount { seed: None }.dispatch(
+            program_id,
+            &[signer_info.clone(), account_info.clone()],
+            &FixedSysvars {
+                unix_timestamp: 0,
+                slot: 0,
+            },
+        )
+    }
+
+    /// Send a MigrateAccount instruction for an account that is moved straight to the processor
+    fn move_account<'a>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo<'a>],
+        seed: Option<&str>,
+    ) -> ProgramResult {
+        PaymentProcessorInstruction::MigrateAccount {
+            seed: seed.map(String::from),
+        }
+        .dispatch(
+            program_id,
+            accounts,
+            &FixedSysvars {
+                unix_timestamp: 0,
+                slot: 0,
+            },
+        )
+    }
+
+    /// Send a CloseMerchant instruction straight to the processor
+    fn close_merchant<'a>(
+        program_id: &Pubkey,
+        signer_info: &AccountInfo<'a>,
+        merchant_info: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        PaymentProcessorInstruction::CloseMerchant.dispatch(
+            program_id,
+            &[signer_info.clone(), merchant_info.clone()],
+            &FixedSysvars {
+                unix_timestamp: 0,
+                slot: 0,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_migrate_account() {
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let signer = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let order_key = Pubkey::new_unique();
+        let (migration_key, _) = get_migration_address(&program_id, &merchant_key);
+        let mut merchant = MerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: signer.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: SPONSOR_FEE as u64,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: format!("{{}}{}", " ".repeat(MerchantAccount::ADDED_LEN)),
+            version: 0,
+        };
+        // accounts in the legacy layout end before the fields added after it
+        let mut merchant_data =
+            vec![0; get_merchant_account_size(&merchant.data) - MerchantAccount::ADDED_LEN];
+        merchant.pack(&mut merchant_data);
+        assert_eq!(merchant, MerchantAccount::unpack(&merchant_data).unwrap());
+        let order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097600,
+            merchant: merchant_key.to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 100,
+            paid_amount: 100,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: 0,
+        };
+        let mut order_data =
+            vec![
+                0;
+                get_order_account_size(&order.order_id, &order.secret, &order.data)
+                    - OrderAccount::ADDED_LEN
+            ];
+        order.pack(&mut order_data);
+        let mut signer_lamports = 0;
+        let mut signer_data = vec![];
+        let mut merchant_lamports = 0;
+        let mut order_lamports = 0;
+        let mut migration_lamports = 0;
+        let mut migration_data = vec![];
+        let mut system_program_lamports = 0;
+        let mut system_program_data = vec![];
+        let signer_info = AccountInfo::new(
+            &signer,
+            true,
+            false,
+            &mut signer_lamports,
+            &mut signer_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let migration_info = AccountInfo::new(
+            &migration_key,
+            false,
+            true,
+            &mut migration_lamports,
+            &mut migration_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let system_program_info = AccountInfo::new(
+            &system_program_id,
+            false,
+            false,
+            &mut system_program_lamports,
+            &mut system_program_data,
+            &system_program_id,
+            true,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        let order_info = AccountInfo::new(
+            &order_key,
+            false,
+            true,
+            &mut order_lamports,
+            &mut order_data,
+            &program_id,
+            false,
+            0,
+        );
+
+        // the order has no padding to make room for the latest layout
+        assert_eq!(
+            Err(ProgramError::AccountDataTooSmall),
+            migrate_account(&program_id, &signer_info, &order_info)
+        );
+        assert_eq!(
+            order,
+            OrderAccount::unpack(&order_info.data.borrow()).unwrap()
+        );
+
+        // the merchant in the legacy layout has not counted its open order
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            close_merchant(&program_id, &signer_info, &merchant_info)
+        );
+
+        // the merchant gives up its padding for the latest layout, in the same
+        // size, counting its open order once
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            move_account(
+                &program_id,
+                &[
+                    signer_info.clone(),
+                    merchant_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                    order_info.clone(),
+                    order_info.clone(),
+                ],
+                None,
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            move_account(
+                &program_id,
+                &[
+                    signer_info.clone(),
+                    merchant_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                    order_info.clone(),
+                ],
+                None,
+            )
+        );
+        merchant.references = 1;
+        merchant.data = String::from("{}");
+        assert_eq!(
+            get_merchant_account_size(&merchant.data),
+            merchant_info.data_len()
+        );
+        merchant.version = ACCOUNT_VERSION;
+        assert_eq!(
+            merchant,
+            MerchantAccount::unpack(&merchant_info.data.borrow()).unwrap()
+        );
+        // the migrated merchant starts with the full discriminator, while the
+        // order in the legacy layout keeps its one-byte discriminator
+        assert!(has_account_discriminator(&merchant_info.data.borrow()));
+        assert!(!has_account_discriminator(&order_info.data.borrow()));
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            migrate_account(&program_id, &signer_info, &merchant_info)
+        );
+
+        // the open order keeps the merchant from being closed
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            close_merchant(&program_id, &signer_info, &merchant_info)
+        );
+        assert_eq!(
+            merchant,
+            MerchantAccount::unpack(&merchant_info.data.borrow()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_baseline_account() {
+        // a merchant account registered before any field was added to it, with
+        // no padding to make room for them
+        #[derive(BorshSerialize)]
+        struct BaselineMerchantAccount {
+            discriminator: u8,
+            owner: PublicKey,
+            sponsor: PublicKey,
+            fee: u64,
+            data: String,
+        }
+        let program_id = Pubkey::new_unique();
+        let system_program_id = solana_program::system_program::id();
+        let owner = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let merchant_key = Pubkey::create_with_seed(&owner, MERCHANT, &program_id).unwrap();
+        let (migration_key, _) = get_migration_address(&program_id, &merchant_key);
+        let mut merchant_data = BaselineMerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 5000,
+            data: String::from("{}"),
+        }
+        .try_to_vec()
+        .unwrap();
+        let mut merchant = MerchantAccount::unpack(&merchant_data).unwrap();
+        merchant.version = ACCOUNT_VERSION;
+        let migrated_data = merchant.to_account_data();
+        // the System program creates the migration account (see start_account_move)
+        let mut migration_data = vec![0; get_migration_account_size(&migrated_data)];
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut other_lamports = 0;
+        let mut other_data = vec![];
+        let mut merchant_lamports = 1000;
+        let mut migration_lamports = 2000;
+        let mut system_program_lamports = 0;
+        let mut system_program_data = vec![];
+        let owner_info = AccountInfo::new(
+            &owner,
+            true,
+            true,
+            &mut owner_lamports,
+            &mut owner_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            true,
+            true,
+            &mut other_lamports,
+            &mut other_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        let migration_info = AccountInfo::new(
+            &migration_key,
+            false,
+            true,
+            &mut migration_lamports,
+            &mut migration_data,
+            &program_id,
+            false,
+            0,
+        );
+        let system_program_info = AccountInfo::new(
+            &system_program_id,
+            false,
+            false,
+            &mut system_program_lamports,
+            &mut system_program_data,
+            &system_program_id,
+            true,
+            0,
+        );
+
+        // the merchant has no padding, so it has to be moved, by its owner
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            migrate_account(&program_id, &owner_info, &merchant_info)
+        );
+        assert_eq!(
+            Err(PaymentProcessorError::WrongMerchant.into()),
+            move_account(
+                &program_id,
+                &[
+                    other_info.clone(),
+                    merchant_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                ],
+                None,
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            move_account(
+                &program_id,
+                &[
+                    owner_info.clone(),
+                    merchant_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                ],
+                None,
+            )
+        );
+        // the merchant account is emptied, to be removed
+        assert_eq!(0, merchant_info.lamports());
+        assert_eq!(1000, owner_info.lamports());
+        assert!(merchant_info.data.borrow().iter().all(|byte| *byte == 0));
+        let migration = MigrationAccount::unpack(&migration_info.data.borrow()).unwrap();
+        assert_eq!(merchant_key.to_bytes(), migration.account);
+        assert_eq!(migrated_data, migration.data);
+
+        // the System program created the merchant account again, in its new size
+        let mut moved_lamports = 0;
+        let mut moved_data = vec![0; migrated_data.len()];
+        let moved_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut moved_lamports,
+            &mut moved_data,
+            &system_program_id,
+            false,
+            0,
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidSeeds),
+            move_account(
+                &program_id,
+                &[
+                    owner_info.clone(),
+                    moved_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                ],
+                Some("other"),
+            )
+        );
+        assert_eq!(
+            Ok(()),
+            move_account(
+                &program_id,
+                &[
+                    owner_info.clone(),
+                    moved_info.clone(),
+                    migration_info.clone(),
+                    system_program_info.clone(),
+                ],
+                None,
+            )
+        );
+        assert_eq!(
+            merchant,
+            MerchantAccount::unpack(&moved_info.data.borrow()).unwrap()
+        );
+        assert_eq!(3000, owner_info.lamports());
+        assert_eq!(0, migration_info.lamports());
+        assert_eq!(Discriminator::Closed as u8, migration_info.data.borrow()[0]);
+        assert!(move_account(
+            &program_id,
+            &[
+                owner_info.clone(),
+                moved_info.clone(),
+                migration_info.clone(),
+                system_program_info.clone(),
+            ],
+            None,
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unpack_baseline_accounts() {
+        // the accounts as laid out before any field was added to them
+        #[derive(BorshSerialize)]
+        struct BaselineMerchantAccount {
+            discriminator: u8,
+            owner: PublicKey,
+            sponsor: PublicKey,
+            fee: u64,
+            data: String,
+        }
+        #[derive(BorshSerialize)]
+        struct BaselineOrderAccount {
+            discriminator: u8,
+            status: u8,
+            created: UnixTimestamp,
+            modified: UnixTimestamp,
+            merchant: PublicKey,
+            mint: PublicKey,
+            token: PublicKey,
+            payer: PublicKey,
+            expected_amount: u64,
+            paid_amount: u64,
+            order_id: String,
+            secret: String,
+            data: String,
+        }
+        #[derive(BorshSerialize)]
+        struct BaselineSubscriptionAccount {
+            discriminator: u8,
+            status: u8,
+            owner: PublicKey,
+            merchant: PublicKey,
+            name: String,
+            joined: UnixTimestamp,
+            period_start: UnixTimestamp,
+            period_end: UnixTimestamp,
+            data: String,
+        }
+
+        let baseline_merchant = BaselineMerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: [1; 32],
+            sponsor: [2; 32],
+            fee: 5000,
+            data: String::from(r#"{"packages":[]}"#),
+        };
+        let merchant_data = baseline_merchant.try_to_vec().unwrap();
+        assert_eq!(
+            get_merchant_account_size(&baseline_merchant.data) - MerchantAccount::ADDED_LEN,
+            merchant_data.len()
+        );
+        let merchant = MerchantAccount::unpack(&merchant_data).unwrap();
+        assert_eq!(
+            (
+                baseline_merchant.discriminator,
+                baseline_merchant.owner,
+                baseline_merchant.sponsor,
+                baseline_merchant.fee,
+                &baseline_merchant.data
+            ),
+            (
+                merchant.discriminator,
+                merchant.owner,
+                merchant.sponsor,
+                merchant.fee,
+                &merchant.data
+            )
+        );
+        assert_eq!(0, merchant.version);
+        assert_eq!(SPONSOR_FEE as u64, merchant.sponsor_fee);
+        // the type and owner are read in place in the legacy layout too
+        assert_eq!(
+            Ok(baseline_merchant.discriminator),
+            check_account_type::<MerchantAccount>(&merchant_data)
+        );
+        assert_eq!(
+            Err(PaymentProcessorError::WrongAccountType.into()),
+            check_account_type::<OrderAccount>(&merchant_data)
+        );
+        assert_eq!(
+            Ok(baseline_merchant.owner),
+            MerchantAccount::OWNER.get(&merchant_data)
+        );
+        assert_eq!(
+            (0, 0, 0),
+            (
+                merchant.references,
+                merchant.payout_count,
+                merchant.sweep_threshold
+            )
+        );
+        assert!(merchant.catalog.is_empty() && merchant.packages.is_empty());
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            check_account_migrated(merchant.version, "merchant")
+        );
+        let mut packed = vec![0; merchant_data.len()];
+        merchant.pack(&mut packed);
+        assert_eq!(merchant_data, packed);
+        // accounts that do not end where the legacy layout does are not read in
+        // it, whether they are truncated or longer
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            MerchantAccount::unpack(&merchant_data[..merchant_data.len() - 1])
+        );
+        let mut extended_data = merchant_data.clone();
+        extended_data.extend_from_slice(&[0; 8]);
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            MerchantAccount::unpack(&extended_data)
+        );
+
+        let baseline_order = BaselineOrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1625097600,
+            modified: 1625097700,
+            merchant: [1; 32],
+            mint: [2; 32],
+            token: [3; 32],
+            payer: [4; 32],
+            expected_amount: 100,
+            paid_amount: 100,
+            order_id: String::from("1"),
+            secret: String::from("hunter2"),
+            data: String::from("{}"),
+        };
+        let order_data = baseline_order.try_to_vec().unwrap();
+        let order = OrderAccount::unpack(&order_data).unwrap();
+        assert_eq!(
+            (
+                baseline_order.status,
+                baseline_order.created,
+                baseline_order.modified,
+                baseline_order.merchant,
+                baseline_order.payer,
+                baseline_order.paid_amount,
+                &baseline_order.order_id,
+                &baseline_order.secret,
+                &baseline_order.data
+            ),
+            (
+                order.status,
+                order.created,
+                order.modified,
+                order.merchant,
+                order.payer,
+                order.paid_amount,
+                &order.order_id,
+                &order.secret,
+                &order.data
+            )
+        );
+        assert_eq!(
+            (0, 0, 0, 0, 0),
+            (
+                order.tip,
+                order.transfer_fee,
+                order.withdrawn_amount,
+                order.sequence,
+                order.version
+            )
+        );
+        assert!(order.items.is_empty());
+        // the fixed fields are where they were
+        assert_eq!(Ok(2), OrderAccount::STATUS.get(&order_data));
+        assert_eq!(Ok(100), OrderAccount::PAID_AMOUNT.get(&order_data));
+        assert_eq!(
+            Ok(baseline_order.payer),
+            OrderAccount::PAYER.get(&order_data)
+        );
+        let mut packed = vec![0; order_data.len()];
+        order.pack(&mut packed);
+        assert_eq!(order_data, packed);
+
+        let baseline_subscription = BaselineSubscriptionAccount {
+            discriminator: Discriminator::Subscription as u8,
+            status: SubscriptionStatus::Initialized as u8,
+            owner: [4; 32],
+            merchant: [1; 32],
+            name: String::from("basic"),
+            joined: 1625097600,
+            period_start: 1625097600,
+            period_end: 1627776000,
+            data: String::from("{}"),
+        };
+        let subscription_data = baseline_subscription.try_to_vec().unwrap();
+        let subscription = SubscriptionAccount::unpack(&subscription_data).unwrap();
+        assert_eq!(
+            (
+                baseline_subscription.owner,
+                baseline_subscription.merchant,
+                &baseline_subscription.name,
+                baseline_subscription.period_end,
+                &baseline_subscription.data
+            ),
+            (
+                subscription.owner,
+                subscription.merchant,
+                &subscription.name,
+                subscription.period_end,
+                &subscription.data
+            )
+        );
+        // subscriptions in the legacy layout have a single seat, of a package
+        // found in the merchant data
+        assert_eq!(1, subscription.seats);
+        assert_eq!([0; 32], subscription.package);
+        assert_eq!(
+            (0, 0, 0),
+            (
+                subscription.remaining_seconds,
+                subscription.usage_units,
+                subscription.version
+            )
+        );
+        let mut packed = vec![0; subscription_data.len()];
+        subscription.pack(&mut packed);
+        assert_eq!(subscription_data, packed);
+    }
+
+    #[tokio::test]
+    async fn test_account_validation() {
+        let program_id = Pubkey::new_unique();
+        let (pda, pda_nonce) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let other = Pubkey::new_unique();
+        let mut pda_lamports = 0;
+        let mut pda_data = vec![];
+        let pda_info = AccountInfo::new(
+            &pda,
+            false,
+            false,
+            &mut pda_lamports,
+            &mut pda_data,
+            &program_id,
+            false,
+            0,
+        );
+        let mut other_lamports = 0;
+        let mut other_data = vec![];
+        let other_info = AccountInfo::new(
+            &other,
+            true,
+            false,
+            &mut other_lamports,
+            &mut other_data,
+            &other,
+            false,
+            0,
+        );
+
+        assert_eq!(Ok(()), check_signer(&other_info));
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            check_signer(&pda_info)
+        );
+        assert_eq!(Ok(()), check_program_owned(&program_id, &pda_info, "pda"));
+        assert_eq!(
+            Err(ProgramError::IncorrectProgramId),
+            check_program_owned(&program_id, &other_info, "other")
+        );
+        assert_eq!(
+            Ok((pda, pda_nonce)),
+            check_pda(&program_id, &pda_info, &[PDA_SEED])
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidSeeds),
+            check_pda(&program_id, &other_info, &[PDA_SEED])
+        );
+        assert_eq!(Ok(()), check_sysvar(&other_info, &other));
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            check_sysvar(&pda_info, &solana_program::sysvar::rent::id())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_freeze_policy() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let mut merchant = MerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut merchant_lamports = 0;
+        let owner_info = AccountInfo::new(
+            &owner,
+            true,
+            false,
+            &mut owner_lamports,
+            &mut owner_data,
+            &owner,
+            false,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::SetFreezePolicy {
+                rejects_freezable_mints: true,
+            }
+            .dispatch(
+                &program_id,
+                &[owner_info.clone(), merchant_info.clone()],
+                &FixedSysvars {
+                    unix_timestamp: 0,
+                    slot: 0,
+                },
+            )
+        );
+        merchant = MerchantAccount::unpack(&merchant_info.data.borrow()).unwrap();
+        assert!(merchant.rejects_freezable_mints);
+
+        let mint_key = Pubkey::new_unique();
+        let freezable_mint_key = Pubkey::new_unique();
+        let mut mint_data = vec![0; Mint::LEN];
+        let mut freezable_mint_data = vec![0; Mint::LEN];
+        let mint = Mint {
+            mint_authority: COption::Some(owner),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        Mint::pack(mint, &mut mint_data).unwrap();
+        Mint::pack(
+            Mint {
+                freeze_authority: COption::Some(owner),
+                ..mint
+            },
+            &mut freezable_mint_data,
+        )
+        .unwrap();
+        let mut mint_lamports = 0;
+        let mut freezable_mint_lamports = 0;
+        let token_program_id = spl_token::id();
+        let mint_info = AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut mint_lamports,
+            &mut mint_data,
+            &token_program_id,
+            false,
+            0,
+        );
+        let freezable_mint_info = AccountInfo::new(
+            &freezable_mint_key,
+            false,
+            false,
+            &mut freezable_mint_lamports,
+            &mut freezable_mint_data,
+            &token_program_id,
+            false,
+            0,
+        );
+
+        assert_eq!(Ok(()), check_freezable_mints(&merchant, &[&mint_info]));
+        assert_eq!(
+            Err(PaymentProcessorError::FreezableMint.into()),
+            check_freezable_mints(&merchant, &[&mint_info, &freezable_mint_info])
+        );
+        // the mints that the merchant accepts are allowed
+        merchant.restricts_mints = true;
+        assert_eq!(
+            Ok(()),
+            check_freezable_mints(&merchant, &[&freezable_mint_info])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sweep_policy() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let mut merchant = MerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        // no bounty is set aside while sweeping is off
+        assert_eq!(0, get_sweep_bounty(&merchant, 500000));
+
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut merchant_lamports = 0;
+        let owner_info = AccountInfo::new(
+            &owner,
+            true,
+            false,
+            &mut owner_lamports,
+            &mut owner_data,
+            &owner,
+            false,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        // the sweep bounty account of the merchant was created earlier
+        let (sweep_bounty_key, _) = get_sweep_bounty_address(&program_id, &merchant_key);
+        let mut sweep_bounty_data = vec![0; SweepBountyAccount::MIN_LEN];
+        SweepBountyAccount {
+            discriminator: Discriminator::SweepBounty as u8,
+            merchant: merchant_key.to_bytes(),
+            held: 0,
+            returned: 0,
+            version: ACCOUNT_VERSION,
+        }
+        .pack(&mut sweep_bounty_data);
+        let mut sweep_bounty_lamports = 0;
+        let sweep_bounty_info = AccountInfo::new(
+            &sweep_bounty_key,
+            false,
+            true,
+            &mut sweep_bounty_lamports,
+            &mut sweep_bounty_data,
+            &program_id,
+            false,
+            0,
+        );
+        let system_program_id = solana_program::system_program::id();
+        let mut system_program_lamports = 0;
+        let mut system_program_data = vec![];
+        let system_program_info = AccountInfo::new(
+            &system_program_id,
+            false,
+            false,
+            &mut system_program_lamports,
+            &mut system_program_data,
+            &system_program_id,
+            true,
+            0,
+        );
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::SetSweepPolicy {
+                threshold: 3000,
+                delay: 150,
+            }
+            .dispatch(
+                &program_id,
+                &[
+                    owner_info.clone(),
+                    merchant_info.clone(),
+                    sweep_bounty_info.clone(),
+                    system_program_info.clone(),
+                ],
+                &FixedSysvars {
+                    unix_timestamp: 0,
+                    slot: 0,
+                },
+            )
+        );
+        merchant = MerchantAccount::unpack(&merchant_info.data.borrow()).unwrap();
+        assert_eq!(3000, merchant.sweep_threshold);
+        assert_eq!(150, merchant.sweep_delay);
+
+        // the bounty comes out of the program owner fee, and never exceeds it
+        assert_eq!(
+            SWEEP_BOUNTY_IN_LAMPORTS,
+            get_sweep_bounty(&merchant, 500000)
+        );
+        assert_eq!(1000, get_sweep_bounty(&merchant, 1000));
+    }
+
+    #[tokio::test]
+    async fn test_sweep_bounty_returned_when_order_closed() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let program_owner = Pubkey::new_unique();
+        let merchant_key = Pubkey::new_unique();
+        let order_key = Pubkey::new_unique();
+        let bounty = 5000;
+        let merchant = MerchantAccount {
+            discriminator: Discriminator::Merchant as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: u64::MAX,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        // an order that set aside a bounty, and was settled without being swept
+        let order = OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Withdrawn as u8,
+            created: 0,
+            modified: 0,
+            merchant: merchant_key.to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: 1000,
+            paid_amount: 1000,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 1000,
+            sequence: 1,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: bounty,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let config = ProgramConfigAccount {
+            discriminator: Discriminator::ProgramConfig as u8,
+            state_version: PROGRAM_VERSION,
+            owner: program_owner.to_bytes(),
+            authority: program_owner.to_bytes(),
+            default_fee: 0,
+            min_fee: 0,
+            sponsor_fee: 0,
+            migration_ends: 0,
+            name: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        };
+        let mut merchant_data = vec![0; get_merchant_account_size(&merchant.data)];
+        merchant.pack(&mut merchant_data);
+        let mut order_data =
+            vec![0; get_order_account_size(&order.order_id, &order.secret, &order.data)];
+        order.pack(&mut order_data);
+        let mut config_data = vec![0; config.to_account_data().len()];
+        config.pack(&mut config_data);
+        let (sweep_bounty_key, _) = get_sweep_bounty_address(&program_id, &merchant_key);
+        let mut sweep_bounty_data = vec![0; SweepBountyAccount::MIN_LEN];
+        SweepBountyAccount {
+            discriminator: Discriminator::SweepBounty as u8,
+            merchant: merchant_key.to_bytes(),
+            held: bounty,
+            returned: 0,
+            version: ACCOUNT_VERSION,
+        }
+        .pack(&mut sweep_bounty_data);
+        let (pda, _) = Pubkey::find_program_address(&[PDA_SEED], &program_id);
+        let (config_key, _) = get_program_config_address(&program_id);
+        let token_program_id = spl_token::id();
+
+        let mut owner_lamports = 0;
+        let mut owner_data = vec![];
+        let mut program_owner_lamports = 0;
+        let mut program_owner_data = vec![];
+        let mut merchant_lamports = 0;
+        let mut order_lamports = 1000000;
+        let mut config_lamports = 0;
+        let mut sweep_bounty_lamports = 1000000 + bounty;
+        let mut pda_lamports = 0;
+        let mut pda_data = vec![];
+        let mut token_program_lamports = 0;
+        let mut token_program_data = vec![];
+        let owner_info = AccountInfo::new(
+            &owner,
+            true,
+            true,
+            &mut owner_lamports,
+            &mut owner_data,
+            &owner,
+            false,
+            0,
+        );
+        let program_owner_info = AccountInfo::new(
+            &program_owner,
+            true,
+            true,
+            &mut program_owner_lamports,
+            &mut program_owner_data,
+            &program_owner,
+            false,
+            0,
+        );
+        let merchant_info = AccountInfo::new(
+            &merchant_key,
+            false,
+            true,
+            &mut merchant_lamports,
+            &mut merchant_data,
+            &program_id,
+            false,
+            0,
+        );
+        let order_info = AccountInfo::new(
+            &order_key,
+            false,
+            true,
+            &mut order_lamports,
+            &mut order_data,
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut config_lamports,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let sweep_bounty_info = AccountInfo::new(
+            &sweep_bounty_key,
+            false,
+            true,
+            &mut sweep_bounty_lamports,
+            &mut sweep_bounty_data,
+            &program_id,
+            false,
+            0,
+        );
+        let pda_info = AccountInfo::new(
+            &pda,
+            false,
+            false,
+            &mut pda_lamports,
+            &mut pda_data,
+            &program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program_id,
+            false,
+            false,
+            &mut token_program_lamports,
+            &mut token_program_data,
+            &token_program_id,
+            true,
+            0,
+        );
+        let sysvars = FixedSysvars {
+            unix_timestamp: 0,
+            slot: 0,
+        };
+
+        // the order cannot be closed without the sweep bounty account, so that
+        // the merchant never gets the bounty with the rent of the order account
+        let close_accounts = [
+            owner_info.clone(),
+            order_info.clone(),
+            merchant_info.clone(),
+            owner_info.clone(),
+            pda_info.clone(),
+            token_program_info.clone(),
+        ];
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            PaymentProcessorInstruction::CloseOrder.dispatch(
+                &program_id,
+                &close_accounts,
+                &sysvars
+            )
+        );
+        let close_accounts = [
+            owner_info.clone(),
+            order_info.clone(),
+            merchant_info.clone(),
+            owner_info.clone(),
+            pda_info.clone(),
+            token_program_info.clone(),
+            sweep_bounty_info.clone(),
+        ];
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::CloseOrder.dispatch(
+                &program_id,
+                &close_accounts,
+                &sysvars
+            )
+        );
+        // the merchant only got the rent of the order account back
+        assert_eq!(1000000, owner_info.lamports());
+        let sweep_bounty = SweepBountyAccount::unpack(&sweep_bounty_info.data.borrow()).unwrap();
+        assert_eq!((0, bounty), (sweep_bounty.held, sweep_bounty.returned));
+
+        // only the program owner can claim the bounty back
+        let claim_accounts = [
+            owner_info.clone(),
+            sweep_bounty_info.clone(),
+            config_info.clone(),
+        ];
+        assert_eq!(
+            Err(PaymentProcessorError::WrongProgramOwner.into()),
+            PaymentProcessorInstruction::ClaimSweepBounties.dispatch(
+                &program_id,
+                &claim_accounts,
+                &sysvars
+            )
+        );
+        let claim_accounts = [
+            program_owner_info.clone(),
+            sweep_bounty_info.clone(),
+            config_info.clone(),
+        ];
+        assert_eq!(
+            Ok(()),
+            PaymentProcessorInstruction::ClaimSweepBounties.dispatch(
+                &program_id,
+                &claim_accounts,
+                &sysvars
+            )
+        );
+        assert_eq!(bounty, program_owner_info.lamports());
+        assert_eq!(1000000, sweep_bounty_info.lamports());
+        let sweep_bounty = SweepBountyAccount::unpack(&sweep_bounty_info.data.borrow()).unwrap();
+        assert_eq!((0, 0), (sweep_bounty.held, sweep_bounty.returned));
+    }
 }
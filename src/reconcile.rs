@@ -0,0 +1,338 @@
+//! Reconciliation of a merchant's expected orders against on-chain orders
+//!
+//! Meant to be used off-chain (e.g. by an indexer or support tooling) to find
+//! orders that were not paid, were paid the wrong amount, were paid more than once
+//! or that the merchant does not know about.
+
+use crate::state::{OrderAccount, OrderStatus};
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ReconciliationError {
+    /// Invalid CSV line
+    #[error("Error: Invalid CSV line {0}")]
+    InvalidLine(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// An order that the merchant expects to be paid
+pub struct ExpectedOrder {
+    pub order_id: String,
+    pub amount: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+/// A difference between the expected orders and the on-chain orders
+pub enum Discrepancy {
+    /// no payment was found for an expected order
+    Unpaid {
+        order_id: String,
+        expected_amount: u64,
+    },
+    /// less than the expected amount was paid
+    Underpaid {
+        order_id: String,
+        order_account: String,
+        expected_amount: u64,
+        paid_amount: u64,
+    },
+    /// more than the expected amount was paid
+    Overpaid {
+        order_id: String,
+        order_account: String,
+        expected_amount: u64,
+        paid_amount: u64,
+    },
+    /// more than one payment was made for the same order id
+    DuplicateOrderId {
+        order_id: String,
+        order_accounts: Vec<String>,
+    },
+    /// a payment was made for an order id that the merchant does not expect
+    Unknown {
+        order_id: String,
+        order_account: String,
+        paid_amount: u64,
+    },
+}
+
+/// Parse the expected orders from CSV
+///
+/// Each line looks like so: order_id,amount
+/// A header line and empty lines are ignored
+pub fn parse_expected_orders(csv: &str) -> Result<Vec<ExpectedOrder>, ReconciliationError> {
+    let mut expected_orders = vec![];
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut columns = line.splitn(2, ',');
+        let order_id = columns.next().unwrap_or("").trim();
+        let amount = columns.next().unwrap_or("").trim();
+        match amount.parse::<u64>() {
+            Ok(amount) if !order_id.is_empty() => expected_orders.push(ExpectedOrder {
+                order_id: String::from(order_id),
+                amount,
+            }),
+            _ => {
+                if index == 0 {
+                    // the header
+                    continue;
+                }
+                return Err(ReconciliationError::InvalidLine(index + 1));
+            }
+        }
+    }
+    Ok(expected_orders)
+}
+
+/// Is the order account holding (or did it hold) a payment?
+///
+/// Orders that are being paid in installments count as soon as the first one is paid
+fn is_paid(order_account: &OrderAccount) -> bool {
+    (order_account.status == OrderStatus::Pending as u8 && order_account.paid_amount > 0)
+        || order_account.status == OrderStatus::Paid as u8
+        || order_account.status == OrderStatus::Withdrawn as u8
+        || order_account.status == OrderStatus::Releasable as u8
+}
+
+/// Reconcile the expected orders against the on-chain orders of a merchant
+///
+/// The orders are expected as (address, order account) pairs e.g. as returned by
+/// getProgramAccounts.  Orders that belong to other merchants are ignored.
+pub fn reconcile(
+    merchant: &Pubkey,
+    expected_orders: &[ExpectedOrder],
+    orders: &[(Pubkey, OrderAccount)],
+) -> Vec<Discrepancy> {
+    let mut discrepancies = vec![];
+
+    // group the paid orders by order id
+    let mut paid_orders: BTreeMap<&str, Vec<(&Pubkey, &OrderAccount)>> = BTreeMap::new();
+    for (address, order_account) in orders {
+        if order_account.merchant != merchant.to_bytes() || !is_paid(order_account) {
+            continue;
+        }
+        paid_orders
+            .entry(order_account.order_id.as_str())
+            .or_insert_with(Vec::new)
+            .push((address, order_account));
+    }
+
+    for (order_id, payments) in paid_orders.iter() {
+        if payments.len() > 1 {
+            discrepancies.push(Discrepancy::DuplicateOrderId {
+                order_id: String::from(*order_id),
+                order_accounts: payments
+                    .iter()
+                    .map(|(address, _order_account)| address.to_string())
+                    .collect(),
+            });
+        }
+    }
+
+    for expected_order in expected_orders {
+        let payments = match paid_orders.get(expected_order.order_id.as_str()) {
+            None => {
+                discrepancies.push(Discrepancy::Unpaid {
+                    order_id: expected_order.order_id.clone(),
+                    expected_amount: expected_order.amount,
+                });
+                continue;
+            }
+            Some(value) => value,
+        };
+        for (address, order_account) in payments {
+            if order_account.paid_amount < expected_order.amount {
+                discrepancies.push(Discrepancy::Underpaid {
+                    order_id: expected_order.order_id.clone(),
+                    order_account: address.to_string(),
+                    expected_amount: expected_order.amount,
+                    paid_amount: order_account.paid_amount,
+                });
+            } else if order_account.paid_amount > expected_order.amount {
+                discrepancies.push(Discrepancy::Overpaid {
+                    order_id: expected_order.order_id.clone(),
+                    order_account: address.to_string(),
+                    expected_amount: expected_order.amount,
+                    paid_amount: order_account.paid_amount,
+                });
+            }
+        }
+    }
+
+    for (order_id, payments) in paid_orders.iter() {
+        if expected_orders
+            .iter()
+            .any(|expected_order| expected_order.order_id == *order_id)
+        {
+            continue;
+        }
+        for (address, order_account) in payments {
+            discrepancies.push(Discrepancy::Unknown {
+                order_id: String::from(*order_id),
+                order_account: address.to_string(),
+                paid_amount: order_account.paid_amount,
+            });
+        }
+    }
+
+    discrepancies
+}
+
+/// Reconcile and return the discrepancies as a JSON string
+pub fn reconcile_to_json(
+    merchant: &Pubkey,
+    expected_orders: &[ExpectedOrder],
+    orders: &[(Pubkey, OrderAccount)],
+) -> String {
+    serde_json::to_string(&reconcile(merchant, expected_orders, orders)).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*, crate::engine::constants::ACCOUNT_VERSION, crate::state::Discriminator,
+        solana_program_test::*,
+    };
+
+    fn order(
+        merchant: &Pubkey,
+        order_id: &str,
+        paid_amount: u64,
+        status: OrderStatus,
+    ) -> OrderAccount {
+        OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: status as u8,
+            created: 0,
+            modified: 0,
+            merchant: merchant.to_bytes(),
+            mint: Pubkey::new_unique().to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: paid_amount,
+            paid_amount,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from(order_id),
+            secret: String::from(""),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_expected_orders() {
+        assert_eq!(
+            Ok(vec![
+                ExpectedOrder {
+                    order_id: String::from("A-1"),
+                    amount: 100
+                },
+                ExpectedOrder {
+                    order_id: String::from("A-2"),
+                    amount: 2500
+                },
+            ]),
+            parse_expected_orders("order_id,amount\nA-1,100\n\nA-2, 2500\n")
+        );
+        assert_eq!(
+            Err(ReconciliationError::InvalidLine(3)),
+            parse_expected_orders("A-1,100\nA-2,2500\nA-3,lots\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let merchant = Pubkey::new_unique();
+        let expected_orders =
+            parse_expected_orders("paid,100\nunpaid,200\nunder,300\nover,400\ndouble,500").unwrap();
+        let orders = vec![
+            (Pubkey::new_unique(), order(&merchant, "paid", 100, OrderStatus::Withdrawn)),
+            (Pubkey::new_unique(), order(&merchant, "under", 299, OrderStatus::Paid)),
+            (Pubkey::new_unique(), order(&merchant, "over", 401, OrderStatus::Paid)),
+            (Pubkey::new_unique(), order(&merchant, "double", 500, OrderStatus::Paid)),
+            (Pubkey::new_unique(), order(&merchant, "double", 500, OrderStatus::Paid)),
+            (Pubkey::new_unique(), order(&merchant, "stranger", 5, OrderStatus::Paid)),
+            // refunded orders and other merchants' orders are ignored
+            (Pubkey::new_unique(), order(&merchant, "unpaid", 200, OrderStatus::Expired)),
+            (
+                Pubkey::new_unique(),
+                order(&Pubkey::new_unique(), "paid", 100, OrderStatus::Paid),
+            ),
+        ];
+        let discrepancies = reconcile(&merchant, &expected_orders, &orders);
+        assert_eq!(
+            vec![
+                Discrepancy::DuplicateOrderId {
+                    order_id: String::from("double"),
+                    order_accounts: vec![orders[3].0.to_string(), orders[4].0.to_string()],
+                },
+                Discrepancy::Unpaid {
+                    order_id: String::from("unpaid"),
+                    expected_amount: 200,
+                },
+                Discrepancy::Underpaid {
+                    order_id: String::from("under"),
+                    order_account: orders[1].0.to_string(),
+                    expected_amount: 300,
+                    paid_amount: 299,
+                },
+                Discrepancy::Overpaid {
+                    order_id: String::from("over"),
+                    order_account: orders[2].0.to_string(),
+                    expected_amount: 400,
+                    paid_amount: 401,
+                },
+                Discrepancy::Unknown {
+                    order_id: String::from("stranger"),
+                    order_account: orders[5].0.to_string(),
+                    paid_amount: 5,
+                },
+            ],
+            discrepancies
+        );
+        let json_value: serde_json::Value =
+            serde_json::from_str(&reconcile_to_json(&merchant, &expected_orders, &orders))
+                .unwrap();
+        assert_eq!("duplicate_order_id", json_value[0]["issue"]);
+        assert_eq!("unpaid", json_value[1]["issue"]);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_installments() {
+        let merchant = Pubkey::new_unique();
+        let expected_orders = parse_expected_orders("partial,300\nnothing_yet,300").unwrap();
+        let orders = vec![
+            (Pubkey::new_unique(), order(&merchant, "partial", 100, OrderStatus::Pending)),
+            (Pubkey::new_unique(), order(&merchant, "nothing_yet", 0, OrderStatus::Pending)),
+        ];
+        assert_eq!(
+            vec![
+                Discrepancy::Underpaid {
+                    order_id: String::from("partial"),
+                    order_account: orders[0].0.to_string(),
+                    expected_amount: 300,
+                    paid_amount: 100,
+                },
+                Discrepancy::Unpaid {
+                    order_id: String::from("nothing_yet"),
+                    expected_amount: 300,
+                },
+            ],
+            reconcile(&merchant, &expected_orders, &orders)
+        );
+    }
+}
@@ -0,0 +1,562 @@
+//! Hands-off settlement of a merchant's paid orders
+//!
+//! Meant to be used off-chain by merchants (or the services that run checkouts
+//! for them) to withdraw paid orders without doing it by hand.  The settlement
+//! policy decides which orders are withdrawn and when: `plan_settlement` turns
+//! the merchant's orders into withdraw instructions and `run_settlement` submits
+//! them.  With the worker feature, `run_settlement_worker` does this on a tokio
+//! timer until it is told to stop, and reports every run as a `tracing` span.
+
+use crate::{
+    engine::associated_token::get_associated_token_address,
+    engine::common::{
+        get_order_balance, get_order_escrow_timeout, get_order_received_amount, get_order_tokens,
+        is_order_expired,
+    },
+    engine::json::OrderSubscription,
+    instruction::{use_transfer_checked, withdraw_to_wallet, WithdrawToWalletArgs},
+    state::{Discriminator, MerchantAccount, OrderAccount, OrderStatus},
+};
+use solana_program::{clock::UnixTimestamp, instruction::Instruction, pubkey::Pubkey};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+/// When and how the orders of a merchant are settled
+pub struct SettlementPolicy {
+    /// the orders paid in a mint are only withdrawn once they hold at least this
+    /// much (in total) so that small payments are settled in batches
+    pub min_amount: u64,
+    /// the number of seconds that the worker waits between two runs
+    pub interval: u64,
+    /// close the order accounts when withdrawing, to get their rent back
+    pub close_order_accounts: bool,
+    /// the maximum number of withdrawals submitted in a run
+    pub max_withdrawals: usize,
+}
+
+impl Default for SettlementPolicy {
+    fn default() -> Self {
+        SettlementPolicy {
+            min_amount: 0,
+            interval: 3600,
+            close_order_accounts: true,
+            max_withdrawals: 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A withdrawal planned for an order
+pub struct Settlement {
+    pub order: Pubkey,
+    pub mint: Pubkey,
+    /// the amount withdrawn (including the tip) in the order mint
+    pub amount: u64,
+    pub instruction: Instruction,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// The outcome of a settlement run
+pub struct SettlementReport {
+    pub settled: Vec<Pubkey>,
+    /// the orders whose withdrawal failed, with the error
+    pub failed: Vec<(Pubkey, String)>,
+}
+
+/// What the settlement needs from the cluster
+///
+/// The client signs the submitted transactions with the key of the signer
+/// given to `run_settlement`, which pays for them.
+pub trait SettlementClient {
+    /// the merchant account
+    fn get_merchant(&self, merchant: &Pubkey) -> Result<MerchantAccount, String>;
+    /// the order accounts of the merchant e.g. using getProgramAccounts
+    fn get_orders(&self, merchant: &Pubkey) -> Result<Vec<(Pubkey, OrderAccount)>, String>;
+    /// the current cluster time
+    fn unix_timestamp(&self) -> Result<UnixTimestamp, String>;
+    /// sign and send a transaction made of the instruction, and wait for it
+    fn submit(&self, instruction: &Instruction) -> Result<(), String>;
+}
+
+/// Hooks to keep track of the settlement e.g. to export metrics
+///
+/// Every hook does nothing by default.
+pub trait SettlementMetrics {
+    fn run_started(&self, _merchant: &Pubkey) {}
+    fn run_failed(&self, _merchant: &Pubkey, _error: &str) {}
+    fn settled(&self, _settlement: &Settlement) {}
+    fn settlement_failed(&self, _settlement: &Settlement, _error: &str) {}
+    /// how long the client took to submit a withdrawal, whether it failed or not
+    fn submitted(&self, _settlement: &Settlement, _duration: Duration) {}
+}
+
+/// Metrics hooks that do nothing
+pub struct NoMetrics;
+
+impl SettlementMetrics for NoMetrics {}
+
+/// Check whether the merchant can withdraw an order now
+fn is_withdrawable(order_account: &OrderAccount, timestamp: UnixTimestamp) -> bool {
+    if order_account.status == OrderStatus::Releasable as u8 {
+        return true;
+    }
+    if order_account.status != OrderStatus::Paid as u8 {
+        return false;
+    }
+    match get_order_received_amount(order_account) {
+        Ok(received) if received >= order_account.expected_amount => {}
+        _ => return false,
+    }
+    // expired orders can only be reclaimed by the payer
+    if is_order_expired(order_account, timestamp) {
+        return false;
+    }
+    // escrow orders are held until the buyer confirms delivery or the timeout elapses
+    if let Some(escrow_timeout) = get_order_escrow_timeout(order_account) {
+        match order_account.created.checked_add(escrow_timeout) {
+            Some(released_at) if timestamp >= released_at => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Get the subscription (if any) that an order was made for
+fn get_order_subscription(order_account: &OrderAccount) -> Option<Pubkey> {
+    let order_json_data: OrderSubscription = serde_json::from_str(&order_account.data).ok()?;
+    Pubkey::from_str(&order_json_data.subscription).ok()
+}
+
+/// Plan the withdrawals of the orders of a merchant
+///
+/// The orders are expected as (address, order account) pairs e.g. as returned
+/// by getProgramAccounts.  The merchant is paid out to the (existing) associated
+/// token accounts of the merchant owner, who also gets back the rent of the
+/// closed accounts.  The oldest orders are settled first.
+pub fn plan_settlement(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    merchant: &Pubkey,
+    merchant_account: &MerchantAccount,
+    orders: &[(Pubkey, OrderAccount)],
+    policy: &SettlementPolicy,
+    timestamp: UnixTimestamp,
+) -> Vec<Settlement> {
+    let merchant_owner = Pubkey::new_from_array(merchant_account.owner);
+    // orders whose balance is out of range cannot be withdrawn by the program either
+    let mut orders: Vec<(&Pubkey, &OrderAccount, u64)> = orders
+        .iter()
+        .filter(|(_address, order_account)| {
+            order_account.merchant == merchant.to_bytes()
+                && is_withdrawable(order_account, timestamp)
+        })
+        .filter_map(|(address, order_account)| {
+            Some((
+                address,
+                order_account,
+                get_order_balance(order_account).ok()?,
+            ))
+        })
+        .collect();
+    // orders created in the same second keep the order sequence of the merchant
+    orders.sort_by_key(|(_address, order_account, _balance)| {
+        (order_account.created, order_account.sequence)
+    });
+
+    // small payments wait until there is enough to withdraw in their mint
+    let mut mint_amounts: BTreeMap<[u8; 32], u64> = BTreeMap::new();
+    for (_address, order_account, balance) in orders.iter() {
+        let mint_amount = mint_amounts.entry(order_account.mint).or_insert(0);
+        *mint_amount = mint_amount.saturating_add(*balance);
+    }
+
+    orders
+        .into_iter()
+        .filter(|(_address, order_account, _balance)| {
+            mint_amounts[&order_account.mint] >= policy.min_amount
+        })
+        .take(policy.max_withdrawals)
+        .map(|(address, order_account, balance)| {
+            let mint = Pubkey::new_from_array(order_account.mint);
+            let subscription = match merchant_account.discriminator {
+                value if value == Discriminator::MerchantSubscriptionWithTrial as u8 => {
+                    get_order_subscription(order_account)
+                }
+                _ => None,
+            };
+            // the amounts paid in other mints are swept to the merchant as well
+            let mut mints = vec![mint];
+            let other_tokens = get_order_tokens(order_account)
+                .iter()
+                .filter_map(|order_token| {
+                    let other_mint = Pubkey::from_str(&order_token.mint).ok()?;
+                    let order_token = Pubkey::from_str(&order_token.token).ok()?;
+                    mints.push(other_mint);
+                    Some((
+                        order_token,
+                        get_associated_token_address(
+                            &merchant_owner,
+                            &spl_token::id(),
+                            &other_mint,
+                        ),
+                    ))
+                })
+                .collect();
+            let instruction = withdraw_to_wallet(
+                *program_id,
+                WithdrawToWalletArgs {
+                    signer: *signer,
+                    order: *address,
+                    merchant: *merchant,
+                    merchant_owner,
+                    mint,
+                    account_to_receive_sol_refund: merchant_owner,
+                    subscription,
+                    close_order_account: policy.close_order_accounts,
+                    create_merchant_token: false,
+                    other_tokens,
+                },
+            );
+            Settlement {
+                order: *address,
+                mint,
+                amount: balance,
+                // mints with a transfer fee are only moved with TransferChecked
+                instruction: use_transfer_checked(instruction, mints),
+            }
+        })
+        .collect()
+}
+
+/// Settle the orders of a merchant once
+///
+/// A failed withdrawal does not stop the others from being submitted.
+pub fn run_settlement(
+    client: &dyn SettlementClient,
+    metrics: &dyn SettlementMetrics,
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    merchant: &Pubkey,
+    policy: &SettlementPolicy,
+) -> Result<SettlementReport, String> {
+    metrics.run_started(merchant);
+    let merchant_account = client.get_merchant(merchant)?;
+    let orders = client.get_orders(merchant)?;
+    let timestamp = client.unix_timestamp()?;
+    let mut report = SettlementReport::default();
+    for settlement in plan_settlement(
+        program_id,
+        signer,
+        merchant,
+        &merchant_account,
+        &orders,
+        policy,
+        timestamp,
+    ) {
+        let started = Instant::now();
+        let result = client.submit(&settlement.instruction);
+        metrics.submitted(&settlement, started.elapsed());
+        match result {
+            Ok(()) => {
+                metrics.settled(&settlement);
+                report.settled.push(settlement.order);
+            }
+            Err(error) => {
+                metrics.settlement_failed(&settlement, &error);
+                report.failed.push((settlement.order, error));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Settle the orders of a merchant every policy interval until shutdown is set
+///
+/// The client is called from the worker task, so a client that blocks should be
+/// used from a multi-threaded runtime.
+#[cfg(feature = "worker")]
+pub async fn run_settlement_worker(
+    client: &dyn SettlementClient,
+    metrics: &dyn SettlementMetrics,
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    merchant: &Pubkey,
+    policy: &SettlementPolicy,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    while !*shutdown.borrow() {
+        let span = tracing::info_span!("settlement", merchant = %merchant);
+        span.in_scope(|| {
+            match run_settlement(client, metrics, program_id, signer, merchant, policy) {
+                Ok(report) => {
+                    for (order, error) in report.failed.iter() {
+                        tracing::warn!(order = %order, error = %error, "withdrawal failed");
+                    }
+                    tracing::info!(
+                        settled = report.settled.len(),
+                        failed = report.failed.len(),
+                        "settlement run finished"
+                    );
+                }
+                Err(error) => {
+                    tracing::error!(error = %error, "settlement run failed");
+                    metrics.run_failed(merchant, &error);
+                }
+            }
+        });
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(policy.interval)) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::engine::constants::{ACCOUNT_VERSION, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS},
+        solana_program_test::*,
+        std::cell::RefCell,
+    };
+
+    fn merchant_account(owner: &Pubkey) -> MerchantAccount {
+        MerchantAccount {
+            discriminator: Discriminator::MerchantSubscription as u8,
+            owner: owner.to_bytes(),
+            sponsor: Pubkey::new_unique().to_bytes(),
+            fee: 0,
+            tolerance: 0,
+            references: 0,
+            is_paused: false,
+            restricts_mints: false,
+            instant_settlement: false,
+            blocked_buyers: 0,
+            lifetime_volume: 0,
+            order_sequence: 0,
+            rejects_freezable_mints: false,
+            sponsor_fee: 0,
+            payout_count: 0,
+            payout_wallets: [[0; 32]; MAX_PAYOUT_DESTINATIONS],
+            payout_shares: [0; MAX_PAYOUT_DESTINATIONS],
+            operator_count: 0,
+            operators: [[0; 32]; MAX_OPERATORS],
+            sweep_threshold: 0,
+            sweep_delay: 0,
+            catalog: vec![],
+            packages: vec![],
+            metadata: vec![],
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    fn order(
+        merchant: &Pubkey,
+        mint: &Pubkey,
+        created: i64,
+        amount: u64,
+        data: &str,
+    ) -> OrderAccount {
+        OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created,
+            modified: created,
+            merchant: merchant.to_bytes(),
+            mint: mint.to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: amount,
+            paid_amount: amount,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("1"),
+            secret: String::from(""),
+            data: String::from(data),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_settlement() {
+        let program_id = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let dust_mint = Pubkey::new_unique();
+        let mut withdrawn = order(&merchant, &usdc, 1, 500, "{}");
+        withdrawn.status = OrderStatus::Withdrawn as u8;
+        let orders = vec![
+            (Pubkey::new_unique(), order(&merchant, &usdc, 30, 600, "{}")),
+            (Pubkey::new_unique(), order(&merchant, &usdc, 20, 500, "{}")),
+            // not enough paid in this mint yet
+            (
+                Pubkey::new_unique(),
+                order(&merchant, &dust_mint, 10, 5, "{}"),
+            ),
+            // held in escrow
+            (
+                Pubkey::new_unique(),
+                order(&merchant, &usdc, 90, 100, r#"{"escrow": 3600}"#),
+            ),
+            // expired
+            (
+                Pubkey::new_unique(),
+                order(&merchant, &usdc, 1, 100, r#"{"expires_at": 50}"#),
+            ),
+            (Pubkey::new_unique(), withdrawn),
+            (
+                Pubkey::new_unique(),
+                order(&Pubkey::new_unique(), &usdc, 1, 100, "{}"),
+            ),
+        ];
+        let policy = SettlementPolicy {
+            min_amount: 1000,
+            ..SettlementPolicy::default()
+        };
+        let settlements = plan_settlement(
+            &program_id,
+            &signer,
+            &merchant,
+            &merchant_account(&Pubkey::new_unique()),
+            &orders,
+            &policy,
+            100,
+        );
+        assert_eq!(2, settlements.len());
+        // the oldest order comes first
+        assert_eq!(orders[1].0, settlements[0].order);
+        assert_eq!(500, settlements[0].amount);
+        assert_eq!(orders[0].0, settlements[1].order);
+        assert_eq!(usdc, settlements[1].mint);
+        assert_eq!(program_id, settlements[0].instruction.program_id);
+
+        let policy = SettlementPolicy {
+            max_withdrawals: 1,
+            ..SettlementPolicy::default()
+        };
+        let settlements = plan_settlement(
+            &program_id,
+            &signer,
+            &merchant,
+            &merchant_account(&Pubkey::new_unique()),
+            &orders,
+            &policy,
+            100,
+        );
+        assert_eq!(1, settlements.len());
+        assert_eq!(orders[2].0, settlements[0].order);
+    }
+
+    struct TestClient {
+        merchant_account: MerchantAccount,
+        orders: Vec<(Pubkey, OrderAccount)>,
+        submitted: RefCell<Vec<Instruction>>,
+    }
+
+    impl SettlementClient for TestClient {
+        fn get_merchant(&self, _merchant: &Pubkey) -> Result<MerchantAccount, String> {
+            Ok(MerchantAccount {
+                catalog: self.merchant_account.catalog.clone(),
+                packages: self.merchant_account.packages.clone(),
+                metadata: self.merchant_account.metadata.clone(),
+                data: self.merchant_account.data.clone(),
+                ..self.merchant_account
+            })
+        }
+
+        fn get_orders(&self, _merchant: &Pubkey) -> Result<Vec<(Pubkey, OrderAccount)>, String> {
+            Ok(self
+                .orders
+                .iter()
+                .map(|(address, order_account)| {
+                    (
+                        *address,
+                        OrderAccount {
+                            items: order_account.items.clone(),
+                            order_id: order_account.order_id.clone(),
+                            secret: order_account.secret.clone(),
+                            data: order_account.data.clone(),
+                            ..*order_account
+                        },
+                    )
+                })
+                .collect())
+        }
+
+        fn unix_timestamp(&self) -> Result<UnixTimestamp, String> {
+            Ok(100)
+        }
+
+        fn submit(&self, instruction: &Instruction) -> Result<(), String> {
+            self.submitted.borrow_mut().push(instruction.clone());
+            if self.submitted.borrow().len() == 1 {
+                return Err(String::from("custom program error: 0x13"));
+            }
+            Ok(())
+        }
+    }
+
+    struct CountingMetrics {
+        settled: RefCell<u64>,
+        failed: RefCell<u64>,
+    }
+
+    impl SettlementMetrics for CountingMetrics {
+        fn settled(&self, _settlement: &Settlement) {
+            *self.settled.borrow_mut() += 1;
+        }
+
+        fn settlement_failed(&self, _settlement: &Settlement, _error: &str) {
+            *self.failed.borrow_mut() += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_settlement() {
+        let merchant = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let client = TestClient {
+            merchant_account: merchant_account(&Pubkey::new_unique()),
+            orders: vec![
+                (Pubkey::new_unique(), order(&merchant, &mint, 1, 100, "{}")),
+                (Pubkey::new_unique(), order(&merchant, &mint, 2, 100, "{}")),
+            ],
+            submitted: RefCell::new(vec![]),
+        };
+        let metrics = CountingMetrics {
+            settled: RefCell::new(0),
+            failed: RefCell::new(0),
+        };
+        let report = run_settlement(
+            &client,
+            &metrics,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &merchant,
+            &SettlementPolicy::default(),
+        )
+        .unwrap();
+        // the first withdrawal failed but the second one was still submitted
+        assert_eq!(2, client.submitted.borrow().len());
+        assert_eq!(vec![client.orders[1].0], report.settled);
+        assert_eq!(
+            vec![(
+                client.orders[0].0,
+                String::from("custom program error: 0x13")
+            )],
+            report.failed
+        );
+        assert_eq!(1, *metrics.settled.borrow());
+        assert_eq!(1, *metrics.failed.borrow());
+    }
+}
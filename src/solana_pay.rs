@@ -0,0 +1,382 @@
+//! Solana Pay compatibility for express checkouts
+//!
+//! Meant to be used off-chain by checkouts that receive Solana Pay transfer
+//! requests (`solana:<recipient>?amount=...`), or that want to hand one out.  The
+//! recipient of the request is the merchant account, and the request is paid with
+//! an ExpressCheckout instead of a plain token transfer.  As Solana Pay expects,
+//! the reference keys are added to the checkout instruction as read-only accounts
+//! so that wallets and indexers can find the payment with getSignaturesForAddress,
+//! and the memo (if any) is sent in a memo instruction right before it.
+
+use crate::{
+    deeplink::encode_uri_component,
+    instruction::{express_checkout_from_wallet, ExpressCheckoutFromWalletArgs},
+};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// the id of the SPL Memo program
+pub const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum SolanaPayError {
+    /// Not a Solana Pay transfer request
+    #[error("Error: Invalid Solana Pay URL")]
+    InvalidUrl,
+    /// The recipient is not a public key
+    #[error("Error: Invalid recipient")]
+    InvalidRecipient,
+    /// The amount is not a positive decimal with at most as many decimals as the mint
+    #[error("Error: Invalid amount {0}")]
+    InvalidAmount(String),
+    /// The spl-token or a reference is not a public key
+    #[error("Error: Invalid public key {0}")]
+    InvalidPublicKey(String),
+    /// An express checkout needs the amount to charge
+    #[error("Error: The amount is missing")]
+    MissingAmount,
+    /// Requests for native SOL are paid with ExpressCheckoutSol instead
+    #[error("Error: Native SOL requests are not supported")]
+    NativeTransfer,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// A Solana Pay transfer request
+pub struct TransferRequest {
+    /// the merchant account
+    pub recipient: Pubkey,
+    /// the amount in the units of the mint (e.g. "1.5"), if set
+    pub amount: Option<String>,
+    /// the mint, or native SOL if not set
+    pub spl_token: Option<Pubkey>,
+    /// keys that mark the transaction, in order
+    pub references: Vec<Pubkey>,
+    /// the name of the merchant
+    pub label: Option<String>,
+    /// what is being paid for
+    pub message: Option<String>,
+    /// recorded on-chain in a memo instruction
+    pub memo: Option<String>,
+}
+
+fn decode(value: &str) -> Result<String, SolanaPayError> {
+    percent_decode_str(&value.replace('+', " "))
+        .decode_utf8()
+        .map(|value| value.to_string())
+        .map_err(|_| SolanaPayError::InvalidUrl)
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey, SolanaPayError> {
+    Pubkey::from_str(value).map_err(|_| SolanaPayError::InvalidPublicKey(String::from(value)))
+}
+
+impl TransferRequest {
+    /// Parse a Solana Pay transfer request URL
+    pub fn parse(url: &str) -> Result<TransferRequest, SolanaPayError> {
+        let rest = url
+            .strip_prefix("solana:")
+            .ok_or(SolanaPayError::InvalidUrl)?;
+        let mut parts = rest.splitn(2, '?');
+        let recipient = parts.next().unwrap_or("");
+        let mut request = TransferRequest {
+            recipient: Pubkey::from_str(recipient).map_err(|_| SolanaPayError::InvalidRecipient)?,
+            ..TransferRequest::default()
+        };
+        for pair in parts.next().unwrap_or("").split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let mut key_value = pair.splitn(2, '=');
+            let key = key_value.next().unwrap_or("");
+            let value = decode(key_value.next().unwrap_or(""))?;
+            match key {
+                "amount" => request.amount = Some(value),
+                "spl-token" => request.spl_token = Some(parse_pubkey(&value)?),
+                "reference" => request.references.push(parse_pubkey(&value)?),
+                "label" => request.label = Some(value),
+                "message" => request.message = Some(value),
+                "memo" => request.memo = Some(value),
+                // unknown parameters are ignored, as the specification asks
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+
+    /// The Solana Pay transfer request URL
+    pub fn to_url(&self) -> String {
+        let mut params = vec![];
+        if let Some(amount) = &self.amount {
+            params.push(format!("amount={}", encode_uri_component(amount)));
+        }
+        if let Some(spl_token) = &self.spl_token {
+            params.push(format!("spl-token={}", spl_token));
+        }
+        for reference in self.references.iter() {
+            params.push(format!("reference={}", reference));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", encode_uri_component(label)));
+        }
+        if let Some(message) = &self.message {
+            params.push(format!("message={}", encode_uri_component(message)));
+        }
+        if let Some(memo) = &self.memo {
+            params.push(format!("memo={}", encode_uri_component(memo)));
+        }
+        if params.is_empty() {
+            return format!("solana:{}", self.recipient);
+        }
+        format!("solana:{}?{}", self.recipient, params.join("&"))
+    }
+
+    /// The order data holding the label and message of the request
+    pub fn order_data(&self) -> Option<String> {
+        if self.label.is_none() && self.message.is_none() {
+            return None;
+        }
+        let mut data = json!({});
+        if let Some(label) = &self.label {
+            data["label"] = Value::from(label.as_str());
+        }
+        if let Some(message) = &self.message {
+            data["message"] = Value::from(message.as_str());
+        }
+        Some(data.to_string())
+    }
+}
+
+/// Convert a decimal amount (e.g. "1.5") into the smallest unit of a mint
+pub fn parse_amount(amount: &str, decimals: u8) -> Result<u64, SolanaPayError> {
+    let invalid = || SolanaPayError::InvalidAmount(String::from(amount));
+    let mut parts = amount.splitn(2, '.');
+    let units = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    if units.is_empty()
+        || fraction.len() > decimals as usize
+        || !units
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let digits = format!(
+        "{}{}{}",
+        units,
+        fraction,
+        "0".repeat(decimals as usize - fraction.len())
+    );
+    match digits.parse::<u64>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(invalid()),
+    }
+}
+
+/// The arguments of express_checkout_instructions
+pub struct ExpressCheckoutInstructionsArgs<'a> {
+    pub signer: Pubkey,
+    pub order: Pubkey,
+    pub program_owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub request: &'a TransferRequest,
+    pub decimals: u8,
+    pub order_id: String,
+    pub secret: String,
+}
+
+/// Create the instructions that pay a transfer request with an ExpressCheckout
+///
+/// The buyer (signer) pays from their associated token account.  decimals are the
+/// decimals of the requested mint.  Returns the memo instruction (if any) followed
+/// by the ExpressCheckout instruction.
+pub fn express_checkout_instructions(
+    program_id: Pubkey,
+    args: ExpressCheckoutInstructionsArgs<'_>,
+) -> Result<Vec<Instruction>, SolanaPayError> {
+    let ExpressCheckoutInstructionsArgs {
+        signer,
+        order,
+        program_owner,
+        sponsor,
+        request,
+        decimals,
+        order_id,
+        secret,
+    } = args;
+    let mint = request.spl_token.ok_or(SolanaPayError::NativeTransfer)?;
+    let amount = match &request.amount {
+        None => return Err(SolanaPayError::MissingAmount),
+        Some(value) => parse_amount(value, decimals)?,
+    };
+    let mut checkout = express_checkout_from_wallet(
+        program_id,
+        ExpressCheckoutFromWalletArgs {
+            signer,
+            order,
+            merchant: request.recipient,
+            mint,
+            program_owner,
+            sponsor,
+            integrator: Option::None,
+            coupon: Option::None,
+            link: Option::None,
+            amount,
+            tip: 0,
+            order_id,
+            secret,
+            data: request.order_data(),
+        },
+    );
+    for reference in request.references.iter() {
+        checkout
+            .accounts
+            .push(AccountMeta::new_readonly(*reference, false));
+    }
+
+    let mut instructions = vec![];
+    if let Some(memo) = &request.memo {
+        instructions.push(Instruction {
+            program_id: Pubkey::from_str(MEMO_PROGRAM_ID).unwrap(),
+            accounts: vec![],
+            data: memo.as_bytes().to_vec(),
+        });
+    }
+    instructions.push(checkout);
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_transfer_request_url() {
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let references = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let request = TransferRequest {
+            recipient,
+            amount: Some(String::from("1.5")),
+            spl_token: Some(mint),
+            references: references.clone(),
+            label: Some(String::from("Coffee & Co")),
+            message: Some(String::from("Order #42")),
+            memo: Some(String::from("42")),
+        };
+        let url = request.to_url();
+        assert_eq!(
+            format!(
+                "solana:{}?amount=1.5&spl-token={}&reference={}&reference={}&label=Coffee%20%26%20Co&message=Order%20%2342&memo=42",
+                recipient, mint, references[0], references[1]
+            ),
+            url
+        );
+        assert_eq!(Ok(request), TransferRequest::parse(&url));
+
+        let request =
+            TransferRequest::parse(&format!("solana:{}?label=Coffee+shop&foo=bar", recipient))
+                .unwrap();
+        assert_eq!(recipient, request.recipient);
+        assert_eq!(Some(String::from("Coffee shop")), request.label);
+        assert_eq!(None, request.amount);
+
+        assert_eq!(
+            Err(SolanaPayError::InvalidUrl),
+            TransferRequest::parse("https://shop.example")
+        );
+        assert_eq!(
+            Err(SolanaPayError::InvalidRecipient),
+            TransferRequest::parse("solana:https%3A%2F%2Fshop.example")
+        );
+        assert_eq!(
+            Err(SolanaPayError::InvalidPublicKey(String::from("nope"))),
+            TransferRequest::parse(&format!("solana:{}?reference=nope", recipient))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_amount() {
+        assert_eq!(Ok(1500000), parse_amount("1.5", 6));
+        assert_eq!(Ok(1), parse_amount("0.000001", 6));
+        assert_eq!(Ok(20), parse_amount("20", 0));
+        assert_eq!(
+            Err(SolanaPayError::InvalidAmount(String::from("0.0000001"))),
+            parse_amount("0.0000001", 6)
+        );
+        assert!(parse_amount("0", 6).is_err());
+        assert!(parse_amount(".5", 6).is_err());
+        assert!(parse_amount("-1", 6).is_err());
+        assert!(parse_amount("1e6", 6).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_express_checkout_instructions() {
+        let program_id = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let reference = Pubkey::new_unique();
+        let request = TransferRequest {
+            recipient: Pubkey::new_unique(),
+            amount: Some(String::from("2.25")),
+            spl_token: Some(Pubkey::new_unique()),
+            references: vec![reference],
+            label: Some(String::from("Coffee")),
+            memo: Some(String::from("table 4")),
+            ..TransferRequest::default()
+        };
+        let instructions = express_checkout_instructions(
+            program_id,
+            ExpressCheckoutInstructionsArgs {
+                signer,
+                order: Pubkey::new_unique(),
+                program_owner: Pubkey::new_unique(),
+                sponsor: Pubkey::new_unique(),
+                request: &request,
+                decimals: 2,
+                order_id: String::from("42"),
+                secret: String::from(""),
+            },
+        )
+        .unwrap();
+        assert_eq!(2, instructions.len());
+        assert_eq!(
+            Pubkey::from_str(MEMO_PROGRAM_ID).unwrap(),
+            instructions[0].program_id
+        );
+        assert_eq!(b"table 4".to_vec(), instructions[0].data);
+        let checkout = &instructions[1];
+        assert_eq!(program_id, checkout.program_id);
+        assert_eq!(request.recipient, checkout.accounts[2].pubkey);
+        assert_eq!(
+            &AccountMeta::new_readonly(reference, false),
+            checkout.accounts.last().unwrap()
+        );
+
+        let native_request = TransferRequest {
+            spl_token: None,
+            ..request.clone()
+        };
+        assert_eq!(
+            Err(SolanaPayError::NativeTransfer),
+            express_checkout_instructions(
+                program_id,
+                ExpressCheckoutInstructionsArgs {
+                    signer,
+                    order: Pubkey::new_unique(),
+                    program_owner: Pubkey::new_unique(),
+                    sponsor: Pubkey::new_unique(),
+                    request: &native_request,
+                    decimals: 2,
+                    order_id: String::from("42"),
+                    secret: String::from(""),
+                },
+            )
+        );
+    }
+}
@@ -1,35 +1,311 @@
+use crate::{
+    engine::constants::{MAX_ACCEPTED_MINTS, MAX_OPERATORS, MAX_PAYOUT_DESTINATIONS, SPONSOR_FEE},
+    error::PaymentProcessorError,
+};
 use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use num_derive::FromPrimitive;
 use solana_program::{
-    clock::UnixTimestamp,
+    clock::{Slot, UnixTimestamp},
+    hash::hashv,
+    program_error::ProgramError,
     program_pack::{IsInitialized, Sealed},
 };
-use std::mem::size_of;
+use std::{convert::TryInto, marker::PhantomData, mem::size_of};
 
 pub type PublicKey = [u8; 32];
 
 pub trait Serdes: Sized + BorshSerialize + BorshDeserialize {
+    /// the discriminators of the accounts of this type - unpacking an account
+    /// of another type fails, so that one account cannot be passed for another
+    const DISCRIMINATORS: &'static [u8];
+
+    /// the size of the fields that accounts in the legacy layout (version 0) lack,
+    /// at their zero value, and of the rest of their discriminator (see
+    /// get_account_discriminator) - just the version for accounts that were
+    /// created with all their fields
+    const ADDED_LEN: usize = DISCRIMINATOR_LEN - LEGACY_DISCRIMINATOR_LEN + size_of::<u8>();
+
+    /// Get the data of the account, which starts with its discriminator (see
+    /// get_account_discriminator) and ends with its version (see ACCOUNT_VERSION)
+    ///
+    /// The accounts in the legacy layout (version 0) are packed back in it, with
+    /// the one-byte discriminator and without the fields added after it, which
+    /// they have no room for.  Handlers only set these fields once the account is
+    /// migrated (see check_account_migrated).
+    fn to_account_data(&self) -> Vec<u8> {
+        let mut encoded = self.try_to_vec().unwrap();
+        if encoded.last() == Some(&0) {
+            encoded.truncate(
+                encoded.len() + DISCRIMINATOR_LEN - LEGACY_DISCRIMINATOR_LEN - Self::ADDED_LEN,
+            );
+            return encoded;
+        }
+        [
+            &get_account_discriminator(encoded[0])[..],
+            &encoded[LEGACY_DISCRIMINATOR_LEN..],
+        ]
+        .concat()
+    }
     fn pack(&self, dst: &mut [u8]) {
-        let encoded = self.try_to_vec().unwrap();
+        let encoded = self.to_account_data();
         dst[..encoded.len()].copy_from_slice(&encoded);
     }
-    fn unpack(src: &[u8]) -> Result<Self, std::io::Error> {
-        Self::try_from_slice(src)
+    /// Accounts in the legacy layout start with a one-byte discriminator and end
+    /// before the fields added after it, and are read as version 0 with these
+    /// fields at their legacy value (see with_legacy_defaults) until they are
+    /// migrated (see MigrateAccount).  Only accounts that end right where the
+    /// legacy layout does are read in it, so that truncated or corrupt accounts are
+    /// not taken for legacy ones.
+    fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        if let Some(discriminator) = src.first() {
+            if *discriminator != Discriminator::Uninitialized as u8
+                && *discriminator != Discriminator::Closed as u8
+                && !Self::DISCRIMINATORS.contains(discriminator)
+            {
+                return Err(PaymentProcessorError::WrongAccountType.into());
+            }
+        }
+        // accounts that are not initialized yet are all zeros
+        if has_account_discriminator(src)
+            || (src.len() >= DISCRIMINATOR_LEN
+                && src
+                    .iter()
+                    .all(|byte| *byte == Discriminator::Uninitialized as u8))
+        {
+            return Self::try_from_slice(
+                &[&src[..LEGACY_DISCRIMINATOR_LEN], &src[DISCRIMINATOR_LEN..]].concat(),
+            )
+            .map_err(|_error| ProgramError::InvalidAccountData);
+        }
+        let mut legacy_src = src.to_vec();
+        legacy_src.resize(
+            src.len() + Self::ADDED_LEN + LEGACY_DISCRIMINATOR_LEN - DISCRIMINATOR_LEN,
+            0,
+        );
+        match Self::try_from_slice(&legacy_src) {
+            Ok(value) if value.legacy_len() == src.len() => Ok(value.with_legacy_defaults()),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+    /// The size of the account in the legacy layout, i.e. with the one-byte
+    /// discriminator and without the fields added after it
+    fn legacy_len(&self) -> usize {
+        self.try_to_vec().unwrap().len() + DISCRIMINATOR_LEN
+            - LEGACY_DISCRIMINATOR_LEN
+            - Self::ADDED_LEN
+    }
+    /// Give the fields added after the legacy layout the value that they stand
+    /// for in accounts read in it, where they are all zeros
+    fn with_legacy_defaults(self) -> Self {
+        self
+    }
+}
+
+/// the size of the discriminator that accounts start with (see
+/// get_account_discriminator)
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// the size of the discriminator of the accounts in the legacy layout, i.e. just
+/// their type
+pub const LEGACY_DISCRIMINATOR_LEN: usize = size_of::<u8>();
+
+/// Get the discriminator that the accounts of a type start with
+///
+/// It is the type of the account (see Discriminator) followed by the first bytes
+/// of the hash of "account:" and the type, so that the data of an account of
+/// another type, or of another program, is not taken for an account of the type.
+/// Accounts in the legacy layout start with their type alone.
+pub fn get_account_discriminator(discriminator: u8) -> [u8; DISCRIMINATOR_LEN] {
+    let hash = hashv(&[b"account:", &[discriminator]]).to_bytes();
+    let mut account_discriminator = [discriminator; DISCRIMINATOR_LEN];
+    account_discriminator[LEGACY_DISCRIMINATOR_LEN..]
+        .copy_from_slice(&hash[..DISCRIMINATOR_LEN - LEGACY_DISCRIMINATOR_LEN]);
+    account_discriminator
+}
+
+/// Check whether the data of an account starts with the discriminator of its
+/// type, i.e. it is not in the legacy layout
+pub fn has_account_discriminator(data: &[u8]) -> bool {
+    match data.first() {
+        Some(discriminator) if data.len() >= DISCRIMINATOR_LEN => {
+            data[..DISCRIMINATOR_LEN] == get_account_discriminator(*discriminator)
+        }
+        _ => false,
+    }
+}
+
+/// A fixed-size field of an account, read and written in place
+///
+/// The leading fixed-size fields of merchant, order and subscription accounts
+/// are at the same offset in every account, and can be accessed without
+/// unpacking (and packing back) the whole account.  The fields added after the
+/// legacy layout come after the data of the accounts, so their offset varies and
+/// they are only accessed by unpacking the account.
+///
+/// The offsets are part of the layout of the accounts, so they do not change
+/// across upgrades: indexers can filter the accounts of the program on them with
+/// getProgramAccounts (see crate::filters).  They are the offsets in accounts that
+/// start with the full discriminator (see get_account_discriminator); the fields
+/// after it are at legacy_offset in accounts in the legacy layout, which get and
+/// set handle.
+pub struct FixedField<T> {
+    pub offset: usize,
+    field_type: PhantomData<T>,
+}
+
+impl<T> FixedField<T> {
+    pub const fn new(offset: usize) -> Self {
+        FixedField {
+            offset,
+            field_type: PhantomData,
+        }
+    }
+
+    /// the offset of the field in accounts in the legacy layout, which start with
+    /// the one-byte discriminator
+    pub const fn legacy_offset(&self) -> usize {
+        if self.offset < DISCRIMINATOR_LEN {
+            self.offset
+        } else {
+            self.offset + LEGACY_DISCRIMINATOR_LEN - DISCRIMINATOR_LEN
+        }
+    }
+
+    fn range(&self, data: &[u8]) -> Result<std::ops::Range<usize>, ProgramError> {
+        let offset = if has_account_discriminator(data) {
+            self.offset
+        } else {
+            self.legacy_offset()
+        };
+        let end = offset + size_of::<T>();
+        if end > data.len() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(offset..end)
+    }
+}
+
+/// the discriminator, which every account of the program starts with
+pub const DISCRIMINATOR: FixedField<u8> = FixedField::new(0);
+
+impl FixedField<u8> {
+    pub fn get(&self, data: &[u8]) -> Result<u8, ProgramError> {
+        Ok(data[self.range(data)?][0])
+    }
+    pub fn set(&self, data: &mut [u8], value: u8) -> Result<(), ProgramError> {
+        let range = self.range(data)?;
+        data[range][0] = value;
+        Ok(())
+    }
+}
+
+impl FixedField<u64> {
+    pub fn get(&self, data: &[u8]) -> Result<u64, ProgramError> {
+        Ok(u64::from_le_bytes(
+            data[self.range(data)?].try_into().unwrap(),
+        ))
+    }
+    pub fn set(&self, data: &mut [u8], value: u64) -> Result<(), ProgramError> {
+        let range = self.range(data)?;
+        data[range].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl FixedField<i64> {
+    pub fn get(&self, data: &[u8]) -> Result<i64, ProgramError> {
+        Ok(i64::from_le_bytes(
+            data[self.range(data)?].try_into().unwrap(),
+        ))
+    }
+    pub fn set(&self, data: &mut [u8], value: i64) -> Result<(), ProgramError> {
+        let range = self.range(data)?;
+        data[range].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl FixedField<PublicKey> {
+    pub fn get(&self, data: &[u8]) -> Result<PublicKey, ProgramError> {
+        Ok(data[self.range(data)?].try_into().unwrap())
+    }
+    pub fn set(&self, data: &mut [u8], value: &PublicKey) -> Result<(), ProgramError> {
+        let range = self.range(data)?;
+        data[range].copy_from_slice(value);
+        Ok(())
     }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 pub enum Discriminator {
     Uninitialized = 0,
+    ProgramConfig = 1,
     Merchant = 10,
     MerchantSubscription = 11,
     MerchantSubscriptionWithTrial = 12,
     MerchantChainCheckout = 15,
     OrderExpressCheckout = 20,
     OrderChainCheckout = 21,
+    OrderSplitCheckout = 22,
+    OrderInvoice = 23,
     Subscription = 30,
+    Package = 31,
+    Migration = 35,
+    Integrator = 40,
+    Attestation = 50,
+    Voucher = 60,
+    Coupon = 70,
+    PaymentLink = 80,
+    LinkPayer = 81,
+    PriceGuarantee = 90,
+    AutoDebit = 100,
+    FeeHistory = 110,
+    FeeSchedule = 111,
+    Campaign = 120,
+    RefundCode = 130,
+    MerchantRecovery = 140,
+    ItemStock = 150,
+    PriceOracle = 160,
+    Bundle = 170,
+    BundleMember = 171,
+    Settlement = 180,
+    OrderNotes = 190,
+    OrderNote = 191,
+    MerchantTransfer = 200,
+    MerchantStats = 210,
+    SweepBounty = 211,
+    Store = 220,
+    AcceptedMints = 230,
+    BlockedBuyer = 240,
+    Referrer = 250,
     Closed = 255,
 }
 
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct ProgramConfigAccount {
+    pub discriminator: u8,
+    /// the version of the program state (see PROGRAM_VERSION)
+    pub state_version: u8,
+    /// the account that receives the transaction fees
+    pub owner: PublicKey,
+    /// the account allowed to administer this instance of the program
+    pub authority: PublicKey,
+    /// the fee (in SOL lamports) charged when a merchant does not set one
+    pub default_fee: u64,
+    /// the minimum fee (in SOL lamports) that a merchant can set
+    pub min_fee: u64,
+    /// the sponsor share (in tenths of a percent) of the transaction fee
+    pub sponsor_fee: u64,
+    /// payments are paused until this time while accounts are being migrated
+    pub migration_ends: UnixTimestamp,
+    /// the name under which this instance of the program is branded
+    pub name: String,
+    /// this is represented as a string but really is meant to hold JSON
+    /// found this to be a convenient hack to allow flexible data
+    pub data: String,
+    pub version: u8,
+}
+
 #[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
 pub struct MerchantAccount {
     pub discriminator: u8,
@@ -37,18 +313,127 @@ pub struct MerchantAccount {
     pub sponsor: PublicKey,
     /// represents the fee (in SOL lamports) that will be charged for transactions
     pub fee: u64,
-    /// this is represented as a string but really is meant to hold JSON
-    /// found this to be a convenient hack to allow flexible data
+    /// the catalog or subscription packages of merchants registered before they
+    /// were typed, as JSON, until they are migrated (see MigrateMerchantData) -
+    /// otherwise just padding that keeps the account size
     pub data: String,
+    /// represents the amount (in basis points of the price) by which a chain
+    /// checkout may underpay or overpay - 0 means no tolerance policy
+    pub tolerance: u64,
+    /// the number of open orders and active subscriptions of the merchant - the
+    /// merchant account can only be closed once there are none
+    pub references: u64,
+    /// checkouts and subscriptions of the merchant fail while it is paused
+    pub is_paused: bool,
+    /// payments are only taken in the mints of the accepted mints account of the merchant
+    pub restricts_mints: bool,
+    /// checkouts of the merchant pay the merchant token account directly, leaving
+    /// nothing to withdraw (see SetInstantSettlement)
+    pub instant_settlement: bool,
+    /// the number of buyers blocked by the merchant - while there are any, checkouts
+    /// have to show that the buyer is not blocked
+    pub blocked_buyers: u64,
+    /// the sum of the amounts paid in checkouts of the merchant (in the base units
+    /// of whichever mint they were paid in) - picks the fee tier of the merchant
+    pub lifetime_volume: u64,
+    /// the sequence number of the last order of the merchant - each order is
+    /// stamped with the next one (see OrderAccount::sequence)
+    pub order_sequence: u64,
+    /// checkouts in mints with a freeze authority fail, unless the merchant
+    /// accepts the mint in its accepted mints account
+    pub rejects_freezable_mints: bool,
+    /// the sponsor share (in tenths of a percent) of the transaction fee
+    pub sponsor_fee: u64,
+    /// the number of wallets of the payout schedule, which are the first ones of
+    /// payout_wallets - 0 means that withdrawals pay a single token account
+    pub payout_count: u64,
+    /// the wallets that withdrawals of the merchant are split among
+    pub payout_wallets: [PublicKey; MAX_PAYOUT_DESTINATIONS],
+    /// the share (in basis points) of each wallet of the payout schedule
+    pub payout_shares: [u64; MAX_PAYOUT_DESTINATIONS],
+    /// the number of operators of the merchant, which are the first ones of operators
+    pub operator_count: u64,
+    /// the wallets that can withdraw payments on behalf of the merchant owner
+    pub operators: [PublicKey; MAX_OPERATORS],
+    /// orders are swept to the merchant once the ones old enough hold at least
+    /// this much (in total) - 0 means the orders of the merchant are not swept
+    pub sweep_threshold: u64,
+    /// the slots that an order waits after it was created (see
+    /// OrderAccount::created_slot) before it is swept
+    pub sweep_delay: Slot,
+    /// the items sold in chain checkouts of the merchant
+    pub catalog: Vec<CatalogItem>,
+    /// the subscription packages of the merchant
+    pub packages: Vec<SubscriptionPackage>,
+    /// free-form metadata of the merchant (at most MAX_METADATA_LEN bytes), which
+    /// the program does not read
+    pub metadata: Vec<u8>,
+    pub version: u8,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Clone, Debug, PartialEq)]
+/// What a merchant registers to sell (see RegisterMerchantWithTerms)
+pub enum MerchantTerms {
+    /// checkouts of any amount
+    Checkout,
+    /// chain checkouts of the items of the catalog
+    Catalog(#[allow(dead_code)] Vec<CatalogItem>),
+    /// subscriptions to the packages, which can be empty for merchants whose
+    /// packages all have package accounts of their own
+    Packages(#[allow(dead_code)] Vec<SubscriptionPackage>),
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Clone, Debug, PartialEq)]
+/// An item for which a payment can be made in a chain checkout
+pub struct CatalogItem {
+    /// the key of the item in the order items of a chain checkout
+    pub name: String,
+    pub price: u64,
+    /// the mint (currency) that the item is priced in
+    pub mint: PublicKey,
+    /// the item is only sold while in stock, as counted in its item stock account
+    pub track_stock: bool,
+    /// the collection (mint) of the NFTs whose holders this item is gated for
+    pub collection: Option<PublicKey>,
+    /// the discount (in basis points) that holders of an NFT of the collection get,
+    /// in which case the item is sold to anyone instead of holders only
+    pub holder_discount: Option<u64>,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Clone, Debug, PartialEq)]
+/// A subscription package of the merchant (see engine::json::Package)
+pub struct SubscriptionPackage {
+    pub name: String,
+    /// duration of the trial period in seconds
+    pub trial: Option<i64>,
+    /// duration of the subscription in seconds
+    pub duration: i64,
+    /// the price in full for this subscription option
+    pub price: u64,
+    /// the mint (currency) used for this package
+    pub mint: PublicKey,
+    /// whether cancelling after the trial period refunds the unused part of the period
+    pub prorated_refunds: bool,
+    /// whether the trial period can be started without paying
+    pub price_due_at_trial_end: bool,
+    /// how long (in seconds) after the end of a period that has not been renewed
+    /// the subscription is past due before it expires
+    pub grace_seconds: Option<i64>,
+    /// the price of a usage unit for metered packages
+    pub unit_price: Option<u64>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, FromPrimitive, PartialEq)]
 pub enum OrderStatus {
     Uninitialized = 0,
     Pending = 1,
     Paid = 2,
     Withdrawn = 3,
     Cancelled = 4,
+    Expired = 5,
+    Releasable = 6,
+    FeesWaived = 7,
+    Refunded = 8,
 }
 
 #[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
@@ -68,13 +453,48 @@ pub struct OrderAccount {
     /// this is represented as a string but really is meant to hold JSON
     /// found this to be a convenient hack to allow flexible data
     pub data: String,
+    /// an optional tip paid on top of the order amount
+    pub tip: u64,
+    /// the part of the payment withheld by the mint as a transfer fee (Token-2022)
+    pub transfer_fee: u64,
+    /// the part of the payment (and tip) already withdrawn with WithdrawAmount
+    pub withdrawn_amount: u64,
+    /// the number of the order in the order sequence of the merchant, starting
+    /// at 1 - 0 for orders created before orders were numbered
+    pub sequence: u64,
+    /// the items bought in a chain checkout
+    pub items: Vec<OrderLineItem>,
+    /// the part of the payment (and tip) of an escrow order that the buyer released
+    /// with ConfirmDelivery while the rest is held - the merchant can withdraw it
+    /// with WithdrawAmount before the escrow timeout
+    pub released_amount: u64,
+    /// the slot that the order was created in, which the sweep delay of the
+    /// merchant counts from - 0 for orders created before it was recorded
+    pub created_slot: Slot,
+    /// the part of the program owner fee that the order set aside for the sweep
+    /// crank, held by the sweep bounty account of the merchant until the order is
+    /// swept (or settled otherwise, when it goes back to the program owner)
+    pub sweep_bounty: u64,
+    pub version: u8,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Clone, Debug, PartialEq)]
+/// An item bought in a chain checkout, and how many of it
+pub struct OrderLineItem {
+    /// the name of the catalog item
+    pub name: String,
+    pub quantity: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, FromPrimitive, PartialEq)]
 pub enum SubscriptionStatus {
     Uninitialized = 0,
     Initialized = 1,
     Cancelled = 2,
+    Paused = 3,
+    Trialing = 4,
+    PastDue = 5,
+    Expired = 6,
 }
 
 #[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
@@ -90,49 +510,1096 @@ pub struct SubscriptionAccount {
     /// this is represented as a string but really is meant to hold JSON
     /// found this to be a convenient hack to allow flexible data
     pub data: String,
+    /// the seconds left in the period when the subscription was paused
+    pub remaining_seconds: i64,
+    /// the usage units recorded by the merchant that have not been settled yet
+    pub usage_units: u64,
+    /// the number of seats of the subscription - the package price is per seat
+    pub seats: u64,
+    /// the package account for packages of the catalog of the merchant (see
+    /// PackageAccount), or all zeros for packages found in the merchant data
+    pub package: PublicKey,
+    /// the package price when subscribing - subscriptions to packages of the
+    /// catalog keep renewing at this price when the package is updated
+    pub price: u64,
+    /// the order that paid for the current period (the last one subscribed or
+    /// renewed with), which a prorated refund is taken from - all zeros for
+    /// subscriptions that have not been renewed since they were migrated
+    pub period_order: PublicKey,
+    pub version: u8,
 }
 
-// impl for MerchantAccount
-impl Sealed for MerchantAccount {}
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct IntegratorAccount {
+    pub discriminator: u8,
+    /// the account allowed to withdraw the accrued integrator fees
+    pub owner: PublicKey,
+    /// the id of the app (e.g. wallet or plugin) that facilitates payments
+    pub app_id: String,
+    pub version: u8,
+}
 
-impl Serdes for MerchantAccount {}
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct AttestationAccount {
+    pub discriminator: u8,
+    /// the program id of the instance that issued the attestation
+    pub source: PublicKey,
+    /// the merchant account (on the issuing instance) that is attested
+    pub merchant: PublicKey,
+    /// the owner of the attested merchant account
+    pub owner: PublicKey,
+    pub created: UnixTimestamp,
+    /// the number of orders that were paid
+    pub order_count: u64,
+    /// the number of subscriptions
+    pub subscription_count: u64,
+    /// the number of subscriptions that were active when the attestation was issued
+    pub active_subscription_count: u64,
+    /// hash of the merchant, order and subscription accounts that were attested
+    pub digest: [u8; 32],
+    pub version: u8,
+}
 
-impl MerchantAccount {
-    pub const MIN_LEN: usize =
-        size_of::<u8>() + size_of::<PublicKey>() + size_of::<PublicKey>() + size_of::<u64>();
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct VoucherAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the order that was created when the voucher was redeemed
+    pub order: PublicKey,
+    pub nonce: u64,
+    pub redeemed: UnixTimestamp,
+    pub version: u8,
 }
 
-// impl for OrderAccount
-impl Sealed for OrderAccount {}
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub enum DiscountType {
+    Percentage = 0,
+    Fixed = 1,
+}
 
-impl Serdes for OrderAccount {}
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct CouponAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the mint (currency) of a fixed discount
+    pub mint: PublicKey,
+    /// one of DiscountType
+    pub discount_type: u8,
+    /// the discount in basis points for a percentage discount, or the amount
+    /// taken off the order for a fixed discount
+    pub discount: u64,
+    /// the number of times that the coupon can still be redeemed
+    pub remaining_uses: u64,
+    pub expires_at: UnixTimestamp,
+    /// the discount code given out to buyers
+    pub code: String,
+    pub version: u8,
+}
 
-impl OrderAccount {
-    pub const MIN_LEN: usize = size_of::<u8>()
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct PaymentLinkAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the mint (currency) that the link is paid in
+    pub mint: PublicKey,
+    pub created: UnixTimestamp,
+    /// the time of the last payment made through the link
+    pub last_paid: UnixTimestamp,
+    /// the number of payments made through the link
+    pub payments: u64,
+    /// the total amount paid through the link
+    pub volume: u64,
+    /// the number of distinct wallets that paid through the link
+    pub unique_payers: u64,
+    /// the amount to be paid through the link - 0 means that any amount can be paid
+    pub amount: u64,
+    /// the id of the link (as in issued by the merchant)
+    pub link_id: String,
+    /// this is represented as a string but really is meant to hold JSON
+    /// found this to be a convenient hack to allow flexible data
+    pub data: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct LinkPayerAccount {
+    pub discriminator: u8,
+    pub link: PublicKey,
+    pub payer: PublicKey,
+    /// the time of the first payment made by the payer through the link
+    pub first_paid: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct PriceGuaranteeAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the price of the package when the guarantee was given
+    pub price: u64,
+    /// subscriptions that joined up to this time are covered by the guarantee
+    pub created: UnixTimestamp,
+    /// the price does not increase for covered subscriptions before this time
+    pub guaranteed_until: UnixTimestamp,
+    /// the subscription package name
+    pub package: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct AutoDebitAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the buyer who approved the auto-debit
+    pub owner: PublicKey,
+    /// the buyer token account that the payments are pulled from
+    pub token: PublicKey,
+    pub mint: PublicKey,
+    /// the amount that the merchant can still collect
+    pub allowance: u64,
+    /// the most that the merchant can collect at once
+    pub max_amount: u64,
+    /// the minimum number of seconds between two collections
+    pub interval: i64,
+    pub created: UnixTimestamp,
+    pub last_collected: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct FeeHistoryAccount {
+    pub discriminator: u8,
+    /// the number of fee schedules recorded so far
+    pub count: u64,
+    /// the slot from which the current fee schedule of the program config applies
+    pub since: Slot,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct FeeScheduleAccount {
+    pub discriminator: u8,
+    /// the position of this fee schedule in the fee history
+    pub index: u64,
+    pub default_fee: u64,
+    pub min_fee: u64,
+    pub sponsor_fee: u64,
+    /// the fee schedule applied from this slot on
+    pub effective_from: Slot,
+    /// until this slot, when it was replaced
+    pub effective_until: Slot,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct CampaignAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the mint (currency) that donations to the campaign are made in
+    pub mint: PublicKey,
+    pub created: UnixTimestamp,
+    /// the amount that the campaign aims to raise - 0 means that there is no goal
+    pub goal: u64,
+    /// the total amount donated to the campaign
+    pub raised: u64,
+    /// the number of donations made to the campaign
+    pub donations: u64,
+    /// the id of the campaign (as in issued by the merchant)
+    pub campaign_id: String,
+    /// this is represented as a string but really is meant to hold JSON
+    /// found this to be a convenient hack to allow flexible data
+    pub data: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct RefundCodeAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the order that the code authorizes a refund of
+    pub order: PublicKey,
+    /// the hash of the code (see get_refund_code_hash)
+    pub code_hash: [u8; 32],
+    pub created: UnixTimestamp,
+    /// the time at which the code was redeemed - 0 while it has not been
+    pub redeemed: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct MerchantRecoveryAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the key that may take over the merchant account if the owner goes quiet
+    pub recovery_key: PublicKey,
+    /// how long (in seconds) the owner has to be inactive before recovery can start
+    pub timeout: i64,
+    /// how long (in seconds) the owner has to cancel a recovery once started
+    pub challenge_window: i64,
+    /// the last time the owner showed that they still hold the owner key
+    pub last_active: UnixTimestamp,
+    /// the time at which the recovery was started - 0 while it has not been
+    pub recovery_started: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct MerchantTransferAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the key that the merchant owner proposed to hand the merchant account over to
+    pub new_owner: PublicKey,
+    pub proposed: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct MerchantStatsAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the program-owned token accounts of the orders of the merchant that are still open
+    pub open_token_accounts: u64,
+    /// the most token accounts that the orders of the merchant can keep open
+    pub max_open_token_accounts: u64,
+    /// the token accounts opened and closed since the merchant started tracking them
+    pub opened: u64,
+    pub closed: u64,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct SweepBountyAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the sweep bounties set aside by the orders of the merchant that are not
+    /// swept (or settled) yet, which only the sweep crank is paid out of
+    pub held: u64,
+    /// the sweep bounties of the orders that were settled without being swept,
+    /// which the program owner claims back
+    pub returned: u64,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct MigrationAccount {
+    pub discriminator: u8,
+    /// the account being moved to a bigger account at the same address to make
+    /// room for the latest layout (see MigrateAccount)
+    pub account: PublicKey,
+    /// the account in the latest layout, copied to it once it is created again
+    pub data: Vec<u8>,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct StoreAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the wallet that the payments made at the store can be withdrawn to
+    pub fee_destination: PublicKey,
+    pub created: UnixTimestamp,
+    /// the id of the store (as in issued by the merchant)
+    pub store_id: String,
+    /// the items sold at the store, in the same format as the items of a chain
+    /// checkout merchant - empty to sell the items of the merchant
+    pub catalog: String,
+    /// this is represented as a string but really is meant to hold JSON
+    /// found this to be a convenient hack to allow flexible data
+    pub data: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct AcceptedMintsAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the number of mints accepted, which are the first ones of mints
+    pub count: u64,
+    pub mints: [PublicKey; MAX_ACCEPTED_MINTS],
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct BlockedBuyerAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the wallet that can no longer check out with the merchant
+    pub buyer: PublicKey,
+    pub blocked: UnixTimestamp,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct ReferrerAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the wallet that gets the referral share of the orders it refers
+    pub wallet: PublicKey,
+    /// the share (in basis points) of the amount paid that the referrer gets
+    pub referral_fee: u64,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct PackageAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// retired packages take no new subscriptions, existing ones still renew
+    pub is_retired: bool,
+    pub modified: UnixTimestamp,
+    /// the subscription package name
+    pub name: String,
+    /// the package as JSON (see engine::json::Package)
+    pub data: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct PriceOracleAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the payment mint that fiat amounts are converted into
+    pub mint: PublicKey,
+    /// the Pyth price account that prices the mint in fiat
+    pub price_feed: PublicKey,
+    /// how old (in seconds) the oracle price may be
+    pub max_staleness: i64,
+    /// how wide (in basis points of the price) the confidence interval of the
+    /// oracle price may be
+    pub max_slippage: u64,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct BundleAccount {
+    pub discriminator: u8,
+    /// the account that decides which merchants take part in the bundle
+    pub authority: PublicKey,
+    /// the discount (in basis points) taken off each package of the bundle
+    pub discount: u64,
+    pub created: UnixTimestamp,
+    /// the number of packages that take part in the bundle
+    pub members: u64,
+    pub bundle_id: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct BundleMemberAccount {
+    pub discriminator: u8,
+    pub bundle: PublicKey,
+    pub merchant: PublicKey,
+    pub joined: UnixTimestamp,
+    /// the subscription package of the merchant that takes part in the bundle
+    pub package: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct OrderNotesAccount {
+    pub discriminator: u8,
+    pub order: PublicKey,
+    /// the number of notes posted so far
+    pub count: u64,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct OrderNoteAccount {
+    pub discriminator: u8,
+    pub order: PublicKey,
+    /// the position of this note in the notes of the order
+    pub index: u64,
+    /// the buyer or merchant owner that signed the note
+    pub author: PublicKey,
+    pub posted: UnixTimestamp,
+    /// the note, hashed or encrypted by the author
+    pub note: String,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct SettlementAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the mint (currency) that the merchant prefers to be paid out in
+    pub mint: PublicKey,
+    pub version: u8,
+}
+
+#[derive(BorshSerialize, BorshSchema, BorshDeserialize, Debug, PartialEq)]
+pub struct ItemStockAccount {
+    pub discriminator: u8,
+    pub merchant: PublicKey,
+    /// the quantity of the item left in stock
+    pub stock: u64,
+    /// the quantity of the item sold so far
+    pub sold: u64,
+    /// the id of the item in the merchant's item catalog
+    pub item: String,
+    pub version: u8,
+}
+
+// impl for ProgramConfigAccount
+impl Sealed for ProgramConfigAccount {}
+
+impl Serdes for ProgramConfigAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::ProgramConfig as u8];
+}
+
+impl ProgramConfigAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
         + size_of::<u8>()
-        + size_of::<UnixTimestamp>()
-        + size_of::<UnixTimestamp>()
-        + size_of::<PublicKey>()
-        + size_of::<PublicKey>()
         + size_of::<PublicKey>()
         + size_of::<PublicKey>()
         + size_of::<u64>()
-        + size_of::<u64>();
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
 }
 
-// impl for SubscriptionAccount
-impl Sealed for SubscriptionAccount {}
+// impl for MerchantAccount
+impl Sealed for MerchantAccount {}
 
-impl Serdes for SubscriptionAccount {}
+impl Serdes for MerchantAccount {
+    const DISCRIMINATORS: &'static [u8] = &[
+        Discriminator::Merchant as u8,
+        Discriminator::MerchantSubscription as u8,
+        Discriminator::MerchantSubscriptionWithTrial as u8,
+        Discriminator::MerchantChainCheckout as u8,
+    ];
+    const ADDED_LEN: usize = MerchantAccount::MIN_LEN - MerchantAccount::LEGACY_MIN_LEN;
 
-impl SubscriptionAccount {
-    pub const MIN_LEN: usize = size_of::<u8>()
-        + size_of::<u8>()
+    /// merchants in the legacy layout pay the sponsor the default share of the fee
+    fn with_legacy_defaults(mut self) -> Self {
+        self.sponsor_fee = SPONSOR_FEE as u64;
+        self
+    }
+    fn legacy_len(&self) -> usize {
+        MerchantAccount::LEGACY_MIN_LEN + size_of::<u32>() + self.data.len()
+    }
+}
+
+impl MerchantAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
         + size_of::<PublicKey>()
         + size_of::<PublicKey>()
-        + size_of::<UnixTimestamp>()
-        + size_of::<UnixTimestamp>()
-        + size_of::<UnixTimestamp>();
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<bool>()
+        + size_of::<bool>()
+        + size_of::<bool>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<bool>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<[PublicKey; MAX_PAYOUT_DESTINATIONS]>()
+        + size_of::<[u64; MAX_PAYOUT_DESTINATIONS]>()
+        + size_of::<u64>()
+        + size_of::<[PublicKey; MAX_OPERATORS]>()
+        + size_of::<u64>()
+        + size_of::<Slot>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u8>();
+
+    /// the fixed-size fields of the legacy layout: discriminator, owner, sponsor and fee
+    pub const LEGACY_MIN_LEN: usize =
+        size_of::<u8>() + size_of::<PublicKey>() + size_of::<PublicKey>() + size_of::<u64>();
+
+    /// the offset of owner, after discriminator
+    pub const OWNER: FixedField<PublicKey> = FixedField::new(DISCRIMINATOR_LEN);
+}
+
+// impl for OrderAccount
+impl Sealed for OrderAccount {}
+
+impl Serdes for OrderAccount {
+    const DISCRIMINATORS: &'static [u8] = &[
+        Discriminator::OrderExpressCheckout as u8,
+        Discriminator::OrderChainCheckout as u8,
+        Discriminator::OrderSplitCheckout as u8,
+        Discriminator::OrderInvoice as u8,
+    ];
+    const ADDED_LEN: usize = OrderAccount::MIN_LEN - OrderAccount::LEGACY_MIN_LEN;
+
+    fn legacy_len(&self) -> usize {
+        OrderAccount::LEGACY_MIN_LEN
+            + size_of::<u32>()
+            + self.order_id.len()
+            + size_of::<u32>()
+            + self.secret.len()
+            + size_of::<u32>()
+            + self.data.len()
+    }
+}
+
+impl OrderAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<u8>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u32>()
+        + size_of::<u64>()
+        + size_of::<Slot>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+
+    /// the fixed-size fields of the legacy layout, up to paid_amount
+    pub const LEGACY_MIN_LEN: usize = OrderAccount::PAID_AMOUNT.legacy_offset() + size_of::<u64>();
+
+    pub const STATUS: FixedField<u8> = FixedField::new(DISCRIMINATOR_LEN);
+    /// the offset of modified, after status and created
+    pub const MODIFIED: FixedField<i64> =
+        FixedField::new(DISCRIMINATOR_LEN + size_of::<u8>() + size_of::<UnixTimestamp>());
+    /// the offset of merchant, after modified - which the orders of a merchant are
+    /// found by with getProgramAccounts
+    pub const MERCHANT: FixedField<PublicKey> =
+        FixedField::new(OrderAccount::MODIFIED.offset + size_of::<UnixTimestamp>());
+    /// the offset of payer, after merchant, mint and token
+    pub const PAYER: FixedField<PublicKey> = FixedField::new(
+        OrderAccount::MERCHANT.offset
+            + size_of::<PublicKey>()
+            + size_of::<PublicKey>()
+            + size_of::<PublicKey>(),
+    );
+    /// the offset of paid_amount, after modified, the four keys and expected_amount
+    pub const PAID_AMOUNT: FixedField<u64> = FixedField::new(
+        OrderAccount::MODIFIED.offset
+            + size_of::<UnixTimestamp>()
+            + size_of::<PublicKey>()
+            + size_of::<PublicKey>()
+            + size_of::<PublicKey>()
+            + size_of::<PublicKey>()
+            + size_of::<u64>(),
+    );
+}
+
+// impl for SubscriptionAccount
+impl Sealed for SubscriptionAccount {}
+
+impl Serdes for SubscriptionAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Subscription as u8];
+    const ADDED_LEN: usize = SubscriptionAccount::MIN_LEN - SubscriptionAccount::LEGACY_MIN_LEN;
+
+    /// subscriptions in the legacy layout have a single seat
+    fn with_legacy_defaults(mut self) -> Self {
+        self.seats = 1;
+        self
+    }
+    fn legacy_len(&self) -> usize {
+        SubscriptionAccount::LEGACY_MIN_LEN
+            + size_of::<u32>()
+            + self.name.len()
+            + size_of::<u32>()
+            + self.data.len()
+    }
+}
+
+impl SubscriptionAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<u8>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<i64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<PublicKey>()
+        + size_of::<u8>();
+
+    /// the fixed-size fields of the legacy layout, up to period_end
+    pub const LEGACY_MIN_LEN: usize = size_of::<u8>()
+        + size_of::<u8>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>();
+
+    pub const STATUS: FixedField<u8> = FixedField::new(DISCRIMINATOR_LEN);
+    /// the offset of owner (the subscriber), after status
+    pub const OWNER: FixedField<PublicKey> = FixedField::new(DISCRIMINATOR_LEN + size_of::<u8>());
+    /// the offset of merchant, after owner - which the subscriptions to a merchant
+    /// are found by with getProgramAccounts
+    pub const MERCHANT: FixedField<PublicKey> =
+        FixedField::new(SubscriptionAccount::OWNER.offset + size_of::<PublicKey>());
+}
+
+// impl for IntegratorAccount
+impl Sealed for IntegratorAccount {}
+
+impl Serdes for IntegratorAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Integrator as u8];
+}
+
+impl IntegratorAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN + size_of::<PublicKey>() + size_of::<u8>();
+}
+
+// impl for AttestationAccount
+impl Sealed for AttestationAccount {}
+
+impl Serdes for AttestationAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Attestation as u8];
+}
+
+impl AttestationAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<[u8; 32]>()
+        + size_of::<u8>();
+}
+
+// impl for VoucherAccount
+impl Sealed for VoucherAccount {}
+
+impl Serdes for VoucherAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Voucher as u8];
+}
+
+impl VoucherAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for CouponAccount
+impl Sealed for CouponAccount {}
+
+impl Serdes for CouponAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Coupon as u8];
+}
+
+impl CouponAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<u8>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for PaymentLinkAccount
+impl Sealed for PaymentLinkAccount {}
+
+impl Serdes for PaymentLinkAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::PaymentLink as u8];
+}
+
+impl PaymentLinkAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for LinkPayerAccount
+impl Sealed for LinkPayerAccount {}
+
+impl Serdes for LinkPayerAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::LinkPayer as u8];
+}
+
+impl LinkPayerAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for PriceGuaranteeAccount
+impl Sealed for PriceGuaranteeAccount {}
+
+impl Serdes for PriceGuaranteeAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::PriceGuarantee as u8];
+}
+
+impl PriceGuaranteeAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for AutoDebitAccount
+impl Sealed for AutoDebitAccount {}
+
+impl Serdes for AutoDebitAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::AutoDebit as u8];
+}
+
+impl AutoDebitAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<i64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for FeeHistoryAccount
+impl Sealed for FeeHistoryAccount {}
+
+impl Serdes for FeeHistoryAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::FeeHistory as u8];
+}
+
+impl FeeHistoryAccount {
+    pub const MIN_LEN: usize =
+        DISCRIMINATOR_LEN + size_of::<u64>() + size_of::<Slot>() + size_of::<u8>();
+}
+
+// impl for FeeScheduleAccount
+impl Sealed for FeeScheduleAccount {}
+
+impl Serdes for FeeScheduleAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::FeeSchedule as u8];
+}
+
+impl FeeScheduleAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<Slot>()
+        + size_of::<Slot>()
+        + size_of::<u8>();
+}
+
+// impl for RefundCodeAccount
+impl Sealed for RefundCodeAccount {}
+
+impl Serdes for RefundCodeAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::RefundCode as u8];
+}
+
+impl RefundCodeAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<[u8; 32]>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for MerchantRecoveryAccount
+impl Sealed for MerchantRecoveryAccount {}
+
+impl Serdes for MerchantRecoveryAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::MerchantRecovery as u8];
+}
+
+impl MerchantRecoveryAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<i64>()
+        + size_of::<i64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for MerchantTransferAccount
+impl Sealed for MerchantTransferAccount {}
+
+impl Serdes for MerchantTransferAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::MerchantTransfer as u8];
+}
+
+impl MerchantTransferAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for MerchantStatsAccount
+impl Sealed for MerchantStatsAccount {}
+
+impl Serdes for MerchantStatsAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::MerchantStats as u8];
+}
+
+impl MerchantStatsAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for SweepBountyAccount
+impl Sealed for SweepBountyAccount {}
+
+impl Serdes for SweepBountyAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::SweepBounty as u8];
+}
+
+impl SweepBountyAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for MigrationAccount
+impl Sealed for MigrationAccount {}
+
+impl Serdes for MigrationAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Migration as u8];
+}
+
+impl MigrationAccount {
+    pub const MIN_LEN: usize =
+        DISCRIMINATOR_LEN + size_of::<PublicKey>() + size_of::<u32>() + size_of::<u8>();
+}
+
+// impl for StoreAccount
+impl Sealed for StoreAccount {}
+
+impl Serdes for StoreAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Store as u8];
+}
+
+impl StoreAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for AcceptedMintsAccount
+impl Sealed for AcceptedMintsAccount {}
+
+impl Serdes for AcceptedMintsAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::AcceptedMints as u8];
+}
+
+impl AcceptedMintsAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<[PublicKey; MAX_ACCEPTED_MINTS]>()
+        + size_of::<u8>();
+}
+
+// impl for BlockedBuyerAccount
+impl Sealed for BlockedBuyerAccount {}
+
+impl Serdes for BlockedBuyerAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::BlockedBuyer as u8];
+}
+
+impl BlockedBuyerAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for ReferrerAccount
+impl Sealed for ReferrerAccount {}
+
+impl Serdes for ReferrerAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Referrer as u8];
+}
+
+impl ReferrerAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for PackageAccount
+impl Sealed for PackageAccount {}
+
+impl Serdes for PackageAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Package as u8];
+}
+
+impl PackageAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<bool>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for PriceOracleAccount
+impl Sealed for PriceOracleAccount {}
+
+impl Serdes for PriceOracleAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::PriceOracle as u8];
+}
+
+impl PriceOracleAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<i64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for BundleAccount
+impl Sealed for BundleAccount {}
+
+impl Serdes for BundleAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Bundle as u8];
+}
+
+impl BundleAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for BundleMemberAccount
+impl Sealed for BundleMemberAccount {}
+
+impl Serdes for BundleMemberAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::BundleMember as u8];
+}
+
+impl BundleMemberAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for OrderNotesAccount
+impl Sealed for OrderNotesAccount {}
+
+impl Serdes for OrderNotesAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::OrderNotes as u8];
+}
+
+impl OrderNotesAccount {
+    pub const MIN_LEN: usize =
+        DISCRIMINATOR_LEN + size_of::<PublicKey>() + size_of::<u64>() + size_of::<u8>();
+}
+
+// impl for OrderNoteAccount
+impl Sealed for OrderNoteAccount {}
+
+impl Serdes for OrderNoteAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::OrderNote as u8];
+}
+
+impl OrderNoteAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u8>();
+}
+
+// impl for SettlementAccount
+impl Sealed for SettlementAccount {}
+
+impl Serdes for SettlementAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Settlement as u8];
+}
+
+impl SettlementAccount {
+    pub const MIN_LEN: usize =
+        DISCRIMINATOR_LEN + size_of::<PublicKey>() + size_of::<PublicKey>() + size_of::<u8>();
+}
+
+// impl for ItemStockAccount
+impl Sealed for ItemStockAccount {}
+
+impl Serdes for ItemStockAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::ItemStock as u8];
+}
+
+impl ItemStockAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
+}
+
+// impl for CampaignAccount
+impl Sealed for CampaignAccount {}
+
+impl Serdes for CampaignAccount {
+    const DISCRIMINATORS: &'static [u8] = &[Discriminator::Campaign as u8];
+}
+
+impl CampaignAccount {
+    pub const MIN_LEN: usize = DISCRIMINATOR_LEN
+        + size_of::<PublicKey>()
+        + size_of::<PublicKey>()
+        + size_of::<UnixTimestamp>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u8>();
 }
 
 /// Check if a program account state is closed
@@ -161,5 +1628,73 @@ macro_rules! impl_IsClosed {
     }
 }
 
-impl_IsInitialized!(for MerchantAccount, OrderAccount, SubscriptionAccount);
-impl_IsClosed!(for MerchantAccount, OrderAccount, SubscriptionAccount);
+impl_IsInitialized!(
+    for ProgramConfigAccount,
+    MerchantAccount,
+    OrderAccount,
+    SubscriptionAccount,
+    IntegratorAccount,
+    AttestationAccount,
+    VoucherAccount,
+    CouponAccount,
+    PaymentLinkAccount,
+    LinkPayerAccount,
+    PriceGuaranteeAccount,
+    AutoDebitAccount,
+    FeeHistoryAccount,
+    FeeScheduleAccount,
+    CampaignAccount,
+    RefundCodeAccount,
+    MerchantRecoveryAccount,
+    ItemStockAccount,
+    PriceOracleAccount,
+    BundleAccount,
+    BundleMemberAccount,
+    SettlementAccount,
+    OrderNotesAccount,
+    OrderNoteAccount,
+    MerchantTransferAccount,
+    MerchantStatsAccount,
+    SweepBountyAccount,
+    MigrationAccount,
+    StoreAccount,
+    AcceptedMintsAccount,
+    BlockedBuyerAccount,
+    ReferrerAccount,
+    PackageAccount
+);
+impl_IsClosed!(
+    for ProgramConfigAccount,
+    MerchantAccount,
+    OrderAccount,
+    SubscriptionAccount,
+    IntegratorAccount,
+    AttestationAccount,
+    VoucherAccount,
+    CouponAccount,
+    PaymentLinkAccount,
+    LinkPayerAccount,
+    PriceGuaranteeAccount,
+    AutoDebitAccount,
+    FeeHistoryAccount,
+    FeeScheduleAccount,
+    CampaignAccount,
+    RefundCodeAccount,
+    MerchantRecoveryAccount,
+    ItemStockAccount,
+    PriceOracleAccount,
+    BundleAccount,
+    BundleMemberAccount,
+    SettlementAccount,
+    OrderNotesAccount,
+    OrderNoteAccount,
+    MerchantTransferAccount,
+    MerchantStatsAccount,
+    SweepBountyAccount,
+    MigrationAccount,
+    StoreAccount,
+    AcceptedMintsAccount,
+    BlockedBuyerAccount,
+    ReferrerAccount,
+    PackageAccount
+);
@@ -0,0 +1,125 @@
+//! Helpers shared by the tests that run the program in a test validator
+//!
+//! The instruction handlers read the time through the SysvarProvider of the
+//! processor, which reads the Clock sysvar of the test validator.  Tests of
+//! periods, trials and grace periods start the validator with a context and
+//! warp its clock forward instead of using packages that last 0 or 1 second.
+
+use crate::{engine::config::get_program_data_address, instruction::PaymentProcessorInstruction};
+use solana_program::{
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::{Clock, UnixTimestamp, DEFAULT_MS_PER_SLOT},
+    native_token::sol_to_lamports,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar,
+};
+use solana_program_test::{processor, BanksClient, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{keypair_from_seed, Keypair, Signer},
+};
+use std::str::FromStr;
+
+/// the address of the program in the test validator
+pub const TEST_PROGRAM_ID: &str = "mosh111111111111111111111111111111111111111";
+
+/// the compute units that a chain checkout is kept under, checked by the tests
+/// run with `cargo test-bpf` (see the README)
+#[cfg(feature = "test-bpf")]
+pub const CHAIN_CHECKOUT_COMPUTE_BUDGET: u64 = 150_000;
+
+/// the seed of the keypair that deployed the program in the test validator
+const TEST_UPGRADE_AUTHORITY_SEED: [u8; 32] = [7; 32];
+
+/// the most times that warp_seconds warps before giving up on the clock
+const MAX_WARPS: u32 = 16;
+
+/// Get the address of the program in the test validator
+pub fn get_test_program_id() -> Pubkey {
+    Pubkey::from_str(TEST_PROGRAM_ID).unwrap()
+}
+
+/// Get the upgrade authority of the program in the test validator, the only
+/// one that can initialize the program
+pub fn get_test_upgrade_authority() -> Keypair {
+    keypair_from_seed(&TEST_UPGRADE_AUTHORITY_SEED).unwrap()
+}
+
+/// Add the program data account of a program to a test validator, as if the
+/// test upgrade authority had deployed the program
+///
+/// The upgrade authority is funded too, since it pays for the program config
+/// account when it initializes the program.
+pub fn add_program_data(program_test: &mut ProgramTest, program_id: &Pubkey) {
+    let upgrade_authority = get_test_upgrade_authority().pubkey();
+    let data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+        slot: 0,
+        upgrade_authority_address: Some(upgrade_authority),
+    })
+    .unwrap();
+    program_test.add_account(
+        get_program_data_address(program_id),
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            ..Account::default()
+        },
+    );
+    program_test.add_account(
+        upgrade_authority,
+        Account {
+            lamports: sol_to_lamports(10.0),
+            ..Account::default()
+        },
+    );
+}
+
+/// Get a test validator running the program
+pub fn program_test() -> ProgramTest {
+    let program_id = get_test_program_id();
+    let mut program_test = ProgramTest::new(
+        "sol_payment_processor",
+        program_id,
+        processor!(PaymentProcessorInstruction::process),
+    );
+    add_program_data(&mut program_test, &program_id);
+    program_test
+}
+
+/// Get the unix timestamp of the Clock sysvar of the test validator
+pub async fn get_unix_timestamp(banks_client: &mut BanksClient) -> UnixTimestamp {
+    let clock_account = banks_client
+        .get_account(sysvar::clock::id())
+        .await
+        .unwrap()
+        .unwrap();
+    bincode::deserialize::<Clock>(&clock_account.data)
+        .unwrap()
+        .unix_timestamp
+}
+
+/// Warp the test validator until its clock has moved at least the given
+/// seconds forward
+///
+/// The clock follows the slots, so the validator is warped by the slots that
+/// the seconds left to go last, until the clock gets there.  The last blockhash
+/// of the context is refreshed so that the next transactions can be signed.
+/// Returns the new unix timestamp.
+pub async fn warp_seconds(context: &mut ProgramTestContext, seconds: i64) -> UnixTimestamp {
+    let mut timestamp = get_unix_timestamp(&mut context.banks_client).await;
+    let target = timestamp + seconds;
+    for _warp in 0..MAX_WARPS {
+        let milliseconds = (target - timestamp).max(1) as u64 * 1000;
+        let slots = milliseconds.div_ceil(DEFAULT_MS_PER_SLOT);
+        let slot = context.banks_client.get_root_slot().await.unwrap();
+        context.warp_to_slot(slot + slots).unwrap();
+        timestamp = get_unix_timestamp(&mut context.banks_client).await;
+        if timestamp >= target {
+            context.last_blockhash = context.banks_client.get_recent_blockhash().await.unwrap();
+            return timestamp;
+        }
+    }
+    panic!("The clock of the test validator did not reach {:?}", target);
+}
@@ -1,23 +1,133 @@
-use crate::engine::constants::STRING_SIZE;
-use crate::state::{MerchantAccount, OrderAccount, SubscriptionAccount};
+use crate::engine::config::get_fee_tiers;
+use crate::engine::constants::{BASIS_POINTS, INTEGRATOR_FEE, STRING_SIZE};
+use crate::error::PaymentProcessorError;
+use crate::state::{
+    BundleAccount, BundleMemberAccount, CampaignAccount, CatalogItem, CouponAccount,
+    IntegratorAccount, ItemStockAccount, MerchantAccount, MigrationAccount, OrderAccount,
+    OrderLineItem,
+    OrderNoteAccount, PackageAccount, PaymentLinkAccount, PriceGuaranteeAccount,
+    ProgramConfigAccount, StoreAccount, SubscriptionAccount, SubscriptionPackage,
+};
+use borsh::BorshSerialize;
+use solana_program::{clock::UnixTimestamp, program_error::ProgramError};
+use std::{convert::TryFrom, mem::size_of};
 
 /// Given the expected amount, calculate the fee and take home amount
 /// Currently fee is 0.3% with a minimum fee of 1 lamport
 /// If the amount is less than 100 lamports the fee is 0
-pub fn get_amounts(amount: u64, fee_percentage: u128) -> (u64, u64) {
+/// A fee larger than the amount fails with AmountOverflow
+pub fn get_amounts(amount: u64, fee_percentage: u128) -> Result<(u64, u64), ProgramError> {
     let mut fee_amount: u64 = 0;
     let mut take_home_amount: u64 = amount;
 
     if amount >= 100 {
-        let possible_fee_amount: u128 = (amount as u128 * fee_percentage) / 1000;
+        let possible_fee_amount: u128 = (amount as u128)
+            .checked_mul(fee_percentage)
+            .ok_or(PaymentProcessorError::AmountOverflow)?
+            / 1000;
         fee_amount = 1;
         if possible_fee_amount > 0 {
-            fee_amount = possible_fee_amount as u64;
+            fee_amount = u64::try_from(possible_fee_amount)
+                .map_err(|_error| PaymentProcessorError::AmountOverflow)?;
         }
-        take_home_amount = amount - fee_amount;
+        take_home_amount = amount
+            .checked_sub(fee_amount)
+            .ok_or(PaymentProcessorError::AmountOverflow)?;
     }
 
-    (take_home_amount, fee_amount)
+    Ok((take_home_amount, fee_amount))
+}
+
+/// Get the smallest and largest amounts accepted for a price
+///
+/// The tolerance (in basis points) of the price is allowed either way.  Amounts
+/// out of range fail with AmountOverflow.
+pub fn get_tolerated_range(price: u64, tolerance: u64) -> Result<(u64, u64), ProgramError> {
+    let allowance = u64::try_from((price as u128 * tolerance as u128) / BASIS_POINTS as u128)
+        .map_err(|_error| PaymentProcessorError::AmountOverflow)?;
+    let min_amount = price
+        .checked_sub(allowance)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    let max_amount = price
+        .checked_add(allowance)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    Ok((min_amount, max_amount))
+}
+
+/// Take a discount off the amount paid and the expected amount of an order
+///
+/// A discount larger than either of them fails with AmountOverflow.
+pub fn get_discounted_amounts(
+    amount: u64,
+    expected_amount: u64,
+    discount: u64,
+) -> Result<(u64, u64), ProgramError> {
+    let amount = amount
+        .checked_sub(discount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    let expected_amount = expected_amount
+        .checked_sub(discount)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    Ok((amount, expected_amount))
+}
+
+/// Get what renewing a subscription for quantity periods adds to it
+///
+/// Returns the seconds added to the subscription period and the amount due,
+/// the price being per seat.  The quantity has to be at least 1, and amounts or
+/// periods out of range fail with AmountOverflow rather than wrapping around.
+pub fn get_renewal(
+    duration: UnixTimestamp,
+    price: u64,
+    seats: u64,
+    quantity: i64,
+) -> Result<(UnixTimestamp, u64), ProgramError> {
+    if quantity < 1 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let seconds = duration
+        .checked_mul(quantity)
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    let amount = price
+        .checked_mul(seats)
+        .and_then(|value| value.checked_mul(quantity as u64))
+        .ok_or(PaymentProcessorError::AmountOverflow)?;
+    Ok((seconds, amount))
+}
+
+/// Get the shares of the transaction fee
+///
+/// Returns the program owner, sponsor and integrator fees.  This is what the
+/// program charges for a payment, so quotes made off-chain (see fee_preview)
+/// go through here as well.
+///
+/// Merchants whose lifetime volume reached a fee tier of the program config are
+/// charged the fee of the highest such tier, unless their own fee is lower.
+pub fn get_fees(
+    merchant_account: &MerchantAccount,
+    config_account: &ProgramConfigAccount,
+    with_integrator: bool,
+) -> Result<(u64, u64, u64), ProgramError> {
+    let mut fee = merchant_account.fee;
+    if let Some(fee_tier) = get_fee_tiers(&config_account.data)
+        .unwrap_or_default()
+        .iter()
+        .filter(|fee_tier| merchant_account.lifetime_volume >= fee_tier.threshold)
+        .max_by_key(|fee_tier| fee_tier.threshold)
+    {
+        fee = fee.min(fee_tier.fee);
+    }
+    let mut integrator_fee = 0;
+    if with_integrator {
+        let (remaining_fee, value) = get_amounts(fee, INTEGRATOR_FEE)?;
+        fee = remaining_fee;
+        integrator_fee = value;
+    }
+    if merchant_account.sponsor == config_account.owner {
+        return Ok((fee, 0, integrator_fee));
+    }
+    let (program_owner_fee, sponsor_fee) = get_amounts(fee, merchant_account.sponsor_fee as u128)?;
+    Ok((program_owner_fee, sponsor_fee, integrator_fee))
 }
 
 pub fn get_account_size(min_len: usize, strings: &Vec<&String>) -> usize {
@@ -34,38 +144,319 @@ pub fn get_order_account_size(order_id: &String, secret: &String, data: &String)
     get_account_size(OrderAccount::MIN_LEN, &vec![order_id, secret, data])
 }
 
+/// get the size that the items bought in a chain checkout add to an order account
+///
+/// The length of the items is already counted in OrderAccount::MIN_LEN
+pub fn get_order_items_size(items: &Vec<OrderLineItem>) -> usize {
+    items.try_to_vec().unwrap().len() - size_of::<u32>()
+}
+
 /// get merchant account size
 pub fn get_merchant_account_size(data: &String) -> usize {
     get_account_size(MerchantAccount::MIN_LEN, &vec![data])
 }
 
+/// get the size that the catalog, packages and metadata add to a merchant account
+///
+/// Their lengths are already counted in MerchantAccount::MIN_LEN
+pub fn get_merchant_terms_size(
+    catalog: &[CatalogItem],
+    packages: &[SubscriptionPackage],
+    metadata: &[u8],
+) -> usize {
+    catalog.try_to_vec().unwrap().len() + packages.try_to_vec().unwrap().len() + metadata.len()
+        - 2 * size_of::<u32>()
+}
+
 /// get subscription account size
 pub fn get_subscription_account_size(name: &String, data: &String) -> usize {
     get_account_size(SubscriptionAccount::MIN_LEN, &vec![name, data])
 }
 
+/// get program config account size
+pub fn get_program_config_account_size(name: &String, data: &String) -> usize {
+    get_account_size(ProgramConfigAccount::MIN_LEN, &vec![name, data])
+}
+
+/// get integrator account size
+pub fn get_integrator_account_size(app_id: &String) -> usize {
+    get_account_size(IntegratorAccount::MIN_LEN, &vec![app_id])
+}
+
+/// get coupon account size
+pub fn get_coupon_account_size(code: &String) -> usize {
+    get_account_size(CouponAccount::MIN_LEN, &vec![code])
+}
+
+/// get payment link account size
+pub fn get_payment_link_account_size(link_id: &String, data: &String) -> usize {
+    get_account_size(PaymentLinkAccount::MIN_LEN, &vec![link_id, data])
+}
+
+/// get price guarantee account size
+pub fn get_price_guarantee_account_size(package: &String) -> usize {
+    get_account_size(PriceGuaranteeAccount::MIN_LEN, &vec![package])
+}
+
+/// get package account size
+pub fn get_package_account_size(name: &String, data: &String) -> usize {
+    get_account_size(PackageAccount::MIN_LEN, &vec![name, data])
+}
+
+/// get campaign account size
+pub fn get_campaign_account_size(campaign_id: &String, data: &String) -> usize {
+    get_account_size(CampaignAccount::MIN_LEN, &vec![campaign_id, data])
+}
+
+/// get item stock account size
+pub fn get_item_stock_account_size(item: &String) -> usize {
+    get_account_size(ItemStockAccount::MIN_LEN, &vec![item])
+}
+
+/// get bundle account size
+pub fn get_bundle_account_size(bundle_id: &String) -> usize {
+    get_account_size(BundleAccount::MIN_LEN, &vec![bundle_id])
+}
+
+/// get bundle member account size
+pub fn get_bundle_member_account_size(package: &String) -> usize {
+    get_account_size(BundleMemberAccount::MIN_LEN, &vec![package])
+}
+
+/// get order note account size
+pub fn get_order_note_account_size(note: &String) -> usize {
+    get_account_size(OrderNoteAccount::MIN_LEN, &vec![note])
+}
+
+/// get store account size
+pub fn get_store_account_size(store_id: &String, catalog: &String, data: &String) -> usize {
+    get_account_size(StoreAccount::MIN_LEN, &vec![store_id, catalog, data])
+}
+
+/// get migration account size
+///
+/// The length of the data is already counted in MigrationAccount::MIN_LEN
+pub fn get_migration_account_size(data: &[u8]) -> usize {
+    MigrationAccount::MIN_LEN + data.len()
+}
+
 #[cfg(test)]
 mod test {
-    use {super::*, solana_program_test::*};
+    use {
+        super::*, crate::engine::constants::ACCOUNT_VERSION, crate::engine::token::get_net_amounts,
+        crate::state::Serdes, proptest::prelude::*, solana_program_test::*,
+    };
 
     #[tokio::test]
     async fn test_get_amounts() {
-        assert_eq!((997000000, 3000000), get_amounts(1000000000, 3));
-        assert_eq!((1994000, 6000), get_amounts(2000000, 3));
-        assert_eq!((1994, 6), get_amounts(2000, 3));
-        assert_eq!((100, 1), get_amounts(101, 3));
-        assert_eq!((99, 1), get_amounts(100, 3));
-        assert_eq!((99, 0), get_amounts(99, 3));
-        assert_eq!((80, 0), get_amounts(80, 3));
-        assert_eq!((0, 0), get_amounts(0, 3));
-        assert_eq!((990, 10), get_amounts(1000, 10));
-        assert_eq!((996, 4), get_amounts(1000, 4));
+        assert_eq!(Ok((997000000, 3000000)), get_amounts(1000000000, 3));
+        assert_eq!(Ok((1994000, 6000)), get_amounts(2000000, 3));
+        assert_eq!(Ok((1994, 6)), get_amounts(2000, 3));
+        assert_eq!(Ok((100, 1)), get_amounts(101, 3));
+        assert_eq!(Ok((99, 1)), get_amounts(100, 3));
+        assert_eq!(Ok((99, 0)), get_amounts(99, 3));
+        assert_eq!(Ok((80, 0)), get_amounts(80, 3));
+        assert_eq!(Ok((0, 0)), get_amounts(0, 3));
+        assert_eq!(Ok((990, 10)), get_amounts(1000, 10));
+        assert_eq!(Ok((996, 4)), get_amounts(1000, 4));
+    }
+
+    #[tokio::test]
+    async fn test_get_amounts_extreme_values() {
+        let amounts = [0, 99, 100, 101, u64::MAX / 1000, u64::MAX - 1, u64::MAX];
+        let fee_percentages = [0, 1, 3, 999, 1000];
+        for amount in amounts.iter() {
+            for fee_percentage in fee_percentages.iter() {
+                // the fee and the take home amount always add up to the amount
+                let (take_home_amount, fee_amount) = get_amounts(*amount, *fee_percentage).unwrap();
+                assert_eq!(
+                    *amount as u128,
+                    take_home_amount as u128 + fee_amount as u128
+                );
+            }
+        }
+        assert_eq!(Ok((0, u64::MAX)), get_amounts(u64::MAX, 1000));
+        // a fee larger than the amount is out of range
+        let overflow = Err(PaymentProcessorError::AmountOverflow.into());
+        assert_eq!(overflow, get_amounts(1000, 1001));
+        assert_eq!(overflow, get_amounts(u64::MAX, u64::MAX as u128));
+        assert_eq!(overflow, get_amounts(u64::MAX, u128::MAX));
+        assert_eq!(Ok((99, 0)), get_amounts(99, u128::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_get_tolerated_range() {
+        assert_eq!(Ok((1000, 1000)), get_tolerated_range(1000, 0));
+        assert_eq!(Ok((990, 1010)), get_tolerated_range(1000, 100));
+        assert_eq!(Ok((0, 2000)), get_tolerated_range(1000, BASIS_POINTS));
+        assert_eq!(Ok((u64::MAX, u64::MAX)), get_tolerated_range(u64::MAX, 0));
+        assert_eq!(Ok((0, 0)), get_tolerated_range(0, u64::MAX));
+        // the allowance cannot take the range past either end of a u64
+        let overflow = Err(PaymentProcessorError::AmountOverflow.into());
+        assert_eq!(overflow, get_tolerated_range(u64::MAX, 1));
+        assert_eq!(overflow, get_tolerated_range(1000, 2 * BASIS_POINTS));
+        assert_eq!(overflow, get_tolerated_range(u64::MAX, u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_get_discounted_amounts() {
+        assert_eq!(Ok((800, 1800)), get_discounted_amounts(1000, 2000, 200));
+        assert_eq!(Ok((0, 1000)), get_discounted_amounts(1000, 2000, 1000));
+        assert_eq!(Ok((1000, 2000)), get_discounted_amounts(1000, 2000, 0));
+        assert_eq!(
+            Ok((0, 0)),
+            get_discounted_amounts(u64::MAX, u64::MAX, u64::MAX)
+        );
+        // a discount cannot be larger than what it is taken off
+        let overflow = Err(PaymentProcessorError::AmountOverflow.into());
+        assert_eq!(overflow, get_discounted_amounts(1000, 2000, 1001));
+        assert_eq!(overflow, get_discounted_amounts(1000, 500, 600));
+        assert_eq!(overflow, get_discounted_amounts(0, 0, u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_get_net_amounts() {
+        // nothing withheld
+        assert_eq!(Ok((1000, 50, 0)), get_net_amounts(1000, 50, 1050));
+        // the transfer fee comes off the amount before the tip
+        assert_eq!(Ok((990, 50, 10)), get_net_amounts(1000, 50, 1040));
+        assert_eq!(Ok((0, 40, 1010)), get_net_amounts(1000, 50, 40));
+        assert_eq!(
+            Ok((u64::MAX - 1, 1, 0)),
+            get_net_amounts(u64::MAX - 1, 1, u64::MAX)
+        );
+        // a tip that takes the payment past u64::MAX does not wrap around
+        let overflow = Err(PaymentProcessorError::AmountOverflow.into());
+        assert_eq!(overflow, get_net_amounts(1000, u64::MAX - 999, 0));
+        assert_eq!(overflow, get_net_amounts(u64::MAX, u64::MAX, u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_get_renewal() {
+        assert_eq!(Ok((60, 2000)), get_renewal(60, 1000, 2, 1));
+        assert_eq!(Ok((180, 6000)), get_renewal(60, 1000, 2, 3));
+        assert_eq!(Ok((60, 0)), get_renewal(60, 0, 2, 1));
+        // the quantity has to be at least 1
+        for quantity in [0, -1, -2, i64::MIN].iter() {
+            assert_eq!(
+                Err(ProgramError::InvalidInstructionData),
+                get_renewal(60, 1000, 1, *quantity)
+            );
+        }
+        let overflow = Err(PaymentProcessorError::AmountOverflow.into());
+        assert_eq!(overflow, get_renewal(i64::MAX, 1, 1, 2));
+        assert_eq!(overflow, get_renewal(60, u64::MAX, 2, 1));
+        assert_eq!(overflow, get_renewal(60, u64::MAX / 2, 2, 2));
+        assert_eq!(overflow, get_renewal(60, 2, 1, i64::MAX));
+        assert_eq!(
+            Ok((i64::MAX, i64::MAX as u64)),
+            get_renewal(1, 1, 1, i64::MAX)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_get_amounts_properties(amount in any::<u64>(), fee_percentage in any::<u128>()) {
+            let fee_in_range = amount < 100
+                || (amount as u128)
+                    .checked_mul(fee_percentage)
+                    .is_some_and(|value| value / 1000 <= amount as u128);
+            match get_amounts(amount, fee_percentage) {
+                // the fee and the take home amount always add up to the amount
+                Ok((take_home_amount, fee_amount)) => {
+                    prop_assert!(fee_in_range);
+                    prop_assert_eq!(
+                        amount as u128,
+                        take_home_amount as u128 + fee_amount as u128
+                    );
+                }
+                Err(error) => {
+                    prop_assert!(!fee_in_range);
+                    prop_assert_eq!(ProgramError::from(PaymentProcessorError::AmountOverflow), error);
+                }
+            }
+        }
+
+        #[test]
+        fn test_get_tolerated_range_properties(price in any::<u64>(), tolerance in any::<u64>()) {
+            let allowance = (price as u128 * tolerance as u128) / BASIS_POINTS as u128;
+            let min_amount = (price as u128).checked_sub(allowance);
+            let max_amount = price as u128 + allowance;
+            match get_tolerated_range(price, tolerance) {
+                Ok((min, max)) => {
+                    prop_assert_eq!(min_amount, Some(min as u128));
+                    prop_assert_eq!(max_amount, max as u128);
+                }
+                Err(_error) => {
+                    prop_assert!(min_amount.is_none() || max_amount > u64::MAX as u128);
+                }
+            }
+        }
+
+        #[test]
+        fn test_get_discounted_amounts_properties(
+            amount in any::<u64>(),
+            expected_amount in any::<u64>(),
+            discount in any::<u64>(),
+        ) {
+            match get_discounted_amounts(amount, expected_amount, discount) {
+                Ok((discounted_amount, discounted_expected_amount)) => {
+                    prop_assert_eq!(amount, discounted_amount + discount);
+                    prop_assert_eq!(expected_amount, discounted_expected_amount + discount);
+                }
+                Err(_error) => prop_assert!(discount > amount || discount > expected_amount),
+            }
+        }
+
+        #[test]
+        fn test_get_renewal_properties(
+            duration in any::<i64>(),
+            price in any::<u64>(),
+            seats in any::<u64>(),
+            quantity in any::<i64>(),
+        ) {
+            let seconds = duration as i128 * quantity as i128;
+            let amount = (price as u128 * seats as u128).checked_mul(quantity.max(0) as u128);
+            match get_renewal(duration, price, seats, quantity) {
+                Ok((renewal_seconds, renewal_amount)) => {
+                    prop_assert!(quantity >= 1);
+                    prop_assert_eq!(seconds, renewal_seconds as i128);
+                    prop_assert_eq!(amount, Some(renewal_amount as u128));
+                }
+                Err(_error) => prop_assert!(
+                    quantity < 1
+                        || seconds > i64::MAX as i128
+                        || seconds < i64::MIN as i128
+                        || amount.is_none_or(|value| value > u64::MAX as u128)
+                ),
+            }
+        }
+
+        #[test]
+        fn test_get_net_amounts_properties(
+            amount in any::<u64>(),
+            tip in any::<u64>(),
+            received in any::<u64>(),
+        ) {
+            match get_net_amounts(amount, tip, received) {
+                // what was received is split between the payment and the tip
+                Ok((net_amount, net_tip, transfer_fee)) => {
+                    prop_assert_eq!(received as u128, net_amount as u128 + net_tip as u128);
+                    prop_assert!(net_amount <= amount);
+                    prop_assert_eq!(
+                        (amount as u128 + tip as u128).saturating_sub(received as u128),
+                        transfer_fee as u128
+                    );
+                }
+                Err(_error) => prop_assert!(amount.checked_add(tip).is_none()),
+            }
+        }
     }
 
     #[tokio::test]
     async fn test_get_order_account_size() {
         assert_eq!(
-            198,
+            266,
             get_order_account_size(
                 &String::from("123456"),
                 &String::from("password"),
@@ -73,21 +464,88 @@ mod test {
             )
         );
         assert_eq!(
-            190,
+            258,
             get_order_account_size(
                 &String::from("test-6"),
                 &String::from(""),
                 &String::from(r#"{"a": "b"}"#)
             )
         );
-        assert_eq!(423, get_order_account_size(&String::from("WSUDUBDG2"), &String::from("Lorem Ipsum is simply dummy text of the printing and typesetting industry. Lorem Ipsum has been the industry's standard dummy text ever since the 1500s, when an unknown printer took a galley of type and scrambled it to make a type"), &String::from(r#"{"a": "b"}"#)));
+        assert_eq!(491, get_order_account_size(&String::from("WSUDUBDG2"), &String::from("Lorem Ipsum is simply dummy text of the printing and typesetting industry. Lorem Ipsum has been the industry's standard dummy text ever since the 1500s, when an unknown printer took a galley of type and scrambled it to make a type"), &String::from(r#"{"a": "b"}"#)));
+    }
+
+    #[tokio::test]
+    async fn test_get_order_items_size() {
+        assert_eq!(0, get_order_items_size(&vec![]));
+        assert_eq!(
+            30,
+            get_order_items_size(&vec![
+                OrderLineItem {
+                    name: String::from("1"),
+                    quantity: 2,
+                },
+                OrderLineItem {
+                    name: String::from("shoes"),
+                    quantity: 1,
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fixed_fields() {
+        assert_eq!(8, MerchantAccount::OWNER.offset);
+        assert_eq!(25, OrderAccount::MERCHANT.offset);
+        assert_eq!(161, OrderAccount::PAID_AMOUNT.offset);
+        assert_eq!(154, OrderAccount::PAID_AMOUNT.legacy_offset());
+        let order_account = OrderAccount {
+            discriminator: 20,
+            status: 1,
+            created: 1,
+            modified: -2,
+            merchant: [1; 32],
+            mint: [2; 32],
+            token: [3; 32],
+            payer: [4; 32],
+            expected_amount: 5,
+            paid_amount: 6,
+            tip: 7,
+            transfer_fee: 8,
+            withdrawn_amount: 9,
+            sequence: 0,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("123456"),
+            secret: String::new(),
+            data: String::new(),
+            version: ACCOUNT_VERSION,
+        };
+        let mut data = order_account.to_account_data();
+        assert_eq!(1, OrderAccount::STATUS.get(&data).unwrap());
+        assert_eq!(-2, OrderAccount::MODIFIED.get(&data).unwrap());
+        assert_eq!(6, OrderAccount::PAID_AMOUNT.get(&data).unwrap());
+        OrderAccount::STATUS.set(&mut data, 2).unwrap();
+        OrderAccount::PAID_AMOUNT.set(&mut data, 13).unwrap();
+        let updated = OrderAccount::unpack(&data).unwrap();
+        assert_eq!(
+            (2, 13, 7, 9),
+            (
+                updated.status,
+                updated.paid_amount,
+                updated.tip,
+                updated.withdrawn_amount
+            )
+        );
+        assert!(OrderAccount::PAID_AMOUNT.get(&data[..167]).is_err());
     }
 
     #[tokio::test]
     async fn test_get_merchant_account_size() {
-        assert_eq!(79, get_merchant_account_size(&String::from("{}")));
+        assert_eq!(439, get_merchant_account_size(&String::from("{}")));
         assert_eq!(
-            168,
+            528,
             get_merchant_account_size(&String::from(
                 r#"{"code":200,"success":true,"payload":{"features":["awesome","easyAPI","lowLearningCurve"]}}"#
             ))
@@ -97,15 +555,89 @@ mod test {
     #[tokio::test]
     async fn test_get_subscription_account_size() {
         assert_eq!(
-            100,
+            204,
             get_subscription_account_size(&String::from("a"), &String::from("b"))
         );
         assert_eq!(
-            132,
+            236,
             get_subscription_account_size(
                 &String::from("Annual"),
                 &String::from(r#"{"foo": "bar", "price": 200}"#)
             )
         );
     }
+
+    #[tokio::test]
+    async fn test_get_program_config_account_size() {
+        assert_eq!(
+            127,
+            get_program_config_account_size(&String::from("SolPayments"), &String::from("{}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_integrator_account_size() {
+        assert_eq!(
+            59,
+            get_integrator_account_size(&String::from("phantom-wallet"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_coupon_account_size() {
+        assert_eq!(110, get_coupon_account_size(&String::from("SUMMER10")));
+    }
+
+    #[tokio::test]
+    async fn test_get_payment_link_account_size() {
+        assert_eq!(
+            138,
+            get_payment_link_account_size(&String::from("twitter"), &String::from("{}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_price_guarantee_account_size() {
+        assert_eq!(74, get_price_guarantee_account_size(&String::from("basic")));
+    }
+
+    #[tokio::test]
+    async fn test_get_package_account_size() {
+        assert_eq!(
+            65,
+            get_package_account_size(&String::from("basic"), &String::from("{}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_campaign_account_size() {
+        assert_eq!(
+            121,
+            get_campaign_account_size(&String::from("relief"), &String::from("{}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_item_stock_account_size() {
+        assert_eq!(62, get_item_stock_account_size(&String::from("1")));
+    }
+
+    #[tokio::test]
+    async fn test_get_bundle_account_sizes() {
+        assert_eq!(70, get_bundle_account_size(&String::from("1")));
+        assert_eq!(86, get_bundle_member_account_size(&String::from("1")));
+    }
+
+    #[tokio::test]
+    async fn test_get_order_note_account_size() {
+        assert_eq!(94, get_order_note_account_size(&String::from("a")));
+    }
+
+    #[tokio::test]
+    async fn test_get_store_account_size() {
+        assert_eq!(
+            96,
+            get_store_account_size(&String::from("1"), &String::from(""), &String::from("{}"))
+        );
+    }
 }
@@ -0,0 +1,195 @@
+//! Off-chain verification of payments
+//!
+//! Meant to be used by the backends of web shops once the buyer's wallet has sent
+//! the checkout: the backend fetches the order account and checks that it pays
+//! the order that the shop issued, in full.  The backend should also check that
+//! the order account is owned by the program, since anyone can create an account
+//! with the same data.
+
+use crate::state::{OrderAccount, OrderStatus, Serdes};
+use solana_program::pubkey::Pubkey;
+
+#[derive(Clone, Debug, PartialEq)]
+/// What an order account says about the payment of an order
+pub enum PaymentVerdict {
+    /// the order is paid in full (and may since have been withdrawn)
+    Paid,
+    /// the order is not paid in full yet - paid is what the order account
+    /// received, including the part withheld by the mint as a transfer fee
+    Underpaid { paid: u64, expected: u64 },
+    /// the order is paid in another mint than the one the shop prices it in
+    WrongMint { mint: Pubkey },
+    /// the payment was reversed: the order was cancelled, expired or refunded
+    Reversed { status: u8 },
+    /// the order account is not an order account, or is for another merchant,
+    /// order id or secret than the order that the shop issued
+    Tampered,
+}
+
+/// Verify that an order account pays an order issued by the shop
+///
+/// The checkout must have been made for the merchant, in the mint and for the
+/// order id and secret of the order, and the order account must have received
+/// at least the expected amount.  Orders whose amount was lowered on-chain (e.g.
+/// by a coupon) are reported as underpaid.
+pub fn verify_payment(
+    order_account_data: &[u8],
+    expected_merchant: &Pubkey,
+    expected_mint: &Pubkey,
+    expected_amount: u64,
+    expected_order_id: &str,
+    secret: &str,
+) -> PaymentVerdict {
+    let order_account = match OrderAccount::unpack(order_account_data) {
+        Err(_error) => return PaymentVerdict::Tampered,
+        Ok(value) => value,
+    };
+    if order_account.merchant != expected_merchant.to_bytes()
+        || order_account.order_id != expected_order_id
+        || order_account.secret != secret
+    {
+        return PaymentVerdict::Tampered;
+    }
+    if order_account.mint != expected_mint.to_bytes() {
+        return PaymentVerdict::WrongMint {
+            mint: Pubkey::new_from_array(order_account.mint),
+        };
+    }
+    let status = order_account.status;
+    if status == OrderStatus::Uninitialized as u8
+        || status == OrderStatus::Cancelled as u8
+        || status == OrderStatus::Expired as u8
+        || status == OrderStatus::Refunded as u8
+    {
+        return PaymentVerdict::Reversed { status };
+    }
+    let paid = order_account
+        .paid_amount
+        .saturating_add(order_account.transfer_fee);
+    if status == OrderStatus::Pending as u8
+        || order_account.expected_amount < expected_amount
+        || paid < expected_amount
+    {
+        return PaymentVerdict::Underpaid {
+            paid,
+            expected: expected_amount,
+        };
+    }
+    PaymentVerdict::Paid
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{engine::constants::ACCOUNT_VERSION, state::Discriminator},
+        solana_program_test::*,
+    };
+
+    fn order(merchant: &Pubkey, mint: &Pubkey, amount: u64) -> OrderAccount {
+        OrderAccount {
+            discriminator: Discriminator::OrderExpressCheckout as u8,
+            status: OrderStatus::Paid as u8,
+            created: 1,
+            modified: 1,
+            merchant: merchant.to_bytes(),
+            mint: mint.to_bytes(),
+            token: Pubkey::new_unique().to_bytes(),
+            payer: Pubkey::new_unique().to_bytes(),
+            expected_amount: amount,
+            paid_amount: amount,
+            tip: 0,
+            transfer_fee: 0,
+            withdrawn_amount: 0,
+            sequence: 1,
+            items: vec![],
+            released_amount: 0,
+            created_slot: 0,
+            sweep_bounty: 0,
+            order_id: String::from("order-1"),
+            secret: String::from("hunter2"),
+            data: String::from("{}"),
+            version: ACCOUNT_VERSION,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_payment() {
+        let merchant = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let verify = |order_account: &OrderAccount| {
+            verify_payment(
+                &order_account.to_account_data(),
+                &merchant,
+                &mint,
+                1000,
+                "order-1",
+                "hunter2",
+            )
+        };
+
+        let mut order_account = order(&merchant, &mint, 1000);
+        assert_eq!(PaymentVerdict::Paid, verify(&order_account));
+        // withdrawn orders were paid
+        order_account.status = OrderStatus::Withdrawn as u8;
+        assert_eq!(PaymentVerdict::Paid, verify(&order_account));
+        // the part withheld as a transfer fee counts towards the amount
+        order_account.paid_amount = 990;
+        order_account.transfer_fee = 10;
+        assert_eq!(PaymentVerdict::Paid, verify(&order_account));
+
+        // a checkout for less than the order amount
+        let order_account = order(&merchant, &mint, 900);
+        assert_eq!(
+            PaymentVerdict::Underpaid {
+                paid: 900,
+                expected: 1000
+            },
+            verify(&order_account)
+        );
+        let mut order_account = order(&merchant, &mint, 1000);
+        order_account.status = OrderStatus::Pending as u8;
+        order_account.paid_amount = 0;
+        assert_eq!(
+            PaymentVerdict::Underpaid {
+                paid: 0,
+                expected: 1000
+            },
+            verify(&order_account)
+        );
+
+        let other_mint = Pubkey::new_unique();
+        assert_eq!(
+            PaymentVerdict::WrongMint { mint: other_mint },
+            verify(&order(&merchant, &other_mint, 1000))
+        );
+
+        let mut order_account = order(&merchant, &mint, 1000);
+        order_account.status = OrderStatus::Refunded as u8;
+        assert_eq!(
+            PaymentVerdict::Reversed {
+                status: OrderStatus::Refunded as u8
+            },
+            verify(&order_account)
+        );
+
+        // another merchant, order id or secret
+        assert_eq!(
+            PaymentVerdict::Tampered,
+            verify(&order(&Pubkey::new_unique(), &mint, 1000))
+        );
+        let mut order_account = order(&merchant, &mint, 1000);
+        order_account.order_id = String::from("order-2");
+        assert_eq!(PaymentVerdict::Tampered, verify(&order_account));
+        let mut order_account = order(&merchant, &mint, 1000);
+        order_account.secret = String::from("");
+        assert_eq!(PaymentVerdict::Tampered, verify(&order_account));
+        // not an order account
+        let mut data = order(&merchant, &mint, 1000).to_account_data();
+        data[0] = Discriminator::Subscription as u8;
+        assert_eq!(
+            PaymentVerdict::Tampered,
+            verify_payment(&data, &merchant, &mint, 1000, "order-1", "hunter2")
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Webhooks of the events of the program
+//!
+//! Meant to be used by webhook relays (see the `solpay-webhooks` binary), which
+//! follow the transaction logs of the program, decode its events (see
+//! crate::events) and POST them as JSON to the URL that each merchant configured.
+//! The body of a webhook is signed with the secret of the merchant using
+//! HMAC-SHA256, so that the merchant can check that it comes from its relay.
+//!
+//! The events relayed are `order.paid` (OrderPaid), `subscription.renewed`
+//! (Renewed) and `order.refunded` (Refunded).
+
+use crate::events::PaymentProcessorEvent;
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+/// the header that holds the signature of the body of a webhook
+pub const SIGNATURE_HEADER: &str = "X-SolPayments-Signature";
+/// the header that holds the type of a webhook e.g. order.paid
+pub const EVENT_HEADER: &str = "X-SolPayments-Event";
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+/// Where the webhooks of a merchant are sent
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// the secret that the webhooks are signed with, shared with the merchant
+    pub secret: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+/// The endpoints of the merchants, by merchant account e.g.
+/// {"merchants": {"<merchant account>": {"url": "https://...", "secret": "..."}}}
+pub struct WebhookConfig {
+    pub merchants: BTreeMap<String, WebhookEndpoint>,
+}
+
+impl WebhookConfig {
+    pub fn get_endpoint(&self, merchant: &Pubkey) -> Option<&WebhookEndpoint> {
+        self.merchants.get(&merchant.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// The JSON body of a webhook
+pub struct Webhook {
+    /// unique per event, so that merchants can ignore the deliveries that were
+    /// retried after they had been received
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// the signature of the transaction that logged the event
+    pub transaction: String,
+    pub data: Value,
+}
+
+/// Get the account that the merchant of an event is read from, for the events
+/// that are relayed but do not name their merchant: the subscription of a
+/// renewal and the order of a refund
+pub fn get_event_account(event: &PaymentProcessorEvent) -> Option<Pubkey> {
+    match event {
+        PaymentProcessorEvent::Renewed { subscription, .. } => {
+            Some(Pubkey::new_from_array(*subscription))
+        }
+        PaymentProcessorEvent::Refunded { order, .. } => Some(Pubkey::new_from_array(*order)),
+        _ => Option::None,
+    }
+}
+
+/// Get the webhook of an event of a merchant, if the event is relayed
+///
+/// index is the position of the event among the events of the transaction.
+pub fn get_webhook(
+    event: &PaymentProcessorEvent,
+    merchant: &Pubkey,
+    transaction: &str,
+    index: usize,
+) -> Option<Webhook> {
+    let key = |value: &[u8; 32]| Pubkey::new_from_array(*value).to_string();
+    let (event_type, data) = match event {
+        PaymentProcessorEvent::OrderPaid {
+            order,
+            payer,
+            mint,
+            amount,
+            tip,
+            ..
+        } => (
+            "order.paid",
+            json!({
+                "order": key(order),
+                "merchant": merchant.to_string(),
+                "payer": key(payer),
+                "mint": key(mint),
+                "amount": amount,
+                "tip": tip,
+            }),
+        ),
+        PaymentProcessorEvent::Renewed {
+            subscription,
+            period_end,
+        } => (
+            "subscription.renewed",
+            json!({
+                "subscription": key(subscription),
+                "merchant": merchant.to_string(),
+                "period_end": period_end,
+            }),
+        ),
+        PaymentProcessorEvent::Refunded { order, amount } => (
+            "order.refunded",
+            json!({
+                "order": key(order),
+                "merchant": merchant.to_string(),
+                "amount": amount,
+            }),
+        ),
+        _ => return Option::None,
+    };
+    Some(Webhook {
+        id: format!("{}:{}", transaction, index),
+        event_type: String::from(event_type),
+        transaction: String::from(transaction),
+        data,
+    })
+}
+
+/// Sign the body of a webhook with the secret of the merchant
+///
+/// Returns the value of the SIGNATURE_HEADER e.g. "sha256=5bdc...", the hex
+/// encoded HMAC-SHA256 of the body.
+pub fn sign_webhook(secret: &str, body: &[u8]) -> String {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, solana_program_test::*};
+
+    #[tokio::test]
+    async fn test_get_webhook() {
+        let merchant = Pubkey::new_unique();
+        let order = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let event = PaymentProcessorEvent::OrderPaid {
+            order: order.to_bytes(),
+            merchant: merchant.to_bytes(),
+            payer: payer.to_bytes(),
+            mint: mint.to_bytes(),
+            amount: 1000,
+            tip: 10,
+        };
+        assert_eq!(None, get_event_account(&event));
+        let webhook = get_webhook(&event, &merchant, "5xyz", 1).unwrap();
+        assert_eq!(
+            json!({
+                "id": "5xyz:1",
+                "type": "order.paid",
+                "transaction": "5xyz",
+                "data": {
+                    "order": order.to_string(),
+                    "merchant": merchant.to_string(),
+                    "payer": payer.to_string(),
+                    "mint": mint.to_string(),
+                    "amount": 1000,
+                    "tip": 10,
+                },
+            }),
+            serde_json::to_value(&webhook).unwrap()
+        );
+
+        // the merchant of a refund is read from the order account
+        let event = PaymentProcessorEvent::Refunded {
+            order: order.to_bytes(),
+            amount: 1000,
+        };
+        assert_eq!(Some(order), get_event_account(&event));
+        let webhook = get_webhook(&event, &merchant, "5xyz", 0).unwrap();
+        assert_eq!("order.refunded", webhook.event_type);
+        assert_eq!(json!(merchant.to_string()), webhook.data["merchant"]);
+
+        let subscription = Pubkey::new_unique();
+        let event = PaymentProcessorEvent::Renewed {
+            subscription: subscription.to_bytes(),
+            period_end: 1700000000,
+        };
+        assert_eq!(Some(subscription), get_event_account(&event));
+        let webhook = get_webhook(&event, &merchant, "5xyz", 0).unwrap();
+        assert_eq!("subscription.renewed", webhook.event_type);
+        assert_eq!(json!(1700000000), webhook.data["period_end"]);
+
+        // other events are not relayed
+        let event = PaymentProcessorEvent::OrderWithdrawn {
+            order: order.to_bytes(),
+            merchant: merchant.to_bytes(),
+            amount: 1000,
+        };
+        assert_eq!(None, get_webhook(&event, &merchant, "5xyz", 0));
+    }
+
+    #[tokio::test]
+    async fn test_sign_webhook() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign_webhook("Jefe", b"what do ya want for nothing?")
+        );
+
+        let config: WebhookConfig = serde_json::from_str(
+            r#"{"merchants": {"11111111111111111111111111111111": {"url": "https://example.com/hooks", "secret": "s3cret"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&WebhookEndpoint {
+                url: String::from("https://example.com/hooks"),
+                secret: String::from("s3cret"),
+            }),
+            config.get_endpoint(&Pubkey::default())
+        );
+        assert_eq!(None, config.get_endpoint(&Pubkey::new_unique()));
+    }
+}